@@ -0,0 +1,71 @@
+//! Compares installing and restoring fakes one by one with doing it in a batch.
+//!
+//! Run with `cargo bench --bench batch`. Every iteration installs a fake of each of the
+//! functions below and restores them, reporting the time taken and how many page protection
+//! changes and instruction cache flushes it needed, see `InjectorPP::patch_stats`.
+
+use injectorpp::interface::injector::*;
+use std::hint::black_box;
+use std::time::{Duration, Instant};
+
+const ITERATIONS: u32 = 200;
+
+macro_rules! targets {
+    ($($name:ident),*) => {
+        $(
+            #[inline(never)]
+            fn $name() -> u64 {
+                black_box(0)
+            }
+        )*
+
+        fn install_all(injector: &mut InjectorPP) {
+            $(
+                injector
+                    .when_called(injectorpp::func!(fn ($name)() -> u64))
+                    .will_return_u64(1);
+            )*
+        }
+
+        const TARGETS: usize = [$(stringify!($name)),*].len();
+    };
+}
+
+targets!(t0, t1, t2, t3, t4, t5, t6, t7, t8, t9, t10, t11, t12, t13, t14, t15);
+
+/// Installs and restores every fake `ITERATIONS` times, returning the time one iteration
+/// took and the counts of all of them.
+fn measure(batched: bool) -> (Duration, PatchStats) {
+    let before = InjectorPP::patch_stats();
+    let start = Instant::now();
+
+    for _ in 0..ITERATIONS {
+        let mut injector = InjectorPP::new();
+        if batched {
+            injector.batch(install_all);
+        } else {
+            install_all(&mut injector);
+        }
+        assert_eq!(t0(), 1);
+    }
+
+    (
+        start.elapsed() / ITERATIONS,
+        InjectorPP::patch_stats() - before,
+    )
+}
+
+fn main() {
+    // Warm up so the first mode does not pay for loading the pages of the code involved.
+    measure(false);
+
+    println!("{TARGETS} fakes installed and restored per iteration");
+    for (name, batched) in [("individual", false), ("batch", true)] {
+        let (elapsed, stats) = measure(batched);
+        println!(
+            "{name:<12} {elapsed:>10.1?} {:>5} protection changes {:>5} cache flushes",
+            stats.protection_changes / ITERATIONS as usize,
+            stats.cache_flushes / ITERATIONS as usize
+        );
+    }
+}
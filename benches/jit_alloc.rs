@@ -0,0 +1,49 @@
+//! Compares how long installing a fake takes with each `JitAllocStrategy`.
+//!
+//! Run with `cargo bench --bench jit_alloc`. Every iteration installs and restores one
+//! fake, which maps and unmaps a page of JIT memory.
+
+use injectorpp::interface::injector::*;
+use std::hint::black_box;
+use std::time::{Duration, Instant};
+
+const ITERATIONS: u32 = 200;
+
+#[inline(never)]
+fn is_ready() -> bool {
+    black_box(false)
+}
+
+fn install_and_restore(strategy: JitAllocStrategy) -> Duration {
+    let start = Instant::now();
+
+    for _ in 0..ITERATIONS {
+        let mut injector =
+            InjectorPP::new_with_options(InjectorOptions::new().jit_alloc_strategy(strategy));
+        injector
+            .when_called(injectorpp::func!(fn (is_ready)() -> bool))
+            .will_return_boolean(true);
+        assert!(is_ready());
+    }
+
+    start.elapsed() / ITERATIONS
+}
+
+fn main() {
+    let target = is_ready as fn() -> bool as usize;
+    let strategies = [
+        ("nearest", JitAllocStrategy::Nearest),
+        ("ascending", JitAllocStrategy::Ascending),
+        ("hint +64MB", JitAllocStrategy::Hint(target + 0x400_0000)),
+    ];
+
+    // Warm up so the first strategy does not pay for loading the pages of the code involved.
+    install_and_restore(JitAllocStrategy::Nearest);
+
+    for (name, strategy) in strategies {
+        println!(
+            "{name:<12} {:>10.1?} per install",
+            install_and_restore(strategy)
+        );
+    }
+}
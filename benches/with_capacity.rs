@@ -0,0 +1,47 @@
+//! Compares installing and restoring a fake in a loop with and without
+//! `InjectorPP::with_capacity`.
+//!
+//! Run with `cargo bench --bench with_capacity`. Without reserved memory, restoring the only
+//! fake unmaps its page of JIT memory and the next install maps one again. With it, the page
+//! stays mapped and every iteration only writes the patch.
+
+use injectorpp::interface::injector::*;
+use std::hint::black_box;
+use std::time::{Duration, Instant};
+
+const ITERATIONS: u32 = 100_000;
+
+#[inline(never)]
+fn is_ready() -> bool {
+    black_box(false)
+}
+
+fn install_and_restore(mut injector: InjectorPP) -> Duration {
+    let start = Instant::now();
+
+    for _ in 0..ITERATIONS {
+        let handle = injector
+            .when_called(injectorpp::func!(fn (is_ready)() -> bool))
+            .will_return_boolean(true);
+        assert!(is_ready());
+        injector.restore(handle);
+    }
+
+    start.elapsed() / ITERATIONS
+}
+
+fn main() {
+    // Warm up so the first run does not pay for loading the pages of the code involved.
+    install_and_restore(InjectorPP::new());
+
+    println!(
+        "{:<16} {:>10.1?} per install",
+        "new",
+        install_and_restore(InjectorPP::new())
+    );
+    println!(
+        "{:<16} {:>10.1?} per install",
+        "with_capacity(1)",
+        install_and_restore(InjectorPP::with_capacity(1))
+    );
+}
@@ -1,11 +1,26 @@
+pub(crate) mod amd64_codegenerator;
+pub(crate) mod amd64_relocator;
 pub(crate) mod arm64_codegenerator;
+pub(crate) mod arm64_relocator;
+pub(crate) mod arm_codegenerator;
 pub(crate) mod common;
+#[cfg(feature = "debug-disasm")]
+pub(crate) mod disasm;
+pub(crate) mod fault_catcher;
 pub(crate) mod internal;
+pub(crate) mod jit_arena;
+pub(crate) mod jit_search;
+pub(crate) mod jit_unwind;
 pub(crate) mod linuxapi;
 pub(crate) mod macosapi;
 pub(crate) mod patch_amd64;
 pub(crate) mod patch_arm;
 pub(crate) mod patch_arm64;
+pub(crate) mod patch_riscv64;
 pub(crate) mod patch_trait;
+pub(crate) mod patch_x86;
+pub(crate) mod riscv64_codegenerator;
+pub(crate) mod symbols;
 pub(crate) mod utils;
 pub(crate) mod winapi;
+pub(crate) mod x86_codegenerator;
@@ -0,0 +1,459 @@
+//! Encoders for the AMD64 (x86_64) instructions used by the patches.
+//!
+//! Every function only builds bytes and never executes them, so this module is compiled and
+//! tested on every host.
+#![cfg_attr(not(target_arch = "x86_64"), allow(dead_code))]
+
+/// Opcode constants for AMD64 jump and move instructions.
+const JMP_REL_OPCODE: u8 = 0xE9;
+const MOV_RAX_OPCODE: [u8; 2] = [0x48, 0xB8];
+const JMP_RAX_OPCODE: [u8; 2] = [0xFF, 0xE0];
+const MOV_RDX_OPCODE: [u8; 2] = [0x48, 0xBA];
+const MOV_RCX_OPCODE: [u8; 2] = [0x48, 0xB9];
+const MOVQ_XMM0_RAX_OPCODE: [u8; 5] = [0x66, 0x48, 0x0F, 0x6E, 0xC0];
+const RET_OPCODE: u8 = 0xC3;
+
+/// The one byte `nop` patches are padded with up to the next instruction boundary.
+pub(crate) const NOP_OPCODE: u8 = 0x90;
+
+/// Returns a bare `ret`.
+pub(crate) fn emit_return_void() -> Vec<u8> {
+    vec![RET_OPCODE]
+}
+
+/// Returns a `jmp rel32` placed at `from` that lands on `to`, or `None` if `to` is more than
+/// 2GB away.
+pub(crate) fn emit_jmp_rel32(from: usize, to: usize) -> Option<Vec<u8>> {
+    let offset = to as i64 - (from as i64 + 5);
+    let offset = i32::try_from(offset).ok()?;
+
+    let mut branch_code = Vec::with_capacity(5);
+    branch_code.push(JMP_REL_OPCODE);
+    branch_code.extend_from_slice(&offset.to_le_bytes());
+    Some(branch_code)
+}
+
+/// Returns whether `code` starts with a `jmp rel32`.
+pub(crate) fn is_jmp_rel32(code: &[u8]) -> bool {
+    code.first() == Some(&JMP_REL_OPCODE)
+}
+
+/// Returns a position independent jump to the absolute address `target`, through rax.
+pub(crate) fn emit_abs_jump(target: usize) -> Vec<u8> {
+    let mut branch_code = Vec::with_capacity(12);
+    branch_code.extend_from_slice(&MOV_RAX_OPCODE);
+    branch_code.extend_from_slice(&(target as u64).to_le_bytes());
+    branch_code.extend_from_slice(&JMP_RAX_OPCODE);
+    branch_code
+}
+
+/// Generates a jump from `ori_func` to `target_func`, relative when in range and absolute
+/// otherwise.
+pub(crate) fn emit_branch(ori_func: usize, target_func: usize) -> Vec<u8> {
+    emit_jmp_rel32(ori_func, target_func).unwrap_or_else(|| emit_abs_jump(target_func))
+}
+
+/// Returns a return-boolean JIT sequence.
+///
+/// `mov rax, imm32` sign-extends its immediate, so al holds 0 or 1 and the rest of rax is
+/// zeroed. Callers testing only al and callers testing the whole register agree, like with
+/// the `movz x0` of the AArch64 sequence.
+pub(crate) fn emit_return_boolean(value: bool) -> Vec<u8> {
+    let mut asm_code: [u8; 8] = [
+        0x48, 0xC7, 0xC0, // mov rax, imm32
+        0x00, 0x00, 0x00, 0x00, // imm32
+        0xC3, // ret
+    ];
+
+    asm_code[3] = value as u8;
+
+    asm_code.to_vec()
+}
+
+/// Returns a JIT sequence that returns the 64-bit `value` in rax.
+pub(crate) fn emit_return_integer(value: u64) -> Vec<u8> {
+    let mut asm_code = Vec::with_capacity(11);
+    asm_code.extend_from_slice(&MOV_RAX_OPCODE); // mov rax, imm64
+    asm_code.extend_from_slice(&value.to_le_bytes());
+    asm_code.extend(emit_return_void());
+
+    asm_code
+}
+
+/// Returns a JIT sequence that returns the double whose bit pattern is `bits` in xmm0, where
+/// both the System V and the Windows x64 conventions return floating point values.
+pub(crate) fn emit_return_float(bits: u64) -> Vec<u8> {
+    let mut asm_code = Vec::with_capacity(16);
+    asm_code.extend_from_slice(&MOV_RAX_OPCODE); // mov rax, imm64
+    asm_code.extend_from_slice(&bits.to_le_bytes());
+    asm_code.extend_from_slice(&MOVQ_XMM0_RAX_OPCODE); // movq xmm0, rax
+    asm_code.extend(emit_return_void());
+
+    asm_code
+}
+
+/// Returns a JIT sequence that returns `first` in rax and `second` in rdx.
+///
+/// This is how the System V and Rust ABIs return aggregates made of two eightbytes.
+pub(crate) fn emit_return_pair(first: u64, second: u64) -> Vec<u8> {
+    let mut asm_code = Vec::with_capacity(21);
+    asm_code.extend_from_slice(&MOV_RAX_OPCODE); // mov rax, imm64
+    asm_code.extend_from_slice(&first.to_le_bytes());
+    asm_code.extend_from_slice(&MOV_RDX_OPCODE); // mov rdx, imm64
+    asm_code.extend_from_slice(&second.to_le_bytes());
+    asm_code.extend(emit_return_void());
+
+    asm_code
+}
+
+/// Returns a JIT sequence that atomically increments the 64-bit counter at `counter` and
+/// returns.
+///
+/// The generated instructions are:
+///   mov rax, counter
+///   lock inc qword ptr [rax]
+///   ret
+pub(crate) fn emit_increment_counter(counter: usize) -> Vec<u8> {
+    let mut asm_code = Vec::with_capacity(15);
+    asm_code.extend_from_slice(&MOV_RAX_OPCODE);
+    asm_code.extend_from_slice(&(counter as u64).to_le_bytes());
+    asm_code.extend_from_slice(&[0xF0, 0x48, 0xFF, 0x00]);
+    asm_code.extend(emit_return_void());
+
+    asm_code
+}
+
+/// Returns a 52-byte JIT sequence that returns the byte `values[min(n, last)]`, where `n` is
+/// the value of the 64-bit counter at `counter`, which it atomically increments.
+///
+/// The generated instructions are:
+///   mov rcx, counter
+///   mov eax, 1
+///   lock xadd qword ptr [rcx], rax
+///   mov rcx, last
+///   cmp rax, rcx
+///   cmova rax, rcx
+///   mov rcx, values
+///   movzx eax, byte ptr [rcx + rax]
+///   ret
+pub(crate) fn emit_return_byte_sequence(counter: usize, values: usize, last: usize) -> Vec<u8> {
+    let mut asm_code = Vec::with_capacity(52);
+    asm_code.extend_from_slice(&MOV_RCX_OPCODE);
+    asm_code.extend_from_slice(&(counter as u64).to_le_bytes());
+    asm_code.extend_from_slice(&[0xB8, 0x01, 0x00, 0x00, 0x00]);
+    asm_code.extend_from_slice(&[0xF0, 0x48, 0x0F, 0xC1, 0x01]);
+    asm_code.extend_from_slice(&MOV_RCX_OPCODE);
+    asm_code.extend_from_slice(&(last as u64).to_le_bytes());
+    asm_code.extend_from_slice(&[0x48, 0x39, 0xC8]);
+    asm_code.extend_from_slice(&[0x48, 0x0F, 0x47, 0xC1]);
+    asm_code.extend_from_slice(&MOV_RCX_OPCODE);
+    asm_code.extend_from_slice(&(values as u64).to_le_bytes());
+    asm_code.extend_from_slice(&[0x0F, 0xB6, 0x04, 0x01]);
+    asm_code.extend(emit_return_void());
+
+    asm_code
+}
+
+/// Returns a 43-byte JIT sequence that copies the `size` bytes at `value` to the return slot
+/// whose address the caller passed in the register numbered `destination`, then returns
+/// that address in rax. `size` must not be zero.
+///
+/// The generated instructions are:
+///   mov rax, destination
+///   mov r10, value
+///   mov r11, size
+///   xor ecx, ecx
+/// 1:
+///   cmp rcx, r11
+///   jae 2f
+///   mov dl, byte ptr [r10 + rcx]
+///   mov byte ptr [rax + rcx], dl
+///   inc rcx
+///   jmp 1b
+/// 2:
+///   ret
+pub(crate) fn emit_return_struct(destination: u8, value: usize, size: usize) -> Vec<u8> {
+    let mut asm_code = Vec::with_capacity(43);
+    asm_code.extend_from_slice(&[0x48, 0x89, 0xC0 | (destination << 3)]);
+    asm_code.extend_from_slice(&[0x49, 0xBA]);
+    asm_code.extend_from_slice(&(value as u64).to_le_bytes());
+    asm_code.extend_from_slice(&[0x49, 0xBB]);
+    asm_code.extend_from_slice(&(size as u64).to_le_bytes());
+    asm_code.extend_from_slice(&[0x31, 0xC9]);
+    asm_code.extend_from_slice(&[0x4C, 0x39, 0xD9, 0x73, 0x0C]);
+    asm_code.extend_from_slice(&[0x41, 0x8A, 0x14, 0x0A, 0x88, 0x14, 0x08]);
+    asm_code.extend_from_slice(&[0x48, 0xFF, 0xC1, 0xEB, 0xEF]);
+    asm_code.extend(emit_return_void());
+
+    asm_code
+}
+
+/// Positions of rdi, rsi, rdx, rcx, r8 and r9 in the registers saved by `emit_call_hook`.
+pub(crate) const SYSV_ARGUMENT_SLOTS: [usize; 6] = [7, 6, 5, 4, 3, 2];
+
+/// Positions of rcx, rdx, r8 and r9 in the registers saved by `emit_call_hook`.
+pub(crate) const WIN64_ARGUMENT_SLOTS: [usize; 4] = [4, 5, 3, 2];
+
+/// The registers `emit_call_hook` pushes, in order, numbered as in instruction encodings and
+/// Windows unwind codes: rdi, rsi, rdx, rcx, r8, r9, rax and r10.
+pub(crate) const CALL_HOOK_SAVED_REGISTERS: [u8; 8] = [7, 6, 2, 1, 8, 9, 0, 10];
+
+/// The bytes `emit_call_hook` reserves below the saved registers.
+pub(crate) const CALL_HOOK_FRAME_SIZE: u32 = 168;
+
+/// The size of the code `emit_call_hook` returns.
+pub(crate) const CALL_HOOK_SIZE: usize = 216;
+
+/// Returns `push reg` for a register of `CALL_HOOK_SAVED_REGISTERS`.
+fn emit_push(register: u8) -> Vec<u8> {
+    match register {
+        0..=7 => vec![0x50 + register],
+        _ => vec![0x41, 0x50 + register - 8],
+    }
+}
+
+/// Returns `pop reg` for a register of `CALL_HOOK_SAVED_REGISTERS`.
+fn emit_pop(register: u8) -> Vec<u8> {
+    match register {
+        0..=7 => vec![0x58 + register],
+        _ => vec![0x41, 0x58 + register - 8],
+    }
+}
+
+/// Returns how the stack pointer moves through the code of `emit_call_hook`: from each
+/// offset on, how many bytes it is below its value on entry, until the next offset.
+pub(crate) fn call_hook_stack_depths() -> Vec<(usize, u32)> {
+    let mut depths = Vec::new();
+    let mut offset = 0;
+    let mut depth = 0;
+
+    for register in CALL_HOOK_SAVED_REGISTERS {
+        offset += emit_push(register).len();
+        depth += 8;
+        depths.push((offset, depth));
+    }
+
+    // sub rsp, FRAME_SIZE
+    offset += 7;
+    depths.push((offset, depth + CALL_HOOK_FRAME_SIZE));
+
+    // add rsp, FRAME_SIZE, then the pops end the code.
+    let pops: usize = CALL_HOOK_SAVED_REGISTERS
+        .iter()
+        .map(|&register| emit_pop(register).len())
+        .sum();
+    offset = CALL_HOOK_SIZE - pops;
+    depths.push((offset, depth));
+
+    for &register in CALL_HOOK_SAVED_REGISTERS.iter().rev() {
+        offset += emit_pop(register).len();
+        depth -= 8;
+        depths.push((offset, depth));
+    }
+
+    depths
+}
+
+/// Returns code that calls `hook(data, registers)` and then falls through to the code placed
+/// after it.
+///
+/// Every register that may carry an argument is saved around the call: rdi, rsi, rdx, rcx,
+/// r8, r9 and xmm0-xmm7 for System V, rax for the vector count of variadic calls and r10
+/// for the static chain. `registers` points at the saved general purpose registers, laid out
+/// as described by `SYSV_ARGUMENT_SLOTS` and `WIN64_ARGUMENT_SLOTS`. The arguments are passed
+/// in both rdi/rsi and rcx/rdx so the same code works for the System V and the Windows x64
+/// calling conventions, and 32 bytes of shadow space are reserved for the latter.
+///
+/// The stack pointer is `8 mod 16` on entry, eight pushes keep it there and the 168-byte
+/// frame realigns it before the call.
+pub(crate) fn emit_call_hook(hook: usize, data: usize) -> Vec<u8> {
+    const FRAME_SIZE: u32 = CALL_HOOK_FRAME_SIZE;
+    const XMM_AREA: u32 = 32;
+
+    let mut asm_code = Vec::with_capacity(CALL_HOOK_SIZE);
+
+    // push rdi, rsi, rdx, rcx, r8, r9, rax, r10
+    for register in CALL_HOOK_SAVED_REGISTERS {
+        asm_code.extend(emit_push(register));
+    }
+
+    // sub rsp, FRAME_SIZE
+    asm_code.extend_from_slice(&[0x48, 0x81, 0xEC]);
+    asm_code.extend_from_slice(&FRAME_SIZE.to_le_bytes());
+
+    // movdqu [rsp + XMM_AREA + 16 * i], xmm{i}
+    for i in 0..8u8 {
+        asm_code.extend_from_slice(&[0xF3, 0x0F, 0x7F, 0x84 | (i << 3), 0x24]);
+        asm_code.extend_from_slice(&(XMM_AREA + 16 * i as u32).to_le_bytes());
+    }
+
+    // mov rdi, data
+    asm_code.extend_from_slice(&[0x48, 0xBF]);
+    asm_code.extend_from_slice(&(data as u64).to_le_bytes());
+
+    // mov rcx, rdi
+    asm_code.extend_from_slice(&[0x48, 0x89, 0xF9]);
+
+    // lea rsi, [rsp + FRAME_SIZE]
+    asm_code.extend_from_slice(&[0x48, 0x8D, 0xB4, 0x24]);
+    asm_code.extend_from_slice(&FRAME_SIZE.to_le_bytes());
+
+    // mov rdx, rsi
+    asm_code.extend_from_slice(&[0x48, 0x89, 0xF2]);
+
+    // mov rax, hook
+    asm_code.extend_from_slice(&MOV_RAX_OPCODE);
+    asm_code.extend_from_slice(&(hook as u64).to_le_bytes());
+
+    // call rax
+    asm_code.extend_from_slice(&[0xFF, 0xD0]);
+
+    // movdqu xmm{i}, [rsp + XMM_AREA + 16 * i]
+    for i in 0..8u8 {
+        asm_code.extend_from_slice(&[0xF3, 0x0F, 0x6F, 0x84 | (i << 3), 0x24]);
+        asm_code.extend_from_slice(&(XMM_AREA + 16 * i as u32).to_le_bytes());
+    }
+
+    // add rsp, FRAME_SIZE
+    asm_code.extend_from_slice(&[0x48, 0x81, 0xC4]);
+    asm_code.extend_from_slice(&FRAME_SIZE.to_le_bytes());
+
+    // pop r10, rax, r9, r8, rcx, rdx, rsi, rdi
+    for &register in CALL_HOOK_SAVED_REGISTERS.iter().rev() {
+        asm_code.extend(emit_pop(register));
+    }
+
+    asm_code
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_emit_jmp_rel32_forward_and_backward() {
+        assert_eq!(
+            emit_jmp_rel32(0x1000, 0x2000),
+            Some(vec![0xE9, 0xFB, 0x0F, 0x00, 0x00])
+        );
+        assert_eq!(
+            emit_jmp_rel32(0x2000, 0x1000),
+            Some(vec![0xE9, 0xFB, 0xEF, 0xFF, 0xFF])
+        );
+    }
+
+    #[test]
+    fn test_emit_jmp_rel32_out_of_range_should_fall_back_to_abs_jump() {
+        let target = 0x7FFF_0000_1234usize;
+
+        assert_eq!(emit_jmp_rel32(0x1000, target), None);
+        assert_eq!(
+            emit_branch(0x1000, target),
+            vec![0x48, 0xB8, 0x34, 0x12, 0x00, 0x00, 0xFF, 0x7F, 0x00, 0x00, 0xFF, 0xE0]
+        );
+    }
+
+    #[test]
+    fn test_emit_return_encodings() {
+        assert_eq!(emit_return_void(), vec![0xC3]);
+        assert_eq!(
+            emit_return_boolean(true),
+            vec![0x48, 0xC7, 0xC0, 0x01, 0x00, 0x00, 0x00, 0xC3]
+        );
+        assert_eq!(
+            emit_return_boolean(false),
+            vec![0x48, 0xC7, 0xC0, 0x00, 0x00, 0x00, 0x00, 0xC3]
+        );
+        assert_eq!(
+            emit_return_integer(0x1234_5678_9ABC_DEF0),
+            vec![0x48, 0xB8, 0xF0, 0xDE, 0xBC, 0x9A, 0x78, 0x56, 0x34, 0x12, 0xC3]
+        );
+        assert_eq!(
+            emit_return_float(1.5f64.to_bits()),
+            vec![
+                0x48, 0xB8, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0xF8, 0x3F, 0x66, 0x48, 0x0F, 0x6E,
+                0xC0, 0xC3
+            ]
+        );
+    }
+
+    #[test]
+    fn test_emit_return_byte_sequence_encoding() {
+        let code = emit_return_byte_sequence(0x1000, 0x2000, 2);
+
+        assert_eq!(code.len(), 52);
+        assert_eq!(code[..2], MOV_RCX_OPCODE);
+        assert_eq!(code[2..10], 0x1000u64.to_le_bytes());
+        assert_eq!(
+            code[10..22],
+            [0xB8, 0x01, 0x00, 0x00, 0x00, 0xF0, 0x48, 0x0F, 0xC1, 0x01, 0x48, 0xB9]
+        );
+        assert_eq!(code[22..30], 2u64.to_le_bytes());
+        assert_eq!(
+            code[30..39],
+            [0x48, 0x39, 0xC8, 0x48, 0x0F, 0x47, 0xC1, 0x48, 0xB9]
+        );
+        assert_eq!(code[39..47], 0x2000u64.to_le_bytes());
+        assert_eq!(code[47..], [0x0F, 0xB6, 0x04, 0x01, 0xC3]);
+    }
+
+    #[test]
+    fn test_emit_return_struct_encoding() {
+        let code = emit_return_struct(7, 0x1000, 64);
+
+        assert_eq!(code.len(), 43);
+        // mov rax, rdi; mov r10, 0x1000
+        assert_eq!(code[..5], [0x48, 0x89, 0xF8, 0x49, 0xBA]);
+        assert_eq!(code[5..13], 0x1000u64.to_le_bytes());
+        // mov r11, 64
+        assert_eq!(code[13..15], [0x49, 0xBB]);
+        assert_eq!(code[15..23], 64u64.to_le_bytes());
+        // xor ecx, ecx; cmp rcx, r11; jae +12; mov dl, [r10 + rcx]; mov [rax + rcx], dl;
+        // inc rcx; jmp -17; ret
+        assert_eq!(
+            code[23..],
+            [
+                0x31, 0xC9, 0x4C, 0x39, 0xD9, 0x73, 0x0C, 0x41, 0x8A, 0x14, 0x0A, 0x88, 0x14, 0x08,
+                0x48, 0xFF, 0xC1, 0xEB, 0xEF, 0xC3
+            ]
+        );
+
+        // mov rax, rcx
+        assert_eq!(emit_return_struct(1, 0x1000, 64)[..3], [0x48, 0x89, 0xC8]);
+    }
+
+    #[test]
+    fn test_emit_call_hook_should_match_its_stack_depths() {
+        let code = emit_call_hook(0x1000, 0x2000);
+        assert_eq!(code.len(), CALL_HOOK_SIZE);
+        // push rdi, rsi, rdx, rcx, r8, r9, rax, r10; sub rsp, 168
+        assert_eq!(
+            code[..18],
+            [
+                0x57, 0x56, 0x52, 0x51, 0x41, 0x50, 0x41, 0x51, 0x50, 0x41, 0x52, 0x48, 0x81, 0xEC,
+                0xA8, 0x00, 0x00, 0x00
+            ]
+        );
+        // add rsp, 168; pop r10, rax, r9, r8, rcx, rdx, rsi, rdi
+        assert_eq!(
+            code[198..],
+            [
+                0x48, 0x81, 0xC4, 0xA8, 0x00, 0x00, 0x00, 0x41, 0x5A, 0x58, 0x41, 0x59, 0x41, 0x58,
+                0x59, 0x5A, 0x5E, 0x5F
+            ]
+        );
+
+        let depths = call_hook_stack_depths();
+        assert_eq!(depths[7], (11, 64));
+        assert_eq!(depths[8], (18, 232));
+        assert_eq!(depths[9], (205, 64));
+        assert_eq!(depths.last(), Some(&(CALL_HOOK_SIZE, 0)));
+
+        // The return address of `call rax` is in the frame.
+        let call = code
+            .windows(2)
+            .position(|bytes| bytes == [0xFF, 0xD0])
+            .unwrap();
+        assert!(depths[8].0 <= call + 2 && call + 2 < depths[9].0);
+    }
+}
@@ -0,0 +1,683 @@
+//! Copies the first instructions of an AMD64 (x86_64) function elsewhere so the function
+//! can still be called once a patch overwrites them.
+//!
+//! Only the length of each instruction and its RIP-relative parts are decoded, which is all
+//! relocation needs. Like the code generator this never executes anything, so it is compiled
+//! and tested on every host.
+#![cfg_attr(not(target_arch = "x86_64"), allow(dead_code))]
+
+use crate::interface::error::InjectError;
+
+/// What makes an instruction depend on its own address.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Relative {
+    /// Nothing, the instruction can be copied as is.
+    None,
+    /// A memory operand addressed as `[rip + disp32]`, with the displacement at `offset`.
+    RipDisplacement { offset: usize },
+    /// `jmp rel8` or `jmp rel32`.
+    Jump { displacement: i32 },
+    /// `jcc rel8` or `jcc rel32` with condition code `condition`.
+    ConditionalJump { condition: u8, displacement: i32 },
+    /// `call rel32`.
+    Call { displacement: i32 },
+    /// A relative branch that cannot be rewritten, such as `loop` or `jrcxz`.
+    Unsupported,
+}
+
+/// The length of an instruction and how it depends on its address.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct Instruction {
+    pub(crate) len: usize,
+    pub(crate) relative: Relative,
+}
+
+/// Decodes the instruction at the start of `code`, or returns `None` if it is not understood.
+///
+/// Covers the general purpose, x87, SSE and VEX encoded instructions a compiler emits. EVEX
+/// encoded instructions and the few opcodes that are invalid in 64-bit mode are rejected.
+pub(crate) fn decode(code: &[u8]) -> Option<Instruction> {
+    let mut cursor = 0;
+    let mut operand_size_16 = false;
+
+    // Legacy prefixes.
+    loop {
+        match *code.get(cursor)? {
+            0x66 => operand_size_16 = true,
+            0x67 | 0xF0 | 0xF2 | 0xF3 | 0x2E | 0x36 | 0x3E | 0x26 | 0x64 | 0x65 => {}
+            _ => break,
+        }
+        cursor += 1;
+    }
+
+    let mut rex_w = false;
+    if let 0x40..=0x4F = *code.get(cursor)? {
+        rex_w = code[cursor] & 0x08 != 0;
+        cursor += 1;
+    }
+
+    // The size of a `z` immediate: 16 bits with an operand size prefix, 32 bits otherwise.
+    let imm_z = if operand_size_16 { 2 } else { 4 };
+
+    let opcode = *code.get(cursor)?;
+    cursor += 1;
+
+    let (has_modrm, immediate) = match opcode {
+        0x0F => return decode_two_byte(code, cursor),
+
+        // VEX prefixes, always VEX in 64-bit mode.
+        0xC4 | 0xC5 => return decode_vex(code, cursor - 1),
+
+        // EVEX and opcodes that are invalid in 64-bit mode.
+        0x06
+        | 0x07
+        | 0x0E
+        | 0x16
+        | 0x17
+        | 0x1E
+        | 0x1F
+        | 0x27
+        | 0x2F
+        | 0x37
+        | 0x3F
+        | 0x60..=0x62
+        | 0x82
+        | 0x9A
+        | 0xCE
+        | 0xD4..=0xD6
+        | 0xEA => return None,
+
+        0x70..=0x7F => {
+            let displacement = *code.get(cursor)? as i8 as i32;
+            return Some(Instruction {
+                len: cursor + 1,
+                relative: Relative::ConditionalJump {
+                    condition: opcode & 0x0F,
+                    displacement,
+                },
+            });
+        }
+        0xEB => {
+            let displacement = *code.get(cursor)? as i8 as i32;
+            return Some(Instruction {
+                len: cursor + 1,
+                relative: Relative::Jump { displacement },
+            });
+        }
+        0xE9 | 0xE8 => {
+            let displacement = i32::from_le_bytes(code.get(cursor..cursor + 4)?.try_into().ok()?);
+            let relative = if opcode == 0xE9 {
+                Relative::Jump { displacement }
+            } else {
+                Relative::Call { displacement }
+            };
+            return Some(Instruction {
+                len: cursor + 4,
+                relative,
+            });
+        }
+        0xE0..=0xE3 => {
+            code.get(cursor)?;
+            return Some(Instruction {
+                len: cursor + 1,
+                relative: Relative::Unsupported,
+            });
+        }
+
+        // Arithmetic: r/m forms, then the accumulator with an immediate.
+        0x00..=0x3F => match opcode & 0x07 {
+            0..=3 => (true, 0),
+            4 => (false, 1),
+            _ => (false, imm_z),
+        },
+
+        0x50..=0x5F => (false, 0),
+        0x63 => (true, 0),
+        0x68 => (false, imm_z),
+        0x69 => (true, imm_z),
+        0x6A => (false, 1),
+        0x6B => (true, 1),
+        0x6C..=0x6F => (false, 0),
+        0x80 | 0x83 => (true, 1),
+        0x81 => (true, imm_z),
+        0x84..=0x8F => (true, 0),
+        0x90..=0x9F => (false, 0),
+        // mov between the accumulator and a 64-bit absolute address.
+        0xA0..=0xA3 => (false, 8),
+        0xA4..=0xA7 | 0xAA..=0xAF => (false, 0),
+        0xA8 => (false, 1),
+        0xA9 => (false, imm_z),
+        0xB0..=0xB7 => (false, 1),
+        0xB8..=0xBF => (false, if rex_w { 8 } else { imm_z }),
+        0xC0 | 0xC1 | 0xC6 => (true, 1),
+        0xC2 | 0xCA => (false, 2),
+        0xC3 | 0xC9 | 0xCB | 0xCC | 0xCF => (false, 0),
+        0xC7 => (true, imm_z),
+        0xC8 => (false, 3),
+        0xCD => (false, 1),
+        0xD0..=0xD3 | 0xD8..=0xDF => (true, 0),
+        0xD7 => (false, 0),
+        0xE4..=0xE7 => (false, 1),
+        0xEC..=0xEF | 0xF1 | 0xF4 | 0xF5 | 0xF8..=0xFD => (false, 0),
+        0xF6 | 0xF7 => {
+            // test r/m, imm is the only form of the group taking an immediate.
+            let reg = (*code.get(cursor)? >> 3) & 0x07;
+            let immediate = match (opcode, reg) {
+                (0xF6, 0 | 1) => 1,
+                (0xF7, 0 | 1) => imm_z,
+                _ => 0,
+            };
+            (true, immediate)
+        }
+        0xFE | 0xFF => (true, 0),
+
+        // Prefixes already consumed above.
+        _ => return None,
+    };
+
+    finish(code, cursor, has_modrm, immediate)
+}
+
+/// Decodes the opcode following a 0x0F escape, `cursor` being just past the escape.
+fn decode_two_byte(code: &[u8], mut cursor: usize) -> Option<Instruction> {
+    let opcode = *code.get(cursor)?;
+    cursor += 1;
+
+    let (has_modrm, immediate) = match opcode {
+        0x38 => {
+            code.get(cursor)?;
+            return finish(code, cursor + 1, true, 0);
+        }
+        0x3A => {
+            code.get(cursor)?;
+            return finish(code, cursor + 1, true, 1);
+        }
+        0x80..=0x8F => {
+            let displacement = i32::from_le_bytes(code.get(cursor..cursor + 4)?.try_into().ok()?);
+            return Some(Instruction {
+                len: cursor + 4,
+                relative: Relative::ConditionalJump {
+                    condition: opcode & 0x0F,
+                    displacement,
+                },
+            });
+        }
+
+        0x04 | 0x0A | 0x0C | 0x24..=0x27 | 0x36 | 0x39 | 0x3B..=0x3F | 0xA6 | 0xA7 => return None,
+
+        0x05..=0x09
+        | 0x0B
+        | 0x0E
+        | 0x30..=0x35
+        | 0x37
+        | 0x77
+        | 0xA0..=0xA2
+        | 0xA8..=0xAA
+        | 0xC8..=0xCF => (false, 0),
+
+        0x0F | 0x70..=0x73 | 0xA4 | 0xAC | 0xBA | 0xC2 | 0xC4..=0xC6 => (true, 1),
+
+        _ => (true, 0),
+    };
+
+    finish(code, cursor, has_modrm, immediate)
+}
+
+/// Decodes a VEX encoded instruction starting at the VEX prefix at `start`.
+fn decode_vex(code: &[u8], start: usize) -> Option<Instruction> {
+    let (map, opcode_at) = if code[start] == 0xC5 {
+        (1, start + 2)
+    } else {
+        (*code.get(start + 1)? & 0x1F, start + 3)
+    };
+
+    let opcode = *code.get(opcode_at)?;
+    let (has_modrm, immediate) = match map {
+        // vzeroupper and vzeroall
+        1 if opcode == 0x77 => (false, 0),
+        1 => (
+            true,
+            matches!(opcode, 0x70..=0x73 | 0xC2 | 0xC4..=0xC6) as usize,
+        ),
+        2 => (true, 0),
+        3 => (true, 1),
+        _ => return None,
+    };
+
+    finish(code, opcode_at + 1, has_modrm, immediate)
+}
+
+/// Adds the ModRM, SIB, displacement and immediate bytes following the opcode that ends
+/// just before `cursor`.
+fn finish(
+    code: &[u8],
+    mut cursor: usize,
+    has_modrm: bool,
+    immediate: usize,
+) -> Option<Instruction> {
+    let mut relative = Relative::None;
+
+    if has_modrm {
+        let modrm = *code.get(cursor)?;
+        cursor += 1;
+
+        let mode = modrm >> 6;
+        let rm = modrm & 0x07;
+
+        if mode != 3 && rm == 4 {
+            let sib = *code.get(cursor)?;
+            cursor += 1;
+
+            if mode == 0 && sib & 0x07 == 5 {
+                cursor += 4;
+            }
+        }
+
+        match mode {
+            0 if rm == 5 => {
+                relative = Relative::RipDisplacement { offset: cursor };
+                cursor += 4;
+            }
+            1 => cursor += 1,
+            2 => cursor += 4,
+            _ => {}
+        }
+    }
+
+    cursor += immediate;
+    code.get(..cursor)?;
+
+    Some(Instruction {
+        len: cursor,
+        relative,
+    })
+}
+
+/// The size of the `jmp [rip]` followed by its 8-byte target that ends a relocation.
+pub(crate) const JUMP_BACK_SIZE: usize = 14;
+
+/// Copies the instructions at the start of `code`, which lives at `from`, so they can run
+/// at `to`, and appends a jump to the first instruction left in place.
+///
+/// Whole instructions are copied until at least `min_len` bytes are covered. RIP-relative
+/// operands and relative branches are adjusted to keep their targets, short branches being
+/// widened to their 32-bit forms. The result has the same length whatever `to` is, so it can
+/// be sized by relocating to `from` before the destination is known.
+///
+/// Fails if an instruction cannot be decoded, branches into the copied bytes, has no 32-bit
+/// form or ends up more than 2GB away from its target.
+pub(crate) fn relocate(
+    code: &[u8],
+    from: usize,
+    to: usize,
+    min_len: usize,
+) -> Result<Vec<u8>, InjectError> {
+    let mut relocated = Vec::with_capacity(min_len + 32);
+    let mut offset = 0;
+
+    while offset < min_len {
+        let address = from + offset;
+        let unrelocatable = InjectError::UnrelocatableInstruction { address };
+
+        let instruction = decode(&code[offset..]).ok_or(unrelocatable.clone())?;
+        let bytes = &code[offset..offset + instruction.len];
+        let next = address + instruction.len;
+
+        // Where the next instruction of the copy lives, `rel32` operands are relative to it.
+        let displacement_to = |target: usize, len: usize| -> Result<i32, InjectError> {
+            let end = to + relocated.len() + len;
+            i32::try_from(target as i64 - end as i64).map_err(|_| unrelocatable.clone())
+        };
+        let branch_target = |displacement: i32| -> Result<usize, InjectError> {
+            let target = (next as i64 + displacement as i64) as usize;
+            if (from..from + min_len.max(offset + instruction.len)).contains(&target) {
+                return Err(unrelocatable.clone());
+            }
+            Ok(target)
+        };
+
+        match instruction.relative {
+            Relative::None => relocated.extend_from_slice(bytes),
+            Relative::RipDisplacement { offset: at } => {
+                let displacement = i32::from_le_bytes(bytes[at..at + 4].try_into().unwrap());
+                let target = (next as i64 + displacement as i64) as usize;
+                let new_displacement = displacement_to(target, instruction.len)?;
+
+                relocated.extend_from_slice(&bytes[..at]);
+                relocated.extend_from_slice(&new_displacement.to_le_bytes());
+                relocated.extend_from_slice(&bytes[at + 4..]);
+            }
+            Relative::Jump { displacement } => {
+                let target = branch_target(displacement)?;
+                let new_displacement = displacement_to(target, 5)?;
+
+                relocated.push(0xE9);
+                relocated.extend_from_slice(&new_displacement.to_le_bytes());
+            }
+            Relative::ConditionalJump {
+                condition,
+                displacement,
+            } => {
+                let target = branch_target(displacement)?;
+                let new_displacement = displacement_to(target, 6)?;
+
+                relocated.extend_from_slice(&[0x0F, 0x80 | condition]);
+                relocated.extend_from_slice(&new_displacement.to_le_bytes());
+            }
+            Relative::Call { displacement } => {
+                let target = (next as i64 + displacement as i64) as usize;
+                let new_displacement = displacement_to(target, 5)?;
+
+                relocated.push(0xE8);
+                relocated.extend_from_slice(&new_displacement.to_le_bytes());
+            }
+            Relative::Unsupported => return Err(unrelocatable),
+        }
+
+        offset += instruction.len;
+    }
+
+    // jmp [rip + 0], followed by the address to continue at.
+    relocated.extend_from_slice(&[0xFF, 0x25, 0x00, 0x00, 0x00, 0x00]);
+    relocated.extend_from_slice(&((from + offset) as u64).to_le_bytes());
+
+    Ok(relocated)
+}
+
+/// Returns the offset of the first instruction boundary at or after `len` bytes into `code`,
+/// i.e. how many bytes a patch of `len` bytes must cover to not split an instruction.
+///
+/// Returns `None` if an instruction before that cannot be decoded.
+pub(crate) fn instruction_boundary(code: &[u8], len: usize) -> Option<usize> {
+    let mut offset = 0;
+
+    while offset < len {
+        offset += decode(&code[offset..])?.len;
+    }
+
+    Some(offset)
+}
+
+/// Returns where the function at the start of `code` ends when that is before `len` bytes and
+/// is followed by something else than padding, i.e. when a `len` bytes patch would overwrite
+/// the start of the next function.
+///
+/// The function ends at its first `ret`, `jmp`, `ud2` or `int3` that no earlier branch jumps
+/// past. Padding is made of `int3` and `nop` instructions. Returns `None` as well when the
+/// code cannot be decoded, since nothing can be told about it then.
+pub(crate) fn function_end_before(code: &[u8], len: usize) -> Option<usize> {
+    let mut offset = 0;
+    // The furthest offset a branch seen so far can continue at.
+    let mut reachable = 0;
+
+    while offset < len {
+        let instruction = decode(&code[offset..])?;
+        let next = offset + instruction.len;
+
+        if let Relative::Jump { displacement } | Relative::ConditionalJump { displacement, .. } =
+            instruction.relative
+        {
+            reachable = reachable.max((next as i64 + displacement as i64).max(0) as usize);
+        }
+
+        if ends_function(&code[offset..next]) && reachable < next {
+            return (next < len && !is_padding(&code[next..], len - next)).then_some(next);
+        }
+
+        offset = next;
+    }
+
+    None
+}
+
+/// Returns whether `instruction` never falls through to the next one.
+fn ends_function(instruction: &[u8]) -> bool {
+    match skip_prefixes(instruction) {
+        [0xC3 | 0xC2 | 0xCB | 0xCA | 0xCC | 0xE9 | 0xEB, ..] => true,
+        // jmp r/m64 and jmp m16:64
+        [0xFF, modrm, ..] => matches!((modrm >> 3) & 0x7, 4 | 5),
+        // ud2
+        [0x0F, 0x0B, ..] => true,
+        _ => false,
+    }
+}
+
+/// Returns whether the first `len` bytes of `code` are `int3` and `nop` instructions. A `nop`
+/// cut short by the end of `code` counts as padding.
+fn is_padding(code: &[u8], len: usize) -> bool {
+    let mut offset = 0;
+
+    while offset < len {
+        offset += match skip_prefixes(&code[offset..]) {
+            [0xCC | 0x90, ..] => 1,
+            [0x0F, 0x1F, ..] => match decode(&code[offset..]) {
+                Some(instruction) => instruction.len,
+                None => return true,
+            },
+            _ => return false,
+        };
+    }
+
+    true
+}
+
+/// Skips the legacy and REX prefixes of the instruction at the start of `code`.
+fn skip_prefixes(code: &[u8]) -> &[u8] {
+    let start = code
+        .iter()
+        .position(|byte| !matches!(byte, 0x66 | 0xF2 | 0xF3 | 0x2E | 0x3E | 0x40..=0x4F))
+        .unwrap_or(code.len());
+    &code[start..]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn length(code: &[u8]) -> Option<usize> {
+        decode(code).map(|instruction| instruction.len)
+    }
+
+    #[test]
+    fn test_decode_common_prologue_instructions() {
+        assert_eq!(length(&[0xF3, 0x0F, 0x1E, 0xFA]), Some(4)); // endbr64
+        assert_eq!(length(&[0x55]), Some(1)); // push rbp
+        assert_eq!(length(&[0x41, 0x57]), Some(2)); // push r15
+        assert_eq!(length(&[0x48, 0x89, 0xE5]), Some(3)); // mov rbp, rsp
+        assert_eq!(length(&[0x48, 0x83, 0xEC, 0x38]), Some(4)); // sub rsp, 0x38
+        assert_eq!(length(&[0x48, 0x81, 0xEC, 0x00, 0x01, 0x00, 0x00]), Some(7)); // sub rsp, 0x100
+        assert_eq!(length(&[0x48, 0x89, 0x7C, 0x24, 0x10]), Some(5)); // mov [rsp+0x10], rdi
+        assert_eq!(length(&[0x89, 0x7C, 0x24, 0xF4]), Some(4)); // mov [rsp-0xc], edi
+        assert_eq!(
+            length(&[0x48, 0x8B, 0x84, 0x24, 0x00, 0x01, 0x00, 0x00]),
+            Some(8)
+        ); // mov rax, [rsp+0x100]
+        assert_eq!(length(&[0xC7, 0x45, 0xFC, 0x01, 0x00, 0x00, 0x00]), Some(7)); // mov dword [rbp-4], 1
+        assert_eq!(length(&[0x66, 0xC7, 0x45, 0xFC, 0x01, 0x00]), Some(6)); // mov word [rbp-4], 1
+        assert_eq!(length(&[0x48, 0xB8, 1, 2, 3, 4, 5, 6, 7, 8]), Some(10)); // movabs rax, imm64
+        assert_eq!(length(&[0xB8, 0x06, 0x00, 0x00, 0x00]), Some(5)); // mov eax, 6
+        assert_eq!(length(&[0x31, 0xC0]), Some(2)); // xor eax, eax
+        assert_eq!(length(&[0xF6, 0xC1, 0x01]), Some(3)); // test cl, 1
+        assert_eq!(length(&[0xF7, 0xD8]), Some(2)); // neg eax
+        assert_eq!(length(&[0x0F, 0x1F, 0x44, 0x00, 0x00]), Some(5)); // nop dword [rax+rax]
+        assert_eq!(length(&[0x0F, 0xAF, 0xC1]), Some(3)); // imul eax, ecx
+        assert_eq!(length(&[0x66, 0x0F, 0x3A, 0x0F, 0xC1, 0x08]), Some(6)); // palignr xmm0, xmm1, 8
+        assert_eq!(length(&[0xC5, 0xF8, 0x77]), Some(3)); // vzeroupper
+        assert_eq!(length(&[0x48, 0x83, 0xEC]), None); // truncated
+        assert_eq!(length(&[0xC5, 0xFC, 0x28, 0xC1]), Some(4)); // vmovaps ymm0, ymm1
+        assert_eq!(length(&[0xC4, 0xE3, 0x7D, 0x18, 0xC1, 0x01]), Some(6)); // vinsertf128
+        assert_eq!(length(&[0x62, 0xF1, 0x7C, 0x48, 0x28, 0xC1]), None); // EVEX
+    }
+
+    #[test]
+    fn test_decode_address_dependent_instructions() {
+        // lea rax, [rip + 0x10]
+        assert_eq!(
+            decode(&[0x48, 0x8D, 0x05, 0x10, 0x00, 0x00, 0x00]),
+            Some(Instruction {
+                len: 7,
+                relative: Relative::RipDisplacement { offset: 3 },
+            })
+        );
+        // cmp byte [rip + 0x10], 0
+        assert_eq!(
+            decode(&[0x80, 0x3D, 0x10, 0x00, 0x00, 0x00, 0x00]),
+            Some(Instruction {
+                len: 7,
+                relative: Relative::RipDisplacement { offset: 2 },
+            })
+        );
+        // je -2
+        assert_eq!(
+            decode(&[0x74, 0xFE]),
+            Some(Instruction {
+                len: 2,
+                relative: Relative::ConditionalJump {
+                    condition: 4,
+                    displacement: -2,
+                },
+            })
+        );
+        // call +0x100
+        assert_eq!(
+            decode(&[0xE8, 0x00, 0x01, 0x00, 0x00]),
+            Some(Instruction {
+                len: 5,
+                relative: Relative::Call {
+                    displacement: 0x100
+                },
+            })
+        );
+        // loop -2
+        assert_eq!(
+            decode(&[0xE2, 0xFE]).map(|instruction| instruction.relative),
+            Some(Relative::Unsupported)
+        );
+    }
+
+    #[test]
+    fn test_relocate_copies_whole_instructions_and_jumps_back() {
+        // push rbp; mov rbp, rsp; sub rsp, 0x10; ret
+        let code = [0x55, 0x48, 0x89, 0xE5, 0x48, 0x83, 0xEC, 0x10, 0xC3];
+
+        let relocated = relocate(&code, 0x1000, 0x9000, 5).unwrap();
+
+        let mut expected = code[..8].to_vec();
+        expected.extend_from_slice(&[0xFF, 0x25, 0x00, 0x00, 0x00, 0x00]);
+        expected.extend_from_slice(&0x1008u64.to_le_bytes());
+        assert_eq!(relocated, expected);
+    }
+
+    #[test]
+    fn test_relocate_adjusts_rip_relative_operands_and_branches() {
+        // lea rax, [rip + 0x100]; jne +0x20
+        let code = [0x48, 0x8D, 0x05, 0x00, 0x01, 0x00, 0x00, 0x75, 0x20];
+
+        let relocated = relocate(&code, 0x1000, 0x2000, 8).unwrap();
+
+        // The lea still points at 0x1107 and the jne at 0x1029.
+        let lea_displacement = 0x1107i32 - 0x2007;
+        let jne_displacement = 0x1029i32 - (0x2007 + 6);
+
+        let mut expected = vec![0x48, 0x8D, 0x05];
+        expected.extend_from_slice(&lea_displacement.to_le_bytes());
+        expected.extend_from_slice(&[0x0F, 0x85]);
+        expected.extend_from_slice(&jne_displacement.to_le_bytes());
+        expected.extend_from_slice(&[0xFF, 0x25, 0x00, 0x00, 0x00, 0x00]);
+        expected.extend_from_slice(&0x1009u64.to_le_bytes());
+        assert_eq!(relocated, expected);
+
+        // The length does not depend on the destination.
+        assert_eq!(
+            relocate(&code, 0x1000, 0x1000, 8).unwrap().len(),
+            expected.len()
+        );
+    }
+
+    #[test]
+    fn test_relocate_rejects_branches_into_copied_bytes() {
+        // xor eax, eax; jmp -4 (back to the xor); nop
+        let code = [0x31, 0xC0, 0xEB, 0xFC, 0x90, 0x90];
+
+        assert_eq!(
+            relocate(&code, 0x1000, 0x2000, 5),
+            Err(InjectError::UnrelocatableInstruction { address: 0x1002 })
+        );
+    }
+
+    #[test]
+    fn test_instruction_boundary_should_cover_whole_instructions() {
+        // endbr64; push rbp; mov rbp, rsp; sub rsp, 0x10; lea rax, [rip + 0x10]
+        let code = [
+            0xF3, 0x0F, 0x1E, 0xFA, 0x55, 0x48, 0x89, 0xE5, 0x48, 0x83, 0xEC, 0x10, 0x48, 0x8D,
+            0x05, 0x10, 0x00, 0x00, 0x00,
+        ];
+
+        assert_eq!(instruction_boundary(&code, 4), Some(4));
+        assert_eq!(instruction_boundary(&code, 5), Some(5));
+        assert_eq!(instruction_boundary(&code, 6), Some(8));
+        assert_eq!(instruction_boundary(&code, 12), Some(12));
+        assert_eq!(instruction_boundary(&code, 13), Some(19));
+    }
+
+    #[test]
+    fn test_instruction_boundary_when_code_cannot_be_decoded_should_return_none() {
+        // push rbp; truncated sub rsp, imm8
+        assert_eq!(instruction_boundary(&[0x55, 0x48, 0x83, 0xEC], 5), None);
+        // EVEX encoded vmovaps zmm0, zmm1
+        let evex = [0x62, 0xF1, 0x7C, 0x48, 0x28, 0xC1];
+        assert_eq!(instruction_boundary(&evex, 5), None);
+    }
+
+    #[test]
+    fn test_function_end_before_when_next_function_follows_should_return_end() {
+        // xor eax, eax; ret; mov eax, 8; ret
+        let code = [0x31, 0xC0, 0xC3, 0xB8, 0x08, 0x00, 0x00, 0x00, 0xC3];
+        assert_eq!(function_end_before(&code, 5), Some(3));
+
+        // ret; push rbp
+        assert_eq!(
+            function_end_before(&[0xC3, 0x55, 0x90, 0x90, 0x90], 5),
+            Some(1)
+        );
+        // jmp -0x10; push rbp
+        assert_eq!(
+            function_end_before(&[0xEB, 0xF0, 0x55, 0x90, 0x90], 5),
+            Some(2)
+        );
+        // jmp qword [rip + 0]; push rbp
+        let code = [
+            0xFF, 0x25, 0x00, 0x00, 0x00, 0x00, 0x55, 0x90, 0x90, 0x90, 0x90, 0x90,
+        ];
+        assert_eq!(function_end_before(&code, 12), Some(6));
+    }
+
+    #[test]
+    fn test_function_end_before_when_padding_or_long_function_should_return_none() {
+        // xor eax, eax; ret; int3 padding
+        let code = [0x31, 0xC0, 0xC3, 0xCC, 0xCC, 0xCC];
+        assert_eq!(function_end_before(&code, 5), None);
+
+        // ret; nop word cs:[rax + rax] (cut short)
+        let code = [0xC3, 0x66, 0x2E, 0x0F, 0x1F, 0x84];
+        assert_eq!(function_end_before(&code, 5), None);
+
+        // ret; nop; nop dword [rax]
+        let code = [0xC3, 0x90, 0x0F, 0x1F, 0x00];
+        assert_eq!(function_end_before(&code, 5), None);
+
+        // push rbp; mov rbp, rsp; ret
+        let code = [0x55, 0x48, 0x89, 0xE5, 0xC3, 0x55];
+        assert_eq!(function_end_before(&code, 5), None);
+    }
+
+    #[test]
+    fn test_function_end_before_when_branch_jumps_past_ret_should_return_none() {
+        // test edi, edi; je +1; ret; mov eax, 1
+        let code = [0x85, 0xFF, 0x74, 0x01, 0xC3, 0xB8, 0x01, 0x00, 0x00, 0x00];
+        assert_eq!(function_end_before(&code, 12), None);
+
+        // Undecodable code cannot be checked.
+        assert_eq!(function_end_before(&[0x06, 0xC3, 0x55], 5), None);
+    }
+}
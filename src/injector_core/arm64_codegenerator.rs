@@ -1,4 +1,8 @@
-#![cfg(target_arch = "aarch64")]
+//! Encoders for the AArch64 instructions used by the patches.
+//!
+//! Every function only builds bytes and never executes them, so this module is compiled and
+//! tested on every host.
+#![cfg_attr(not(target_arch = "aarch64"), allow(dead_code))]
 
 use crate::injector_core::utils::*;
 
@@ -340,14 +344,383 @@ pub(crate) fn emit_movz(
     code_bits
 }
 
+/// Returns a `ret` through x30.
+pub(crate) fn emit_return_void() -> Vec<u8> {
+    let mut asm_code = Vec::with_capacity(4);
+    append_instruction(&mut asm_code, bool_array_to_u32(emit_ret_x30()));
+    asm_code
+}
+
+/// Generates a 20-byte JIT code block that loads the absolute address of `target`
+/// into register X9 (using a MOVZ and three MOVK instructions) and then branches to X9.
+/// This avoids branch-range limitations.
+///
+/// The generated instructions are:
+///   movz x9, #imm0, lsl #0
+///   movk x9, #imm1, lsl #16
+///   movk x9, #imm2, lsl #32
+///   movk x9, #imm3, lsl #48
+///   br x9
+pub(crate) fn emit_abs_jump(target: usize) -> Vec<u8> {
+    let target_addr = target as u64;
+
+    // x9
+    let register_name: [bool; 5] = u8_to_bits::<5>(9);
+
+    // MOVZ x9, #imm0 (clears the rest)
+    let movz = emit_movz_from_address(target_addr, 0, true, u8_to_bits::<2>(0), register_name);
+
+    // MOVK x9, #imm1, LSL #16
+    let movk1 = emit_movk_from_address(target_addr, 16, true, u8_to_bits::<2>(1), register_name);
+
+    // MOVK x9, #imm2, LSL #32
+    let movk2 = emit_movk_from_address(target_addr, 32, true, u8_to_bits::<2>(2), register_name);
+
+    // MOVK x9, #imm3, LSL #48
+    let movk3 = emit_movk_from_address(target_addr, 48, true, u8_to_bits::<2>(3), register_name);
+
+    // BR x9
+    let br = emit_br(register_name);
+
+    // Write instructions in the correct order: bottom-up so no overwrite
+    let mut asm_code: Vec<u8> = Vec::new();
+    append_instruction(&mut asm_code, bool_array_to_u32(movz));
+    append_instruction(&mut asm_code, bool_array_to_u32(movk1));
+    append_instruction(&mut asm_code, bool_array_to_u32(movk2));
+    append_instruction(&mut asm_code, bool_array_to_u32(movk3));
+    append_instruction(&mut asm_code, bool_array_to_u32(br));
+
+    asm_code
+}
+
+/// Generates an 8-byte JIT code block that returns the specified boolean.
+/// The code moves the immediate into x0, which also zeroes its upper bits, and then returns.
+pub(crate) fn emit_return_boolean(value: bool) -> Vec<u8> {
+    let mut asm_code = [0u8; 8]; // 2 instructions = 2 * 4
+    let mut cursor = 0;
+
+    let mut value_bits = [false; 16];
+    value_bits[0] = value;
+
+    let movz = emit_movz(value_bits, true, u8_to_bits::<2>(0), u8_to_bits::<5>(0));
+    let ret = emit_ret_x30();
+
+    write_instruction(&mut asm_code, &mut cursor, bool_array_to_u32(movz));
+    write_instruction(&mut asm_code, &mut cursor, bool_array_to_u32(ret));
+
+    asm_code.to_vec()
+}
+
+/// Generates a JIT code block that returns the 64-bit `value` in x0.
+///
+/// The generated instructions are a MOVZ of the low 16 bits, a MOVK for each higher 16-bit
+/// chunk that is not zero, and a `ret`, so 8 to 20 bytes.
+pub(crate) fn emit_return_integer(value: u64) -> Vec<u8> {
+    let register_name: [bool; 5] = u8_to_bits::<5>(0);
+    let mut asm_code: Vec<u8> = Vec::with_capacity(20);
+
+    let movz = emit_movz_from_address(value, 0, true, u8_to_bits::<2>(0), register_name);
+    append_instruction(&mut asm_code, bool_array_to_u32(movz));
+
+    for hw in 1..4u8 {
+        if (value >> (16 * hw as u32)) & 0xFFFF == 0 {
+            continue;
+        }
+
+        let movk = emit_movk_from_address(
+            value,
+            16 * hw as usize,
+            true,
+            u8_to_bits::<2>(hw),
+            register_name,
+        );
+        append_instruction(&mut asm_code, bool_array_to_u32(movk));
+    }
+
+    asm_code.extend(emit_return_void());
+    asm_code
+}
+
+/// Generates a 24-byte JIT code block that returns the double whose bit pattern is `bits` in
+/// d0.
+///
+/// The generated instructions are:
+///   movz/movk x9, #bits
+///   fmov d0, x9
+///   ret
+pub(crate) fn emit_return_float(bits: u64) -> Vec<u8> {
+    const FMOV_D0_X9: u32 = 0x9E67_0120;
+
+    let mut asm_code: Vec<u8> = Vec::with_capacity(24);
+    append_mov_imm64(&mut asm_code, 9, bits);
+    append_instruction(&mut asm_code, FMOV_D0_X9);
+    asm_code.extend(emit_return_void());
+
+    asm_code
+}
+
+/// Generates a 36-byte JIT code block that returns `first` in x0 and `second` in x1.
+///
+/// AAPCS64 returns composite types of up to 16 bytes in x0/x1, so this covers
+/// aggregates such as `(u64, u64)` or a `#[repr(C)]` struct of two 64-bit fields.
+///
+/// The generated instructions are:
+///   movz x0, #imm0 / movk x0, #imm1..imm3
+///   movz x1, #imm0 / movk x1, #imm1..imm3
+///   ret
+pub(crate) fn emit_return_pair(first: u64, second: u64) -> Vec<u8> {
+    let mut asm_code: Vec<u8> = Vec::with_capacity(36);
+    append_mov_imm64(&mut asm_code, 0, first);
+    append_mov_imm64(&mut asm_code, 1, second);
+    asm_code.extend(emit_return_void());
+
+    asm_code
+}
+
+/// Generates a JIT code block that atomically increments the 64-bit counter at `counter`
+/// and returns.
+///
+/// The generated instructions are:
+///   movz/movk x9, #counter
+///   ldaxr x10, [x9]
+///   add x10, x10, #1
+///   stlxr w11, x10, [x9]
+///   cbnz w11, #-12
+///   ret
+pub(crate) fn emit_increment_counter(counter: usize) -> Vec<u8> {
+    const LDAXR_X10_X9: u32 = 0xC85F_FD2A;
+    const ADD_X10_X10_1: u32 = 0x9100_054A;
+    const STLXR_W11_X10_X9: u32 = 0xC80B_FD2A;
+    const CBNZ_W11_MINUS_12: u32 = 0x35FF_FFAB;
+
+    let mut asm_code: Vec<u8> = Vec::with_capacity(36);
+    append_mov_imm64(&mut asm_code, 9, counter as u64);
+    append_instruction(&mut asm_code, LDAXR_X10_X9);
+    append_instruction(&mut asm_code, ADD_X10_X10_1);
+    append_instruction(&mut asm_code, STLXR_W11_X10_X9);
+    append_instruction(&mut asm_code, CBNZ_W11_MINUS_12);
+    asm_code.extend(emit_return_void());
+
+    asm_code
+}
+
+/// Generates an 80-byte JIT code block that returns the byte `values[min(n, last)]`, where
+/// `n` is the value of the 64-bit counter at `counter`, which it atomically increments.
+///
+/// The generated instructions are:
+///   movz/movk x9, #counter
+///   ldaxr x10, [x9]
+///   add x11, x10, #1
+///   stlxr w12, x11, [x9]
+///   cbnz w12, #-12
+///   movz/movk x11, #last
+///   cmp x10, x11
+///   csel x10, x11, x10, hi
+///   movz/movk x9, #values
+///   ldrb w0, [x9, x10]
+///   ret
+pub(crate) fn emit_return_byte_sequence(counter: usize, values: usize, last: usize) -> Vec<u8> {
+    const LDAXR_X10_X9: u32 = 0xC85F_FD2A;
+    const ADD_X11_X10_1: u32 = 0x9100_054B;
+    const STLXR_W12_X11_X9: u32 = 0xC80C_FD2B;
+    const CBNZ_W12_MINUS_12: u32 = 0x35FF_FFAC;
+    const CMP_X10_X11: u32 = 0xEB0B_015F;
+    const CSEL_X10_X11_X10_HI: u32 = 0x9A8A_816A;
+    const LDRB_W0_X9_X10: u32 = 0x386A_6920;
+
+    let mut asm_code: Vec<u8> = Vec::with_capacity(80);
+    append_mov_imm64(&mut asm_code, 9, counter as u64);
+    append_instruction(&mut asm_code, LDAXR_X10_X9);
+    append_instruction(&mut asm_code, ADD_X11_X10_1);
+    append_instruction(&mut asm_code, STLXR_W12_X11_X9);
+    append_instruction(&mut asm_code, CBNZ_W12_MINUS_12);
+    append_mov_imm64(&mut asm_code, 11, last as u64);
+    append_instruction(&mut asm_code, CMP_X10_X11);
+    append_instruction(&mut asm_code, CSEL_X10_X11_X10_HI);
+    append_mov_imm64(&mut asm_code, 9, values as u64);
+    append_instruction(&mut asm_code, LDRB_W0_X9_X10);
+    asm_code.extend(emit_return_void());
+
+    asm_code
+}
+
+/// Generates a 52-byte JIT code block that copies the `size` bytes at `value` to the return
+/// slot whose address the caller passed in x8, then returns. `size` must not be zero.
+///
+/// The generated instructions are:
+///   movz/movk x9, #value
+///   movz/movk x10, #size
+/// 1:
+///   ldrb w11, [x9], #1
+///   strb w11, [x8], #1
+///   subs x10, x10, #1
+///   b.ne 1b
+///   ret
+pub(crate) fn emit_return_struct(value: usize, size: usize) -> Vec<u8> {
+    const LDRB_W11_X9_POST_1: u32 = 0x3840_152B;
+    const STRB_W11_X8_POST_1: u32 = 0x3800_150B;
+    const SUBS_X10_X10_1: u32 = 0xF100_054A;
+    const B_NE_MINUS_12: u32 = 0x54FF_FFA1;
+
+    let mut asm_code: Vec<u8> = Vec::with_capacity(52);
+    append_mov_imm64(&mut asm_code, 9, value as u64);
+    append_mov_imm64(&mut asm_code, 10, size as u64);
+    append_instruction(&mut asm_code, LDRB_W11_X9_POST_1);
+    append_instruction(&mut asm_code, STRB_W11_X8_POST_1);
+    append_instruction(&mut asm_code, SUBS_X10_X10_1);
+    append_instruction(&mut asm_code, B_NE_MINUS_12);
+    asm_code.extend(emit_return_void());
+
+    asm_code
+}
+
+/// Returns whether `instruction` is an unconditional `b`. A function starting with one is a
+/// single tail call: nothing after the branch belongs to it.
+pub(crate) fn is_unconditional_branch(instruction: u32) -> bool {
+    instruction & 0xFC00_0000 == 0x1400_0000
+}
+
+/// Returns where the unconditional `b` at `address` jumps to, or `None` if `instruction` is
+/// not one.
+pub(crate) fn unconditional_branch_target(instruction: u32, address: usize) -> Option<usize> {
+    if !is_unconditional_branch(instruction) {
+        return None;
+    }
+
+    // imm26 counts instructions, sign-extended from bit 25.
+    let offset = (((instruction & 0x03FF_FFFF) << 6) as i32 >> 6) as isize * 4;
+
+    Some(address.wrapping_add_signed(offset))
+}
+
+/// Returns whether `instruction` ends a function when it runs: an unconditional `b`, a `br`
+/// or a `ret`.
+pub(crate) fn ends_function(instruction: u32) -> bool {
+    is_unconditional_branch(instruction)
+        || instruction & 0xFFFF_FC1F == 0xD61F_0000
+        || instruction & 0xFFFF_FC1F == 0xD65F_0000
+}
+
+/// Returns how many bytes at the start of `code` belong to the function it starts: up to
+/// and including the first instruction that ends it, or all of `code` if none does.
+///
+/// A conditional branch may skip over that instruction, so this is a lower bound.
+pub(crate) fn function_window(code: &[u8]) -> usize {
+    let words = code.chunks_exact(4);
+    let whole = words.len() * 4;
+
+    words
+        .map(|word| u32::from_le_bytes(word.try_into().unwrap()))
+        .position(ends_function)
+        .map_or(whole, |index| (index + 1) * 4)
+}
+
+/// Returns the position of `x{index}` in the registers saved by `emit_call_hook`, for the
+/// eight integer argument registers.
+pub(crate) fn argument_slot(index: usize) -> Option<usize> {
+    // The pairs are pushed from x0, x1 down to x8, x9, so x8 sits at the lowest address.
+    (index < 8).then(|| 8 - index / 2 * 2 + index % 2)
+}
+
+/// Generates code that calls `hook(data, registers)` and then falls through to the code
+/// placed after it.
+///
+/// The frame record and every argument register (x0-x7, the indirect result register x8
+/// and q0-q7) are saved around the call. `registers` points at the saved x8, see
+/// `argument_slot`. The stack stays 16-byte aligned throughout.
+///
+/// The generated instructions are:
+///   stp x29, x30, [sp, #-16]!
+///   mov x29, sp
+///   stp x0, x1 .. x8, x9, [sp, #-16]!
+///   stp q0, q1 .. q6, q7, [sp, #-32]!
+///   movz/movk x0, #data
+///   add x1, sp, #128
+///   movz/movk x16, #hook
+///   blr x16
+///   ldp (in reverse order)
+pub(crate) fn emit_call_hook(hook: usize, data: usize) -> Vec<u8> {
+    const SP: u32 = 31;
+    const STP_X_PRE: u32 = 0xA980_0000;
+    const LDP_X_POST: u32 = 0xA8C0_0000;
+    const STP_Q_PRE: u32 = 0xAD80_0000;
+    const LDP_Q_POST: u32 = 0xACC0_0000;
+    const MOV_X29_SP: u32 = 0x9100_03FD;
+    const BLR_X16: u32 = 0xD63F_0200;
+    const ADD_X1_SP_128: u32 = 0x9102_03E1;
+
+    // Encodes a pair load/store of `first` and `second` with the scaled 7-bit `offset`.
+    let pair = |base: u32, first: u32, second: u32, offset: i32| -> u32 {
+        base | ((offset as u32 & 0x7F) << 15) | (second << 10) | (SP << 5) | first
+    };
+
+    let mut asm_code: Vec<u8> = Vec::with_capacity(144);
+
+    append_instruction(&mut asm_code, pair(STP_X_PRE, 29, 30, -2));
+    append_instruction(&mut asm_code, MOV_X29_SP);
+    for first in (0..10).step_by(2) {
+        append_instruction(&mut asm_code, pair(STP_X_PRE, first, first + 1, -2));
+    }
+    for first in (0..8).step_by(2) {
+        append_instruction(&mut asm_code, pair(STP_Q_PRE, first, first + 1, -2));
+    }
+
+    append_mov_imm64(&mut asm_code, 0, data as u64);
+    append_instruction(&mut asm_code, ADD_X1_SP_128);
+    append_mov_imm64(&mut asm_code, 16, hook as u64);
+    append_instruction(&mut asm_code, BLR_X16);
+
+    for first in (0..8).step_by(2).rev() {
+        append_instruction(&mut asm_code, pair(LDP_Q_POST, first, first + 1, 2));
+    }
+    for first in (0..10).step_by(2).rev() {
+        append_instruction(&mut asm_code, pair(LDP_X_POST, first, first + 1, 2));
+    }
+    append_instruction(&mut asm_code, pair(LDP_X_POST, 29, 30, 2));
+
+    asm_code
+}
+
+/// Appends a MOVZ followed by three MOVKs that load the full 64-bit `value` into register `x{register}`.
+pub(crate) fn append_mov_imm64(asm_code: &mut Vec<u8>, register: u8, value: u64) {
+    let register_name: [bool; 5] = u8_to_bits::<5>(register);
+
+    let movz = emit_movz_from_address(value, 0, true, u8_to_bits::<2>(0), register_name);
+    append_instruction(asm_code, bool_array_to_u32(movz));
+
+    for hw in 1..4u8 {
+        let movk = emit_movk_from_address(
+            value,
+            16 * hw as usize,
+            true,
+            u8_to_bits::<2>(hw),
+            register_name,
+        );
+        append_instruction(asm_code, bool_array_to_u32(movk));
+    }
+}
+
+#[inline]
+fn write_instruction(buf: &mut [u8], cursor: &mut usize, instruction: u32) {
+    let bytes = instruction.to_le_bytes();
+    buf[*cursor..*cursor + 4].copy_from_slice(&bytes);
+    *cursor += 4;
+}
+
+pub(crate) fn append_instruction(asm_code: &mut Vec<u8>, instruction: u32) {
+    asm_code.push((instruction & 0xFF) as u8);
+    asm_code.push(((instruction >> 8) & 0xFF) as u8);
+    asm_code.push(((instruction >> 16) & 0xFF) as u8);
+    asm_code.push(((instruction >> 24) & 0xFF) as u8);
+}
+
 /// Emit machine code for a long jump if the target falls out of range of the +-128MB bounds imposed
 /// by ARM's branch instruction. If it is, we use the x16 register to store the address and jump
-/// there as such:
+/// there as such, which reaches +-4GB:
 ///
 /// ADRP x16, target
 /// ADD x16, x16, #:lo12:
 /// BR x16
-#[cfg(target_os = "macos")]
 pub(crate) fn maybe_emit_long_jump(pc: usize, target: usize) -> Vec<u32> {
     // We are storing the address in x16.
     const REGISTER: u32 = 16;
@@ -387,3 +760,251 @@ pub(crate) fn maybe_emit_long_jump(pc: usize, target: usize) -> Vec<u32> {
 
     words
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_is_unconditional_branch() {
+        // b #0x100, b #-4
+        assert!(is_unconditional_branch(0x1400_0040));
+        assert!(is_unconditional_branch(0x17FF_FFFF));
+        // bl #0x100, b.eq #0x10, ret
+        assert!(!is_unconditional_branch(0x9400_0040));
+        assert!(!is_unconditional_branch(0x5400_0080));
+        assert!(!is_unconditional_branch(0xD65F_03C0));
+    }
+
+    #[test]
+    fn test_unconditional_branch_target() {
+        // b #0x100, b #-4, b #-0x8000000
+        assert_eq!(
+            unconditional_branch_target(0x1400_0040, 0x10000),
+            Some(0x10100)
+        );
+        assert_eq!(
+            unconditional_branch_target(0x17FF_FFFF, 0x10000),
+            Some(0xFFFC)
+        );
+        assert_eq!(
+            unconditional_branch_target(0x1600_0000, 0x1000_0000),
+            Some(0x0800_0000)
+        );
+        // bl #0x100
+        assert_eq!(unconditional_branch_target(0x9400_0040, 0x10000), None);
+    }
+
+    #[test]
+    fn test_ends_function() {
+        // b #-4, br x16, ret, ret x1
+        assert!(ends_function(0x17FF_FFFF));
+        assert!(ends_function(0xD61F_0200));
+        assert!(ends_function(0xD65F_03C0));
+        assert!(ends_function(0xD65F_0020));
+        // bl #0x100, blr x16, b.eq #0x10, nop
+        assert!(!ends_function(0x9400_0040));
+        assert!(!ends_function(0xD63F_0200));
+        assert!(!ends_function(0x5400_0080));
+        assert!(!ends_function(0xD503_201F));
+    }
+
+    #[test]
+    fn test_function_window() {
+        let code =
+            |words: &[u32]| -> Vec<u8> { words.iter().flat_map(|w| w.to_le_bytes()).collect() };
+
+        // ret; mov w0, #7
+        assert_eq!(function_window(&code(&[0xD65F_03C0, 0x5280_00E0])), 4);
+        // mov w0, #1; ret; mov w0, #7
+        assert_eq!(
+            function_window(&code(&[0x5280_0020, 0xD65F_03C0, 0x5280_00E0])),
+            8
+        );
+        // mov w0, #1; add w0, w0, #1; ret
+        assert_eq!(
+            function_window(&code(&[0x5280_0020, 0x1100_0400, 0xD65F_03C0])),
+            12
+        );
+        // mov w0, #1; add w0, w0, #1; add w0, w0, #1
+        assert_eq!(
+            function_window(&code(&[0x5280_0020, 0x1100_0400, 0x1100_0400])),
+            12
+        );
+        // A function cut short by the end of readable memory.
+        assert_eq!(function_window(&code(&[0x5280_0020])), 4);
+    }
+
+    #[test]
+    fn test_maybe_emit_long_jump_within_branch_range_should_emit_b() {
+        // b #0x7fffffc, b #-0x8000000
+        assert_eq!(
+            maybe_emit_long_jump(0x1000_0000, 0x17FF_FFFC),
+            vec![0x15FF_FFFF]
+        );
+        assert_eq!(
+            maybe_emit_long_jump(0x1000_0000, 0x0800_0000),
+            vec![0x1600_0000]
+        );
+    }
+
+    #[test]
+    fn test_maybe_emit_long_jump_beyond_branch_range_should_branch_through_x16() {
+        // adrp x16, #0x40000000; add x16, x16, #0x345; br x16
+        assert_eq!(
+            maybe_emit_long_jump(0x5555_0000_0234, 0x5555_4000_0345),
+            vec![0x9020_0010, 0x910D_1610, 0xD61F_0200]
+        );
+        // adrp x16, #-0x40000000 from a pc in the middle of its page
+        assert_eq!(
+            maybe_emit_long_jump(0x5555_4000_0800, 0x5555_0000_0010),
+            vec![0x90E0_0010, 0x9100_4210, 0xD61F_0200]
+        );
+    }
+
+    #[test]
+    fn test_emit_return_void_encoding() {
+        // ret (x30) = 0xD65F03C0
+        assert_eq!(emit_return_void(), vec![0xC0, 0x03, 0x5F, 0xD6]);
+    }
+
+    #[test]
+    fn test_emit_abs_jump_encoding() {
+        let expected: Vec<u8> = [
+            0xD28ACF09u32, // movz x9, #0x5678
+            0xF2A24689,    // movk x9, #0x1234, lsl #16
+            0xF2DFFFE9,    // movk x9, #0xffff, lsl #32
+            0xF2E00009,    // movk x9, #0x0, lsl #48
+            0xD61F0120,    // br x9
+        ]
+        .iter()
+        .flat_map(|instruction| instruction.to_le_bytes())
+        .collect();
+
+        assert_eq!(emit_abs_jump(0x0000_FFFF_1234_5678), expected);
+    }
+
+    #[test]
+    fn test_argument_slot_matches_push_order() {
+        let slots: Vec<_> = (0..9).map(argument_slot).collect();
+        assert_eq!(
+            slots,
+            [8, 9, 6, 7, 4, 5, 2, 3]
+                .into_iter()
+                .map(Some)
+                .chain([None])
+                .collect::<Vec<_>>()
+        );
+    }
+
+    #[test]
+    fn test_emit_return_boolean_encoding() {
+        // movz x0, #1; ret
+        assert_eq!(
+            emit_return_boolean(true),
+            vec![0x20, 0x00, 0x80, 0xD2, 0xC0, 0x03, 0x5F, 0xD6]
+        );
+    }
+
+    #[test]
+    fn test_emit_return_integer_encoding() {
+        let words = |values: &[u32]| -> Vec<u8> {
+            values
+                .iter()
+                .flat_map(|instruction| instruction.to_le_bytes())
+                .collect()
+        };
+
+        // movz x0, #0; ret
+        assert_eq!(emit_return_integer(0), words(&[0xD2800000, 0xD65F03C0]));
+        // movz x0, #0x2a; movk x0, #0x1, lsl #48; ret
+        assert_eq!(
+            emit_return_integer(0x0001_0000_0000_002A),
+            words(&[0xD2800540, 0xF2E00020, 0xD65F03C0])
+        );
+        // movz x0, #0xdef0; movk x0, #0x9abc, lsl #16; movk x0, #0x5678, lsl #32;
+        // movk x0, #0x1234, lsl #48; ret
+        assert_eq!(
+            emit_return_integer(0x1234_5678_9ABC_DEF0),
+            words(&[0xD29BDE00, 0xF2B35780, 0xF2CACF00, 0xF2E24680, 0xD65F03C0])
+        );
+    }
+
+    #[test]
+    fn test_emit_return_float_encoding() {
+        let code = emit_return_float(1.5f64.to_bits());
+        let expected: Vec<u8> = [
+            0xD2800009u32, // movz x9, #0
+            0xF2A00009,    // movk x9, #0, lsl #16
+            0xF2C00009,    // movk x9, #0, lsl #32
+            0xF2E7FF09,    // movk x9, #0x3ff8, lsl #48
+            0x9E670120,    // fmov d0, x9
+            0xD65F03C0,    // ret
+        ]
+        .iter()
+        .flat_map(|instruction| instruction.to_le_bytes())
+        .collect();
+
+        assert_eq!(code, expected);
+    }
+
+    #[test]
+    fn test_emit_increment_counter_encoding() {
+        let code = emit_increment_counter(0x1000);
+        let expected: Vec<u8> = [
+            0xC85FFD2Au32, // ldaxr x10, [x9]
+            0x9100054A,    // add x10, x10, #1
+            0xC80BFD2A,    // stlxr w11, x10, [x9]
+            0x35FFFFAB,    // cbnz w11, #-12
+            0xD65F03C0,    // ret
+        ]
+        .iter()
+        .flat_map(|instruction| instruction.to_le_bytes())
+        .collect();
+
+        assert_eq!(code[16..], expected[..]);
+    }
+
+    #[test]
+    fn test_emit_return_byte_sequence_encoding() {
+        let code = emit_return_byte_sequence(0x1000, 0x2000, 2);
+        let words: Vec<u32> = code
+            .chunks_exact(4)
+            .map(|bytes| u32::from_le_bytes(bytes.try_into().unwrap()))
+            .collect();
+
+        assert_eq!(code.len(), 80);
+        // ldaxr x10, [x9]; add x11, x10, #1; stlxr w12, x11, [x9]; cbnz w12, #-12
+        assert_eq!(
+            words[4..8],
+            [0xC85FFD2A, 0x9100054B, 0xC80CFD2B, 0x35FFFFAC]
+        );
+        // movz x11, #2
+        assert_eq!(words[8], 0xD280004B);
+        // cmp x10, x11; csel x10, x11, x10, hi
+        assert_eq!(words[12..14], [0xEB0B015F, 0x9A8A816A]);
+        // movk x9, #0x2000
+        assert_eq!(words[14], 0xD2840009);
+        // ldrb w0, [x9, x10]; ret
+        assert_eq!(words[18..], [0x386A6920, 0xD65F03C0]);
+    }
+
+    #[test]
+    fn test_emit_return_struct_encoding() {
+        let code = emit_return_struct(0x1000, 64);
+        let words: Vec<u32> = code
+            .chunks_exact(4)
+            .map(|bytes| u32::from_le_bytes(bytes.try_into().unwrap()))
+            .collect();
+
+        assert_eq!(code.len(), 52);
+        // movz x9, #0x1000; movz x10, #64
+        assert_eq!(words[0], 0xD2820009);
+        assert_eq!(words[4], 0xD280080A);
+        // ldrb w11, [x9], #1; strb w11, [x8], #1; subs x10, x10, #1; b.ne #-12; ret
+        assert_eq!(
+            words[8..],
+            [0x3840152B, 0x3800150B, 0xF100054A, 0x54FFFFA1, 0xD65F03C0]
+        );
+    }
+}
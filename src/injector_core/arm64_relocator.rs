@@ -0,0 +1,210 @@
+//! Copies the first instructions of an AArch64 function elsewhere so the function can still
+//! be called once a patch overwrites them.
+//!
+//! Every PC-relative instruction is rewritten into an equivalent sequence that uses absolute
+//! addresses, so the result does not depend on where it is placed. Like the code generator
+//! this never executes anything, so it is compiled and tested on every host.
+#![cfg_attr(not(target_arch = "aarch64"), allow(dead_code))]
+
+use crate::injector_core::arm64_codegenerator::{append_instruction, append_mov_imm64};
+use crate::interface::error::InjectError;
+
+/// The intra-procedure-call scratch register used by the rewritten branches.
+const SCRATCH: u8 = 17;
+
+const BR_X17: u32 = 0xD61F_0220;
+const BLR_X17: u32 = 0xD63F_0220;
+
+/// The number of instructions the `b.cond`, `cbz` and `tbz` rewrites skip when the original
+/// branch is not taken: the branch itself plus a four-instruction move and a `br`.
+const SKIP_ABSOLUTE_JUMP: u32 = 6;
+
+/// Copies the `len` bytes of instructions at the start of `code`, which lives at `from`, and
+/// appends a jump to the first instruction left in place.
+///
+/// Branches, `adr`, `adrp` and literal loads are rewritten to reach the same addresses from
+/// anywhere. The rewritten branches and the final jump clobber x17, which the procedure call
+/// standard reserves for this purpose.
+///
+/// Fails if a branch targets one of the copied instructions.
+pub(crate) fn relocate(code: &[u8], from: usize, len: usize) -> Result<Vec<u8>, InjectError> {
+    let copied = from..from + len;
+    let mut relocated = Vec::with_capacity(len * 6 + 20);
+
+    for (index, bytes) in code[..len].chunks_exact(4).enumerate() {
+        let pc = from + index * 4;
+        let instruction = u32::from_le_bytes(bytes.try_into().unwrap());
+
+        let branch_target = |displacement: i64| -> Result<u64, InjectError> {
+            let target = (pc as i64 + displacement) as usize;
+            if copied.contains(&target) {
+                return Err(InjectError::UnrelocatableInstruction { address: pc });
+            }
+            Ok(target as u64)
+        };
+
+        if instruction & 0x7C00_0000 == 0x1400_0000 {
+            // b and bl
+            let target = branch_target(sign_extend(instruction & 0x03FF_FFFF, 26) * 4)?;
+            let link = instruction & 0x8000_0000 != 0;
+
+            append_mov_imm64(&mut relocated, SCRATCH, target);
+            append_instruction(&mut relocated, if link { BLR_X17 } else { BR_X17 });
+        } else if instruction & 0xFF00_0010 == 0x5400_0000 {
+            // b.cond, where "always" and "never" both branch unconditionally.
+            let target = branch_target(sign_extend((instruction >> 5) & 0x7_FFFF, 19) * 4)?;
+            let condition = instruction & 0xF;
+
+            if condition < 0xE {
+                let inverted = 0x5400_0000 | (SKIP_ABSOLUTE_JUMP << 5) | (condition ^ 1);
+                append_instruction(&mut relocated, inverted);
+            }
+            append_mov_imm64(&mut relocated, SCRATCH, target);
+            append_instruction(&mut relocated, BR_X17);
+        } else if instruction & 0x7E00_0000 == 0x3400_0000 {
+            // cbz and cbnz
+            let target = branch_target(sign_extend((instruction >> 5) & 0x7_FFFF, 19) * 4)?;
+            let inverted = ((instruction & 0xFF00_001F) ^ 0x0100_0000) | (SKIP_ABSOLUTE_JUMP << 5);
+
+            append_instruction(&mut relocated, inverted);
+            append_mov_imm64(&mut relocated, SCRATCH, target);
+            append_instruction(&mut relocated, BR_X17);
+        } else if instruction & 0x7E00_0000 == 0x3600_0000 {
+            // tbz and tbnz
+            let target = branch_target(sign_extend((instruction >> 5) & 0x3FFF, 14) * 4)?;
+            let inverted = ((instruction & 0xFFF8_001F) ^ 0x0100_0000) | (SKIP_ABSOLUTE_JUMP << 5);
+
+            append_instruction(&mut relocated, inverted);
+            append_mov_imm64(&mut relocated, SCRATCH, target);
+            append_instruction(&mut relocated, BR_X17);
+        } else if instruction & 0x1F00_0000 == 0x1000_0000 {
+            // adr and adrp
+            let immediate = sign_extend(
+                ((instruction >> 3) & 0x1F_FFFC) | ((instruction >> 29) & 0x3),
+                21,
+            );
+            let value = if instruction & 0x8000_0000 != 0 {
+                (pc as i64 & !0xFFF) + (immediate << 12)
+            } else {
+                pc as i64 + immediate
+            };
+
+            append_mov_imm64(&mut relocated, (instruction & 0x1F) as u8, value as u64);
+        } else if instruction & 0x3B00_0000 == 0x1800_0000 {
+            // Literal loads
+            let address = (pc as i64 + sign_extend((instruction >> 5) & 0x7_FFFF, 19) * 4) as u64;
+            let register = instruction & 0x1F;
+            let simd = instruction & 0x0400_0000 != 0;
+
+            // The equivalent load from the address in x{base}, with no offset.
+            let load = match (instruction >> 30, simd) {
+                (0, false) => Some(0xB940_0000),
+                (1, false) => Some(0xF940_0000),
+                (2, false) => Some(0xB980_0000),
+                // prfm has no effect on the result, drop it.
+                (3, false) => None,
+                (0, true) => Some(0xBD40_0000),
+                (1, true) => Some(0xFD40_0000),
+                (2, true) => Some(0x3DC0_0000),
+                _ => return Err(InjectError::UnrelocatableInstruction { address: pc }),
+            };
+
+            if let Some(load) = load {
+                // A general purpose destination can hold its own address.
+                let base = if simd { SCRATCH as u32 } else { register };
+                append_mov_imm64(&mut relocated, base as u8, address);
+                append_instruction(&mut relocated, load | (base << 5) | register);
+            }
+        } else {
+            append_instruction(&mut relocated, instruction);
+        }
+    }
+
+    append_mov_imm64(&mut relocated, SCRATCH, (from + len) as u64);
+    append_instruction(&mut relocated, BR_X17);
+
+    Ok(relocated)
+}
+
+/// Sign extends the `bits` wide `value`.
+fn sign_extend(value: u32, bits: u32) -> i64 {
+    let shift = 64 - bits;
+    ((value as i64) << shift) >> shift
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn words(code: &[u8]) -> Vec<u32> {
+        code.chunks_exact(4)
+            .map(|bytes| u32::from_le_bytes(bytes.try_into().unwrap()))
+            .collect()
+    }
+
+    fn bytes(words: &[u32]) -> Vec<u8> {
+        words.iter().flat_map(|word| word.to_le_bytes()).collect()
+    }
+
+    /// movz/movk x{register} for `value`, then `last`.
+    fn absolute(register: u8, value: u64, last: u32) -> Vec<u32> {
+        let mut code = Vec::new();
+        append_mov_imm64(&mut code, register, value);
+        append_instruction(&mut code, last);
+        words(&code)
+    }
+
+    #[test]
+    fn test_relocate_copies_plain_instructions_and_jumps_back() {
+        // stp x29, x30, [sp, #-16]!; mov x29, sp; sub sp, sp, #32
+        let code = bytes(&[0xA9BF_7BFD, 0x9100_03FD, 0xD100_83FF]);
+
+        let relocated = words(&relocate(&code, 0x1000, 12).unwrap());
+
+        let mut expected = vec![0xA9BF_7BFD, 0x9100_03FD, 0xD100_83FF];
+        expected.extend(absolute(17, 0x100C, BR_X17));
+        assert_eq!(relocated, expected);
+    }
+
+    #[test]
+    fn test_relocate_rewrites_adrp_and_bl() {
+        // adrp x8, #0x1000; bl #0x100; nop
+        let code = bytes(&[0xB000_0008, 0x9400_0040, 0xD503_201F]);
+
+        let relocated = words(&relocate(&code, 0x1000, 12).unwrap());
+
+        let mut expected = absolute(8, 0x2000, 0);
+        expected.pop();
+        expected.extend(absolute(17, 0x1104, BLR_X17));
+        expected.push(0xD503_201F);
+        expected.extend(absolute(17, 0x100C, BR_X17));
+        assert_eq!(relocated, expected);
+    }
+
+    #[test]
+    fn test_relocate_rewrites_conditional_branches_and_literal_loads() {
+        // cbz x0, #0x40; ldr x1, #0x80; b.ne #0x20
+        let code = bytes(&[0xB400_0200, 0x5800_0401, 0x5400_0101]);
+
+        let relocated = words(&relocate(&code, 0x1000, 12).unwrap());
+
+        let mut expected = vec![0xB500_00C0]; // cbnz x0, #24
+        expected.extend(absolute(17, 0x1040, BR_X17));
+        expected.extend(absolute(1, 0x1084, 0xF940_0021)); // ldr x1, [x1]
+        expected.push(0x5400_00C0); // b.eq #24
+        expected.extend(absolute(17, 0x1028, BR_X17));
+        expected.extend(absolute(17, 0x100C, BR_X17));
+        assert_eq!(relocated, expected);
+    }
+
+    #[test]
+    fn test_relocate_rejects_branches_into_copied_instructions() {
+        // nop; b #-4; nop
+        let code = bytes(&[0xD503_201F, 0x17FF_FFFF, 0xD503_201F]);
+
+        assert_eq!(
+            relocate(&code, 0x1000, 12),
+            Err(InjectError::UnrelocatableInstruction { address: 0x1004 })
+        );
+    }
+}
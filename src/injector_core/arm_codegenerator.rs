@@ -0,0 +1,188 @@
+//! Encoders for the 32-bit ARM (A32) and Thumb (T32) instructions used by the patches.
+//!
+//! Addresses are those of the instructions, without the Thumb bit of function pointers.
+//! Every function only builds bytes and never executes them, so this module is compiled and
+//! tested on every host.
+#![cfg_attr(not(target_arch = "arm"), allow(dead_code))]
+
+/// `bx lr`.
+const A32_BX_LR: u32 = 0xE12F_FF1E;
+const T32_BX_LR: u16 = 0x4770;
+
+/// `mov r8, r8`, the T32 nop.
+const T32_NOP: u16 = 0x46C0;
+
+fn halfwords(code: &[u16]) -> Vec<u8> {
+    code.iter()
+        .flat_map(|halfword| halfword.to_le_bytes())
+        .collect()
+}
+
+fn words(code: &[u32]) -> Vec<u8> {
+    code.iter().flat_map(|word| word.to_le_bytes()).collect()
+}
+
+/// Returns `mov r0, #value; bx lr`, which returns `value` from the function it is written
+/// over: 8 bytes of A32 code, or 4 bytes of T32 code with `movs`.
+pub(crate) fn emit_return_boolean(value: bool, thumb: bool) -> Vec<u8> {
+    if thumb {
+        halfwords(&[0x2000 | value as u16, T32_BX_LR])
+    } else {
+        words(&[0xE3A0_0000 | value as u32, A32_BX_LR])
+    }
+}
+
+/// Returns whether `patch` was made by `emit_return_boolean`.
+pub(crate) fn is_return_boolean(patch: &[u8]) -> bool {
+    [false, true].iter().any(|&value| {
+        [false, true]
+            .iter()
+            .any(|&thumb| emit_return_boolean(value, thumb) == patch)
+    })
+}
+
+/// Returns a `b` at `pc` to `target`, or `None` if `target` is out of its reach: ±32MB for
+/// A32 and ±16MB for the T32 `b.w`.
+///
+/// The branch keeps the instruction set, so `target` must be code of the same one.
+pub(crate) fn emit_near_branch(pc: usize, target: usize, thumb: bool) -> Option<Vec<u8>> {
+    if thumb {
+        // Relative to the branch plus 4, split into S:I1:I2:imm10:imm11, with J1 and J2
+        // encoding I1 and I2 as NOT(I XOR S).
+        let offset = target as i64 - (pc as i64 + 4);
+        if offset % 2 != 0 || !(-(1 << 24)..(1 << 24)).contains(&offset) {
+            return None;
+        }
+
+        let imm = offset as u32;
+        let s = (imm >> 24) & 1;
+        let j1 = ((imm >> 23) & 1 ^ 1) ^ s;
+        let j2 = ((imm >> 22) & 1 ^ 1) ^ s;
+
+        Some(halfwords(&[
+            (0xF000 | (s << 10) | ((imm >> 12) & 0x3FF)) as u16,
+            (0x9000 | (j1 << 13) | (j2 << 11) | ((imm >> 1) & 0x7FF)) as u16,
+        ]))
+    } else {
+        // Relative to the branch plus 8, in words.
+        let offset = target as i64 - (pc as i64 + 8);
+        if offset % 4 != 0 || !(-(1 << 25)..(1 << 25)).contains(&offset) {
+            return None;
+        }
+
+        Some(words(&[0xEA00_0000 | ((offset >> 2) as u32 & 0x00FF_FFFF)]))
+    }
+}
+
+/// Returns whether `patch` starts with a branch made by `emit_near_branch`.
+pub(crate) fn is_near_branch(patch: &[u8]) -> bool {
+    match patch {
+        [_, _, _, 0xEA, ..] => true,
+        [_, first, _, second, ..] => first & 0xF8 == 0xF0 && second & 0xD0 == 0x90,
+        _ => false,
+    }
+}
+
+/// Returns 12 bytes at `pc` jumping to `target`, anywhere in the address space and in
+/// either instruction set, through a literal holding its address.
+///
+/// T32 code loads the literal into r7, which must be word aligned, so a nop comes first at
+/// a `pc` that is not.
+pub(crate) fn emit_literal_jump(pc: usize, target: usize, thumb: bool) -> Vec<u8> {
+    if thumb {
+        // ldr r7, [pc, #0]; bx r7; .word target
+        let mut code = halfwords(&[0x4F00, 0x4738]);
+        code.extend((target as u32).to_le_bytes());
+
+        if pc.is_multiple_of(4) {
+            code.extend([0; 2]);
+        } else {
+            code.splice(0..0, T32_NOP.to_le_bytes());
+        }
+
+        code.extend([0; 2]);
+        code
+    } else {
+        // ldr r9, [pc, #-0]; bx r9; .word target
+        words(&[0xE51F_9000, 0xE12F_FF19, target as u32])
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_emit_return_boolean() {
+        // mov r0, #1; bx lr
+        assert_eq!(
+            emit_return_boolean(true, false),
+            [0x01, 0x00, 0xA0, 0xE3, 0x1E, 0xFF, 0x2F, 0xE1]
+        );
+        // movs r0, #0; bx lr
+        assert_eq!(emit_return_boolean(false, true), [0x00, 0x20, 0x70, 0x47]);
+
+        assert!(is_return_boolean(&emit_return_boolean(false, false)));
+        assert!(is_return_boolean(&emit_return_boolean(true, true)));
+        assert!(!is_return_boolean(&emit_literal_jump(0x1000, 0x2000, true)));
+    }
+
+    #[test]
+    fn test_emit_near_branch_a32() {
+        // b 0x1008 at 0, b 0 at 4
+        assert_eq!(
+            emit_near_branch(0, 0x1008, false),
+            Some(vec![0x00, 0x04, 0x00, 0xEA])
+        );
+        assert_eq!(
+            emit_near_branch(4, 0, false),
+            Some(vec![0xFD, 0xFF, 0xFF, 0xEA])
+        );
+
+        assert!(emit_near_branch(0x1000_0000, 0x1000_0008 + (32 << 20) - 4, false).is_some());
+        assert_eq!(
+            emit_near_branch(0x1000_0000, 0x1000_0008 + (32 << 20), false),
+            None
+        );
+    }
+
+    #[test]
+    fn test_emit_near_branch_t32() {
+        // b.w 0x1008 at 0, b.w 0 at 4
+        assert_eq!(
+            emit_near_branch(0, 0x1008, true),
+            Some(vec![0x01, 0xF0, 0x02, 0xB8])
+        );
+        assert_eq!(
+            emit_near_branch(4, 0, true),
+            Some(vec![0xFF, 0xF7, 0xFC, 0xBF])
+        );
+
+        assert!(emit_near_branch(0x1000_0000, 0x1000_0004 - (16 << 20), true).is_some());
+        assert_eq!(
+            emit_near_branch(0x1000_0000, 0x1000_0004 + (16 << 20), true),
+            None
+        );
+    }
+
+    #[test]
+    fn test_is_near_branch() {
+        assert!(is_near_branch(&emit_near_branch(0, 0x1008, false).unwrap()));
+        assert!(is_near_branch(&emit_near_branch(4, 0, true).unwrap()));
+        assert!(!is_near_branch(&emit_literal_jump(0x1000, 0x2000, false)));
+        assert!(!is_near_branch(&emit_literal_jump(0x1000, 0x2000, true)));
+        assert!(!is_near_branch(&emit_literal_jump(0x1002, 0x2000, true)));
+    }
+
+    #[test]
+    fn test_emit_literal_jump_t32_when_pc_not_word_aligned_should_start_with_nop() {
+        assert_eq!(
+            emit_literal_jump(0x1000, 0x1234_5679, true),
+            [0x00, 0x4F, 0x38, 0x47, 0x79, 0x56, 0x34, 0x12, 0x00, 0x00, 0x00, 0x00]
+        );
+        assert_eq!(
+            emit_literal_jump(0x1002, 0x1234_5679, true),
+            [0xC0, 0x46, 0x00, 0x4F, 0x38, 0x47, 0x79, 0x56, 0x34, 0x12, 0x00, 0x00]
+        );
+    }
+}
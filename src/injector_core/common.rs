@@ -1,11 +1,38 @@
 use libc::*;
+use std::cell::RefCell;
+use std::ops::Range;
 use std::ptr;
 use std::ptr::NonNull;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+
+use crate::interface::error::InjectError;
+use crate::interface::failure::record_restore_failure;
+use crate::interface::options::JitAllocStrategy;
+use crate::interface::restore::{notify_restore, RestoreInfo};
+
+#[cfg(any(
+    target_arch = "aarch64",
+    target_arch = "x86_64",
+    target_arch = "riscv64",
+    target_arch = "x86"
+))]
+use crate::injector_core::jit_arena::JitArena;
+use std::sync::{Mutex, PoisonError};
+
+#[cfg(any(
+    target_arch = "aarch64",
+    target_arch = "x86_64",
+    target_arch = "riscv64"
+))]
+use crate::injector_core::jit_search::{jit_candidates, USER_SPACE};
 
 #[cfg(target_os = "windows")]
 use crate::injector_core::winapi::*;
 
-#[cfg(target_os = "linux")]
+#[cfg(all(
+    target_os = "linux",
+    not(any(target_arch = "x86", target_arch = "x86_64", target_arch = "aarch64"))
+))]
 use crate::injector_core::linuxapi::*;
 
 #[cfg(target_os = "macos")]
@@ -40,18 +67,364 @@ impl FuncPtrInternal {
 }
 
 /// Allocates a block of executable memory near the provided source address,
-/// ensuring that the allocated memory lies within ±128MB of the source.
+/// ensuring that the allocated memory lies within branch range of the source, see
+/// `jit_max_range`.
 /// This mirrors the C++ approach.
-#[cfg(any(target_arch = "aarch64", target_arch = "x86_64"))]
+///
+/// # Panics
+/// Panics with the error of `try_allocate_jit_memory` if it fails.
+#[cfg(any(
+    target_arch = "aarch64",
+    target_arch = "x86_64",
+    target_arch = "riscv64",
+    target_arch = "x86"
+))]
 pub(crate) fn allocate_jit_memory(src: &FuncPtrInternal, code_size: usize) -> *mut u8 {
+    try_allocate_jit_memory(src, code_size).unwrap_or_else(|error| panic!("{error}"))
+}
+
+/// The pages of JIT memory that stubs of up to a page are allocated from.
+///
+/// A page is shared by every patch within branch range of it and unmapped once its last stub
+/// is freed, so faking many nearby functions maps a handful of pages instead of one each.
+#[cfg(any(
+    target_arch = "aarch64",
+    target_arch = "x86_64",
+    target_arch = "riscv64",
+    target_arch = "x86"
+))]
+static JIT_ARENAS: Mutex<Vec<JitArena>> = Mutex::new(Vec::new());
+
+/// Where the search for new JIT memory starts, set by the injector currently alive.
+static JIT_ALLOC_STRATEGY: Mutex<JitAllocStrategy> = Mutex::new(JitAllocStrategy::Nearest);
+
+/// Makes the following JIT allocations search for memory according to `strategy`.
+pub(crate) fn set_jit_alloc_strategy(strategy: JitAllocStrategy) {
+    *JIT_ALLOC_STRATEGY
+        .lock()
+        .unwrap_or_else(PoisonError::into_inner) = strategy;
+}
+
+/// Whether memory is never writable and executable at once, set by the injector currently
+/// alive, see `InjectorOptions::w_xor_x`.
+static W_XOR_X: AtomicBool = AtomicBool::new(false);
+
+/// Makes the following patches avoid leaving memory writable and executable, see
+/// `InjectorOptions::w_xor_x`.
+pub(crate) fn set_w_xor_x(enabled: bool) {
+    W_XOR_X.store(enabled, Ordering::Relaxed);
+}
+
+fn w_xor_x() -> bool {
+    W_XOR_X.load(Ordering::Relaxed)
+}
+
+/// Returns the address the search for JIT memory near `original` starts at.
+#[cfg(any(
+    target_arch = "aarch64",
+    target_arch = "x86_64",
+    target_arch = "riscv64"
+))]
+fn jit_search_origin(original: u64) -> u64 {
+    let strategy = *JIT_ALLOC_STRATEGY
+        .lock()
+        .unwrap_or_else(PoisonError::into_inner);
+
+    // `jit_candidates` starts an origin outside the reachable window at its nearest end.
+    match strategy {
+        JitAllocStrategy::Nearest => original,
+        JitAllocStrategy::Ascending => 0,
+        JitAllocStrategy::Hint(address) => address as u64,
+    }
+}
+
+/// Like `allocate_jit_memory`, but returns `InjectError::AllocationFailed` when no memory can
+/// be allocated at all and `InjectError::OutOfBranchRange` when none is close enough.
+#[cfg(any(
+    target_arch = "aarch64",
+    target_arch = "x86_64",
+    target_arch = "riscv64",
+    target_arch = "x86"
+))]
+pub(crate) fn try_allocate_jit_memory(
+    src: &FuncPtrInternal,
+    code_size: usize,
+) -> Result<*mut u8, InjectError> {
+    // A W^X page can no longer be written once executable, so its stubs cannot share it.
+    let page_size = jit_page_size();
+    if code_size > page_size || w_xor_x() {
+        return map_jit_memory(src, code_size);
+    }
+
+    let mut arenas = JIT_ARENAS.lock().unwrap_or_else(PoisonError::into_inner);
+
+    for arena in arenas.iter_mut() {
+        if !is_in_branch_range(&arena.page(), src) {
+            continue;
+        }
+
+        if let Some(address) = arena.allocate(code_size) {
+            return Ok(address as *mut u8);
+        }
+    }
+
+    let page = map_jit_memory(src, page_size)?;
+    let mut arena = JitArena::new(page as usize, page_size);
+    let address = arena
+        .allocate(code_size)
+        .expect("A fresh JIT page holds any stub of up to a page");
+    arenas.push(arena);
+
+    Ok(address as *mut u8)
+}
+
+/// Returns whether the whole of `page` is within branch range of `src`.
+#[cfg(any(
+    target_arch = "aarch64",
+    target_arch = "x86_64",
+    target_arch = "riscv64",
+    target_arch = "x86"
+))]
+fn is_in_branch_range(page: &Range<usize>, src: &FuncPtrInternal) -> bool {
+    let src_addr = src.as_ptr() as usize;
+    let max_range = jit_max_range() as usize;
+
+    page.start.abs_diff(src_addr) <= max_range && page.end.abs_diff(src_addr) <= max_range
+}
+
+/// The room `reserve_jit_memory` sets aside for each fake, enough for the stub of a fake
+/// returning a fixed value or branching to a replacement.
+#[cfg(any(
+    target_arch = "aarch64",
+    target_arch = "x86_64",
+    target_arch = "riscv64",
+    target_arch = "x86"
+))]
+const JIT_SLOT_SIZE: usize = 64;
+
+/// Maps pages of JIT memory near the code of this crate for `count` fakes, which stay mapped
+/// once empty until `release_jit_memory`, so installing and restoring fakes repeatedly does
+/// not map and unmap a page each time.
+///
+/// The pages serve the functions within branch range of them, usually those of the binary
+/// this crate is linked into. Does nothing when memory is never writable and executable at
+/// once, as stubs do not share pages then.
+pub(crate) fn reserve_jit_memory(count: usize) -> Result<(), InjectError> {
+    #[cfg(any(
+        target_arch = "aarch64",
+        target_arch = "x86_64",
+        target_arch = "riscv64",
+        target_arch = "x86"
+    ))]
+    {
+        if w_xor_x() {
+            return Ok(());
+        }
+
+        let page_size = jit_page_size();
+        let code = reserve_jit_memory as fn(usize) -> Result<(), InjectError> as *mut ();
+        let near = unsafe { FuncPtrInternal::new(NonNull::new(code).unwrap()) };
+        let pages = count.saturating_mul(JIT_SLOT_SIZE).div_ceil(page_size);
+        let mut arenas = JIT_ARENAS.lock().unwrap_or_else(PoisonError::into_inner);
+
+        for _ in 0..pages {
+            let page = map_jit_memory(&near, page_size)?;
+            let mut arena = JitArena::new(page as usize, page_size);
+            arena.set_retained(true);
+            arenas.push(arena);
+        }
+    }
+
+    #[cfg(not(any(
+        target_arch = "aarch64",
+        target_arch = "x86_64",
+        target_arch = "riscv64",
+        target_arch = "x86"
+    )))]
+    let _ = count;
+
+    Ok(())
+}
+
+/// Lets the pages of `reserve_jit_memory` be unmapped again, right away for those no stub
+/// uses anymore.
+pub(crate) fn release_jit_memory() {
+    #[cfg(any(
+        target_arch = "aarch64",
+        target_arch = "x86_64",
+        target_arch = "riscv64",
+        target_arch = "x86"
+    ))]
+    {
+        let mut arenas = JIT_ARENAS.lock().unwrap_or_else(PoisonError::into_inner);
+
+        arenas.retain_mut(|arena| {
+            arena.set_retained(false);
+            if !arena.is_empty() {
+                return true;
+            }
+
+            let page = arena.page();
+            unsafe {
+                unmap_jit_memory(page.start as *mut u8, page.len());
+            }
+            false
+        });
+    }
+}
+
+/// Maps `code_size` bytes of JIT memory of its own near `src`.
+#[cfg(any(
+    target_arch = "aarch64",
+    target_arch = "x86_64",
+    target_arch = "riscv64",
+    target_arch = "x86"
+))]
+fn map_jit_memory(src: &FuncPtrInternal, code_size: usize) -> Result<*mut u8, InjectError> {
+    #[cfg(any(target_os = "linux", target_os = "macos"))]
+    let jit_memory = allocate_jit_memory_unix(src, code_size)?;
+
+    #[cfg(target_os = "windows")]
+    let jit_memory = allocate_jit_memory_windows(src, code_size)?;
+
+    #[cfg(feature = "metrics")]
+    JIT_BYTES_USED.fetch_add(code_size, Ordering::Relaxed);
+
+    Ok(jit_memory)
+}
+
+#[cfg(any(
+    target_arch = "aarch64",
+    target_arch = "x86_64",
+    target_arch = "riscv64",
+    target_arch = "x86"
+))]
+fn jit_page_size() -> usize {
     #[cfg(any(target_os = "linux", target_os = "macos"))]
     {
-        allocate_jit_memory_unix(src, code_size)
+        unsafe { sysconf(_SC_PAGESIZE) as usize }
     }
 
     #[cfg(target_os = "windows")]
     {
-        allocate_jit_memory_windows(src, code_size)
+        unsafe { get_page_size() }
+    }
+}
+
+/// How far from a patched function its JIT memory may be, so the patch can branch to it.
+///
+/// On aarch64, memory beyond the ±128MB a `b` instruction reaches is branched to through an
+/// `adrp`/`add`/`br` sequence, so it has a ±2GB memory range everywhere.
+/// On macOS and Windows, x86_64 has a ±2GB memory range for `jmp rel32` instructions, and on
+/// Linux a ±128MB one. Beyond it, memory anywhere is branched to through rax.
+/// riscv64 has a ±2GB one.
+/// On 32-bit x86, `jmp rel32` wraps around the address space, so any memory is in range and
+/// it is mapped wherever the OS chooses.
+#[cfg(any(
+    target_arch = "aarch64",
+    target_arch = "x86_64",
+    target_arch = "riscv64",
+    target_arch = "x86"
+))]
+fn jit_max_range() -> u64 {
+    #[cfg(any(
+        target_arch = "aarch64",
+        all(target_arch = "x86_64", not(target_os = "linux"))
+    ))]
+    let max_range: u64 = 0x8000_0000; // ±2GB
+
+    #[cfg(all(target_os = "linux", target_arch = "x86_64"))]
+    let max_range: u64 = 0x8000000; // ±128MB
+
+    // The reach of an auipc/jalr pair, rounded down to a page.
+    #[cfg(all(target_os = "linux", target_arch = "riscv64"))]
+    let max_range: u64 = 0x7FFF_F000; // ±2GB
+
+    #[cfg(target_arch = "x86")]
+    let max_range: u64 = 0xFFFF_FFFF; // The whole address space
+
+    max_range
+}
+
+/// Allocates one page of JIT memory near `src` and frees it right away, so a patch that
+/// cannot get JIT memory is reported before anything is written.
+///
+/// Returns the address of the page, which later allocations are likely to land close to.
+#[cfg(any(
+    target_arch = "aarch64",
+    target_arch = "x86_64",
+    target_arch = "riscv64"
+))]
+pub(crate) fn probe_jit_memory(src: &FuncPtrInternal) -> Result<usize, InjectError> {
+    // A nearby page with room left will hold the stub, so there is nothing to map.
+    if !w_xor_x() {
+        let arenas = JIT_ARENAS.lock().unwrap_or_else(PoisonError::into_inner);
+        if let Some(arena) = arenas.iter().find(|arena| {
+            is_in_branch_range(&arena.page(), src) && arena.can_allocate(JIT_SLOT_SIZE)
+        }) {
+            return Ok(arena.page().start);
+        }
+    }
+
+    let page_size = jit_page_size();
+    let jit_memory = map_jit_memory(src, page_size)?;
+
+    unsafe {
+        unmap_jit_memory(jit_memory, page_size);
+    }
+
+    Ok(jit_memory as usize)
+}
+
+/// Releases JIT memory returned by `allocate_jit_memory`, unmapping its page once no other
+/// stub uses it.
+///
+/// # Safety
+///
+/// `jit_memory` must have been allocated with `jit_size` bytes and must not be used anymore.
+unsafe fn free_jit_memory(jit_memory: *mut u8, jit_size: usize) {
+    crate::injector_core::jit_unwind::unregister(jit_memory as usize);
+
+    #[cfg(any(
+        target_arch = "aarch64",
+        target_arch = "x86_64",
+        target_arch = "riscv64",
+        target_arch = "x86"
+    ))]
+    {
+        let address = jit_memory as usize;
+        let mut arenas = JIT_ARENAS.lock().unwrap_or_else(PoisonError::into_inner);
+
+        if let Some(index) = arenas
+            .iter()
+            .position(|arena| arena.page().contains(&address))
+        {
+            arenas[index].free(address, jit_size);
+            if arenas[index].is_empty() && !arenas[index].is_retained() {
+                let page = arenas.swap_remove(index).page();
+                unmap_jit_memory(page.start as *mut u8, page.len());
+            }
+            return;
+        }
+    }
+
+    unmap_jit_memory(jit_memory, jit_size);
+}
+
+/// Unmaps JIT memory mapped on its own.
+unsafe fn unmap_jit_memory(jit_memory: *mut u8, jit_size: usize) {
+    #[cfg(feature = "metrics")]
+    JIT_BYTES_USED.fetch_sub(jit_size, Ordering::Relaxed);
+
+    #[cfg(any(target_os = "linux", target_os = "macos"))]
+    {
+        libc::munmap(jit_memory as *mut c_void, jit_size);
+    }
+
+    #[cfg(target_os = "windows")]
+    {
+        let _ = jit_size;
+        VirtualFree(jit_memory as *mut c_void, 0, MEM_RELEASE);
     }
 }
 
@@ -59,158 +432,200 @@ pub(crate) fn allocate_jit_memory(src: &FuncPtrInternal, code_size: usize) -> *m
 // See https://github.com/microsoft/injectorppforrust/issues/88
 /// Allocate JIT memory on Unix platforms.
 ///
-/// On MacOS, both aarch64 and x86_64 architectures have a ±2GB memory range.
-/// On Linux, both aarch64 and x86_64 architectures have a ±128MB memory range.
+/// The memory must be within `jit_max_range` of the source on aarch64 and riscv64. On x86_64
+/// memory in range is preferred, and memory anywhere is used when no page in range is free.
 /// Other architectures have no enforced address range constraint.
 ///
-/// # Panics
-/// Panics if memory allocation fails or if no memory is found within the valid address range on
-/// `aarch64` or `x86_64`.
+/// Pages are tried nearest to the source first, see `jit_candidates`.
+///
+/// # Errors
+/// Returns `InjectError::AllocationFailed` if memory allocation fails and
+/// `InjectError::OutOfBranchRange` if no memory is found within the valid address range on
+/// `aarch64` or `riscv64`.
 #[cfg(any(target_os = "linux", target_os = "macos"))]
-#[cfg(any(target_arch = "aarch64", target_arch = "x86_64"))]
-fn allocate_jit_memory_unix(_src: &FuncPtrInternal, code_size: usize) -> *mut u8 {
+#[cfg(any(
+    target_arch = "aarch64",
+    target_arch = "x86_64",
+    target_arch = "riscv64",
+    target_arch = "x86"
+))]
+fn allocate_jit_memory_unix(
+    _src: &FuncPtrInternal,
+    code_size: usize,
+) -> Result<*mut u8, InjectError> {
+    // In W^X mode `write_jit_code` makes the memory executable once written, so plain pages
+    // are mapped rather than MAP_JIT ones, which are toggled per thread instead.
     #[cfg(target_os = "macos")]
-    let flags = libc::MAP_ANON | libc::MAP_PRIVATE | libc::MAP_JIT;
+    let flags = if w_xor_x() {
+        libc::MAP_ANON | libc::MAP_PRIVATE
+    } else {
+        libc::MAP_ANON | libc::MAP_PRIVATE | libc::MAP_JIT
+    };
 
     #[cfg(target_os = "linux")]
     let flags = libc::MAP_ANONYMOUS | libc::MAP_PRIVATE;
 
-    #[cfg(any(target_arch = "x86_64", target_arch = "aarch64"))]
-    {
-        #[cfg(target_os = "macos")]
-        let max_range: u64 = 0x8000_0000; // ±2GB
-
-        #[cfg(target_os = "linux")]
-        let max_range: u64 = 0x8000000; // ±128MB
+    let protection = if w_xor_x() {
+        PROT_READ | PROT_WRITE
+    } else {
+        PROT_READ | PROT_WRITE | PROT_EXEC
+    };
 
+    #[cfg(any(
+        target_arch = "x86_64",
+        target_arch = "aarch64",
+        target_arch = "riscv64"
+    ))]
+    {
+        let max_range = jit_max_range();
         let original_addr = _src.as_ptr() as u64;
         let page_size = unsafe { sysconf(_SC_PAGESIZE) as u64 };
-        let mut start_address = original_addr.saturating_sub(max_range);
+        let mut allocated_any = false;
 
-        while start_address <= original_addr + max_range {
+        for start_address in jit_candidates(
+            original_addr,
+            jit_search_origin(original_addr),
+            max_range,
+            page_size,
+            code_size as u64,
+            USER_SPACE,
+        ) {
             let ptr = unsafe {
                 libc::mmap(
                     start_address as *mut c_void,
                     code_size,
-                    PROT_READ | PROT_WRITE | PROT_EXEC,
+                    protection,
                     flags,
                     -1,
                     0,
                 )
             };
             if ptr != libc::MAP_FAILED {
+                allocated_any = true;
                 let allocated = ptr as u64;
                 let diff = allocated.abs_diff(original_addr);
                 if diff <= max_range {
-                    return ptr as *mut u8;
+                    return Ok(ptr as *mut u8);
                 } else {
                     unsafe { libc::munmap(ptr, code_size) };
                 }
             }
-            start_address += page_size;
         }
 
-        panic!(
-            "Failed to allocate JIT memory within ±{max_range} of source on {} arch",
-            std::env::consts::ARCH
-        );
+        // Patches branch to memory out of `jmp rel32` reach through rax, so when every page
+        // nearby is taken any address will do.
+        #[cfg(target_arch = "x86_64")]
+        {
+            let ptr =
+                unsafe { libc::mmap(std::ptr::null_mut(), code_size, protection, flags, -1, 0) };
+            if ptr != libc::MAP_FAILED {
+                return Ok(ptr as *mut u8);
+            }
+        }
+
+        if allocated_any {
+            Err(InjectError::OutOfBranchRange {
+                address: original_addr as usize,
+                max_range: max_range as usize,
+            })
+        } else {
+            Err(InjectError::AllocationFailed { size: code_size })
+        }
     }
 
-    #[cfg(not(any(target_arch = "aarch64", target_arch = "x86_64")))]
+    #[cfg(not(any(
+        target_arch = "aarch64",
+        target_arch = "x86_64",
+        target_arch = "riscv64"
+    )))]
     {
-        let ptr = unsafe {
-            libc::mmap(
-                std::ptr::null_mut(),
-                code_size,
-                PROT_READ | PROT_WRITE | PROT_EXEC,
-                flags,
-                -1,
-                0,
-            )
-        };
+        let ptr = unsafe { libc::mmap(std::ptr::null_mut(), code_size, protection, flags, -1, 0) };
 
         if ptr == libc::MAP_FAILED {
-            panic!(
-                "Failed to allocate executable memory on {} arch",
-                std::env::consts::ARCH
-            );
+            return Err(InjectError::AllocationFailed { size: code_size });
         }
 
-        ptr as *mut u8
+        Ok(ptr as *mut u8)
     }
 }
 // See https://github.com/microsoft/injectorppforrust/issues/84
 /// Allocate executable JIT memory on Windows platforms.
 ///
-/// For AArch64, memory must be within ±128MB due to instruction encoding limits (e.g., B/BL).
-/// For x86_64, memory must be within ±2GB for `jmp rel32` instructions.
+/// For AArch64, memory must be within `jit_max_range` of the source. For x86_64 memory in
+/// range is preferred, and memory anywhere is used when no page in range is free.
 #[cfg(target_os = "windows")]
-fn allocate_jit_memory_windows(_src: &FuncPtrInternal, code_size: usize) -> *mut u8 {
-    #[cfg(target_arch = "aarch64")]
+fn allocate_jit_memory_windows(
+    _src: &FuncPtrInternal,
+    code_size: usize,
+) -> Result<*mut u8, InjectError> {
+    let protection = if w_xor_x() {
+        PAGE_READWRITE
+    } else {
+        PAGE_EXECUTE_READWRITE
+    };
+
+    #[cfg(any(target_arch = "aarch64", target_arch = "x86_64"))]
     {
-        let max_range: u64 = 0x8000000; // ±128MB
+        let max_range = jit_max_range();
         let original_addr = _src.as_ptr() as u64;
         let page_size = unsafe { get_page_size() as u64 };
-        let mut start_address = original_addr.saturating_sub(max_range);
+        let mut allocated_any = false;
 
-        while start_address <= original_addr + max_range {
+        for start_address in jit_candidates(
+            original_addr,
+            jit_search_origin(original_addr),
+            max_range,
+            page_size,
+            code_size as u64,
+            USER_SPACE,
+        ) {
             let ptr = unsafe {
                 VirtualAlloc(
                     start_address as *mut c_void,
                     code_size,
                     MEM_COMMIT | MEM_RESERVE,
-                    PAGE_EXECUTE_READWRITE,
+                    protection,
                 )
             };
             if !ptr.is_null() {
+                allocated_any = true;
                 let allocated = ptr as u64;
                 let diff = allocated.abs_diff(original_addr);
                 if diff <= max_range {
-                    return ptr as *mut u8;
+                    return Ok(ptr as *mut u8);
                 } else {
                     unsafe {
                         VirtualFree(ptr, 0, MEM_RELEASE);
                     }
                 }
             }
-            start_address += page_size;
         }
 
-        panic!("Failed to allocate executable memory within ±128MB of original function address on AArch64 Windows");
-    }
-
-    #[cfg(target_arch = "x86_64")]
-    {
-        let max_range: usize = 0x8000_0000; // ±2GB
-        let original_addr = _src.as_ptr() as usize;
-        let page_size = unsafe { get_page_size() };
-        let mut addr = original_addr.saturating_sub(max_range);
-
-        while addr <= original_addr + max_range {
+        // Patches branch to memory out of `jmp rel32` reach through rax, so when every page
+        // nearby is taken any address will do.
+        #[cfg(target_arch = "x86_64")]
+        {
             let ptr = unsafe {
                 VirtualAlloc(
-                    addr as *mut c_void,
+                    std::ptr::null_mut(),
                     code_size,
                     MEM_COMMIT | MEM_RESERVE,
-                    PAGE_EXECUTE_READWRITE,
+                    protection,
                 )
             };
-
             if !ptr.is_null() {
-                let allocated = ptr as usize;
-                if allocated.abs_diff(original_addr) <= max_range {
-                    return ptr as *mut u8;
-                } else {
-                    unsafe {
-                        VirtualFree(ptr, 0, MEM_RELEASE);
-                    }
-                }
+                return Ok(ptr as *mut u8);
             }
-
-            addr += page_size;
         }
 
-        panic!("Failed to allocate executable memory within ±2GB of original function address on x86_64 Windows");
+        if allocated_any {
+            Err(InjectError::OutOfBranchRange {
+                address: original_addr as usize,
+                max_range: max_range as usize,
+            })
+        } else {
+            Err(InjectError::AllocationFailed { size: code_size })
+        }
     }
 
     #[cfg(all(not(target_arch = "x86_64"), not(target_arch = "aarch64")))]
@@ -220,15 +635,15 @@ fn allocate_jit_memory_windows(_src: &FuncPtrInternal, code_size: usize) -> *mut
                 std::ptr::null_mut(), // let OS choose suitable address
                 code_size,
                 MEM_COMMIT | MEM_RESERVE,
-                PAGE_EXECUTE_READWRITE,
+                protection,
             )
         };
 
         if ptr.is_null() {
-            panic!("Failed to allocate executable memory on Windows (unsupported architecture)");
+            return Err(InjectError::AllocationFailed { size: code_size });
         }
 
-        ptr as *mut u8
+        Ok(ptr as *mut u8)
     }
 }
 
@@ -243,15 +658,249 @@ pub(crate) unsafe fn read_bytes(ptr: *const u8, len: usize) -> Vec<u8> {
     buf
 }
 
+/// Copies `len` bytes at `ptr` after checking that the whole range is readable memory.
+///
+/// Unlike `read_bytes` this never faults: an unmapped or protected range, e.g. a short
+/// function at the very end of a mapping, is reported as an error.
+pub(crate) fn try_read_bytes(ptr: *const u8, len: usize) -> Result<Vec<u8>, InjectError> {
+    let unreadable = || InjectError::UnreadableMemory {
+        address: ptr as usize,
+        len,
+    };
+
+    #[cfg(target_os = "macos")]
+    {
+        use mach2::kern_return::KERN_SUCCESS;
+        use mach2::traps::mach_task_self;
+        use mach2::vm::mach_vm_read_overwrite;
+
+        // The kernel performs the copy and reports unmapped pages instead of faulting.
+        let mut buf = vec![0u8; len];
+        let mut copied = 0;
+        let result = unsafe {
+            mach_vm_read_overwrite(
+                mach_task_self(),
+                ptr as u64,
+                len as u64,
+                buf.as_mut_ptr() as u64,
+                &mut copied,
+            )
+        };
+
+        if result != KERN_SUCCESS || copied != len as u64 {
+            return Err(unreadable());
+        }
+
+        Ok(buf)
+    }
+
+    #[cfg(not(target_os = "macos"))]
+    {
+        if !is_range_readable(ptr as usize, len) {
+            return Err(unreadable());
+        }
+
+        Ok(unsafe { read_bytes(ptr, len) })
+    }
+}
+
+/// Returns whether `[start, start + len)` is covered by contiguous readable mappings.
+#[cfg(target_os = "linux")]
+fn is_range_readable(start: usize, len: usize) -> bool {
+    let Some(end) = start.checked_add(len) else {
+        return false;
+    };
+
+    let Ok(maps) = std::fs::read_to_string("/proc/self/maps") else {
+        return false;
+    };
+
+    // Mappings are listed in ascending order, walk them while they cover the range.
+    let mut covered = start;
+    for line in maps.lines() {
+        let mut fields = line.split_whitespace();
+        let (Some(range), Some(perms)) = (fields.next(), fields.next()) else {
+            continue;
+        };
+        let Some((low, high)) = range.split_once('-') else {
+            continue;
+        };
+        let (Ok(low), Ok(high)) = (
+            usize::from_str_radix(low, 16),
+            usize::from_str_radix(high, 16),
+        ) else {
+            continue;
+        };
+
+        if high <= covered {
+            continue;
+        }
+        if low > covered || !perms.starts_with('r') {
+            return false;
+        }
+
+        covered = high;
+        if covered >= end {
+            return true;
+        }
+    }
+
+    false
+}
+
+/// Returns whether `[start, start + len)` is covered by committed, accessible regions.
+#[cfg(target_os = "windows")]
+fn is_range_readable(start: usize, len: usize) -> bool {
+    let Some(end) = start.checked_add(len) else {
+        return false;
+    };
+
+    let mut covered = start;
+    while covered < end {
+        let mut info = unsafe { std::mem::zeroed::<MemoryBasicInformation>() };
+        let written = unsafe {
+            VirtualQuery(
+                covered as *const c_void,
+                &mut info,
+                std::mem::size_of::<MemoryBasicInformation>(),
+            )
+        };
+
+        if written == 0
+            || info.state != MEM_COMMIT
+            || info.protect & (PAGE_NOACCESS | PAGE_GUARD) != 0
+        {
+            return false;
+        }
+
+        covered = info.base_address as usize + info.region_size;
+    }
+
+    true
+}
+
+/// Fails unless `address` lies in readable, executable memory.
+///
+/// Function pointers always do, but an address resolved some other way may point at data,
+/// e.g. where the code of an inlined function would be if it existed.
+pub(crate) fn check_executable(address: usize) -> Result<(), InjectError> {
+    try_read_bytes(address as *const u8, 1)?;
+
+    if is_executable(address) {
+        Ok(())
+    } else {
+        Err(InjectError::NotExecutable { address })
+    }
+}
+
+/// Returns whether the mapping containing `address` is executable.
+#[cfg(target_os = "linux")]
+fn is_executable(address: usize) -> bool {
+    let Ok(maps) = std::fs::read_to_string("/proc/self/maps") else {
+        return false;
+    };
+
+    maps.lines().any(|line| {
+        let mut fields = line.split_whitespace();
+        let (Some(range), Some(perms)) = (fields.next(), fields.next()) else {
+            return false;
+        };
+        let Some((low, high)) = range.split_once('-') else {
+            return false;
+        };
+
+        match (
+            usize::from_str_radix(low, 16),
+            usize::from_str_radix(high, 16),
+        ) {
+            (Ok(low), Ok(high)) => {
+                (low..high).contains(&address) && perms.as_bytes().get(2) == Some(&b'x')
+            }
+            _ => false,
+        }
+    })
+}
+
+/// Returns whether the region containing `address` is committed with an executable protection.
+#[cfg(target_os = "windows")]
+fn is_executable(address: usize) -> bool {
+    let mut info = unsafe { std::mem::zeroed::<MemoryBasicInformation>() };
+    let written = unsafe {
+        VirtualQuery(
+            address as *const c_void,
+            &mut info,
+            std::mem::size_of::<MemoryBasicInformation>(),
+        )
+    };
+
+    written != 0
+        && info.state == MEM_COMMIT
+        && info.protect & PAGE_GUARD == 0
+        && info.protect
+            & (PAGE_EXECUTE | PAGE_EXECUTE_READ | PAGE_EXECUTE_READWRITE | PAGE_EXECUTE_WRITECOPY)
+            != 0
+}
+
+/// Returns whether the region containing `address` is mapped with execute protection.
+#[cfg(target_os = "macos")]
+fn is_executable(address: usize) -> bool {
+    use mach2::kern_return::KERN_SUCCESS;
+    use mach2::traps::mach_task_self;
+    use mach2::vm::mach_vm_region;
+    use mach2::vm_prot::VM_PROT_EXECUTE;
+    use mach2::vm_region::{vm_region_basic_info_64, vm_region_info_t, VM_REGION_BASIC_INFO_64};
+
+    // `mach_vm_region` returns the first region at or above the address it is given.
+    let mut region = address as u64;
+    let mut size = 0;
+    let mut info = vm_region_basic_info_64::default();
+    let mut count = vm_region_basic_info_64::count();
+    let mut object_name = 0;
+    let result = unsafe {
+        mach_vm_region(
+            mach_task_self(),
+            &mut region,
+            &mut size,
+            VM_REGION_BASIC_INFO_64,
+            &mut info as *mut vm_region_basic_info_64 as vm_region_info_t,
+            &mut count,
+            &mut object_name,
+        )
+    };
+
+    result == KERN_SUCCESS && region <= address as u64 && info.protection & VM_PROT_EXECUTE != 0
+}
+
+/// How many `PatchGuard`s were created and not restored in this process, see
+/// `InjectorPP::live_patch_count`. A patch that failed to be restored stays counted.
+static LIVE_PATCHES: AtomicUsize = AtomicUsize::new(0);
+
+/// Returns the value of `LIVE_PATCHES`.
+pub(crate) fn live_patch_count() -> usize {
+    LIVE_PATCHES.load(Ordering::SeqCst)
+}
+
+/// How many bytes of JIT memory are mapped in this process, see
+/// `InjectorPP::jit_bytes_used`. Memory leaked by a patch that failed to be restored stays
+/// counted.
+#[cfg(feature = "metrics")]
+static JIT_BYTES_USED: AtomicUsize = AtomicUsize::new(0);
+
+/// Returns the value of `JIT_BYTES_USED`.
+#[cfg(feature = "metrics")]
+pub(crate) fn jit_bytes_used() -> usize {
+    JIT_BYTES_USED.load(Ordering::Relaxed)
+}
+
 /// A guard that stores the original bytes of a patched function and the allocated JIT memory.
 /// When dropped, it restores the original function code and frees the JIT memory.
 pub(crate) struct PatchGuard {
     func_ptr: *mut u8,
     original_bytes: Vec<u8>,
+    patch_bytes: Vec<u8>,
     patch_size: usize,
+    enabled: bool,
     jit_memory: *mut u8,
-
-    #[cfg_attr(target_os = "windows", allow(dead_code))]
     jit_size: usize,
 }
 
@@ -263,151 +912,526 @@ impl PatchGuard {
         jit_memory: *mut u8,
         jit_size: usize,
     ) -> Self {
+        // The patch has just been written, keep a copy so it can be applied again later.
+        let patch_bytes = unsafe { read_bytes(func_ptr, patch_size) };
+        LIVE_PATCHES.fetch_add(1, Ordering::SeqCst);
+
         Self {
             func_ptr,
             original_bytes,
+            patch_bytes,
             patch_size,
+            enabled: true,
             jit_memory,
             jit_size,
         }
     }
+
+    /// The addresses of the bytes the patch overwrites.
+    pub(crate) fn patched_range(&self) -> Range<usize> {
+        self.func_ptr as usize..self.func_ptr as usize + self.patch_size
+    }
+
+    /// Returns the bytes the patch replaced.
+    pub(crate) fn original_bytes(&self) -> &[u8] {
+        &self.original_bytes[..self.patch_size]
+    }
+
+    /// Returns the bytes the patch writes over the function.
+    pub(crate) fn patch_bytes(&self) -> &[u8] {
+        &self.patch_bytes
+    }
+
+    /// Returns the JIT memory the patch branches to, null if it needed none, and a copy of
+    /// the code in it.
+    pub(crate) fn jit_code(&self) -> (*mut u8, Vec<u8>) {
+        if self.jit_memory.is_null() {
+            return (self.jit_memory, Vec::new());
+        }
+
+        (self.jit_memory, unsafe {
+            read_bytes(self.jit_memory, self.jit_size)
+        })
+    }
+
+    /// Returns whether this patch overwrites some of the bytes in `range`.
+    pub(crate) fn overlaps(&self, range: &Range<usize>) -> bool {
+        let patched = self.patched_range();
+
+        patched.start < range.end && range.start < patched.end
+    }
+
+    /// Returns whether the patch is currently applied.
+    pub(crate) fn is_enabled(&self) -> bool {
+        self.enabled
+    }
+
+    /// Records whether the patch should be applied. Nothing is written: the caller writes
+    /// the bytes of whichever patch of the function must run, see `write_patch` and
+    /// `write_original`. The JIT memory is kept either way.
+    pub(crate) fn set_enabled(&mut self, enabled: bool) {
+        self.enabled = enabled;
+    }
+
+    /// Writes the patch over the function again.
+    pub(crate) fn write_patch(&self) -> Result<(), InjectError> {
+        unsafe { try_patch_function(self.func_ptr, &self.patch_bytes) }
+    }
+
+    /// Writes back the bytes the patch replaced, which are those of the previous patch of the
+    /// function if there was one when it was applied.
+    pub(crate) fn write_original(&self) -> Result<(), InjectError> {
+        unsafe { try_patch_function(self.func_ptr, &self.original_bytes[..self.patch_size]) }
+    }
 }
 
 impl Drop for PatchGuard {
+    /// Never panics, as guards are often dropped while a failed test unwinds. A failure to
+    /// write the original bytes back is reported through `record_restore_failure` instead,
+    /// and the JIT memory is leaked since the function still branches to it.
     fn drop(&mut self) {
+        if let Err(error) = self.write_original() {
+            record_restore_failure(self.func_ptr as usize, error);
+            return;
+        }
+
         unsafe {
-            patch_function(self.func_ptr, &self.original_bytes[..self.patch_size]);
             if !self.jit_memory.is_null() {
-                #[cfg(any(target_os = "linux", target_os = "macos"))]
-                {
-                    libc::munmap(self.jit_memory as *mut c_void, self.jit_size);
-                }
-
-                #[cfg(target_os = "windows")]
-                {
-                    VirtualFree(self.jit_memory as *mut c_void, 0, MEM_RELEASE);
-                }
+                free_jit_memory(self.jit_memory, self.jit_size);
             }
 
             // Explicitly flush cache and synchronize pipeline after restoring original bytes
             clear_cache(self.func_ptr, self.func_ptr.add(self.patch_size));
         }
+
+        LIVE_PATCHES.fetch_sub(1, Ordering::SeqCst);
+        notify_restore(RestoreInfo {
+            function: self.func_ptr as usize,
+            jit_address: self.jit_memory as usize,
+            jit_size: self.jit_size,
+        });
     }
 }
 
 /// Unsafely patches the code at `func` with the given patch bytes.
 ///
+/// # Panics
+/// Panics with the error of `try_patch_function` if it fails.
+///
 /// # Safety
 ///
 /// The caller must ensure that `func` points to a valid, patchable code region.
-#[cfg(not(target_os = "macos"))]
 pub(crate) unsafe fn patch_function(func: *mut u8, patch: &[u8]) {
-    make_memory_writable_and_executable(func);
+    try_patch_function(func, patch).unwrap_or_else(|error| panic!("{error}"))
+}
 
-    inject_asm_code(patch, func);
+/// Like `patch_function`, but returns `InjectError::ProtectionFailed` when the code cannot be
+/// made writable.
+///
+/// Other threads may be calling the function meanwhile, so the patch is written such that a
+/// thread entering it never runs a mix of old and new instructions, see `write_code`.
+///
+/// # Safety
+///
+/// The caller must ensure that `func` points to a valid, patchable code region.
+#[cfg(not(target_os = "macos"))]
+pub(crate) unsafe fn try_patch_function(func: *mut u8, patch: &[u8]) -> Result<(), InjectError> {
+    if w_xor_x() {
+        return write_code_w_xor_x(func, patch);
+    }
+
+    make_memory_writable_and_executable(func)?;
+
+    write_code(&ProcessCode, func, patch);
+    Ok(())
+}
+
+/// Writes `code` at `func` through `/proc/self/mem`, see `InjectorOptions::w_xor_x`.
+///
+/// The kernel lets it write read-only pages, so the code pages keep their protection and
+/// are never writable, let alone writable and executable. Longer code is parked first like in
+/// `write_code`, though the writes are not atomic stores.
+#[cfg(target_os = "linux")]
+unsafe fn write_code_w_xor_x(func: *mut u8, code: &[u8]) -> Result<(), InjectError> {
+    use std::os::unix::fs::FileExt;
+
+    let failed = |call: &'static str, error: std::io::Error| InjectError::ProtectionFailed {
+        call,
+        address: func as usize,
+        code: error.raw_os_error().unwrap_or(0),
+    };
+    let mem = std::fs::OpenOptions::new()
+        .write(true)
+        .open("/proc/self/mem")
+        .map_err(|error| failed("open", error))?;
+    let write = |bytes: &[u8], offset: usize| {
+        mem.write_all_at(bytes, func as u64 + offset as u64)
+            .map_err(|error| failed("pwrite", error))
+    };
+
+    let head = PARK_INSTRUCTION.len();
+    if head != 0 && code.len() > head {
+        write(PARK_INSTRUCTION, 0)?;
+        clear_cache(func, func.add(head));
+        write(&code[head..], head)?;
+        write(&code[..head], 0)?;
+    } else {
+        write(code, 0)?;
+    }
+
+    clear_cache(func, func.add(code.len()));
+    Ok(())
+}
+
+/// Writes `code` at `func` with the pages it covers writable and executable only meanwhile,
+/// giving them their original protection back afterwards, see `InjectorOptions::w_xor_x`.
+#[cfg(target_os = "windows")]
+unsafe fn write_code_w_xor_x(func: *mut u8, code: &[u8]) -> Result<(), InjectError> {
+    let mut original: u32 = 0;
+
+    PROTECTION_CHANGES.fetch_add(1, Ordering::Relaxed);
+    if VirtualProtect(
+        func as *mut c_void,
+        code.len(),
+        PAGE_EXECUTE_READWRITE,
+        &mut original,
+    ) == 0
+    {
+        return Err(protection_failed("VirtualProtect", func));
+    }
+
+    write_code(&ProcessCode, func, code);
+
+    let mut patched: u32 = 0;
+    PROTECTION_CHANGES.fetch_add(1, Ordering::Relaxed);
+    if VirtualProtect(func as *mut c_void, code.len(), original, &mut patched) == 0 {
+        return Err(protection_failed("VirtualProtect", func));
+    }
+
+    Ok(())
+}
+
+/// Writes the code of a fake to JIT memory returned by `allocate_jit_memory`, before anything
+/// runs it.
+///
+/// In W^X mode the memory is only writable until then, and is made executable once written,
+/// see `InjectorOptions::w_xor_x`.
+///
+/// # Panics
+/// Panics with `InjectError::ProtectionFailed` if the memory cannot be made executable.
+///
+/// # Safety
+///
+/// `jit_memory` must have been allocated with at least `code.len()` bytes.
+#[cfg(any(
+    target_arch = "aarch64",
+    target_arch = "x86_64",
+    target_arch = "riscv64",
+    target_arch = "x86"
+))]
+pub(crate) unsafe fn write_jit_code(code: &[u8], jit_memory: *mut u8) {
+    if !w_xor_x() {
+        inject_asm_code(code, jit_memory);
+        return;
+    }
+
+    ptr::copy_nonoverlapping(code.as_ptr(), jit_memory, code.len());
+    make_jit_memory_executable(jit_memory, code.len()).unwrap_or_else(|error| panic!("{error}"));
+    clear_cache(jit_memory, jit_memory.add(code.len()));
+}
+
+/// Makes the JIT memory of `len` bytes at `jit_memory`, mapped on its own, executable but no
+/// longer writable.
+#[cfg(any(
+    target_arch = "aarch64",
+    target_arch = "x86_64",
+    target_arch = "riscv64",
+    target_arch = "x86"
+))]
+unsafe fn make_jit_memory_executable(jit_memory: *mut u8, len: usize) -> Result<(), InjectError> {
+    PROTECTION_CHANGES.fetch_add(1, Ordering::Relaxed);
+
+    #[cfg(any(target_os = "linux", target_os = "macos"))]
+    {
+        if libc::mprotect(jit_memory as *mut c_void, len, PROT_READ | PROT_EXEC) != 0 {
+            return Err(protection_failed("mprotect", jit_memory));
+        }
+    }
+
+    #[cfg(target_os = "windows")]
+    {
+        let mut old_protect: u32 = 0;
+        if VirtualProtect(
+            jit_memory as *mut c_void,
+            len,
+            PAGE_EXECUTE_READ,
+            &mut old_protect,
+        ) == 0
+        {
+            return Err(protection_failed("VirtualProtect", jit_memory));
+        }
+    }
+
+    Ok(())
 }
 
+/// An instruction branching to itself, written first to park the threads entering a function
+/// while the rest of its patch is written. 32-bit ARM code may be ARM or Thumb, so it is
+/// never parked.
+#[cfg(all(
+    not(target_os = "macos"),
+    any(target_arch = "x86", target_arch = "x86_64")
+))]
+const PARK_INSTRUCTION: &[u8] = &[0xEB, 0xFE]; // jmp $
+
+#[cfg(all(not(target_os = "macos"), target_arch = "aarch64"))]
+const PARK_INSTRUCTION: &[u8] = &0x1400_0000u32.to_le_bytes(); // b .
+
+#[cfg(all(not(target_os = "macos"), target_arch = "riscv64"))]
+const PARK_INSTRUCTION: &[u8] = &[0x01, 0xA0]; // c.j .
+
+#[cfg(all(not(target_os = "macos"), target_arch = "arm"))]
+const PARK_INSTRUCTION: &[u8] = &[];
+
+/// The memory `write_code` writes to: the code of the process, or a `loom` model of it in
+/// the crate's own tests.
+#[cfg(not(target_os = "macos"))]
+pub(crate) trait CodeMemory {
+    /// Writes `code` at `dest` with a single atomic store if it fits in one aligned 8-byte
+    /// word, and returns whether it did. The other bytes of the word are left as they are.
+    unsafe fn write_word(&self, dest: *mut u8, code: &[u8]) -> bool;
+
+    /// Copies `code` to `dest` and makes it visible to instruction fetch.
+    unsafe fn copy(&self, dest: *mut u8, code: &[u8]);
+
+    /// Makes the code written between `start` and `end` visible to instruction fetch.
+    unsafe fn clear_cache(&self, start: *mut u8, end: *mut u8);
+}
+
+/// The code of the current process.
+#[cfg(not(target_os = "macos"))]
+pub(crate) struct ProcessCode;
+
+#[cfg(not(target_os = "macos"))]
+impl CodeMemory for ProcessCode {
+    unsafe fn write_word(&self, dest: *mut u8, code: &[u8]) -> bool {
+        use std::sync::atomic::AtomicU64;
+
+        let offset = dest as usize % 8;
+        if offset + code.len() > 8 {
+            return false;
+        }
+
+        let word = &*(dest.sub(offset) as *const AtomicU64);
+        let mut current = word.load(Ordering::Relaxed);
+        loop {
+            let mut bytes = current.to_le_bytes();
+            bytes[offset..offset + code.len()].copy_from_slice(code);
+
+            match word.compare_exchange_weak(
+                current,
+                u64::from_le_bytes(bytes),
+                Ordering::Release,
+                Ordering::Relaxed,
+            ) {
+                Ok(_) => return true,
+                Err(actual) => current = actual,
+            }
+        }
+    }
+
+    unsafe fn copy(&self, dest: *mut u8, code: &[u8]) {
+        inject_asm_code(code, dest);
+    }
+
+    unsafe fn clear_cache(&self, start: *mut u8, end: *mut u8) {
+        clear_cache(start, end);
+    }
+}
+
+/// Writes `code` at `func` in `memory`, which other threads may be executing.
+///
+/// Code that fits in one aligned 8-byte word is written with a single atomic store. Longer
+/// code first replaces the first instruction with `PARK_INSTRUCTION`, then writes everything
+/// after it, and finally replaces `PARK_INSTRUCTION` with the start of `code`. Threads
+/// calling the function meanwhile spin until the whole patch is in place. Threads already
+/// past the first instruction when the patch starts are not protected.
+#[cfg(not(target_os = "macos"))]
+pub(crate) unsafe fn write_code(memory: &impl CodeMemory, func: *mut u8, code: &[u8]) {
+    let head = PARK_INSTRUCTION.len();
+
+    if memory.write_word(func, code) {
+        memory.clear_cache(func, func.add(code.len()));
+    } else if head != 0 && code.len() > head && memory.write_word(func, PARK_INSTRUCTION) {
+        memory.clear_cache(func, func.add(head));
+        memory.copy(func.add(head), &code[head..]);
+        memory.write_word(func, &code[..head]);
+        memory.clear_cache(func, func.add(head));
+    } else {
+        memory.copy(func, code);
+    }
+}
+
+/// Writes `patch` through a writable copy-on-write alias of the code page, since code
+/// pages cannot be made writable in place, then maps the alias back over the code, returning
+/// `InjectError::ProtectionFailed` if a Mach call fails.
+///
+/// Apple silicon and Intel Macs, including x86_64 code running under Rosetta, all go through
+/// this path, as `mprotect` cannot make the code pages of a hardened process writable. The
+/// alias covers the whole patch, which may cross a page boundary.
 #[cfg(target_os = "macos")]
-pub(crate) unsafe fn patch_function(func: *mut u8, patch: &[u8]) {
+pub(crate) unsafe fn try_patch_function(func: *mut u8, patch: &[u8]) -> Result<(), InjectError> {
     use mach2::traps::mach_task_self;
     use mach2::vm::{mach_vm_protect, mach_vm_remap};
     use mach2::vm_inherit::VM_INHERIT_NONE;
     use mach2::vm_prot::VM_PROT_COPY;
     use mach2::vm_statistics::{VM_FLAGS_ANYWHERE, VM_FLAGS_OVERWRITE, VM_FLAGS_RETURN_DATA_ADDR};
 
+    PROTECTION_CHANGES.fetch_add(1, Ordering::Relaxed);
+
+    let len = patch.len() as u64;
     let mut addr = func as mach_vm_address_t;
     let mut remap: mach_vm_address_t = std::mem::zeroed();
     let mut cur: vm_prot_t = std::mem::zeroed();
     let mut max: vm_prot_t = std::mem::zeroed();
-    mach_vm_remap(
-        mach_task_self(),
-        &mut remap,
-        patch.len() as u64,
-        0,
-        VM_FLAGS_ANYWHERE | VM_FLAGS_RETURN_DATA_ADDR,
-        mach_task_self(),
-        addr,
-        0,
-        &mut cur,
-        &mut max,
-        VM_INHERIT_NONE,
-    );
+    check_kern_return(
+        "mach_vm_remap",
+        func,
+        mach_vm_remap(
+            mach_task_self(),
+            &mut remap,
+            len,
+            0,
+            VM_FLAGS_ANYWHERE | VM_FLAGS_RETURN_DATA_ADDR,
+            mach_task_self(),
+            addr,
+            0,
+            &mut cur,
+            &mut max,
+            VM_INHERIT_NONE,
+        ),
+    )?;
 
-    mach_vm_protect(
-        mach_task_self(),
-        remap,
-        0x8,
-        0,
-        VM_PROT_READ | VM_PROT_WRITE | VM_PROT_COPY,
-    );
+    check_kern_return(
+        "mach_vm_protect",
+        func,
+        mach_vm_protect(
+            mach_task_self(),
+            remap,
+            len,
+            0,
+            VM_PROT_READ | VM_PROT_WRITE | VM_PROT_COPY,
+        ),
+    )?;
 
     inject_asm_code(patch, remap as *mut u8);
 
     sys_dcache_flush(func, patch.len());
 
-    mach_vm_protect(
-        mach_task_self(),
-        remap,
-        0x8,
-        0,
-        VM_PROT_READ | VM_PROT_EXECUTE,
-    );
+    check_kern_return(
+        "mach_vm_protect",
+        func,
+        mach_vm_protect(
+            mach_task_self(),
+            remap,
+            len,
+            0,
+            VM_PROT_READ | VM_PROT_EXECUTE,
+        ),
+    )?;
 
-    sys_icache_invalidate(func, patch.len());
-
-    mach_vm_remap(
-        mach_task_self(),
-        &mut addr,
-        patch.len() as u64,
-        0,
-        VM_FLAGS_OVERWRITE | VM_FLAGS_RETURN_DATA_ADDR,
-        mach_task_self(),
-        remap,
-        0,
-        &mut cur,
-        &mut max,
-        VM_INHERIT_NONE,
-    );
+    if !PatchBatch::defer_flush(func as usize..func as usize + patch.len()) {
+        CACHE_FLUSHES.fetch_add(1, Ordering::Relaxed);
+        sys_icache_invalidate(func, patch.len());
+    }
+
+    check_kern_return(
+        "mach_vm_remap",
+        func,
+        mach_vm_remap(
+            mach_task_self(),
+            &mut addr,
+            len,
+            0,
+            VM_FLAGS_OVERWRITE | VM_FLAGS_RETURN_DATA_ADDR,
+            mach_task_self(),
+            remap,
+            0,
+            &mut cur,
+            &mut max,
+            VM_INHERIT_NONE,
+        ),
+    )
+}
+
+/// Returns the name of the Mach call that failed while patching `func` and its error code.
+#[cfg(target_os = "macos")]
+fn check_kern_return(
+    call: &'static str,
+    func: *mut u8,
+    result: mach2::kern_return::kern_return_t,
+) -> Result<(), InjectError> {
+    if result != mach2::kern_return::KERN_SUCCESS {
+        return Err(InjectError::ProtectionFailed {
+            call,
+            address: func as usize,
+            code: result,
+        });
+    }
+
+    Ok(())
 }
 
 // MacOS forces memory to be writable or executable but not both. So we don't need an
 // implementation for it.
 #[cfg(not(target_os = "macos"))]
-unsafe fn make_memory_writable_and_executable(func: *mut u8) {
+unsafe fn make_memory_writable_and_executable(func: *mut u8) -> Result<(), InjectError> {
     #[cfg(target_os = "linux")]
     {
-        make_memory_writable_and_executable_linux(func);
+        make_memory_writable_and_executable_linux(func)
     }
 
     #[cfg(target_os = "windows")]
     {
-        make_memory_writable_and_executable_windows(func);
+        make_memory_writable_and_executable_windows(func)
     }
 }
 
 #[cfg(target_os = "linux")]
-unsafe fn make_memory_writable_and_executable_linux(func: *mut u8) {
+unsafe fn make_memory_writable_and_executable_linux(func: *mut u8) -> Result<(), InjectError> {
     let page_size = sysconf(_SC_PAGESIZE) as usize;
     let addr = func as usize;
     let page_start = addr & !(page_size - 1);
+    if !PatchBatch::claim_page(page_start) {
+        return Ok(());
+    }
+
+    PROTECTION_CHANGES.fetch_add(1, Ordering::Relaxed);
     if libc::mprotect(
         page_start as *mut c_void,
         page_size,
         PROT_READ | PROT_WRITE | PROT_EXEC,
     ) != 0
     {
-        panic!("mprotect failed");
+        return Err(protection_failed("mprotect", func));
     }
+
+    Ok(())
 }
 
 #[cfg(target_os = "windows")]
-unsafe fn make_memory_writable_and_executable_windows(func: *const u8) {
+unsafe fn make_memory_writable_and_executable_windows(func: *const u8) -> Result<(), InjectError> {
     let page_size = get_page_size();
     let addr = func as usize;
     let page_start = addr & !(page_size - 1);
 
+    if !PatchBatch::claim_page(page_start) {
+        return Ok(());
+    }
+
     let mut old_protect: u32 = 0;
 
+    PROTECTION_CHANGES.fetch_add(1, Ordering::Relaxed);
     let result = VirtualProtect(
         page_start as *mut c_void,
         page_size,
@@ -416,28 +1440,205 @@ unsafe fn make_memory_writable_and_executable_windows(func: *const u8) {
     );
 
     if result == 0 {
-        panic!("VirtualProtect failed");
+        return Err(protection_failed("VirtualProtect", func));
+    }
+
+    Ok(())
+}
+
+/// Returns the error of `call` failing on the code at `func`, with the last OS error.
+fn protection_failed(call: &'static str, func: *const u8) -> InjectError {
+    InjectError::ProtectionFailed {
+        call,
+        address: func as usize,
+        code: std::io::Error::last_os_error().raw_os_error().unwrap_or(0),
     }
 }
 
+/// How many times the code pages of patched functions were made writable, see
+/// `InjectorPP::patch_stats`.
+static PROTECTION_CHANGES: AtomicUsize = AtomicUsize::new(0);
+
+/// How many times the instruction cache was flushed, see `InjectorPP::patch_stats`.
+static CACHE_FLUSHES: AtomicUsize = AtomicUsize::new(0);
+
+/// Returns the values of `PROTECTION_CHANGES` and `CACHE_FLUSHES`.
+pub(crate) fn patch_counters() -> (usize, usize) {
+    (
+        PROTECTION_CHANGES.load(Ordering::Relaxed),
+        CACHE_FLUSHES.load(Ordering::Relaxed),
+    )
+}
+
+/// The writes made on this thread while a `PatchBatch` is alive.
+struct PendingWrites {
+    /// The pages already made writable, which stay so until the process exits. macOS writes
+    /// through a copy of the page instead.
+    #[cfg(not(target_os = "macos"))]
+    pages: Vec<usize>,
+    /// The code ranges whose instruction cache has not been flushed yet.
+    flushes: Vec<Range<usize>>,
+}
+
+thread_local! {
+    static PENDING_WRITES: RefCell<Option<PendingWrites>> = const { RefCell::new(None) };
+}
+
+/// Makes the patches written on this thread while alive share their page protection changes
+/// and cache flushes, see `InjectorPP::batch`.
+///
+/// Each page is made writable once, and the flushes are deferred until the batch is dropped,
+/// where overlapping and adjacent ranges are flushed together. Until then, code the batch
+/// wrote may not be visible to instruction fetch yet. Batches nest, only the outermost one
+/// flushes.
+pub(crate) struct PatchBatch {
+    outermost: bool,
+}
+
+impl PatchBatch {
+    pub(crate) fn begin() -> Self {
+        let outermost = PENDING_WRITES.with(|pending| {
+            let mut pending = pending.borrow_mut();
+            if pending.is_some() {
+                return false;
+            }
+
+            *pending = Some(PendingWrites {
+                #[cfg(not(target_os = "macos"))]
+                pages: Vec::new(),
+                flushes: Vec::new(),
+            });
+            true
+        });
+
+        Self { outermost }
+    }
+
+    /// Returns whether the page at `page_start` must be made writable, which is always the
+    /// case outside of a batch.
+    #[cfg(not(target_os = "macos"))]
+    fn claim_page(page_start: usize) -> bool {
+        PENDING_WRITES.with(|pending| match pending.borrow_mut().as_mut() {
+            Some(pending) if pending.pages.contains(&page_start) => false,
+            Some(pending) => {
+                pending.pages.push(page_start);
+                true
+            }
+            None => true,
+        })
+    }
+
+    /// Records `range` to be flushed when the batch ends, and returns whether there is a
+    /// batch to do so.
+    fn defer_flush(range: Range<usize>) -> bool {
+        PENDING_WRITES.with(|pending| match pending.borrow_mut().as_mut() {
+            Some(pending) => {
+                pending.flushes.push(range);
+                true
+            }
+            None => false,
+        })
+    }
+}
+
+impl Drop for PatchBatch {
+    fn drop(&mut self) {
+        if !self.outermost {
+            return;
+        }
+
+        let Some(pending) = PENDING_WRITES.with(|pending| pending.borrow_mut().take()) else {
+            return;
+        };
+
+        for range in coalesce_ranges(pending.flushes) {
+            unsafe {
+                #[cfg(target_os = "macos")]
+                {
+                    CACHE_FLUSHES.fetch_add(1, Ordering::Relaxed);
+                    sys_icache_invalidate(range.start as *mut u8, range.len());
+                }
+
+                #[cfg(not(target_os = "macos"))]
+                flush_cache(range.start as *mut u8, range.end as *mut u8);
+            }
+        }
+    }
+}
+
+/// Sorts `ranges` and merges the ones that overlap or touch.
+fn coalesce_ranges(mut ranges: Vec<Range<usize>>) -> Vec<Range<usize>> {
+    ranges.sort_by_key(|range| range.start);
+
+    let mut merged: Vec<Range<usize>> = Vec::with_capacity(ranges.len());
+    for range in ranges {
+        match merged.last_mut() {
+            Some(last) if range.start <= last.end => last.end = last.end.max(range.end),
+            _ => merged.push(range),
+        }
+    }
+
+    merged
+}
+
 pub(crate) unsafe fn inject_asm_code(asm_code: &[u8], dest: *mut u8) {
-    #[cfg(target_os = "macos")]
+    // Only Apple silicon toggles MAP_JIT pages between writable and executable per thread,
+    // on x86_64 they are both at once.
+    #[cfg(all(target_os = "macos", target_arch = "aarch64"))]
     pthread_jit_write_protect_np(0);
 
     ptr::copy_nonoverlapping(asm_code.as_ptr(), dest, asm_code.len());
 
-    #[cfg(target_os = "macos")]
+    #[cfg(all(target_os = "macos", target_arch = "aarch64"))]
     pthread_jit_write_protect_np(1);
 
     clear_cache(dest, dest.add(asm_code.len()));
 }
 
+/// Makes freshly written code visible to instruction fetch.
+///
+/// x86 and x86_64 keep instruction fetch coherent with data stores, including stores made by
+/// other cores, so Linux needs no flush there and `__clear_cache` would only be an empty call. A
+/// serializing instruction such as `cpuid` is not issued either: the cross-modifying code
+/// protocol requires it on the core that executes the new code, which the patching thread
+/// cannot do on its behalf. Threads that start calling the function after the patch is
+/// installed, e.g. after being released through a channel or a barrier, already observe the
+/// stores through the synchronization that released them. Only the compiler must not sink the
+/// stores past the point where the patched code may run, hence the fence.
+///
+/// Windows documents `FlushInstructionCache` as required after modifying code on every
+/// architecture, so it is kept on x86 and x86_64 as well. On AArch64 Linux the cache lines are
+/// maintained directly, see `clear_cache_lines`.
 unsafe fn clear_cache(start: *mut u8, end: *mut u8) {
-    #[cfg(target_os = "linux")]
+    if !PatchBatch::defer_flush(start as usize..end as usize) {
+        flush_cache(start, end);
+    }
+}
+
+/// Flushes the instruction cache over `start..end` right away, see `clear_cache`.
+unsafe fn flush_cache(start: *mut u8, end: *mut u8) {
+    CACHE_FLUSHES.fetch_add(1, Ordering::Relaxed);
+
+    #[cfg(all(target_os = "linux", target_arch = "aarch64"))]
+    {
+        clear_cache_lines(start as usize, end as usize)
+    }
+
+    #[cfg(all(
+        target_os = "linux",
+        not(any(target_arch = "x86", target_arch = "x86_64", target_arch = "aarch64"))
+    ))]
     {
         __clear_cache(start, end)
     }
 
+    #[cfg(all(target_os = "linux", any(target_arch = "x86", target_arch = "x86_64")))]
+    {
+        let _ = start;
+        let _ = end;
+        std::sync::atomic::compiler_fence(std::sync::atomic::Ordering::SeqCst);
+    }
+
     #[cfg(target_os = "windows")]
     {
         let size = end.offset_from(start) as usize;
@@ -462,3 +1663,261 @@ unsafe fn clear_cache(start: *mut u8, end: *mut u8) {
         core::arch::asm!("dsb sy", "isb", options(nostack, nomem));
     }
 }
+
+/// Cleans the data cache and invalidates the instruction cache line by line over
+/// `start..end`, which may span several pages.
+///
+/// The line sizes are read from `CTR_EL0` on every call rather than cached: the kernel reports
+/// the smallest line size of all cores, so a thread migrating between big and little cores
+/// never skips a line. Cores that advertise coherent caches through the `IDC` and `DIC` bits
+/// only need the barriers.
+#[cfg(all(target_os = "linux", target_arch = "aarch64"))]
+unsafe fn clear_cache_lines(start: usize, end: usize) {
+    use core::arch::asm;
+
+    const IDC: u64 = 1 << 28;
+    const DIC: u64 = 1 << 29;
+
+    let ctr: u64;
+    asm!("mrs {}, ctr_el0", out(reg) ctr, options(nostack, nomem));
+
+    // Both fields are log2 of the number of 4-byte words in a line.
+    let data_line = 4usize << ((ctr >> 16) & 0xF);
+    let instruction_line = 4usize << (ctr & 0xF);
+
+    if ctr & IDC == 0 {
+        let mut line = start & !(data_line - 1);
+        while line < end {
+            asm!("dc cvau, {}", in(reg) line, options(nostack));
+            line += data_line;
+        }
+    }
+    asm!("dsb ish", options(nostack));
+
+    if ctr & DIC == 0 {
+        let mut line = start & !(instruction_line - 1);
+        while line < end {
+            asm!("ic ivau, {}", in(reg) line, options(nostack));
+            line += instruction_line;
+        }
+        asm!("dsb ish", options(nostack));
+    }
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_coalesce_ranges_when_ranges_overlap_or_touch_should_merge_them() {
+        let ranges = vec![
+            0x2000..0x2010,
+            0x1000..0x1008,
+            0x1004..0x1010,
+            0x1010..0x1020,
+        ];
+
+        assert_eq!(
+            coalesce_ranges(ranges),
+            vec![0x1000..0x1020, 0x2000..0x2010]
+        );
+    }
+
+    #[test]
+    fn test_coalesce_ranges_when_range_contains_next_should_keep_its_end() {
+        let ranges = vec![0x1000..0x1100, 0x1010..0x1020, 0x1200..0x1210];
+
+        assert_eq!(
+            coalesce_ranges(ranges),
+            vec![0x1000..0x1100, 0x1200..0x1210]
+        );
+    }
+
+    /// Maps two pages and unmaps the second one, returning the first page.
+    fn map_page_followed_by_hole() -> (*mut u8, usize) {
+        let page_size = unsafe { sysconf(_SC_PAGESIZE) as usize };
+        let page = unsafe {
+            mmap(
+                ptr::null_mut(),
+                page_size * 2,
+                PROT_READ | PROT_WRITE,
+                MAP_PRIVATE | MAP_ANONYMOUS,
+                -1,
+                0,
+            )
+        };
+        assert_ne!(page, MAP_FAILED);
+
+        unsafe {
+            munmap((page as *mut u8).add(page_size) as *mut c_void, page_size);
+            ptr::write_bytes(page as *mut u8, 0xAB, page_size);
+        }
+
+        (page as *mut u8, page_size)
+    }
+
+    #[test]
+    fn test_patch_guard_drop_when_restore_fails_should_record_failure_without_panicking() {
+        use crate::interface::failure::{take_restore_failures, Failure};
+
+        let (page, page_size) = map_page_followed_by_hole();
+        let guard = PatchGuard::new(page, vec![0; 4], 4, ptr::null_mut(), 0);
+
+        // The page cannot be made writable once unmapped, like the code of a process that
+        // forbids it.
+        unsafe {
+            munmap(page as *mut c_void, page_size);
+        }
+
+        // Panicking in the drop while a failed test unwinds would abort the process.
+        let result = std::panic::catch_unwind(std::panic::AssertUnwindSafe(move || {
+            let _guard = guard;
+            panic!("The test failed");
+        }));
+        assert!(result.is_err());
+
+        assert!(take_restore_failures().iter().any(|failure| matches!(
+            failure,
+            Failure::Restore {
+                function,
+                error: InjectError::ProtectionFailed { .. },
+            } if *function == page as usize
+        )));
+    }
+
+    #[test]
+    fn test_try_read_bytes_past_mapping_end_should_return_error() {
+        let (page, page_size) = map_page_followed_by_hole();
+        let tail = unsafe { page.add(page_size - 4) };
+
+        assert_eq!(try_read_bytes(tail, 4), Ok(vec![0xAB; 4]));
+        assert_eq!(
+            try_read_bytes(tail, 8),
+            Err(InjectError::UnreadableMemory {
+                address: tail as usize,
+                len: 8
+            })
+        );
+
+        unsafe {
+            munmap(page as *mut c_void, page_size);
+        }
+    }
+
+    #[test]
+    fn test_try_read_bytes_when_page_not_readable_should_return_error() {
+        let (page, page_size) = map_page_followed_by_hole();
+
+        unsafe {
+            mprotect(page as *mut c_void, page_size, PROT_NONE);
+        }
+        assert!(try_read_bytes(page, 1).is_err());
+
+        unsafe {
+            munmap(page as *mut c_void, page_size);
+        }
+    }
+
+    #[cfg(any(
+        target_arch = "aarch64",
+        target_arch = "x86_64",
+        target_arch = "riscv64",
+        target_arch = "x86"
+    ))]
+    #[test]
+    fn test_allocate_jit_memory_for_nearby_functions_should_share_one_page() {
+        fn first() {}
+        fn second() {}
+
+        let first = unsafe { FuncPtrInternal::new(NonNull::new(first as *mut ()).unwrap()) };
+        let second = unsafe { FuncPtrInternal::new(NonNull::new(second as *mut ()).unwrap()) };
+        let page_size = jit_page_size();
+
+        let first_stub = allocate_jit_memory(&first, 40);
+        let second_stub = allocate_jit_memory(&second, 40);
+        assert_ne!(first_stub, second_stub);
+        assert_eq!(
+            first_stub as usize / page_size,
+            second_stub as usize / page_size
+        );
+
+        unsafe {
+            free_jit_memory(first_stub, 40);
+        }
+        assert!(try_read_bytes(second_stub, 1).is_ok());
+
+        // The page is unmapped with its last stub.
+        unsafe {
+            free_jit_memory(second_stub, 40);
+        }
+        assert!(try_read_bytes(second_stub, 1).is_err());
+    }
+
+    /// Returns code that runs `nops` no-op instructions and returns `value`.
+    #[cfg(any(
+        target_arch = "aarch64",
+        target_arch = "x86_64",
+        target_arch = "riscv64",
+        target_arch = "x86"
+    ))]
+    fn return_value_code(value: u8, nops: usize) -> Vec<u8> {
+        #[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
+        let code = [vec![0x90; nops], vec![0xB8, value, 0, 0, 0, 0xC3]].concat();
+
+        #[cfg(target_arch = "aarch64")]
+        let code = std::iter::repeat_n(0xD503201F_u32, nops) // nop
+            .chain([0x52800000 | (value as u32) << 5, 0xD65F03C0]) // movz w0, #value; ret
+            .flat_map(u32::to_le_bytes)
+            .collect();
+
+        #[cfg(target_arch = "riscv64")]
+        let code = std::iter::repeat_n(0x00000013_u32, nops) // nop
+            .chain([0x00000513 | (value as u32) << 20, 0x00008067]) // li a0, value; ret
+            .flat_map(u32::to_le_bytes)
+            .collect();
+
+        code
+    }
+
+    #[cfg(all(
+        target_os = "linux",
+        any(
+            target_arch = "aarch64",
+            target_arch = "x86_64",
+            target_arch = "riscv64",
+            target_arch = "x86"
+        )
+    ))]
+    #[test]
+    fn test_inject_asm_code_spanning_cache_lines_and_pages_should_run_new_code() {
+        let page_size = jit_page_size();
+        let pages = unsafe {
+            mmap(
+                ptr::null_mut(),
+                page_size * 2,
+                PROT_READ | PROT_WRITE | PROT_EXEC,
+                MAP_PRIVATE | MAP_ANONYMOUS,
+                -1,
+                0,
+            )
+        };
+        assert_ne!(pages, MAP_FAILED);
+
+        // Hundreds of bytes starting in the middle of a line of the first page and ending with
+        // the `ret` on the second page.
+        let code = return_value_code(1, 96);
+        let start = unsafe { (pages as *mut u8).add(page_size - code.len() / 2 - 4) };
+
+        for value in [1, 2, 3] {
+            unsafe {
+                inject_asm_code(&return_value_code(value, 96), start);
+                let function: extern "C" fn() -> u32 = std::mem::transmute(start);
+                assert_eq!(function(), value as u32);
+            }
+        }
+
+        unsafe {
+            munmap(pages, page_size * 2);
+        }
+    }
+}
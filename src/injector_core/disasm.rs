@@ -0,0 +1,288 @@
+//! Lists code as instructions for `PatchDebug::disassembly`.
+//!
+//! Instructions are split by length, and only those the patches are made of are named:
+//! branches, the moves loading their targets and the padding around them. Like the code
+//! generators this never executes anything, so it is compiled and tested on every host.
+#![allow(dead_code)]
+
+use crate::injector_core::amd64_relocator::decode;
+use crate::injector_core::arm64_codegenerator::unconditional_branch_target;
+
+/// Lists the instructions of `code`, placed at `address`, one per line.
+pub(crate) fn disassemble(code: &[u8], address: usize) -> Vec<String> {
+    #[cfg(target_arch = "aarch64")]
+    {
+        disassemble_arm64(code, address)
+    }
+
+    #[cfg(target_arch = "x86_64")]
+    {
+        disassemble_amd64(code, address)
+    }
+
+    #[cfg(target_arch = "riscv64")]
+    {
+        disassemble_riscv64(code, address)
+    }
+
+    #[cfg(not(any(
+        target_arch = "aarch64",
+        target_arch = "x86_64",
+        target_arch = "riscv64"
+    )))]
+    {
+        code.chunks(4)
+            .enumerate()
+            .map(|(index, bytes)| line(address + index * 4, bytes, ""))
+            .collect()
+    }
+}
+
+/// Formats one instruction of the listing.
+fn line(address: usize, bytes: &[u8], text: &str) -> String {
+    let bytes = bytes
+        .iter()
+        .map(|byte| format!("{byte:02x}"))
+        .collect::<Vec<_>>()
+        .join(" ");
+
+    format!("{address:#x}: {bytes:<30} {text}")
+        .trim_end()
+        .to_string()
+}
+
+const AMD64_REGISTERS: [&str; 8] = ["rax", "rcx", "rdx", "rbx", "rsp", "rbp", "rsi", "rdi"];
+
+/// Splits x86_64 `code` with the relocator's decoder. Bytes it does not understand end the
+/// listing on a single line.
+fn disassemble_amd64(code: &[u8], address: usize) -> Vec<String> {
+    let mut lines = Vec::new();
+    let mut offset = 0;
+
+    while offset < code.len() {
+        let Some(instruction) = decode(&code[offset..]).filter(|i| offset + i.len <= code.len())
+        else {
+            lines.push(line(address + offset, &code[offset..], "(unknown)"));
+            break;
+        };
+
+        let bytes = &code[offset..offset + instruction.len];
+        let next = address + offset + instruction.len;
+        lines.push(line(address + offset, bytes, &name_amd64(bytes, next)));
+        offset += instruction.len;
+    }
+
+    lines
+}
+
+/// Names the x86_64 instruction `bytes`, which ends at `next`, or returns an empty string.
+fn name_amd64(bytes: &[u8], next: usize) -> String {
+    let relative = |displacement: &[u8]| {
+        let displacement = match displacement.len() {
+            1 => displacement[0] as i8 as isize,
+            _ => i32::from_le_bytes(displacement.try_into().unwrap()) as isize,
+        };
+        next.wrapping_add_signed(displacement)
+    };
+
+    match bytes {
+        [0xE9, displacement @ ..] | [0xEB, displacement @ ..] => {
+            format!("jmp {:#x}", relative(displacement))
+        }
+        [0xE8, displacement @ ..] => format!("call {:#x}", relative(displacement)),
+        [0x48, opcode @ 0xB8..=0xBF, imm @ ..] if imm.len() == 8 => format!(
+            "mov {}, {:#x}",
+            AMD64_REGISTERS[(opcode - 0xB8) as usize],
+            u64::from_le_bytes(imm.try_into().unwrap())
+        ),
+        [0xFF, modrm] if modrm >> 6 == 3 && (modrm >> 3) & 7 == 4 => {
+            format!("jmp {}", AMD64_REGISTERS[(modrm & 7) as usize])
+        }
+        [0xFF, modrm] if modrm >> 6 == 3 && (modrm >> 3) & 7 == 2 => {
+            format!("call {}", AMD64_REGISTERS[(modrm & 7) as usize])
+        }
+        [0xC3] => "ret".to_string(),
+        [0xCC] => "int3".to_string(),
+        [0x90] | [0x0F, 0x1F, ..] | [0x66, 0x0F, 0x1F, ..] => "nop".to_string(),
+        [0x0F, 0x0B] => "ud2".to_string(),
+        _ => String::new(),
+    }
+}
+
+/// Splits AArch64 `code` into its 4-byte instructions.
+fn disassemble_arm64(code: &[u8], address: usize) -> Vec<String> {
+    code.chunks(4)
+        .enumerate()
+        .map(|(index, bytes)| {
+            let address = address + index * 4;
+            let text = match bytes.try_into() {
+                Ok(word) => name_arm64(u32::from_le_bytes(word), address),
+                Err(_) => "(truncated)".to_string(),
+            };
+            line(address, bytes, &text)
+        })
+        .collect()
+}
+
+/// Names the AArch64 `instruction` at `address`, or returns an empty string.
+fn name_arm64(instruction: u32, address: usize) -> String {
+    let rd = instruction & 0x1F;
+    let rn = (instruction >> 5) & 0x1F;
+    let sign_extend =
+        |value: u32, bits: u32| ((value << (32 - bits)) as i32 >> (32 - bits)) as isize;
+
+    if let Some(target) = unconditional_branch_target(instruction, address) {
+        return format!("b {target:#x}");
+    }
+
+    match instruction {
+        0xD503_201F => "nop".to_string(),
+        _ if instruction & 0xFC00_0000 == 0x9400_0000 => {
+            let offset = sign_extend(instruction & 0x03FF_FFFF, 26) * 4;
+            format!("bl {:#x}", address.wrapping_add_signed(offset))
+        }
+        _ if instruction & 0xFFFF_FC1F == 0xD61F_0000 => format!("br x{rn}"),
+        _ if instruction & 0xFFFF_FC1F == 0xD63F_0000 => format!("blr x{rn}"),
+        0xD65F_03C0 => "ret".to_string(),
+        _ if instruction & 0xFFFF_FC1F == 0xD65F_0000 => format!("ret x{rn}"),
+        _ if instruction & 0xFF80_0000 == 0xD280_0000
+            || instruction & 0xFF80_0000 == 0xF280_0000 =>
+        {
+            let mnemonic = if instruction & 0x2000_0000 == 0 {
+                "movz"
+            } else {
+                "movk"
+            };
+            let imm16 = (instruction >> 5) & 0xFFFF;
+            let shift = ((instruction >> 21) & 3) * 16;
+            format!("{mnemonic} x{rd}, #{imm16:#x}, lsl #{shift}")
+        }
+        _ if instruction & 0x9F00_0000 == 0x9000_0000 => {
+            let imm = ((instruction >> 5) & 0x7_FFFF) << 2 | (instruction >> 29) & 3;
+            let page = (address & !0xFFF).wrapping_add_signed(sign_extend(imm, 21) << 12);
+            format!("adrp x{rd}, {page:#x}")
+        }
+        _ if instruction & 0xFF80_0000 == 0x9100_0000 => {
+            let imm12 = (instruction >> 10) & 0xFFF;
+            let imm = if instruction & (1 << 22) != 0 {
+                imm12 << 12
+            } else {
+                imm12
+            };
+            format!("add x{rd}, x{rn}, #{imm:#x}")
+        }
+        _ if instruction & 0xFF00_0000 == 0x5800_0000 => {
+            let offset = sign_extend((instruction >> 5) & 0x7_FFFF, 19) * 4;
+            format!("ldr x{rd}, {:#x}", address.wrapping_add_signed(offset))
+        }
+        _ => String::new(),
+    }
+}
+
+/// Splits RISC-V `code` into its 2-byte compressed and 4-byte instructions.
+fn disassemble_riscv64(code: &[u8], address: usize) -> Vec<String> {
+    let mut lines = Vec::new();
+    let mut offset = 0;
+
+    while offset < code.len() {
+        let len = if code[offset] & 0b11 == 0b11 { 4 } else { 2 };
+        let bytes = &code[offset..code.len().min(offset + len)];
+        lines.push(line(address + offset, bytes, ""));
+        offset += len;
+    }
+
+    lines
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::injector_core::amd64_codegenerator::{emit_abs_jump, emit_jmp_rel32};
+    use crate::injector_core::arm64_codegenerator::maybe_emit_long_jump;
+
+    fn arm64_code(words: &[u32]) -> Vec<u8> {
+        words.iter().flat_map(|word| word.to_le_bytes()).collect()
+    }
+
+    #[test]
+    fn test_disassemble_amd64_when_near_branch_padded_with_nops_should_name_them() {
+        let mut code = emit_jmp_rel32(0x1000, 0x2000).unwrap();
+        code.extend([0x90, 0x0F, 0x1F, 0x00]);
+
+        assert_eq!(
+            disassemble_amd64(&code, 0x1000),
+            vec![
+                format!("0x1000: {:<30} jmp 0x2000", "e9 fb 0f 00 00"),
+                format!("0x1005: {:<30} nop", "90"),
+                format!("0x1006: {:<30} nop", "0f 1f 00"),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_disassemble_amd64_when_absolute_jump_should_name_move_and_jump() {
+        let code = emit_abs_jump(0x7FFF_1234_5678);
+
+        let lines = disassemble_amd64(&code, 0x1000);
+
+        assert_eq!(lines.len(), 2);
+        assert!(lines[0].ends_with("mov rax, 0x7fff12345678"));
+        assert_eq!(lines[1], format!("0x100a: {:<30} jmp rax", "ff e0"));
+    }
+
+    #[test]
+    fn test_disassemble_amd64_when_bytes_cut_short_should_end_with_unknown_line() {
+        let lines = disassemble_amd64(&[0xC3, 0xE9, 0x00], 0x1000);
+
+        assert_eq!(lines[0], format!("0x1000: {:<30} ret", "c3"));
+        assert_eq!(lines[1], format!("0x1001: {:<30} (unknown)", "e9 00"));
+    }
+
+    #[test]
+    fn test_disassemble_arm64_when_near_branch_padded_with_nops_should_name_them() {
+        let mut words = maybe_emit_long_jump(0x10_0000, 0x10_8000);
+        words.extend([0xD503_201F, 0xD503_201F]);
+
+        let lines = disassemble_arm64(&arm64_code(&words), 0x10_0000);
+
+        assert!(lines[0].ends_with("b 0x108000"));
+        assert!(lines[1].ends_with("nop"));
+        assert!(lines[2].starts_with("0x100008: ") && lines[2].ends_with("nop"));
+    }
+
+    #[test]
+    fn test_disassemble_arm64_when_long_jump_should_name_adrp_add_and_br() {
+        let words = maybe_emit_long_jump(0x5555_0000_0234, 0x5555_4000_1238);
+
+        let lines = disassemble_arm64(&arm64_code(&words), 0x5555_0000_0234);
+
+        assert!(lines[0].ends_with("adrp x16, 0x555540001000"));
+        assert!(lines[1].ends_with("add x16, x16, #0x238"));
+        assert!(lines[2].ends_with("br x16"));
+    }
+
+    #[test]
+    fn test_name_arm64_when_moves_and_loads_should_decode_operands() {
+        // movz x9, #0x5678; movk x9, #0x1234, lsl #16; ldr x17, #8; ret; blr x9
+        assert_eq!(name_arm64(0xD28A_CF09, 0), "movz x9, #0x5678, lsl #0");
+        assert_eq!(name_arm64(0xF2A2_4689, 0), "movk x9, #0x1234, lsl #16");
+        assert_eq!(name_arm64(0x5800_0051, 0x1000), "ldr x17, 0x1008");
+        assert_eq!(name_arm64(0xD65F_03C0, 0), "ret");
+        assert_eq!(name_arm64(0xD63F_0120, 0), "blr x9");
+        assert_eq!(name_arm64(0x1234_5678, 0), "");
+    }
+
+    #[test]
+    fn test_disassemble_riscv64_when_compressed_and_full_instructions_should_split_them() {
+        // c.nop; auipc t0, 0
+        let code = [0x01, 0x00, 0x97, 0x02, 0x00, 0x00];
+
+        assert_eq!(
+            disassemble_riscv64(&code, 0x1000),
+            vec![
+                "0x1000: 01 00".to_string(),
+                "0x1002: 97 02 00 00".to_string()
+            ]
+        );
+    }
+}
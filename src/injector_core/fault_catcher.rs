@@ -0,0 +1,115 @@
+#![cfg(all(feature = "safe-verify", any(target_os = "linux", target_os = "macos")))]
+
+use std::sync::atomic::{AtomicI32, AtomicUsize, Ordering};
+use std::sync::mpsc::{self, RecvTimeoutError};
+use std::sync::{Mutex, PoisonError};
+use std::time::Duration;
+
+use libc::{c_int, c_void, siginfo_t};
+
+/// The signals raised by running a faulty patch or JIT code: branching to unmapped or
+/// non-executable memory, an invalid or trap instruction, or a misaligned access.
+const FAULT_SIGNALS: [c_int; 4] = [libc::SIGSEGV, libc::SIGBUS, libc::SIGILL, libc::SIGTRAP];
+
+/// Serializes `run_catching_faults`, as signal handlers are process-global.
+static CATCHING: Mutex<()> = Mutex::new(());
+
+/// The `pthread_t` of the thread whose faults are caught, 0 when there is none.
+static CATCHING_THREAD: AtomicUsize = AtomicUsize::new(0);
+
+/// The signal the thread in `CATCHING_THREAD` raised, 0 if none.
+static CAUGHT_SIGNAL: AtomicI32 = AtomicI32::new(0);
+
+/// Runs `call` on a new thread while handlers for `FAULT_SIGNALS` are installed, and returns
+/// its result or the signal it raised. A panic of `call` is resumed on the current thread.
+///
+/// A thread cannot resume after a fault and Rust code cannot unwind out of a signal handler,
+/// so the faulting thread is parked in the handler forever. It is leaked, and so is whatever
+/// `call` owns. A fault of any other thread while the handlers are installed gets the default
+/// action, which usually terminates the process, instead of the handler installed before.
+pub(crate) fn run_catching_faults<R: Send + 'static>(
+    call: impl FnOnce() -> R + Send + 'static,
+) -> Result<R, i32> {
+    let _catching = CATCHING.lock().unwrap_or_else(PoisonError::into_inner);
+    CAUGHT_SIGNAL.store(0, Ordering::SeqCst);
+    let previous = unsafe { install_handlers() };
+
+    let (sender, receiver) = mpsc::channel();
+    let thread = std::thread::spawn(move || {
+        CATCHING_THREAD.store(unsafe { libc::pthread_self() } as usize, Ordering::SeqCst);
+        let result = call();
+        CATCHING_THREAD.store(0, Ordering::SeqCst);
+
+        let _ = sender.send(result);
+    });
+
+    // The result is `None` when `call` panicked.
+    let outcome = loop {
+        match receiver.recv_timeout(Duration::from_millis(1)) {
+            Ok(result) => break Some(Ok(result)),
+            Err(RecvTimeoutError::Timeout) => match CAUGHT_SIGNAL.load(Ordering::SeqCst) {
+                0 => continue,
+                signal => break Some(Err(signal)),
+            },
+            Err(RecvTimeoutError::Disconnected) => break None,
+        }
+    };
+
+    unsafe {
+        restore_handlers(&previous);
+    }
+    CATCHING_THREAD.store(0, Ordering::SeqCst);
+
+    match outcome {
+        Some(outcome) => outcome,
+        None => std::panic::resume_unwind(
+            thread
+                .join()
+                .expect_err("The thread ended without sending its result"),
+        ),
+    }
+}
+
+/// Installs `handle_fault` for every signal of `FAULT_SIGNALS` and returns the previous
+/// actions.
+unsafe fn install_handlers() -> Vec<(c_int, libc::sigaction)> {
+    let handler: extern "C" fn(c_int, *mut siginfo_t, *mut c_void) = handle_fault;
+
+    FAULT_SIGNALS
+        .iter()
+        .map(|&signal| {
+            let mut action: libc::sigaction = std::mem::zeroed();
+            action.sa_sigaction = handler as libc::sighandler_t;
+            // Rust threads have an alternate stack, so a stack overflow is caught as well.
+            action.sa_flags = libc::SA_SIGINFO | libc::SA_ONSTACK;
+            libc::sigemptyset(&mut action.sa_mask);
+
+            let mut previous: libc::sigaction = std::mem::zeroed();
+            libc::sigaction(signal, &action, &mut previous);
+            (signal, previous)
+        })
+        .collect()
+}
+
+unsafe fn restore_handlers(previous: &[(c_int, libc::sigaction)]) {
+    for (signal, action) in previous {
+        libc::sigaction(*signal, action, std::ptr::null_mut());
+    }
+}
+
+extern "C" fn handle_fault(signal: c_int, _info: *mut siginfo_t, _context: *mut c_void) {
+    if unsafe { libc::pthread_self() } as usize != CATCHING_THREAD.load(Ordering::SeqCst) {
+        // The faulting instruction runs again once the handler returns, and faults again.
+        unsafe {
+            libc::signal(signal, libc::SIG_DFL);
+        }
+        return;
+    }
+
+    CAUGHT_SIGNAL.store(signal, Ordering::SeqCst);
+    loop {
+        unsafe {
+            libc::pause();
+        }
+    }
+}
@@ -1,4 +1,7 @@
 use crate::injector_core::common::*;
+use crate::interface::debug::BranchKind;
+use crate::interface::error::InjectError;
+use std::sync::atomic::AtomicUsize;
 
 #[cfg(target_arch = "aarch64")]
 use super::patch_arm64::PatchArm64;
@@ -9,52 +12,611 @@ use super::patch_amd64::PatchAmd64;
 #[cfg(target_arch = "arm")]
 use super::patch_arm::PatchArm;
 
+#[cfg(target_arch = "riscv64")]
+use super::patch_riscv64::PatchRiscv64;
+
+#[cfg(target_arch = "x86")]
+use super::patch_x86::PatchX86;
+
 use super::patch_trait::PatchTrait;
 
 /// An internal builder for patching a function. Not exposed publicly.
 pub(crate) struct WhenCalled {
     func_ptr: FuncPtrInternal,
+    prologue: Vec<u8>,
+    allow_tail_call: bool,
+    assume_patchable: bool,
 }
 
+/// A function called with a registration specific pointer each time a patched function runs.
+///
+/// `registers` points at the argument registers saved by the patched function, use
+/// `WhenCalled::argument_slot` to find a given argument in it.
+///
+/// A panic of the hook, such as one of a callback run by `will_invoke_callback`, unwinds
+/// through the JIT block where it has unwind information, see `jit_unwind`.
+pub(crate) type CallHook = extern "C-unwind" fn(data: *const (), registers: *const u64);
+
 impl WhenCalled {
     pub(crate) fn new(func: FuncPtrInternal) -> Self {
-        Self { func_ptr: func }
+        Self {
+            func_ptr: func,
+            prologue: Vec::new(),
+            allow_tail_call: false,
+            assume_patchable: false,
+        }
+    }
+
+    /// Returns the address of the target function.
+    pub(crate) fn address(&self) -> usize {
+        self.func_ptr.as_ptr() as usize
+    }
+
+    /// Checks that the target function can be patched, see `PatchTrait::check_patch_site`.
+    pub(crate) fn check_patch_site(&self) -> Result<(), InjectError> {
+        check_executable(self.address())?;
+
+        #[cfg(target_arch = "aarch64")]
+        {
+            PatchArm64::check_patch_site(&self.func_ptr)
+        }
+
+        #[cfg(target_arch = "x86_64")]
+        {
+            PatchAmd64::check_patch_site(&self.func_ptr)
+        }
+
+        #[cfg(target_arch = "arm")]
+        {
+            PatchArm::check_patch_site(&self.func_ptr)
+        }
+
+        #[cfg(target_arch = "riscv64")]
+        {
+            PatchRiscv64::check_patch_site(&self.func_ptr)
+        }
+
+        #[cfg(target_arch = "x86")]
+        {
+            PatchX86::check_patch_site(&self.func_ptr)
+        }
+    }
+
+    /// Accepts a target function whose whole body is a tail call, see `check_tail_call`.
+    pub(crate) fn allow_tail_call(&mut self) {
+        self.allow_tail_call = true;
+    }
+
+    /// Skips the checks that the target function is long enough for the patch, see
+    /// `check_patchable`.
+    pub(crate) fn assume_patchable(&mut self) {
+        self.assume_patchable = true;
+    }
+
+    /// Targets the function an AArch64 thunk, a function made of a single unconditional `b`,
+    /// jumps to instead of the thunk itself. Does nothing for other functions and on other
+    /// architectures.
+    pub(crate) fn follow_branch_thunk(&mut self) {
+        #[cfg(target_arch = "aarch64")]
+        if let Some(target) = PatchArm64::branch_thunk_target(&self.func_ptr) {
+            self.func_ptr = target;
+        }
+    }
+
+    /// Refuses to patch an AArch64 function that starts with an unconditional branch unless
+    /// `allow_tail_call` or `assume_patchable` was called.
+    ///
+    /// Such a function is a single tail call and may be shorter than the usual patch, so only
+    /// its branch is replaced, which the caller must opt in to. On x86_64 the patch checks
+    /// that it does not overwrite the end of the function unless `assume_patchable` was
+    /// called, see `PatchAmd64::set_assume_patchable`.
+    fn check_patchable(&self) {
+        #[cfg(target_arch = "x86_64")]
+        PatchAmd64::set_assume_patchable(self.assume_patchable);
+
+        #[cfg(target_arch = "aarch64")]
+        if !self.allow_tail_call
+            && !self.assume_patchable
+            && PatchArm64::is_tail_call(&self.func_ptr)
+        {
+            panic!(
+                "The function at {:p} is a single tail call, call allow_tail_call_target() to replace its branch",
+                self.func_ptr.as_ptr()
+            );
+        }
+    }
+
+    /// Returns the position of the integer argument number `index` in the `registers` passed
+    /// to a `CallHook`, or `None` if that argument is not passed in a register.
+    pub(crate) fn argument_slot(index: usize) -> Option<usize> {
+        #[cfg(target_arch = "aarch64")]
+        {
+            PatchArm64::argument_slot(index)
+        }
+
+        #[cfg(target_arch = "x86_64")]
+        {
+            PatchAmd64::argument_slot(index)
+        }
+
+        #[cfg(target_arch = "arm")]
+        {
+            PatchArm::argument_slot(index)
+        }
+
+        #[cfg(target_arch = "riscv64")]
+        {
+            PatchRiscv64::argument_slot(index)
+        }
+
+        #[cfg(target_arch = "x86")]
+        {
+            PatchX86::argument_slot(index)
+        }
+    }
+
+    /// Returns how the bytes `patch` written over a function branch away from it, see
+    /// `PatchTrait::branch_kind`.
+    pub(crate) fn branch_kind(patch: &[u8]) -> BranchKind {
+        #[cfg(target_arch = "aarch64")]
+        {
+            PatchArm64::branch_kind(patch)
+        }
+
+        #[cfg(target_arch = "x86_64")]
+        {
+            PatchAmd64::branch_kind(patch)
+        }
+
+        #[cfg(target_arch = "arm")]
+        {
+            PatchArm::branch_kind(patch)
+        }
+
+        #[cfg(target_arch = "riscv64")]
+        {
+            PatchRiscv64::branch_kind(patch)
+        }
+
+        #[cfg(target_arch = "x86")]
+        {
+            PatchX86::branch_kind(patch)
+        }
+    }
+
+    /// Makes the JIT block call `hook(data, registers)` before doing anything else.
+    ///
+    /// `data` must stay valid for as long as the patch is installed.
+    pub(crate) fn add_call_hook(&mut self, hook: CallHook, data: *const ()) {
+        let hook = hook as usize;
+        let data = data as usize;
+
+        #[cfg(target_arch = "aarch64")]
+        {
+            self.prologue.extend(PatchArm64::emit_call_hook(hook, data));
+        }
+
+        #[cfg(target_arch = "x86_64")]
+        {
+            self.prologue.extend(PatchAmd64::emit_call_hook(hook, data));
+        }
+
+        #[cfg(target_arch = "arm")]
+        {
+            self.prologue.extend(PatchArm::emit_call_hook(hook, data));
+        }
+
+        #[cfg(target_arch = "riscv64")]
+        {
+            self.prologue
+                .extend(PatchRiscv64::emit_call_hook(hook, data));
+        }
+
+        #[cfg(target_arch = "x86")]
+        {
+            self.prologue.extend(PatchX86::emit_call_hook(hook, data));
+        }
     }
 
     /// Patches the target function so that it branches to a JIT block that uses an absolute jump
     /// to call the target function.
     pub(crate) fn will_execute_guard(self, target: FuncPtrInternal) -> PatchGuard {
+        self.check_patchable();
+
+        #[cfg(target_arch = "aarch64")]
+        {
+            PatchArm64::replace_function_with_other_function(self.func_ptr, target, &self.prologue)
+        }
+
+        #[cfg(target_arch = "x86_64")]
+        {
+            PatchAmd64::replace_function_with_other_function(self.func_ptr, target, &self.prologue)
+        }
+
+        #[cfg(target_arch = "arm")]
+        {
+            PatchArm::replace_function_with_other_function(self.func_ptr, target, &self.prologue)
+        }
+
+        #[cfg(target_arch = "riscv64")]
+        {
+            PatchRiscv64::replace_function_with_other_function(
+                self.func_ptr,
+                target,
+                &self.prologue,
+            )
+        }
+
+        #[cfg(target_arch = "x86")]
+        {
+            PatchX86::replace_function_with_other_function(self.func_ptr, target, &self.prologue)
+        }
+    }
+
+    /// Like `will_execute_guard`, but keeps the original function callable at the address
+    /// passed to `publish_original` before the patch is written, see
+    /// `PatchTrait::replace_function_keeping_original` for `original_code`.
+    pub(crate) fn will_execute_keeping_original_guard(
+        self,
+        target: FuncPtrInternal,
+        original_code: &[u8],
+        publish_original: &mut dyn FnMut(usize),
+    ) -> PatchGuard {
+        self.check_patchable();
+
         #[cfg(target_arch = "aarch64")]
         {
-            PatchArm64::replace_function_with_other_function(self.func_ptr, target)
+            PatchArm64::replace_function_keeping_original(
+                self.func_ptr,
+                target,
+                &self.prologue,
+                original_code,
+                publish_original,
+            )
         }
 
         #[cfg(target_arch = "x86_64")]
         {
-            PatchAmd64::replace_function_with_other_function(self.func_ptr, target)
+            PatchAmd64::replace_function_keeping_original(
+                self.func_ptr,
+                target,
+                &self.prologue,
+                original_code,
+                publish_original,
+            )
         }
-        
+
         #[cfg(target_arch = "arm")]
         {
-            PatchArm::replace_function_with_other_function(self.func_ptr, target)
+            PatchArm::replace_function_keeping_original(
+                self.func_ptr,
+                target,
+                &self.prologue,
+                original_code,
+                publish_original,
+            )
+        }
+
+        #[cfg(target_arch = "riscv64")]
+        {
+            PatchRiscv64::replace_function_keeping_original(
+                self.func_ptr,
+                target,
+                &self.prologue,
+                original_code,
+                publish_original,
+            )
+        }
+
+        #[cfg(target_arch = "x86")]
+        {
+            PatchX86::replace_function_keeping_original(
+                self.func_ptr,
+                target,
+                &self.prologue,
+                original_code,
+                publish_original,
+            )
         }
     }
 
     /// Patches the target function so that it branches to a JIT block that returns the specified boolean.
     pub(crate) fn will_return_boolean_guard(self, value: bool) -> PatchGuard {
+        self.check_patchable();
+
         #[cfg(target_arch = "aarch64")]
         {
-            PatchArm64::replace_function_return_boolean(self.func_ptr, value)
+            PatchArm64::replace_function_return_boolean(self.func_ptr, value, &self.prologue)
         }
 
         #[cfg(target_arch = "x86_64")]
         {
-            PatchAmd64::replace_function_return_boolean(self.func_ptr, value)
+            PatchAmd64::replace_function_return_boolean(self.func_ptr, value, &self.prologue)
         }
-        
+
         #[cfg(target_arch = "arm")]
         {
-            PatchArm::replace_function_return_boolean(self.func_ptr, value)
+            PatchArm::replace_function_return_boolean(self.func_ptr, value, &self.prologue)
+        }
+
+        #[cfg(target_arch = "riscv64")]
+        {
+            PatchRiscv64::replace_function_return_boolean(self.func_ptr, value, &self.prologue)
+        }
+
+        #[cfg(target_arch = "x86")]
+        {
+            PatchX86::replace_function_return_boolean(self.func_ptr, value, &self.prologue)
+        }
+    }
+
+    /// Patches the target function so that it branches to a JIT block that returns the
+    /// 64-bit `value` in the first integer return register.
+    pub(crate) fn will_return_integer_guard(self, value: u64) -> PatchGuard {
+        self.check_patchable();
+
+        #[cfg(target_arch = "aarch64")]
+        {
+            PatchArm64::replace_function_return_integer(self.func_ptr, value, &self.prologue)
+        }
+
+        #[cfg(target_arch = "x86_64")]
+        {
+            PatchAmd64::replace_function_return_integer(self.func_ptr, value, &self.prologue)
+        }
+
+        #[cfg(target_arch = "arm")]
+        {
+            PatchArm::replace_function_return_integer(self.func_ptr, value, &self.prologue)
+        }
+
+        #[cfg(target_arch = "riscv64")]
+        {
+            PatchRiscv64::replace_function_return_integer(self.func_ptr, value, &self.prologue)
+        }
+
+        #[cfg(target_arch = "x86")]
+        {
+            PatchX86::replace_function_return_integer(self.func_ptr, value, &self.prologue)
+        }
+    }
+
+    /// Patches the target function so that it branches to a JIT block that returns the
+    /// double whose bit pattern is `bits` in the first floating point return register.
+    pub(crate) fn will_return_float_guard(self, bits: u64) -> PatchGuard {
+        self.check_patchable();
+
+        #[cfg(target_arch = "aarch64")]
+        {
+            PatchArm64::replace_function_return_float(self.func_ptr, bits, &self.prologue)
+        }
+
+        #[cfg(target_arch = "x86_64")]
+        {
+            PatchAmd64::replace_function_return_float(self.func_ptr, bits, &self.prologue)
+        }
+
+        #[cfg(target_arch = "arm")]
+        {
+            PatchArm::replace_function_return_float(self.func_ptr, bits, &self.prologue)
+        }
+
+        #[cfg(target_arch = "riscv64")]
+        {
+            PatchRiscv64::replace_function_return_float(self.func_ptr, bits, &self.prologue)
+        }
+
+        #[cfg(target_arch = "x86")]
+        {
+            PatchX86::replace_function_return_float(self.func_ptr, bits, &self.prologue)
+        }
+    }
+
+    /// Patches the target function so that it branches to a JIT block that returns the
+    /// two given 64-bit words in the first two integer return registers.
+    pub(crate) fn will_return_pair_guard(self, first: u64, second: u64) -> PatchGuard {
+        self.check_patchable();
+
+        #[cfg(target_arch = "aarch64")]
+        {
+            PatchArm64::replace_function_return_pair(self.func_ptr, first, second, &self.prologue)
+        }
+
+        #[cfg(target_arch = "x86_64")]
+        {
+            PatchAmd64::replace_function_return_pair(self.func_ptr, first, second, &self.prologue)
+        }
+
+        #[cfg(target_arch = "arm")]
+        {
+            PatchArm::replace_function_return_pair(self.func_ptr, first, second, &self.prologue)
+        }
+
+        #[cfg(target_arch = "riscv64")]
+        {
+            PatchRiscv64::replace_function_return_pair(self.func_ptr, first, second, &self.prologue)
+        }
+
+        #[cfg(target_arch = "x86")]
+        {
+            PatchX86::replace_function_return_pair(self.func_ptr, first, second, &self.prologue)
+        }
+    }
+
+    /// Patches the target function so that it branches to a JIT block that returns
+    /// `values[min(n, values.len() - 1)]`, where `n` is the value of `counter`, which it
+    /// increments.
+    ///
+    /// `counter` and `values` must outlive the patch.
+    pub(crate) fn will_return_byte_sequence_guard(
+        self,
+        counter: &AtomicUsize,
+        values: &[u8],
+    ) -> PatchGuard {
+        self.check_patchable();
+
+        let counter = counter.as_ptr() as usize;
+        let last = values.len() - 1;
+        let values = values.as_ptr() as usize;
+
+        #[cfg(target_arch = "aarch64")]
+        {
+            PatchArm64::replace_function_return_byte_sequence(
+                self.func_ptr,
+                counter,
+                values,
+                last,
+                &self.prologue,
+            )
+        }
+
+        #[cfg(target_arch = "x86_64")]
+        {
+            PatchAmd64::replace_function_return_byte_sequence(
+                self.func_ptr,
+                counter,
+                values,
+                last,
+                &self.prologue,
+            )
+        }
+
+        #[cfg(target_arch = "arm")]
+        {
+            PatchArm::replace_function_return_byte_sequence(
+                self.func_ptr,
+                counter,
+                values,
+                last,
+                &self.prologue,
+            )
+        }
+
+        #[cfg(target_arch = "riscv64")]
+        {
+            PatchRiscv64::replace_function_return_byte_sequence(
+                self.func_ptr,
+                counter,
+                values,
+                last,
+                &self.prologue,
+            )
+        }
+
+        #[cfg(target_arch = "x86")]
+        {
+            PatchX86::replace_function_return_byte_sequence(
+                self.func_ptr,
+                counter,
+                values,
+                last,
+                &self.prologue,
+            )
+        }
+    }
+
+    /// Patches the target function so that it branches to a JIT block that copies `value` to
+    /// the return slot passed by its caller.
+    ///
+    /// `value` must outlive the patch.
+    pub(crate) fn will_return_struct_guard(self, value: &[u8]) -> PatchGuard {
+        self.check_patchable();
+
+        let size = value.len();
+        let value = value.as_ptr() as usize;
+
+        #[cfg(target_arch = "aarch64")]
+        {
+            PatchArm64::replace_function_return_struct(self.func_ptr, value, size, &self.prologue)
+        }
+
+        #[cfg(target_arch = "x86_64")]
+        {
+            PatchAmd64::replace_function_return_struct(self.func_ptr, value, size, &self.prologue)
+        }
+
+        #[cfg(target_arch = "arm")]
+        {
+            PatchArm::replace_function_return_struct(self.func_ptr, value, size, &self.prologue)
+        }
+
+        #[cfg(target_arch = "riscv64")]
+        {
+            PatchRiscv64::replace_function_return_struct(self.func_ptr, value, size, &self.prologue)
+        }
+
+        #[cfg(target_arch = "x86")]
+        {
+            PatchX86::replace_function_return_struct(self.func_ptr, value, size, &self.prologue)
+        }
+    }
+
+    /// Patches the target function so that it branches to a JIT block that atomically
+    /// increments `counter` and returns.
+    pub(crate) fn will_increment_guard(self, counter: &'static AtomicUsize) -> PatchGuard {
+        self.check_patchable();
+
+        let counter = counter.as_ptr() as usize;
+
+        #[cfg(target_arch = "aarch64")]
+        {
+            PatchArm64::replace_function_increment_counter(self.func_ptr, counter, &self.prologue)
+        }
+
+        #[cfg(target_arch = "x86_64")]
+        {
+            PatchAmd64::replace_function_increment_counter(self.func_ptr, counter, &self.prologue)
+        }
+
+        #[cfg(target_arch = "arm")]
+        {
+            PatchArm::replace_function_increment_counter(self.func_ptr, counter, &self.prologue)
+        }
+
+        #[cfg(target_arch = "riscv64")]
+        {
+            PatchRiscv64::replace_function_increment_counter(self.func_ptr, counter, &self.prologue)
+        }
+
+        #[cfg(target_arch = "x86")]
+        {
+            PatchX86::replace_function_increment_counter(self.func_ptr, counter, &self.prologue)
+        }
+    }
+
+    /// Patches the target function so that it branches to a JIT block that calls
+    /// `hook(data, registers)` and returns.
+    ///
+    /// `data` must stay valid for as long as the patch is installed.
+    pub(crate) fn will_call_hook_guard(self, hook: CallHook, data: *const ()) -> PatchGuard {
+        self.check_patchable();
+
+        let hook = hook as usize;
+        let data = data as usize;
+
+        #[cfg(target_arch = "aarch64")]
+        {
+            PatchArm64::replace_function_call_hook(self.func_ptr, hook, data, &self.prologue)
+        }
+
+        #[cfg(target_arch = "x86_64")]
+        {
+            PatchAmd64::replace_function_call_hook(self.func_ptr, hook, data, &self.prologue)
+        }
+
+        #[cfg(target_arch = "arm")]
+        {
+            PatchArm::replace_function_call_hook(self.func_ptr, hook, data, &self.prologue)
+        }
+
+        #[cfg(target_arch = "riscv64")]
+        {
+            PatchRiscv64::replace_function_call_hook(self.func_ptr, hook, data, &self.prologue)
+        }
+
+        #[cfg(target_arch = "x86")]
+        {
+            PatchX86::replace_function_call_hook(self.func_ptr, hook, data, &self.prologue)
         }
     }
 }
@@ -0,0 +1,149 @@
+//! Hands out the JIT stubs of nearby patches from shared pages of executable memory.
+//!
+//! Stubs are a few dozen bytes, so mapping a page for each one makes a test suite with many
+//! fakes spend its time searching for and mapping pages. This only does the bookkeeping of
+//! one page and never maps anything, so it is compiled and tested on every host.
+#![cfg_attr(
+    not(any(
+        target_arch = "aarch64",
+        target_arch = "x86_64",
+        target_arch = "riscv64",
+        target_arch = "x86"
+    )),
+    allow(dead_code)
+)]
+
+use std::ops::Range;
+
+/// The alignment of every stub, enough for the instructions and literals of any of them.
+const STUB_ALIGNMENT: usize = 16;
+
+/// One page of JIT memory that stubs are allocated from, first fit.
+pub(crate) struct JitArena {
+    page: Range<usize>,
+    /// The free parts of the page, sorted and never adjacent to each other.
+    free: Vec<Range<usize>>,
+    /// Whether the page stays mapped once empty, see `reserve_jit_memory`.
+    retained: bool,
+}
+
+impl JitArena {
+    /// Manages the `size` bytes at `base`, all of them free.
+    pub(crate) fn new(base: usize, size: usize) -> Self {
+        let page = base..base + size;
+
+        Self {
+            free: vec![page.clone()],
+            page,
+            retained: false,
+        }
+    }
+
+    /// The address range of the page.
+    pub(crate) fn page(&self) -> Range<usize> {
+        self.page.clone()
+    }
+
+    /// Sets whether the page stays mapped once empty.
+    pub(crate) fn set_retained(&mut self, retained: bool) {
+        self.retained = retained;
+    }
+
+    /// Returns whether the page stays mapped once empty.
+    pub(crate) fn is_retained(&self) -> bool {
+        self.retained
+    }
+
+    /// Returns whether `allocate` would find `size` free bytes.
+    // 32-bit x86 patches reach any memory, so they do not probe for it.
+    #[cfg_attr(target_arch = "x86", allow(dead_code))]
+    pub(crate) fn can_allocate(&self, size: usize) -> bool {
+        let size = size.max(1).next_multiple_of(STUB_ALIGNMENT);
+        self.free.iter().any(|free| free.len() >= size)
+    }
+
+    /// Returns the address of `size` free bytes and marks them used, or `None` if no free
+    /// part of the page is large enough.
+    pub(crate) fn allocate(&mut self, size: usize) -> Option<usize> {
+        let size = size.max(1).next_multiple_of(STUB_ALIGNMENT);
+        let index = self.free.iter().position(|free| free.len() >= size)?;
+
+        let address = self.free[index].start;
+        self.free[index].start += size;
+        if self.free[index].is_empty() {
+            self.free.remove(index);
+        }
+
+        Some(address)
+    }
+
+    /// Marks the `size` bytes at `address`, returned by `allocate`, free again.
+    pub(crate) fn free(&mut self, address: usize, size: usize) {
+        let size = size.max(1).next_multiple_of(STUB_ALIGNMENT);
+        let index = self.free.partition_point(|free| free.start < address);
+        self.free.insert(index, address..address + size);
+
+        // Merge with the following and the preceding free parts.
+        if index + 1 < self.free.len() && self.free[index].end == self.free[index + 1].start {
+            self.free[index].end = self.free.remove(index + 1).end;
+        }
+        if index > 0 && self.free[index - 1].end == self.free[index].start {
+            self.free[index - 1].end = self.free.remove(index).end;
+        }
+    }
+
+    /// Returns whether no stub is allocated from the page anymore.
+    pub(crate) fn is_empty(&self) -> bool {
+        self.free.len() == 1 && self.free[0] == self.page
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_allocate_should_return_aligned_disjoint_stubs() {
+        let mut arena = JitArena::new(0x1000, 0x1000);
+
+        assert_eq!(arena.allocate(13), Some(0x1000));
+        assert_eq!(arena.allocate(16), Some(0x1010));
+        assert_eq!(arena.allocate(40), Some(0x1020));
+        assert_eq!(arena.allocate(1), Some(0x1050));
+        assert!(!arena.is_empty());
+    }
+
+    #[test]
+    fn test_allocate_when_page_is_full_should_return_none() {
+        let mut arena = JitArena::new(0x1000, 0x40);
+
+        assert_eq!(arena.allocate(0x30), Some(0x1000));
+        assert_eq!(arena.allocate(0x20), None);
+        assert_eq!(arena.allocate(0x10), Some(0x1030));
+        assert_eq!(arena.allocate(1), None);
+        assert!(!arena.can_allocate(1));
+    }
+
+    #[test]
+    fn test_free_should_reuse_and_merge_free_parts() {
+        let mut arena = JitArena::new(0x1000, 0x40);
+        let first = arena.allocate(0x10).unwrap();
+        let second = arena.allocate(0x10).unwrap();
+        let third = arena.allocate(0x20).unwrap();
+
+        arena.free(first, 0x10);
+        assert!(arena.can_allocate(0x10));
+        assert!(!arena.can_allocate(0x20));
+        assert_eq!(arena.allocate(0x20), None);
+        assert_eq!(arena.allocate(0x10), Some(first));
+
+        arena.free(second, 0x10);
+        arena.free(first, 0x10);
+        assert_eq!(arena.allocate(0x20), Some(first));
+
+        arena.free(first, 0x20);
+        arena.free(third, 0x20);
+        assert!(arena.is_empty());
+        assert_eq!(arena.allocate(0x40), Some(0x1000));
+    }
+}
@@ -0,0 +1,205 @@
+//! Chooses where to look for JIT memory close enough to a patched function.
+//!
+//! This only computes addresses and never maps anything, so it is compiled and tested on
+//! every host.
+#![cfg_attr(
+    not(any(
+        target_arch = "aarch64",
+        target_arch = "x86_64",
+        target_arch = "riscv64"
+    )),
+    allow(dead_code)
+)]
+
+use std::ops::Range;
+
+/// The addresses user space mappings can be placed at.
+///
+/// The first 64KB are never mappable: Linux refuses them through `vm.mmap_min_addr`, and
+/// Windows and macOS reserve them. The end is the top of the default 47-bit (x86_64) or
+/// 48-bit (AArch64) user address space.
+#[cfg(not(target_arch = "aarch64"))]
+pub(crate) const USER_SPACE: Range<u64> = 0x1_0000..0x7FFF_FFFF_F000;
+
+#[cfg(target_arch = "aarch64")]
+pub(crate) const USER_SPACE: Range<u64> = 0x1_0000..0xFFFF_FFFF_F000;
+
+/// Returns the page aligned addresses to try for `code_size` bytes of JIT memory within
+/// `max_range` of `original`, nearest to `origin` first, alternating above and below it.
+///
+/// The window is clamped to `user_space`, so sources close to either end of the address
+/// space neither overflow nor spend the search on addresses that can never be mapped. A
+/// source beyond the end of `user_space`, as with a larger address space, extends it. An
+/// `origin` outside the window starts the search at its nearest end, so `0` scans upward
+/// from the lowest address in range.
+pub(crate) fn jit_candidates(
+    original: u64,
+    origin: u64,
+    max_range: u64,
+    page_size: u64,
+    code_size: u64,
+    user_space: Range<u64>,
+) -> impl Iterator<Item = u64> {
+    let page_mask = !(page_size - 1);
+    let user_space_end = user_space.end.max(original.saturating_add(page_size));
+
+    let low = original
+        .saturating_sub(max_range)
+        .max(user_space.start)
+        .saturating_add(page_size - 1)
+        & page_mask;
+    let high = original
+        .saturating_add(max_range)
+        .min(user_space_end.saturating_sub(code_size))
+        & page_mask;
+
+    let start = (low <= high).then(|| (origin & page_mask).clamp(low, high));
+
+    let mut above = std::iter::successors(start, move |address| {
+        address.checked_add(page_size).filter(|next| *next <= high)
+    });
+    let mut below = std::iter::successors(
+        start.and_then(|start| start.checked_sub(page_size)),
+        move |address| address.checked_sub(page_size),
+    )
+    .take_while(move |address| *address >= low);
+
+    let mut take_above = false;
+    std::iter::from_fn(move || {
+        take_above = !take_above;
+        if take_above {
+            above.next().or_else(|| below.next())
+        } else {
+            below.next().or_else(|| above.next())
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const PAGE: u64 = 0x1000;
+    const RANGE: u64 = 0x800_0000;
+    const SPACE: Range<u64> = 0x1_0000..0x7FFF_FFFF_F000;
+
+    #[test]
+    fn test_jit_candidates_alternate_around_source() {
+        let candidates: Vec<u64> =
+            jit_candidates(0x5555_0000_0123, 0x5555_0000_0123, RANGE, PAGE, 64, SPACE)
+                .take(5)
+                .collect();
+
+        assert_eq!(
+            candidates,
+            vec![
+                0x5555_0000_0000,
+                0x5554_FFFF_F000,
+                0x5555_0000_1000,
+                0x5554_FFFF_E000,
+                0x5555_0000_2000,
+            ]
+        );
+    }
+
+    #[test]
+    fn test_jit_candidates_visit_every_page_once_never_moving_further_away_early() {
+        let original = 0x5555_0000_0123;
+        let candidates: Vec<u64> =
+            jit_candidates(original, original, RANGE, PAGE, 64, SPACE).collect();
+
+        let distances: Vec<u64> = candidates
+            .iter()
+            .map(|address| address.abs_diff(original & !(PAGE - 1)) / PAGE)
+            .collect();
+        assert!(distances.windows(2).all(|pair| pair[0] <= pair[1]));
+
+        let mut sorted = candidates.clone();
+        sorted.sort_unstable();
+        sorted.dedup();
+        assert_eq!(sorted.len(), candidates.len());
+        assert_eq!(candidates.len() as u64, 2 * RANGE / PAGE);
+        assert!(candidates
+            .iter()
+            .all(|address| address.abs_diff(original) <= RANGE));
+    }
+
+    #[test]
+    fn test_jit_candidates_near_zero_stay_above_mappable_start() {
+        let candidates: Vec<u64> =
+            jit_candidates(0x2_0040, 0x2_0040, RANGE, PAGE, 64, SPACE).collect();
+
+        assert_eq!(&candidates[..4], &[0x2_0000, 0x1_F000, 0x2_1000, 0x1_E000]);
+        assert_eq!(candidates.iter().min(), Some(&0x1_0000));
+        assert_eq!(candidates.iter().max(), Some(&(0x2_0040 + RANGE - 0x40)));
+        assert_eq!(
+            candidates.len() as u64,
+            (0x2_0000 + RANGE - 0x1_0000) / PAGE + 1
+        );
+    }
+
+    #[test]
+    fn test_jit_candidates_near_top_stay_below_user_space_end() {
+        let original = SPACE.end - 0x2_0000;
+        let candidates: Vec<u64> =
+            jit_candidates(original, original, RANGE, PAGE, 0x2000, SPACE).collect();
+
+        assert_eq!(candidates[0], original);
+        assert_eq!(candidates.iter().max(), Some(&(SPACE.end - 0x2000)));
+        assert_eq!(candidates.iter().min(), Some(&(original - RANGE)));
+        assert!(candidates.iter().all(|address| address % PAGE == 0));
+    }
+
+    #[test]
+    fn test_jit_candidates_at_end_of_address_space_do_not_overflow() {
+        let candidates: Vec<u64> =
+            jit_candidates(u64::MAX - 0xFFF, u64::MAX - 0xFFF, RANGE, PAGE, 64, SPACE).collect();
+
+        assert_eq!(candidates[0], u64::MAX - 0xFFF);
+        assert_eq!(candidates[1], u64::MAX - 0x1FFF);
+        assert_eq!(candidates.iter().min(), Some(&(u64::MAX - 0xFFF - RANGE)));
+    }
+
+    #[test]
+    fn test_jit_candidates_at_zero_are_empty_when_nothing_is_mappable() {
+        assert_eq!(
+            jit_candidates(0x100, 0x100, 0x1000, PAGE, 64, SPACE).count(),
+            0
+        );
+    }
+
+    #[test]
+    fn test_jit_candidates_from_zero_origin_scan_upward_from_lowest_address() {
+        let original = 0x5555_0000_0123;
+        let candidates: Vec<u64> = jit_candidates(original, 0, RANGE, PAGE, 64, SPACE).collect();
+
+        assert_eq!(candidates[0], (original - RANGE + PAGE - 1) & !(PAGE - 1));
+        assert!(candidates.windows(2).all(|pair| pair[1] == pair[0] + PAGE));
+        assert_eq!(
+            candidates.iter().max(),
+            Some(&((original + RANGE) & !(PAGE - 1)))
+        );
+    }
+
+    #[test]
+    fn test_jit_candidates_from_hint_alternate_around_hint_within_range() {
+        let original = 0x5555_0000_0123;
+        let hint = 0x5555_0400_0000;
+        let candidates: Vec<u64> = jit_candidates(original, hint, RANGE, PAGE, 64, SPACE).collect();
+
+        assert_eq!(&candidates[..3], &[hint, hint - PAGE, hint + PAGE]);
+        assert!(candidates
+            .iter()
+            .all(|address| address.abs_diff(original) <= RANGE));
+    }
+
+    #[test]
+    fn test_jit_candidates_from_hint_out_of_range_start_at_nearest_end() {
+        let original = 0x5555_0000_0123;
+        let candidates: Vec<u64> =
+            jit_candidates(original, u64::MAX, RANGE, PAGE, 64, SPACE).collect();
+
+        assert_eq!(candidates[0], (original + RANGE) & !(PAGE - 1));
+        assert_eq!(candidates[1], candidates[0] - PAGE);
+    }
+}
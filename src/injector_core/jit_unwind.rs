@@ -0,0 +1,333 @@
+//! Unwind information for the x86_64 JIT blocks calling hooks.
+//!
+//! A hook runs while the return address on top of the stack points into the JIT block, which
+//! the unwinder knows nothing about, so backtraces stop there and a panic of the hook cannot
+//! reach the caller of the faked function. This describes how `emit_call_hook` moves the
+//! stack pointer: as a `.eh_frame` CIE and FDE registered with `__register_frame` on Linux,
+//! and as an `UNWIND_INFO` registered with `RtlAddFunctionTable` on Windows. The encoders only
+//! build bytes, so they are compiled and tested on every host.
+// Each OS only uses the encoder of its own format.
+#![allow(dead_code)]
+
+use crate::injector_core::amd64_codegenerator::{
+    call_hook_stack_depths, CALL_HOOK_SAVED_REGISTERS, CALL_HOOK_SIZE,
+};
+
+/// The DWARF numbers of the stack pointer and of the return address.
+const DWARF_RSP: u8 = 7;
+const DWARF_RIP: u8 = 16;
+
+const DW_CFA_ADVANCE_LOC4: u8 = 0x04;
+const DW_CFA_DEF_CFA: u8 = 0x0c;
+const DW_CFA_DEF_CFA_OFFSET: u8 = 0x0e;
+const DW_CFA_OFFSET: u8 = 0x80;
+
+const UWOP_PUSH_NONVOL: u8 = 0;
+const UWOP_ALLOC_LARGE: u8 = 1;
+
+/// Returns the offsets of the hooks a JIT block starting with `prologue` calls, which holds
+/// nothing but the code of `emit_call_hook`.
+pub(crate) fn call_hook_offsets(prologue: &[u8]) -> Vec<usize> {
+    (0..prologue.len()).step_by(CALL_HOOK_SIZE).collect()
+}
+
+/// Returns `.eh_frame` data, ended by a zero length, describing the `code_size` bytes at
+/// `address` that call hooks at the offsets `hooks`.
+///
+/// Outside of the hooks, the stack pointer is the one of the function entry, right below the
+/// return address.
+pub(crate) fn encode_eh_frame(address: usize, code_size: usize, hooks: &[usize]) -> Vec<u8> {
+    let mut frame = Vec::new();
+
+    // CIE: version 1, augmentation "zR" with absolute pointers, code alignment 1, data
+    // alignment -8. On entry the CFA is rsp + 8 and the return address is at CFA - 8.
+    let mut cie = vec![0, 0, 0, 0, 1, b'z', b'R', 0, 1, 0x78, DWARF_RIP, 1, 0x00];
+    cie.extend([DW_CFA_DEF_CFA, DWARF_RSP, 8, DW_CFA_OFFSET | DWARF_RIP, 1]);
+    push_entry(&mut frame, &cie);
+
+    // FDE: the offset back to the CIE, the code range and no augmentation data.
+    let mut fde = Vec::new();
+    fde.extend((frame.len() as u32 + 4).to_le_bytes());
+    fde.extend((address as u64).to_le_bytes());
+    fde.extend((code_size as u64).to_le_bytes());
+    fde.push(0);
+
+    let mut location = 0;
+    for &hook in hooks {
+        for (offset, depth) in call_hook_stack_depths() {
+            fde.push(DW_CFA_ADVANCE_LOC4);
+            fde.extend(((hook + offset - location) as u32).to_le_bytes());
+            location = hook + offset;
+
+            fde.push(DW_CFA_DEF_CFA_OFFSET);
+            push_uleb128(&mut fde, 8 + depth);
+        }
+    }
+    push_entry(&mut frame, &fde);
+
+    frame.extend(0u32.to_le_bytes());
+    frame
+}
+
+/// Appends `content` to `frame` preceded by its length, padded with `DW_CFA_nop` to keep the
+/// entries aligned to eight bytes.
+fn push_entry(frame: &mut Vec<u8>, content: &[u8]) {
+    let padded = (content.len() + 4).next_multiple_of(8) - 4;
+
+    frame.extend((padded as u32).to_le_bytes());
+    frame.extend(content);
+    frame.resize(frame.len() + padded - content.len(), 0);
+}
+
+fn push_uleb128(bytes: &mut Vec<u8>, mut value: u32) {
+    loop {
+        let byte = (value & 0x7f) as u8;
+        value >>= 7;
+        if value == 0 {
+            bytes.push(byte);
+            return;
+        }
+        bytes.push(byte | 0x80);
+    }
+}
+
+/// Returns the Windows `UNWIND_INFO` of the code of `emit_call_hook`, whose prologue saves
+/// the registers and reserves its frame.
+pub(crate) fn encode_unwind_info() -> Vec<u8> {
+    let depths = call_hook_stack_depths();
+    let saved = CALL_HOOK_SAVED_REGISTERS.len();
+    let prologue_size = depths[saved].0;
+
+    // The codes are listed from the end of the prologue to its start.
+    let frame_size = depths[saved].1 - depths[saved - 1].1;
+    let mut codes = vec![
+        unwind_code(prologue_size, UWOP_ALLOC_LARGE, 0),
+        (frame_size / 8) as u16,
+    ];
+    for (index, &register) in CALL_HOOK_SAVED_REGISTERS.iter().enumerate().rev() {
+        codes.push(unwind_code(depths[index].0, UWOP_PUSH_NONVOL, register));
+    }
+
+    // Version 1 without flags or frame register, then the codes padded to an even count.
+    let mut info = vec![1, prologue_size as u8, codes.len() as u8, 0];
+    codes.resize(codes.len().next_multiple_of(2), 0);
+    for code in codes {
+        info.extend(code.to_le_bytes());
+    }
+
+    info
+}
+
+fn unwind_code(offset: usize, operation: u8, info: u8) -> u16 {
+    u16::from_le_bytes([offset as u8, operation | (info << 4)])
+}
+
+#[cfg(all(target_arch = "x86_64", target_os = "linux", target_env = "gnu"))]
+mod registration {
+    use std::sync::{Mutex, PoisonError};
+
+    extern "C" {
+        fn __register_frame(begin: *const u8);
+        fn __deregister_frame(begin: *const u8);
+    }
+
+    /// The `.eh_frame` data registered for each JIT block, which the unwinder reads until
+    /// it is deregistered. `u64`s keep the entries aligned.
+    static REGISTERED: Mutex<Vec<(usize, Box<[u64]>)>> = Mutex::new(Vec::new());
+
+    /// Returns nothing to append to the JIT block, as the unwinder takes the `.eh_frame` data
+    /// from anywhere.
+    pub(crate) fn unwind_data(_code_size: usize, _hooks: &[usize]) -> Vec<u8> {
+        Vec::new()
+    }
+
+    pub(crate) unsafe fn register(address: usize, code_size: usize, hooks: &[usize]) {
+        if hooks.is_empty() {
+            return;
+        }
+
+        let frame = super::encode_eh_frame(address, code_size, hooks);
+        let frame: Box<[u64]> = frame
+            .chunks(8)
+            .map(|chunk| {
+                let mut word = [0u8; 8];
+                word[..chunk.len()].copy_from_slice(chunk);
+                u64::from_le_bytes(word)
+            })
+            .collect();
+
+        __register_frame(frame.as_ptr() as *const u8);
+        REGISTERED
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .push((address, frame));
+    }
+
+    pub(crate) unsafe fn unregister(address: usize) {
+        let mut registered = REGISTERED.lock().unwrap_or_else(PoisonError::into_inner);
+
+        if let Some(index) = registered.iter().position(|(start, _)| *start == address) {
+            let (_, frame) = registered.swap_remove(index);
+            __deregister_frame(frame.as_ptr() as *const u8);
+        }
+    }
+}
+
+#[cfg(all(target_arch = "x86_64", target_os = "windows"))]
+mod registration {
+    use crate::injector_core::amd64_codegenerator::CALL_HOOK_SIZE;
+    use crate::injector_core::winapi::{
+        RtlAddFunctionTable, RtlDeleteFunctionTable, RuntimeFunction,
+    };
+    use std::sync::{Mutex, PoisonError};
+
+    /// The function tables registered for each JIT block, which the unwinder reads until
+    /// they are deleted.
+    static REGISTERED: Mutex<Vec<(usize, Box<[RuntimeFunction]>)>> = Mutex::new(Vec::new());
+
+    /// Returns the `UNWIND_INFO` shared by the hooks of a JIT block of `code_size` bytes,
+    /// aligned to four bytes, to append to it: the table refers to it by its offset from the
+    /// block, which memory allocated elsewhere may not be within 4GB of.
+    pub(crate) fn unwind_data(code_size: usize, hooks: &[usize]) -> Vec<u8> {
+        if hooks.is_empty() {
+            return Vec::new();
+        }
+
+        let mut data = vec![0xCC; code_size.next_multiple_of(4) - code_size];
+        data.extend(super::encode_unwind_info());
+        data
+    }
+
+    pub(crate) unsafe fn register(address: usize, code_size: usize, hooks: &[usize]) {
+        if hooks.is_empty() {
+            return;
+        }
+
+        let unwind_info = code_size.next_multiple_of(4) as u32;
+        let table: Box<[RuntimeFunction]> = hooks
+            .iter()
+            .map(|&hook| RuntimeFunction {
+                begin_address: hook as u32,
+                end_address: (hook + CALL_HOOK_SIZE) as u32,
+                unwind_data: unwind_info,
+            })
+            .collect();
+
+        RtlAddFunctionTable(table.as_ptr(), table.len() as u32, address as u64);
+        REGISTERED
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .push((address, table));
+    }
+
+    pub(crate) unsafe fn unregister(address: usize) {
+        let mut registered = REGISTERED.lock().unwrap_or_else(PoisonError::into_inner);
+
+        if let Some(index) = registered.iter().position(|(start, _)| *start == address) {
+            let (_, table) = registered.swap_remove(index);
+            RtlDeleteFunctionTable(table.as_ptr());
+        }
+    }
+}
+
+#[cfg(not(all(
+    target_arch = "x86_64",
+    any(all(target_os = "linux", target_env = "gnu"), target_os = "windows")
+)))]
+mod registration {
+    pub(crate) fn unwind_data(_code_size: usize, _hooks: &[usize]) -> Vec<u8> {
+        Vec::new()
+    }
+
+    pub(crate) unsafe fn register(_address: usize, _code_size: usize, _hooks: &[usize]) {}
+
+    pub(crate) unsafe fn unregister(_address: usize) {}
+}
+
+/// Returns the bytes to append to a JIT block of `code_size` bytes calling hooks at the
+/// offsets `hooks`, which `register` expects right after its code.
+#[cfg_attr(not(target_arch = "x86_64"), allow(unused_imports))]
+pub(crate) use registration::unwind_data;
+
+/// Registers the unwind information of the JIT block of `code_size` bytes at `address`,
+/// calling hooks at the offsets `hooks`, once its code and `unwind_data` are written.
+///
+/// # Safety
+///
+/// The block must stay mapped until `unregister` is called with `address`.
+#[cfg_attr(not(target_arch = "x86_64"), allow(unused_imports))]
+pub(crate) use registration::register;
+
+/// Removes the unwind information registered for the JIT block at `address`, if any.
+///
+/// # Safety
+///
+/// Must be called before the block is unmapped or reused.
+pub(crate) use registration::unregister;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_call_hook_offsets_should_list_each_hook_of_the_prologue() {
+        assert!(call_hook_offsets(&[]).is_empty());
+        assert_eq!(
+            call_hook_offsets(&[0; 2 * CALL_HOOK_SIZE]),
+            [0, CALL_HOOK_SIZE]
+        );
+    }
+
+    #[test]
+    fn test_encode_eh_frame_should_describe_each_hook() {
+        let frame = encode_eh_frame(0x1000, 300, &[16]);
+
+        // The CIE, the FDE and the terminator are each aligned to eight bytes.
+        let cie_length = u32::from_le_bytes(frame[..4].try_into().unwrap()) as usize;
+        assert_eq!((cie_length + 4) % 8, 0);
+        assert_eq!(frame[4..8], [0, 0, 0, 0]);
+        assert_eq!(frame[8], 1);
+        assert_eq!(frame[9..12], *b"zR\0");
+
+        let fde = &frame[cie_length + 4..];
+        let fde_length = u32::from_le_bytes(fde[..4].try_into().unwrap()) as usize;
+        assert_eq!((fde_length + 4) % 8, 0);
+        assert_eq!(
+            u32::from_le_bytes(fde[4..8].try_into().unwrap()) as usize,
+            cie_length + 8
+        );
+        assert_eq!(u64::from_le_bytes(fde[8..16].try_into().unwrap()), 0x1000);
+        assert_eq!(u64::from_le_bytes(fde[16..24].try_into().unwrap()), 300);
+        assert_eq!(fde[24], 0);
+
+        // After `push rdi` at the start of the hook, the CFA is rsp + 16.
+        assert_eq!(fde[25], DW_CFA_ADVANCE_LOC4);
+        assert_eq!(u32::from_le_bytes(fde[26..30].try_into().unwrap()), 17);
+        assert_eq!(fde[30..32], [DW_CFA_DEF_CFA_OFFSET, 16]);
+
+        assert_eq!(frame[frame.len() - 4..], [0, 0, 0, 0]);
+        assert_eq!(frame.len(), cie_length + fde_length + 12);
+    }
+
+    #[test]
+    fn test_encode_eh_frame_should_encode_large_offsets_as_uleb128() {
+        let mut bytes = Vec::new();
+        push_uleb128(&mut bytes, 240);
+
+        assert_eq!(bytes, [0xF0, 0x01]);
+    }
+
+    #[test]
+    fn test_encode_unwind_info_should_describe_the_hook_prologue() {
+        let info = encode_unwind_info();
+
+        // Version 1, an 18-byte prologue and ten codes.
+        assert_eq!(info[..4], [1, 18, 10, 0]);
+        // sub rsp, 168 ends at 18 and reserves 21 slots of eight bytes.
+        assert_eq!(info[4..8], [18, UWOP_ALLOC_LARGE, 21, 0]);
+        // push r10 ends at 11 and push rdi at 1.
+        assert_eq!(info[8..10], [11, UWOP_PUSH_NONVOL | (10 << 4)]);
+        assert_eq!(info[22..24], [1, UWOP_PUSH_NONVOL | (7 << 4)]);
+        assert_eq!(info.len(), 24);
+    }
+}
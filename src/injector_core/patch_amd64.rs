@@ -1,87 +1,304 @@
 #![cfg(target_arch = "x86_64")]
 
+use crate::injector_core::amd64_codegenerator::*;
+use crate::injector_core::amd64_relocator::{
+    function_end_before, instruction_boundary, relocate, JUMP_BACK_SIZE,
+};
 use crate::injector_core::common::*;
+use crate::injector_core::jit_unwind;
 use crate::injector_core::patch_trait::*;
+use crate::interface::debug::BranchKind;
+use crate::interface::error::InjectError;
+use std::cell::Cell;
 
 /// Patch implementation for AMD64 (x86_64) architecture.
 pub(crate) struct PatchAmd64;
 
-/// Opcode constants for AMD64 jump and move instructions.
-const JMP_REL_OPCODE: u8 = 0xE9;
-const MOV_RAX_OPCODE: [u8; 2] = [0x48, 0xB8];
-const JMP_RAX_OPCODE: [u8; 2] = [0xFF, 0xE0];
-
 impl PatchTrait for PatchAmd64 {
     fn replace_function_with_other_function(
         src: FuncPtrInternal,
         target: FuncPtrInternal,
+        prologue: &[u8],
+    ) -> PatchGuard {
+        const JIT_SIZE: usize = 13;
+        let target_addr = target.as_ptr() as usize;
+
+        install_jit_code(src, prologue, JIT_SIZE, |body_addr| {
+            emit_branch(body_addr, target_addr)
+        })
+    }
+
+    fn replace_function_keeping_original(
+        src: FuncPtrInternal,
+        target: FuncPtrInternal,
+        prologue: &[u8],
+        original_code: &[u8],
+        publish_original: &mut dyn FnMut(usize),
     ) -> PatchGuard {
-        const JIT_SIZE: usize = 12;
-        let jit_memory = allocate_jit_memory(&src, JIT_SIZE);
+        const JIT_SIZE: usize = 13;
+        const MAX_PATCH_SIZE: usize = 12;
+        const MAX_INSTRUCTION_SIZE: usize = 15;
+        // Widening short branches can at most triple the copied bytes, plus the jump back.
+        const MAX_ORIGINAL_SIZE: usize =
+            3 * (MAX_PATCH_SIZE + MAX_INSTRUCTION_SIZE) + JUMP_BACK_SIZE;
 
+        let func_addr = src.as_ptr() as usize;
         let target_addr = target.as_ptr() as usize;
+        let mut code = try_read_bytes(
+            src.as_ptr() as *const u8,
+            MAX_PATCH_SIZE + MAX_INSTRUCTION_SIZE,
+        )
+        .unwrap_or_else(|error| panic!("{error}"));
+        code[..original_code.len()].copy_from_slice(original_code);
+
+        let hooks = jit_unwind::call_hook_offsets(prologue);
+        let code_size = prologue.len() + JIT_SIZE + MAX_ORIGINAL_SIZE;
+        let unwind_data = jit_unwind::unwind_data(code_size, &hooks);
+        let jit_size = code_size + unwind_data.len();
+        let jit_memory = allocate_jit_memory(&src, jit_size);
         let jit_addr = jit_memory as usize;
 
-        let jit_code = generate_branch_to_target_function(jit_addr, target_addr);
+        let mut jit_code = prologue.to_vec();
+        jit_code.extend(emit_branch(jit_addr + jit_code.len(), target_addr));
+
+        // Only the bytes the patch overwrites need to move.
+        let patch_size = emit_branch(func_addr, jit_addr).len();
+        let original_addr = jit_addr + jit_code.len();
+        jit_code.extend(
+            relocate(&code, func_addr, original_addr, patch_size)
+                .unwrap_or_else(|error| panic!("{error}")),
+        );
+        jit_code.resize(code_size, NOP_OPCODE);
+        jit_code.extend(unwind_data);
 
         unsafe {
-            inject_asm_code(&jit_code, jit_memory);
+            write_jit_code(&jit_code, jit_memory);
+            jit_unwind::register(jit_addr, code_size, &hooks);
         }
 
-        patch_and_guard(src, jit_memory, JIT_SIZE)
+        publish_original(original_addr);
+
+        patch_and_guard(src, jit_memory, jit_size)
     }
 
-    fn replace_function_return_boolean(src: FuncPtrInternal, value: bool) -> PatchGuard {
+    fn replace_function_return_boolean(
+        src: FuncPtrInternal,
+        value: bool,
+        prologue: &[u8],
+    ) -> PatchGuard {
         const JIT_SIZE: usize = 8;
-        let jit_memory = allocate_jit_memory(&src, JIT_SIZE);
 
-        generate_will_return_boolean_jit_code(jit_memory, value);
+        install_jit_code(src, prologue, JIT_SIZE, |_| emit_return_boolean(value))
+    }
+
+    fn replace_function_return_integer(
+        src: FuncPtrInternal,
+        value: u64,
+        prologue: &[u8],
+    ) -> PatchGuard {
+        const JIT_SIZE: usize = 11;
+
+        install_jit_code(src, prologue, JIT_SIZE, |_| emit_return_integer(value))
+    }
+
+    fn replace_function_return_float(
+        src: FuncPtrInternal,
+        bits: u64,
+        prologue: &[u8],
+    ) -> PatchGuard {
+        const JIT_SIZE: usize = 16;
+
+        install_jit_code(src, prologue, JIT_SIZE, |_| emit_return_float(bits))
+    }
+
+    fn replace_function_return_pair(
+        src: FuncPtrInternal,
+        first: u64,
+        second: u64,
+        prologue: &[u8],
+    ) -> PatchGuard {
+        const JIT_SIZE: usize = 21;
+
+        install_jit_code(src, prologue, JIT_SIZE, |_| emit_return_pair(first, second))
+    }
+
+    fn replace_function_increment_counter(
+        src: FuncPtrInternal,
+        counter: usize,
+        prologue: &[u8],
+    ) -> PatchGuard {
+        const JIT_SIZE: usize = 15;
+
+        install_jit_code(src, prologue, JIT_SIZE, |_| emit_increment_counter(counter))
+    }
+
+    fn replace_function_return_byte_sequence(
+        src: FuncPtrInternal,
+        counter: usize,
+        values: usize,
+        last: usize,
+        prologue: &[u8],
+    ) -> PatchGuard {
+        const JIT_SIZE: usize = 52;
+
+        install_jit_code(src, prologue, JIT_SIZE, |_| {
+            emit_return_byte_sequence(counter, values, last)
+        })
+    }
+
+    fn replace_function_return_struct(
+        src: FuncPtrInternal,
+        value: usize,
+        size: usize,
+        prologue: &[u8],
+    ) -> PatchGuard {
+        const JIT_SIZE: usize = 43;
+        // The caller passes the address of the return slot as a hidden first argument.
+        const RCX: u8 = 1;
+        const RDI: u8 = 7;
+        let destination = if cfg!(target_os = "windows") {
+            RCX
+        } else {
+            RDI
+        };
+
+        install_jit_code(src, prologue, JIT_SIZE, |_| {
+            emit_return_struct(destination, value, size)
+        })
+    }
+
+    fn replace_function_call_hook(
+        src: FuncPtrInternal,
+        hook: usize,
+        data: usize,
+        prologue: &[u8],
+    ) -> PatchGuard {
+        // The hook joins the prologue, so its unwind information is registered with it.
+        let prologue = [prologue, &emit_call_hook(hook, data)].concat();
+        let body = emit_return_void();
+
+        install_jit_code(src, &prologue, body.len(), |_| body)
+    }
+
+    fn emit_call_hook(hook: usize, data: usize) -> Vec<u8> {
+        emit_call_hook(hook, data)
+    }
+
+    fn argument_slot(index: usize) -> Option<usize> {
+        if cfg!(target_os = "windows") {
+            WIN64_ARGUMENT_SLOTS.get(index).copied()
+        } else {
+            SYSV_ARGUMENT_SLOTS.get(index).copied()
+        }
+    }
+
+    fn check_patch_site(src: &FuncPtrInternal) -> Result<(), InjectError> {
+        let func_addr = src.as_ptr() as usize;
+
+        // The shortest patch is a `jmp rel32`, so an unreadable function fails before any
+        // memory is allocated.
+        try_read_bytes(func_addr as *const u8, 5)?;
 
-        patch_and_guard(src, jit_memory, JIT_SIZE)
+        let jit_addr = probe_jit_memory(src)?;
+        check_patch_window(func_addr, emit_branch(func_addr, jit_addr).len())
+    }
+
+    fn branch_kind(patch: &[u8]) -> BranchKind {
+        if is_jmp_rel32(patch) {
+            BranchKind::Near
+        } else {
+            BranchKind::Long
+        }
     }
 }
 
-/// Injects a return-boolean JIT sequence at `jit_ptr`.
-fn generate_will_return_boolean_jit_code(jit_ptr: *mut u8, value: bool) {
-    let mut asm_code: [u8; 8] = [
-        0x48, 0xC7, 0xC0, // mov rax, imm32
-        0x00, 0x00, 0x00, 0x00, // imm32
-        0xC3, // ret
-    ];
+thread_local! {
+    /// Whether the fake being installed on this thread skips `check_patch_window`.
+    static ASSUME_PATCHABLE: Cell<bool> = const { Cell::new(false) };
+}
 
-    asm_code[3] = value as u8;
+impl PatchAmd64 {
+    /// Makes the following installs on this thread skip `check_patch_window`, set before
+    /// every install.
+    pub(crate) fn set_assume_patchable(assume: bool) {
+        ASSUME_PATCHABLE.with(|cell| cell.set(assume));
+    }
+}
 
-    unsafe {
-        inject_asm_code(&asm_code, jit_ptr);
+/// Fails if patching `patch_size` bytes at `func_addr` would overwrite the function placed
+/// right after it, which happens to tiny functions that are not padded. The `int3` MSVC pads
+/// functions with and the `nop` GCC and LLVM use both count as padding, on every OS.
+fn check_patch_window(func_addr: usize, patch_size: usize) -> Result<(), InjectError> {
+    const MAX_INSTRUCTION_SIZE: usize = 15;
+
+    // The extra bytes let the last instruction of the window decode, but may be unreadable.
+    let code = try_read_bytes(func_addr as *const u8, patch_size + MAX_INSTRUCTION_SIZE)
+        .or_else(|_| try_read_bytes(func_addr as *const u8, patch_size))?;
+
+    match function_end_before(&code, patch_size) {
+        Some(available) => Err(InjectError::PatchWindowTooSmall {
+            address: func_addr,
+            available,
+            required: patch_size,
+        }),
+        None => Ok(()),
     }
 }
 
-/// Generates a jump from `ori_func` to `target_func`.
-fn generate_branch_to_target_function(ori_func: usize, target_func: usize) -> Vec<u8> {
-    let offset = target_func as isize - (ori_func as isize + 5);
-
-    if offset >= i32::MIN as isize && offset <= i32::MAX as isize {
-        let mut branch_code = Vec::with_capacity(5);
-        branch_code.push(JMP_REL_OPCODE);
-        branch_code.extend_from_slice(&(offset as i32).to_le_bytes());
-        branch_code
-    } else {
-        let mut branch_code = Vec::with_capacity(13);
-        branch_code.extend_from_slice(&MOV_RAX_OPCODE);
-        branch_code.extend_from_slice(&(target_func as u64).to_le_bytes());
-        branch_code.extend_from_slice(&JMP_RAX_OPCODE);
-        branch_code
+/// Returns the number of bytes a branch of `branch_size` bytes at `func_addr` must overwrite
+/// to end on an instruction boundary, or `branch_size` if the instructions cannot be decoded.
+fn patch_size_at(func_addr: usize, branch_size: usize) -> usize {
+    const MAX_INSTRUCTION_SIZE: usize = 15;
+
+    try_read_bytes(func_addr as *const u8, branch_size + MAX_INSTRUCTION_SIZE)
+        .ok()
+        .and_then(|code| instruction_boundary(&code, branch_size))
+        .unwrap_or(branch_size)
+}
+
+/// Copies `prologue` followed by the body emitted for its final address into JIT memory
+/// near `src`, then patches `src` to branch to it.
+fn install_jit_code(
+    src: FuncPtrInternal,
+    prologue: &[u8],
+    body_size: usize,
+    emit_body: impl FnOnce(usize) -> Vec<u8>,
+) -> PatchGuard {
+    let hooks = jit_unwind::call_hook_offsets(prologue);
+    let code_size = prologue.len() + body_size;
+    let unwind_data = jit_unwind::unwind_data(code_size, &hooks);
+    let jit_size = code_size + unwind_data.len();
+    let jit_memory = allocate_jit_memory(&src, jit_size);
+
+    let mut jit_code = prologue.to_vec();
+    jit_code.extend(emit_body(jit_memory as usize + prologue.len()));
+    jit_code.resize(code_size, NOP_OPCODE);
+    jit_code.extend(unwind_data);
+
+    unsafe {
+        write_jit_code(&jit_code, jit_memory);
+        jit_unwind::register(jit_memory as usize, code_size, &hooks);
     }
+
+    patch_and_guard(src, jit_memory, jit_size)
 }
 
 fn patch_and_guard(src: FuncPtrInternal, jit_memory: *mut u8, jit_size: usize) -> PatchGuard {
     let func_addr = src.as_ptr() as usize;
     let jit_addr = jit_memory as usize;
 
-    let branch_code = generate_branch_to_target_function(func_addr, jit_addr);
-    let patch_size = branch_code.len();
+    let mut branch_code = emit_branch(func_addr, jit_addr);
+    if !ASSUME_PATCHABLE.with(Cell::get) {
+        check_patch_window(func_addr, branch_code.len()).unwrap_or_else(|error| panic!("{error}"));
+    }
+
+    // Overwrite the rest of the last instruction the branch cuts into with nops, so that no
+    // fragment of it is left to be decoded as garbage.
+    let patch_size = patch_size_at(func_addr, branch_code.len());
+    branch_code.resize(patch_size, NOP_OPCODE);
 
-    let original_bytes = unsafe { read_bytes(src.as_ptr() as *mut u8, patch_size) };
+    let original_bytes = try_read_bytes(src.as_ptr() as *const u8, patch_size)
+        .unwrap_or_else(|error| panic!("{error}"));
 
     unsafe {
         patch_function(src.as_ptr() as *mut u8, &branch_code);
@@ -1,10 +1,12 @@
 #![cfg(target_arch = "arm")]
 
 use std::ptr::null_mut;
-use std::ptr::NonNull;
 
+use crate::injector_core::arm_codegenerator::*;
 use crate::injector_core::common::*;
 use crate::injector_core::patch_trait::*;
+use crate::interface::debug::BranchKind;
+use crate::interface::error::InjectError;
 
 pub(crate) struct PatchArm;
 
@@ -12,85 +14,171 @@ impl PatchTrait for PatchArm {
     fn replace_function_with_other_function(
         src: FuncPtrInternal,
         target: FuncPtrInternal,
+        prologue: &[u8],
     ) -> PatchGuard {
-        // Thumb mode (T32) functions are aligned on odd addresses,
-        // while ARM mode (A32) functions are aligned on even addresses.
-        let is_src_thumb = src.as_ptr() as usize & 1 != 0;
-
-        // Even if the function jump is on an odd address, the previous byte
-        // is executed in Thumb mode, so we need to align the memory on 2 bytes.
-        let src_ptr = if is_src_thumb {
-            (src.as_ptr() as u32 - 1) as *const ()
-        } else {
-            src.as_ptr()
-        };
-
-        let patch_size = 12;
-        let original_bytes = unsafe { read_bytes(src_ptr as *mut u8, patch_size) };
-
-        let instructions: [u32; 3] = if is_src_thumb {
-            [
-                // ldr r7, [pc, #0] ; 0x4F00. It will load pc + 0 into r6, so the target word
-                // bx r7 ; 4738
-                // Reversed because of little endian
-                0x47384F00,
-                // .word target
-                target.as_ptr() as u32,
-                // .word anything (unused)
-                0x00000000,
-            ]
-        } else {
-            [
-                // ldr r9, [pc, #-0] ; Load pc + 8 into r9, so the target word
-                0xE51F9000,
-                // bx r9 ; Branch to the target function
-                0xE12FFF19,
-                // .word target
-                target.as_ptr() as u32,
-            ]
-        };
-
-        let mut patch = [0u8; 12];
-
-        patch[0..4].copy_from_slice(&instructions[0].to_le_bytes());
-        patch[4..8].copy_from_slice(&instructions[1].to_le_bytes());
-        patch[8..12].copy_from_slice(&instructions[2].to_le_bytes());
-
-        // In thumb mode, if the source is not aligned on 32 bit, add a NOP to align it, so the target adress is also aligned on 32 bit
-        // If we don't do that, the load adress will be misaligned and will load the bx instruction instead of the target function.
-        if is_src_thumb && (src_ptr as usize % 4 != 0) {
-            patch.rotate_right(2);
-            patch[0] = 0xC0;
-            patch[1] = 0x46; // NOP instruction in Thumb mode
-        }
+        assert_no_prologue(prologue);
 
-        unsafe {
-            patch_function(src_ptr as *mut u8, &patch);
-        }
+        let (src_addr, is_src_thumb) = code_address(&src);
+        let (target_addr, is_target_thumb) = code_address(&target);
+
+        // A `b` keeps the instruction set, a literal loaded into a register for `bx` switches
+        // it as the Thumb bit of the target says.
+        let patch = is_src_thumb
+            .eq(&is_target_thumb)
+            .then(|| emit_near_branch(src_addr, target_addr, is_src_thumb))
+            .flatten()
+            .unwrap_or_else(|| emit_literal_jump(src_addr, target.as_ptr() as usize, is_src_thumb));
+
+        write_patch(src_addr, &patch)
+    }
+
+    fn replace_function_keeping_original(
+        _src: FuncPtrInternal,
+        _target: FuncPtrInternal,
+        _prologue: &[u8],
+        _original_code: &[u8],
+        _publish_original: &mut dyn FnMut(usize),
+    ) -> PatchGuard {
+        panic!("Calling the original function is not supported on 32-bit ARM");
+    }
+
+    fn replace_function_return_boolean(
+        src: FuncPtrInternal,
+        value: bool,
+        prologue: &[u8],
+    ) -> PatchGuard {
+        assert_no_prologue(prologue);
+
+        let (src_addr, is_src_thumb) = code_address(&src);
+        write_patch(src_addr, &emit_return_boolean(value, is_src_thumb))
+    }
+
+    fn replace_function_return_integer(
+        _src: FuncPtrInternal,
+        _value: u64,
+        _prologue: &[u8],
+    ) -> PatchGuard {
+        // AAPCS splits 64-bit integers across r0 and r1, and there is no JIT block to load
+        // them from.
+        panic!("Returning 64-bit integers is not supported on 32-bit ARM");
+    }
+
+    fn replace_function_return_float(
+        _src: FuncPtrInternal,
+        _bits: u64,
+        _prologue: &[u8],
+    ) -> PatchGuard {
+        // Depending on the float ABI the value goes to d0 or r0/r1, and there is no JIT block
+        // to load it from either way.
+        panic!("Returning floating point values is not supported on 32-bit ARM");
+    }
+
+    fn replace_function_return_pair(
+        _src: FuncPtrInternal,
+        _first: u64,
+        _second: u64,
+        _prologue: &[u8],
+    ) -> PatchGuard {
+        // AAPCS returns composite types larger than 4 bytes through memory, so there is
+        // no register pair to load here.
+        panic!("Returning 16-byte aggregates in registers is not supported on 32-bit ARM");
+    }
 
-        PatchGuard::new(
-            src_ptr as *mut u8,
-            original_bytes,
-            patch_size,
-            null_mut(), // No JIT memory needed for ARM
-            0,
-        )
+    fn replace_function_increment_counter(
+        _src: FuncPtrInternal,
+        _counter: usize,
+        _prologue: &[u8],
+    ) -> PatchGuard {
+        // Without a JIT block there is no place to run the increment from.
+        panic!("Incrementing a counter is not supported on 32-bit ARM");
+    }
+
+    fn replace_function_return_byte_sequence(
+        _src: FuncPtrInternal,
+        _counter: usize,
+        _values: usize,
+        _last: usize,
+        _prologue: &[u8],
+    ) -> PatchGuard {
+        panic!("Returning a sequence of values is not supported on 32-bit ARM");
+    }
+
+    fn replace_function_return_struct(
+        _src: FuncPtrInternal,
+        _value: usize,
+        _size: usize,
+        _prologue: &[u8],
+    ) -> PatchGuard {
+        panic!("Returning a struct is not supported on 32-bit ARM");
+    }
+
+    fn replace_function_call_hook(
+        _src: FuncPtrInternal,
+        _hook: usize,
+        _data: usize,
+        _prologue: &[u8],
+    ) -> PatchGuard {
+        panic!("Invoking callbacks is not supported on 32-bit ARM");
+    }
+
+    fn emit_call_hook(_hook: usize, _data: usize) -> Vec<u8> {
+        panic!("Recording calls is not supported on 32-bit ARM");
     }
 
-    fn replace_function_return_boolean(src: FuncPtrInternal, value: bool) -> PatchGuard {
-        Self::replace_function_with_other_function(src, unsafe {
-            FuncPtrInternal::new(
-                NonNull::new(if value { return_true } else { return_false } as *mut ())
-                    .expect("Failed to create FuncPtrInternal"), // Should never fail
-            )
-        })
+    fn argument_slot(_index: usize) -> Option<usize> {
+        None
+    }
+
+    fn check_patch_site(src: &FuncPtrInternal) -> Result<(), InjectError> {
+        // The longest patch is a literal jump.
+        try_read_bytes(code_address(src).0 as *const u8, 12)?;
+        Ok(())
+    }
+
+    fn branch_kind(patch: &[u8]) -> BranchKind {
+        if is_return_boolean(patch) {
+            BranchKind::Inline
+        } else if is_near_branch(patch) {
+            BranchKind::Near
+        } else {
+            BranchKind::Long
+        }
     }
 }
 
-fn return_true() -> bool {
-    true
+/// Returns the address of the first instruction of `func` and whether it is Thumb code.
+///
+/// Thumb (T32) function pointers have their lowest bit set, which must be cleared to get the
+/// address of the code, while ARM (A32) ones are word aligned.
+fn code_address(func: &FuncPtrInternal) -> (usize, bool) {
+    let address = func.as_ptr() as usize;
+
+    (address & !1, address & 1 != 0)
+}
+
+/// Writes `patch` over the function at `src_addr`.
+fn write_patch(src_addr: usize, patch: &[u8]) -> PatchGuard {
+    let original_bytes = try_read_bytes(src_addr as *const u8, patch.len())
+        .unwrap_or_else(|error| panic!("{error}"));
+
+    unsafe {
+        patch_function(src_addr as *mut u8, patch);
+    }
+
+    PatchGuard::new(
+        src_addr as *mut u8,
+        original_bytes,
+        patch.len(),
+        null_mut(), // No JIT memory needed for ARM
+        0,
+    )
 }
 
-fn return_false() -> bool {
-    false
+/// 32-bit ARM branches straight to the target without a JIT block, so there is nowhere to
+/// run a prologue from.
+fn assert_no_prologue(prologue: &[u8]) {
+    assert!(
+        prologue.is_empty(),
+        "Recording calls is not supported on 32-bit ARM"
+    );
 }
@@ -1,9 +1,14 @@
 #![cfg(target_arch = "aarch64")]
 
 use crate::injector_core::arm64_codegenerator::*;
+use crate::injector_core::arm64_relocator::relocate;
 use crate::injector_core::common::*;
 use crate::injector_core::patch_trait::*;
-use crate::injector_core::utils::*;
+use crate::interface::debug::BranchKind;
+use crate::interface::error::InjectError;
+
+/// The bytes of the long jump patch, three instructions.
+const PATCH_SIZE: usize = 12;
 
 pub(crate) struct PatchArm64;
 
@@ -11,105 +16,214 @@ impl PatchTrait for PatchArm64 {
     fn replace_function_with_other_function(
         src: FuncPtrInternal,
         target: FuncPtrInternal,
+        prologue: &[u8],
     ) -> PatchGuard {
-        const PATCH_SIZE: usize = 12;
-        const JIT_SIZE: usize = 20;
+        install_jit_code(src, prologue, &emit_abs_jump(target.as_ptr() as usize))
+    }
 
-        let original_bytes = unsafe { read_bytes(src.as_ptr() as *mut u8, PATCH_SIZE) };
-        let jit_memory = allocate_jit_memory(&src, JIT_SIZE);
-        generate_will_execute_jit_code_abs(jit_memory, target.as_ptr());
+    fn replace_function_keeping_original(
+        src: FuncPtrInternal,
+        target: FuncPtrInternal,
+        prologue: &[u8],
+        original_code: &[u8],
+        publish_original: &mut dyn FnMut(usize),
+    ) -> PatchGuard {
+        let func_addr = src.as_ptr() as usize;
+        let code = read_patch_window(&src).unwrap_or_else(|error| panic!("{error}"));
+        let mut real_code = code.clone();
+        real_code[..original_code.len()].copy_from_slice(original_code);
+
+        let body = emit_abs_jump(target.as_ptr() as usize);
+        let original = relocate(&real_code, func_addr, patch_size(&real_code))
+            .unwrap_or_else(|error| panic!("{error}"));
+
+        let jit_code = [prologue, &body, &original].concat();
+        let jit_memory = allocate_jit_memory(&src, jit_code.len());
+
+        unsafe {
+            write_jit_code(&jit_code, jit_memory);
+        }
 
-        apply_branch_patch(src, jit_memory, JIT_SIZE, &original_bytes)
+        publish_original(jit_memory as usize + prologue.len() + body.len());
+
+        apply_branch_patch(src, jit_memory, jit_code.len(), &code)
     }
 
-    fn replace_function_return_boolean(src: FuncPtrInternal, value: bool) -> PatchGuard {
-        const PATCH_SIZE: usize = 12;
-        const JIT_SIZE: usize = 8;
+    fn replace_function_return_boolean(
+        src: FuncPtrInternal,
+        value: bool,
+        prologue: &[u8],
+    ) -> PatchGuard {
+        install_jit_code(src, prologue, &emit_return_boolean(value))
+    }
 
-        let original_bytes = unsafe { read_bytes(src.as_ptr() as *mut u8, PATCH_SIZE) };
-        let jit_memory = allocate_jit_memory(&src, JIT_SIZE);
-        generate_will_return_boolean_jit_code(jit_memory, value);
+    fn replace_function_return_integer(
+        src: FuncPtrInternal,
+        value: u64,
+        prologue: &[u8],
+    ) -> PatchGuard {
+        install_jit_code(src, prologue, &emit_return_integer(value))
+    }
 
-        apply_branch_patch(src, jit_memory, JIT_SIZE, &original_bytes)
+    fn replace_function_return_float(
+        src: FuncPtrInternal,
+        bits: u64,
+        prologue: &[u8],
+    ) -> PatchGuard {
+        install_jit_code(src, prologue, &emit_return_float(bits))
     }
-}
 
-/// Generates a 16-byte JIT code block that loads the absolute address of `target`
-/// into register X9 (using a MOVZ and two MOVK instructions) and then branches to X9.
-/// This avoids branch-range limitations.
-///
-/// The generated instructions are:
-///   movz x9, #imm0, lsl #0
-///   movk x9, #imm1, lsl #16
-///   movk x9, #imm2, lsl #32
-///   br x9
-fn generate_will_execute_jit_code_abs(jit_ptr: *mut u8, target: *const ()) {
-    let target_addr = target as usize as u64;
+    fn replace_function_return_pair(
+        src: FuncPtrInternal,
+        first: u64,
+        second: u64,
+        prologue: &[u8],
+    ) -> PatchGuard {
+        install_jit_code(src, prologue, &emit_return_pair(first, second))
+    }
 
-    // x9
-    let register_name: [bool; 5] = u8_to_bits::<5>(9);
+    fn replace_function_increment_counter(
+        src: FuncPtrInternal,
+        counter: usize,
+        prologue: &[u8],
+    ) -> PatchGuard {
+        install_jit_code(src, prologue, &emit_increment_counter(counter))
+    }
 
-    // MOVZ x9, #imm0 (clears the rest)
-    let movz = emit_movz_from_address(target_addr, 0, true, u8_to_bits::<2>(0), register_name);
+    fn replace_function_return_byte_sequence(
+        src: FuncPtrInternal,
+        counter: usize,
+        values: usize,
+        last: usize,
+        prologue: &[u8],
+    ) -> PatchGuard {
+        install_jit_code(
+            src,
+            prologue,
+            &emit_return_byte_sequence(counter, values, last),
+        )
+    }
 
-    // MOVK x9, #imm1, LSL #16
-    let movk1 = emit_movk_from_address(target_addr, 16, true, u8_to_bits::<2>(1), register_name);
+    fn replace_function_return_struct(
+        src: FuncPtrInternal,
+        value: usize,
+        size: usize,
+        prologue: &[u8],
+    ) -> PatchGuard {
+        install_jit_code(src, prologue, &emit_return_struct(value, size))
+    }
 
-    // MOVK x9, #imm2, LSL #32
-    let movk2 = emit_movk_from_address(target_addr, 32, true, u8_to_bits::<2>(2), register_name);
+    fn replace_function_call_hook(
+        src: FuncPtrInternal,
+        hook: usize,
+        data: usize,
+        prologue: &[u8],
+    ) -> PatchGuard {
+        let body = [emit_call_hook(hook, data), emit_return_void()].concat();
 
-    // MOVK x9, #imm3, LSL #48
-    let movk3 = emit_movk_from_address(target_addr, 48, true, u8_to_bits::<2>(3), register_name);
+        install_jit_code(src, prologue, &body)
+    }
 
-    // BR x9
-    let br = emit_br(register_name);
+    fn emit_call_hook(hook: usize, data: usize) -> Vec<u8> {
+        emit_call_hook(hook, data)
+    }
 
-    // Write instructions in the correct order: bottom-up so no overwrite
-    let mut asm_code: Vec<u8> = Vec::new();
-    append_instruction(&mut asm_code, bool_array_to_u32(movz));
-    append_instruction(&mut asm_code, bool_array_to_u32(movk1));
-    append_instruction(&mut asm_code, bool_array_to_u32(movk2));
-    append_instruction(&mut asm_code, bool_array_to_u32(movk3));
-    append_instruction(&mut asm_code, bool_array_to_u32(br));
+    fn argument_slot(index: usize) -> Option<usize> {
+        argument_slot(index)
+    }
 
-    unsafe {
-        inject_asm_code(&asm_code, jit_ptr);
+    fn check_patch_site(src: &FuncPtrInternal) -> Result<(), InjectError> {
+        let func_addr = src.as_ptr() as usize;
+        let code = read_patch_window(src)?;
+
+        let jit_addr = probe_jit_memory(src)?;
+        check_patch_window(func_addr, jit_addr, &code)
+    }
+
+    fn branch_kind(patch: &[u8]) -> BranchKind {
+        let first = u32::from_le_bytes(patch[..4].try_into().unwrap());
+
+        if is_unconditional_branch(first) {
+            BranchKind::Near
+        } else {
+            BranchKind::Long
+        }
     }
 }
 
-/// Generates a 16-byte JIT code block that returns the specified boolean.
-/// The code moves the immediate into w0 and then returns.
-/// Two NOPs are added for padding.
-fn generate_will_return_boolean_jit_code(jit_ptr: *mut u8, value: bool) {
-    let mut asm_code = [0u8; 8]; // 2 instructions = 2 * 4
-    let mut cursor = 0;
+impl PatchArm64 {
+    /// Returns whether the function at `src` starts with an unconditional branch, i.e. its
+    /// whole body is a tail call.
+    pub(crate) fn is_tail_call(src: &FuncPtrInternal) -> bool {
+        try_read_bytes(src.as_ptr() as *const u8, 4).is_ok_and(|bytes| {
+            is_unconditional_branch(u32::from_le_bytes(bytes.try_into().unwrap()))
+        })
+    }
+
+    /// Returns the function the unconditional branch starting the function at `src` jumps
+    /// to, e.g. the body behind an incremental linking thunk, or `None` if it starts with
+    /// something else.
+    pub(crate) fn branch_thunk_target(src: &FuncPtrInternal) -> Option<FuncPtrInternal> {
+        let func_addr = src.as_ptr() as usize;
+        let bytes = try_read_bytes(func_addr as *const u8, 4).ok()?;
+        let target =
+            unconditional_branch_target(u32::from_le_bytes(bytes.try_into().unwrap()), func_addr)?;
+
+        // A branch to itself is a hang, not a thunk.
+        let target = std::ptr::NonNull::new((target != func_addr).then_some(target)? as *mut ())?;
 
-    let mut value_bits = [false; 16];
-    value_bits[0] = value;
+        Some(unsafe { FuncPtrInternal::new(target) })
+    }
+}
 
-    let movz = emit_movz(value_bits, true, u8_to_bits::<2>(0), u8_to_bits::<5>(0));
-    let ret = emit_ret_x30();
+/// Copies `prologue` followed by `body` into JIT memory near `src`, then patches `src` to
+/// branch to it.
+fn install_jit_code(src: FuncPtrInternal, prologue: &[u8], body: &[u8]) -> PatchGuard {
+    let original_bytes = read_patch_window(&src).unwrap_or_else(|error| panic!("{error}"));
 
-    write_instruction(&mut asm_code, &mut cursor, bool_array_to_u32(movz));
-    write_instruction(&mut asm_code, &mut cursor, bool_array_to_u32(ret));
+    let jit_code = [prologue, body].concat();
+    let jit_memory = allocate_jit_memory(&src, jit_code.len());
 
     unsafe {
-        inject_asm_code(&asm_code, jit_ptr);
+        write_jit_code(&jit_code, jit_memory);
     }
+
+    apply_branch_patch(src, jit_memory, jit_code.len(), &original_bytes)
 }
 
-#[inline]
-fn write_instruction(buf: &mut [u8], cursor: &mut usize, instruction: u32) {
-    let bytes = instruction.to_le_bytes();
-    buf[*cursor..*cursor + 4].copy_from_slice(&bytes);
-    *cursor += 4;
+/// Reads the bytes the patch at `src` may overwrite, only the first instruction if the
+/// others are not readable.
+fn read_patch_window(src: &FuncPtrInternal) -> Result<Vec<u8>, InjectError> {
+    let func_addr = src.as_ptr() as *const u8;
+
+    try_read_bytes(func_addr, PATCH_SIZE).or_else(|_| try_read_bytes(func_addr, 4))
 }
 
-fn append_instruction(asm_code: &mut Vec<u8>, instruction: u32) {
-    asm_code.push((instruction & 0xFF) as u8);
-    asm_code.push(((instruction >> 8) & 0xFF) as u8);
-    asm_code.push(((instruction >> 16) & 0xFF) as u8);
-    asm_code.push(((instruction >> 24) & 0xFF) as u8);
+/// Returns how many bytes of `code`, read by `read_patch_window`, the patch overwrites.
+///
+/// A function shorter than the long jump, such as a leaf returning a constant or a single
+/// tail call, only has its first instruction replaced by a `b`, so that whatever follows its
+/// `ret` or branch is left alone.
+fn patch_size(code: &[u8]) -> usize {
+    if function_window(code) < PATCH_SIZE {
+        4
+    } else {
+        PATCH_SIZE
+    }
+}
+
+/// Fails if the function at `func_addr`, starting with `code`, is too short for the patch
+/// branching to `jit_addr`: a single `b` only reaches ±128MB.
+fn check_patch_window(func_addr: usize, jit_addr: usize, code: &[u8]) -> Result<(), InjectError> {
+    if patch_size(code) < PATCH_SIZE && maybe_emit_long_jump(func_addr, jit_addr).len() != 1 {
+        return Err(InjectError::PatchWindowTooSmall {
+            address: func_addr,
+            available: function_window(code),
+            required: PATCH_SIZE,
+        });
+    }
+
+    Ok(())
 }
 
 fn apply_branch_patch(
@@ -118,51 +232,34 @@ fn apply_branch_patch(
     jit_size: usize,
     original_bytes: &[u8],
 ) -> PatchGuard {
-    const PATCH_SIZE: usize = 12;
     const NOP: u32 = 0xd503201f;
 
     let func_addr = src.as_ptr() as usize;
     let jit_addr = jit_memory as usize;
 
-    let mut patch = [0u8; PATCH_SIZE];
-
-    #[cfg(target_os = "macos")]
-    {
-        let instrs = maybe_emit_long_jump(func_addr, jit_addr);
-        if instrs.len() == 1 {
-            patch[0..4].copy_from_slice(&instrs[0].to_le_bytes());
-            patch[4..8].copy_from_slice(&NOP.to_le_bytes());
-            patch[8..12].copy_from_slice(&NOP.to_le_bytes());
-        } else {
-            patch[0..4].copy_from_slice(&instrs[0].to_le_bytes());
-            patch[4..8].copy_from_slice(&instrs[1].to_le_bytes());
-            patch[8..12].copy_from_slice(&instrs[2].to_le_bytes());
-        }
-    }
+    // Memory beyond the ±128MB a `b` reaches is branched to through x16, which needs the
+    // whole patch window.
+    check_patch_window(func_addr, jit_addr, original_bytes)
+        .unwrap_or_else(|error| panic!("{error}"));
+    let patch_size = patch_size(original_bytes);
+    let instrs = maybe_emit_long_jump(func_addr, jit_addr);
 
-    #[cfg(not(target_os = "macos"))]
+    let mut patch = [0u8; PATCH_SIZE];
+    for (slot, instr) in patch
+        .chunks_exact_mut(4)
+        .zip(instrs.iter().chain(std::iter::repeat(&NOP)))
     {
-        const BRANCH_RANGE: std::ops::RangeInclusive<isize> = -0x2000000..=0x1FFF_FFFF; // ±32MB
-
-        let offset = (jit_addr as isize - func_addr as isize) / 4;
-        if !BRANCH_RANGE.contains(&offset) {
-            panic!("JIT memory is out of branch range: offset = {offset}, expected ±32MB");
-        }
-
-        let branch_instr: u32 = 0x14000000 | ((offset as u32) & 0x03FF_FFFF);
-        patch[0..4].copy_from_slice(&branch_instr.to_le_bytes());
-        patch[4..8].copy_from_slice(&NOP.to_le_bytes());
-        patch[8..12].copy_from_slice(&NOP.to_le_bytes());
+        slot.copy_from_slice(&instr.to_le_bytes());
     }
 
     unsafe {
-        patch_function(src.as_ptr() as *mut u8, &patch);
+        patch_function(src.as_ptr() as *mut u8, &patch[..patch_size]);
     }
 
     PatchGuard::new(
         src.as_ptr() as *mut u8,
         original_bytes.to_vec(),
-        PATCH_SIZE,
+        patch_size,
         jit_memory,
         jit_size,
     )
@@ -0,0 +1,161 @@
+#![cfg(target_arch = "riscv64")]
+
+use crate::injector_core::common::*;
+use crate::injector_core::patch_trait::*;
+use crate::injector_core::riscv64_codegenerator::*;
+use crate::interface::debug::BranchKind;
+use crate::interface::error::InjectError;
+
+pub(crate) struct PatchRiscv64;
+
+/// The size of the `auipc`/`jalr` pair written over the patched function.
+const PATCH_SIZE: usize = 8;
+
+impl PatchTrait for PatchRiscv64 {
+    fn replace_function_with_other_function(
+        src: FuncPtrInternal,
+        target: FuncPtrInternal,
+        prologue: &[u8],
+    ) -> PatchGuard {
+        install_jit_code(src, prologue, &emit_abs_jump(target.as_ptr() as usize))
+    }
+
+    fn replace_function_keeping_original(
+        _src: FuncPtrInternal,
+        _target: FuncPtrInternal,
+        _prologue: &[u8],
+        _original_code: &[u8],
+        _publish_original: &mut dyn FnMut(usize),
+    ) -> PatchGuard {
+        panic!("Calling the original function is not supported on RISC-V");
+    }
+
+    fn replace_function_return_boolean(
+        src: FuncPtrInternal,
+        value: bool,
+        prologue: &[u8],
+    ) -> PatchGuard {
+        install_jit_code(src, prologue, &emit_return_boolean(value))
+    }
+
+    fn replace_function_return_integer(
+        src: FuncPtrInternal,
+        value: u64,
+        prologue: &[u8],
+    ) -> PatchGuard {
+        install_jit_code(src, prologue, &emit_return_integer(value))
+    }
+
+    fn replace_function_return_float(
+        src: FuncPtrInternal,
+        bits: u64,
+        prologue: &[u8],
+    ) -> PatchGuard {
+        install_jit_code(src, prologue, &emit_return_float(bits))
+    }
+
+    fn replace_function_return_pair(
+        src: FuncPtrInternal,
+        first: u64,
+        second: u64,
+        prologue: &[u8],
+    ) -> PatchGuard {
+        install_jit_code(src, prologue, &emit_return_pair(first, second))
+    }
+
+    fn replace_function_increment_counter(
+        src: FuncPtrInternal,
+        counter: usize,
+        prologue: &[u8],
+    ) -> PatchGuard {
+        install_jit_code(src, prologue, &emit_increment_counter(counter))
+    }
+
+    fn replace_function_return_byte_sequence(
+        src: FuncPtrInternal,
+        counter: usize,
+        values: usize,
+        last: usize,
+        prologue: &[u8],
+    ) -> PatchGuard {
+        install_jit_code(
+            src,
+            prologue,
+            &emit_return_byte_sequence(counter, values, last),
+        )
+    }
+
+    fn replace_function_return_struct(
+        src: FuncPtrInternal,
+        value: usize,
+        size: usize,
+        prologue: &[u8],
+    ) -> PatchGuard {
+        install_jit_code(src, prologue, &emit_return_struct(value, size))
+    }
+
+    fn replace_function_call_hook(
+        _src: FuncPtrInternal,
+        _hook: usize,
+        _data: usize,
+        _prologue: &[u8],
+    ) -> PatchGuard {
+        panic!("Invoking callbacks is not supported on RISC-V");
+    }
+
+    fn emit_call_hook(_hook: usize, _data: usize) -> Vec<u8> {
+        panic!("Recording calls is not supported on RISC-V");
+    }
+
+    fn argument_slot(_index: usize) -> Option<usize> {
+        None
+    }
+
+    fn check_patch_site(src: &FuncPtrInternal) -> Result<(), InjectError> {
+        try_read_bytes(src.as_ptr() as *const u8, PATCH_SIZE)?;
+        probe_jit_memory(src)?;
+        Ok(())
+    }
+
+    fn branch_kind(_patch: &[u8]) -> BranchKind {
+        // JIT memory is always allocated within reach of `auipc`/`jalr`.
+        BranchKind::Near
+    }
+}
+
+/// Copies `prologue` followed by `body` into JIT memory near `src`, then patches `src` to
+/// jump to it with an `auipc`/`jalr` pair, which reaches ±2GB.
+fn install_jit_code(src: FuncPtrInternal, prologue: &[u8], body: &[u8]) -> PatchGuard {
+    let original_bytes = try_read_bytes(src.as_ptr() as *const u8, PATCH_SIZE)
+        .unwrap_or_else(|error| panic!("{error}"));
+
+    let jit_code = [prologue, body].concat();
+    let jit_memory = allocate_jit_memory(&src, jit_code.len());
+
+    unsafe {
+        write_jit_code(&jit_code, jit_memory);
+    }
+
+    let func_addr = src.as_ptr() as usize;
+    let patch = emit_pc_relative_jump(func_addr, jit_memory as usize).unwrap_or_else(|| {
+        panic!(
+            "{}",
+            InjectError::OutOfBranchRange {
+                address: func_addr,
+                max_range: 0x7FFF_F000,
+            }
+        )
+    });
+
+    unsafe {
+        patch_function(src.as_ptr() as *mut u8, &patch);
+    }
+
+    PatchGuard::new(
+        src.as_ptr() as *mut u8,
+        original_bytes,
+        PATCH_SIZE,
+        jit_memory,
+        jit_code.len(),
+    )
+}
@@ -1,10 +1,109 @@
 use crate::injector_core::common::*;
+use crate::interface::debug::BranchKind;
+use crate::interface::error::InjectError;
 
+/// Architecture specific patching.
+///
+/// Every `replace_*` method runs `prologue` first when the patched function is called. A
+/// prologue is made of blocks such as the one returned by `emit_call_hook`: it must leave
+/// every argument register untouched and fall through to the code that follows it.
 pub(crate) trait PatchTrait {
     fn replace_function_with_other_function(
         src: FuncPtrInternal,
         target: FuncPtrInternal,
+        prologue: &[u8],
     ) -> PatchGuard;
 
-    fn replace_function_return_boolean(src: FuncPtrInternal, value: bool) -> PatchGuard;
+    /// Like `replace_function_with_other_function`, but also copies the instructions the
+    /// patch overwrites into the JIT block, followed by a jump to the rest of `src`, so the
+    /// original function stays callable.
+    ///
+    /// `original_code` is the real code at the start of `src` where earlier fakes patched it,
+    /// which is copied instead of their patches, and is empty if `src` is not patched.
+    /// `publish_original` receives the address of that copy before the patch is written.
+    fn replace_function_keeping_original(
+        src: FuncPtrInternal,
+        target: FuncPtrInternal,
+        prologue: &[u8],
+        original_code: &[u8],
+        publish_original: &mut dyn FnMut(usize),
+    ) -> PatchGuard;
+
+    fn replace_function_return_boolean(
+        src: FuncPtrInternal,
+        value: bool,
+        prologue: &[u8],
+    ) -> PatchGuard;
+
+    /// Makes `src` return the 64-bit `value` in the first integer return register.
+    fn replace_function_return_integer(
+        src: FuncPtrInternal,
+        value: u64,
+        prologue: &[u8],
+    ) -> PatchGuard;
+
+    /// Makes `src` return the double whose bit pattern is `bits` in the first floating point
+    /// return register.
+    fn replace_function_return_float(
+        src: FuncPtrInternal,
+        bits: u64,
+        prologue: &[u8],
+    ) -> PatchGuard;
+
+    fn replace_function_return_pair(
+        src: FuncPtrInternal,
+        first: u64,
+        second: u64,
+        prologue: &[u8],
+    ) -> PatchGuard;
+
+    /// Makes `src` atomically increment the 64-bit counter at `counter` and return.
+    fn replace_function_increment_counter(
+        src: FuncPtrInternal,
+        counter: usize,
+        prologue: &[u8],
+    ) -> PatchGuard;
+
+    /// Makes `src` return the byte `values[min(n, last)]` zero-extended in the first integer
+    /// return register, where `n` is the value of the 64-bit counter at `counter`, which is
+    /// atomically incremented.
+    fn replace_function_return_byte_sequence(
+        src: FuncPtrInternal,
+        counter: usize,
+        values: usize,
+        last: usize,
+        prologue: &[u8],
+    ) -> PatchGuard;
+
+    /// Makes `src` copy the `size` bytes at `value` to the return slot its caller passed a
+    /// pointer to, which is how functions return types too large for registers.
+    fn replace_function_return_struct(
+        src: FuncPtrInternal,
+        value: usize,
+        size: usize,
+        prologue: &[u8],
+    ) -> PatchGuard;
+
+    /// Makes `src` call `hook(data, registers)` like a prologue block would, then return.
+    fn replace_function_call_hook(
+        src: FuncPtrInternal,
+        hook: usize,
+        data: usize,
+        prologue: &[u8],
+    ) -> PatchGuard;
+
+    /// Returns a prologue block that calls `hook(data, registers)` through the C calling
+    /// convention, where `registers` points at the saved general purpose registers.
+    fn emit_call_hook(hook: usize, data: usize) -> Vec<u8>;
+
+    /// Returns the position of the integer argument register number `index` in the
+    /// `registers` passed to a hook, or `None` if that argument is not passed in a register.
+    fn argument_slot(index: usize) -> Option<usize>;
+
+    /// Checks that `src` can be patched at all, without writing anything: the bytes the patch
+    /// overwrites are readable and JIT memory can be allocated within branch range.
+    fn check_patch_site(src: &FuncPtrInternal) -> Result<(), InjectError>;
+
+    /// Returns how the bytes `patch` written over a function branch away from it.
+    fn branch_kind(patch: &[u8]) -> BranchKind;
 }
@@ -0,0 +1,151 @@
+#![cfg(target_arch = "x86")]
+
+use crate::injector_core::common::*;
+use crate::injector_core::patch_trait::*;
+use crate::injector_core::x86_codegenerator::*;
+use crate::interface::debug::BranchKind;
+use crate::interface::error::InjectError;
+
+/// Patch implementation for 32-bit x86 (i686).
+pub(crate) struct PatchX86;
+
+impl PatchTrait for PatchX86 {
+    fn replace_function_with_other_function(
+        src: FuncPtrInternal,
+        target: FuncPtrInternal,
+        prologue: &[u8],
+    ) -> PatchGuard {
+        install_jit_code(src, prologue, &emit_abs_jump(target.as_ptr() as u32))
+    }
+
+    fn replace_function_keeping_original(
+        _src: FuncPtrInternal,
+        _target: FuncPtrInternal,
+        _prologue: &[u8],
+        _original_code: &[u8],
+        _publish_original: &mut dyn FnMut(usize),
+    ) -> PatchGuard {
+        panic!("Calling the original function is not supported on 32-bit x86");
+    }
+
+    fn replace_function_return_boolean(
+        src: FuncPtrInternal,
+        value: bool,
+        prologue: &[u8],
+    ) -> PatchGuard {
+        install_jit_code(src, prologue, &emit_return_boolean(value))
+    }
+
+    fn replace_function_return_integer(
+        src: FuncPtrInternal,
+        value: u64,
+        prologue: &[u8],
+    ) -> PatchGuard {
+        install_jit_code(src, prologue, &emit_return_integer(value))
+    }
+
+    fn replace_function_return_float(
+        _src: FuncPtrInternal,
+        _bits: u64,
+        _prologue: &[u8],
+    ) -> PatchGuard {
+        // cdecl returns doubles in st(0) while the Rust ABI returns them in xmm0, and nothing
+        // tells which one the patched function uses.
+        panic!("Returning floating point values is not supported on 32-bit x86");
+    }
+
+    fn replace_function_return_pair(
+        _src: FuncPtrInternal,
+        _first: u64,
+        _second: u64,
+        _prologue: &[u8],
+    ) -> PatchGuard {
+        // cdecl returns aggregates larger than 8 bytes through memory, so there is no
+        // register pair to load here.
+        panic!("Returning 16-byte aggregates in registers is not supported on 32-bit x86");
+    }
+
+    fn replace_function_increment_counter(
+        _src: FuncPtrInternal,
+        _counter: usize,
+        _prologue: &[u8],
+    ) -> PatchGuard {
+        panic!("Incrementing a counter is not supported on 32-bit x86");
+    }
+
+    fn replace_function_return_byte_sequence(
+        _src: FuncPtrInternal,
+        _counter: usize,
+        _values: usize,
+        _last: usize,
+        _prologue: &[u8],
+    ) -> PatchGuard {
+        panic!("Returning a sequence of values is not supported on 32-bit x86");
+    }
+
+    fn replace_function_return_struct(
+        _src: FuncPtrInternal,
+        _value: usize,
+        _size: usize,
+        _prologue: &[u8],
+    ) -> PatchGuard {
+        panic!("Returning a struct is not supported on 32-bit x86");
+    }
+
+    fn replace_function_call_hook(
+        _src: FuncPtrInternal,
+        _hook: usize,
+        _data: usize,
+        _prologue: &[u8],
+    ) -> PatchGuard {
+        panic!("Invoking callbacks is not supported on 32-bit x86");
+    }
+
+    fn emit_call_hook(_hook: usize, _data: usize) -> Vec<u8> {
+        panic!("Recording calls is not supported on 32-bit x86");
+    }
+
+    fn argument_slot(_index: usize) -> Option<usize> {
+        // cdecl passes every argument on the stack.
+        None
+    }
+
+    fn check_patch_site(src: &FuncPtrInternal) -> Result<(), InjectError> {
+        // The patch reaches JIT memory wherever it is mapped, so only the patch site matters.
+        try_read_bytes(src.as_ptr() as *const u8, JMP_REL32_SIZE)?;
+        Ok(())
+    }
+
+    fn branch_kind(_patch: &[u8]) -> BranchKind {
+        // A `jmp rel32` reaches the whole address space.
+        BranchKind::Near
+    }
+}
+
+/// Copies `prologue` followed by `body` into JIT memory, then patches `src` to jump to it with
+/// a `jmp rel32`, which reaches the whole 32-bit address space.
+fn install_jit_code(src: FuncPtrInternal, prologue: &[u8], body: &[u8]) -> PatchGuard {
+    let original_bytes = try_read_bytes(src.as_ptr() as *const u8, JMP_REL32_SIZE)
+        .unwrap_or_else(|error| panic!("{error}"));
+
+    let jit_code = [prologue, body].concat();
+    let jit_memory = allocate_jit_memory(&src, jit_code.len());
+
+    unsafe {
+        write_jit_code(&jit_code, jit_memory);
+    }
+
+    let patch = emit_jmp_rel32(src.as_ptr() as u32, jit_memory as u32);
+
+    unsafe {
+        patch_function(src.as_ptr() as *mut u8, &patch);
+    }
+
+    PatchGuard::new(
+        src.as_ptr() as *mut u8,
+        original_bytes,
+        JMP_REL32_SIZE,
+        jit_memory,
+        jit_code.len(),
+    )
+}
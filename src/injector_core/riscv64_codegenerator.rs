@@ -0,0 +1,405 @@
+//! Encoders for the RISC-V (RV64GC) instructions used by the patches.
+//!
+//! Every function only builds bytes and never executes them, so this module is compiled and
+//! tested on every host.
+#![cfg_attr(not(target_arch = "riscv64"), allow(dead_code))]
+
+/// The return address register.
+const RA: u32 = 1;
+
+/// The temporary registers t0 and t1, which the calling convention lets a callee clobber.
+const T0: u32 = 5;
+const T1: u32 = 6;
+
+/// The first two integer and floating point return registers.
+const A0: u32 = 10;
+const A1: u32 = 11;
+const FA0: u32 = 10;
+
+const OP_IMM: u32 = 0x13;
+const AUIPC: u32 = 0x17;
+const AMO: u32 = 0x2F;
+const JALR: u32 = 0x67;
+
+/// `fmv.d.x` with neither register set.
+const FMV_D_X: u32 = 0xF200_0053;
+
+/// Returns an I-type instruction.
+fn i_type(opcode: u32, funct3: u32, rd: u32, rs1: u32, imm: i32) -> u32 {
+    ((imm as u32 & 0xFFF) << 20) | (rs1 << 15) | (funct3 << 12) | (rd << 7) | opcode
+}
+
+fn addi(rd: u32, rs1: u32, imm: i32) -> u32 {
+    i_type(OP_IMM, 0, rd, rs1, imm)
+}
+
+fn ori(rd: u32, rs1: u32, imm: i32) -> u32 {
+    i_type(OP_IMM, 6, rd, rs1, imm)
+}
+
+fn slli(rd: u32, rs1: u32, shift: u32) -> u32 {
+    i_type(OP_IMM, 1, rd, rs1, shift as i32)
+}
+
+fn jalr(rd: u32, rs1: u32, imm: i32) -> u32 {
+    i_type(JALR, 0, rd, rs1, imm)
+}
+
+pub(crate) fn append_instruction(asm_code: &mut Vec<u8>, instruction: u32) {
+    asm_code.extend_from_slice(&instruction.to_le_bytes());
+}
+
+/// Appends an `addi` followed by five `slli`/`ori` pairs that load the full 64-bit `value`
+/// into register `x{register}`, 11 bits at a time.
+///
+/// Every immediate is positive, so unlike a `lui`/`addiw` sequence no sign extension has to
+/// be compensated for.
+pub(crate) fn append_li64(asm_code: &mut Vec<u8>, register: u32, value: u64) {
+    append_instruction(asm_code, addi(register, 0, (value >> 55) as i32));
+
+    for shift in (0..55).step_by(11).rev() {
+        append_instruction(asm_code, slli(register, register, 11));
+        append_instruction(
+            asm_code,
+            ori(register, register, ((value >> shift) & 0x7FF) as i32),
+        );
+    }
+}
+
+/// Returns a `ret`, i.e. `jalr x0, 0(ra)`.
+pub(crate) fn emit_return_void() -> Vec<u8> {
+    let mut asm_code = Vec::with_capacity(4);
+    append_instruction(&mut asm_code, jalr(0, RA, 0));
+    asm_code
+}
+
+/// Returns a 48-byte position independent jump to the absolute address `target`, through t0.
+pub(crate) fn emit_abs_jump(target: usize) -> Vec<u8> {
+    let mut asm_code = Vec::with_capacity(48);
+    append_li64(&mut asm_code, T0, target as u64);
+    append_instruction(&mut asm_code, jalr(0, T0, 0));
+    asm_code
+}
+
+/// Returns an `auipc t0` and `jalr x0` pair placed at `from` that lands on `to`, or `None` if
+/// `to` is out of their ±2GB reach.
+pub(crate) fn emit_pc_relative_jump(from: usize, to: usize) -> Option<Vec<u8>> {
+    let offset = to as i64 - from as i64;
+
+    // jalr sign extends its low 12 bits, so round the upper part to compensate.
+    let upper = (offset + 0x800) >> 12;
+    let lower = offset - (upper << 12);
+    if !(-0x8_0000..0x8_0000).contains(&upper) {
+        return None;
+    }
+
+    let mut asm_code = Vec::with_capacity(8);
+    append_instruction(
+        &mut asm_code,
+        ((upper as u32 & 0xF_FFFF) << 12) | (T0 << 7) | AUIPC,
+    );
+    append_instruction(&mut asm_code, jalr(0, T0, lower as i32));
+    Some(asm_code)
+}
+
+/// Returns an 8-byte JIT sequence that returns the specified boolean in a0.
+pub(crate) fn emit_return_boolean(value: bool) -> Vec<u8> {
+    let mut asm_code = Vec::with_capacity(8);
+    append_instruction(&mut asm_code, addi(A0, 0, value as i32));
+    asm_code.extend(emit_return_void());
+    asm_code
+}
+
+/// Returns a 48-byte JIT sequence that returns the 64-bit `value` in a0.
+pub(crate) fn emit_return_integer(value: u64) -> Vec<u8> {
+    let mut asm_code = Vec::with_capacity(48);
+    append_li64(&mut asm_code, A0, value);
+    asm_code.extend(emit_return_void());
+    asm_code
+}
+
+/// Returns a 92-byte JIT sequence that returns `first` in a0 and `second` in a1, where the
+/// calling convention returns aggregates of two 64-bit words.
+pub(crate) fn emit_return_pair(first: u64, second: u64) -> Vec<u8> {
+    let mut asm_code = Vec::with_capacity(92);
+    append_li64(&mut asm_code, A0, first);
+    append_li64(&mut asm_code, A1, second);
+    asm_code.extend(emit_return_void());
+    asm_code
+}
+
+/// Returns a 52-byte JIT sequence that returns the double whose bit pattern is `bits` in
+/// fa0.
+///
+/// The generated instructions are:
+///   li t0, bits
+///   fmv.d.x fa0, t0
+///   ret
+pub(crate) fn emit_return_float(bits: u64) -> Vec<u8> {
+    let mut asm_code = Vec::with_capacity(52);
+    append_li64(&mut asm_code, T0, bits);
+    append_instruction(&mut asm_code, FMV_D_X | (T0 << 15) | (FA0 << 7));
+    asm_code.extend(emit_return_void());
+    asm_code
+}
+
+/// Returns a 56-byte JIT sequence that atomically increments the 64-bit counter at
+/// `counter` and returns.
+///
+/// The generated instructions are:
+///   li t0, counter
+///   li t1, 1
+///   amoadd.d.aqrl zero, t1, (t0)
+///   ret
+pub(crate) fn emit_increment_counter(counter: usize) -> Vec<u8> {
+    const AMOADD_D_AQRL: u32 = (0b11 << 25) | (0b011 << 12) | AMO;
+
+    let mut asm_code = Vec::with_capacity(56);
+    append_li64(&mut asm_code, T0, counter as u64);
+    append_instruction(&mut asm_code, addi(T1, 0, 1));
+    append_instruction(&mut asm_code, AMOADD_D_AQRL | (T1 << 20) | (T0 << 15));
+    asm_code.extend(emit_return_void());
+    asm_code
+}
+
+/// Returns a 160-byte JIT sequence that returns the byte `values[min(n, last)]`, where `n`
+/// is the value of the 64-bit counter at `counter`, which it atomically increments.
+///
+/// The generated instructions are:
+///   li t0, counter
+///   li t1, 1
+///   amoadd.d.aqrl t1, t1, (t0)
+///   li t0, last
+///   bgeu t0, t1, 1f
+///   mv t1, t0
+/// 1:
+///   li t0, values
+///   add t0, t0, t1
+///   lbu a0, 0(t0)
+///   ret
+pub(crate) fn emit_return_byte_sequence(counter: usize, values: usize, last: usize) -> Vec<u8> {
+    const AMOADD_D_AQRL: u32 = (0b11 << 25) | (0b011 << 12) | AMO;
+    const BGEU_T0_T1_PLUS_8: u32 = 0x0062_F463;
+    const ADD_T0_T0_T1: u32 = 0x0062_82B3;
+    const LBU_A0_T0: u32 = 0x0002_C503;
+
+    let mut asm_code = Vec::with_capacity(160);
+    append_li64(&mut asm_code, T0, counter as u64);
+    append_instruction(&mut asm_code, addi(T1, 0, 1));
+    append_instruction(
+        &mut asm_code,
+        AMOADD_D_AQRL | (T1 << 20) | (T0 << 15) | (T1 << 7),
+    );
+    append_li64(&mut asm_code, T0, last as u64);
+    append_instruction(&mut asm_code, BGEU_T0_T1_PLUS_8);
+    append_instruction(&mut asm_code, addi(T1, T0, 0));
+    append_li64(&mut asm_code, T0, values as u64);
+    append_instruction(&mut asm_code, ADD_T0_T0_T1);
+    append_instruction(&mut asm_code, LBU_A0_T0);
+    asm_code.extend(emit_return_void());
+    asm_code
+}
+
+/// Returns a 120-byte JIT sequence that copies the `size` bytes at `value` to the return
+/// slot whose address the caller passed in a0, then returns. `size` must not be zero.
+///
+/// The generated instructions are:
+///   li t0, value
+///   li t1, size
+///   mv t2, a0
+/// 1:
+///   lbu t3, 0(t0)
+///   sb t3, 0(t2)
+///   addi t0, t0, 1
+///   addi t2, t2, 1
+///   addi t1, t1, -1
+///   bnez t1, 1b
+///   ret
+pub(crate) fn emit_return_struct(value: usize, size: usize) -> Vec<u8> {
+    const MV_T2_A0: u32 = 0x0005_0393;
+    const LBU_T3_T0: u32 = 0x0002_CE03;
+    const SB_T3_T2: u32 = 0x01C3_8023;
+    const ADDI_T2_T2_1: u32 = 0x0013_8393;
+    const BNEZ_T1_MINUS_20: u32 = 0xFE03_16E3;
+
+    let mut asm_code = Vec::with_capacity(120);
+    append_li64(&mut asm_code, T0, value as u64);
+    append_li64(&mut asm_code, T1, size as u64);
+    append_instruction(&mut asm_code, MV_T2_A0);
+    append_instruction(&mut asm_code, LBU_T3_T0);
+    append_instruction(&mut asm_code, SB_T3_T2);
+    append_instruction(&mut asm_code, addi(T0, T0, 1));
+    append_instruction(&mut asm_code, ADDI_T2_T2_1);
+    append_instruction(&mut asm_code, addi(T1, T1, -1));
+    append_instruction(&mut asm_code, BNEZ_T1_MINUS_20);
+    asm_code.extend(emit_return_void());
+    asm_code
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn words(code: &[u8]) -> Vec<u32> {
+        code.chunks_exact(4)
+            .map(|bytes| u32::from_le_bytes(bytes.try_into().unwrap()))
+            .collect()
+    }
+
+    /// Runs the `addi`, `ori` and `slli` instructions of `code` and returns the registers.
+    fn run(code: &[u8]) -> [u64; 32] {
+        let mut registers = [0u64; 32];
+
+        for instruction in words(code) {
+            assert_eq!(instruction & 0x7F, OP_IMM);
+            let rd = ((instruction >> 7) & 0x1F) as usize;
+            let rs1 = registers[((instruction >> 15) & 0x1F) as usize];
+            let imm = (instruction as i32 >> 20) as i64 as u64;
+
+            registers[rd] = match (instruction >> 12) & 0x7 {
+                0 => rs1.wrapping_add(imm),
+                1 => rs1 << (imm & 0x3F),
+                6 => rs1 | imm,
+                funct3 => panic!("unexpected funct3 {funct3}"),
+            };
+        }
+
+        registers
+    }
+
+    #[test]
+    fn test_append_li64_loads_every_value() {
+        for value in [
+            0,
+            1,
+            0x7FF,
+            0x800,
+            u64::MAX,
+            i64::MIN as u64,
+            0x1234_5678_9ABC_DEF0,
+            0x0000_7FFF_DEAD_B000,
+        ] {
+            let mut code = Vec::new();
+            append_li64(&mut code, T0, value);
+
+            assert_eq!(code.len(), 44);
+            assert_eq!(run(&code)[T0 as usize], value, "{value:#x}");
+        }
+    }
+
+    #[test]
+    fn test_emit_return_encodings() {
+        // ret
+        assert_eq!(words(&emit_return_void()), [0x0000_8067]);
+        // li a0, 1; ret
+        assert_eq!(
+            words(&emit_return_boolean(true)),
+            [0x0010_0513, 0x0000_8067]
+        );
+        // li a0, 0; ret
+        assert_eq!(
+            words(&emit_return_boolean(false)),
+            [0x0000_0513, 0x0000_8067]
+        );
+
+        let code = emit_return_float(1.5f64.to_bits());
+        // fmv.d.x fa0, t0; ret
+        assert_eq!(words(&code[44..]), [0xF202_8553, 0x0000_8067]);
+        assert_eq!(run(&code[..44])[T0 as usize], 1.5f64.to_bits());
+    }
+
+    #[test]
+    fn test_emit_return_integer_and_pair_load_return_registers() {
+        let code = emit_return_pair(0x1234_5678_9ABC_DEF0, u64::MAX);
+        let registers = run(&code[..88]);
+
+        assert_eq!(registers[A0 as usize], 0x1234_5678_9ABC_DEF0);
+        assert_eq!(registers[A1 as usize], u64::MAX);
+        assert_eq!(words(&code[88..]), [0x0000_8067]);
+
+        let code = emit_return_integer(-2i64 as u64);
+        assert_eq!(run(&code[..44])[A0 as usize], -2i64 as u64);
+    }
+
+    #[test]
+    fn test_emit_increment_counter_encoding() {
+        let code = emit_increment_counter(0x1000);
+
+        assert_eq!(run(&code[..44])[T0 as usize], 0x1000);
+        // li t1, 1; amoadd.d.aqrl zero, t1, (t0); ret
+        assert_eq!(words(&code[44..]), [0x0010_0313, 0x0662_B02F, 0x0000_8067]);
+    }
+
+    #[test]
+    fn test_emit_return_byte_sequence_encoding() {
+        let code = emit_return_byte_sequence(0x1000, 0x2000, 2);
+
+        assert_eq!(code.len(), 160);
+        assert_eq!(run(&code[..44])[T0 as usize], 0x1000);
+        // li t1, 1; amoadd.d.aqrl t1, t1, (t0)
+        assert_eq!(words(&code[44..52]), [0x0010_0313, 0x0662_B32F]);
+        assert_eq!(run(&code[52..96])[T0 as usize], 2);
+        // bgeu t0, t1, 8; mv t1, t0
+        assert_eq!(words(&code[96..104]), [0x0062_F463, 0x0002_8313]);
+        assert_eq!(run(&code[104..148])[T0 as usize], 0x2000);
+        // add t0, t0, t1; lbu a0, 0(t0); ret
+        assert_eq!(words(&code[148..]), [0x0062_82B3, 0x0002_C503, 0x0000_8067]);
+    }
+
+    #[test]
+    fn test_emit_return_struct_encoding() {
+        let code = emit_return_struct(0x1000, 64);
+
+        assert_eq!(code.len(), 120);
+        assert_eq!(run(&code[..44])[T0 as usize], 0x1000);
+        assert_eq!(run(&code[44..88])[T1 as usize], 64);
+        // mv t2, a0; lbu t3, 0(t0); sb t3, 0(t2); addi t0, t0, 1; addi t2, t2, 1;
+        // addi t1, t1, -1; bnez t1, -20; ret
+        assert_eq!(
+            words(&code[88..]),
+            [
+                0x0005_0393,
+                0x0002_CE03,
+                0x01C3_8023,
+                0x0012_8293,
+                0x0013_8393,
+                0xFFF3_0313,
+                0xFE03_16E3,
+                0x0000_8067
+            ]
+        );
+    }
+
+    #[test]
+    fn test_emit_abs_jump_encoding() {
+        let code = emit_abs_jump(0x0000_7FFF_1234_5678);
+
+        assert_eq!(run(&code[..44])[T0 as usize], 0x0000_7FFF_1234_5678);
+        // jr t0
+        assert_eq!(words(&code[44..]), [0x0002_8067]);
+    }
+
+    #[test]
+    fn test_emit_pc_relative_jump_rounds_and_limits_offset() {
+        // auipc t0, 0x1; jr 0(t0)
+        assert_eq!(
+            words(&emit_pc_relative_jump(0x1000, 0x2000).unwrap()),
+            [0x0000_1297, 0x0002_8067]
+        );
+        // auipc t0, 0x1; jr -2048(t0)
+        assert_eq!(
+            words(&emit_pc_relative_jump(0x1000, 0x1800).unwrap()),
+            [0x0000_1297, 0x8002_8067]
+        );
+        // auipc t0, 0xfffff; jr -4(t0)
+        assert_eq!(
+            words(&emit_pc_relative_jump(0x2000, 0xFFC).unwrap()),
+            [0xFFFF_F297, 0xFFC2_8067]
+        );
+
+        assert!(emit_pc_relative_jump(0x1000_0000, 0x1000_0000 + 0x7FFF_F7FF).is_some());
+        assert!(emit_pc_relative_jump(0x1000_0000, 0x1000_0000 + 0x7FFF_F800).is_none());
+        assert!(emit_pc_relative_jump(0x9000_0000, 0x9000_0000 - 0x8000_0800).is_some());
+        assert!(emit_pc_relative_jump(0x9000_0000, 0x9000_0000 - 0x8000_0801).is_none());
+    }
+}
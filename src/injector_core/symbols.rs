@@ -0,0 +1,531 @@
+use crate::injector_core::common::{try_read_bytes, FuncPtrInternal};
+use std::ffi::CString;
+use std::ptr::NonNull;
+
+#[cfg(target_os = "windows")]
+use crate::injector_core::winapi::*;
+
+/// Resolves `name` to the address of a function loaded in the current process.
+///
+/// The dynamic loader is asked first, which finds symbols exported by the executable and the
+/// shared libraries it loaded. On Linux the static symbol table of the running executable is
+/// searched as well, because functions of the executable itself, including `#[no_mangle]` and
+/// `#[export_name]` ones, are usually not exported dynamically.
+///
+/// Returns `None` if the symbol cannot be found.
+pub(crate) fn resolve_symbol(name: &str) -> Option<FuncPtrInternal> {
+    let address = resolve_dynamic_symbol(name).or_else(|| resolve_executable_symbol(name))?;
+    let non_null = NonNull::new(address as *mut ())?;
+
+    Some(unsafe { FuncPtrInternal::new(non_null) })
+}
+
+/// Resolves `name` to the address of a function exported by a shared library loaded in the
+/// current process, such as a function of the C library.
+///
+/// Only the dynamic loader is asked, searching every loaded library. When the address it
+/// returns is an import stub, a PLT entry or an import thunk that only jumps through a pointer
+/// slot, the slot is followed to the actual implementation, so patching the result affects
+/// every caller and not only those going through that particular stub.
+///
+/// Returns `None` if the symbol cannot be found.
+pub(crate) fn resolve_library_symbol(name: &str) -> Option<FuncPtrInternal> {
+    let mut address = resolve_library_export(name)? as usize;
+
+    // Stubs never chain deeply, the limit only guards against a slot pointing back at a stub.
+    for _ in 0..4 {
+        let Ok(code) = try_read_bytes(address as *const u8, MAX_THUNK_SIZE) else {
+            break;
+        };
+        let Some(slot) = jump_thunk_slot(&code, address) else {
+            break;
+        };
+        let Ok(slot) = try_read_bytes(slot as *const u8, std::mem::size_of::<usize>()) else {
+            break;
+        };
+
+        let target = usize::from_ne_bytes(slot.try_into().unwrap());
+
+        // A slot that is not bound yet points back into the stub to reach the lazy resolver.
+        if target == 0 || (address..address + MAX_THUNK_SIZE).contains(&target) {
+            break;
+        }
+
+        address = target;
+    }
+
+    let non_null = NonNull::new(address as *mut ())?;
+
+    Some(unsafe { FuncPtrInternal::new(non_null) })
+}
+
+/// Resolves `name` to the address of a function exported by the library behind `handle`,
+/// as returned by `dlopen` on Linux and macOS or `LoadLibrary` on Windows.
+///
+/// Only that library is asked, with `dlsym` or `GetProcAddress`, so a function of the same
+/// name in another library or in the executable is never returned.
+///
+/// Returns `None` if the library does not export the symbol.
+pub(crate) fn resolve_symbol_in_library(
+    handle: *mut core::ffi::c_void,
+    name: &str,
+) -> Option<FuncPtrInternal> {
+    let c_name = CString::new(name).ok()?;
+
+    #[cfg(any(target_os = "linux", target_os = "macos"))]
+    let address = unsafe { libc::dlsym(handle, c_name.as_ptr()) };
+
+    #[cfg(target_os = "windows")]
+    let address = unsafe { GetProcAddress(handle, c_name.as_ptr()) };
+
+    let non_null = NonNull::new(address as *mut ())?;
+
+    Some(unsafe { FuncPtrInternal::new(non_null) })
+}
+
+#[cfg(any(target_os = "linux", target_os = "macos"))]
+fn resolve_library_export(name: &str) -> Option<*const ()> {
+    resolve_dynamic_symbol(name)
+}
+
+/// Asks every module of the process, the executable first, for an export named `name`.
+#[cfg(target_os = "windows")]
+fn resolve_library_export(name: &str) -> Option<*const ()> {
+    let c_name = CString::new(name).ok()?;
+
+    let mut modules: Vec<*mut core::ffi::c_void> = Vec::new();
+    let mut needed: u32 = 0;
+    loop {
+        let capacity = (modules.capacity() * std::mem::size_of::<*mut core::ffi::c_void>()) as u32;
+        let ok = unsafe {
+            K32EnumProcessModules(
+                GetCurrentProcess(),
+                modules.as_mut_ptr(),
+                capacity,
+                &mut needed,
+            )
+        };
+        if ok == 0 {
+            return resolve_dynamic_symbol(name);
+        }
+
+        let count = needed as usize / std::mem::size_of::<*mut core::ffi::c_void>();
+        if count <= modules.capacity() {
+            unsafe { modules.set_len(count) };
+            break;
+        }
+
+        modules.reserve(count);
+    }
+
+    modules.into_iter().find_map(|module| {
+        let address = unsafe { GetProcAddress(module, c_name.as_ptr()) };
+        (!address.is_null()).then_some(address as *const ())
+    })
+}
+
+/// The largest number of bytes `jump_thunk_slot` looks at.
+const MAX_THUNK_SIZE: usize = 16;
+
+/// Returns the address of the pointer slot the stub at `address` jumps through, or `None` if
+/// `code`, the bytes at `address`, does not start with such a stub.
+fn jump_thunk_slot(code: &[u8], address: usize) -> Option<usize> {
+    #[cfg(target_arch = "x86_64")]
+    return amd64_jump_thunk_slot(code, address);
+
+    #[cfg(target_arch = "aarch64")]
+    return arm64_jump_thunk_slot(code, address);
+
+    #[cfg(target_arch = "riscv64")]
+    return riscv64_jump_thunk_slot(code, address);
+
+    #[cfg(not(any(
+        target_arch = "x86_64",
+        target_arch = "aarch64",
+        target_arch = "riscv64"
+    )))]
+    {
+        let _ = (code, address);
+        None
+    }
+}
+
+/// Recognizes `jmp [rip + disp32]`, optionally after `endbr64`, `bnd` or `rex.w` as written
+/// by linkers for PLT entries and by Windows for import thunks.
+#[cfg_attr(not(target_arch = "x86_64"), allow(dead_code))]
+fn amd64_jump_thunk_slot(code: &[u8], address: usize) -> Option<usize> {
+    let mut offset = 0;
+    if code.starts_with(&[0xF3, 0x0F, 0x1E, 0xFA]) {
+        offset += 4;
+    }
+    if matches!(code.get(offset), Some(0xF2 | 0x48)) {
+        offset += 1;
+    }
+
+    if code.get(offset..offset + 2)? != [0xFF, 0x25] {
+        return None;
+    }
+
+    let disp = i32::from_le_bytes(code.get(offset + 2..offset + 6)?.try_into().ok()?);
+    let next = address + offset + 6;
+
+    Some(next.wrapping_add_signed(disp as isize))
+}
+
+/// Recognizes `adrp xA, page; ldr xB, [xA, #off]; [add xA, xA, #off;] br xB`, optionally after
+/// `bti c`, as written by linkers for PLT entries and stubs and by Windows for import thunks.
+#[cfg_attr(not(target_arch = "aarch64"), allow(dead_code))]
+fn arm64_jump_thunk_slot(code: &[u8], address: usize) -> Option<usize> {
+    const BTI_C: u32 = 0xD503245F;
+
+    let word = |index: usize| -> Option<u32> {
+        Some(u32::from_le_bytes(
+            code.get(index * 4..index * 4 + 4)?.try_into().ok()?,
+        ))
+    };
+
+    let mut index = 0;
+    if word(0)? == BTI_C {
+        index += 1;
+    }
+
+    let adrp = word(index)?;
+    if adrp & 0x9F00_0000 != 0x9000_0000 {
+        return None;
+    }
+    let base_reg = adrp & 0x1F;
+    let immhi = ((adrp >> 5) & 0x7FFFF) as i64;
+    let immlo = ((adrp >> 29) & 0x3) as i64;
+    let pages = ((immhi << 2 | immlo) << 43) >> 43;
+    let page = ((address + index * 4) & !0xFFF).wrapping_add_signed((pages << 12) as isize);
+
+    let ldr = word(index + 1)?;
+    if ldr & 0xFFC0_0000 != 0xF940_0000 || (ldr >> 5) & 0x1F != base_reg {
+        return None;
+    }
+    let target_reg = ldr & 0x1F;
+    let slot = page + (((ldr >> 10) & 0xFFF) as usize) * 8;
+
+    let mut br = word(index + 2)?;
+    if br & 0xFF80_0000 == 0x9100_0000 {
+        br = word(index + 3)?;
+    }
+    if br != 0xD61F_0000 | (target_reg << 5) {
+        return None;
+    }
+
+    Some(slot)
+}
+
+/// Recognizes `auipc tA, hi; ld tB, lo(tA); jalr tC, tB`, as written by linkers for PLT
+/// entries.
+#[cfg_attr(not(target_arch = "riscv64"), allow(dead_code))]
+fn riscv64_jump_thunk_slot(code: &[u8], address: usize) -> Option<usize> {
+    let word = |index: usize| -> Option<u32> {
+        Some(u32::from_le_bytes(
+            code.get(index * 4..index * 4 + 4)?.try_into().ok()?,
+        ))
+    };
+
+    let auipc = word(0)?;
+    if auipc & 0x7F != 0x17 {
+        return None;
+    }
+    let base_reg = (auipc >> 7) & 0x1F;
+    let hi = (auipc & 0xFFFF_F000) as i32 as isize;
+
+    let ld = word(1)?;
+    if ld & 0x707F != 0x3003 || (ld >> 15) & 0x1F != base_reg {
+        return None;
+    }
+    let target_reg = (ld >> 7) & 0x1F;
+    let lo = (ld as i32 >> 20) as isize;
+
+    let jalr = word(2)?;
+    if jalr & 0xFFF0_707F != 0x0000_0067 || (jalr >> 15) & 0x1F != target_reg {
+        return None;
+    }
+
+    Some(address.wrapping_add_signed(hi + lo))
+}
+
+#[cfg(any(target_os = "linux", target_os = "macos"))]
+fn resolve_dynamic_symbol(name: &str) -> Option<*const ()> {
+    let c_name = CString::new(name).ok()?;
+    let address = unsafe { libc::dlsym(libc::RTLD_DEFAULT, c_name.as_ptr()) };
+
+    (!address.is_null()).then_some(address as *const ())
+}
+
+#[cfg(target_os = "windows")]
+fn resolve_dynamic_symbol(name: &str) -> Option<*const ()> {
+    let c_name = CString::new(name).ok()?;
+    let address = unsafe {
+        let module = GetModuleHandleW(std::ptr::null());
+        GetProcAddress(module, c_name.as_ptr())
+    };
+
+    (!address.is_null()).then_some(address as *const ())
+}
+
+#[cfg(not(target_os = "linux"))]
+fn resolve_executable_symbol(_name: &str) -> Option<*const ()> {
+    None
+}
+
+/// Looks `name` up in the static symbol table of `/proc/self/exe` and relocates it by the
+/// load bias of the main program.
+#[cfg(target_os = "linux")]
+fn resolve_executable_symbol(name: &str) -> Option<*const ()> {
+    let image = std::fs::read("/proc/self/exe").ok()?;
+    let value = ElfImage::parse(&image)?.find_function(name)?;
+
+    Some((main_program_load_bias() + value as usize) as *const ())
+}
+
+/// Returns the difference between the addresses the main program was linked at and loaded at.
+#[cfg(target_os = "linux")]
+fn main_program_load_bias() -> usize {
+    unsafe extern "C" fn first_object(
+        info: *mut libc::dl_phdr_info,
+        _size: usize,
+        data: *mut libc::c_void,
+    ) -> libc::c_int {
+        *(data as *mut usize) = (*info).dlpi_addr as usize;
+
+        // The main program is always reported first, stop right away.
+        1
+    }
+
+    let mut bias: usize = 0;
+    unsafe {
+        libc::dl_iterate_phdr(
+            Some(first_object),
+            &mut bias as *mut usize as *mut libc::c_void,
+        );
+    }
+
+    bias
+}
+
+/// A minimal little-endian ELF reader that only knows how to walk symbol tables.
+#[cfg(target_os = "linux")]
+struct ElfImage<'a> {
+    data: &'a [u8],
+    is_64: bool,
+}
+
+#[cfg(target_os = "linux")]
+impl<'a> ElfImage<'a> {
+    const SHT_SYMTAB: u32 = 2;
+    const SHT_DYNSYM: u32 = 11;
+    const STT_FUNC: u8 = 2;
+
+    fn parse(data: &'a [u8]) -> Option<Self> {
+        if data.get(0..4)? != b"\x7fELF" {
+            return None;
+        }
+
+        let is_64 = match data.get(4)? {
+            1 => false,
+            2 => true,
+            _ => return None,
+        };
+
+        // Only little-endian images are supported.
+        if *data.get(5)? != 1 {
+            return None;
+        }
+
+        Some(Self { data, is_64 })
+    }
+
+    /// Returns the link-time address of the defined function named `name`.
+    fn find_function(&self, name: &str) -> Option<u64> {
+        let (shoff, shentsize, shnum) = if self.is_64 {
+            (self.u64_at(0x28)?, self.u16_at(0x3A)?, self.u16_at(0x3C)?)
+        } else {
+            (
+                self.u32_at(0x20)? as u64,
+                self.u16_at(0x2E)?,
+                self.u16_at(0x30)?,
+            )
+        };
+
+        let section = |index: u16| -> Option<usize> {
+            usize::try_from(shoff + index as u64 * shentsize as u64).ok()
+        };
+
+        // Prefer the full symbol table and fall back to the dynamic one for stripped binaries.
+        for wanted_type in [Self::SHT_SYMTAB, Self::SHT_DYNSYM] {
+            for index in 0..shnum {
+                let header = section(index)?;
+                if self.u32_at(header + 4)? != wanted_type {
+                    continue;
+                }
+
+                let (offset, size, link, entsize) = if self.is_64 {
+                    (
+                        self.u64_at(header + 0x18)?,
+                        self.u64_at(header + 0x20)?,
+                        self.u32_at(header + 0x28)?,
+                        self.u64_at(header + 0x38)?,
+                    )
+                } else {
+                    (
+                        self.u32_at(header + 0x10)? as u64,
+                        self.u32_at(header + 0x14)? as u64,
+                        self.u32_at(header + 0x18)?,
+                        self.u32_at(header + 0x24)? as u64,
+                    )
+                };
+
+                let strtab_header = section(u16::try_from(link).ok()?)?;
+                let strtab = if self.is_64 {
+                    self.u64_at(strtab_header + 0x18)?
+                } else {
+                    self.u32_at(strtab_header + 0x10)? as u64
+                };
+
+                if entsize == 0 {
+                    continue;
+                }
+
+                for symbol in (offset..offset + size).step_by(entsize as usize) {
+                    if let Some(value) = self.match_function(symbol as usize, strtab as usize, name)
+                    {
+                        return Some(value);
+                    }
+                }
+            }
+        }
+
+        None
+    }
+
+    /// Returns the value of the symbol at `symbol` if it is a defined function named `name`.
+    fn match_function(&self, symbol: usize, strtab: usize, name: &str) -> Option<u64> {
+        let (name_offset, info, shndx, value) = if self.is_64 {
+            (
+                self.u32_at(symbol)?,
+                *self.data.get(symbol + 4)?,
+                self.u16_at(symbol + 6)?,
+                self.u64_at(symbol + 8)?,
+            )
+        } else {
+            (
+                self.u32_at(symbol)?,
+                *self.data.get(symbol + 12)?,
+                self.u16_at(symbol + 14)?,
+                self.u32_at(symbol + 4)? as u64,
+            )
+        };
+
+        if info & 0xF != Self::STT_FUNC || shndx == 0 {
+            return None;
+        }
+
+        let start = strtab + name_offset as usize;
+        let symbol_name = self.data.get(start..start + name.len() + 1)?;
+        (symbol_name[..name.len()] == *name.as_bytes() && symbol_name[name.len()] == 0)
+            .then_some(value)
+    }
+
+    fn u16_at(&self, offset: usize) -> Option<u16> {
+        Some(u16::from_le_bytes(
+            self.data.get(offset..offset + 2)?.try_into().ok()?,
+        ))
+    }
+
+    fn u32_at(&self, offset: usize) -> Option<u32> {
+        Some(u32::from_le_bytes(
+            self.data.get(offset..offset + 4)?.try_into().ok()?,
+        ))
+    }
+
+    fn u64_at(&self, offset: usize) -> Option<u64> {
+        Some(u64::from_le_bytes(
+            self.data.get(offset..offset + 8)?.try_into().ok()?,
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_amd64_jump_thunk_slot_should_decode_plt_and_import_thunks() {
+        // jmp [rip + 0x10]
+        let plt = [0xFF, 0x25, 0x10, 0x00, 0x00, 0x00];
+        assert_eq!(amd64_jump_thunk_slot(&plt, 0x1000), Some(0x1016));
+
+        // endbr64; bnd jmp [rip - 0x20]
+        let plt_sec = [
+            0xF3, 0x0F, 0x1E, 0xFA, 0xF2, 0xFF, 0x25, 0xE0, 0xFF, 0xFF, 0xFF,
+        ];
+        assert_eq!(amd64_jump_thunk_slot(&plt_sec, 0x1000), Some(0x0FEB));
+
+        // rex.w jmp [rip + 0x100]
+        let import = [0x48, 0xFF, 0x25, 0x00, 0x01, 0x00, 0x00];
+        assert_eq!(amd64_jump_thunk_slot(&import, 0x1000), Some(0x1107));
+    }
+
+    #[test]
+    fn test_amd64_jump_thunk_slot_when_function_should_return_none() {
+        // mov eax, 39; syscall; ret
+        let getpid = [0xB8, 0x27, 0x00, 0x00, 0x00, 0x0F, 0x05, 0xC3];
+        assert_eq!(amd64_jump_thunk_slot(&getpid, 0x1000), None);
+
+        // jmp rel32 is a direct branch, not a stub jumping through a slot.
+        let jump = [0xE9, 0x10, 0x00, 0x00, 0x00];
+        assert_eq!(amd64_jump_thunk_slot(&jump, 0x1000), None);
+    }
+
+    #[test]
+    fn test_arm64_jump_thunk_slot_should_decode_plt_and_import_thunks() {
+        // bti c; adrp x16, #0x2000; ldr x17, [x16, #24]; add x16, x16, #24; br x17
+        let plt = [
+            0x5F, 0x24, 0x03, 0xD5, 0x10, 0x00, 0x00, 0xD0, 0x11, 0x0E, 0x40, 0xF9, 0x10, 0x62,
+            0x00, 0x91, 0x20, 0x02, 0x1F, 0xD6,
+        ];
+        assert_eq!(arm64_jump_thunk_slot(&plt, 0x10010), Some(0x12018));
+
+        // adrp x16, #-0x1000; ldr x16, [x16, #576]; br x16
+        let import = [
+            0xF0, 0xFF, 0xFF, 0xF0, 0x10, 0x22, 0x41, 0xF9, 0x00, 0x02, 0x1F, 0xD6,
+        ];
+        assert_eq!(arm64_jump_thunk_slot(&import, 0x10000), Some(0xF240));
+    }
+
+    #[test]
+    fn test_arm64_jump_thunk_slot_when_branch_register_differs_should_return_none() {
+        // adrp x16, #0x2000; ldr x17, [x16, #24]; br x16
+        let code = [
+            0x10, 0x00, 0x00, 0xD0, 0x11, 0x0E, 0x40, 0xF9, 0x00, 0x02, 0x1F, 0xD6,
+        ];
+        assert_eq!(arm64_jump_thunk_slot(&code, 0x10000), None);
+
+        // ret
+        assert_eq!(
+            arm64_jump_thunk_slot(&[0xC0, 0x03, 0x5F, 0xD6], 0x10000),
+            None
+        );
+    }
+
+    #[test]
+    fn test_riscv64_jump_thunk_slot_should_decode_plt_entry() {
+        // auipc t3, 2; ld t3, -16(t3); jalr t1, t3; nop
+        let plt = [
+            0x17, 0x2E, 0x00, 0x00, 0x03, 0x3E, 0x0E, 0xFF, 0x67, 0x03, 0x0E, 0x00, 0x13, 0x00,
+            0x00, 0x00,
+        ];
+        assert_eq!(riscv64_jump_thunk_slot(&plt, 0x10000), Some(0x11FF0));
+
+        // addi sp, sp, -16
+        assert_eq!(
+            riscv64_jump_thunk_slot(&[0x13, 0x01, 0x01, 0xFF], 0x10000),
+            None
+        );
+    }
+}
@@ -1,4 +1,4 @@
-#![cfg(target_arch = "aarch64")]
+#![cfg_attr(not(target_arch = "aarch64"), allow(dead_code))]
 
 /// Convert a u64 value into a [bool; 64] array of bits.
 /// Bit 0 is the least-significant bit.
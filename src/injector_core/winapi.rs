@@ -1,11 +1,31 @@
 #![cfg(target_os = "windows")]
 
+use core::ffi::c_char;
 use core::ffi::c_void;
 
 pub(crate) const MEM_COMMIT: u32 = 0x1000;
 pub(crate) const MEM_RESERVE: u32 = 0x2000;
+pub(crate) const PAGE_READWRITE: u32 = 0x04;
+pub(crate) const PAGE_EXECUTE: u32 = 0x10;
+pub(crate) const PAGE_EXECUTE_READ: u32 = 0x20;
 pub(crate) const PAGE_EXECUTE_READWRITE: u32 = 0x40;
+pub(crate) const PAGE_EXECUTE_WRITECOPY: u32 = 0x80;
 pub(crate) const MEM_RELEASE: u32 = 0x8000;
+pub(crate) const PAGE_NOACCESS: u32 = 0x01;
+pub(crate) const PAGE_GUARD: u32 = 0x100;
+
+#[repr(C)]
+pub(crate) struct MemoryBasicInformation {
+    pub(crate) base_address: *mut c_void,
+    pub(crate) allocation_base: *mut c_void,
+    pub(crate) allocation_protect: u32,
+    #[cfg(target_pointer_width = "64")]
+    pub(crate) partition_id: u16,
+    pub(crate) region_size: usize,
+    pub(crate) state: u32,
+    pub(crate) protect: u32,
+    pub(crate) type_: u32,
+}
 
 #[repr(C)]
 struct SystemInfo {
@@ -22,6 +42,15 @@ struct SystemInfo {
     w_processor_revision: u16,
 }
 
+/// An entry of a function table, with addresses relative to the base of the table.
+#[cfg(target_arch = "x86_64")]
+#[repr(C)]
+pub(crate) struct RuntimeFunction {
+    pub(crate) begin_address: u32,
+    pub(crate) end_address: u32,
+    pub(crate) unwind_data: u32,
+}
+
 extern "system" {
     pub(crate) fn VirtualProtect(
         lpAddress: *mut c_void,
@@ -37,6 +66,12 @@ extern "system" {
         flProtect: u32,
     ) -> *mut c_void;
 
+    pub(crate) fn VirtualQuery(
+        lpAddress: *const c_void,
+        lpBuffer: *mut MemoryBasicInformation,
+        dwLength: usize,
+    ) -> usize;
+
     pub(crate) fn VirtualFree(lpAddress: *mut c_void, dwSize: usize, dwFreeType: u32) -> i32;
 
     pub(crate) fn FlushInstructionCache(
@@ -47,7 +82,28 @@ extern "system" {
 
     pub(crate) fn GetCurrentProcess() -> *mut c_void;
 
+    pub(crate) fn GetModuleHandleW(lpModuleName: *const u16) -> *mut c_void;
+
+    pub(crate) fn GetProcAddress(hModule: *mut c_void, lpProcName: *const c_char) -> *mut c_void;
+
+    pub(crate) fn K32EnumProcessModules(
+        hProcess: *mut c_void,
+        lphModule: *mut *mut c_void,
+        cb: u32,
+        lpcbNeeded: *mut u32,
+    ) -> i32;
+
     fn GetSystemInfo(lpSystemInfo: *mut SystemInfo);
+
+    #[cfg(target_arch = "x86_64")]
+    pub(crate) fn RtlAddFunctionTable(
+        FunctionTable: *const RuntimeFunction,
+        EntryCount: u32,
+        BaseAddress: u64,
+    ) -> u8;
+
+    #[cfg(target_arch = "x86_64")]
+    pub(crate) fn RtlDeleteFunctionTable(FunctionTable: *const RuntimeFunction) -> u8;
 }
 
 pub(crate) unsafe fn get_page_size() -> usize {
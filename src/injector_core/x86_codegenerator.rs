@@ -0,0 +1,111 @@
+//! Encoders for the 32-bit x86 (i686) instructions used by the patches.
+//!
+//! Every function only builds bytes and never executes them, so this module is compiled and
+//! tested on every host.
+#![cfg_attr(not(target_arch = "x86"), allow(dead_code))]
+
+/// Opcode constants for x86 jump and move instructions.
+const JMP_REL_OPCODE: u8 = 0xE9;
+const PUSH_IMM32_OPCODE: u8 = 0x68;
+const MOV_EAX_OPCODE: u8 = 0xB8;
+const MOV_EDX_OPCODE: u8 = 0xBA;
+const RET_OPCODE: u8 = 0xC3;
+
+/// The size of a `jmp rel32`.
+pub(crate) const JMP_REL32_SIZE: usize = 5;
+
+/// Returns a `jmp rel32` placed at `from` that lands on `to`.
+///
+/// The displacement wraps around the 4GB address space, so every address is in range.
+pub(crate) fn emit_jmp_rel32(from: u32, to: u32) -> [u8; JMP_REL32_SIZE] {
+    let offset = to.wrapping_sub(from.wrapping_add(JMP_REL32_SIZE as u32));
+
+    let mut branch_code = [0u8; JMP_REL32_SIZE];
+    branch_code[0] = JMP_REL_OPCODE;
+    branch_code[1..].copy_from_slice(&offset.to_le_bytes());
+    branch_code
+}
+
+/// Returns a position independent jump to the absolute address `target`, through the stack,
+/// so no register is clobbered.
+pub(crate) fn emit_abs_jump(target: u32) -> Vec<u8> {
+    let mut branch_code = Vec::with_capacity(6);
+    branch_code.push(PUSH_IMM32_OPCODE);
+    branch_code.extend_from_slice(&target.to_le_bytes());
+    branch_code.push(RET_OPCODE);
+    branch_code
+}
+
+/// Returns a return-boolean JIT sequence, `mov eax, imm32; ret`.
+///
+/// The whole of eax is written, so callers testing only al and callers testing the whole
+/// register agree.
+pub(crate) fn emit_return_boolean(value: bool) -> Vec<u8> {
+    let mut asm_code = Vec::with_capacity(6);
+    asm_code.push(MOV_EAX_OPCODE);
+    asm_code.extend_from_slice(&(value as u32).to_le_bytes());
+    asm_code.push(RET_OPCODE);
+    asm_code
+}
+
+/// Returns a JIT sequence returning the 64-bit `value` in edx:eax, as cdecl does, which also
+/// returns smaller integers in eax.
+pub(crate) fn emit_return_integer(value: u64) -> Vec<u8> {
+    let mut asm_code = Vec::with_capacity(11);
+    asm_code.push(MOV_EAX_OPCODE);
+    asm_code.extend_from_slice(&(value as u32).to_le_bytes());
+    asm_code.push(MOV_EDX_OPCODE);
+    asm_code.extend_from_slice(&((value >> 32) as u32).to_le_bytes());
+    asm_code.push(RET_OPCODE);
+    asm_code
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_emit_jmp_rel32_forward_and_backward() {
+        assert_eq!(
+            emit_jmp_rel32(0x1000, 0x2000),
+            [0xE9, 0xFB, 0x0F, 0x00, 0x00]
+        );
+        assert_eq!(
+            emit_jmp_rel32(0x2000, 0x1000),
+            [0xE9, 0xFB, 0xEF, 0xFF, 0xFF]
+        );
+    }
+
+    #[test]
+    fn test_emit_jmp_rel32_across_the_address_space_should_wrap_around() {
+        // From the top of the address space to its bottom, and back.
+        assert_eq!(
+            emit_jmp_rel32(0xFFFF_F000, 0x1000),
+            [0xE9, 0xFB, 0x1F, 0x00, 0x00]
+        );
+        assert_eq!(
+            emit_jmp_rel32(0x1000, 0xFFFF_F000),
+            [0xE9, 0xFB, 0xDF, 0xFF, 0xFF]
+        );
+    }
+
+    #[test]
+    fn test_emit_return_encodings() {
+        assert_eq!(
+            emit_abs_jump(0x1234_5678),
+            vec![0x68, 0x78, 0x56, 0x34, 0x12, 0xC3]
+        );
+        assert_eq!(
+            emit_return_boolean(true),
+            vec![0xB8, 0x01, 0x00, 0x00, 0x00, 0xC3]
+        );
+        assert_eq!(
+            emit_return_boolean(false),
+            vec![0xB8, 0x00, 0x00, 0x00, 0x00, 0xC3]
+        );
+        assert_eq!(
+            emit_return_integer(0x1234_5678_9ABC_DEF0),
+            vec![0xB8, 0xF0, 0xDE, 0xBC, 0x9A, 0xBA, 0x78, 0x56, 0x34, 0x12, 0xC3]
+        );
+    }
+}
@@ -1,4 +1,19 @@
+mod callback;
+mod capture;
+pub(crate) mod debug;
+mod delay;
+pub(crate) mod error;
+pub(crate) mod failure;
 mod func_ptr;
 pub mod injector;
+mod into_fake;
+mod into_map;
+mod lock;
 mod macros;
+pub(crate) mod options;
+mod register_pair;
+pub(crate) mod restore;
+mod return_value;
+mod sequence;
+mod spy;
 mod verifier;
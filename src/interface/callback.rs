@@ -0,0 +1,123 @@
+/// The most arguments `will_invoke_callback` can pass to a callback.
+pub(crate) const MAX_CALLBACK_ARGUMENTS: usize = 6;
+
+/// How a callback of `will_invoke_callback` is called.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub(crate) enum CallbackAbi {
+    /// A `fn` pointer.
+    Rust,
+    /// An `extern "C" fn` pointer, and any callback of a target without a signature.
+    C,
+}
+
+/// The data a patched function hands to `invoke_callback`.
+pub(crate) struct CallbackInvocations {
+    /// Where the callback is found in the saved registers, see `WhenCalled::argument_slot`.
+    pub(crate) slot: usize,
+    pub(crate) abi: CallbackAbi,
+    /// The arguments of each call, in order.
+    pub(crate) calls: Vec<Vec<u64>>,
+}
+
+/// Calls `$callback` as a function of the ABI `$abi` taking exactly the values of `$args`.
+macro_rules! call_with_exact_arguments {
+    ($abi:literal, $callback:expr, $args:expr) => {
+        unsafe {
+            use std::mem::transmute;
+
+            match *$args {
+                [] => transmute::<*const (), extern $abi fn()>($callback)(),
+                [a] => transmute::<*const (), extern $abi fn(u64)>($callback)(a),
+                [a, b] => transmute::<*const (), extern $abi fn(u64, u64)>($callback)(a, b),
+                [a, b, c] => {
+                    transmute::<*const (), extern $abi fn(u64, u64, u64)>($callback)(a, b, c)
+                }
+                [a, b, c, d] => transmute::<*const (), extern $abi fn(u64, u64, u64, u64)>(
+                    $callback,
+                )(a, b, c, d),
+                [a, b, c, d, e] => transmute::<
+                    *const (),
+                    extern $abi fn(u64, u64, u64, u64, u64),
+                >($callback)(a, b, c, d, e),
+                [a, b, c, d, e, f] => transmute::<
+                    *const (),
+                    extern $abi fn(u64, u64, u64, u64, u64, u64),
+                >($callback)(a, b, c, d, e, f),
+                _ => unreachable!("checked by will_invoke_callback"),
+            }
+        }
+    };
+}
+
+/// Called from the JIT block of a function faked with `will_invoke_callback`.
+pub(crate) extern "C-unwind" fn invoke_callback(data: *const (), registers: *const u64) {
+    let invocations = unsafe { &*(data as *const CallbackInvocations) };
+    let callback = unsafe { *registers.add(invocations.slot) } as *const ();
+
+    for args in &invocations.calls {
+        match invocations.abi {
+            CallbackAbi::Rust => call_with_exact_arguments!("Rust", callback, args.as_slice()),
+            CallbackAbi::C => call_with_exact_arguments!("C", callback, args.as_slice()),
+        }
+    }
+}
+
+/// Returns how the argument number `arg_index` of the function type `signature`, as
+/// rendered by `std::any::type_name`, is called and how many parameters it takes, or `None`
+/// if it is not a `fn` or `extern "C" fn` pointer.
+pub(crate) fn callback_signature(
+    signature: &str,
+    arg_index: usize,
+) -> Option<(CallbackAbi, usize)> {
+    let argument = *argument_types(signature)?.get(arg_index)?;
+
+    let mut argument = argument.trim();
+    if argument.starts_with("for<") {
+        argument = argument[argument.find("> ")? + 2..].trim_start();
+    }
+    let argument = argument.strip_prefix("unsafe ").unwrap_or(argument);
+
+    let abi = if argument.starts_with("fn(") {
+        CallbackAbi::Rust
+    } else if argument.starts_with("extern \"C\" fn(") {
+        CallbackAbi::C
+    } else {
+        return None;
+    };
+
+    Some((abi, argument_types(argument)?.len()))
+}
+
+/// Returns the argument types of the outermost function type of `signature`.
+fn argument_types(signature: &str) -> Option<Vec<&str>> {
+    let start = signature.find("fn(")? + 3;
+
+    let mut arguments = Vec::new();
+    let mut depth = 0;
+    let mut argument_start = start;
+    let mut previous = ' ';
+    for (index, character) in signature[start..].char_indices() {
+        let index = start + index;
+        match character {
+            '(' | '<' | '[' => depth += 1,
+            // The `>` of a `->` does not close anything.
+            '>' if previous == '-' => {}
+            ')' | '>' | ']' if depth > 0 => depth -= 1,
+            ',' if depth == 0 => {
+                arguments.push(signature[argument_start..index].trim());
+                argument_start = index + 1;
+            }
+            ')' => {
+                let last = signature[argument_start..index].trim();
+                if !last.is_empty() {
+                    arguments.push(last);
+                }
+                return Some(arguments);
+            }
+            _ => {}
+        }
+        previous = character;
+    }
+
+    None
+}
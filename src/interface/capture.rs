@@ -0,0 +1,305 @@
+use crate::interface::func_ptr::FuncPtr;
+use crate::interface::into_fake::current_closure;
+use crate::interface::into_fake::private::FakeParts;
+use crate::interface::verifier::CallCountVerifier;
+use std::sync::{Arc, Mutex, PoisonError};
+
+/// An argument `WhenCalledBuilder::will_capture` can keep a copy of.
+///
+/// Borrowed arguments are copied into owned values, so the snapshot stays valid after the
+/// call returns: `&str` is captured as a `String` and `&[T]` as a `Vec<T>`.
+pub trait CaptureArg {
+    /// The type the argument is captured as.
+    type Owned: Clone + Send + 'static;
+
+    fn to_captured(&self) -> Self::Owned;
+}
+
+macro_rules! impl_capture_arg_for_copy {
+    ($($ty:ty),*) => {
+        $(
+            impl CaptureArg for $ty {
+                type Owned = $ty;
+
+                fn to_captured(&self) -> $ty {
+                    *self
+                }
+            }
+        )*
+    };
+}
+
+impl_capture_arg_for_copy!(
+    bool, char, f32, f64, i8, i16, i32, i64, i128, isize, u8, u16, u32, u64, u128, usize
+);
+
+impl CaptureArg for &str {
+    type Owned = String;
+
+    fn to_captured(&self) -> String {
+        self.to_string()
+    }
+}
+
+impl CaptureArg for String {
+    type Owned = String;
+
+    fn to_captured(&self) -> String {
+        self.clone()
+    }
+}
+
+impl<T: Clone + Send + 'static> CaptureArg for &[T] {
+    type Owned = Vec<T>;
+
+    fn to_captured(&self) -> Vec<T> {
+        self.to_vec()
+    }
+}
+
+impl<T: Clone + Send + 'static> CaptureArg for Vec<T> {
+    type Owned = Vec<T>;
+
+    fn to_captured(&self) -> Vec<T> {
+        self.clone()
+    }
+}
+
+/// The arguments of every call to a function faked with `WhenCalledBuilder::will_capture`.
+///
+/// Clones observe the same function.
+pub struct Capture<T> {
+    function: usize,
+    calls: Arc<Mutex<Vec<T>>>,
+}
+
+impl<T> Capture<T> {
+    /// Creates an empty capture of the calls to the function at `function`.
+    pub(crate) fn new(function: usize) -> Self {
+        Self {
+            function,
+            calls: Arc::new(Mutex::new(Vec::new())),
+        }
+    }
+
+    /// The address of the captured function, 0 for a capture made with `Default`.
+    pub(crate) fn function(&self) -> usize {
+        self.function
+    }
+
+    /// Returns how many calls were captured so far.
+    pub fn call_count(&self) -> usize {
+        self.calls
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .len()
+    }
+
+    /// Returns whether any call so far had arguments matching `expected`, see `ArgsMatcher`.
+    pub fn was_called_with(&self, expected: impl ArgsMatcher<T>) -> bool {
+        self.calls
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .iter()
+            .any(|args| expected.matches(args))
+    }
+}
+
+impl<T> Clone for Capture<T> {
+    fn clone(&self) -> Self {
+        Self {
+            function: self.function,
+            calls: self.calls.clone(),
+        }
+    }
+}
+
+impl<T> Default for Capture<T> {
+    fn default() -> Self {
+        Self::new(0)
+    }
+}
+
+impl<T: Clone> Capture<T> {
+    /// Returns the arguments of the latest call as a tuple, or `None` if the function was
+    /// not called yet.
+    pub fn last_args(&self) -> Option<T> {
+        self.calls
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .last()
+            .cloned()
+    }
+
+    /// Returns the arguments of every call so far as tuples, oldest first.
+    pub fn all_args(&self) -> Vec<T> {
+        self.calls
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .clone()
+    }
+}
+
+/// Matches any value of a captured argument, see `ArgsMatcher`.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct AnyArg;
+
+/// Something a captured argument of type `T` can be compared with, see `ArgsMatcher`.
+///
+/// Implemented for `AnyArg`, which matches every value, and for the types `CaptureArg` is
+/// implemented for, which match equal values. A `String` argument can be matched with a
+/// `&str` and a `Vec<T>` one with a `&[T]`, the types the function took them as.
+pub trait ArgMatcher<T> {
+    fn matches_arg(&self, arg: &T) -> bool;
+}
+
+impl<T> ArgMatcher<T> for AnyArg {
+    fn matches_arg(&self, _arg: &T) -> bool {
+        true
+    }
+}
+
+macro_rules! impl_arg_matcher_for_copy {
+    ($($ty:ty),*) => {
+        $(
+            impl ArgMatcher<$ty> for $ty {
+                fn matches_arg(&self, arg: &$ty) -> bool {
+                    self == arg
+                }
+            }
+        )*
+    };
+}
+
+impl_arg_matcher_for_copy!(
+    bool, char, f32, f64, i8, i16, i32, i64, i128, isize, u8, u16, u32, u64, u128, usize
+);
+
+impl ArgMatcher<String> for &str {
+    fn matches_arg(&self, arg: &String) -> bool {
+        arg == self
+    }
+}
+
+impl ArgMatcher<String> for String {
+    fn matches_arg(&self, arg: &String) -> bool {
+        arg == self
+    }
+}
+
+impl<T: PartialEq> ArgMatcher<Vec<T>> for &[T] {
+    fn matches_arg(&self, arg: &Vec<T>) -> bool {
+        arg == self
+    }
+}
+
+impl<T: PartialEq> ArgMatcher<Vec<T>> for Vec<T> {
+    fn matches_arg(&self, arg: &Vec<T>) -> bool {
+        arg == self
+    }
+}
+
+/// The expected arguments of a call, see `InjectorPP::verify_called_with`.
+///
+/// Implemented for tuples with one `ArgMatcher` per argument, such as `("localhost", 8080)`
+/// or `("localhost", AnyArg)` to only match the first argument.
+pub trait ArgsMatcher<T> {
+    fn matches(&self, args: &T) -> bool;
+}
+
+macro_rules! impl_args_matcher_for_tuple {
+    ($($arg:ident $matcher:ident $index:tt),*) => {
+        impl<$($arg, $matcher: ArgMatcher<$arg>),*> ArgsMatcher<($($arg,)*)> for ($($matcher,)*) {
+            fn matches(&self, args: &($($arg,)*)) -> bool {
+                $(self.$index.matches_arg(&args.$index))&&*
+            }
+        }
+    };
+}
+
+impl_args_matcher_for_tuple!(A1 M1 0);
+impl_args_matcher_for_tuple!(A1 M1 0, A2 M2 1);
+impl_args_matcher_for_tuple!(A1 M1 0, A2 M2 1, A3 M3 2);
+impl_args_matcher_for_tuple!(A1 M1 0, A2 M2 1, A3 M3 2, A4 M4 3);
+impl_args_matcher_for_tuple!(A1 M1 0, A2 M2 1, A3 M3 2, A4 M4 3, A5 M5 4);
+impl_args_matcher_for_tuple!(A1 M1 0, A2 M2 1, A3 M3 2, A4 M4 3, A5 M5 4, A6 M6 5);
+
+/// Something `WhenCalledBuilder::will_capture` can replace a function with.
+///
+/// Implemented for closures taking one to six arguments that all implement `CaptureArg`. The
+/// `Marker` parameter only tells the implementations apart and is always inferred.
+pub trait IntoCapture<Marker>: private::IntoCaptureParts<Marker> {}
+
+impl<T: private::IntoCaptureParts<Marker>, Marker> IntoCapture<Marker> for T {}
+
+pub(crate) mod private {
+    use super::*;
+
+    pub trait IntoCaptureParts<Marker> {
+        /// The tuple of the captured arguments.
+        type Args: Clone + Send + 'static;
+
+        /// Makes a fake that stores the arguments of each call in `capture`, then runs the
+        /// closure.
+        fn into_capture_parts(self, capture: &Capture<Self::Args>) -> FakeParts;
+    }
+}
+
+use private::IntoCaptureParts;
+
+/// A closure together with the list the arguments of its calls are stored in.
+struct CaptureState<F, T> {
+    closure: F,
+    calls: Arc<Mutex<Vec<T>>>,
+}
+
+macro_rules! impl_into_capture_for_closure {
+    ($trampoline:ident, $($arg:ident),*) => {
+        /// Has the exact signature of the faked function, stores a copy of the arguments,
+        /// then forwards them to the closure.
+        #[allow(non_snake_case)]
+        fn $trampoline<F, $($arg: CaptureArg,)* R>($($arg: $arg),*) -> R
+        where
+            F: Fn($($arg),*) -> R,
+        {
+            let state = unsafe {
+                current_closure::<CaptureState<F, ($(<$arg as CaptureArg>::Owned,)*)>>()
+            };
+            state
+                .calls
+                .lock()
+                .unwrap_or_else(PoisonError::into_inner)
+                .push(($($arg.to_captured(),)*));
+
+            (state.closure)($($arg),*)
+        }
+
+        impl<F, $($arg: CaptureArg,)* R> IntoCaptureParts<fn($($arg),*) -> R> for F
+        where
+            F: Fn($($arg),*) -> R + Sync + 'static,
+        {
+            type Args = ($(<$arg as CaptureArg>::Owned,)*);
+
+            fn into_capture_parts(self, capture: &Capture<Self::Args>) -> FakeParts {
+                let trampoline: fn($($arg),*) -> R = $trampoline::<F, $($arg,)* R>;
+                let signature = std::any::type_name_of_val(&trampoline);
+
+                FakeParts {
+                    func: unsafe { FuncPtr::new(trampoline as *const (), signature) },
+                    verifier: CallCountVerifier::Dummy,
+                    closure: Some(Box::new(CaptureState {
+                        closure: self,
+                        calls: capture.calls.clone(),
+                    })),
+                }
+            }
+        }
+    };
+}
+
+impl_into_capture_for_closure!(capture_trampoline1, A1);
+impl_into_capture_for_closure!(capture_trampoline2, A1, A2);
+impl_into_capture_for_closure!(capture_trampoline3, A1, A2, A3);
+impl_into_capture_for_closure!(capture_trampoline4, A1, A2, A3, A4);
+impl_into_capture_for_closure!(capture_trampoline5, A1, A2, A3, A4, A5);
+impl_into_capture_for_closure!(capture_trampoline6, A1, A2, A3, A4, A5, A6);
@@ -0,0 +1,79 @@
+/// How a patched function branches to its fake, see `PatchDebug::branch_kind`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+#[non_exhaustive]
+pub enum BranchKind {
+    /// A single PC-relative branch: a `b` reaching ±128MB on AArch64, ±32MB in ARM code and
+    /// ±16MB in Thumb code on 32-bit ARM, a `jmp rel32` on x86 and x86_64 and an
+    /// `auipc`/`jalr` pair on RISC-V.
+    Near,
+
+    /// The address of the fake is loaded into a register that is branched to, because the
+    /// JIT memory is out of reach of a near branch: through `x16` on AArch64, `rax` on
+    /// x86_64 and a literal loaded into `r9` or `r7` on 32-bit ARM.
+    Long,
+
+    /// The fake is written over the function itself and branches nowhere, as 32-bit ARM
+    /// does for `will_return_boolean`.
+    Inline,
+}
+
+/// What was written to install a fake, see `InjectorPP::debug_info`.
+///
+/// Comparing these with the output of a disassembler helps understand a fake that does not
+/// behave as expected. With the `debug-disasm` feature, `disassembly` lists them as
+/// instructions.
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct PatchDebug {
+    /// The address of the patched function.
+    pub address: usize,
+
+    /// The bytes the patch replaced, which are those of the previous fake of the function if
+    /// it had one.
+    pub original_bytes: Vec<u8>,
+
+    /// The bytes written over the start of the function.
+    pub patch_bytes: Vec<u8>,
+
+    /// The address of the JIT code the patch branches to, or 0 if the fake needed none.
+    pub jit_address: usize,
+
+    /// The JIT code of the fake, empty if it needed none.
+    pub jit_bytes: Vec<u8>,
+
+    /// How the patch branches to the JIT code or the fake.
+    pub branch_kind: BranchKind,
+}
+
+#[cfg(feature = "debug-disasm")]
+impl PatchDebug {
+    /// Lists the original, patch and JIT bytes as instructions, one per line with its address
+    /// and bytes.
+    ///
+    /// Only the instructions injectorpp itself writes are named, such as branches, moves of
+    /// addresses and `nop`s. The others are listed by their bytes alone.
+    pub fn disassembly(&self) -> String {
+        use crate::injector_core::disasm::disassemble;
+
+        let mut listing = String::new();
+        for (title, address, code) in [
+            ("original", self.address, &self.original_bytes),
+            ("patch", self.address, &self.patch_bytes),
+            ("jit", self.jit_address, &self.jit_bytes),
+        ] {
+            if code.is_empty() {
+                continue;
+            }
+
+            listing.push_str(title);
+            listing.push_str(":\n");
+            for line in disassemble(code, address) {
+                listing.push_str("  ");
+                listing.push_str(&line);
+                listing.push('\n');
+            }
+        }
+
+        listing
+    }
+}
@@ -0,0 +1,8 @@
+use std::time::Duration;
+
+/// Called from the JIT block of a function faked with `will_sleep` or registered with
+/// `with_delay`.
+pub(crate) extern "C-unwind" fn sleep_for(data: *const (), _registers: *const u64) {
+    let duration = unsafe { &*(data as *const Duration) };
+    std::thread::sleep(*duration);
+}
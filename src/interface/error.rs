@@ -0,0 +1,114 @@
+use std::fmt;
+
+use crate::interface::debug::PatchDebug;
+
+/// An error that prevented a fake from being installed.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum InjectError {
+    /// The `len` bytes starting at `address` are not mapped as readable memory.
+    UnreadableMemory { address: usize, len: usize },
+
+    /// The injector already has `limit` fakes installed, see
+    /// `InjectorPP::set_max_active_patches`.
+    PatchLimitExceeded { limit: usize },
+
+    /// The instruction at `address` would be overwritten by the patch but cannot be moved
+    /// elsewhere, so the original function cannot be kept callable.
+    UnrelocatableInstruction { address: usize },
+
+    /// No executable memory of `size` bytes could be allocated for the fake.
+    AllocationFailed { size: usize },
+
+    /// Executable memory was allocated, but never within the `max_range` bytes a branch
+    /// written at `address` can reach.
+    OutOfBranchRange { address: usize, max_range: usize },
+
+    /// The function at `address` only leaves `available` bytes to patch, but reaching its
+    /// fake needs `required` bytes.
+    PatchWindowTooSmall {
+        address: usize,
+        available: usize,
+        required: usize,
+    },
+
+    /// `InjectorPP::self_test` found that `step` does not work on this platform.
+    SelfTestFailed { step: &'static str },
+
+    /// A null address was given as a function, see `FuncAddress::from_raw`.
+    NullAddress,
+
+    /// `address` is not in executable memory, so it cannot be the code of a function.
+    NotExecutable { address: usize },
+
+    /// `call` failed with the OS error `code` while making the code at `address` writable.
+    ProtectionFailed {
+        call: &'static str,
+        address: usize,
+        code: i32,
+    },
+
+    /// Calling the fake described by `debug` raised the signal `signal`, see
+    /// `InjectorPP::safe_verify`.
+    PatchFaulted { signal: i32, debug: Box<PatchDebug> },
+}
+
+impl fmt::Display for InjectError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            InjectError::UnreadableMemory { address, len } => write!(
+                f,
+                "Cannot read {len} byte(s) at {address:#x}: the range is not mapped as readable memory"
+            ),
+            InjectError::PatchLimitExceeded { limit } => write!(
+                f,
+                "Cannot install more than {limit} fake(s) with this injector, see InjectorPP::set_max_active_patches"
+            ),
+            InjectError::UnrelocatableInstruction { address } => write!(
+                f,
+                "Cannot relocate the instruction at {address:#x}, the original function cannot be called once patched"
+            ),
+            InjectError::AllocationFailed { size } => write!(
+                f,
+                "Failed to allocate {size} byte(s) of executable memory on {} arch",
+                std::env::consts::ARCH
+            ),
+            InjectError::OutOfBranchRange { address, max_range } => write!(
+                f,
+                "Failed to allocate JIT memory within ±{max_range} of source {address:#x} on {} arch",
+                std::env::consts::ARCH
+            ),
+            InjectError::PatchWindowTooSmall {
+                address,
+                available,
+                required,
+            } => write!(
+                f,
+                "The function at {address:#x} only leaves {available} byte(s) to patch but {required} are needed to reach its fake"
+            ),
+            InjectError::SelfTestFailed { step } => {
+                write!(f, "The injectorpp self test failed: {step}")
+            }
+            InjectError::NullAddress => write!(f, "The function address is null"),
+            InjectError::NotExecutable { address } => write!(
+                f,
+                "The address {address:#x} is not in executable memory, so there is no function code to patch there. If the function is inlined, e.g. marked #[inline(always)], mark it #[inline(never)] instead"
+            ),
+            InjectError::ProtectionFailed {
+                call,
+                address,
+                code,
+            } => write!(
+                f,
+                "{call} failed with error {code} while making the code at {address:#x} writable"
+            ),
+            InjectError::PatchFaulted { signal, debug } => write!(
+                f,
+                "Calling the fake of the function at {:#x} raised signal {signal}\n  patch: {:02x?}\n  JIT code at {:#x}: {:02x?}",
+                debug.address, debug.patch_bytes, debug.jit_address, debug.jit_bytes
+            ),
+        }
+    }
+}
+
+impl std::error::Error for InjectError {}
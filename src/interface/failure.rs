@@ -0,0 +1,218 @@
+use std::fmt;
+use std::sync::{Arc, Mutex, PoisonError};
+
+use crate::interface::error::InjectError;
+
+/// A verification that failed when a fake or an `Expectations` was dropped, or a fake that
+/// could not be removed, see `InjectorPP::set_failure_sink`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum Failure {
+    /// The fake of the function at address `function` was called `actual` times instead of
+    /// `expected`.
+    CallCount {
+        function: usize,
+        expected: usize,
+        actual: usize,
+    },
+
+    /// The calls recorded in a `Sequence` first differed from the `Expectations` at `step`,
+    /// counted from 1. Both orders are listed as `label xN` runs.
+    Sequence {
+        step: usize,
+        expected: String,
+        actual: String,
+    },
+
+    /// The fakes installed with the install numbers in `expected` were not called in that
+    /// order, see `InjectorPP::assert_called_in_order`. `step` is the first of them, counted
+    /// from 1, that was not called after the previous ones, and `actual` lists the install
+    /// numbers of the recorded calls.
+    CallOrder {
+        step: usize,
+        expected: Vec<usize>,
+        actual: Vec<usize>,
+    },
+
+    /// None of the `calls` calls captured for the function at address `function` had the
+    /// expected arguments, see `InjectorPP::verify_called_with`.
+    CalledWith { function: usize, calls: usize },
+
+    /// The original code of the function at address `function` could not be written back,
+    /// see `InjectorPP::take_restore_failures`.
+    Restore { function: usize, error: InjectError },
+}
+
+impl Failure {
+    /// Renders the failure as a single line JSON object with a `kind` field, for CI tools
+    /// aggregating test failures.
+    pub fn to_json(&self) -> String {
+        match self {
+            Failure::CallCount {
+                function,
+                expected,
+                actual,
+            } => format!(
+                r#"{{"kind":"call_count","function":"{function:#x}","expected":{expected},"actual":{actual}}}"#
+            ),
+            Failure::Sequence {
+                step,
+                expected,
+                actual,
+            } => format!(
+                r#"{{"kind":"sequence","step":{step},"expected":"{}","actual":"{}"}}"#,
+                escape_json(expected),
+                escape_json(actual)
+            ),
+            Failure::CallOrder {
+                step,
+                expected,
+                actual,
+            } => format!(
+                r#"{{"kind":"call_order","step":{step},"expected":[{}],"actual":[{}]}}"#,
+                join_numbers(expected),
+                join_numbers(actual)
+            ),
+            Failure::CalledWith { function, calls } => {
+                format!(r#"{{"kind":"called_with","function":"{function:#x}","calls":{calls}}}"#)
+            }
+            Failure::Restore { function, error } => format!(
+                r#"{{"kind":"restore","function":"{function:#x}","error":"{}"}}"#,
+                escape_json(&error.to_string())
+            ),
+        }
+    }
+}
+
+impl fmt::Display for Failure {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Failure::CallCount {
+                expected, actual, ..
+            } => write!(
+                f,
+                "Fake function was expected to be called {expected} time(s), but it is actually called {actual} time(s)"
+            ),
+            Failure::Sequence {
+                step,
+                expected,
+                actual,
+            } => write!(
+                f,
+                "Calls did not happen as expected, first difference at step {step}\n  expected: {expected}\n  actual:   {actual}"
+            ),
+            Failure::CallOrder {
+                step,
+                expected,
+                actual,
+            } => write!(
+                f,
+                "Fakes were not called in the expected order, first difference at step {step}\n  expected: {}\n  actual:   {}",
+                describe_fakes(expected),
+                describe_fakes(actual)
+            ),
+            Failure::CalledWith { calls, .. } => write!(
+                f,
+                "Fake function was expected to be called with the given arguments, but none of its {calls} call(s) matched"
+            ),
+            Failure::Restore { function, error } => {
+                write!(f, "Failed to restore the function at {function:#x}: {error}")
+            }
+        }
+    }
+}
+
+fn join_numbers(numbers: &[usize]) -> String {
+    numbers
+        .iter()
+        .map(usize::to_string)
+        .collect::<Vec<_>>()
+        .join(",")
+}
+
+fn describe_fakes(indices: &[usize]) -> String {
+    if indices.is_empty() {
+        return "no calls".to_string();
+    }
+
+    indices
+        .iter()
+        .map(|index| format!("fake #{index}"))
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+type FailureSink = Arc<dyn Fn(&Failure) + Send + Sync>;
+
+static FAILURE_SINK: Mutex<Option<FailureSink>> = Mutex::new(None);
+
+pub(crate) fn set_failure_sink(sink: Option<FailureSink>) {
+    *FAILURE_SINK.lock().unwrap_or_else(PoisonError::into_inner) = sink;
+}
+
+/// Hands `failure` to the failure sink, if any, then panics with its message.
+pub(crate) fn fail(failure: Failure) -> ! {
+    notify_sink(&failure);
+
+    panic!("{failure}");
+}
+
+/// The fakes that could not be removed since the last `take_restore_failures`.
+static RESTORE_FAILURES: Mutex<Vec<Failure>> = Mutex::new(Vec::new());
+
+/// Reports that the original code of `function` could not be written back, without
+/// panicking: fakes are removed while dropping, often while a failed test unwinds, where a
+/// second panic would abort the process.
+///
+/// The failure is printed, handed to the failure sink, if any, and kept for
+/// `take_restore_failures`.
+pub(crate) fn record_restore_failure(function: usize, error: InjectError) {
+    let failure = Failure::Restore { function, error };
+    eprintln!("injectorpp: {failure}");
+
+    notify_sink(&failure);
+
+    RESTORE_FAILURES
+        .lock()
+        .unwrap_or_else(PoisonError::into_inner)
+        .push(failure);
+}
+
+fn notify_sink(failure: &Failure) {
+    let sink = FAILURE_SINK
+        .lock()
+        .unwrap_or_else(PoisonError::into_inner)
+        .clone();
+
+    if let Some(sink) = sink {
+        sink(failure);
+    }
+}
+
+pub(crate) fn take_restore_failures() -> Vec<Failure> {
+    std::mem::take(
+        &mut *RESTORE_FAILURES
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner),
+    )
+}
+
+fn escape_json(value: &str) -> String {
+    let mut escaped = String::with_capacity(value.len());
+
+    for character in value.chars() {
+        match character {
+            '"' => escaped.push_str("\\\""),
+            '\\' => escaped.push_str("\\\\"),
+            '\n' => escaped.push_str("\\n"),
+            '\r' => escaped.push_str("\\r"),
+            '\t' => escaped.push_str("\\t"),
+            character if character.is_control() => {
+                escaped.push_str(&format!("\\u{:04x}", character as u32))
+            }
+            character => escaped.push(character),
+        }
+    }
+
+    escaped
+}
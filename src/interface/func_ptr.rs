@@ -1,4 +1,6 @@
-use crate::injector_core::common::FuncPtrInternal;
+use crate::injector_core::common::{try_read_bytes, FuncPtrInternal};
+use crate::interface::error::InjectError;
+use std::marker::PhantomData;
 use std::ptr::NonNull;
 
 /// A safe wrapper around a raw function pointer.
@@ -11,12 +13,18 @@ use std::ptr::NonNull;
 /// # Safety
 ///
 /// The caller must ensure that the pointer is valid and points to a function.
-pub struct FuncPtr {
+///
+/// `F` is the function pointer type given to `func!` or `closure!`, such as
+/// `fn(i32) -> i32`, and `()` for a `FuncPtr` without signature. `when_called` carries it to
+/// its builder, so `will_execute_raw` only accepts a replacement of the same type and a
+/// mismatch fails to compile.
+pub struct FuncPtr<F = ()> {
     /// The internal representation of the function pointer.
     ///
     /// This is a wrapper around a non-null pointer to ensure safety.
     pub(super) func_ptr_internal: FuncPtrInternal,
     pub(super) signature: &'static str,
+    pub(super) function_type: PhantomData<F>,
 }
 
 impl FuncPtr {
@@ -35,6 +43,106 @@ impl FuncPtr {
         Self {
             func_ptr_internal: FuncPtrInternal::new(nn),
             signature,
+            function_type: PhantomData,
+        }
+    }
+}
+
+impl<F> FuncPtr<F> {
+    /// Creates a new `FuncPtr` to a function of type `F`, a function pointer type such as
+    /// `fn(i32) -> i32`, from a raw pointer.
+    ///
+    /// # Safety
+    ///
+    /// The caller must ensure that the pointer is valid and points to a function of type `F`.
+    pub unsafe fn new_typed(ptr: *const ()) -> Self {
+        let untyped = FuncPtr::new(ptr, std::any::type_name::<F>());
+
+        Self {
+            func_ptr_internal: untyped.func_ptr_internal,
+            signature: untyped.signature,
+            function_type: PhantomData,
         }
     }
+
+    /// Forgets the function type, keeping the signature checked at runtime.
+    pub fn untyped(self) -> FuncPtr {
+        FuncPtr {
+            func_ptr_internal: self.func_ptr_internal,
+            signature: self.signature,
+            function_type: PhantomData,
+        }
+    }
+}
+
+/// The address of a function that is only known at runtime, e.g. resolved by a plugin system,
+/// which `func!` cannot name.
+///
+/// Turn it into a `FuncPtr` for `InjectorPP::when_called` with `with_signature`, which keeps
+/// the signature checks of the `will_*` methods, or with `FuncPtr::from`, which has no
+/// signature, like `func_unchecked!`, and so is meant for the `*_unchecked` methods.
+///
+/// # Example
+///
+/// ```rust
+/// use injectorpp::interface::injector::*;
+///
+/// fn plugin_version() -> u32 {
+///     1
+/// }
+///
+/// let resolved = plugin_version as fn() -> u32 as *const ();
+/// let address = unsafe { FuncAddress::from_raw(resolved) }.unwrap();
+///
+/// let mut injector = InjectorPP::new();
+/// injector
+///     .when_called(address.with_signature::<fn() -> u32>())
+///     .will_execute(|| 2u32);
+///
+/// assert_eq!(plugin_version(), 2);
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct FuncAddress {
+    address: usize,
+}
+
+impl FuncAddress {
+    /// Wraps the function at `address` after checking that it is not null and readable.
+    ///
+    /// Returns `InjectError::NullAddress` or `InjectError::UnreadableMemory` otherwise.
+    ///
+    /// # Safety
+    ///
+    /// `address` must be the entry point of a function, of the type given to
+    /// `with_signature` if it is used. Readable memory can still be data, which patching
+    /// would corrupt.
+    pub unsafe fn from_raw(address: *const ()) -> Result<Self, InjectError> {
+        if address.is_null() {
+            return Err(InjectError::NullAddress);
+        }
+
+        try_read_bytes(address as *const u8, 1)?;
+
+        Ok(Self {
+            address: address as usize,
+        })
+    }
+
+    /// Returns the address.
+    pub fn as_ptr(&self) -> *const () {
+        self.address as *const ()
+    }
+
+    /// Returns a `FuncPtr` to the function, declaring its type `F`, a function pointer type
+    /// such as `fn(i32) -> i32`, as `func!` would.
+    pub fn with_signature<F: Copy + 'static>(self) -> FuncPtr<F> {
+        unsafe { FuncPtr::new_typed(self.as_ptr()) }
+    }
+}
+
+impl From<FuncAddress> for FuncPtr {
+    /// Returns a `FuncPtr` without signature, like `func_unchecked!`.
+    fn from(address: FuncAddress) -> Self {
+        unsafe { FuncPtr::new(address.as_ptr(), "") }
+    }
 }
@@ -1,47 +1,62 @@
 use crate::injector_core::common::*;
 use crate::injector_core::internal::*;
-pub use crate::interface::func_ptr::FuncPtr;
-pub use crate::interface::macros::__assert_future_output;
+use crate::injector_core::symbols::{
+    resolve_library_symbol, resolve_symbol, resolve_symbol_in_library,
+};
+pub use crate::interface::capture::{
+    AnyArg, ArgMatcher, ArgsMatcher, Capture, CaptureArg, IntoCapture,
+};
+pub use crate::interface::debug::{BranchKind, PatchDebug};
+pub use crate::interface::error::InjectError;
+pub use crate::interface::failure::Failure;
+pub use crate::interface::func_ptr::{FuncAddress, FuncPtr};
+pub use crate::interface::into_fake::{IntoFake, IntoPredicate};
+pub use crate::interface::into_map::{IntoHook, IntoMap};
+pub use crate::interface::macros::{
+    __assert_future_output, __func_ptr_of, __future_output_signature,
+};
+pub use crate::interface::options::{InjectorOptions, JitAllocStrategy};
+pub use crate::interface::register_pair::RegisterPair;
+pub use crate::interface::restore::{CallRecord, RestoreInfo};
+pub use crate::interface::return_value::ReturnValue;
+pub use crate::interface::sequence::{Expectations, Sequence};
+pub use crate::interface::spy::Spy;
 pub use crate::interface::verifier::CallCountVerifier;
 
+use crate::interface::callback::{
+    callback_signature, invoke_callback, CallbackAbi, CallbackInvocations, MAX_CALLBACK_ARGUMENTS,
+};
+use crate::interface::delay::sleep_for;
+use crate::interface::failure::{
+    fail, record_restore_failure, set_failure_sink, take_restore_failures,
+};
+use crate::interface::into_fake::private::{FakeParts, IntoFakeParts};
+use crate::interface::into_fake::{panic_with_current_message, set_current_closure};
+use crate::interface::into_map::private::{IntoMapParts, MapParts};
+use crate::interface::lock::FunctionLockGuard;
+use crate::interface::restore::{count_restore_call, set_restore_sink, RestoreHook};
+use crate::interface::return_value::private::Registers;
+use crate::interface::sequence::{
+    first_out_of_order, record_call_order, record_sequence_call, CallOrderEntry, SequenceEntry,
+};
+use crate::interface::verifier::count_call;
+use std::any::Any;
+use std::collections::HashMap;
+use std::ffi::{c_char, CStr, CString};
+
 use std::future::Future;
+use std::marker::PhantomData;
+use std::ops::RangeInclusive;
 use std::pin::Pin;
-use std::sync::Mutex;
-use std::sync::MutexGuard;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex, PoisonError};
 use std::task::Context;
 use std::task::Poll;
+use std::thread::ThreadId;
+use std::time::Duration;
 
-/// A `Mutex` that never stays poisoned: on panic it just recovers the guard.
-///
-/// This is a trade-off between user experience and potential data corrupt issue.
-/// When panic happens in the multi thread scenario, the std Mutex will cause poison error.
-/// This will fail other unrelated test cases. The test failure accuracy is
-/// more important to users so ignore the poison error.
-struct NoPoisonMutex<T> {
-    inner: Mutex<T>,
-}
-
-impl<T> NoPoisonMutex<T> {
-    /// Create a new mutex.
-    const fn new(value: T) -> Self {
-        Self {
-            inner: Mutex::new(value),
-        }
-    }
-
-    /// Lock, recovering if the mutex was poisoned.
-    fn lock(&self) -> MutexGuard<'_, T> {
-        match self.inner.lock() {
-            Ok(guard) => guard,
-            Err(poisoned) => {
-                // Swallow the poison and give the guard anyway
-                poisoned.into_inner()
-            }
-        }
-    }
-}
-
-static LOCK_FUNCTION: NoPoisonMutex<()> = NoPoisonMutex::new(());
+/// Tells injectors apart so a `MockHandle` can only be used with the injector that created it.
+static NEXT_INJECTOR_ID: AtomicUsize = AtomicUsize::new(0);
 
 /// A high-level type that holds patch guards so that when it goes out of scope,
 /// the original function code is automatically restored.
@@ -53,10 +68,38 @@ static LOCK_FUNCTION: NoPoisonMutex<()> = NoPoisonMutex::new(());
 /// function after the InjectorPP instance is dropped. If multiple threads may execute
 /// the patched function concurrently, ensure that InjectorPP instances remain alive
 /// until all threads have completed execution of the patched function.
+///
+/// Because that mutex is held from `new` until the injector is dropped, capturing the original
+/// bytes of a function and writing its patch never interleave with another injector doing the
+/// same, so every fake captures the real code of the function and restores it.
+///
+/// # Restoration Order
+///
+/// Fakes of the same function are restored strictly last installed, first restored. Only one
+/// injector is alive at a time: creating another one waits until the live one is dropped, on
+/// any thread, and creating it on the thread that holds the live one panics. Injectors
+/// therefore cannot be dropped out of order, and each one finds the real code of the
+/// functions it fakes. Within an injector, dropping it or `rollback` restores its fakes in
+/// reverse order, and `restore` panics for a fake whose function was faked again later.
 pub struct InjectorPP {
-    guards: Vec<PatchGuard>,
-    verifiers: Vec<CallCountVerifier>,
-    _lock: MutexGuard<'static, ()>,
+    id: usize,
+    // Every installed guard with its install number, in install order.
+    guards: Vec<(usize, PatchGuard)>,
+    installs: usize,
+    // Call count verifiers with the address of the function their fake replaces.
+    verifiers: Vec<(usize, CallCountVerifier)>,
+    // Data the JIT blocks point to with the install number of their fake, boxed so its
+    // address stays stable.
+    hook_data: Vec<(usize, Box<dyn Any>)>,
+    // `on_restore` callbacks with the install number of their fake, in install order.
+    restore_hooks: Vec<(usize, Box<RestoreHook>)>,
+    // `count_calls` counters with the install number of their fake and the address of the
+    // function it replaces, in install order.
+    call_counters: Vec<(usize, usize, Box<AtomicUsize>)>,
+    // Install numbers of the `record_call_order` fakes, in the order they were called.
+    call_order: Arc<Mutex<Vec<usize>>>,
+    max_active_patches: Option<usize>,
+    _lock: FunctionLockGuard<'static>,
 }
 
 impl InjectorPP {
@@ -64,6 +107,8 @@ impl InjectorPP {
     ///
     /// `InjectorPP` allows faking Rust functions at runtime without modifying the original code.
     /// It ensures thread safety by holding a global mutex for the entire lifetime of the patch.
+    /// Panics if the current thread already holds an injector or a `Preventer`, as waiting for
+    /// it would never end.
     ///
     /// # Example
     ///
@@ -73,433 +118,3219 @@ impl InjectorPP {
     /// let injector = InjectorPP::new();
     /// ```
     pub fn new() -> Self {
-        let lock = LOCK_FUNCTION.lock();
-
-        Self {
-            guards: Vec::new(),
-            verifiers: Vec::new(),
-            _lock: lock,
-        }
-    }
-
-    /// Prevents injectorpp from other threads to change the functions.
-    /// This is useful when the test does not want to be affected by injectorpp usage in other threads.
-    pub fn prevent() -> Preventer {
-        let lock = LOCK_FUNCTION.lock();
-        Preventer { _lock: lock }
+        Self::new_with_options(InjectorOptions::new())
     }
 
-    /// Begins faking a function.
-    ///
-    /// Accepts a FuncPtr to the function you want to fake. Use the `func!` macro to obtain this pointer.
-    ///
-    /// # Parameters
-    ///
-    /// - `func`: A FuncPtr holds the pointer to the target function obtained via `func!` macro.
+    /// Creates a new `InjectorPP` instance with JIT memory for `capacity` fakes mapped up front.
     ///
-    /// # Returns
+    /// The pages stay mapped while the injector lives, even when no fake uses them, so a loop
+    /// installing and restoring fakes, e.g. in a benchmark, does not map and unmap memory on
+    /// every iteration. They are placed near the code of this crate and serve the functions
+    /// of the same binary, while functions too far from them, such as those of shared
+    /// libraries, get their own memory as usual. Fakes beyond `capacity` or with larger stubs
+    /// than a fixed return or a branch to a replacement simply map more.
     ///
-    /// A builder (`WhenCalledBuilder`) to further specify the fake behavior.
+    /// Mapping is best effort: memory that cannot be mapped now is mapped by the fakes
+    /// needing it.
     ///
     /// # Example
     ///
     /// ```rust
     /// use injectorpp::interface::injector::*;
-    /// use std::path::Path;
     ///
-    /// fn fake_exists(_path: &Path) -> bool {
-    ///     true
+    /// #[inline(never)]
+    /// fn is_ready() -> bool {
+    ///     std::hint::black_box(false)
     /// }
     ///
-    /// let mut injector = InjectorPP::new();
-    /// injector
-    ///     .when_called(injectorpp::func!(fn (Path::exists)(&Path) -> bool))
-    ///     .will_execute_raw(injectorpp::func!(fn (fake_exists)(&Path) -> bool));
+    /// let mut injector = InjectorPP::with_capacity(1);
+    /// for _ in 0..100 {
+    ///     let handle = injector
+    ///         .when_called(injectorpp::func!(fn (is_ready)() -> bool))
+    ///         .will_return_boolean(true);
+    ///     assert!(is_ready());
     ///
-    /// assert!(Path::new("/non/existent/path").exists());
+    ///     injector.restore(handle);
+    ///     assert!(!is_ready());
+    /// }
     /// ```
-    pub fn when_called(&mut self, func: FuncPtr) -> WhenCalledBuilder<'_> {
-        let when = WhenCalled::new(func.func_ptr_internal);
-        WhenCalledBuilder {
-            lib: self,
-            when,
-            expected_signature: func.signature,
-        }
+    pub fn with_capacity(capacity: usize) -> Self {
+        let injector = Self::new();
+        let _ = reserve_jit_memory(capacity);
+
+        injector
     }
 
-    /// Begins faking a function.
-    ///
-    /// Accepts a FuncPtr to the function you want to fake. Use the `func!` macro to obtain this pointer.
+    /// Creates a new `InjectorPP` instance with the given options.
     ///
-    /// # Parameters
-    ///
-    /// - `func`: A FuncPtr holds the pointer to the target function obtained via `func!` macro.
+    /// The options apply to every fake the injector installs, see `InjectorOptions`.
     ///
-    /// # Returns
+    /// # Example
     ///
-    /// A builder (`WhenCalledBuilder`) to further specify the fake behavior.
+    /// ```rust
+    /// use injectorpp::interface::injector::*;
     ///
-    /// # Safety
+    /// let injector = InjectorPP::new_with_options(
+    ///     InjectorOptions::new().jit_alloc_strategy(JitAllocStrategy::Hint(0x7000_0000)),
+    /// );
+    /// ```
+    pub fn new_with_options(options: InjectorOptions) -> Self {
+        let lock = FunctionLockGuard::acquire();
+
+        // Only one injector is alive at a time, so the setting is the one of this injector
+        // for as long as it lives.
+        set_jit_alloc_strategy(options.jit_alloc_strategy);
+        set_w_xor_x(options.w_xor_x);
+
+        Self {
+            id: NEXT_INJECTOR_ID.fetch_add(1, Ordering::Relaxed),
+            guards: Vec::new(),
+            installs: 0,
+            verifiers: Vec::new(),
+            hook_data: Vec::new(),
+            restore_hooks: Vec::new(),
+            call_counters: Vec::new(),
+            call_order: Arc::default(),
+            max_active_patches: None,
+            _lock: lock,
+        }
+    }
+
+    /// Runs `body` with a new injector and restores every fake it installed when `body`
+    /// returns, including when it panics.
     ///
-    /// This method is unsafe because it skips type check.
+    /// The injector cannot outlive `body`, so the fakes cannot leak into code running after
+    /// it. A panic of `body` propagates once the fakes are removed, so the original functions
+    /// are back by the time `catch_unwind` returns.
     ///
     /// # Example
     ///
     /// ```rust
     /// use injectorpp::interface::injector::*;
-    /// use std::path::Path;
     ///
-    /// fn fake_exists(_path: &Path) -> bool {
-    ///     true
+    /// fn is_online() -> bool {
+    ///     std::hint::black_box(false)
     /// }
     ///
-    /// let mut injector = InjectorPP::new();
-    ///
-    /// unsafe {
+    /// let seen = InjectorPP::scoped(|injector| {
     ///     injector
-    ///         .when_called_unchecked(injectorpp::func_unchecked!(Path::exists))
-    ///         .will_execute_raw_unchecked(injectorpp::func_unchecked!(fake_exists));
-    /// }
+    ///         .when_called(injectorpp::func!(fn (is_online)() -> bool))
+    ///         .will_return_boolean(true);
     ///
-    /// assert!(Path::new("/non/existent/path").exists());
+    ///     is_online()
+    /// });
+    ///
+    /// assert!(seen);
+    /// assert!(!is_online());
     /// ```
-    pub unsafe fn when_called_unchecked(&mut self, func: FuncPtr) -> WhenCalledBuilder<'_> {
-        let when = WhenCalled::new(func.func_ptr_internal);
-        WhenCalledBuilder {
-            lib: self,
-            when,
-            expected_signature: "",
-        }
+    pub fn scoped<R>(body: impl FnOnce(&mut InjectorPP) -> R) -> R {
+        let mut injector = InjectorPP::new();
+        body(&mut injector)
     }
 
-    /// Begins faking an asynchronous function.
-    ///
-    /// Accepts a pinned mutable reference to the async function future. Use the `async_func!` macro to obtain this reference.
+    /// Runs `body`, which installs fakes with this injector, writing all of their patches
+    /// before making them visible to the CPU at once.
     ///
-    /// # Parameters
-    ///
-    /// - `_`: A pinned mutable reference to the async function future.
-    ///
-    /// # Returns
+    /// Each patch normally makes the page of its function writable and flushes the
+    /// instruction cache on its own. In a batch each page is made writable once, and the
+    /// ranges written are flushed together when `body` returns, merging the ones that touch.
+    /// This saves system calls and cache maintenance when installing many fakes, see
+    /// `patch_stats`. Dropping the injector or rolling it back restores its fakes the same
+    /// way.
     ///
-    /// A builder (`WhenCalledBuilderAsync`) to further specify the async fake behavior.
+    /// Until `body` returns, the CPU may keep running the original code of the functions it
+    /// faked, so `body` should only install fakes, and other threads must not call the
+    /// functions being faked meanwhile.
     ///
     /// # Example
     ///
     /// ```rust
     /// use injectorpp::interface::injector::*;
     ///
-    /// async fn async_add_one(x: u32) -> u32 {
-    ///     x + 1
+    /// fn is_online() -> bool {
+    ///     false
     /// }
     ///
-    /// #[tokio::main]
-    /// async fn main() {
-    ///     let mut injector = InjectorPP::new();
+    /// fn retries() -> u8 {
+    ///     3
+    /// }
+    ///
+    /// let mut injector = InjectorPP::new();
+    /// injector.batch(|injector| {
     ///     injector
-    ///         .when_called_async(injectorpp::async_func!(async_add_one(u32::default()), u32))
-    ///         .will_return_async(injectorpp::async_return!(123, u32));
+    ///         .when_called(injectorpp::func!(fn (is_online)() -> bool))
+    ///         .will_return_boolean(true);
+    ///     injector
+    ///         .when_called(injectorpp::func!(fn (retries)() -> u8))
+    ///         .will_return(0u8);
+    /// });
     ///
-    ///     let result = async_add_one(5).await;
-    ///     assert_eq!(result, 123); // The patched value
-    /// }
+    /// assert!(is_online());
+    /// assert_eq!(retries(), 0);
     /// ```
-    pub fn when_called_async<F, T>(
-        &mut self,
-        fake_pair: (Pin<&mut F>, &'static str),
-    ) -> WhenCalledBuilderAsync<'_>
-    where
-        F: Future<Output = T>,
-    {
-        let poll_fn: fn(Pin<&mut F>, &mut Context<'_>) -> Poll<T> = <F as Future>::poll;
-        let when = WhenCalled::new(
-            crate::func!(poll_fn, fn(Pin<&mut F>, &mut Context<'_>) -> Poll<T>).func_ptr_internal,
-        );
+    pub fn batch<R>(&mut self, body: impl FnOnce(&mut InjectorPP) -> R) -> R {
+        let _batch = PatchBatch::begin();
+        body(self)
+    }
 
-        let signature = fake_pair.1;
-        WhenCalledBuilderAsync {
-            lib: self,
-            when,
-            expected_signature: signature,
+    /// Returns how many times patching changed page protections and flushed the instruction
+    /// cache in this process so far, to compare the cost of installing fakes one way or
+    /// another, see `batch`.
+    pub fn patch_stats() -> PatchStats {
+        let (protection_changes, cache_flushes) = patch_counters();
+
+        PatchStats {
+            protection_changes,
+            cache_flushes,
         }
     }
 
-    /// Begins faking an asynchronous function.
+    /// Limits how many fakes this injector can have installed at the same time.
     ///
-    /// Accepts a pinned mutable reference to the async function future. Use the `async_func!` macro to obtain this reference.
+    /// Once `limit` fakes are installed, `try_when_called` returns
+    /// `InjectError::PatchLimitExceeded` and the other `when_*` methods panic. Fakes removed by
+    /// `rollback` no longer count. This guards against a runaway loop installing fakes until
+    /// memory runs out.
     ///
-    /// # Parameters
+    /// # Example
     ///
-    /// - `_`: A pinned mutable reference to the async function future.
+    /// ```rust
+    /// use injectorpp::interface::injector::*;
     ///
-    /// # Returns
+    /// fn is_ready() -> bool {
+    ///     false
+    /// }
     ///
-    /// A builder (`WhenCalledBuilderAsync`) to further specify the async fake behavior.
+    /// let mut injector = InjectorPP::new();
+    /// injector.set_max_active_patches(1);
     ///
-    /// # Safety
+    /// injector
+    ///     .when_called(injectorpp::func!(fn (is_ready)() -> bool))
+    ///     .will_return_boolean(true);
     ///
-    /// This method is unsafe because it skips type check.
+    /// assert!(injector
+    ///     .try_when_called(injectorpp::func!(fn (is_ready)() -> bool))
+    ///     .is_err());
+    /// ```
+    pub fn set_max_active_patches(&mut self, limit: usize) -> &mut Self {
+        self.max_active_patches = Some(limit);
+        self
+    }
+
+    /// Temporarily restores the original behavior of a faked function.
+    ///
+    /// The fake stays registered: its call counts are kept and `enable` brings it back without
+    /// installing it again. Disabling a disabled fake does nothing.
     ///
     /// # Example
     ///
     /// ```rust
     /// use injectorpp::interface::injector::*;
     ///
-    /// async fn async_add_one(x: u32) -> u32 {
-    ///     x + 1
+    /// fn is_ready() -> bool {
+    ///     false
     /// }
     ///
-    /// #[tokio::main]
-    /// async fn main() {
-    ///     let mut injector = InjectorPP::new();
+    /// let mut injector = InjectorPP::new();
+    /// let handle = injector
+    ///     .when_called(injectorpp::func!(fn (is_ready)() -> bool))
+    ///     .will_return_boolean(true);
     ///
-    ///     unsafe {
-    ///         injector
-    ///             .when_called_async_unchecked(injectorpp::async_func_unchecked!(async_add_one(u32::default())))
-    ///             .will_return_async_unchecked(injectorpp::async_return_unchecked!(123, u32));
-    ///     }
+    /// injector.disable(handle);
+    /// assert!(!is_ready());
     ///
-    ///     let result = async_add_one(5).await;
-    ///     assert_eq!(result, 123); // The patched value
-    /// }
+    /// injector.enable(handle);
+    /// assert!(is_ready());
     /// ```
-    pub unsafe fn when_called_async_unchecked<F, T>(
-        &mut self,
-        _: Pin<&mut F>,
-    ) -> WhenCalledBuilderAsync<'_>
-    where
-        F: Future<Output = T>,
-    {
-        let poll_fn: fn(Pin<&mut F>, &mut Context<'_>) -> Poll<T> = <F as Future>::poll;
-        let when = WhenCalled::new(
-            crate::func!(poll_fn, fn(Pin<&mut F>, &mut Context<'_>) -> Poll<T>).func_ptr_internal,
-        );
-
-        WhenCalledBuilderAsync {
-            lib: self,
-            when,
-            expected_signature: "",
-        }
-    }
-}
-
-impl Default for InjectorPP {
-    fn default() -> Self {
-        Self::new()
+    pub fn disable(&mut self, handle: MockHandle) {
+        self.guard_mut(handle).set_enabled(false);
+        self.sync_patches(self.guard(handle).patched_range())
+            .unwrap_or_else(|error| panic!("{error}"));
     }
-}
-
-/// A guard that prevents injectorpp affecting the test while alive.
-///
-/// When this guard is held, no any injectorpp instance can be created.
-/// This is useful for threads that need to call functions with their
-/// original behavior.
-pub struct Preventer {
-    _lock: MutexGuard<'static, ()>,
-}
 
-impl Preventer {
-    /// Check if patching is currently prevented by this guard.
-    ///
-    /// This always returns `true` while the guard exists, as the guard
-    /// holds the global mutex that prevents patching.
-    pub fn is_active(&self) -> bool {
-        true
+    /// Re-applies a fake disabled by `disable`. Enabling an enabled fake does nothing.
+    pub fn enable(&mut self, handle: MockHandle) {
+        self.guard_mut(handle).set_enabled(true);
+        self.sync_patches(self.guard(handle).patched_range())
+            .unwrap_or_else(|error| panic!("{error}"));
     }
-}
-
-/// A builder that lets you chain patching operations.
-pub struct WhenCalledBuilder<'a> {
-    lib: &'a mut InjectorPP,
-    when: WhenCalled,
-    expected_signature: &'static str,
-}
 
-impl WhenCalledBuilder<'_> {
-    /// Fake the target function to branch to the provided function.
-    ///
-    /// Allows full customization of the faked function behavior by providing your own function or closure.
+    /// Removes the fake behind `handle` right away instead of when the injector is dropped.
     ///
-    /// # Parameters
+    /// The original bytes are written back, the JIT memory of the fake is freed and its
+    /// `on_restore` callbacks run. Other fakes stay installed, and call counts are still
+    /// verified when the injector is dropped. Restoring a fake that was already restored or
+    /// rolled back does nothing, but `handle` cannot be used with any other method anymore.
     ///
-    /// - `target`: A FuncPtr holds the pointer to the replacement function or closure. Using injectorpp::func! or injectorpp::closure! macros is recommended to obtain this pointer.
+    /// Panics if a fake installed later patches the same function, since it captured the
+    /// bytes of this one: restore that fake first.
     ///
     /// # Example
     ///
-    /// Using closure:
     /// ```rust
     /// use injectorpp::interface::injector::*;
-    /// use std::path::Path;
     ///
-    /// let fake_closure = |_: &Path| -> bool {
-    ///    true
-    /// };
+    /// fn is_ready() -> bool {
+    ///     false
+    /// }
     ///
     /// let mut injector = InjectorPP::new();
-    /// injector
-    ///     .when_called(injectorpp::func!(fn (Path::exists)(&Path) -> bool))
-    ///     .will_execute_raw(injectorpp::closure!(fake_closure, fn(&Path) -> bool));
+    /// let handle = injector
+    ///     .when_called(injectorpp::func!(fn (is_ready)() -> bool))
+    ///     .will_return_boolean(true);
+    /// assert!(is_ready());
     ///
-    /// assert!(Path::new("/nonexistent").exists());
+    /// injector.restore(handle);
+    /// assert!(!is_ready());
     /// ```
+    pub fn restore(&mut self, handle: MockHandle) {
+        self.check_handle(handle);
+
+        let Ok(position) = self
+            .guards
+            .binary_search_by_key(&handle.index, |(index, _)| *index)
+        else {
+            return;
+        };
+
+        let range = self.guards[position].1.patched_range();
+        if self.guards[position + 1..]
+            .iter()
+            .any(|(_, later)| later.overlaps(&range))
+        {
+            panic!("The fake cannot be restored before the later fakes of the same function");
+        }
+
+        let (index, guard) = self.guards.remove(position);
+        drop(guard);
+        self.restore_patches(range);
+
+        self.hook_data
+            .retain(|(data_index, _)| *data_index != index);
+        self.call_counters
+            .retain(|(counter_index, _, _)| *counter_index != index);
+
+        while let Some(position) = self
+            .restore_hooks
+            .iter()
+            .position(|(hook_index, _)| *hook_index == index)
+        {
+            self.restore_hooks.remove(position).1.run();
+        }
+    }
+
+    /// Returns whether the fake behind `handle` is currently applied.
+    pub fn is_enabled(&self, handle: MockHandle) -> bool {
+        self.guard(handle).is_enabled()
+    }
+
+    /// Returns whether `func` is currently faked by this injector.
+    ///
+    /// A function is faked from the moment a fake is installed until it is restored, rolled
+    /// back or the injector is dropped. It is not faked while all of its fakes are disabled.
+    ///
+    /// # Example
     ///
-    /// Using custom function:
     /// ```rust
     /// use injectorpp::interface::injector::*;
-    /// use std::path::Path;
     ///
-    /// fn fake_exists(_path: &Path) -> bool {
-    ///     true
+    /// fn is_ready() -> bool {
+    ///     false
     /// }
     ///
     /// let mut injector = InjectorPP::new();
-    /// injector
-    ///     .when_called(injectorpp::func!(fn (Path::exists)(&Path) -> bool))
-    ///     .will_execute_raw(injectorpp::func!(fn (fake_exists)(&Path) -> bool));
+    /// assert!(!injector.is_patched(injectorpp::func!(fn (is_ready)() -> bool)));
+    ///
+    /// let handle = injector
+    ///     .when_called(injectorpp::func!(fn (is_ready)() -> bool))
+    ///     .will_return_boolean(true);
+    /// assert!(injector.is_patched(injectorpp::func!(fn (is_ready)() -> bool)));
+    ///
+    /// injector.restore(handle);
+    /// assert!(!injector.is_patched(injectorpp::func!(fn (is_ready)() -> bool)));
+    /// ```
+    pub fn is_patched<F>(&self, func: FuncPtr<F>) -> bool {
+        let address = func.func_ptr_internal.as_ptr() as usize;
+
+        self.guards
+            .iter()
+            .any(|(_, guard)| guard.is_enabled() && guard.patched_range().start == address)
+    }
+
+    /// Returns the bytes written to install the fake behind `handle`, to investigate a fake
+    /// that does not behave as expected.
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// use injectorpp::interface::injector::*;
+    ///
+    /// #[inline(never)]
+    /// fn is_ready() -> bool {
+    ///     std::hint::black_box(false)
+    /// }
+    ///
+    /// let mut injector = InjectorPP::new();
+    /// let handle = injector
+    ///     .when_called(injectorpp::func!(fn (is_ready)() -> bool))
+    ///     .will_return_boolean(true);
+    ///
+    /// let debug = injector.debug_info(handle);
+    /// assert_eq!(debug.address, is_ready as fn() -> bool as usize);
+    /// assert_ne!(debug.patch_bytes, debug.original_bytes);
+    /// ```
+    pub fn debug_info(&self, handle: MockHandle) -> PatchDebug {
+        let guard = self.guard(handle);
+        let (jit_memory, jit_bytes) = guard.jit_code();
+
+        PatchDebug {
+            address: guard.patched_range().start,
+            original_bytes: guard.original_bytes().to_vec(),
+            patch_bytes: guard.patch_bytes().to_vec(),
+            jit_address: jit_memory as usize,
+            jit_bytes,
+            branch_kind: WhenCalled::branch_kind(guard.patch_bytes()),
+        }
+    }
+
+    /// Makes the first call to the fake of `handle` through `call`, turning a crash of the
+    /// fake into an error.
+    ///
+    /// `call` runs on a new thread while handlers for `SIGSEGV`, `SIGBUS`, `SIGILL` and
+    /// `SIGTRAP` are installed. If the patch or its JIT code is faulty and raises one of them,
+    /// this returns `InjectError::PatchFaulted` with the `debug_info` of the fake instead of
+    /// the test runner dying without a word. Otherwise it returns what `call` returned, and
+    /// resumes its panic if it panicked.
+    ///
+    /// This is a diagnostic for injectorpp itself and has limitations:
+    ///
+    /// - Signal handlers are process-global. While `call` runs, a fault of any other thread
+    ///   terminates the process with the default action of the signal, bypassing handlers
+    ///   such as the stack overflow report of Rust. Calls to `safe_verify` are serialized.
+    /// - The faulting thread cannot resume, so it is parked forever and leaked together with
+    ///   what `call` owns.
+    /// - Fakes installed with `when_called_current_thread_only` do not apply to `call`.
+    ///
+    /// Only available on Linux and macOS with the `safe-verify` feature.
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// use injectorpp::interface::injector::*;
+    ///
+    /// fn is_ready() -> bool {
+    ///     false
+    /// }
+    ///
+    /// let mut injector = InjectorPP::new();
+    /// let handle = injector
+    ///     .when_called(injectorpp::func!(fn (is_ready)() -> bool))
+    ///     .will_return_boolean(true);
+    ///
+    /// assert_eq!(injector.safe_verify(handle, is_ready), Ok(true));
+    /// ```
+    #[cfg(all(feature = "safe-verify", any(target_os = "linux", target_os = "macos")))]
+    pub fn safe_verify<R: Send + 'static>(
+        &self,
+        handle: MockHandle,
+        call: impl FnOnce() -> R + Send + 'static,
+    ) -> Result<R, InjectError> {
+        let debug = self.debug_info(handle);
+
+        crate::injector_core::fault_catcher::run_catching_faults(call).map_err(|signal| {
+            InjectError::PatchFaulted {
+                signal,
+                debug: Box::new(debug),
+            }
+        })
+    }
+
+    /// Enables a fake until the returned token is dropped.
+    ///
+    /// When the token is dropped the fake goes back to the state it was in before, so a fake
+    /// disabled outside the token is only effective inside the scope that holds it. Call counts
+    /// are kept across scopes.
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// use injectorpp::interface::injector::*;
+    ///
+    /// fn is_ready() -> bool {
+    ///     false
+    /// }
+    ///
+    /// let mut injector = InjectorPP::new();
+    /// let handle = injector
+    ///     .when_called(injectorpp::func!(fn (is_ready)() -> bool))
+    ///     .will_return_boolean(true);
+    /// injector.disable(handle);
+    ///
+    /// {
+    ///     let _scope = injector.enable_scoped(handle);
+    ///     assert!(is_ready());
+    /// }
+    ///
+    /// assert!(!is_ready());
+    /// ```
+    pub fn enable_scoped(&mut self, handle: MockHandle) -> ScopedMock<'_> {
+        let was_enabled = self.is_enabled(handle);
+        self.enable(handle);
+
+        ScopedMock {
+            injector: self,
+            handle,
+            was_enabled,
+        }
+    }
+
+    /// Returns how many times the fake of `handle` was called so far.
+    ///
+    /// Panics if the fake was not installed after `WhenCalledBuilder::count_calls`.
+    pub fn call_count(&self, handle: MockHandle) -> usize {
+        self.call_counter(handle).2.load(Ordering::SeqCst)
+    }
+
+    /// Checks that the fake of `handle` was called exactly `expected` times so far.
+    ///
+    /// Unlike the `times` of `fake!`, which is checked when the injector is dropped, this can
+    /// be called at any point and works for every kind of fake. The failure is reported like
+    /// the other verification failures, see `set_failure_sink`. Panics if the fake was not
+    /// installed after `WhenCalledBuilder::count_calls`.
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// use injectorpp::interface::injector::*;
+    ///
+    /// fn is_ready() -> bool {
+    ///     false
+    /// }
+    ///
+    /// let mut injector = InjectorPP::new();
+    /// let handle = injector
+    ///     .when_called(injectorpp::func!(fn (is_ready)() -> bool))
+    ///     .count_calls()
+    ///     .will_return_boolean(true);
+    ///
+    /// injector.verify_called_times(handle, 0);
+    /// assert!(is_ready());
+    /// assert!(is_ready());
+    /// injector.verify_called_times(handle, 2);
+    /// ```
+    pub fn verify_called_times(&self, handle: MockHandle, expected: usize) {
+        let (_, function, calls) = self.call_counter(handle);
+        let actual = calls.load(Ordering::SeqCst);
+
+        if actual != expected {
+            fail(Failure::CallCount {
+                function: *function,
+                expected,
+                actual,
+            });
+        }
+    }
+
+    /// Checks that the fake of `handle` was never called so far, e.g. to confirm that a code
+    /// path returned early before reaching it.
+    ///
+    /// This is `verify_called_times(handle, 0)`: the failure reports how many times the fake
+    /// was actually called. It works for every kind of fake, including fixed return values
+    /// such as `will_return_boolean`, since `count_calls` adds the counter to the patched
+    /// code itself. Panics if the fake was not installed after
+    /// `WhenCalledBuilder::count_calls`.
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// use injectorpp::interface::injector::*;
+    ///
+    /// fn is_cached() -> bool {
+    ///     false
+    /// }
+    ///
+    /// fn load(skip: bool) -> bool {
+    ///     skip || is_cached()
+    /// }
+    ///
+    /// let mut injector = InjectorPP::new();
+    /// let handle = injector
+    ///     .when_called(injectorpp::func!(fn (is_cached)() -> bool))
+    ///     .count_calls()
+    ///     .will_return_boolean(true);
+    ///
+    /// assert!(load(true));
+    /// injector.verify_never_called(handle);
+    /// ```
+    pub fn verify_never_called(&self, handle: MockHandle) {
+        self.verify_called_times(handle, 0);
+    }
+
+    /// Checks that at least one call captured by `capture` had arguments matching
+    /// `expected`, a tuple with one value per argument.
+    ///
+    /// Arguments are compared with their captured form, so a `&str` argument can be
+    /// expected as a `&str` or a `String`. Use `AnyArg` for arguments that do not matter.
+    /// Reports a `Failure::CalledWith` otherwise, listing how many calls were captured.
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// use injectorpp::interface::injector::*;
+    ///
+    /// fn connect(_host: &str, _port: u16) -> bool {
+    ///     unimplemented!();
+    /// }
+    ///
+    /// let mut injector = InjectorPP::new();
+    /// let capture = injector
+    ///     .when_called(injectorpp::func!(fn (connect)(&str, u16) -> bool))
+    ///     .will_capture(|_: &str, _: u16| true);
+    ///
+    /// assert!(connect("example.com", 443));
+    /// assert!(connect("localhost", 8080));
+    ///
+    /// injector.verify_called_with(&capture, ("localhost", 8080));
+    /// injector.verify_called_with(&capture, ("example.com", AnyArg));
+    /// ```
+    pub fn verify_called_with<T>(&self, capture: &Capture<T>, expected: impl ArgsMatcher<T>) {
+        if !capture.was_called_with(expected) {
+            fail(Failure::CalledWith {
+                function: capture.function(),
+                calls: capture.call_count(),
+            });
+        }
+    }
+
+    /// Returns the fakes installed after `WhenCalledBuilder::record_call_order` in the order
+    /// they were called, one entry per call.
+    ///
+    /// Calls from every thread are recorded in a single order, the one in which they entered
+    /// the fakes.
+    pub fn call_sequence(&self) -> Vec<MockHandle> {
+        self.call_order
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .iter()
+            .map(|&index| MockHandle {
+                injector_id: self.id,
+                index,
+            })
+            .collect()
+    }
+
+    /// Checks that the fakes of `handles` were called in this order so far.
+    ///
+    /// Each fake must have been called after the previous one was, other calls may happen in
+    /// between. The fakes must have been installed after
+    /// `WhenCalledBuilder::record_call_order`, see `call_sequence`. The failure is reported
+    /// like the other verification failures, see `set_failure_sink`.
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// use injectorpp::interface::injector::*;
+    ///
+    /// fn init() -> bool {
+    ///     std::hint::black_box(false)
+    /// }
+    ///
+    /// fn connect() -> bool {
+    ///     std::hint::black_box(false)
+    /// }
+    ///
+    /// let mut injector = InjectorPP::new();
+    /// let init_handle = injector
+    ///     .when_called(injectorpp::func!(fn (init)() -> bool))
+    ///     .record_call_order()
+    ///     .will_return_boolean(true);
+    /// let connect_handle = injector
+    ///     .when_called(injectorpp::func!(fn (connect)() -> bool))
+    ///     .record_call_order()
+    ///     .will_return_boolean(true);
+    ///
+    /// assert!(init());
+    /// assert!(connect());
+    /// injector.assert_called_in_order(&[init_handle, connect_handle]);
+    /// ```
+    pub fn assert_called_in_order(&self, handles: &[MockHandle]) {
+        let expected: Vec<usize> = handles
+            .iter()
+            .map(|&handle| {
+                self.check_handle(handle);
+                handle.index
+            })
+            .collect();
+        let actual = self
+            .call_order
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .clone();
+
+        if let Some(step) = first_out_of_order(&expected, &actual) {
+            fail(Failure::CallOrder {
+                step,
+                expected,
+                actual,
+            });
+        }
+    }
+
+    /// Marks the fakes installed so far, so that `rollback` can remove the ones added later.
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// use injectorpp::interface::injector::*;
+    ///
+    /// fn is_ready() -> bool {
+    ///     false
+    /// }
+    ///
+    /// fn is_busy() -> bool {
+    ///     false
+    /// }
+    ///
+    /// let mut injector = InjectorPP::new();
+    /// injector
+    ///     .when_called(injectorpp::func!(fn (is_ready)() -> bool))
+    ///     .will_return_boolean(true);
+    ///
+    /// let base = injector.checkpoint();
+    /// injector
+    ///     .when_called(injectorpp::func!(fn (is_busy)() -> bool))
+    ///     .will_return_boolean(true);
+    ///
+    /// injector.rollback(base);
+    /// assert!(is_ready());
+    /// assert!(!is_busy());
+    /// ```
+    pub fn checkpoint(&self) -> Checkpoint {
+        Checkpoint {
+            injector_id: self.id,
+            installs: self.installs,
+            verifiers: self.verifiers.len(),
+        }
+    }
+
+    /// Removes every fake installed after `checkpoint`, most recent first.
+    ///
+    /// Fakes installed before the checkpoint are kept as they are. The call counts of the
+    /// removed fakes are verified right away, as if their injector had been dropped, and
+    /// their `MockHandle`s can no longer be used.
+    pub fn rollback(&mut self, checkpoint: Checkpoint) {
+        if checkpoint.injector_id != self.id {
+            panic!("The Checkpoint was created by another InjectorPP instance");
+        }
+
+        self.restore_guards(checkpoint.installs);
+
+        self.hook_data
+            .retain(|(index, _)| *index < checkpoint.installs);
+        self.call_counters
+            .retain(|(index, _, _)| *index < checkpoint.installs);
+        self.verify_calls(checkpoint.verifiers);
+    }
+
+    /// Checks that installing, running and restoring fakes works on this platform.
+    ///
+    /// Fakes private functions of injectorpp with a closure and with a fixed value, calls them
+    /// to verify the fakes took effect, then drops the fakes and verifies the original
+    /// behavior is back. Call it once before relying on injectorpp, e.g. at the start of a
+    /// test run on a new target.
+    ///
+    /// Like `new`, this waits until no other injector is alive, so it must not be called
+    /// while the current thread holds one.
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// use injectorpp::interface::injector::*;
+    ///
+    /// InjectorPP::self_test().expect("injectorpp does not work on this platform");
+    /// ```
+    pub fn self_test() -> Result<(), InjectError> {
+        let check = |passed: bool, step: &'static str| {
+            passed
+                .then_some(())
+                .ok_or(InjectError::SelfTestFailed { step })
+        };
+
+        {
+            let mut injector = InjectorPP::new();
+            injector
+                .when_called(crate::func!(fn (self_test_scale)(u64) -> u64))
+                .will_execute(|value: u64| value * 3);
+            injector
+                .when_called(crate::func!(fn (self_test_is_faked)() -> bool))
+                .will_return_boolean(true);
+
+            check(self_test_scale(7) == 21, "faking with a closure")?;
+            check(self_test_is_faked(), "faking with a fixed value")?;
+        }
+
+        check(
+            self_test_scale(7) == 14,
+            "restoring a function faked with a closure",
+        )?;
+        check(
+            !self_test_is_faked(),
+            "restoring a function faked with a fixed value",
+        )
+    }
+
+    /// Sends every verification failure to `sink` before panicking, for all injectors.
+    ///
+    /// A failure is a fake called a different number of times than its `times`, or an
+    /// `Expectations` not met by its `Sequence`. The panic message is unchanged, the sink
+    /// additionally receives the details as a `Failure`, e.g. to print `Failure::to_json` for
+    /// CI tooling. Replaces the previous sink, see `clear_failure_sink`.
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// use injectorpp::interface::injector::*;
+    ///
+    /// InjectorPP::set_failure_sink(|failure| eprintln!("{}", failure.to_json()));
+    /// # InjectorPP::clear_failure_sink();
+    /// ```
+    pub fn set_failure_sink(sink: impl Fn(&Failure) + Send + Sync + 'static) {
+        set_failure_sink(Some(std::sync::Arc::new(sink)));
+    }
+
+    /// Removes the sink installed by `set_failure_sink`.
+    pub fn clear_failure_sink() {
+        set_failure_sink(None);
+    }
+
+    /// Calls `hook` every time the original code of a function faked by any injector is
+    /// written back, with the JIT memory freed for its fake.
+    ///
+    /// Together with `live_patch_count`, this helps tracking down fakes that are never
+    /// removed in long running test processes. The hook runs while the fake is dropped, often
+    /// while a failed test unwinds, so it must not panic. Replaces the previous hook, see
+    /// `clear_on_restore`.
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// use injectorpp::interface::injector::*;
+    ///
+    /// InjectorPP::on_restore(|info| {
+    ///     eprintln!("freed {} byte(s) at {:#x}", info.jit_size, info.jit_address)
+    /// });
+    /// # InjectorPP::clear_on_restore();
+    /// ```
+    pub fn on_restore(hook: impl Fn(&RestoreInfo) + Send + Sync + 'static) {
+        set_restore_sink(Some(Arc::new(hook)));
+    }
+
+    /// Removes the hook installed by `on_restore`.
+    pub fn clear_on_restore() {
+        set_restore_sink(None);
+    }
+
+    /// Returns how many fakes, of all injectors, are currently installed.
+    ///
+    /// Disabled fakes count until they are restored. A fake whose original code could not be
+    /// written back, see `take_restore_failures`, stays counted since its JIT memory is
+    /// leaked.
+    pub fn live_patch_count() -> usize {
+        live_patch_count()
+    }
+
+    /// Returns how many bytes of JIT memory are currently mapped for the fakes of all
+    /// injectors.
+    ///
+    /// Fakes of up to a page share pages, which are counted whole until their last fake is
+    /// restored. Together with `live_patch_count`, this helps budgeting the executable memory
+    /// a large test suite needs. Only available with the `metrics` feature, which adds the
+    /// bookkeeping to every JIT allocation.
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// use injectorpp::interface::injector::*;
+    ///
+    /// fn is_ready() -> bool {
+    ///     false
+    /// }
+    ///
+    /// let mut injector = InjectorPP::new();
+    /// injector
+    ///     .when_called(injectorpp::func!(fn (is_ready)() -> bool))
+    ///     .will_return_boolean(true);
+    ///
+    /// assert!(InjectorPP::jit_bytes_used() > 0);
+    /// ```
+    #[cfg(feature = "metrics")]
+    pub fn jit_bytes_used() -> usize {
+        jit_bytes_used()
+    }
+
+    /// Returns the fakes, of all injectors, whose original code could not be written back
+    /// since the last call, and forgets them.
+    ///
+    /// Removing a fake happens while dropping, often while a failed test unwinds, so it never
+    /// panics: a failure, e.g. the code page could not be made writable again, is printed,
+    /// sent to the failure sink and kept here as a `Failure::Restore` instead. The function
+    /// then still runs its fake, whose JIT memory is leaked.
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// use injectorpp::interface::injector::*;
+    ///
+    /// fn is_ready() -> bool {
+    ///     false
+    /// }
+    ///
+    /// {
+    ///     let mut injector = InjectorPP::new();
+    ///     injector
+    ///         .when_called(injectorpp::func!(fn (is_ready)() -> bool))
+    ///         .will_return_boolean(true);
+    /// }
+    ///
+    /// assert!(InjectorPP::take_restore_failures().is_empty());
+    /// ```
+    pub fn take_restore_failures() -> Vec<Failure> {
+        take_restore_failures()
+    }
+
+    /// Prevents injectorpp from other threads to change the functions.
+    /// This is useful when the test does not want to be affected by injectorpp usage in other threads.
+    ///
+    /// The guard is process-wide, see `prevent_all`.
+    pub fn prevent() -> Preventer {
+        let lock = FunctionLockGuard::acquire();
+        Preventer { _lock: lock }
+    }
+
+    /// Blocks every fake in the process until the returned guard is dropped.
+    ///
+    /// Waits until every injector alive on other threads is dropped, so all their fakes are
+    /// restored, then keeps any new injector from being created: `InjectorPP::new` on another
+    /// thread blocks until the guard is dropped, and so does any `when_called` that would
+    /// follow it. Code running while the guard is held therefore only observes original
+    /// behavior. Like `new`, this panics if the current thread already holds an injector or
+    /// a `Preventer`.
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// use injectorpp::interface::injector::*;
+    ///
+    /// fn is_online() -> bool {
+    ///     false
+    /// }
+    ///
+    /// {
+    ///     let _guard = InjectorPP::prevent_all();
+    ///     assert!(!is_online());
+    /// }
+    ///
+    /// let mut injector = InjectorPP::new();
+    /// injector
+    ///     .when_called(injectorpp::func!(fn (is_online)() -> bool))
+    ///     .will_return_boolean(true);
+    /// assert!(is_online());
+    /// ```
+    pub fn prevent_all() -> Preventer {
+        Self::prevent()
+    }
+
+    /// Begins faking a function.
+    ///
+    /// Accepts a FuncPtr to the function you want to fake. Use the `func!` macro to obtain this pointer.
+    ///
+    /// # Parameters
+    ///
+    /// - `func`: A FuncPtr holds the pointer to the target function obtained via `func!` macro.
+    ///
+    /// # Returns
+    ///
+    /// A builder (`WhenCalledBuilder`) to further specify the fake behavior.
+    ///
+    /// Calls the compiler inlined into their callers never reach the patched function, so
+    /// mark functions faked this way `#[inline(never)]` rather than `#[inline(always)]`.
+    ///
+    /// # Panics
+    ///
+    /// Panics with the errors `try_when_called` returns, e.g. when `func` does not point
+    /// into executable memory.
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// use injectorpp::interface::injector::*;
+    /// use std::path::Path;
+    ///
+    /// fn fake_exists(_path: &Path) -> bool {
+    ///     true
+    /// }
+    ///
+    /// let mut injector = InjectorPP::new();
+    /// injector
+    ///     .when_called(injectorpp::func!(fn (Path::exists)(&Path) -> bool))
+    ///     .will_execute_raw(injectorpp::func!(fn (fake_exists)(&Path) -> bool));
+    ///
+    /// assert!(Path::new("/non/existent/path").exists());
+    /// ```
+    pub fn when_called<F>(&mut self, func: FuncPtr<F>) -> WhenCalledBuilder<'_, F> {
+        self.try_when_called(func)
+            .unwrap_or_else(|error| panic!("{error}"))
+    }
+
+    /// Begins faking a function only for the calls whose arguments `predicate` accepts. The
+    /// other calls run the original function.
+    ///
+    /// The predicate receives references to the arguments, is called on every call and must
+    /// not capture references, like the closures of `will_execute`. The instructions the
+    /// patch overwrites are copied next to the fake so the original function can still run,
+    /// see `WhenCalledBuilder::will_map` for the functions this supports. Functions called
+    /// by the predicate itself always run their original code, so a predicate calling the
+    /// function it guards does not recurse.
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// use injectorpp::interface::injector::*;
+    ///
+    /// #[inline(never)]
+    /// fn classify(n: i32) -> &'static str {
+    ///     if std::hint::black_box(n) < 0 {
+    ///         "negative"
+    ///     } else {
+    ///         "non-negative"
+    ///     }
+    /// }
+    ///
+    /// let mut injector = InjectorPP::new();
+    /// injector
+    ///     .when_called_with(
+    ///         injectorpp::func!(fn (classify)(i32) -> &'static str),
+    ///         |n: &i32| *n < 0,
+    ///     )
+    ///     .will_execute(|_: i32| "fake");
+    ///
+    /// assert_eq!(classify(-1), "fake");
+    /// assert_eq!(classify(1), "non-negative");
+    /// ```
+    pub fn when_called_with<P: Sync + 'static, F>(
+        &mut self,
+        func: FuncPtr<F>,
+        predicate: P,
+    ) -> WhenCalledWithBuilder<'_, P> {
+        WhenCalledWithBuilder {
+            builder: self.when_called(func.untyped()),
+            predicate,
+        }
+    }
+
+    /// Begins faking a function only for the calls made on the current thread. Other threads
+    /// keep running the original function, even while the fake is installed.
+    ///
+    /// The patch itself is shared by all threads, so every call goes through a check of the
+    /// calling thread, which makes the fake slower than one installed with `when_called`.
+    /// The instructions the patch overwrites are copied next to the fake so the original
+    /// function can still run, see `WhenCalledBuilder::will_map` for the functions this
+    /// supports.
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// use injectorpp::interface::injector::*;
+    ///
+    /// #[inline(never)]
+    /// fn worker_count() -> usize {
+    ///     std::hint::black_box(4)
+    /// }
+    ///
+    /// let mut injector = InjectorPP::new();
+    /// injector
+    ///     .when_called_current_thread_only(injectorpp::func!(fn (worker_count)() -> usize))
+    ///     .will_execute(|| -> usize { 1 });
+    ///
+    /// assert_eq!(worker_count(), 1);
+    /// assert_eq!(std::thread::spawn(worker_count).join().unwrap(), 4);
+    /// ```
+    pub fn when_called_current_thread_only<F>(
+        &mut self,
+        func: FuncPtr<F>,
+    ) -> WhenCalledCurrentThreadBuilder<'_> {
+        WhenCalledCurrentThreadBuilder {
+            builder: self.when_called(func.untyped()),
+            thread: std::thread::current().id(),
+        }
+    }
+
+    /// Begins faking a function, failing instead of panicking when it cannot be patched.
+    ///
+    /// Behaves like `when_called`, but returns:
+    ///
+    /// - `InjectError::PatchLimitExceeded` once the limit set by `set_max_active_patches` is
+    ///   reached,
+    /// - `InjectError::UnreadableMemory` when the bytes the patch overwrites are not readable,
+    /// - `InjectError::NotExecutable` when the address is not in executable memory, so it is
+    ///   not the code of a function,
+    /// - `InjectError::AllocationFailed` when no JIT memory can be allocated,
+    /// - `InjectError::OutOfBranchRange` when no JIT memory can be allocated close enough to
+    ///   the function for the patch to branch to it,
+    /// - `InjectError::PatchWindowTooSmall` when the function is too short for the patch.
+    ///
+    /// These are checked up front and nothing is written, so the injector stays usable after
+    /// an error.
+    pub fn try_when_called<F>(
+        &mut self,
+        func: FuncPtr<F>,
+    ) -> Result<WhenCalledBuilder<'_, F>, InjectError> {
+        self.check_patch_limit()?;
+
+        let when = WhenCalled::new(func.func_ptr_internal);
+        when.check_patch_site()?;
+        Ok(WhenCalledBuilder {
+            lib: self,
+            when,
+            expected_signature: func.signature,
+            function_type: PhantomData,
+        })
+    }
+
+    /// Begins faking a function.
+    ///
+    /// Accepts a FuncPtr to the function you want to fake. Use the `func!` macro to obtain this pointer.
+    ///
+    /// # Parameters
+    ///
+    /// - `func`: A FuncPtr holds the pointer to the target function obtained via `func!` macro.
+    ///
+    /// # Returns
+    ///
+    /// A builder (`WhenCalledBuilder`) to further specify the fake behavior.
+    ///
+    /// # Safety
+    ///
+    /// This method is unsafe because it skips type check.
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// use injectorpp::interface::injector::*;
+    /// use std::path::Path;
+    ///
+    /// fn fake_exists(_path: &Path) -> bool {
+    ///     true
+    /// }
+    ///
+    /// let mut injector = InjectorPP::new();
+    ///
+    /// unsafe {
+    ///     injector
+    ///         .when_called_unchecked(injectorpp::func_unchecked!(Path::exists))
+    ///         .will_execute_raw_unchecked(injectorpp::func_unchecked!(fake_exists));
+    /// }
+    ///
+    /// assert!(Path::new("/non/existent/path").exists());
+    /// ```
+    pub unsafe fn when_called_unchecked<F>(&mut self, func: FuncPtr<F>) -> WhenCalledBuilder<'_> {
+        self.assert_patch_limit();
+
+        let when = WhenCalled::new(func.func_ptr_internal);
+        WhenCalledBuilder {
+            lib: self,
+            when,
+            expected_signature: "",
+            function_type: PhantomData,
+        }
+    }
+
+    /// Begins faking a function resolved by its symbol name.
+    ///
+    /// This is useful for functions that can only be named by their linker symbol, such as
+    /// functions declared with `#[no_mangle]` or `#[export_name = "..."]`. The symbol is
+    /// looked up through the dynamic loader first. On Linux the symbol table of the running
+    /// executable is searched as well, so functions of the test binary itself are found even
+    /// though they are not exported dynamically. This includes functions of static libraries
+    /// linked into the test binary, as long as the linker kept them: see the README for the
+    /// effect of unreferenced functions, `-C link-dead-code` and identical code folding.
+    ///
+    /// # Parameters
+    ///
+    /// - `name`: The exact symbol name, e.g. the string given to `#[export_name]`.
+    ///
+    /// # Returns
+    ///
+    /// A builder (`WhenCalledBuilder`) to further specify the fake behavior.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the symbol cannot be resolved.
+    ///
+    /// # Safety
+    ///
+    /// This method is unsafe because the signature of the resolved function is unknown and
+    /// cannot be checked. The caller must make sure the fake matches it.
+    ///
+    /// # Example
+    ///
+    /// ```rust,no_run
+    /// use injectorpp::interface::injector::*;
+    ///
+    /// #[export_name = "my_sym"]
+    /// pub fn exported() -> i32 {
+    ///     1
+    /// }
+    ///
+    /// fn fake_exported() -> i32 {
+    ///     2
+    /// }
+    ///
+    /// let mut injector = InjectorPP::new();
+    ///
+    /// unsafe {
+    ///     injector
+    ///         .when_named("my_sym")
+    ///         .will_execute_raw_unchecked(injectorpp::func_unchecked!(fake_exported));
+    /// }
+    ///
+    /// assert_eq!(exported(), 2);
+    /// ```
+    pub unsafe fn when_named(&mut self, name: &str) -> WhenCalledBuilder<'_> {
+        self.assert_patch_limit();
+
+        let func =
+            resolve_symbol(name).unwrap_or_else(|| panic!("Failed to resolve symbol {name:?}"));
+
+        WhenCalledBuilder {
+            lib: self,
+            when: WhenCalled::new(func),
+            expected_signature: "",
+            function_type: PhantomData,
+        }
+    }
+
+    /// Begins faking a function of a shared library, such as the C library, by its symbol name.
+    ///
+    /// Use this for `extern "C"` functions only known by name. The symbol is resolved through
+    /// the dynamic loader, `dlsym(RTLD_DEFAULT, ...)` on Linux and macOS and `GetProcAddress`
+    /// on every loaded module on Windows. If that yields a PLT entry or an import thunk, the
+    /// actual implementation it jumps to is patched instead, so every caller sees the fake.
+    /// Unlike `when_named`, the symbol table of the executable is not searched.
+    ///
+    /// # Parameters
+    ///
+    /// - `name`: The exact exported symbol name, e.g. `"getpid"`.
+    ///
+    /// # Returns
+    ///
+    /// A builder (`WhenCalledBuilder`) to further specify the fake behavior.
+    ///
+    /// # Panics
+    ///
+    /// Panics if no loaded library exports the symbol.
+    ///
+    /// # Safety
+    ///
+    /// This method is unsafe because the signature of the resolved function is unknown and
+    /// cannot be checked. The caller must make sure the fake matches it.
+    ///
+    /// # Example
+    ///
+    /// ```rust,no_run
+    /// use injectorpp::interface::injector::*;
+    ///
+    /// extern "C" fn fake_getpid() -> i32 {
+    ///     4242
+    /// }
+    ///
+    /// let mut injector = InjectorPP::new();
+    ///
+    /// unsafe {
+    ///     injector
+    ///         .when_called_symbol("getpid")
+    ///         .will_execute_raw_unchecked(injectorpp::func_unchecked!(fake_getpid));
+    /// }
+    ///
+    /// assert_eq!(std::process::id(), 4242);
+    /// ```
+    pub unsafe fn when_called_symbol(&mut self, name: &str) -> WhenCalledBuilder<'_> {
+        self.assert_patch_limit();
+
+        let func = resolve_library_symbol(name)
+            .unwrap_or_else(|| panic!("Failed to resolve symbol {name:?}"));
+
+        WhenCalledBuilder {
+            lib: self,
+            when: WhenCalled::new(func),
+            expected_signature: "",
+            function_type: PhantomData,
+        }
+    }
+
+    /// Begins faking a function exported by a library the test loaded at runtime.
+    ///
+    /// `handle` is the library as returned by `dlopen` on Linux and macOS or `LoadLibrary` on
+    /// Windows, and `name` is looked up in that library only, with `dlsym` or
+    /// `GetProcAddress`. This tells apart functions of the same name in several plugins,
+    /// and finds functions of libraries loaded with `RTLD_LOCAL`, which `when_called_symbol`
+    /// cannot see.
+    ///
+    /// Like any fake, the patch is removed when the injector is dropped or the fake restored,
+    /// whether the library is still loaded or not, so unload the library only after that.
+    ///
+    /// # Parameters
+    ///
+    /// - `handle`: The handle of the loaded library.
+    /// - `name`: The exact exported symbol name.
+    ///
+    /// # Returns
+    ///
+    /// A builder (`WhenCalledBuilder`) to further specify the fake behavior.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the library does not export the symbol.
+    ///
+    /// # Safety
+    ///
+    /// This method is unsafe because the signature of the resolved function is unknown and
+    /// cannot be checked. The caller must make sure the fake matches it, and that `handle`
+    /// is a library that stays loaded while the fake is installed.
+    ///
+    /// # Example
+    ///
+    /// ```rust,no_run
+    /// use injectorpp::interface::injector::*;
+    ///
+    /// extern "C" fn fake_plugin_version() -> i32 {
+    ///     2
+    /// }
+    ///
+    /// # #[cfg(unix)]
+    /// # {
+    /// let handle = unsafe { libc::dlopen(c"./libplugin.so".as_ptr(), libc::RTLD_NOW) };
+    ///
+    /// let mut injector = InjectorPP::new();
+    ///
+    /// unsafe {
+    ///     injector
+    ///         .when_called_in_library(handle, "plugin_version")
+    ///         .will_execute_raw_unchecked(injectorpp::func_unchecked!(fake_plugin_version));
+    /// }
+    /// # }
+    /// ```
+    pub unsafe fn when_called_in_library(
+        &mut self,
+        handle: *mut std::ffi::c_void,
+        name: &str,
+    ) -> WhenCalledBuilder<'_> {
+        self.assert_patch_limit();
+
+        let func = resolve_symbol_in_library(handle, name)
+            .unwrap_or_else(|| panic!("Failed to resolve symbol {name:?} in the library"));
+
+        WhenCalledBuilder {
+            lib: self,
+            when: WhenCalled::new(func),
+            expected_signature: "",
+            function_type: PhantomData,
+        }
+    }
+
+    /// Begins faking an asynchronous function.
+    ///
+    /// Accepts a pinned mutable reference to the async function future. Use the `async_func!` macro to obtain this reference.
+    ///
+    /// # Parameters
+    ///
+    /// - `_`: A pinned mutable reference to the async function future.
+    ///
+    /// # Returns
+    ///
+    /// A builder (`WhenCalledBuilderAsync`) to further specify the async fake behavior.
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// use injectorpp::interface::injector::*;
+    ///
+    /// async fn async_add_one(x: u32) -> u32 {
+    ///     x + 1
+    /// }
+    ///
+    /// #[tokio::main]
+    /// async fn main() {
+    ///     let mut injector = InjectorPP::new();
+    ///     injector
+    ///         .when_called_async(injectorpp::async_func!(async_add_one(u32::default()), u32))
+    ///         .will_return_async(injectorpp::async_return!(123, u32));
+    ///
+    ///     let result = async_add_one(5).await;
+    ///     assert_eq!(result, 123); // The patched value
+    /// }
+    /// ```
+    pub fn when_called_async<F, T>(
+        &mut self,
+        fake_pair: (Pin<&mut F>, &'static str),
+    ) -> WhenCalledBuilderAsync<'_>
+    where
+        F: Future<Output = T>,
+    {
+        self.assert_patch_limit();
+
+        let poll_fn: fn(Pin<&mut F>, &mut Context<'_>) -> Poll<T> = <F as Future>::poll;
+        let when = WhenCalled::new(
+            crate::func!(poll_fn, fn(Pin<&mut F>, &mut Context<'_>) -> Poll<T>).func_ptr_internal,
+        );
+
+        let signature = fake_pair.1;
+        WhenCalledBuilderAsync {
+            lib: self,
+            when,
+            expected_signature: signature,
+        }
+    }
+
+    /// Begins faking an asynchronous function.
+    ///
+    /// Accepts a pinned mutable reference to the async function future. Use the `async_func!` macro to obtain this reference.
+    ///
+    /// # Parameters
+    ///
+    /// - `_`: A pinned mutable reference to the async function future.
+    ///
+    /// # Returns
+    ///
+    /// A builder (`WhenCalledBuilderAsync`) to further specify the async fake behavior.
+    ///
+    /// # Safety
+    ///
+    /// This method is unsafe because it skips type check.
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// use injectorpp::interface::injector::*;
+    ///
+    /// async fn async_add_one(x: u32) -> u32 {
+    ///     x + 1
+    /// }
+    ///
+    /// #[tokio::main]
+    /// async fn main() {
+    ///     let mut injector = InjectorPP::new();
+    ///
+    ///     unsafe {
+    ///         injector
+    ///             .when_called_async_unchecked(injectorpp::async_func_unchecked!(async_add_one(u32::default())))
+    ///             .will_return_async_unchecked(injectorpp::async_return_unchecked!(123, u32));
+    ///     }
+    ///
+    ///     let result = async_add_one(5).await;
+    ///     assert_eq!(result, 123); // The patched value
+    /// }
+    /// ```
+    pub unsafe fn when_called_async_unchecked<F, T>(
+        &mut self,
+        _: Pin<&mut F>,
+    ) -> WhenCalledBuilderAsync<'_>
+    where
+        F: Future<Output = T>,
+    {
+        self.assert_patch_limit();
+
+        let poll_fn: fn(Pin<&mut F>, &mut Context<'_>) -> Poll<T> = <F as Future>::poll;
+        let when = WhenCalled::new(
+            crate::func!(poll_fn, fn(Pin<&mut F>, &mut Context<'_>) -> Poll<T>).func_ptr_internal,
+        );
+
+        WhenCalledBuilderAsync {
+            lib: self,
+            when,
+            expected_signature: "",
+        }
+    }
+}
+
+impl InjectorPP {
+    /// Runs `install` and keeps the resulting guard until the injector is dropped.
+    fn install(&mut self, install: impl FnOnce() -> PatchGuard) -> MockHandle {
+        let guard = install();
+        let index = self.installs;
+        self.installs += 1;
+        self.guards.push((index, guard));
+
+        MockHandle {
+            injector_id: self.id,
+            index,
+        }
+    }
+
+    /// Installs `parts` as the fake of `when`, publishing its closure first if it has one.
+    fn install_fake(&mut self, mut when: WhenCalled, parts: FakeParts) -> MockHandle {
+        if let Some(closure) = parts.closure {
+            when.add_call_hook(
+                set_current_closure,
+                &*closure as *const dyn Any as *const (),
+            );
+            self.hook_data.push((self.installs, closure));
+        }
+
+        self.verifiers.push((when.address(), parts.verifier));
+        self.install(|| when.will_execute_guard(parts.func.func_ptr_internal))
+    }
+
+    /// Installs `parts` as the fake of `when`, keeping the original function callable.
+    fn install_map(&mut self, mut when: WhenCalled, parts: MapParts) -> MockHandle {
+        when.add_call_hook(
+            set_current_closure,
+            &*parts.state as *const dyn Any as *const (),
+        );
+        self.hook_data.push((self.installs, parts.state));
+
+        let original = parts.original;
+        let original_code = self.original_code(when.address());
+        self.install(|| {
+            when.will_execute_keeping_original_guard(
+                parts.func.func_ptr_internal,
+                &original_code,
+                &mut |address| unsafe { (*original).store(address, Ordering::Release) },
+            )
+        })
+    }
+
+    /// Returns the real code at the start of the function at `address` where the fakes of this
+    /// injector patched it, or nothing if it has none.
+    ///
+    /// Each fake captured the bytes live when it was installed, i.e. the patch of the fake
+    /// before it, so every byte is taken from the first fake that patched it.
+    fn original_code(&self, address: usize) -> Vec<u8> {
+        let patches: Vec<&PatchGuard> = self
+            .guards
+            .iter()
+            .map(|(_, guard)| guard)
+            .filter(|guard| guard.patched_range().start == address)
+            .collect();
+
+        let len = patches
+            .iter()
+            .map(|guard| guard.original_bytes().len())
+            .max()
+            .unwrap_or(0);
+        let mut code = vec![0; len];
+        for guard in patches.iter().rev() {
+            let original = guard.original_bytes();
+            code[..original.len()].copy_from_slice(original);
+        }
+
+        code
+    }
+
+    /// Restores the guards with an install number of at least `first`, most recent first, and
+    /// runs their `on_restore` callbacks.
+    fn restore_guards(&mut self, first: usize) {
+        let _batch = PatchBatch::begin();
+
+        // Later patches captured the bytes written by earlier ones, restore in reverse.
+        while let Some((index, guard)) = self.guards.pop_if(|(index, _)| *index >= first) {
+            let range = guard.patched_range();
+            drop(guard);
+            self.restore_patches(range);
+
+            while let Some((_, hook)) = self
+                .restore_hooks
+                .pop_if(|(hook_index, _)| *hook_index >= index)
+            {
+                hook.run();
+            }
+        }
+    }
+
+    /// Rewrites the function patched at `range` so that it runs its latest enabled fake, or
+    /// its real code when none is enabled.
+    ///
+    /// Each fake of a function captured the bytes live when it was installed, i.e. the patch
+    /// of the fake before it. Writing them back alone would bring a disabled earlier fake
+    /// back, so the function is rewritten from all of its fakes instead.
+    fn sync_patches(&self, range: std::ops::Range<usize>) -> Result<(), InjectError> {
+        let patches: Vec<&PatchGuard> = self
+            .guards
+            .iter()
+            .map(|(_, guard)| guard)
+            .filter(|guard| guard.overlaps(&range))
+            .collect();
+
+        match patches.iter().rev().find(|guard| guard.is_enabled()) {
+            Some(latest) => latest.write_patch(),
+            // The first fake captured the real code, the others write what they captured
+            // first so that bytes only later fakes overwrote are restored too.
+            None => patches
+                .iter()
+                .rev()
+                .try_for_each(|guard| guard.write_original()),
+        }
+    }
+
+    /// Like `sync_patches` after a fake of the function at `range` was removed, but reports a
+    /// failure with `record_restore_failure` instead of panicking, as this runs while
+    /// dropping the injector too.
+    fn restore_patches(&self, range: std::ops::Range<usize>) {
+        if let Err(error) = self.sync_patches(range.clone()) {
+            record_restore_failure(range.start, error);
+        }
+    }
+
+    /// Checks the call counts of the fakes registered after the first `len` ones and drops
+    /// their verifiers, failing on the first mismatch.
+    fn verify_calls(&mut self, len: usize) {
+        for (function, mut verifier) in self.verifiers.drain(len..) {
+            if let Some((expected, actual)) = verifier.take_mismatch() {
+                // Avoid double panic
+                if std::thread::panicking() {
+                    continue;
+                }
+
+                fail(Failure::CallCount {
+                    function,
+                    expected,
+                    actual,
+                });
+            }
+        }
+    }
+
+    fn check_patch_limit(&self) -> Result<(), InjectError> {
+        match self.max_active_patches {
+            Some(limit) if self.guards.len() >= limit => {
+                Err(InjectError::PatchLimitExceeded { limit })
+            }
+            _ => Ok(()),
+        }
+    }
+
+    fn assert_patch_limit(&self) {
+        if let Err(error) = self.check_patch_limit() {
+            panic!("{error}");
+        }
+    }
+
+    fn check_handle(&self, handle: MockHandle) {
+        if handle.injector_id != self.id {
+            panic!("The MockHandle was created by another InjectorPP instance");
+        }
+    }
+
+    fn guard_position(&self, handle: MockHandle) -> usize {
+        self.check_handle(handle);
+
+        self.guards
+            .binary_search_by_key(&handle.index, |(index, _)| *index)
+            .unwrap_or_else(|_| panic!("The MockHandle refers to a fake that was rolled back"))
+    }
+
+    fn call_counter(&self, handle: MockHandle) -> &(usize, usize, Box<AtomicUsize>) {
+        self.guard_position(handle);
+
+        let position = self
+            .call_counters
+            .binary_search_by_key(&handle.index, |(index, _, _)| *index)
+            .unwrap_or_else(|_| panic!("The fake was not installed after count_calls"));
+        &self.call_counters[position]
+    }
+
+    fn guard(&self, handle: MockHandle) -> &PatchGuard {
+        &self.guards[self.guard_position(handle)].1
+    }
+
+    fn guard_mut(&mut self, handle: MockHandle) -> &mut PatchGuard {
+        let position = self.guard_position(handle);
+
+        &mut self.guards[position].1
+    }
+}
+
+/// Identifies a fake installed by an `InjectorPP`, see `InjectorPP::disable` and
+/// `InjectorPP::enable`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct MockHandle {
+    injector_id: usize,
+    index: usize,
+}
+
+/// The fakes installed by an `InjectorPP` at some point, see `InjectorPP::checkpoint`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Checkpoint {
+    injector_id: usize,
+    installs: usize,
+    verifiers: usize,
+}
+
+/// Counts of the system calls and cache maintenance patching needed, see
+/// `InjectorPP::patch_stats`.
+///
+/// The counts cover the whole process since it started. Subtract two snapshots to get the
+/// cost of the code between them.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct PatchStats {
+    /// How many times the code pages of functions were made writable, with `mprotect`,
+    /// `VirtualProtect` or a Mach remapping.
+    pub protection_changes: usize,
+
+    /// How many times the instruction cache was flushed over a range of code. On Linux x86
+    /// and x86_64 flushing needs no instruction, but is counted all the same.
+    pub cache_flushes: usize,
+}
+
+impl std::ops::Sub for PatchStats {
+    type Output = PatchStats;
+
+    fn sub(self, earlier: PatchStats) -> PatchStats {
+        PatchStats {
+            protection_changes: self.protection_changes - earlier.protection_changes,
+            cache_flushes: self.cache_flushes - earlier.cache_flushes,
+        }
+    }
+}
+
+/// A token that keeps a fake enabled while alive, returned by `InjectorPP::enable_scoped`.
+pub struct ScopedMock<'a> {
+    injector: &'a mut InjectorPP,
+    handle: MockHandle,
+    was_enabled: bool,
+}
+
+impl Drop for ScopedMock<'_> {
+    fn drop(&mut self) {
+        if !self.was_enabled {
+            self.injector.disable(self.handle);
+        }
+    }
+}
+
+impl Drop for InjectorPP {
+    fn drop(&mut self) {
+        self.restore_guards(0);
+        release_jit_memory();
+        self.verify_calls(0);
+    }
+}
+
+impl Default for InjectorPP {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// A guard that prevents injectorpp affecting the test while alive.
+///
+/// When this guard is held, no any injectorpp instance can be created.
+/// This is useful for threads that need to call functions with their
+/// original behavior.
+pub struct Preventer {
+    _lock: FunctionLockGuard<'static>,
+}
+
+impl Preventer {
+    /// Check if patching is currently prevented by this guard.
+    ///
+    /// This always returns `true` while the guard exists, as the guard
+    /// holds the global mutex that prevents patching.
+    pub fn is_active(&self) -> bool {
+        true
+    }
+}
+
+/// A builder that lets you chain patching operations.
+///
+/// `F` is the type of the function being faked as given to `func!`, or `()` when it is not
+/// known, e.g. for `when_called_unchecked`.
+pub struct WhenCalledBuilder<'a, F = ()> {
+    lib: &'a mut InjectorPP,
+    when: WhenCalled,
+    expected_signature: &'static str,
+    function_type: PhantomData<F>,
+}
+
+impl<'a, F> WhenCalledBuilder<'a, F> {
+    /// Records every call to the target function in `sequence` under `label`.
+    ///
+    /// The call is recorded before the fake runs. Combine it with `Expectations` to assert
+    /// the order and the number of calls across several functions.
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// use injectorpp::interface::injector::*;
+    ///
+    /// fn open() -> bool {
+    ///     false
+    /// }
+    ///
+    /// fn close() -> bool {
+    ///     std::hint::black_box(false)
+    /// }
+    ///
+    /// let sequence = Sequence::new();
+    ///
+    /// let mut injector = InjectorPP::new();
+    /// injector
+    ///     .when_called(injectorpp::func!(fn (open)() -> bool))
+    ///     .in_sequence(&sequence, "open")
+    ///     .will_return_boolean(true);
+    /// injector
+    ///     .when_called(injectorpp::func!(fn (close)() -> bool))
+    ///     .in_sequence(&sequence, "close")
+    ///     .will_return_boolean(true);
+    ///
+    /// let _expectations = Expectations::new(&sequence)
+    ///     .then("open", 1)
+    ///     .then("close", 1);
+    ///
+    /// assert!(open());
+    /// assert!(close());
+    /// ```
+    pub fn in_sequence(mut self, sequence: &Sequence, label: &'static str) -> Self {
+        let entry = Box::new(SequenceEntry {
+            sequence: sequence.clone(),
+            label,
+        });
+
+        self.when.add_call_hook(
+            record_sequence_call,
+            &*entry as *const SequenceEntry as *const (),
+        );
+        self.lib.hook_data.push((self.lib.installs, entry));
+
+        self
+    }
+
+    /// Accepts a target function whose whole body is a tail call to another function.
+    ///
+    /// On AArch64 the optimizer may turn a function into a single `b` to another one. Such a
+    /// function can be as short as that one instruction, so faking it panics by default
+    /// rather than overwriting whatever follows. With this option only the branch is
+    /// replaced. It has no effect on other architectures.
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// use injectorpp::interface::injector::*;
+    ///
+    /// fn checked_len(value: &str) -> usize {
+    ///     value.len()
+    /// }
+    ///
+    /// let mut injector = InjectorPP::new();
+    /// injector
+    ///     .when_called(injectorpp::func!(fn (checked_len)(&str) -> usize))
+    ///     .allow_tail_call_target()
+    ///     .will_execute(|_: &str| 0usize);
+    ///
+    /// assert_eq!(checked_len("abc"), 0);
+    /// ```
+    pub fn allow_tail_call_target(mut self) -> Self {
+        self.when.allow_tail_call();
+        self
+    }
+
+    /// Fakes the function a thunk jumps to rather than the thunk itself.
+    ///
+    /// On AArch64, and typically on Windows with incremental linking, the address of a
+    /// function may be a thunk made of a single `b` to the actual body. Patching the thunk
+    /// only affects callers going through it, and places the JIT memory near the thunk rather
+    /// than near the body. With this option, when the function starts with an unconditional
+    /// branch, the branch target is faked instead. Call it right after `when_called`, before
+    /// options such as `count_calls` record the function. It has no effect on other
+    /// architectures.
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// use injectorpp::interface::injector::*;
+    ///
+    /// fn checked_len(value: &str) -> usize {
+    ///     value.len()
+    /// }
+    ///
+    /// let mut injector = InjectorPP::new();
+    /// injector
+    ///     .when_called(injectorpp::func!(fn (checked_len)(&str) -> usize))
+    ///     .follow_branch_thunk()
+    ///     .will_execute(|_: &str| 0usize);
+    ///
+    /// assert_eq!(checked_len("abc"), 0);
+    /// ```
+    pub fn follow_branch_thunk(mut self) -> Self {
+        self.when.follow_branch_thunk();
+        self
+    }
+
+    /// Patches the target function without checking that it is long enough for the patch.
+    ///
+    /// The patch overwrites the first bytes of the function: 5 or 14 on x86_64, 12 on
+    /// AArch64. Functions shorter than that are refused by default, as judged from where
+    /// their code seems to end. On AArch64 a function that seems to end within those 12
+    /// bytes only has its first instruction replaced by a `b` instead, and is refused only
+    /// when the JIT memory is out of the ±128MB that branch reaches. Hand-written
+    /// assembly, such as a naked function returning early and keeping trap instructions
+    /// after its `ret`, can be long enough although the check rejects it. This option skips
+    /// the check when the fake is installed. On AArch64 it also allows tail calls like
+    /// `allow_tail_call_target`.
+    ///
+    /// `when_called` refuses such functions up front, so start from `when_called_unchecked`,
+    /// which skips that check.
+    ///
+    /// # Safety
+    ///
+    /// The caller must guarantee that the target function is at least as long as the patch
+    /// and that no code jumps into the bytes it overwrites. Otherwise the patch corrupts the
+    /// code placed after the function, or whatever jumps into the patch executes a fragment
+    /// of it. Either is undefined behavior, typically a crash or silently wrong results far
+    /// from the faked function.
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// use injectorpp::interface::injector::*;
+    ///
+    /// fn is_enabled() -> bool {
+    ///     std::hint::black_box(false)
+    /// }
+    ///
+    /// fn fake_is_enabled() -> bool {
+    ///     true
+    /// }
+    ///
+    /// let mut injector = InjectorPP::new();
+    /// unsafe {
+    ///     injector
+    ///         .when_called_unchecked(injectorpp::func_unchecked!(is_enabled))
+    ///         .assume_patchable()
+    ///         .will_execute_raw_unchecked(injectorpp::func_unchecked!(fake_is_enabled));
+    /// }
+    ///
+    /// assert!(is_enabled());
+    /// ```
+    pub unsafe fn assume_patchable(mut self) -> Self {
+        self.when.assume_patchable();
+        self
+    }
+
+    /// Sleeps for `duration` on every call before the fake runs, to slow down a function
+    /// that returns a value.
+    ///
+    /// Combine it with any `will_*` method. Use `will_sleep` for functions returning `()`.
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// use injectorpp::interface::injector::*;
+    /// use std::time::{Duration, Instant};
+    ///
+    /// fn is_reachable() -> bool {
+    ///     false
+    /// }
+    ///
+    /// let mut injector = InjectorPP::new();
+    /// injector
+    ///     .when_called(injectorpp::func!(fn (is_reachable)() -> bool))
+    ///     .with_delay(Duration::from_millis(20))
+    ///     .will_return_boolean(true);
+    ///
+    /// let start = Instant::now();
+    /// assert!(is_reachable());
+    /// assert!(start.elapsed() >= Duration::from_millis(20));
+    /// ```
+    pub fn with_delay(mut self, duration: Duration) -> Self {
+        let duration = Box::new(duration);
+
+        self.when
+            .add_call_hook(sleep_for, &*duration as *const Duration as *const ());
+        self.lib.hook_data.push((self.lib.installs, duration));
+
+        self
+    }
+
+    /// Counts the calls to the fake, for `InjectorPP::call_count` and
+    /// `InjectorPP::verify_called_times`.
+    ///
+    /// The patched code increments a counter owned by the injector before the fake runs.
+    pub fn count_calls(mut self) -> Self {
+        let calls = Box::new(AtomicUsize::new(0));
+
+        self.when
+            .add_call_hook(count_call, &*calls as *const AtomicUsize as *const ());
+        self.lib
+            .call_counters
+            .push((self.lib.installs, self.when.address(), calls));
+
+        self
+    }
+
+    /// Records the calls to the fake in the order shared by every fake of the injector, for
+    /// `InjectorPP::call_sequence` and `InjectorPP::assert_called_in_order`.
+    ///
+    /// The patched code appends the fake's install number to the injector's log before the
+    /// fake runs.
+    pub fn record_call_order(mut self) -> Self {
+        let entry = Box::new(CallOrderEntry {
+            log: self.lib.call_order.clone(),
+            index: self.lib.installs,
+        });
+
+        self.when.add_call_hook(
+            record_call_order,
+            &*entry as *const CallOrderEntry as *const (),
+        );
+        self.lib.hook_data.push((self.lib.installs, entry));
+
+        self
+    }
+
+    /// Runs `callback` right after the original code of the target function is restored,
+    /// when the injector is dropped or rolled back past this fake.
+    ///
+    /// The callback receives a `CallRecord` describing the calls made to the fake, so it can
+    /// assert invariants of this fake at the exact point it is removed. It is not run if the
+    /// thread is already panicking.
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// use injectorpp::interface::injector::*;
+    ///
+    /// fn flush() -> bool {
+    ///     false
+    /// }
+    ///
+    /// let mut injector = InjectorPP::new();
+    /// injector
+    ///     .when_called(injectorpp::func!(fn (flush)() -> bool))
+    ///     .on_restore(|record| assert_eq!(record.call_count(), 1))
+    ///     .will_return_boolean(true);
+    ///
+    /// assert!(flush());
+    /// ```
+    pub fn on_restore(mut self, callback: impl FnOnce(&CallRecord) + 'static) -> Self {
+        let hook = Box::new(RestoreHook::new(callback));
+
+        self.when.add_call_hook(
+            count_restore_call,
+            &*hook as *const RestoreHook as *const (),
+        );
+        self.lib.restore_hooks.push((self.lib.installs, hook));
+
+        self
+    }
+
+    /// Only fakes the calls from the `start`th to the `end`th, inclusive and counted from 1.
+    ///
+    /// The other calls run the original function, which makes it easy to simulate a
+    /// transient window of failures. The instructions the patch overwrites are copied next to
+    /// the fake, so see `will_map` for the functions this supports. Panics if `start` is 0 or
+    /// after `end`.
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// use injectorpp::interface::injector::*;
+    ///
+    /// #[inline(never)]
+    /// fn connect(port: u16) -> Result<u16, String> {
+    ///     Ok(std::hint::black_box(port))
+    /// }
+    ///
+    /// let mut injector = InjectorPP::new();
+    /// injector
+    ///     .when_called(injectorpp::func!(fn (connect)(u16) -> Result<u16, String>))
+    ///     .between_calls(2, 3)
+    ///     .will_execute(|_: u16| -> Result<u16, String> { Err("refused".to_string()) });
+    ///
+    /// assert!(connect(80).is_ok());
+    /// assert!(connect(80).is_err());
+    /// assert!(connect(80).is_err());
+    /// assert!(connect(80).is_ok());
+    /// ```
+    pub fn between_calls(self, start: usize, end: usize) -> BetweenCallsBuilder<'a> {
+        if start == 0 || start > end {
+            panic!("between_calls requires 1 <= start <= end but got {start}..={end}");
+        }
+
+        BetweenCallsBuilder {
+            builder: self.untyped(),
+            calls: start..=end,
+        }
+    }
+
+    /// Fake the target function to branch to the provided function.
+    ///
+    /// Allows full customization of the faked function behavior by providing your own function or closure.
+    ///
+    /// # Parameters
+    ///
+    /// - `target`: A FuncPtr holds the pointer to the replacement function or closure. Using injectorpp::func! or injectorpp::closure! macros is recommended to obtain this pointer.
+    ///
+    /// The target function is entered directly by its callers, so a callback invoked from
+    /// foreign code, such as a signal handler or a C library comparator, must be replaced by
+    /// a function with the same `extern "C"` signature. The signature check enforces this.
+    ///
+    /// `target` must have the function type the builder got from `func!`, so a replacement
+    /// of another signature, e.g. returning `u8` for a function returning `i32`, fails to
+    /// compile. Builders of unknown type, such as those of `when_called_unchecked`, take a
+    /// `FuncPtr` without type and check its signature at runtime.
+    ///
+    /// # Example
+    ///
+    /// Using closure:
+    /// ```rust
+    /// use injectorpp::interface::injector::*;
+    /// use std::path::Path;
+    ///
+    /// let fake_closure = |_: &Path| -> bool {
+    ///    true
+    /// };
+    ///
+    /// let mut injector = InjectorPP::new();
+    /// injector
+    ///     .when_called(injectorpp::func!(fn (Path::exists)(&Path) -> bool))
+    ///     .will_execute_raw(injectorpp::closure!(fake_closure, fn(&Path) -> bool));
+    ///
+    /// assert!(Path::new("/nonexistent").exists());
+    /// ```
+    ///
+    /// Using custom function:
+    /// ```rust
+    /// use injectorpp::interface::injector::*;
+    /// use std::path::Path;
+    ///
+    /// fn fake_exists(_path: &Path) -> bool {
+    ///     true
+    /// }
+    ///
+    /// let mut injector = InjectorPP::new();
+    /// injector
+    ///     .when_called(injectorpp::func!(fn (Path::exists)(&Path) -> bool))
+    ///     .will_execute_raw(injectorpp::func!(fn (fake_exists)(&Path) -> bool));
+    ///
+    /// assert!(Path::new("/nonexistent").exists());
+    /// ```
+    pub fn will_execute_raw(self, target: FuncPtr<F>) -> MockHandle {
+        self.check_signature(target.signature);
+
+        self.lib
+            .install(|| self.when.will_execute_guard(target.func_ptr_internal))
+    }
+
+    /// Redirects the target function to `other`, another function with the same signature.
+    ///
+    /// This is `will_execute_raw` given a function: the patch branches straight to `other`,
+    /// through an absolute jump when it is out of reach of a near branch, so calls run it with
+    /// their own arguments and get its result. Panics if the signatures differ.
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// use injectorpp::interface::injector::*;
+    ///
+    /// fn primary_port() -> u16 {
+    ///     443
+    /// }
+    ///
+    /// fn fallback_port() -> u16 {
+    ///     8443
+    /// }
+    ///
+    /// let mut injector = InjectorPP::new();
+    /// injector
+    ///     .when_called(injectorpp::func!(fn (primary_port)() -> u16))
+    ///     .will_forward_to(injectorpp::func!(fn (fallback_port)() -> u16));
+    ///
+    /// assert_eq!(primary_port(), 8443);
+    /// ```
+    pub fn will_forward_to(self, other: FuncPtr<F>) -> MockHandle {
+        self.will_execute_raw(other)
+    }
+
+    /// Fake the target function to branch to the provided function.
+    ///
+    /// Allows full customization of the faked function behavior by providing your own function or closure.
+    ///
+    /// # Parameters
+    ///
+    /// - `target`: A FuncPtr holds the pointer to the replacement function or closure. Using injectorpp::func_unchecked! or injectorpp::closure! macros is recommended to obtain this pointer.
+    ///
+    /// # Safety
+    ///
+    /// This method is unsafe because it skips type check.
+    ///
+    /// # Example
+    ///
+    /// Using closure:
+    /// ```rust
+    /// use injectorpp::interface::injector::*;
+    /// use std::path::Path;
+    ///
+    /// let fake_closure = |_: &Path| -> bool {
+    ///    true
+    /// };
+    ///
+    /// let mut injector = InjectorPP::new();
+    ///
+    /// unsafe {
+    ///     injector
+    ///         .when_called(injectorpp::func_unchecked!(Path::exists))
+    ///         .will_execute_raw_unchecked(injectorpp::closure!(fake_closure, fn(&Path) -> bool));
+    /// }
+    ///
+    /// assert!(Path::new("/nonexistent").exists());
+    /// ```
+    ///
+    /// Using custom function:
+    /// ```rust
+    /// use injectorpp::interface::injector::*;
+    /// use std::path::Path;
+    ///
+    /// fn fake_exists(_path: &Path) -> bool {
+    ///     true
+    /// }
+    ///
+    /// let mut injector = InjectorPP::new();
+    ///
+    /// unsafe {
+    ///     injector
+    ///         .when_called(injectorpp::func_unchecked!(Path::exists))
+    ///         .will_execute_raw_unchecked(injectorpp::func_unchecked!(fake_exists));
+    /// }
+    ///
+    /// assert!(Path::new("/nonexistent").exists());
+    /// ```
+    pub unsafe fn will_execute_raw_unchecked<G>(self, target: FuncPtr<G>) -> MockHandle {
+        self.lib
+            .install(|| self.when.will_execute_guard(target.func_ptr_internal))
+    }
+
+    /// Fake the target function using a fake function generated by the `fake!` macro.
+    ///
+    /// Suitable for complex scenarios where you specify conditions, assignments, return values, and expected call counts.
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// use injectorpp::interface::injector::*;
+    ///
+    /// fn original_func(a: &mut i32) -> bool {
+    ///     *a = 1;
+    ///     false
+    /// }
+    ///
+    /// let mut injector = InjectorPP::new();
+    /// injector
+    ///     .when_called(injectorpp::func!(fn (original_func)(&mut i32) -> bool))
+    ///     .will_execute(injectorpp::fake!(
+    ///         func_type: fn(a: &mut i32) -> bool,
+    ///         assign: { *a = 6 },
+    ///         returns: true,
+    ///         times: 1
+    ///     ));
+    ///
+    /// let mut value = 0;
+    /// let result = original_func(&mut value);
+    ///
+    /// assert_eq!(value, 6);
+    /// assert_eq!(result, true);
+    /// ```
+    /// Below are supported options:
+    ///
+    /// `func_type``: // Required. The signature of the function to fake.
+    /// `when``: // Optional. A condition check for the parameters of the function to fake.
+    /// `assign``: // Optional. Use to set values to reference variables of the function to fake.
+    /// `returns``: // Required for the function has return. Specify what the return value should be.
+    /// `times``: // Optional. How many times the function should be called. If the value is not satisfied at the end of the test, the test will fail.
+    ///
+    /// A closure taking the same arguments as the target function can be passed directly as
+    /// well. It may capture state, which is dropped together with the injector, and its
+    /// return value, e.g. a `Result` or an `Option`, is handed back to the caller exactly as
+    /// the target function would return it:
+    ///
+    /// ```rust
+    /// use injectorpp::interface::injector::*;
+    ///
+    /// fn parse(input: &str) -> Result<u32, String> {
+    ///     input.parse().map_err(|_| input.to_string())
+    /// }
+    ///
+    /// let limit = 10;
+    ///
+    /// let mut injector = InjectorPP::new();
+    /// injector
+    ///     .when_called(injectorpp::func!(fn (parse)(&str) -> Result<u32, String>))
+    ///     .will_execute(move |input: &str| -> Result<u32, String> {
+    ///         if input.len() < limit {
+    ///             Ok(input.len() as u32)
+    ///         } else {
+    ///             Err("too long".to_string())
+    ///         }
+    ///     });
+    ///
+    /// assert_eq!(parse("abc"), Ok(3));
+    /// assert_eq!(parse("abcdefghijk"), Err("too long".to_string()));
+    /// ```
+    pub fn will_execute<Marker>(self, fake: impl IntoFake<Marker>) -> MockHandle {
+        let parts = fake.into_fake_parts();
+        self.check_signature(parts.func.signature);
+
+        self.lib.install_fake(self.when, parts)
+    }
+
+    /// Fake the target function with a closure that sees both its arguments and the value the
+    /// original function returns for them.
+    ///
+    /// The instructions the patch overwrites are copied next to the fake, so the original
+    /// function keeps working and is called with a clone of the arguments before the closure.
+    /// The closure takes the arguments followed by the original result and returns the value
+    /// handed back to the caller. The original is the real function even when earlier fakes of
+    /// the injector replace it, so disabling them does not change what the closure sees.
+    ///
+    /// Panics if the start of the target function cannot be copied, e.g. when it branches
+    /// back into the overwritten instructions. Not supported on 32-bit ARM.
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// use injectorpp::interface::injector::*;
+    ///
+    /// #[inline(never)]
+    /// fn add_tax(price: u32) -> u32 {
+    ///     std::hint::black_box(price) * 120 / 100
+    /// }
+    ///
+    /// let mut injector = InjectorPP::new();
+    /// injector
+    ///     .when_called(injectorpp::func!(fn (add_tax)(u32) -> u32))
+    ///     .will_map(|price: u32, taxed: u32| taxed.min(price + 10));
+    ///
+    /// assert_eq!(add_tax(10), 12);
+    /// assert_eq!(add_tax(100), 110);
+    /// ```
+    pub fn will_map<Marker>(self, map: impl IntoMap<Marker>) -> MockHandle {
+        let parts = map.into_map_parts();
+        self.check_signature(parts.func.signature);
+
+        self.lib.install_map(self.when, parts)
+    }
+
+    /// Fake the target function with a closure that decides whether and how to call the
+    /// original function.
+    ///
+    /// The closure receives the original function as a `fn` pointer followed by the
+    /// arguments, so it can wrap the real implementation, e.g. log the arguments, change them,
+    /// or skip the call. Reference arguments need a named lifetime in the closure, e.g.
+    /// `fn(&'static str)`, as the `fn` pointer is for a single lifetime.
+    ///
+    /// Like `will_map`, the instructions the patch overwrites are copied next to the fake,
+    /// with PC-relative instructions such as `adrp`, `b` and literal loads rewritten to work
+    /// from there.
+    ///
+    /// Panics if the start of the target function cannot be copied, e.g. when it branches
+    /// back into the overwritten instructions. Not supported on 32-bit ARM.
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// use injectorpp::interface::injector::*;
+    ///
+    /// #[inline(never)]
+    /// fn normalize(name: String) -> String {
+    ///     std::hint::black_box(name).to_lowercase()
+    /// }
+    ///
+    /// let mut injector = InjectorPP::new();
+    /// injector
+    ///     .when_called(injectorpp::func!(fn (normalize)(String) -> String))
+    ///     .will_call_original_with_hook(|original: fn(String) -> String, name: String| {
+    ///         original(name.trim().to_string())
+    ///     });
+    ///
+    /// assert_eq!(normalize("  Alice ".to_string()), "alice");
+    /// ```
+    pub fn will_call_original_with_hook<Marker>(self, hook: impl IntoHook<Marker>) -> MockHandle {
+        let parts = hook.into_hook_parts();
+        self.check_signature(parts.func.signature);
+
+        self.lib.install_map(self.when, parts)
+    }
+
+    /// Fake the target function with a closure and returns a `Capture` holding a copy of the
+    /// arguments of each of its calls.
+    ///
+    /// Every argument must implement `CaptureArg`: primitives are copied, `&str` and `&[T]`
+    /// are captured as `String` and `Vec<T>`. The closure runs after the arguments are
+    /// stored, like with `will_execute`.
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// use injectorpp::interface::injector::*;
+    ///
+    /// fn log(_level: u32, _message: &str) {
+    ///     unimplemented!();
+    /// }
+    ///
+    /// let mut injector = InjectorPP::new();
+    /// let capture = injector
+    ///     .when_called(injectorpp::func!(fn (log)(u32, &str)))
+    ///     .will_capture(|_: u32, _: &str| ());
+    ///
+    /// assert_eq!(capture.last_args(), None);
+    ///
+    /// log(2, "disk almost full");
+    /// assert_eq!(capture.last_args(), Some((2, "disk almost full".to_string())));
+    /// ```
+    pub fn will_capture<Marker, C: IntoCapture<Marker>>(self, fake: C) -> Capture<C::Args> {
+        let capture = Capture::new(self.when.address());
+        let parts = fake.into_capture_parts(&capture);
+        self.check_signature(parts.func.signature);
+
+        self.lib.install_fake(self.when, parts);
+        capture
+    }
+
+    /// Keeps the original behavior of the target function and returns a `Spy` observing its
+    /// calls.
+    ///
+    /// Call `Spy::capture_return` to also record what the original function returned, and
+    /// `Spy::capture_thread_ids` to record which threads called it. The spy forwards the
+    /// first six integer or pointer arguments and the two integer return registers, so
+    /// functions taking or returning floating point values, or more arguments, cannot be
+    /// spied on. On AArch64 neither can functions returning more than
+    /// 16 bytes, whose result address is passed in `x8`. Not supported on 32-bit ARM.
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// use injectorpp::interface::injector::*;
+    ///
+    /// #[inline(never)]
+    /// fn checksum(data: &[u8]) -> u64 {
+    ///     std::hint::black_box(data).iter().map(|byte| *byte as u64).sum()
+    /// }
+    ///
+    /// let mut injector = InjectorPP::new();
+    /// let spy = injector
+    ///     .when_called(injectorpp::func!(fn (checksum)(&[u8]) -> u64))
+    ///     .spy()
+    ///     .capture_return();
+    ///
+    /// assert_eq!(checksum(&[1, 2, 3]), 6);
+    /// assert_eq!(spy.call_count(), 1);
+    /// assert_eq!(spy.returns(), vec![6]);
+    /// ```
+    pub fn spy(self) -> Spy {
+        let spy = Spy::default();
+        let recorder = spy.clone();
+
+        // Register arguments and results are forwarded untouched, whatever their types.
+        let parts =
+            (move |_: u64, _: u64, _: u64, _: u64, _: u64, _: u64, registers: (u64, u64)| {
+                recorder.record(registers);
+                registers
+            })
+            .into_map_parts();
+
+        self.lib.install_map(self.when, parts);
+        spy
+    }
+
+    /// Fake the target function to sleep for `duration`, then return.
+    ///
+    /// Unlike a fake that blocks forever, the caller resumes after the delay, so timeouts and
+    /// watchdogs around a slow call can be tested. The target function must not return a
+    /// value, see `with_delay` for the others.
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// use injectorpp::interface::injector::*;
+    /// use std::time::{Duration, Instant};
+    ///
+    /// fn flush() {}
+    ///
+    /// let mut injector = InjectorPP::new();
+    /// injector
+    ///     .when_called(injectorpp::func!(fn (flush)()))
+    ///     .will_sleep(Duration::from_millis(20));
+    ///
+    /// let start = Instant::now();
+    /// flush();
+    /// assert!(start.elapsed() >= Duration::from_millis(20));
+    /// ```
+    pub fn will_sleep(self, duration: Duration) -> MockHandle {
+        if !self.expected_signature.is_empty() && !returns_unit(self.expected_signature) {
+            panic!(
+                "Signature mismatch: will_sleep requires a function returning () but got {}",
+                self.expected_signature
+            );
+        }
+
+        let duration = Box::new(duration);
+        let data = &*duration as *const Duration as *const ();
+        self.lib.hook_data.push((self.lib.installs, duration));
+
+        self.lib
+            .install(|| self.when.will_call_hook_guard(sleep_for, data))
+    }
+
+    /// Fake the target function to panic with `message`.
+    ///
+    /// Works whatever the signature of the target function, which makes it easy to test how
+    /// callers handle a dependency that panics. The panic unwinds into the caller like a panic
+    /// of the original function would, so it can be caught with `std::panic::catch_unwind`.
+    /// With optimizations the compiler may assume a function that cannot panic never
+    /// unwinds, so use the recommended `profile.test`. Not supported on 32-bit ARM.
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// use injectorpp::interface::injector::*;
+    ///
+    /// fn load_config() -> Result<String, String> {
+    ///     Ok(String::new())
+    /// }
+    ///
+    /// let mut injector = InjectorPP::new();
+    /// injector
+    ///     .when_called(injectorpp::func!(fn (load_config)() -> Result<String, String>))
+    ///     .will_panic("config store unavailable");
+    ///
+    /// assert!(std::panic::catch_unwind(load_config).is_err());
+    /// ```
+    pub fn will_panic(self, message: &'static str) -> MockHandle {
+        let panic_fn: fn() = panic_with_current_message;
+        let parts = FakeParts {
+            func: unsafe { FuncPtr::new(panic_fn as *const (), "") },
+            verifier: CallCountVerifier::Dummy,
+            closure: Some(Box::new(message)),
+        };
+
+        self.lib.install_fake(self.when, parts)
+    }
+
+    /// Fake the target function to always return a fixed boolean value.
+    ///
+    /// This method is convenient for functions that return boolean values.
+    ///
+    /// The whole return register is set to 0 or 1, so callers that only test its low byte
+    /// and callers that test all of it see the same value.
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// use injectorpp::interface::injector::*;
+    /// use std::path::Path;
+    ///
+    /// let mut injector = InjectorPP::new();
+    /// injector
+    ///     .when_called(injectorpp::func!(fn (Path::exists)(&Path) -> bool))
+    ///     .will_return_boolean(true);
     ///
     /// assert!(Path::new("/nonexistent").exists());
     /// ```
-    pub fn will_execute_raw(self, target: FuncPtr) {
-        if target.signature != self.expected_signature {
+    pub fn will_return_boolean(self, value: bool) -> MockHandle {
+        // Ensure the target function returns a bool
+        if !self.expected_signature.trim().ends_with("-> bool") {
+            panic!(
+                "Signature mismatch: will_return_boolean requires a function returning bool but got {}",
+                self.expected_signature
+            );
+        }
+
+        self.lib
+            .install(|| self.when.will_return_boolean_guard(value))
+    }
+
+    /// Fake the target function to return the values of `values` one per call, then keep
+    /// returning the last one.
+    ///
+    /// Useful to drive retry loops. The sequence is read by the JIT block itself, so no
+    /// closure runs. Not supported on 32-bit ARM. Like with `will_return_boolean`, the whole
+    /// return register is set to 0 or 1.
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// use injectorpp::interface::injector::*;
+    ///
+    /// fn is_ready() -> bool {
+    ///     false
+    /// }
+    ///
+    /// let mut injector = InjectorPP::new();
+    /// injector
+    ///     .when_called(injectorpp::func!(fn (is_ready)() -> bool))
+    ///     .will_return_boolean_sequence(&[false, true]);
+    ///
+    /// assert!(!is_ready());
+    /// assert!(is_ready());
+    /// assert!(is_ready());
+    /// ```
+    pub fn will_return_boolean_sequence(self, values: &[bool]) -> MockHandle {
+        if !self.expected_signature.trim().ends_with("-> bool") {
+            panic!(
+                "Signature mismatch: will_return_boolean_sequence requires a function returning bool but got {}",
+                self.expected_signature
+            );
+        }
+
+        if values.is_empty() {
+            panic!("will_return_boolean_sequence requires at least one value");
+        }
+
+        let counter = Box::new(AtomicUsize::new(0));
+        let values: Box<Vec<u8>> = Box::new(values.iter().map(|&value| value as u8).collect());
+        let handle = self
+            .lib
+            .install(|| self.when.will_return_byte_sequence_guard(&counter, &values));
+
+        self.lib.hook_data.push((handle.index, counter));
+        self.lib.hook_data.push((handle.index, values));
+        handle
+    }
+
+    /// Fake the target function to always return a fixed `i64`.
+    ///
+    /// The value is loaded straight into the return register, so this is cheaper than a
+    /// closure for constants such as timestamps. Not supported on 32-bit ARM.
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// use injectorpp::interface::injector::*;
+    ///
+    /// fn current_timestamp() -> i64 {
+    ///     1_700_000_000
+    /// }
+    ///
+    /// let mut injector = InjectorPP::new();
+    /// injector
+    ///     .when_called(injectorpp::func!(fn (current_timestamp)() -> i64))
+    ///     .will_return_i64(-1);
+    ///
+    /// assert_eq!(current_timestamp(), -1);
+    /// ```
+    pub fn will_return_i64(self, value: i64) -> MockHandle {
+        self.check_return_type::<i64>("will_return_i64");
+
+        self.lib
+            .install(|| self.when.will_return_integer_guard(value as u64))
+    }
+
+    /// Fake the target function to always return a fixed `u64`.
+    ///
+    /// See `will_return_i64`. Not supported on 32-bit ARM.
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// use injectorpp::interface::injector::*;
+    ///
+    /// fn free_bytes() -> u64 {
+    ///     0
+    /// }
+    ///
+    /// let mut injector = InjectorPP::new();
+    /// injector
+    ///     .when_called(injectorpp::func!(fn (free_bytes)() -> u64))
+    ///     .will_return_u64(u64::MAX);
+    ///
+    /// assert_eq!(free_bytes(), u64::MAX);
+    /// ```
+    pub fn will_return_u64(self, value: u64) -> MockHandle {
+        self.check_return_type::<u64>("will_return_u64");
+
+        self.lib
+            .install(|| self.when.will_return_integer_guard(value))
+    }
+
+    /// Fake the target function to always return a fixed `i32`.
+    ///
+    /// See `will_return_i64`. This also fakes C functions returning `int`, including variadic
+    /// ones such as `printf` taken with `variadic_func!`: the arguments are never read, so
+    /// their number and types do not matter. Not supported on 32-bit ARM.
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// use injectorpp::interface::injector::*;
+    ///
+    /// fn exit_code() -> i32 {
+    ///     0
+    /// }
+    ///
+    /// let mut injector = InjectorPP::new();
+    /// injector
+    ///     .when_called(injectorpp::func!(fn (exit_code)() -> i32))
+    ///     .will_return_i32(-2);
+    ///
+    /// assert_eq!(exit_code(), -2);
+    /// ```
+    pub fn will_return_i32(self, value: i32) -> MockHandle {
+        self.check_return_type::<i32>("will_return_i32");
+
+        self.lib
+            .install(|| self.when.will_return_integer_guard(value as i64 as u64))
+    }
+
+    /// Fake the target function to always return a fixed `f64`.
+    ///
+    /// The bit pattern of `value` is returned unchanged, so NaN payloads, infinities and the
+    /// sign of zero are preserved. Not supported on 32-bit ARM.
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// use injectorpp::interface::injector::*;
+    ///
+    /// fn price() -> f64 {
+    ///     9.99
+    /// }
+    ///
+    /// let mut injector = InjectorPP::new();
+    /// injector
+    ///     .when_called(injectorpp::func!(fn (price)() -> f64))
+    ///     .will_return_f64(0.5);
+    ///
+    /// assert_eq!(price(), 0.5);
+    /// ```
+    pub fn will_return_f64(self, value: f64) -> MockHandle {
+        self.check_return_type::<f64>("will_return_f64");
+
+        self.lib
+            .install(|| self.when.will_return_float_guard(value.to_bits()))
+    }
+
+    /// Fake the target function to always return a fixed 16-byte aggregate.
+    ///
+    /// Rust returns a pair of 8-byte integers or pointers, e.g. `(u64, u64)`, in two integer
+    /// registers: `x0`/`x1` on AArch64 and `rax`/`rdx` on x86_64. The first field of `value`
+    /// goes to the first register and the second one to the second register.
+    ///
+    /// Other 16-byte types such as `[u64; 2]`, `(f64, f64)` or structs are returned through
+    /// memory or floating point registers instead, so they do not implement `RegisterPair`
+    /// and have to be faked with `will_execute_raw`.
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// use injectorpp::interface::injector::*;
+    ///
+    /// fn pair() -> (u64, u64) {
+    ///     (1, 2)
+    /// }
+    ///
+    /// let mut injector = InjectorPP::new();
+    /// injector
+    ///     .when_called(injectorpp::func!(fn (pair)() -> (u64, u64)))
+    ///     .will_return_aggregate((0xAAAAu64, 0xBBBBu64));
+    ///
+    /// assert_eq!(pair(), (0xAAAA, 0xBBBB));
+    /// ```
+    pub fn will_return_aggregate<T: RegisterPair>(self, value: T) -> MockHandle {
+        self.check_return_type::<T>("will_return_aggregate");
+
+        let words = value.words();
+        self.lib
+            .install(|| self.when.will_return_pair_guard(words[0], words[1]))
+    }
+
+    /// Fake the target function to always return `value`.
+    ///
+    /// `T` is one of the `ReturnValue` types, whose layout tells which registers they are
+    /// returned in. Integers, `bool`, `char` and pointers are returned in `x0` on AArch64 and
+    /// `rax` on x86_64, and `f64` values in the floating point register like
+    /// `will_return_f64` does. On 64-bit targets, the pairs of 8-byte integers or pointers of
+    /// `RegisterPair`, 128-bit integers and references to slices or `str` are returned in
+    /// `x0`/`x1` and `rax`/`rdx` like `will_return_aggregate` does.
+    ///
+    /// The same goes for enums: an `Option` of a reference, a `NonNull` or a `NonZero`
+    /// integer is a single value where `None` is 0, so `Some(&value)` is returned as the
+    /// address of `value` and `None` as 0. An `Option<u32>` keeps its tag next to the value
+    /// and is returned in two registers split by its layout, like a `(u32, u32)`, so neither
+    /// implements `ReturnValue` and both fail to compile.
+    ///
+    /// Other types, such as `f32`, arrays or structs, do not implement `ReturnValue` and have
+    /// to be faked with `will_execute_raw`, or `will_return_struct` for structs larger than
+    /// 16 bytes.
+    ///
+    /// Unlike `will_execute_raw`, a `T` other than the return type of the target function
+    /// panics rather than failing to compile: the return type cannot be taken out of function
+    /// types with borrowed arguments such as `fn(&str) -> usize`.
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// use injectorpp::interface::injector::*;
+    ///
+    /// fn retries() -> u8 {
+    ///     3
+    /// }
+    ///
+    /// let mut injector = InjectorPP::new();
+    /// injector
+    ///     .when_called(injectorpp::func!(fn (retries)() -> u8))
+    ///     .will_return(7u8);
+    ///
+    /// assert_eq!(retries(), 7);
+    /// ```
+    pub fn will_return<T: ReturnValue>(self, value: T) -> MockHandle {
+        self.check_return_type::<T>("will_return");
+
+        match value.registers() {
+            Registers::Integer(value) => self
+                .lib
+                .install(|| self.when.will_return_integer_guard(value)),
+            Registers::Float(bits) => self.lib.install(|| self.when.will_return_float_guard(bits)),
+            Registers::Pair(first, second) => self
+                .lib
+                .install(|| self.when.will_return_pair_guard(first, second)),
+        }
+    }
+
+    /// Fake the target function to always return a fixed struct larger than 16 bytes.
+    ///
+    /// Such types do not fit in registers: the caller passes the address of a return slot as
+    /// a hidden argument, in `x8` on AArch64, `rdi` on x86_64 (`rcx` on Windows) and `a0` on
+    /// RISC-V. The patched code copies `value` into that slot and returns, without calling
+    /// back into Rust. Not supported on 32-bit ARM.
+    ///
+    /// Smaller types are returned in registers: use `will_return` for the ones implementing
+    /// `ReturnValue` and `will_execute_raw` for the others.
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// use injectorpp::interface::injector::*;
+    ///
+    /// #[derive(Clone, Copy, Debug, PartialEq)]
+    /// struct Limits {
+    ///     values: [u64; 4],
+    /// }
+    ///
+    /// fn limits() -> Limits {
+    ///     Limits { values: [0; 4] }
+    /// }
+    ///
+    /// let mut injector = InjectorPP::new();
+    /// injector
+    ///     .when_called(injectorpp::func!(fn (limits)() -> Limits))
+    ///     .will_return_struct(Limits { values: [1, 2, 3, 4] });
+    ///
+    /// assert_eq!(limits(), Limits { values: [1, 2, 3, 4] });
+    /// ```
+    pub fn will_return_struct<T: Copy>(self, value: T) -> MockHandle {
+        let size = std::mem::size_of::<T>();
+        if size <= 16 {
             panic!(
-                "Signature mismatch: expected {:?} but got {:?}",
-                self.expected_signature, target.signature
+                "will_return_struct requires a type larger than 16 bytes but {} is {size} byte(s), use will_return or will_execute_raw instead",
+                std::any::type_name::<T>()
             );
         }
 
-        let guard = self.when.will_execute_guard(target.func_ptr_internal);
-        self.lib.guards.push(guard);
+        self.check_return_type::<T>("will_return_struct");
+
+        let value: Box<Vec<u8>> = Box::new(
+            unsafe { std::slice::from_raw_parts(&value as *const T as *const u8, size) }.to_vec(),
+        );
+        let handle = self
+            .lib
+            .install(|| self.when.will_return_struct_guard(&value));
+
+        self.lib.hook_data.push((handle.index, value));
+        handle
     }
 
-    /// Fake the target function to branch to the provided function.
+    /// Fake the target function to return a reference to `value`.
     ///
-    /// Allows full customization of the faked function behavior by providing your own function or closure.
+    /// This fakes functions returning references, like a method `fn name(&self) -> &str`,
+    /// without a `'static` value to return. The value is leaked rather than dropped with the
+    /// injector, as the references the fake hands out may outlive it.
     ///
-    /// # Parameters
+    /// # Example
     ///
-    /// - `target`: A FuncPtr holds the pointer to the replacement function or closure. Using injectorpp::func_unchecked! or injectorpp::closure! macros is recommended to obtain this pointer.
+    /// ```rust
+    /// use injectorpp::interface::injector::*;
     ///
-    /// # Safety
+    /// struct Config {
+    ///     name: String,
+    /// }
     ///
-    /// This method is unsafe because it skips type check.
+    /// impl Config {
+    ///     fn name(&self) -> &str {
+    ///         &self.name
+    ///     }
+    /// }
+    ///
+    /// let mut injector = InjectorPP::new();
+    /// injector
+    ///     .when_called(injectorpp::func!(fn (Config::name)(&Config) -> &str))
+    ///     .will_return_ref(format!("fake-{}", 1).into_boxed_str());
+    ///
+    /// let config = Config { name: "real".to_string() };
+    /// assert_eq!(config.name(), "fake-1");
+    /// ```
+    pub fn will_return_ref<T: ?Sized + Sync + 'static>(self, value: Box<T>) -> MockHandle {
+        let value: &'static T = Box::leak(value);
+        self.will_return(value)
+    }
+
+    /// Fake the target function to always return the raw pointer `ptr`.
+    ///
+    /// The pointer value is returned as is, so whatever it points to must stay valid for as
+    /// long as the code calling the faked function uses it. See `will_return_cstr` to return
+    /// a C string the injector keeps alive. Not supported on 32-bit ARM.
     ///
     /// # Example
     ///
-    /// Using closure:
     /// ```rust
     /// use injectorpp::interface::injector::*;
-    /// use std::path::Path;
-    ///
-    /// let fake_closure = |_: &Path| -> bool {
-    ///    true
-    /// };
     ///
-    /// let mut injector = InjectorPP::new();
+    /// static LIMITS: [u32; 2] = [10, 20];
     ///
-    /// unsafe {
-    ///     injector
-    ///         .when_called(injectorpp::func_unchecked!(Path::exists))
-    ///         .will_execute_raw_unchecked(injectorpp::closure!(fake_closure, fn(&Path) -> bool));
+    /// unsafe extern "C" fn limits() -> *const u32 {
+    ///     std::ptr::null()
     /// }
     ///
-    /// assert!(Path::new("/nonexistent").exists());
+    /// let mut injector = InjectorPP::new();
+    /// injector
+    ///     .when_called(injectorpp::func!(unsafe{} extern "C" fn (limits)() -> *const u32))
+    ///     .will_return_ptr(LIMITS.as_ptr());
+    ///
+    /// assert_eq!(unsafe { *limits().add(1) }, 20);
     /// ```
+    pub fn will_return_ptr<T>(self, ptr: *const T) -> MockHandle {
+        self.check_return_type::<*const T>("will_return_ptr");
+
+        self.lib
+            .install(|| self.when.will_return_integer_guard(ptr as usize as u64))
+    }
+
+    /// Fake the target function to always return a pointer to a copy of `value`, for
+    /// functions returning a `*const c_char`.
+    ///
+    /// The copy is owned by the injector and freed when it is dropped, after the function is
+    /// restored. Pointers the fake returned must not be used past that point. Not supported
+    /// on 32-bit ARM.
+    ///
+    /// # Example
     ///
-    /// Using custom function:
     /// ```rust
     /// use injectorpp::interface::injector::*;
-    /// use std::path::Path;
+    /// use std::ffi::{c_char, CStr};
     ///
-    /// fn fake_exists(_path: &Path) -> bool {
-    ///     true
+    /// unsafe extern "C" fn device_name() -> *const c_char {
+    ///     std::ptr::null()
     /// }
     ///
     /// let mut injector = InjectorPP::new();
+    /// injector
+    ///     .when_called(injectorpp::func!(unsafe{} extern "C" fn (device_name)() -> *const c_char))
+    ///     .will_return_cstr(c"fake-device");
     ///
-    /// unsafe {
-    ///     injector
-    ///         .when_called(injectorpp::func_unchecked!(Path::exists))
-    ///         .will_execute_raw_unchecked(injectorpp::func_unchecked!(fake_exists));
-    /// }
-    ///
-    /// assert!(Path::new("/nonexistent").exists());
+    /// let name = unsafe { CStr::from_ptr(device_name()) };
+    /// assert_eq!(name, c"fake-device");
     /// ```
-    pub unsafe fn will_execute_raw_unchecked(self, target: FuncPtr) {
-        let guard = self.when.will_execute_guard(target.func_ptr_internal);
-        self.lib.guards.push(guard);
+    pub fn will_return_cstr(self, value: &CStr) -> MockHandle {
+        self.check_return_type::<*const c_char>("will_return_cstr");
+
+        let value: Box<CString> = Box::new(value.to_owned());
+        let ptr = value.as_ptr() as usize as u64;
+        let handle = self
+            .lib
+            .install(|| self.when.will_return_integer_guard(ptr));
+
+        self.lib.hook_data.push((handle.index, value));
+        handle
     }
 
-    /// Fake the target function using a fake function generated by the `fake!` macro.
+    /// Fake the target function to atomically increment `counter` and return.
     ///
-    /// Suitable for complex scenarios where you specify conditions, assignments, return values, and expected call counts.
+    /// The increment is performed by the patched code itself, without calling back into
+    /// Rust. The target function must not return a value.
     ///
     /// # Example
     ///
     /// ```rust
     /// use injectorpp::interface::injector::*;
+    /// use std::sync::atomic::{AtomicUsize, Ordering};
     ///
-    /// fn original_func(a: &mut i32) -> bool {
-    ///     *a = 1;
-    ///     false
+    /// fn notify(_id: u32) {
+    ///     unimplemented!();
     /// }
     ///
+    /// static NOTIFICATIONS: AtomicUsize = AtomicUsize::new(0);
+    ///
     /// let mut injector = InjectorPP::new();
     /// injector
-    ///     .when_called(injectorpp::func!(fn (original_func)(&mut i32) -> bool))
-    ///     .will_execute(injectorpp::fake!(
-    ///         func_type: fn(a: &mut i32) -> bool,
-    ///         assign: { *a = 6 },
-    ///         returns: true,
-    ///         times: 1
-    ///     ));
+    ///     .when_called(injectorpp::func!(fn (notify)(u32)))
+    ///     .will_increment(&NOTIFICATIONS);
     ///
-    /// let mut value = 0;
-    /// let result = original_func(&mut value);
+    /// notify(1);
+    /// notify(2);
     ///
-    /// assert_eq!(value, 6);
-    /// assert_eq!(result, true);
+    /// assert_eq!(NOTIFICATIONS.load(Ordering::SeqCst), 2);
     /// ```
-    /// Below are supported options:
-    ///
-    /// `func_type``: // Required. The signature of the function to fake.
-    /// `when``: // Optional. A condition check for the parameters of the function to fake.
-    /// `assign``: // Optional. Use to set values to reference variables of the function to fake.
-    /// `returns``: // Required for the function has return. Specify what the return value should be.
-    /// `times``: // Optional. How many times the function should be called. If the value is not satisfied at the end of the test, the test will fail.
-    pub fn will_execute(self, fake_pair: (FuncPtr, CallCountVerifier)) {
-        let (fake_func, verifier) = fake_pair;
-        self.lib.verifiers.push(verifier);
-        self.will_execute_raw(fake_func);
+    pub fn will_increment(self, counter: &'static AtomicUsize) -> MockHandle {
+        if !self.expected_signature.is_empty() && !returns_unit(self.expected_signature) {
+            panic!(
+                "Signature mismatch: will_increment requires a function returning () but got {}",
+                self.expected_signature
+            );
+        }
+
+        self.lib.install(|| self.when.will_increment_guard(counter))
     }
 
-    /// Fake the target function to always return a fixed boolean value.
+    /// Fake the target function to call the callback it received as an argument, then return.
     ///
-    /// This method is convenient for functions that return boolean values.
+    /// The callback passed as argument number `arg_index` (counting from zero) is called once
+    /// for each entry of `calls`, with that entry as its arguments. This makes it easy to fake
+    /// event emitter style APIs.
+    ///
+    /// The callback must be a `fn` or `extern "C" fn` pointer passed in an integer register,
+    /// and every argument of the callback must be an integer, a `bool` or a pointer, given as
+    /// a `u64`. The target function must not return a value. Each entry of `calls` must hold
+    /// exactly as many arguments as the callback takes, and the callback is called through a
+    /// pointer of that many parameters. Builders without a signature, from the unchecked APIs,
+    /// call it as an `extern "C" fn`.
+    ///
+    /// On x86_64 Linux (glibc) and Windows a panic in the callback unwinds to the caller of
+    /// the faked function and backtraces show that caller. On other targets it aborts the
+    /// process.
     ///
     /// # Example
     ///
     /// ```rust
     /// use injectorpp::interface::injector::*;
-    /// use std::path::Path;
+    /// use std::sync::atomic::{AtomicU32, Ordering};
+    ///
+    /// fn for_each_event(_callback: fn(u32)) {
+    ///     unimplemented!();
+    /// }
+    ///
+    /// static TOTAL: AtomicU32 = AtomicU32::new(0);
+    ///
+    /// fn on_event(id: u32) {
+    ///     TOTAL.fetch_add(id, Ordering::SeqCst);
+    /// }
     ///
     /// let mut injector = InjectorPP::new();
     /// injector
-    ///     .when_called(injectorpp::func!(fn (Path::exists)(&Path) -> bool))
-    ///     .will_return_boolean(true);
+    ///     .when_called(injectorpp::func!(fn (for_each_event)(fn(u32))))
+    ///     .will_invoke_callback(0, &[&[1], &[2], &[3]]);
     ///
-    /// assert!(Path::new("/nonexistent").exists());
+    /// for_each_event(on_event);
+    ///
+    /// assert_eq!(TOTAL.load(Ordering::SeqCst), 6);
     /// ```
-    pub fn will_return_boolean(self, value: bool) {
-        // Ensure the target function returns a bool
-        if !self.expected_signature.trim().ends_with("-> bool") {
+    pub fn will_invoke_callback(self, arg_index: usize, calls: &[&[u64]]) -> MockHandle {
+        if !self.expected_signature.is_empty() && !returns_unit(self.expected_signature) {
             panic!(
-                "Signature mismatch: will_return_boolean requires a function returning bool but got {}",
+                "Signature mismatch: will_invoke_callback requires a function returning () but got {}",
+                self.expected_signature
+            );
+        }
+
+        let slot = WhenCalled::argument_slot(arg_index).unwrap_or_else(|| {
+            panic!("will_invoke_callback: argument {arg_index} is not passed in a register")
+        });
+
+        if let Some(args) = calls
+            .iter()
+            .find(|args| args.len() > MAX_CALLBACK_ARGUMENTS)
+        {
+            panic!(
+                "will_invoke_callback supports at most {MAX_CALLBACK_ARGUMENTS} callback arguments but got {}",
+                args.len()
+            );
+        }
+
+        let abi = if self.expected_signature.is_empty() {
+            CallbackAbi::C
+        } else {
+            let (abi, parameters) = callback_signature(self.expected_signature, arg_index)
+                .unwrap_or_else(|| {
+                    panic!(
+                        "Signature mismatch: will_invoke_callback requires argument {arg_index} to be a `fn` or `extern \"C\" fn` pointer but got {}",
+                        self.expected_signature
+                    )
+                });
+
+            if let Some(args) = calls.iter().find(|args| args.len() != parameters) {
+                panic!(
+                    "will_invoke_callback: the callback takes {parameters} argument(s) but a call passes {}",
+                    args.len()
+                );
+            }
+
+            abi
+        };
+
+        let invocations = Box::new(CallbackInvocations {
+            slot,
+            abi,
+            calls: calls.iter().map(|args| args.to_vec()).collect(),
+        });
+        let data = &*invocations as *const CallbackInvocations as *const ();
+        self.lib.hook_data.push((self.lib.installs, invocations));
+
+        self.lib
+            .install(|| self.when.will_call_hook_guard(invoke_callback, data))
+    }
+
+    /// Forgets the function type, for the builders that do not need it.
+    fn untyped(self) -> WhenCalledBuilder<'a> {
+        WhenCalledBuilder {
+            lib: self.lib,
+            when: self.when,
+            expected_signature: self.expected_signature,
+            function_type: PhantomData,
+        }
+    }
+
+    /// Panics if `signature` is not the signature of the target function.
+    ///
+    /// Elided lifetimes are ignored: depending on how a function type was obtained,
+    /// `std::any::type_name` renders `&str` either as `&str` or as `&'_ str`.
+    fn check_signature(&self, signature: &str) {
+        let normalize = |signature: &str| signature.replace("'_ ", "");
+
+        if normalize(signature) != normalize(self.expected_signature) {
+            panic!(
+                "Signature mismatch: expected {:?} but got {:?}",
+                self.expected_signature, signature
+            );
+        }
+    }
+
+    /// Panics if the target function is known not to return `T`.
+    ///
+    /// Builders created through the unchecked APIs carry no signature and are not checked.
+    fn check_return_type<T>(&self, api: &str) {
+        if self.expected_signature.is_empty() {
+            return;
+        }
+
+        let return_type = std::any::type_name::<T>();
+        if !self
+            .expected_signature
+            .replace("'_ ", "")
+            .trim()
+            .ends_with(&format!("-> {return_type}"))
+        {
+            panic!(
+                "Signature mismatch: {api} requires a function returning {return_type} but got {}",
                 self.expected_signature
             );
         }
+    }
+}
+
+/// Faked by `InjectorPP::self_test`.
+#[inline(never)]
+fn self_test_scale(value: u64) -> u64 {
+    std::hint::black_box(value) * 2
+}
+
+/// Faked by `InjectorPP::self_test`.
+#[inline(never)]
+fn self_test_is_faked() -> bool {
+    std::hint::black_box(false)
+}
+
+/// Returns whether the function type `signature`, as rendered by `std::any::type_name`, has no
+/// return value.
+fn returns_unit(signature: &str) -> bool {
+    let Some(start) = signature.find("fn(") else {
+        return false;
+    };
+
+    let mut depth = 0;
+    for (index, character) in signature[start + 2..].char_indices() {
+        match character {
+            '(' => depth += 1,
+            ')' => {
+                depth -= 1;
+                if depth == 0 {
+                    return signature[start + 2 + index + 1..].trim().is_empty();
+                }
+            }
+            _ => {}
+        }
+    }
+
+    false
+}
+
+/// A builder for a fake that only runs for a range of calls, see
+/// `WhenCalledBuilder::between_calls`.
+pub struct BetweenCallsBuilder<'a> {
+    builder: WhenCalledBuilder<'a>,
+    calls: RangeInclusive<usize>,
+}
+
+impl BetweenCallsBuilder<'_> {
+    /// Fake the calls in the range with a closure, like `WhenCalledBuilder::will_execute`.
+    ///
+    /// Panics if `fake` is a `fake!` pair rather than a closure.
+    pub fn will_execute<Marker>(self, fake: impl IntoFake<Marker>) -> MockHandle {
+        let parts = fake.into_between_calls_parts(self.calls);
+        self.builder.check_signature(parts.func.signature);
+
+        self.builder.lib.install_map(self.builder.when, parts)
+    }
+}
+
+/// A builder for a fake that only runs for the calls a predicate accepts, see
+/// `InjectorPP::when_called_with`.
+pub struct WhenCalledWithBuilder<'a, P> {
+    builder: WhenCalledBuilder<'a>,
+    predicate: P,
+}
+
+impl<P: Sync + 'static> WhenCalledWithBuilder<'_, P> {
+    /// Fake the accepted calls with a closure, like `WhenCalledBuilder::will_execute`.
+    ///
+    /// The predicate must take references to the same arguments as `fake`. Panics if `fake`
+    /// is a `fake!` pair rather than a closure.
+    pub fn will_execute<Marker>(self, fake: impl IntoFake<Marker>) -> MockHandle
+    where
+        P: IntoPredicate<Marker>,
+    {
+        let parts = fake.into_conditional_parts(self.predicate);
+        self.builder.check_signature(parts.func.signature);
+
+        self.builder.lib.install_map(self.builder.when, parts)
+    }
+}
 
-        let guard = self.when.will_return_boolean_guard(value);
-        self.lib.guards.push(guard);
+/// A builder for a fake that only runs on one thread, see
+/// `InjectorPP::when_called_current_thread_only`.
+pub struct WhenCalledCurrentThreadBuilder<'a> {
+    builder: WhenCalledBuilder<'a>,
+    thread: ThreadId,
+}
+
+impl WhenCalledCurrentThreadBuilder<'_> {
+    /// Fake the calls made on the installing thread with a closure, like
+    /// `WhenCalledBuilder::will_execute`.
+    ///
+    /// Panics if `fake` is a `fake!` pair rather than a closure.
+    pub fn will_execute<Marker>(self, fake: impl IntoFake<Marker>) -> MockHandle {
+        let parts = fake.into_current_thread_parts(self.thread);
+        self.builder.check_signature(parts.func.signature);
+
+        self.builder.lib.install_map(self.builder.when, parts)
     }
 }
 
@@ -514,6 +3345,10 @@ impl WhenCalledBuilderAsync<'_> {
     ///
     /// This method allows you to fake async functions by specifying the return value directly.
     ///
+    /// Neither the future nor its output need to be `Send`, so async functions whose futures
+    /// hold an `Rc` or another `!Send` value can be faked too and awaited on a
+    /// `current_thread` runtime or inside a `LocalSet`.
+    ///
     /// # Example
     ///
     /// ```rust
@@ -534,7 +3369,7 @@ impl WhenCalledBuilderAsync<'_> {
     ///     assert_eq!(result, false);
     /// }
     /// ```
-    pub fn will_return_async(self, target: FuncPtr) {
+    pub fn will_return_async<G>(self, target: FuncPtr<G>) -> MockHandle {
         if target.signature != self.expected_signature {
             panic!(
                 "Signature mismatch: expected {:?} but got {:?}",
@@ -542,8 +3377,191 @@ impl WhenCalledBuilderAsync<'_> {
             );
         }
 
-        let guard = self.when.will_execute_guard(target.func_ptr_internal);
-        self.lib.guards.push(guard);
+        self.lib
+            .install(|| self.when.will_execute_guard(target.func_ptr_internal))
+    }
+
+    /// Fake the target async function to resolve with a clone of `value` on every call.
+    ///
+    /// Unlike `async_return!`, the value is built once by the test and may own heap data
+    /// such as a `String` or a `Vec`. Each returned future yields its own clone, so the
+    /// faked function can be awaited any number of times.
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// use injectorpp::interface::injector::*;
+    ///
+    /// async fn load_name(id: u32) -> String {
+    ///     format!("user {id}")
+    /// }
+    ///
+    /// #[tokio::main]
+    /// async fn main() {
+    ///     let mut injector = InjectorPP::new();
+    ///     injector
+    ///         .when_called_async(injectorpp::async_func!(load_name(0), String))
+    ///         .will_return_async_cloned("fake".to_string());
+    ///
+    ///     assert_eq!(load_name(1).await, "fake");
+    ///     assert_eq!(load_name(2).await, "fake");
+    /// }
+    /// ```
+    pub fn will_return_async_cloned<T>(self, value: T) -> MockHandle
+    where
+        T: Clone + Sync + 'static,
+    {
+        let parts = (move || Poll::Ready(value.clone())).into_fake_parts();
+        if parts.func.signature != self.expected_signature {
+            panic!(
+                "Signature mismatch: expected {:?} but got {:?}",
+                self.expected_signature, parts.func.signature
+            );
+        }
+
+        self.lib.install_fake(self.when, parts)
+    }
+
+    /// Fake the target async function to resolve with a reference to `value` on every call.
+    ///
+    /// This fakes async functions returning references, like a method
+    /// `async fn config(&self) -> &Config` whose future borrows its receiver. The fake cannot
+    /// borrow from the receiver itself, which the future of the target function holds in a
+    /// layout the compiler does not expose. Its output is a `'static` reference instead,
+    /// which callers may use wherever they expect one borrowed from the receiver. The value
+    /// is leaked rather than dropped with the injector, as the references the fake hands out
+    /// may outlive it.
+    ///
+    /// Every async fake requires a `'static` output, so one borrowing from the test, e.g. a
+    /// local variable, fails to compile rather than handing out a dangling reference.
+    /// `async_return!(&VALUE, &'static T)` and `will_return_async_cloned` accept references
+    /// to statics too.
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// use injectorpp::interface::injector::*;
+    ///
+    /// #[derive(Debug, PartialEq)]
+    /// struct Config {
+    ///     retries: u32,
+    /// }
+    ///
+    /// struct Service {
+    ///     config: Config,
+    /// }
+    ///
+    /// impl Service {
+    ///     async fn config(&self) -> &Config {
+    ///         &self.config
+    ///     }
+    /// }
+    ///
+    /// #[tokio::main]
+    /// async fn main() {
+    ///     let service = Service {
+    ///         config: Config { retries: 1 },
+    ///     };
+    ///
+    ///     let mut injector = InjectorPP::new();
+    ///     injector
+    ///         .when_called_async(injectorpp::async_func!(service.config(), &Config))
+    ///         .will_return_async_ref(Box::new(Config { retries: 5 }));
+    ///
+    ///     assert_eq!(service.config().await, &Config { retries: 5 });
+    /// }
+    /// ```
+    pub fn will_return_async_ref<T: ?Sized + Sync + 'static>(self, value: Box<T>) -> MockHandle {
+        let value: &'static T = Box::leak(value);
+        self.will_return_async_cloned(value)
+    }
+
+    /// Fake the target async function to run a future made by `factory` instead.
+    ///
+    /// Unlike `will_return_async`, the fake can await, e.g. to simulate a slow dependency, and
+    /// compute its output when it completes. Each future of the target function gets its own
+    /// future from `factory`, made when it is first polled, so concurrent calls do not share
+    /// state.
+    ///
+    /// `async_func!(expr, $ty)` evaluates `expr` without awaiting it to get a future of the
+    /// target function, whose concrete type `F` cannot be named. `Future::poll` of `F` is the
+    /// function patched, and `$ty` is checked to be `F::Output` at compile time and recorded in
+    /// the signature, against which the output of the futures of `factory` is checked when
+    /// this method is called. Every poll of a future of `F` then polls its fake future, found by
+    /// the address of the pinned future, and forgets it once it is ready.
+    ///
+    /// The output type of `factory` is not inferred from the target function, so literals it
+    /// returns need a suffix such as `42u32`.
+    ///
+    /// A future of the target function dropped before it is ready, e.g. by a timeout, leaves
+    /// its fake future behind until the injector is dropped, and another future later polled
+    /// at the same address resumes it.
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// use injectorpp::interface::injector::*;
+    ///
+    /// async fn fetch_price(item: u32) -> u32 {
+    ///     item
+    /// }
+    ///
+    /// #[tokio::main]
+    /// async fn main() {
+    ///     let mut injector = InjectorPP::new();
+    ///     injector
+    ///         .when_called_async(injectorpp::async_func!(fetch_price(0), u32))
+    ///         .will_execute_async(|| async {
+    ///             tokio::task::yield_now().await;
+    ///             6 * 7u32
+    ///         });
+    ///
+    ///     assert_eq!(fetch_price(1).await, 42);
+    /// }
+    /// ```
+    pub fn will_execute_async<T, Fut>(
+        self,
+        factory: impl Fn() -> Fut + Send + Sync + 'static,
+    ) -> MockHandle
+    where
+        T: 'static,
+        Fut: Future<Output = T> + Send + 'static,
+    {
+        let signature = std::any::type_name::<fn() -> Poll<T>>();
+        if signature != self.expected_signature {
+            panic!(
+                "Signature mismatch: expected {:?} but got {:?}",
+                self.expected_signature, signature
+            );
+        }
+
+        type FakeFuture<T> = Pin<Box<dyn Future<Output = T> + Send>>;
+        let fakes: Mutex<HashMap<usize, FakeFuture<T>>> = Mutex::default();
+
+        // Has the signature of `Future::poll`, a pinned pointer to the future and a pointer to
+        // the context.
+        let poll = move |future: *mut (), context: *mut ()| -> Poll<T> {
+            let context = unsafe { &mut *(context as *mut Context<'_>) };
+
+            // Taken out while polling, so the fake future may await the target function too.
+            let mut fake = fakes
+                .lock()
+                .unwrap_or_else(PoisonError::into_inner)
+                .remove(&(future as usize))
+                .unwrap_or_else(|| Box::pin(factory()));
+
+            let result = fake.as_mut().poll(context);
+            if result.is_pending() {
+                fakes
+                    .lock()
+                    .unwrap_or_else(PoisonError::into_inner)
+                    .insert(future as usize, fake);
+            }
+
+            result
+        };
+
+        self.lib.install_fake(self.when, poll.into_fake_parts())
     }
 
     /// Fake the target async function to return a specified async value.
@@ -577,8 +3595,8 @@ impl WhenCalledBuilderAsync<'_> {
     ///     assert_eq!(result, false);
     /// }
     /// ```
-    pub unsafe fn will_return_async_unchecked(self, target: FuncPtr) {
-        let guard = self.when.will_execute_guard(target.func_ptr_internal);
-        self.lib.guards.push(guard);
+    pub unsafe fn will_return_async_unchecked<G>(self, target: FuncPtr<G>) -> MockHandle {
+        self.lib
+            .install(|| self.when.will_execute_guard(target.func_ptr_internal))
     }
 }
@@ -0,0 +1,376 @@
+use crate::interface::func_ptr::FuncPtr;
+use crate::interface::into_map::private::MapParts;
+use crate::interface::verifier::CallCountVerifier;
+use std::any::Any;
+use std::cell::Cell;
+use std::ops::RangeInclusive;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::thread::ThreadId;
+
+/// Something `WhenCalledBuilder::will_execute` can replace a function with.
+///
+/// Implemented for the pair returned by the `fake!` macro and for closures taking up to six
+/// arguments. The `Marker` parameter only tells the implementations apart and is always
+/// inferred.
+pub trait IntoFake<Marker>: private::IntoFakeParts<Marker> {}
+
+impl<T: private::IntoFakeParts<Marker>, Marker> IntoFake<Marker> for T {}
+
+/// Something `WhenCalledWithBuilder::will_execute` can decide with whether a call is faked.
+///
+/// Implemented for closures returning `bool` and taking references to the arguments of the
+/// target function, up to six. The `Marker` parameter is the one of the fake closure and is
+/// always inferred.
+pub trait IntoPredicate<Marker>: private::PredicateParts<Marker> {}
+
+impl<T: private::PredicateParts<Marker>, Marker> IntoPredicate<Marker> for T {}
+
+pub(crate) mod private {
+    use super::*;
+
+    /// What a fake is made of once it is ready to be installed.
+    pub struct FakeParts {
+        pub(crate) func: FuncPtr,
+        pub(crate) verifier: CallCountVerifier,
+        /// The closure called by `func`, if any, which must be published through
+        /// `set_current_closure` before `func` runs.
+        pub(crate) closure: Option<Box<dyn Any>>,
+    }
+
+    pub trait IntoFakeParts<Marker> {
+        fn into_fake_parts(self) -> FakeParts;
+
+        /// Makes a fake that only runs for the calls in `calls`, counted from 1, and calls
+        /// the original function otherwise.
+        fn into_between_calls_parts(self, _calls: RangeInclusive<usize>) -> MapParts
+        where
+            Self: Sized,
+        {
+            panic!("between_calls requires a closure fake, not a fake! pair");
+        }
+
+        /// Makes a fake that only runs for the calls `predicate` accepts, and calls the
+        /// original function otherwise.
+        fn into_conditional_parts<P>(self, _predicate: P) -> MapParts
+        where
+            Self: Sized,
+            P: PredicateParts<Marker> + Sync + 'static,
+        {
+            panic!("when_called_with requires a closure fake, not a fake! pair");
+        }
+
+        /// Makes a fake that only runs for the calls made on `thread`, and calls the original
+        /// function otherwise.
+        fn into_current_thread_parts(self, _thread: ThreadId) -> MapParts
+        where
+            Self: Sized,
+        {
+            panic!("when_called_current_thread_only requires a closure fake, not a fake! pair");
+        }
+    }
+
+    /// The arguments of a function type as a tuple.
+    pub trait FnArgs {
+        type Args;
+    }
+
+    pub trait PredicateParts<Marker> {
+        fn matches(&self, args: &<Marker as FnArgs>::Args) -> bool
+        where
+            Marker: FnArgs;
+    }
+}
+
+use private::{FakeParts, FnArgs, IntoFakeParts, PredicateParts};
+
+impl IntoFakeParts<(FuncPtr, CallCountVerifier)> for (FuncPtr, CallCountVerifier) {
+    fn into_fake_parts(self) -> FakeParts {
+        FakeParts {
+            func: self.0,
+            verifier: self.1,
+            closure: None,
+        }
+    }
+}
+
+thread_local! {
+    /// The closure the trampoline about to run on this thread must call.
+    static CURRENT_CLOSURE: Cell<*const ()> = const { Cell::new(std::ptr::null()) };
+}
+
+/// Called from the JIT block of a function faked with a closure, right before it jumps to the
+/// trampoline.
+pub(crate) extern "C-unwind" fn set_current_closure(closure: *const (), _registers: *const u64) {
+    CURRENT_CLOSURE.with(|current| current.set(closure));
+}
+
+/// Returns the closure published by `set_current_closure` on this thread.
+///
+/// # Safety
+///
+/// Must be called by a trampoline before anything else can run a faked function on this
+/// thread, and `F` must be the type of the published closure.
+pub(crate) unsafe fn current_closure<'a, F>() -> &'a F {
+    &*(CURRENT_CLOSURE.with(Cell::get) as *const F)
+}
+
+/// A closure only called for some calls, together with the address the original function can
+/// be called at otherwise.
+struct BetweenCallsState<F> {
+    closure: F,
+    calls: RangeInclusive<usize>,
+    count: AtomicUsize,
+    original: AtomicUsize,
+}
+
+/// A closure only called for the calls a predicate accepts, together with the address the
+/// original function can be called at otherwise.
+struct ConditionalState<F, P> {
+    closure: F,
+    predicate: P,
+    original: AtomicUsize,
+}
+
+/// Accepts the calls made on a given thread, the predicate of
+/// `InjectorPP::when_called_current_thread_only`.
+struct OnThread(ThreadId);
+
+impl<Marker> PredicateParts<Marker> for OnThread {
+    fn matches(&self, _args: &<Marker as FnArgs>::Args) -> bool
+    where
+        Marker: FnArgs,
+    {
+        std::thread::current().id() == self.0
+    }
+}
+
+thread_local! {
+    /// Whether a predicate of `when_called_with` is running on this thread.
+    static IN_PREDICATE: Cell<bool> = const { Cell::new(false) };
+}
+
+/// Runs `predicate`, or returns `false` without running it if a predicate is already running
+/// on this thread, so that the functions a predicate calls always run their original code
+/// rather than evaluating a predicate again.
+fn evaluate_predicate(predicate: impl FnOnce() -> bool) -> bool {
+    /// Clears `IN_PREDICATE` even if the predicate panics.
+    struct Reset;
+
+    impl Drop for Reset {
+        fn drop(&mut self) {
+            IN_PREDICATE.with(|in_predicate| in_predicate.set(false));
+        }
+    }
+
+    if IN_PREDICATE.with(|in_predicate| in_predicate.replace(true)) {
+        return false;
+    }
+
+    let _reset = Reset;
+    predicate()
+}
+
+/// Jumped to by the JIT block of a function faked with `WhenCalledBuilder::will_panic`, which
+/// publishes the message through `set_current_closure` first.
+///
+/// The JIT block jumps here rather than calling, so the panic unwinds straight into the
+/// caller of the faked function.
+pub(crate) fn panic_with_current_message() {
+    let message = unsafe { current_closure::<&'static str>() };
+    panic!("{message}");
+}
+
+macro_rules! impl_into_fake_for_closure {
+    (
+        $trampoline:ident,
+        $between_calls_trampoline:ident,
+        $conditional_trampoline:ident,
+        $($arg:ident),*
+    ) => {
+        /// Has the exact signature of the faked function, so the compiler lays out the
+        /// arguments and the return value, then forwards to the closure.
+        #[allow(non_snake_case)]
+        fn $trampoline<F, $($arg,)* R>($($arg: $arg),*) -> R
+        where
+            F: Fn($($arg),*) -> R,
+        {
+            let closure = unsafe { current_closure::<F>() };
+            closure($($arg),*)
+        }
+
+        /// Has the exact signature of the faked function, counts the call, then forwards to
+        /// the closure or to the original function.
+        #[allow(non_snake_case)]
+        fn $between_calls_trampoline<F, $($arg,)* R>($($arg: $arg),*) -> R
+        where
+            F: Fn($($arg),*) -> R,
+        {
+            let state = unsafe { current_closure::<BetweenCallsState<F>>() };
+            let call = state.count.fetch_add(1, Ordering::SeqCst) + 1;
+
+            if state.calls.contains(&call) {
+                (state.closure)($($arg),*)
+            } else {
+                let original: fn($($arg),*) -> R =
+                    unsafe { std::mem::transmute(state.original.load(Ordering::Acquire)) };
+                original($($arg),*)
+            }
+        }
+
+        /// Has the exact signature of the faked function, then forwards to the closure if the
+        /// predicate accepts the arguments or to the original function otherwise.
+        #[allow(non_snake_case)]
+        fn $conditional_trampoline<F, P, $($arg,)* R>($($arg: $arg),*) -> R
+        where
+            F: Fn($($arg),*) -> R,
+            P: PredicateParts<fn($($arg),*) -> R>,
+        {
+            let state = unsafe { current_closure::<ConditionalState<F, P>>() };
+            let args = ($($arg,)*);
+
+            if evaluate_predicate(|| state.predicate.matches(&args)) {
+                let ($($arg,)*) = args;
+                (state.closure)($($arg),*)
+            } else {
+                let ($($arg,)*) = args;
+                let original: fn($($arg),*) -> R =
+                    unsafe { std::mem::transmute(state.original.load(Ordering::Acquire)) };
+                original($($arg),*)
+            }
+        }
+
+        impl<$($arg,)* R> FnArgs for fn($($arg),*) -> R {
+            type Args = ($($arg,)*);
+        }
+
+        impl<P, $($arg,)* R> PredicateParts<fn($($arg),*) -> R> for P
+        where
+            P: Fn($(&$arg),*) -> bool,
+        {
+            #[allow(non_snake_case)]
+            fn matches(&self, args: &<fn($($arg),*) -> R as FnArgs>::Args) -> bool {
+                let ($($arg,)*) = args;
+                self($($arg),*)
+            }
+        }
+
+        impl<F, $($arg,)* R> IntoFakeParts<fn($($arg),*) -> R> for F
+        where
+            F: Fn($($arg),*) -> R + Sync + 'static,
+        {
+            fn into_fake_parts(self) -> FakeParts {
+                let trampoline: fn($($arg),*) -> R = $trampoline::<F, $($arg,)* R>;
+                let signature = std::any::type_name_of_val(&trampoline);
+
+                FakeParts {
+                    func: unsafe { FuncPtr::new(trampoline as *const (), signature) },
+                    verifier: CallCountVerifier::Dummy,
+                    closure: Some(Box::new(self)),
+                }
+            }
+
+            fn into_between_calls_parts(self, calls: RangeInclusive<usize>) -> MapParts {
+                let trampoline: fn($($arg),*) -> R =
+                    $between_calls_trampoline::<F, $($arg,)* R>;
+                let signature = std::any::type_name_of_val(&trampoline);
+
+                let state = Box::new(BetweenCallsState {
+                    closure: self,
+                    calls,
+                    count: AtomicUsize::new(0),
+                    original: AtomicUsize::new(0),
+                });
+                let original = &state.original as *const AtomicUsize;
+
+                MapParts {
+                    func: unsafe { FuncPtr::new(trampoline as *const (), signature) },
+                    state,
+                    original,
+                }
+            }
+
+            fn into_conditional_parts<P>(self, predicate: P) -> MapParts
+            where
+                P: PredicateParts<fn($($arg),*) -> R> + Sync + 'static,
+            {
+                let trampoline: fn($($arg),*) -> R =
+                    $conditional_trampoline::<F, P, $($arg,)* R>;
+                let signature = std::any::type_name_of_val(&trampoline);
+
+                let state = Box::new(ConditionalState {
+                    closure: self,
+                    predicate,
+                    original: AtomicUsize::new(0),
+                });
+                let original = &state.original as *const AtomicUsize;
+
+                MapParts {
+                    func: unsafe { FuncPtr::new(trampoline as *const (), signature) },
+                    state,
+                    original,
+                }
+            }
+
+            fn into_current_thread_parts(self, thread: ThreadId) -> MapParts {
+                self.into_conditional_parts(OnThread(thread))
+            }
+        }
+    };
+}
+
+impl_into_fake_for_closure!(
+    trampoline0,
+    between_calls_trampoline0,
+    conditional_trampoline0,
+);
+impl_into_fake_for_closure!(
+    trampoline1,
+    between_calls_trampoline1,
+    conditional_trampoline1,
+    A1
+);
+impl_into_fake_for_closure!(
+    trampoline2,
+    between_calls_trampoline2,
+    conditional_trampoline2,
+    A1,
+    A2
+);
+impl_into_fake_for_closure!(
+    trampoline3,
+    between_calls_trampoline3,
+    conditional_trampoline3,
+    A1,
+    A2,
+    A3
+);
+impl_into_fake_for_closure!(
+    trampoline4,
+    between_calls_trampoline4,
+    conditional_trampoline4,
+    A1,
+    A2,
+    A3,
+    A4
+);
+impl_into_fake_for_closure!(
+    trampoline5,
+    between_calls_trampoline5,
+    conditional_trampoline5,
+    A1,
+    A2,
+    A3,
+    A4,
+    A5
+);
+impl_into_fake_for_closure!(
+    trampoline6,
+    between_calls_trampoline6,
+    conditional_trampoline6,
+    A1,
+    A2,
+    A3,
+    A4,
+    A5,
+    A6
+);
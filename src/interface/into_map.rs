@@ -0,0 +1,141 @@
+use crate::interface::func_ptr::FuncPtr;
+use crate::interface::into_fake::current_closure;
+use std::any::Any;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+/// Something `WhenCalledBuilder::will_map` can combine with the original function.
+///
+/// Implemented for closures taking the arguments of the target function, up to six, followed
+/// by its original return value. The `Marker` parameter only tells the implementations apart
+/// and is always inferred.
+pub trait IntoMap<Marker>: private::IntoMapParts<Marker> {}
+
+impl<T: private::IntoMapParts<Marker>, Marker> IntoMap<Marker> for T {}
+
+/// Something `WhenCalledBuilder::will_call_original_with_hook` can wrap the original function
+/// with.
+///
+/// Implemented for closures taking the original function as a `fn` pointer followed by the
+/// arguments of the target function, up to six. The `Marker` parameter only tells the
+/// implementations apart and is always inferred.
+pub trait IntoHook<Marker>: private::IntoHookParts<Marker> {}
+
+impl<T: private::IntoHookParts<Marker>, Marker> IntoHook<Marker> for T {}
+
+pub(crate) mod private {
+    use super::*;
+
+    /// What a mapping fake is made of once it is ready to be installed.
+    pub struct MapParts {
+        pub(crate) func: FuncPtr,
+        /// The `MapState` called by `func`, which must be published through
+        /// `set_current_closure` before `func` runs.
+        pub(crate) state: Box<dyn Any>,
+        /// Where the address of the relocated original function must be stored.
+        pub(crate) original: *const AtomicUsize,
+    }
+
+    pub trait IntoMapParts<Marker> {
+        fn into_map_parts(self) -> MapParts;
+    }
+
+    pub trait IntoHookParts<Marker> {
+        fn into_hook_parts(self) -> MapParts;
+    }
+}
+
+use private::{IntoHookParts, IntoMapParts, MapParts};
+
+/// A mapping or hook closure together with the address the original function can be called
+/// at.
+struct MapState<F> {
+    closure: F,
+    original: AtomicUsize,
+}
+
+macro_rules! impl_into_map_for_closure {
+    ($trampoline:ident, $hook_trampoline:ident, $($arg:ident),*) => {
+        /// Has the exact signature of the faked function, calls the original with a copy of
+        /// the arguments, then hands both to the closure.
+        #[allow(non_snake_case)]
+        fn $trampoline<F, $($arg,)* R>($($arg: $arg),*) -> R
+        where
+            F: Fn($($arg,)* R) -> R,
+            $($arg: Clone,)*
+        {
+            let state = unsafe { current_closure::<MapState<F>>() };
+            let original: fn($($arg),*) -> R =
+                unsafe { std::mem::transmute(state.original.load(Ordering::Acquire)) };
+
+            let result = original($($arg.clone()),*);
+            (state.closure)($($arg,)* result)
+        }
+
+        /// Has the exact signature of the faked function and hands the original function
+        /// together with the arguments to the closure.
+        #[allow(non_snake_case)]
+        fn $hook_trampoline<F, $($arg,)* R>($($arg: $arg),*) -> R
+        where
+            F: Fn(fn($($arg),*) -> R, $($arg),*) -> R,
+        {
+            let state = unsafe { current_closure::<MapState<F>>() };
+            let original: fn($($arg),*) -> R =
+                unsafe { std::mem::transmute(state.original.load(Ordering::Acquire)) };
+
+            (state.closure)(original, $($arg),*)
+        }
+
+        impl<F, $($arg,)* R> IntoMapParts<fn($($arg),*) -> R> for F
+        where
+            F: Fn($($arg,)* R) -> R + Sync + 'static,
+            $($arg: Clone,)*
+        {
+            fn into_map_parts(self) -> MapParts {
+                let trampoline: fn($($arg),*) -> R = $trampoline::<F, $($arg,)* R>;
+                let signature = std::any::type_name_of_val(&trampoline);
+
+                let state = Box::new(MapState {
+                    closure: self,
+                    original: AtomicUsize::new(0),
+                });
+                let original = &state.original as *const AtomicUsize;
+
+                MapParts {
+                    func: unsafe { FuncPtr::new(trampoline as *const (), signature) },
+                    state,
+                    original,
+                }
+            }
+        }
+
+        impl<F, $($arg,)* R> IntoHookParts<fn($($arg),*) -> R> for F
+        where
+            F: Fn(fn($($arg),*) -> R, $($arg),*) -> R + Sync + 'static,
+        {
+            fn into_hook_parts(self) -> MapParts {
+                let trampoline: fn($($arg),*) -> R = $hook_trampoline::<F, $($arg,)* R>;
+                let signature = std::any::type_name_of_val(&trampoline);
+
+                let state = Box::new(MapState {
+                    closure: self,
+                    original: AtomicUsize::new(0),
+                });
+                let original = &state.original as *const AtomicUsize;
+
+                MapParts {
+                    func: unsafe { FuncPtr::new(trampoline as *const (), signature) },
+                    state,
+                    original,
+                }
+            }
+        }
+    };
+}
+
+impl_into_map_for_closure!(trampoline0, hook_trampoline0,);
+impl_into_map_for_closure!(trampoline1, hook_trampoline1, A1);
+impl_into_map_for_closure!(trampoline2, hook_trampoline2, A1, A2);
+impl_into_map_for_closure!(trampoline3, hook_trampoline3, A1, A2, A3);
+impl_into_map_for_closure!(trampoline4, hook_trampoline4, A1, A2, A3, A4);
+impl_into_map_for_closure!(trampoline5, hook_trampoline5, A1, A2, A3, A4, A5);
+impl_into_map_for_closure!(trampoline6, hook_trampoline6, A1, A2, A3, A4, A5, A6);
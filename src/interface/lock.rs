@@ -0,0 +1,252 @@
+//! The locks that keep patching from interleaving with other patching or with code that
+//! must see the original functions.
+//!
+//! `FunctionLockGuard` takes any `PatchLock`, and `write_code` any `CodeMemory`, so the
+//! crate's own tests can run them on `loom` models and explore every interleaving of an
+//! installer and a caller:
+//!
+//! ```text
+//! RUSTFLAGS="--cfg injectorpp_loom" cargo test --lib --release loom
+//! ```
+use std::cell::Cell;
+use std::sync::{Mutex, MutexGuard};
+
+/// Held by the live injector or `Preventer`, so only one of them exists at a time.
+static LOCK_FUNCTION: NoPoisonMutex<()> = NoPoisonMutex::new(());
+
+#[cfg(not(all(test, injectorpp_loom)))]
+thread_local! {
+    /// Whether the current thread holds `LOCK_FUNCTION`, through an injector or a `Preventer`.
+    static HOLDS_LOCK_FUNCTION: Cell<bool> = const { Cell::new(false) };
+}
+
+// Only usable inside `loom::model`, which is why the `loom` tests are run on their own.
+#[cfg(all(test, injectorpp_loom))]
+loom::thread_local! {
+    static HOLDS_LOCK_FUNCTION: Cell<bool> = Cell::new(false);
+}
+
+/// A lock guarding a patching step.
+pub(crate) trait PatchLock {
+    type Guard<'a>
+    where
+        Self: 'a;
+
+    /// Blocks until the lock is acquired.
+    fn lock(&self) -> Self::Guard<'_>;
+}
+
+/// `LOCK_FUNCTION`, or another `PatchLock` in tests, held by an injector or a `Preventer`.
+pub(crate) struct FunctionLockGuard<'a, L: PatchLock + 'a = NoPoisonMutex<()>> {
+    _lock: L::Guard<'a>,
+}
+
+impl FunctionLockGuard<'static> {
+    /// Locks `LOCK_FUNCTION`, see `acquire_from`.
+    pub(crate) fn acquire() -> Self {
+        Self::acquire_from(&LOCK_FUNCTION)
+    }
+}
+
+impl<'a, L: PatchLock> FunctionLockGuard<'a, L> {
+    /// Locks `lock`, panicking rather than waiting forever if the current thread already
+    /// holds it.
+    pub(crate) fn acquire_from(lock: &'a L) -> Self {
+        if HOLDS_LOCK_FUNCTION.with(Cell::get) {
+            panic!(
+                "An InjectorPP or a Preventer is already alive on this thread, drop it before creating another one"
+            );
+        }
+
+        let lock = lock.lock();
+        HOLDS_LOCK_FUNCTION.with(|holds| holds.set(true));
+
+        Self { _lock: lock }
+    }
+}
+
+impl<L: PatchLock> Drop for FunctionLockGuard<'_, L> {
+    fn drop(&mut self) {
+        HOLDS_LOCK_FUNCTION.with(|holds| holds.set(false));
+    }
+}
+
+/// A `Mutex` that never stays poisoned: on panic it just recovers the guard.
+///
+/// This is a trade-off between user experience and potential data corrupt issue.
+/// When panic happens in the multi thread scenario, the std Mutex will cause poison error.
+/// This will fail other unrelated test cases. The test failure accuracy is
+/// more important to users so ignore the poison error.
+pub(crate) struct NoPoisonMutex<T> {
+    inner: Mutex<T>,
+}
+
+impl<T> NoPoisonMutex<T> {
+    /// Create a new mutex.
+    pub(crate) const fn new(value: T) -> Self {
+        Self {
+            inner: Mutex::new(value),
+        }
+    }
+}
+
+impl<T> PatchLock for NoPoisonMutex<T> {
+    type Guard<'a>
+        = MutexGuard<'a, T>
+    where
+        T: 'a;
+
+    /// Lock, recovering if the mutex was poisoned.
+    fn lock(&self) -> MutexGuard<'_, T> {
+        match self.inner.lock() {
+            Ok(guard) => guard,
+            Err(poisoned) => {
+                // Swallow the poison and give the guard anyway
+                poisoned.into_inner()
+            }
+        }
+    }
+}
+
+#[cfg(all(test, injectorpp_loom))]
+mod tests {
+    use super::*;
+    use crate::injector_core::common::{write_code, CodeMemory};
+    use loom::sync::atomic::AtomicU64;
+    use loom::sync::atomic::Ordering::{self, Acquire, Relaxed, Release};
+    use loom::sync::Arc;
+    use loom::thread;
+
+    impl<T> PatchLock for loom::sync::Mutex<T> {
+        type Guard<'a>
+            = loom::sync::MutexGuard<'a, T>
+        where
+            T: 'a;
+
+        fn lock(&self) -> Self::Guard<'_> {
+            loom::sync::Mutex::lock(self).unwrap()
+        }
+    }
+
+    // push rbp; mov rbp, rsp; sub rsp, 16; mov [rbp - 4], edi; nop
+    const ORIGINAL: [u8; 12] = [
+        0x55, 0x48, 0x89, 0xE5, 0x48, 0x83, 0xEC, 0x10, 0x89, 0x7D, 0xFC, 0x90,
+    ];
+    // movabs rax, 0x1122334455667788; jmp rax
+    const PATCH: [u8; 12] = [
+        0x48, 0xB8, 0x88, 0x77, 0x66, 0x55, 0x44, 0x33, 0x22, 0x11, 0xFF, 0xE0,
+    ];
+
+    /// The code of one function, longer than a word so `write_code` writes it in steps, kept
+    /// in `loom` atomics so the model sees every byte written.
+    struct ModelCode {
+        /// Gives `write_code` real addresses to pass around, its bytes are never used.
+        addresses: Box<[u64; 2]>,
+        words: [AtomicU64; 2],
+    }
+
+    impl ModelCode {
+        fn new(code: [u8; 12]) -> Self {
+            let mut bytes = [0u8; 16];
+            bytes[..12].copy_from_slice(&code);
+
+            Self {
+                addresses: Box::new([0; 2]),
+                words: [
+                    AtomicU64::new(u64::from_le_bytes(bytes[..8].try_into().unwrap())),
+                    AtomicU64::new(u64::from_le_bytes(bytes[8..].try_into().unwrap())),
+                ],
+            }
+        }
+
+        fn func(&self) -> *mut u8 {
+            self.addresses.as_ptr() as *mut u8
+        }
+
+        /// Returns the word holding the byte at `address` and the offset of the byte in it.
+        fn word(&self, address: *mut u8) -> (&AtomicU64, usize) {
+            let offset = address as usize - self.func() as usize;
+            (&self.words[offset / 8], offset % 8)
+        }
+
+        /// Reads the code with `fetch_or(0)` rather than loads: `loom` only looks for other
+        /// orders of accesses to the same word when one of them writes it, so loads alone
+        /// would hide the reads of one thread behind the later ones of another.
+        fn read(&self) -> [u8; 12] {
+            let mut bytes = [0u8; 16];
+            bytes[..8].copy_from_slice(&self.words[0].fetch_or(0, Acquire).to_le_bytes());
+            bytes[8..].copy_from_slice(&self.words[1].fetch_or(0, Acquire).to_le_bytes());
+            bytes[..12].try_into().unwrap()
+        }
+    }
+
+    /// Changes the bytes of `word` with a compare-exchange loop like `ProcessCode::write_word`.
+    fn update(word: &AtomicU64, ordering: Ordering, change: impl Fn(&mut [u8; 8])) {
+        let mut current = word.load(Relaxed);
+        loop {
+            let mut bytes = current.to_le_bytes();
+            change(&mut bytes);
+
+            match word.compare_exchange(current, u64::from_le_bytes(bytes), ordering, Relaxed) {
+                Ok(_) => return,
+                Err(actual) => current = actual,
+            }
+        }
+    }
+
+    impl CodeMemory for ModelCode {
+        unsafe fn write_word(&self, dest: *mut u8, code: &[u8]) -> bool {
+            let (word, offset) = self.word(dest);
+            if offset + code.len() > 8 {
+                return false;
+            }
+
+            update(word, Release, |bytes| {
+                bytes[offset..offset + code.len()].copy_from_slice(code)
+            });
+            true
+        }
+
+        /// Writes one byte at a time, like a plain copy the other threads may see halfway.
+        unsafe fn copy(&self, dest: *mut u8, code: &[u8]) {
+            for (index, byte) in code.iter().enumerate() {
+                let (word, offset) = self.word(dest.add(index));
+                update(word, Relaxed, |bytes| bytes[offset] = *byte);
+            }
+        }
+
+        unsafe fn clear_cache(&self, _start: *mut u8, _end: *mut u8) {}
+    }
+
+    #[test]
+    fn test_loom_caller_holding_preventer_never_sees_half_patched_code() {
+        loom::model(|| {
+            let function_lock = Arc::new(loom::sync::Mutex::new(()));
+            let code = Arc::new(ModelCode::new(ORIGINAL));
+
+            // `InjectorPP::new`, an install, then the restore when the injector is dropped.
+            let installer = {
+                let function_lock = function_lock.clone();
+                let code = code.clone();
+                thread::spawn(move || {
+                    let _injector = FunctionLockGuard::acquire_from(&*function_lock);
+
+                    let original = code.read();
+                    unsafe { write_code(&*code, code.func(), &PATCH) };
+                    assert_eq!(code.read(), PATCH);
+
+                    unsafe { write_code(&*code, code.func(), &original) };
+                })
+            };
+
+            // `InjectorPP::prevent`, then a call to the function.
+            {
+                let _preventer = FunctionLockGuard::acquire_from(&*function_lock);
+                assert_eq!(code.read(), ORIGINAL);
+            }
+
+            installer.join().unwrap();
+            assert_eq!(code.read(), ORIGINAL);
+        });
+    }
+}
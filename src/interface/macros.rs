@@ -3,96 +3,354 @@
 /// This macro handles both generic and non-generic functions:
 /// - For generic functions, provide the function name and type parameters separately: `func!(function_name, fn(Type1, Type2))`
 /// - For non-generic functions, simply provide the function: `func!(function_name, fn())`
+///
+/// A generic function has one address per instantiation. The one named, e.g.
+/// `func!(fn (identity::<u32>)(u32) -> u32)`, is faked and the others keep running their
+/// original code. Naming the instantiation makes the compiler emit it, so its address is
+/// always available. Type arguments holding references need a lifetime that can be named
+/// here, such as `func!(fn (describe::<&'static str>)(&'static str) -> String)`.
+///
+/// Optimized builds may merge instantiations compiled to identical code, such as
+/// `identity::<u32>` and `identity::<i32>`, into one function, and faking either then fakes
+/// both.
 #[macro_export]
 macro_rules! func {
     // Case 1: Generic function — provide function name and types separately
     ($f:ident :: <$($gen:ty),*>, $fn_type:ty) => {{
         let fn_val:$fn_type = $f::<$($gen),*>;
         let ptr = fn_val as *const ();
-        let sig = std::any::type_name_of_val(&fn_val);
 
-        unsafe { FuncPtr::new(ptr, sig) }
+        unsafe { FuncPtr::<$fn_type>::new_typed(ptr) }
     }};
 
+    // Forwarded by the simplified forms once `__assert_direct_function!` accepted the callable.
+    (@checked $f:expr, $fn_type:ty) => {
+        $crate::func!($f, $fn_type)
+    };
+
+    // Closures, function pointer fields and trait objects are rejected with a clear message.
+    (| $($closure:tt)*) => {
+        $crate::__assert_direct_function!(| $($closure)*)
+    };
+
+    (|| $($closure:tt)*) => {
+        $crate::__assert_direct_function!(|| $($closure)*)
+    };
+
+    (move $($closure:tt)*) => {
+        $crate::__assert_direct_function!(move $($closure)*)
+    };
+
+    (< dyn $($path:tt)*) => {
+        $crate::__assert_direct_function!(< dyn $($path)*)
+    };
+
+    ($base:ident . $($field:tt)*) => {
+        $crate::__assert_direct_function!($base . $($field)*)
+    };
+
     // Case 2: Non-generic function
     ($f:expr, $fn_type:ty) => {{
         let fn_val:$fn_type = $f;
         let ptr = fn_val as *const ();
-        let sig = std::any::type_name_of_val(&fn_val);
 
-        unsafe { FuncPtr::new(ptr, sig) }
+        unsafe { FuncPtr::<$fn_type>::new_typed(ptr) }
     }};
 
     // Simplified fn with return
-    (func_info: fn ( $f:expr ) ( $($arg_ty:ty),* ) -> $ret:ty) => {{
-        $crate::func!($f, fn($($arg_ty),*) -> $ret)
+    (func_info: fn ( $($f:tt)+ ) ( $($arg_ty:ty),* ) -> $ret:ty) => {{
+        $crate::__assert_direct_function!($($f)+);
+        $crate::func!(@checked $($f)+, fn($($arg_ty),*) -> $ret)
     }};
 
-    (fn ( $f:expr ) ( $($arg_ty:ty),* ) -> $ret:ty) => {{
-        $crate::func!($f, fn($($arg_ty),*) -> $ret)
+    (fn ( $($f:tt)+ ) ( $($arg_ty:ty),* ) -> $ret:ty) => {{
+        $crate::__assert_direct_function!($($f)+);
+        $crate::func!(@checked $($f)+, fn($($arg_ty),*) -> $ret)
     }};
 
     // Simplified fn with unit return
-    (func_info: fn ( $f:expr ) ( $($arg_ty:ty),* )) => {{
-        $crate::func!($f, fn($($arg_ty),*))
+    (func_info: fn ( $($f:tt)+ ) ( $($arg_ty:ty),* )) => {{
+        $crate::__assert_direct_function!($($f)+);
+        $crate::func!(@checked $($f)+, fn($($arg_ty),*))
     }};
 
-    (fn ( $f:expr ) ( $($arg_ty:ty),* )) => {{
-        $crate::func!($f, fn($($arg_ty),*))
+    (fn ( $($f:tt)+ ) ( $($arg_ty:ty),* )) => {{
+        $crate::__assert_direct_function!($($f)+);
+        $crate::func!(@checked $($f)+, fn($($arg_ty),*))
     }};
 
     // Simplified unsafe fn with return
-    (func_info: unsafe fn ( $f:expr ) ( $($arg_ty:ty),* ) -> $ret:ty) => {{
-        $crate::func!($f, unsafe fn($($arg_ty),*) -> $ret)
+    (func_info: unsafe fn ( $($f:tt)+ ) ( $($arg_ty:ty),* ) -> $ret:ty) => {{
+        $crate::__assert_direct_function!($($f)+);
+        $crate::func!(@checked $($f)+, unsafe fn($($arg_ty),*) -> $ret)
     }};
 
-    (unsafe{} fn ( $f:expr ) ( $($arg_ty:ty),* ) -> $ret:ty) => {{
-        $crate::func!($f, unsafe fn($($arg_ty),*) -> $ret)
+    (unsafe{} fn ( $($f:tt)+ ) ( $($arg_ty:ty),* ) -> $ret:ty) => {{
+        $crate::__assert_direct_function!($($f)+);
+        $crate::func!(@checked $($f)+, unsafe fn($($arg_ty),*) -> $ret)
     }};
 
     // Simplified unsafe fn with unit return
-    (func_info: unsafe fn ( $f:expr ) ( $($arg_ty:ty),* )) => {{
-        $crate::func!($f, unsafe fn($($arg_ty),*) -> ())
+    (func_info: unsafe fn ( $($f:tt)+ ) ( $($arg_ty:ty),* )) => {{
+        $crate::__assert_direct_function!($($f)+);
+        $crate::func!(@checked $($f)+, unsafe fn($($arg_ty),*) -> ())
     }};
 
-    (unsafe{} fn ( $f:expr ) ( $($arg_ty:ty),* )) => {{
-        $crate::func!($f, unsafe fn($($arg_ty),*) -> ())
+    (unsafe{} fn ( $($f:tt)+ ) ( $($arg_ty:ty),* )) => {{
+        $crate::__assert_direct_function!($($f)+);
+        $crate::func!(@checked $($f)+, unsafe fn($($arg_ty),*) -> ())
     }};
 
     // Simplified unsafe extern "C" fn with return
-    (func_info: unsafe extern "C" fn ( $f:expr ) ( $($arg_ty:ty),* ) -> $ret:ty) => {{
-        $crate::func!($f, unsafe extern "C" fn($($arg_ty),*) -> $ret)
+    (func_info: unsafe extern "C" fn ( $($f:tt)+ ) ( $($arg_ty:ty),* ) -> $ret:ty) => {{
+        $crate::__assert_direct_function!($($f)+);
+        $crate::func!(@checked $($f)+, unsafe extern "C" fn($($arg_ty),*) -> $ret)
     }};
 
-    (unsafe{} extern "C" fn ( $f:expr ) ( $($arg_ty:ty),* ) -> $ret:ty) => {{
-        $crate::func!($f, unsafe extern "C" fn($($arg_ty),*) -> $ret)
+    (unsafe{} extern "C" fn ( $($f:tt)+ ) ( $($arg_ty:ty),* ) -> $ret:ty) => {{
+        $crate::__assert_direct_function!($($f)+);
+        $crate::func!(@checked $($f)+, unsafe extern "C" fn($($arg_ty),*) -> $ret)
     }};
 
     // Simplified unsafe extern "C" fn with unit return
-    (func_info: unsafe extern "C" fn ( $f:expr ) ( $($arg_ty:ty),* )) => {{
-        $crate::func!($f, unsafe extern "C" fn($($arg_ty),*) -> ())
+    (func_info: unsafe extern "C" fn ( $($f:tt)+ ) ( $($arg_ty:ty),* )) => {{
+        $crate::__assert_direct_function!($($f)+);
+        $crate::func!(@checked $($f)+, unsafe extern "C" fn($($arg_ty),*) -> ())
     }};
 
-    (unsafe{} extern "C" fn ( $f:expr ) ( $($arg_ty:ty),* )) => {{
-        $crate::func!($f, unsafe extern "C" fn($($arg_ty),*) -> ())
+    (unsafe{} extern "C" fn ( $($f:tt)+ ) ( $($arg_ty:ty),* )) => {{
+        $crate::__assert_direct_function!($($f)+);
+        $crate::func!(@checked $($f)+, unsafe extern "C" fn($($arg_ty),*) -> ())
     }};
 
     // Simplified unsafe extern "system" fn with return
-    (func_info: unsafe extern "system" fn ( $f:expr ) ( $($arg_ty:ty),* ) -> $ret:ty) => {{
-        $crate::func!($f, unsafe extern "system" fn($($arg_ty),*) -> $ret)
+    (func_info: unsafe extern "system" fn ( $($f:tt)+ ) ( $($arg_ty:ty),* ) -> $ret:ty) => {{
+        $crate::__assert_direct_function!($($f)+);
+        $crate::func!(@checked $($f)+, unsafe extern "system" fn($($arg_ty),*) -> $ret)
     }};
 
-    (unsafe{} extern "system" fn ( $f:expr ) ( $($arg_ty:ty),* ) -> $ret:ty) => {{
-        $crate::func!($f, unsafe extern "system" fn($($arg_ty),*) -> $ret)
+    (unsafe{} extern "system" fn ( $($f:tt)+ ) ( $($arg_ty:ty),* ) -> $ret:ty) => {{
+        $crate::__assert_direct_function!($($f)+);
+        $crate::func!(@checked $($f)+, unsafe extern "system" fn($($arg_ty),*) -> $ret)
     }};
 
     // Simplified unsafe extern "system" fn with unit return
-    (func_info: unsafe extern "system" fn ( $f:expr ) ( $($arg_ty:ty),* )) => {{
-        $crate::func!($f, unsafe extern "system" fn($($arg_ty),*) -> ())
+    (func_info: unsafe extern "system" fn ( $($f:tt)+ ) ( $($arg_ty:ty),* )) => {{
+        $crate::__assert_direct_function!($($f)+);
+        $crate::func!(@checked $($f)+, unsafe extern "system" fn($($arg_ty),*) -> ())
+    }};
+
+    (unsafe{} extern "system" fn ( $($f:tt)+ ) ( $($arg_ty:ty),* )) => {{
+        $crate::__assert_direct_function!($($f)+);
+        $crate::func!(@checked $($f)+, unsafe extern "system" fn($($arg_ty),*) -> ())
+    }};
+}
+
+/// Rejects callables that are not reached through a fixed code address.
+///
+/// Patching overwrites the code at the address of a function item. Closures, function pointers
+/// stored in fields and methods called through `dyn Trait` are called indirectly, so faking
+/// them this way would silently have no effect on the calls the user has in mind.
+#[doc(hidden)]
+#[macro_export]
+macro_rules! __assert_direct_function {
+    (| $($closure:tt)*) => {
+        compile_error!(concat!(
+            "injectorpp::func! cannot fake a closure: closures are called through a pointer, ",
+            "not at a fixed function address. Fake the function that calls the closure instead, ",
+            "or pass a different closure to it. To use a closure as the replacement, ",
+            "pass it to `closure!` or `will_execute`."
+        ))
+    };
+
+    (|| $($closure:tt)*) => {
+        $crate::__assert_direct_function!(| $($closure)*)
+    };
+
+    (move $($closure:tt)*) => {
+        $crate::__assert_direct_function!(| $($closure)*)
+    };
+
+    (< dyn $($path:tt)*) => {
+        compile_error!(concat!(
+            "injectorpp::func! cannot fake a method of `dyn Trait`: calls on a trait object ",
+            "go through its vtable. Fake the implementation instead, ",
+            "e.g. `trait_method!(fn (<MyType as Trait>::method)(&self) -> bool)`, ",
+            "or pass a different trait object."
+        ))
+    };
+
+    ($base:ident . $($field:tt)*) => {
+        compile_error!(concat!(
+            "injectorpp::func! cannot fake a function pointer stored in a field: calls through ",
+            "it are indirect and never reach a patched address. Fake the function it points to ",
+            "by its path, e.g. `func!(fn (module::function)(u32) -> bool)`, or store a ",
+            "different function pointer in the field."
+        ))
+    };
+
+    ($($f:tt)+) => {};
+}
+
+/// Converts a method of a generic type to a `FuncPtr`, taking the type arguments from an
+/// instance.
+///
+/// `func!` needs the type arguments spelled out, e.g.
+/// `func!(fn (Container::<u32>::len)(&Container<u32>) -> usize)`. When they are long or
+/// cannot be named at all, like a closure type, pass an expression of the concrete type and
+/// the method path without type arguments instead. The expression is only borrowed, so no
+/// dummy instance needs to be built when the test already has one.
+///
+/// The method must take `&self`. List its other argument types in parentheses before the
+/// return type. Generic free functions taking the type by reference, such as a fake, work
+/// the same way.
+///
+/// # Example
+///
+/// ```rust
+/// use injectorpp::interface::injector::*;
+///
+/// struct Container<T> {
+///     items: Vec<T>,
+/// }
+///
+/// impl<T> Container<T> {
+///     fn len(&self) -> usize {
+///         self.items.len()
+///     }
+///
+///     fn contains(&self, index: usize) -> bool {
+///         index < self.items.len()
+///     }
+/// }
+///
+/// fn fake_len<T>(_container: &Container<T>) -> usize {
+///     42
+/// }
+///
+/// // The type of this container cannot be named.
+/// let container = Container { items: vec![|| 1] };
+///
+/// let mut injector = InjectorPP::new();
+/// injector
+///     .when_called(injectorpp::func_of!(container, Container::len, usize))
+///     .will_execute_raw(injectorpp::func_of!(container, fake_len, usize));
+/// injector
+///     .when_called(injectorpp::func_of!(container, Container::contains, (usize) -> bool))
+///     .will_return_boolean(true);
+///
+/// assert_eq!(container.len(), 42);
+/// assert!(container.contains(7));
+/// ```
+#[macro_export]
+macro_rules! func_of {
+    ($instance:expr, $method:path, ($($arg:ty),*) -> $ret:ty) => {{
+        fn __method_of<T>(
+            _instance: &T,
+            method: fn(&T $(, $arg)*) -> $ret,
+        ) -> fn(&T $(, $arg)*) -> $ret {
+            method
+        }
+
+        let fn_val = __method_of(&$instance, $method);
+
+        unsafe { __func_ptr_of(&fn_val, fn_val as *const ()) }
+    }};
+
+    ($instance:expr, $method:path, $ret:ty) => {
+        $crate::func_of!($instance, $method, () -> $ret)
+    };
+}
+
+/// Converts the implementation of a trait method for a concrete type to a `FuncPtr`.
+///
+/// Write the method as `<Type as Trait>::method` and its receiver as `&self`, `&mut self` or
+/// `self`, followed by the other argument types. The receiver stands for `&Type`,
+/// `&mut Type` or `Type` in the signature the fake must match.
+///
+/// Calls through a `&dyn Trait` or `Box<dyn Trait>` holding a `Type` reach the same code, so
+/// they are faked too.
+///
+/// A default method the type does not override is written the same way. Every type using it
+/// gets its own copy, so only the copy of `Type` is faked. Optimized builds may fold identical
+/// copies into one function, which is then faked for every type sharing it. To fake a
+/// default method for several types whatever the build, fake the copy of each of them.
+///
+/// # Example
+///
+/// ```rust
+/// use injectorpp::interface::injector::*;
+///
+/// trait Service {
+///     fn get(&self, path: &str) -> u16;
+/// }
+///
+/// struct HttpClient;
+///
+/// impl Service for HttpClient {
+///     fn get(&self, _path: &str) -> u16 {
+///         500
+///     }
+/// }
+///
+/// let mut injector = InjectorPP::new();
+/// injector
+///     .when_called(injectorpp::trait_method!(
+///         fn (<HttpClient as Service>::get)(&self, &str) -> u16
+///     ))
+///     .will_execute(|_: &HttpClient, _: &str| 200u16);
+///
+/// let service: &dyn Service = &HttpClient;
+/// assert_eq!(service.get("/health"), 200);
+/// ```
+#[macro_export]
+macro_rules! trait_method {
+    (fn (< $ty:ty as $trait:path >:: $method:ident) (&self $(, $arg_ty:ty)*) $(-> $ret:ty)?) => {
+        $crate::func!(<$ty as $trait>::$method, fn(&$ty $(, $arg_ty)*) $(-> $ret)?)
+    };
+
+    (fn (< $ty:ty as $trait:path >:: $method:ident) (&mut self $(, $arg_ty:ty)*) $(-> $ret:ty)?) => {
+        $crate::func!(<$ty as $trait>::$method, fn(&mut $ty $(, $arg_ty)*) $(-> $ret)?)
+    };
+
+    (fn (< $ty:ty as $trait:path >:: $method:ident) (self $(, $arg_ty:ty)*) $(-> $ret:ty)?) => {
+        $crate::func!(<$ty as $trait>::$method, fn($ty $(, $arg_ty)*) $(-> $ret)?)
+    };
+}
+
+/// Converts a C-variadic function to a `FuncPtr`.
+///
+/// Write the signature as C declares it, ending the arguments with `...`. Such functions can
+/// only be faked with a constant return such as `will_return_i32`: a Rust closure cannot be
+/// variadic, so forwarding the variadic arguments to one is not supported.
+///
+/// # Example
+///
+/// ```rust,no_run
+/// use injectorpp::interface::injector::*;
+/// use std::os::raw::{c_char, c_int};
+///
+/// extern "C" {
+///     fn printf(format: *const c_char, ...) -> c_int;
+/// }
+///
+/// let mut injector = InjectorPP::new();
+/// injector
+///     .when_called(injectorpp::variadic_func!(
+///         unsafe{} extern "C" fn (printf)(*const c_char, ...) -> c_int
+///     ))
+///     .will_return_i32(0);
+/// ```
+#[macro_export]
+macro_rules! variadic_func {
+    (unsafe{} extern "C" fn ( $($f:tt)+ ) ( $($arg:tt)+ ) -> $ret:ty) => {{
+        $crate::__assert_direct_function!($($f)+);
+        $crate::func!(@checked $($f)+, unsafe extern "C" fn($($arg)+) -> $ret)
     }};
 
-    (unsafe{} extern "system" fn ( $f:expr ) ( $($arg_ty:ty),* )) => {{
-        $crate::func!($f, unsafe extern "system" fn($($arg_ty),*) -> ())
+    (unsafe{} extern "C" fn ( $($f:tt)+ ) ( $($arg:tt)+ )) => {{
+        $crate::__assert_direct_function!($($f)+);
+        $crate::func!(@checked $($f)+, unsafe extern "C" fn($($arg)+) -> ())
     }};
 }
 
@@ -137,13 +395,94 @@ macro_rules! func_unchecked {
 ///
 /// - `$closure`: The closure to convert
 /// - `$fn_type`: The explicit function type signature that the closure conforms to
+///
+/// The closure must not capture anything, so it coerces to a plain `fn` pointer of
+/// `$fn_type`. That pointer uses the same Rust calling convention as the faked function, so
+/// the closure receives the original arguments whatever their number and types: the compiler
+/// places them in integer registers, float registers or on the stack exactly as the caller
+/// did. There is no limit on the number of arguments.
+///
+/// `$fn_type` may return a reference, such as `fn(&Config) -> &str` for a method
+/// `fn name(&self) -> &str`. The closure can then return a `'static` reference or one into
+/// its arguments. Returning a reference to a local fails to compile, and so does a closure
+/// that captures the data it returns. To return data owned by the injector, use
+/// `will_return_ref` instead.
+///
+/// # Example
+///
+/// ```rust
+/// use injectorpp::interface::injector::*;
+///
+/// fn add(a: i32, b: i32) -> i32 {
+///     a + b
+/// }
+///
+/// let mut injector = InjectorPP::new();
+/// injector
+///     .when_called(injectorpp::func!(fn (add)(i32, i32) -> i32))
+///     .will_execute_raw(injectorpp::closure!(
+///         |a: i32, b: i32| a + b + 1,
+///         fn(i32, i32) -> i32
+///     ));
+///
+/// assert_eq!(add(1, 2), 4);
+/// ```
 #[macro_export]
 macro_rules! closure {
     ($closure:expr, $fn_type:ty) => {{
         let fn_val: $fn_type = $closure;
-        let sig = std::any::type_name_of_val(&fn_val);
 
-        unsafe { FuncPtr::new(fn_val as *const (), sig) }
+        unsafe { FuncPtr::<$fn_type>::new_typed(fn_val as *const ()) }
+    }};
+}
+
+/// Checks that a closure capturing its environment matches a function type, for
+/// `WhenCalledBuilder::will_execute`.
+///
+/// A capturing closure is not a plain `fn` pointer, so it cannot go through `closure!` and
+/// `will_execute_raw`. `will_execute` boxes it instead and keeps it alive until the injector
+/// is dropped, calling it through a trampoline with the signature of the faked function. This
+/// macro spells that signature out, so the arguments of the closure need no annotations.
+///
+/// The closure must be `Sync` and `'static`: move shared state such as an `Arc<AtomicUsize>`
+/// or an `Arc<Mutex<_>>` into it.
+///
+/// # Example
+///
+/// ```rust
+/// use injectorpp::interface::injector::*;
+/// use std::sync::atomic::{AtomicUsize, Ordering};
+/// use std::sync::Arc;
+///
+/// fn add(a: i32, b: i32) -> i32 {
+///     a + b
+/// }
+///
+/// let calls = Arc::new(AtomicUsize::new(0));
+/// let counter = calls.clone();
+///
+/// let mut injector = InjectorPP::new();
+/// injector
+///     .when_called(injectorpp::func!(fn (add)(i32, i32) -> i32))
+///     .will_execute(injectorpp::closure_capturing!(
+///         move |a, b| {
+///             counter.fetch_add(1, Ordering::SeqCst);
+///             a * b
+///         },
+///         fn(i32, i32) -> i32
+///     ));
+///
+/// assert_eq!(add(3, 4), 12);
+/// assert_eq!(calls.load(Ordering::SeqCst), 1);
+/// ```
+#[macro_export]
+macro_rules! closure_capturing {
+    ($closure:expr, fn($($arg_ty:ty),*) $(-> $ret:ty)?) => {{
+        fn __capturing<F: Fn($($arg_ty),*) $(-> $ret)? + Sync + 'static>(closure: F) -> F {
+            closure
+        }
+
+        __capturing($closure)
     }};
 }
 
@@ -172,24 +511,60 @@ macro_rules! closure_unchecked {
     }};
 }
 
+/// Returns a `FuncPtr` typed like `function`, for `func_of!` whose function type cannot be
+/// named.
+///
+/// # Safety
+///
+/// `ptr` must be the address of `function`.
 #[doc(hidden)]
-pub fn __assert_future_output<Fut, T>(_: &mut Fut)
+pub unsafe fn __func_ptr_of<F>(
+    _function: &F,
+    ptr: *const (),
+) -> crate::interface::func_ptr::FuncPtr<F> {
+    crate::interface::func_ptr::FuncPtr::new_typed(ptr)
+}
+
+/// Checks at compile time that the future given to `async_func!` has the output `T`.
+#[doc(hidden)]
+pub fn __assert_future_output<Fut, T>(future: Fut) -> Fut
 where
     Fut: std::future::Future<Output = T>,
 {
+    future
+}
+
+/// Returns the signature `async_func!` records for a future of output `T`.
+///
+/// `T` is inferred from a `PhantomData` rather than written in the signature type, so an
+/// output borrowing from the receiver such as `&Config` does not need a named lifetime.
+#[doc(hidden)]
+pub fn __future_output_signature<T>(_: std::marker::PhantomData<T>) -> &'static str {
+    std::any::type_name::<fn() -> std::task::Poll<T>>()
 }
 
 /// Ensure the async function can be correctly used in injectorpp.
+///
+/// `$ty` is the output type of the future. It may be a reference borrowing from the
+/// arguments, such as `&Config` for `async fn config(&self) -> &Config`, see
+/// `will_return_async_ref`.
+///
+/// `$expr` calls the function only to name the type of its future: the future is pinned and
+/// dropped without ever being polled, so the body does not run and a future holding
+/// references into itself never gets to create them. A method may take `&self`, `&mut self`
+/// or `self`, on any instance, even a temporary such as `Counter::default().add(0)`. The
+/// fake then applies to the calls on every instance. A `&mut self` receiver stays borrowed
+/// until the end of the statement using the macro.
 #[macro_export]
 macro_rules! async_func {
-    ($expr:expr, $ty:ty) => {{
-        let mut __fut = $expr;
-
-        let _ = __assert_future_output::<_, $ty>(&mut __fut);
-
-        let sig = std::any::type_name::<fn() -> std::task::Poll<$ty>>();
-        (std::pin::pin!(__fut), sig)
-    }};
+    // No block or `let`, so the temporaries of `$expr`, such as a receiver built only to name
+    // the method, live until the end of the enclosing statement.
+    ($expr:expr, $ty:ty) => {
+        (
+            std::pin::pin!(__assert_future_output::<_, $ty>($expr)),
+            __future_output_signature(std::marker::PhantomData::<$ty>),
+        )
+    };
 }
 
 /// Ensure the async function can be correctly used in injectorpp.
@@ -205,6 +580,38 @@ macro_rules! async_func_unchecked {
 }
 
 /// Config a return value for faking an async function.
+///
+/// `$ty` is the output type of the future, which may be any type including `Result` and
+/// `Option`. The value is returned as is, so `Err` and `None` can be faked as well. It is
+/// built inside a nested function and cannot refer to local variables.
+///
+/// # Example
+///
+/// ```rust
+/// use injectorpp::interface::injector::*;
+///
+/// #[derive(Debug, PartialEq)]
+/// enum FetchError {
+///     Timeout,
+/// }
+///
+/// async fn fetch(url: &str) -> Result<String, FetchError> {
+///     Ok(format!("body of {url}"))
+/// }
+///
+/// #[tokio::main]
+/// async fn main() {
+///     let mut injector = InjectorPP::new();
+///     injector
+///         .when_called_async(injectorpp::async_func!(fetch(""), Result<String, FetchError>))
+///         .will_return_async(injectorpp::async_return!(
+///             Err(FetchError::Timeout),
+///             Result<String, FetchError>
+///         ));
+///
+///     assert_eq!(fetch("https://example.com").await, Err(FetchError::Timeout));
+/// }
+/// ```
 #[macro_export]
 macro_rules! async_return {
     ($val:expr, $ty:ty) => {{
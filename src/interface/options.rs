@@ -0,0 +1,93 @@
+/// Where to start looking for the JIT memory a fake's code is placed in.
+///
+/// On AArch64, x86_64 and RISC-V the patched function branches to that memory, so it has to
+/// be within the branch's reach of the function, see `InjectError::OutOfBranchRange`. Pages
+/// within that range are tried one by one until the OS maps one, and the strategy decides
+/// the order. On x86_64 the OS chooses once none is free, and the patch branches to it with
+/// a 12-byte absolute jump. Other architectures let the OS choose and ignore it.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+#[non_exhaustive]
+pub enum JitAllocStrategy {
+    /// Starts at the patched function and alternates above and below it.
+    #[default]
+    Nearest,
+
+    /// Scans upward from the lowest address in range of the patched function.
+    Ascending,
+
+    /// Starts at the given address and alternates above and below it. Only addresses in
+    /// range of the patched function are tried, so a hint out of range starts at the
+    /// nearest address in range.
+    Hint(usize),
+}
+
+/// Settings of an injector created with `InjectorPP::new_with_options`.
+///
+/// # Example
+///
+/// ```rust
+/// use injectorpp::interface::injector::*;
+///
+/// fn is_ready() -> bool {
+///     false
+/// }
+///
+/// let options = InjectorOptions::new().jit_alloc_strategy(JitAllocStrategy::Ascending);
+/// let mut injector = InjectorPP::new_with_options(options);
+/// injector
+///     .when_called(injectorpp::func!(fn (is_ready)() -> bool))
+///     .will_return_boolean(true);
+///
+/// assert!(is_ready());
+/// ```
+#[derive(Clone, Debug, Default)]
+pub struct InjectorOptions {
+    pub(crate) jit_alloc_strategy: JitAllocStrategy,
+    pub(crate) w_xor_x: bool,
+}
+
+impl InjectorOptions {
+    /// Creates the options `InjectorPP::new` uses.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Sets where to start looking for JIT memory, `JitAllocStrategy::Nearest` by default.
+    pub fn jit_alloc_strategy(mut self, strategy: JitAllocStrategy) -> Self {
+        self.jit_alloc_strategy = strategy;
+        self
+    }
+
+    /// Never makes memory writable and executable at once, for systems that forbid it. Off by
+    /// default.
+    ///
+    /// The code of each fake is written to JIT memory mapped writable only, which is then made
+    /// executable and never written again, so fakes no longer share JIT pages.
+    ///
+    /// The code pages of the patched functions no longer stay writable and executable. On
+    /// Linux patches are written through `/proc/self/mem`, so the pages keep their protection
+    /// throughout. On Windows they are writable and executable only while a patch is written
+    /// or restored, and get their original protection back afterwards. macOS always writes
+    /// patches through a copy of the code page, so only JIT memory is affected there.
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// use injectorpp::interface::injector::*;
+    ///
+    /// fn is_ready() -> bool {
+    ///     false
+    /// }
+    ///
+    /// let mut injector = InjectorPP::new_with_options(InjectorOptions::new().w_xor_x(true));
+    /// injector
+    ///     .when_called(injectorpp::func!(fn (is_ready)() -> bool))
+    ///     .will_return_boolean(true);
+    ///
+    /// assert!(is_ready());
+    /// ```
+    pub fn w_xor_x(mut self, enabled: bool) -> Self {
+        self.w_xor_x = enabled;
+        self
+    }
+}
@@ -0,0 +1,40 @@
+/// A 16-byte value `WhenCalledBuilder::will_return_aggregate` can return in two integer
+/// registers.
+///
+/// Implemented for pairs of 8-byte integers and pointers such as `(u64, u64)` or
+/// `(usize, *const u8)`, which Rust returns in `x0`/`x1` on AArch64 and `rax`/`rdx` on
+/// x86_64. Arrays, floating point pairs and structs are returned differently, so they are
+/// left out and have to be faked with `will_execute_raw`.
+pub trait RegisterPair: private::RegisterPairWords {}
+
+impl<T: private::RegisterPairWords> RegisterPair for T {}
+
+pub(crate) mod private {
+    /// An 8-byte integer or pointer, returned whole in one integer register.
+    pub trait RegisterWord: Copy {}
+
+    impl RegisterWord for u64 {}
+    impl RegisterWord for i64 {}
+
+    #[cfg(target_pointer_width = "64")]
+    impl RegisterWord for usize {}
+    #[cfg(target_pointer_width = "64")]
+    impl RegisterWord for isize {}
+    #[cfg(target_pointer_width = "64")]
+    impl<T> RegisterWord for *const T {}
+    #[cfg(target_pointer_width = "64")]
+    impl<T> RegisterWord for *mut T {}
+
+    pub trait RegisterPairWords: Copy {
+        /// The two register values, in the order of the fields in memory.
+        fn words(self) -> [u64; 2];
+    }
+
+    impl<A: RegisterWord, B: RegisterWord> RegisterPairWords for (A, B) {
+        fn words(self) -> [u64; 2] {
+            // Both fields are 8 bytes, so the pair has no padding and the first register
+            // holds the field at offset 0.
+            unsafe { std::mem::transmute_copy(&self) }
+        }
+    }
+}
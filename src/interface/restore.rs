@@ -0,0 +1,87 @@
+use std::cell::Cell;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex, PoisonError};
+
+/// What happened to a fake while it was installed, handed to `WhenCalledBuilder::on_restore`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct CallRecord {
+    call_count: usize,
+}
+
+impl CallRecord {
+    /// Returns how many times the faked function was called.
+    pub fn call_count(&self) -> usize {
+        self.call_count
+    }
+}
+
+type RestoreCallback = Box<dyn FnOnce(&CallRecord)>;
+
+/// The data a patched function hands to `count_restore_call`, and the callback to run once
+/// its original code is restored.
+pub(crate) struct RestoreHook {
+    calls: AtomicUsize,
+    callback: Cell<Option<RestoreCallback>>,
+}
+
+impl RestoreHook {
+    pub(crate) fn new(callback: impl FnOnce(&CallRecord) + 'static) -> Self {
+        Self {
+            calls: AtomicUsize::new(0),
+            callback: Cell::new(Some(Box::new(callback))),
+        }
+    }
+
+    /// Runs the callback with the calls counted so far, at most once.
+    pub(crate) fn run(&self) {
+        // Avoid double panic
+        if std::thread::panicking() {
+            return;
+        }
+
+        if let Some(callback) = self.callback.take() {
+            callback(&CallRecord {
+                call_count: self.calls.load(Ordering::SeqCst),
+            });
+        }
+    }
+}
+
+/// Called from the JIT block of a function registered with `on_restore`.
+pub(crate) extern "C-unwind" fn count_restore_call(data: *const (), _registers: *const u64) {
+    let hook = unsafe { &*(data as *const RestoreHook) };
+    hook.calls.fetch_add(1, Ordering::SeqCst);
+}
+
+/// A function whose original code was written back, handed to the hook set with
+/// `InjectorPP::on_restore`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct RestoreInfo {
+    /// The address of the restored function.
+    pub function: usize,
+    /// The address of the JIT memory of the fake, now freed, or 0 if it had none.
+    pub jit_address: usize,
+    /// The size of the freed JIT memory, in bytes.
+    pub jit_size: usize,
+}
+
+type RestoreSink = Arc<dyn Fn(&RestoreInfo) + Send + Sync>;
+
+static RESTORE_SINK: Mutex<Option<RestoreSink>> = Mutex::new(None);
+
+pub(crate) fn set_restore_sink(sink: Option<RestoreSink>) {
+    *RESTORE_SINK.lock().unwrap_or_else(PoisonError::into_inner) = sink;
+}
+
+/// Hands `info` to the hook set with `InjectorPP::on_restore`, if any.
+pub(crate) fn notify_restore(info: RestoreInfo) {
+    let sink = RESTORE_SINK
+        .lock()
+        .unwrap_or_else(PoisonError::into_inner)
+        .clone();
+
+    if let Some(sink) = sink {
+        sink(&info);
+    }
+}
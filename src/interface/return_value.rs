@@ -0,0 +1,173 @@
+use std::num::{
+    NonZeroI16, NonZeroI32, NonZeroI64, NonZeroI8, NonZeroIsize, NonZeroU16, NonZeroU32,
+    NonZeroU64, NonZeroU8, NonZeroUsize,
+};
+use std::ptr::NonNull;
+
+use self::private::{Registers, ReturnRegisters};
+use crate::interface::register_pair::private::{RegisterPairWords, RegisterWord};
+
+/// A value `WhenCalledBuilder::will_return` can load into the return registers.
+///
+/// Implemented for the types every supported ABI returns as a single scalar: integers up to
+/// 64 bits, `bool`, `char`, `f64`, raw pointers and `'static` references. `Option`s of a
+/// reference, a `NonNull` or a `NonZero` integer are included as well, since `None` is the
+/// null pointer or zero and `Some` is the value itself. On 64-bit targets the pairs of
+/// `RegisterPair` and 128-bit integers are returned in two registers.
+///
+/// Other types, such as `f32`, arrays, structs, `Option<u32>` or `(u32, u32)`, are returned
+/// in ways that depend on their layout, so they do not implement `ReturnValue` and have to be
+/// faked with `will_execute_raw`.
+pub trait ReturnValue: ReturnRegisters {}
+
+impl<T: ReturnRegisters> ReturnValue for T {}
+
+pub(crate) mod private {
+    /// Where a `ReturnValue` goes.
+    pub enum Registers {
+        /// The first integer return register. On 32-bit targets the upper half goes to the
+        /// second one.
+        Integer(u64),
+        /// The bit pattern of an `f64`, in the first floating point return register.
+        Float(u64),
+        /// The first two integer return registers.
+        Pair(u64, u64),
+    }
+
+    pub trait ReturnRegisters: Copy {
+        fn registers(self) -> Registers;
+    }
+}
+
+macro_rules! impl_zero_extended {
+    ($($ty:ty),*) => {
+        $(
+            impl ReturnRegisters for $ty {
+                fn registers(self) -> Registers {
+                    Registers::Integer(self as u64)
+                }
+            }
+        )*
+    };
+}
+
+macro_rules! impl_sign_extended {
+    ($($ty:ty),*) => {
+        $(
+            impl ReturnRegisters for $ty {
+                fn registers(self) -> Registers {
+                    Registers::Integer(self as i64 as u64)
+                }
+            }
+        )*
+    };
+}
+
+macro_rules! impl_option_non_zero {
+    ($($ty:ty),*) => {
+        $(
+            impl ReturnRegisters for Option<$ty> {
+                fn registers(self) -> Registers {
+                    self.map_or(0, <$ty>::get).registers()
+                }
+            }
+        )*
+    };
+}
+
+impl_zero_extended!(bool, char, u8, u16, u64, usize);
+impl_sign_extended!(i8, i16, i32, i64, isize);
+impl_option_non_zero!(
+    NonZeroU8,
+    NonZeroU16,
+    NonZeroU32,
+    NonZeroU64,
+    NonZeroUsize,
+    NonZeroI8,
+    NonZeroI16,
+    NonZeroI32,
+    NonZeroI64,
+    NonZeroIsize
+);
+
+impl ReturnRegisters for u32 {
+    fn registers(self) -> Registers {
+        // RISC-V expects 32-bit values sign extended to the whole register, unsigned ones too.
+        if cfg!(target_arch = "riscv64") {
+            Registers::Integer(self as i32 as i64 as u64)
+        } else {
+            Registers::Integer(self as u64)
+        }
+    }
+}
+
+#[cfg(target_pointer_width = "64")]
+impl ReturnRegisters for u128 {
+    fn registers(self) -> Registers {
+        Registers::Pair(self as u64, (self >> 64) as u64)
+    }
+}
+
+#[cfg(target_pointer_width = "64")]
+impl ReturnRegisters for i128 {
+    fn registers(self) -> Registers {
+        (self as u128).registers()
+    }
+}
+
+impl ReturnRegisters for f64 {
+    fn registers(self) -> Registers {
+        Registers::Float(self.to_bits())
+    }
+}
+
+impl<T: ?Sized> ReturnRegisters for *const T {
+    fn registers(self) -> Registers {
+        if std::mem::size_of::<*const T>() == std::mem::size_of::<usize>() {
+            return Registers::Integer(self as *const () as usize as u64);
+        }
+
+        // A pointer to a slice, a `str` or a trait object is the data pointer followed by its
+        // length or vtable, returned like a pair of its two words.
+        let [data, metadata] =
+            unsafe { std::ptr::read(&self as *const *const T as *const [usize; 2]) };
+        if cfg!(target_pointer_width = "64") {
+            Registers::Pair(data as u64, metadata as u64)
+        } else {
+            Registers::Integer(data as u64 | (metadata as u64) << 32)
+        }
+    }
+}
+
+impl<T: ?Sized> ReturnRegisters for *mut T {
+    fn registers(self) -> Registers {
+        (self as *const T).registers()
+    }
+}
+
+impl<T: ?Sized> ReturnRegisters for &'static T {
+    fn registers(self) -> Registers {
+        (self as *const T).registers()
+    }
+}
+
+impl<T> ReturnRegisters for Option<&'static T> {
+    fn registers(self) -> Registers {
+        self.map_or(std::ptr::null(), |value| value as *const T)
+            .registers()
+    }
+}
+
+impl<T> ReturnRegisters for Option<NonNull<T>> {
+    fn registers(self) -> Registers {
+        self.map_or(std::ptr::null_mut(), NonNull::as_ptr)
+            .registers()
+    }
+}
+
+impl<A: RegisterWord, B: RegisterWord> ReturnRegisters for (A, B) {
+    fn registers(self) -> Registers {
+        let [first, second] = self.words();
+        Registers::Pair(first, second)
+    }
+}
@@ -0,0 +1,151 @@
+use crate::interface::failure::{fail, Failure};
+use std::sync::{Arc, Mutex, PoisonError};
+
+/// A shared log of calls to faked functions, in the order they happened.
+///
+/// Register a fake with `WhenCalledBuilder::in_sequence` to append its label to the log each
+/// time the faked function is called. Clones share the same log.
+#[derive(Clone, Default)]
+pub struct Sequence {
+    calls: Arc<Mutex<Vec<&'static str>>>,
+}
+
+impl Sequence {
+    /// Creates an empty sequence.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Returns the labels of the recorded calls, oldest first.
+    pub fn calls(&self) -> Vec<&'static str> {
+        self.calls
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .clone()
+    }
+
+    fn record(&self, label: &'static str) {
+        self.calls
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .push(label);
+    }
+}
+
+/// The data a patched function hands to `record_sequence_call`.
+pub(crate) struct SequenceEntry {
+    pub(crate) sequence: Sequence,
+    pub(crate) label: &'static str,
+}
+
+/// Called from the JIT block of a function registered with `in_sequence`.
+pub(crate) extern "C-unwind" fn record_sequence_call(data: *const (), _registers: *const u64) {
+    let entry = unsafe { &*(data as *const SequenceEntry) };
+    entry.sequence.record(entry.label);
+}
+
+/// The data a patched function hands to `record_call_order`.
+pub(crate) struct CallOrderEntry {
+    pub(crate) log: Arc<Mutex<Vec<usize>>>,
+    pub(crate) index: usize,
+}
+
+/// Called from the JIT block of a function registered with `record_call_order`.
+pub(crate) extern "C-unwind" fn record_call_order(data: *const (), _registers: *const u64) {
+    let entry = unsafe { &*(data as *const CallOrderEntry) };
+    entry
+        .log
+        .lock()
+        .unwrap_or_else(PoisonError::into_inner)
+        .push(entry.index);
+}
+
+/// Returns where the first of `expected` that is not called after the previous ones is,
+/// counted from 1, or `None` if `actual` calls them all in order, possibly among other calls.
+pub(crate) fn first_out_of_order(expected: &[usize], actual: &[usize]) -> Option<usize> {
+    let mut calls = actual.iter();
+
+    expected
+        .iter()
+        .position(|index| !calls.any(|call| call == index))
+        .map(|position| position + 1)
+}
+
+/// An ordered list of expected calls, checked against a `Sequence` on drop.
+///
+/// Each step names a label and how many consecutive times it must be called. On drop the
+/// recorded calls must match the steps exactly, otherwise a panic reports both the expected
+/// and the actual order.
+pub struct Expectations {
+    sequence: Sequence,
+    steps: Vec<(&'static str, usize)>,
+}
+
+impl Expectations {
+    /// Creates an empty list of expectations for the calls recorded in `sequence`.
+    pub fn new(sequence: &Sequence) -> Self {
+        Self {
+            sequence: sequence.clone(),
+            steps: Vec::new(),
+        }
+    }
+
+    /// Expects `label` to be called `times` times in a row after the previous step.
+    pub fn then(mut self, label: &'static str, times: usize) -> Self {
+        self.steps.push((label, times));
+        self
+    }
+}
+
+impl Drop for Expectations {
+    fn drop(&mut self) {
+        let expected = group_runs(self.steps.iter().copied());
+        let actual = group_runs(self.sequence.calls().into_iter().map(|label| (label, 1)));
+
+        if expected == actual {
+            return;
+        }
+
+        // Avoid double panic
+        if std::thread::panicking() {
+            return;
+        }
+
+        let position = expected
+            .iter()
+            .zip(&actual)
+            .position(|(expected, actual)| expected != actual)
+            .unwrap_or(expected.len().min(actual.len()));
+
+        fail(Failure::Sequence {
+            step: position + 1,
+            expected: describe_runs(&expected),
+            actual: describe_runs(&actual),
+        });
+    }
+}
+
+/// Merges consecutive steps with the same label and drops steps expected zero times.
+fn group_runs(steps: impl Iterator<Item = (&'static str, usize)>) -> Vec<(&'static str, usize)> {
+    let mut runs: Vec<(&'static str, usize)> = Vec::new();
+
+    for (label, times) in steps.filter(|&(_, times)| times > 0) {
+        match runs.last_mut() {
+            Some((last, count)) if *last == label => *count += times,
+            _ => runs.push((label, times)),
+        }
+    }
+
+    runs
+}
+
+fn describe_runs(runs: &[(&'static str, usize)]) -> String {
+    if runs.is_empty() {
+        return "no calls".to_string();
+    }
+
+    runs.iter()
+        .map(|(label, times)| format!("{label} x{times}"))
+        .collect::<Vec<_>>()
+        .join(", ")
+}
@@ -0,0 +1,92 @@
+use std::collections::HashSet;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex, PoisonError};
+use std::thread::ThreadId;
+
+/// Observes calls to a function that keeps its original behavior, see
+/// `WhenCalledBuilder::spy`.
+///
+/// Clones observe the same function.
+#[derive(Clone, Default)]
+pub struct Spy {
+    record: Arc<SpyRecord>,
+}
+
+#[derive(Default)]
+struct SpyRecord {
+    calls: AtomicUsize,
+    capture_return: AtomicBool,
+    returns: Mutex<Vec<(u64, u64)>>,
+    capture_thread_ids: AtomicBool,
+    threads: Mutex<HashSet<ThreadId>>,
+}
+
+impl Spy {
+    /// Starts recording the return registers of the original function on each call.
+    pub fn capture_return(self) -> Self {
+        self.record.capture_return.store(true, Ordering::SeqCst);
+        self
+    }
+
+    /// Starts recording which threads call the function.
+    pub fn capture_thread_ids(self) -> Self {
+        self.record.capture_thread_ids.store(true, Ordering::SeqCst);
+        self
+    }
+
+    /// Returns how many distinct threads called the function since `capture_thread_ids`.
+    pub fn distinct_threads(&self) -> usize {
+        self.record
+            .threads
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .len()
+    }
+
+    /// Returns how many times the function was called.
+    pub fn call_count(&self) -> usize {
+        self.record.calls.load(Ordering::SeqCst)
+    }
+
+    /// Returns the first return register of each call recorded since `capture_return`,
+    /// `rax` on x86_64 and `x0` on AArch64.
+    ///
+    /// Values narrower than 64 bits only occupy the low bits of the register.
+    pub fn returns(&self) -> Vec<u64> {
+        self.return_pairs()
+            .into_iter()
+            .map(|(first, _)| first)
+            .collect()
+    }
+
+    /// Returns both return registers of each call recorded since `capture_return`, `rax`
+    /// and `rdx` on x86_64 and `x0` and `x1` on AArch64, for 16-byte return values.
+    pub fn return_pairs(&self) -> Vec<(u64, u64)> {
+        self.record
+            .returns
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .clone()
+    }
+
+    /// Counts a call that returned `registers`.
+    pub(crate) fn record(&self, registers: (u64, u64)) {
+        self.record.calls.fetch_add(1, Ordering::SeqCst);
+
+        if self.record.capture_return.load(Ordering::SeqCst) {
+            self.record
+                .returns
+                .lock()
+                .unwrap_or_else(PoisonError::into_inner)
+                .push(registers);
+        }
+
+        if self.record.capture_thread_ids.load(Ordering::SeqCst) {
+            self.record
+                .threads
+                .lock()
+                .unwrap_or_else(PoisonError::into_inner)
+                .insert(std::thread::current().id());
+        }
+    }
+}
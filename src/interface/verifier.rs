@@ -13,6 +13,29 @@ pub enum CallCountVerifier {
     Dummy,
 }
 
+/// Called from the JIT block of a function registered with `WhenCalledBuilder::count_calls`.
+pub(crate) extern "C-unwind" fn count_call(data: *const (), _registers: *const u64) {
+    let calls = unsafe { &*(data as *const AtomicUsize) };
+    calls.fetch_add(1, Ordering::SeqCst);
+}
+
+impl CallCountVerifier {
+    /// Returns the expected and actual call counts if they differ. Either way the verifier no
+    /// longer checks anything on drop.
+    pub(crate) fn take_mismatch(&mut self) -> Option<(usize, usize)> {
+        let mismatch = match self {
+            CallCountVerifier::WithCount { counter, expected } => {
+                let actual = counter.load(Ordering::SeqCst);
+                (actual != *expected).then_some((*expected, actual))
+            }
+            CallCountVerifier::Dummy => None,
+        };
+
+        std::mem::forget(std::mem::replace(self, CallCountVerifier::Dummy));
+        mismatch
+    }
+}
+
 impl Drop for CallCountVerifier {
     fn drop(&mut self) {
         if let CallCountVerifier::WithCount { counter, expected } = self {
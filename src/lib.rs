@@ -63,6 +63,8 @@
 //!
 //! - OS: Linux and Windows
 //! - Arch: arm64 and amd64
+//! - riscv64 on Linux supports `will_execute_raw` and the `will_return_*` methods returning fixed values
+//! - x86 (i686) supports `will_execute_raw`, `will_return_boolean` and the `will_return_*` methods returning integers
 //!
 //! # Usage
 //!
@@ -83,6 +85,9 @@
 //! use injectorpp::interface::injector::*;
 //! ```
 //!
+//! Or import `injectorpp::prelude::*`, which also brings the macros into scope so they can be
+//! used without the `injectorpp::` prefix.
+//!
 //! Below are multiple ways to config the function behavior.
 //!
 //! ## will_return_boolean
@@ -555,3 +560,5 @@
 //! ```
 mod injector_core;
 pub mod interface;
+pub mod prelude;
+pub mod time;
@@ -0,0 +1,36 @@
+//! Everything needed to write tests with injectorpp, in a single import.
+//!
+//! ```rust
+//! use injectorpp::prelude::*;
+//!
+//! fn is_online() -> bool {
+//!     false
+//! }
+//!
+//! let mut injector = InjectorPP::new();
+//! injector
+//!     .when_called(func!(fn (is_online)() -> bool))
+//!     .will_return_boolean(true);
+//!
+//! assert!(is_online());
+//! ```
+
+pub use crate::interface::injector::{
+    AnyArg, ArgMatcher, ArgsMatcher, BetweenCallsBuilder, BranchKind, CallCountVerifier,
+    CallRecord, Capture, CaptureArg, Checkpoint, Expectations, Failure, FuncAddress, FuncPtr,
+    InjectError, InjectorOptions, InjectorPP, IntoCapture, IntoFake, IntoHook, IntoMap,
+    IntoPredicate, JitAllocStrategy, MockHandle, PatchDebug, PatchStats, Preventer, RegisterPair,
+    RestoreInfo, ReturnValue, ScopedMock, Sequence, Spy, WhenCalledBuilder, WhenCalledBuilderAsync,
+    WhenCalledCurrentThreadBuilder, WhenCalledWithBuilder,
+};
+pub use crate::{
+    async_func, async_func_unchecked, async_return, async_return_unchecked, closure,
+    closure_capturing, closure_unchecked, fake, func, func_of, func_unchecked, trait_method,
+    variadic_func,
+};
+
+// Used by the expansion of `async_func!`.
+#[doc(hidden)]
+pub use crate::interface::injector::{
+    __assert_future_output, __func_ptr_of, __future_output_signature,
+};
@@ -0,0 +1,116 @@
+//! A fake clock for `std::time`.
+//!
+//! `FakeClock::install` fakes `Instant::now` and `SystemTime::now` so that they return a
+//! frozen time, which only moves when the test calls `FakeClock::advance`. The `elapsed`
+//! methods of both types are faked to measure against that time too.
+//!
+//! ```rust
+//! use injectorpp::interface::injector::*;
+//! use injectorpp::time::FakeClock;
+//! use std::time::{Duration, Instant};
+//!
+//! let mut injector = InjectorPP::new();
+//! let clock = FakeClock::install(&mut injector);
+//!
+//! let start = Instant::now();
+//! assert_eq!(start.elapsed(), Duration::ZERO);
+//!
+//! clock.advance(Duration::from_secs(30));
+//! assert_eq!(start.elapsed(), Duration::from_secs(30));
+//! ```
+//!
+//! # Caveats
+//!
+//! The fakes replace the `std` functions rather than the system calls behind them. On Linux
+//! `clock_gettime` is usually served by the vDSO, which is mapped by the kernel rather than
+//! loaded as a library, so it has no symbol `when_called_symbol` could resolve. Code reading
+//! the clock through `libc` or a crate calling the OS directly keeps seeing the real time.
+//!
+//! Other `std` functions may read the clock without calling the faked functions, as `std`
+//! is free to inline `now` into its own code.
+//!
+//! Everything in the process calling `Instant::now` sees the frozen time while the injector
+//! is alive, including code that waits for a deadline computed from it, such as
+//! `Receiver::recv_timeout`. Such code keeps waiting until the clock is advanced past the
+//! deadline.
+
+use crate::interface::injector::{FuncPtr, InjectorPP};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant, SystemTime, SystemTimeError};
+
+/// Controls the time `Instant` and `SystemTime` report, see the module documentation.
+///
+/// The fakes are installed in the injector and last as long as it does. Clones control the
+/// same clock.
+#[derive(Clone, Debug)]
+pub struct FakeClock {
+    /// Nanoseconds the clock was advanced by since it was installed.
+    elapsed: Arc<AtomicU64>,
+}
+
+impl FakeClock {
+    /// Freezes `Instant` and `SystemTime` at the current time.
+    pub fn install(injector: &mut InjectorPP) -> Self {
+        let clock = Self {
+            elapsed: Arc::new(AtomicU64::new(0)),
+        };
+
+        // Read before the fakes are installed, as they return these plus the elapsed time.
+        let instant = Instant::now();
+        let system_time = SystemTime::now();
+
+        let elapsed = clock.elapsed.clone();
+        injector
+            .when_called(crate::func!(fn (Instant::now)() -> Instant))
+            .will_execute(move || instant + Duration::from_nanos(elapsed.load(Ordering::SeqCst)));
+
+        let elapsed = clock.elapsed.clone();
+        injector
+            .when_called(crate::func!(fn (SystemTime::now)() -> SystemTime))
+            .will_execute(move || {
+                system_time + Duration::from_nanos(elapsed.load(Ordering::SeqCst))
+            });
+
+        // `std` inlines `now` into these, so they do not go through the fakes above.
+        let elapsed = clock.elapsed.clone();
+        injector
+            .when_called(crate::func!(fn (Instant::elapsed)(&Instant) -> Duration))
+            .will_execute(move |earlier: &Instant| {
+                (instant + Duration::from_nanos(elapsed.load(Ordering::SeqCst)))
+                    .saturating_duration_since(*earlier)
+            });
+
+        let elapsed = clock.elapsed.clone();
+        injector
+            .when_called(crate::func!(
+                fn (SystemTime::elapsed)(&SystemTime) -> Result<Duration, SystemTimeError>
+            ))
+            .will_execute(move |earlier: &SystemTime| {
+                (system_time + Duration::from_nanos(elapsed.load(Ordering::SeqCst)))
+                    .duration_since(*earlier)
+            });
+
+        clock
+    }
+
+    /// Moves the clock forward by `duration`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the clock would be advanced by more than `u64::MAX` nanoseconds in total,
+    /// about 584 years.
+    pub fn advance(&self, duration: Duration) {
+        let nanos = u64::try_from(duration.as_nanos()).ok();
+        self.elapsed
+            .fetch_update(Ordering::SeqCst, Ordering::SeqCst, |elapsed| {
+                nanos.and_then(|nanos| elapsed.checked_add(nanos))
+            })
+            .unwrap_or_else(|_| panic!("Cannot advance the fake clock by {duration:?}"));
+    }
+
+    /// Returns how far the clock was advanced since it was installed.
+    pub fn elapsed(&self) -> Duration {
+        Duration::from_nanos(self.elapsed.load(Ordering::SeqCst))
+    }
+}
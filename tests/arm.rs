@@ -139,3 +139,91 @@ fn test_arm_a32_32_bit_aligned_patch() {
         assert_eq!(result, 99, "A32 function should return 99");
     }
 }
+
+#[inline(never)]
+fn is_ready() -> bool {
+    std::hint::black_box(false)
+}
+
+#[inline(never)]
+fn answer() -> u32 {
+    std::hint::black_box(42)
+}
+
+#[inline(never)]
+fn fake_answer() -> u32 {
+    std::hint::black_box(7)
+}
+
+#[test]
+fn test_arm_will_return_boolean_should_write_return_over_function() {
+    let pointer = is_ready as fn() -> bool as usize;
+    let thumb = pointer & 1 != 0;
+
+    {
+        let mut injector = InjectorPP::new();
+        let handle = injector
+            .when_called(injectorpp::func!(fn (is_ready)() -> bool))
+            .will_return_boolean(true);
+
+        assert!(is_ready());
+
+        let debug = injector.debug_info(handle);
+        assert_eq!(debug.address, pointer & !1);
+        assert_eq!(debug.branch_kind, BranchKind::Inline);
+        assert_eq!(debug.patch_bytes.len(), if thumb { 4 } else { 8 });
+        assert_eq!(debug.jit_address, 0);
+    }
+
+    assert!(!is_ready());
+}
+
+#[test]
+fn test_arm_will_execute_raw_when_fake_in_range_should_write_single_branch() {
+    {
+        let mut injector = InjectorPP::new();
+        let handle = injector
+            .when_called(injectorpp::func!(fn (answer)() -> u32))
+            .will_execute_raw(injectorpp::func!(fn (fake_answer)() -> u32));
+
+        assert_eq!(answer(), 7);
+
+        let debug = injector.debug_info(handle);
+        assert_eq!(debug.branch_kind, BranchKind::Near);
+        assert_eq!(debug.patch_bytes.len(), 4);
+    }
+
+    assert_eq!(answer(), 42);
+}
+
+#[test]
+fn test_arm_t32_will_return_boolean_should_write_movs_and_bx() {
+    unsafe {
+        // Word aligned T32 code, like in test_arm_t32_32_bit_aligned_patch.
+        let allocated_memory: [u8; T32_ALIGNED_32_FUNCTION_OPCODES.len() * 2 + 4] =
+            [0; T32_ALIGNED_32_FUNCTION_OPCODES.len() * 2 + 4];
+        let allocated_memory_ptr = allocated_memory.as_ptr() as *mut u8;
+        let aligned_memory = ((allocated_memory_ptr as usize)
+            + (4 - (allocated_memory_ptr as usize % 4)) % 4)
+            as *mut u8;
+
+        for (i, &opcode) in T32_ALIGNED_32_FUNCTION_OPCODES.iter().enumerate() {
+            for (j, b) in opcode.to_le_bytes().iter().enumerate() {
+                aligned_memory.add(i * 2 + j).write(*b);
+            }
+        }
+
+        let function = aligned_memory.add(1) as *const ();
+
+        let mut injector = InjectorPP::new();
+        let handle = injector
+            .when_called(FuncPtr::new(function, "fn() -> bool"))
+            .will_return_boolean(true);
+
+        assert!(std::mem::transmute::<*const (), fn() -> bool>(function)());
+        assert_eq!(
+            injector.debug_info(handle).patch_bytes,
+            [0x01, 0x20, 0x70, 0x47]
+        );
+    }
+}
@@ -1,4 +1,7 @@
 use injectorpp::interface::injector::*;
+use std::rc::Rc;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::{Duration, Instant};
 
 async fn simple_async_func_u32_add_one(x: u32) -> u32 {
     x + 1
@@ -143,3 +146,340 @@ async fn test_complex_struct_async_func_with_param_should_success() {
     let result = real_client.post("test payload").await;
     assert_eq!(result, "POST test payload to https://test.com".to_string());
 }
+
+async fn local_greeting(name: Rc<str>) -> Rc<String> {
+    let greeting = Rc::new(format!("hello {name}"));
+
+    // Holding an Rc across an await point makes the future !Send.
+    tokio::task::yield_now().await;
+
+    greeting
+}
+
+#[tokio::test(flavor = "current_thread")]
+async fn test_will_return_async_when_future_is_not_send_should_success_in_local_set() {
+    let local = tokio::task::LocalSet::new();
+
+    local
+        .run_until(async {
+            let mut injector = InjectorPP::new();
+
+            injector
+                .when_called_async(injectorpp::async_func!(
+                    local_greeting(Rc::from("")),
+                    Rc<String>
+                ))
+                .will_return_async(injectorpp::async_return!(
+                    Rc::new("faked".to_string()),
+                    Rc<String>
+                ));
+
+            let greeting = tokio::task::spawn_local(local_greeting(Rc::from("world")))
+                .await
+                .unwrap();
+
+            assert_eq!(*greeting, "faked");
+        })
+        .await;
+}
+
+#[derive(Clone, Debug, PartialEq)]
+struct UserProfile {
+    name: String,
+    roles: Vec<String>,
+}
+
+async fn fetch_profile(id: u32) -> UserProfile {
+    UserProfile {
+        name: format!("user {id}"),
+        roles: Vec::new(),
+    }
+}
+
+#[tokio::test]
+async fn test_will_return_async_cloned_when_awaited_twice_should_return_equal_clones() {
+    let profile = UserProfile {
+        name: "faked".to_string(),
+        roles: vec!["admin".to_string(), "auditor".to_string()],
+    };
+
+    let mut injector = InjectorPP::new();
+    injector
+        .when_called_async(injectorpp::async_func!(fetch_profile(0), UserProfile))
+        .will_return_async_cloned(profile.clone());
+
+    let first = fetch_profile(1).await;
+    let second = fetch_profile(2).await;
+
+    assert_eq!(first, profile);
+    assert_eq!(second, profile);
+    assert_ne!(first.roles.as_ptr(), second.roles.as_ptr());
+}
+
+#[derive(Debug, PartialEq)]
+enum FetchError {
+    Timeout,
+}
+
+async fn fetch_body(url: &str) -> Result<String, FetchError> {
+    Ok(format!("body of {url}"))
+}
+
+async fn fetch_status(url: &str) -> Result<u16, FetchError> {
+    Ok(url.len() as u16)
+}
+
+async fn find_header(name: &str) -> Option<String> {
+    Some(name.to_uppercase())
+}
+
+#[tokio::test]
+async fn test_will_return_async_when_result_is_ok_or_err_should_return_exact_value() {
+    let mut injector = InjectorPP::new();
+    injector
+        .when_called_async(injectorpp::async_func!(
+            fetch_body(""),
+            Result<String, FetchError>
+        ))
+        .will_return_async(injectorpp::async_return!(
+            Ok("fake body".to_string()),
+            Result<String, FetchError>
+        ));
+    injector
+        .when_called_async(injectorpp::async_func!(
+            fetch_status(""),
+            Result<u16, FetchError>
+        ))
+        .will_return_async(injectorpp::async_return!(
+            Err(FetchError::Timeout),
+            Result<u16, FetchError>
+        ));
+
+    assert_eq!(fetch_body("a").await, Ok("fake body".to_string()));
+    assert_eq!(fetch_status("a").await, Err(FetchError::Timeout));
+
+    // find_header should not be affected
+    assert_eq!(find_header("host").await, Some("HOST".to_string()));
+}
+
+#[tokio::test]
+async fn test_will_return_async_when_option_is_some_or_none_should_return_exact_value() {
+    {
+        let mut injector = InjectorPP::new();
+        injector
+            .when_called_async(injectorpp::async_func!(find_header(""), Option<String>))
+            .will_return_async(injectorpp::async_return!(None, Option<String>));
+
+        assert_eq!(find_header("host").await, None);
+    }
+
+    {
+        let mut injector = InjectorPP::new();
+        injector
+            .when_called_async(injectorpp::async_func!(find_header(""), Option<String>))
+            .will_return_async(injectorpp::async_return!(
+                Some("fake".to_string()),
+                Option<String>
+            ));
+
+        assert_eq!(find_header("host").await, Some("fake".to_string()));
+    }
+
+    assert_eq!(find_header("host").await, Some("HOST".to_string()));
+    assert_eq!(fetch_body("a").await, Ok("body of a".to_string()));
+}
+
+async fn compute_quote(amount: u32) -> u32 {
+    amount
+}
+
+async fn load_greeting(name: &str) -> String {
+    format!("hello {name}")
+}
+
+#[tokio::test]
+async fn test_will_execute_async_when_factory_sleeps_should_return_computed_value() {
+    let mut injector = InjectorPP::new();
+    injector
+        .when_called_async(injectorpp::async_func!(compute_quote(0), u32))
+        .will_execute_async(|| async {
+            tokio::time::sleep(Duration::from_millis(10)).await;
+            6 * 7u32
+        });
+
+    let start = Instant::now();
+    assert_eq!(compute_quote(1).await, 42);
+    assert!(start.elapsed() >= Duration::from_millis(10));
+}
+
+#[tokio::test]
+async fn test_will_execute_async_when_awaited_concurrently_should_make_a_future_per_call() {
+    static CALLS: AtomicUsize = AtomicUsize::new(0);
+
+    {
+        let mut injector = InjectorPP::new();
+        injector
+            .when_called_async(injectorpp::async_func!(load_greeting(""), String))
+            .will_execute_async(|| async {
+                let call = CALLS.fetch_add(1, Ordering::SeqCst) + 1;
+                tokio::time::sleep(Duration::from_millis(5 * call as u64)).await;
+                format!("fake {call}")
+            });
+
+        let (first, second) = tokio::join!(load_greeting("a"), load_greeting("b"));
+        let mut results = [first, second];
+        results.sort();
+
+        assert_eq!(results, ["fake 1".to_string(), "fake 2".to_string()]);
+        assert_eq!(CALLS.load(Ordering::SeqCst), 2);
+    }
+
+    assert_eq!(load_greeting("a").await, "hello a");
+}
+
+#[tokio::test]
+#[should_panic(expected = "Signature mismatch")]
+async fn test_will_execute_async_when_output_type_differs_should_panic() {
+    let mut injector = InjectorPP::new();
+    injector
+        .when_called_async(injectorpp::async_func!(compute_quote(0), u32))
+        .will_execute_async(|| async { 42u64 });
+}
+
+#[derive(Debug, PartialEq)]
+struct ServiceConfig {
+    retries: u32,
+}
+
+struct Service {
+    config: ServiceConfig,
+    name: String,
+}
+
+impl Service {
+    async fn config(&self) -> &ServiceConfig {
+        &self.config
+    }
+
+    async fn name(&self) -> &str {
+        &self.name
+    }
+}
+
+static STATIC_CONFIG: ServiceConfig = ServiceConfig { retries: 9 };
+
+fn service() -> Service {
+    Service {
+        config: ServiceConfig { retries: 1 },
+        name: "real".to_string(),
+    }
+}
+
+#[tokio::test]
+async fn test_will_return_async_ref_when_output_borrows_receiver_should_return_fake_reference() {
+    let service = service();
+
+    {
+        let mut injector = InjectorPP::new();
+        injector
+            .when_called_async(injectorpp::async_func!(service.config(), &ServiceConfig))
+            .will_return_async_ref(Box::new(ServiceConfig { retries: 5 }));
+
+        assert_eq!(service.config().await, &ServiceConfig { retries: 5 });
+        assert_eq!(service.config().await.retries, 5);
+    }
+
+    assert_eq!(service.config().await, &ServiceConfig { retries: 1 });
+}
+
+#[tokio::test]
+async fn test_will_return_async_ref_when_output_is_str_should_return_fake_str() {
+    let service = service();
+
+    let mut injector = InjectorPP::new();
+    injector
+        .when_called_async(injectorpp::async_func!(service.name(), &str))
+        .will_return_async_ref(format!("fake-{}", 2).into_boxed_str());
+
+    assert_eq!(service.name().await, "fake-2");
+}
+
+#[tokio::test]
+async fn test_will_return_async_when_output_borrows_receiver_should_accept_static_reference() {
+    let service = service();
+
+    let mut injector = InjectorPP::new();
+    injector
+        .when_called_async(injectorpp::async_func!(service.config(), &ServiceConfig))
+        .will_return_async(injectorpp::async_return!(
+            &STATIC_CONFIG,
+            &'static ServiceConfig
+        ));
+
+    assert_eq!(service.config().await, &STATIC_CONFIG);
+}
+
+#[tokio::test]
+async fn test_will_execute_async_when_output_borrows_receiver_should_accept_static_reference() {
+    let service = service();
+
+    let mut injector = InjectorPP::new();
+    injector
+        .when_called_async(injectorpp::async_func!(service.config(), &ServiceConfig))
+        .will_execute_async(|| async {
+            tokio::task::yield_now().await;
+            &STATIC_CONFIG
+        });
+
+    assert_eq!(service.config().await.retries, 9);
+}
+
+#[derive(Default)]
+struct Counter {
+    total: u32,
+}
+
+impl Counter {
+    async fn add(&mut self, value: u32) -> u32 {
+        self.total += value;
+        self.total
+    }
+}
+
+#[tokio::test]
+async fn test_when_called_async_when_method_takes_mut_self_should_fake_every_instance() {
+    let mut counter = Counter { total: 1 };
+
+    {
+        // The future is never polled, so this instance is only borrowed to name the method.
+        let mut temp_counter = Counter::default();
+
+        let mut injector = InjectorPP::new();
+        injector
+            .when_called_async(injectorpp::async_func!(temp_counter.add(0), u32))
+            .will_return_async(injectorpp::async_return!(100, u32));
+
+        assert_eq!(counter.add(5).await, 100);
+        assert_eq!(counter.total, 1);
+        assert_eq!(temp_counter.total, 0);
+    }
+
+    assert_eq!(counter.add(5).await, 6);
+    assert_eq!(counter.total, 6);
+}
+
+#[tokio::test]
+async fn test_when_called_async_when_mut_self_receiver_is_temporary_should_fake_method() {
+    let mut counter = Counter { total: 1 };
+
+    let mut injector = InjectorPP::new();
+    injector
+        .when_called_async(injectorpp::async_func!(Counter::default().add(0), u32))
+        .will_execute_async(|| async {
+            tokio::task::yield_now().await;
+            42u32
+        });
+
+    assert_eq!(counter.add(5).await, 42);
+    assert_eq!(counter.total, 1);
+}
@@ -0,0 +1,106 @@
+use injectorpp::interface::injector::*;
+
+#[inline(never)]
+fn is_online() -> bool {
+    std::hint::black_box(false)
+}
+
+#[inline(never)]
+fn retries() -> u64 {
+    std::hint::black_box(3)
+}
+
+#[inline(never)]
+fn region() -> String {
+    std::hint::black_box("eu-west").to_string()
+}
+
+#[inline(never)]
+fn scale(n: u32) -> u32 {
+    std::hint::black_box(n) * 2
+}
+
+fn install_fakes(injector: &mut InjectorPP) -> Vec<MockHandle> {
+    vec![
+        injector
+            .when_called(injectorpp::func!(fn (is_online)() -> bool))
+            .will_return_boolean(true),
+        injector
+            .when_called(injectorpp::func!(fn (retries)() -> u64))
+            .will_return_u64(0),
+        injector
+            .when_called(injectorpp::func!(fn (region)() -> String))
+            .will_execute(|| "fake".to_string()),
+        injector
+            .when_called(injectorpp::func!(fn (scale)(u32) -> u32))
+            .will_execute(|n: u32| n),
+    ]
+}
+
+fn assert_faked() {
+    assert!(is_online());
+    assert_eq!(retries(), 0);
+    assert_eq!(region(), "fake");
+    assert_eq!(scale(5), 5);
+}
+
+fn assert_original() {
+    assert!(!is_online());
+    assert_eq!(retries(), 3);
+    assert_eq!(region(), "eu-west");
+    assert_eq!(scale(5), 10);
+}
+
+#[test]
+fn test_batch_when_installing_fakes_should_apply_them_after_body_returns() {
+    {
+        let mut injector = InjectorPP::new();
+        let handles = injector.batch(install_fakes);
+
+        assert_eq!(handles.len(), 4);
+        assert_faked();
+    }
+
+    assert_original();
+}
+
+#[test]
+fn test_batch_when_compared_to_individual_installs_should_flush_less() {
+    let individual = {
+        let mut injector = InjectorPP::new();
+        let before = InjectorPP::patch_stats();
+        install_fakes(&mut injector);
+        InjectorPP::patch_stats() - before
+    };
+
+    let mut injector = InjectorPP::new();
+    let before = InjectorPP::patch_stats();
+    injector.batch(install_fakes);
+    let batched = InjectorPP::patch_stats() - before;
+
+    assert_faked();
+    assert!(batched.cache_flushes < individual.cache_flushes);
+    assert!(batched.protection_changes <= individual.protection_changes);
+}
+
+#[test]
+fn test_rollback_when_restoring_fakes_should_flush_less_than_restoring_each() {
+    let mut injector = InjectorPP::new();
+    let checkpoint = injector.checkpoint();
+
+    let handles = install_fakes(&mut injector);
+    let before = InjectorPP::patch_stats();
+    for handle in handles {
+        injector.restore(handle);
+    }
+    let individual = InjectorPP::patch_stats() - before;
+    assert_original();
+
+    install_fakes(&mut injector);
+    let before = InjectorPP::patch_stats();
+    injector.rollback(checkpoint);
+    let rolled_back = InjectorPP::patch_stats() - before;
+
+    assert_original();
+    assert!(rolled_back.cache_flushes < individual.cache_flushes);
+}
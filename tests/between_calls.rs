@@ -0,0 +1,59 @@
+use injectorpp::interface::injector::*;
+
+#[inline(never)]
+fn read_sensor(id: u32) -> Result<u32, String> {
+    Ok(std::hint::black_box(id) * 10)
+}
+
+#[inline(never)]
+fn next_delay(attempt: u32) -> u32 {
+    std::hint::black_box(attempt) + 1
+}
+
+#[test]
+fn test_between_calls_when_calls_two_to_four_should_fake_only_those() {
+    let mut injector = InjectorPP::new();
+    injector
+        .when_called(injectorpp::func!(fn (read_sensor)(u32) -> Result<u32, String>))
+        .between_calls(2, 4)
+        .will_execute(|id: u32| -> Result<u32, String> { Err(format!("sensor {id} busy")) });
+
+    let results: Vec<_> = (1..=6).map(read_sensor).collect();
+
+    assert_eq!(
+        results,
+        vec![
+            Ok(10),
+            Err("sensor 2 busy".to_string()),
+            Err("sensor 3 busy".to_string()),
+            Err("sensor 4 busy".to_string()),
+            Ok(50),
+            Ok(60),
+        ]
+    );
+}
+
+#[test]
+fn test_between_calls_when_injector_dropped_should_restore_original() {
+    {
+        let mut injector = InjectorPP::new();
+        injector
+            .when_called(injectorpp::func!(fn (next_delay)(u32) -> u32))
+            .between_calls(1, 1)
+            .will_execute(|_: u32| 0u32);
+
+        assert_eq!(next_delay(1), 0);
+        assert_eq!(next_delay(1), 2);
+    }
+
+    assert_eq!(next_delay(1), 2);
+}
+
+#[test]
+#[should_panic(expected = "between_calls requires 1 <= start <= end but got 3..=2")]
+fn test_between_calls_when_range_empty_should_panic() {
+    let mut injector = InjectorPP::new();
+    injector
+        .when_called(injectorpp::func!(fn (next_delay)(u32) -> u32))
+        .between_calls(3, 2);
+}
@@ -0,0 +1,126 @@
+use injectorpp::interface::injector::*;
+use std::os::raw::{c_int, c_void};
+use std::sync::atomic::{AtomicI32, Ordering};
+
+/// A callback table as a C library would keep it, holding C ABI function pointers.
+struct Dispatcher {
+    handlers: Vec<extern "C" fn(c_int) -> c_int>,
+}
+
+impl Dispatcher {
+    #[inline(never)]
+    fn dispatch(&self, event: c_int) -> Vec<c_int> {
+        self.handlers
+            .iter()
+            .map(|handler| std::hint::black_box(handler)(event))
+            .collect()
+    }
+}
+
+extern "C" fn double_event(event: c_int) -> c_int {
+    event * 2
+}
+
+unsafe extern "C" fn fake_negate_event(event: c_int) -> c_int {
+    -event
+}
+
+#[test]
+fn test_fake_extern_c_callback_when_invoked_by_dispatcher_should_run_fake() {
+    let dispatcher = Dispatcher {
+        handlers: vec![double_event],
+    };
+    assert_eq!(dispatcher.dispatch(21), vec![42]);
+
+    {
+        let mut injector = InjectorPP::new();
+        injector
+            .when_called(injectorpp::func!(
+                unsafe{} extern "C" fn (double_event)(c_int) -> c_int
+            ))
+            .will_execute_raw(injectorpp::func!(
+                unsafe{} extern "C" fn (fake_negate_event)(c_int) -> c_int
+            ));
+
+        assert_eq!(dispatcher.dispatch(21), vec![-21]);
+    }
+
+    assert_eq!(dispatcher.dispatch(21), vec![42]);
+}
+
+extern "C" fn compare_ascending(left: *const c_void, right: *const c_void) -> c_int {
+    let (left, right) = unsafe { (*(left as *const i32), *(right as *const i32)) };
+    left.cmp(&right) as c_int
+}
+
+unsafe extern "C" fn fake_compare_descending(left: *const c_void, right: *const c_void) -> c_int {
+    let (left, right) = (*(left as *const i32), *(right as *const i32));
+    right.cmp(&left) as c_int
+}
+
+#[cfg(unix)]
+#[test]
+fn test_fake_extern_c_callback_when_invoked_by_libc_qsort_should_run_fake() {
+    let mut values = [3i32, 1, 2];
+
+    let mut injector = InjectorPP::new();
+    injector
+        .when_called(injectorpp::func!(
+            unsafe{} extern "C" fn (compare_ascending)(*const c_void, *const c_void) -> c_int
+        ))
+        .will_execute(injectorpp::fake!(
+            func_type: unsafe extern "C" fn(left: *const c_void, right: *const c_void) -> c_int,
+            returns: fake_compare_descending(left, right)
+        ));
+
+    unsafe {
+        libc::qsort(
+            values.as_mut_ptr() as *mut c_void,
+            values.len(),
+            std::mem::size_of::<i32>(),
+            Some(compare_ascending),
+        );
+    }
+
+    assert_eq!(values, [3, 2, 1]);
+}
+
+static LAST_SIGNAL: AtomicI32 = AtomicI32::new(0);
+
+extern "C" fn on_signal(signal: c_int) {
+    LAST_SIGNAL.store(signal, Ordering::SeqCst);
+}
+
+unsafe extern "C" fn fake_on_signal(signal: c_int) {
+    LAST_SIGNAL.store(-signal, Ordering::SeqCst);
+}
+
+#[cfg(unix)]
+#[test]
+fn test_fake_signal_handler_when_signal_raised_should_run_fake() {
+    let handler: extern "C" fn(c_int) = on_signal;
+
+    unsafe {
+        libc::signal(libc::SIGUSR1, handler as libc::sighandler_t);
+    }
+
+    {
+        let mut injector = InjectorPP::new();
+        injector
+            .when_called(injectorpp::func!(unsafe{} extern "C" fn (on_signal)(c_int)))
+            .will_execute_raw(injectorpp::func!(unsafe{} extern "C" fn (fake_on_signal)(c_int)));
+
+        unsafe {
+            libc::raise(libc::SIGUSR1);
+        }
+
+        assert_eq!(LAST_SIGNAL.load(Ordering::SeqCst), -libc::SIGUSR1);
+    }
+
+    unsafe {
+        libc::raise(libc::SIGUSR1);
+        libc::signal(libc::SIGUSR1, libc::SIG_DFL);
+    }
+
+    assert_eq!(LAST_SIGNAL.load(Ordering::SeqCst), libc::SIGUSR1);
+}
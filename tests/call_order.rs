@@ -0,0 +1,145 @@
+use injectorpp::interface::injector::*;
+use std::sync::{Arc, Barrier};
+
+#[inline(never)]
+fn init() -> bool {
+    std::hint::black_box(false)
+}
+
+#[inline(never)]
+fn connect(port: u16) -> bool {
+    std::hint::black_box(port) == 0
+}
+
+#[inline(never)]
+fn send(payload: &[u8]) -> usize {
+    std::hint::black_box(payload).len() + 1
+}
+
+fn fake_client(injector: &mut InjectorPP) -> [MockHandle; 3] {
+    let init_handle = injector
+        .when_called(injectorpp::func!(fn (init)() -> bool))
+        .record_call_order()
+        .will_return_boolean(true);
+    let connect_handle = injector
+        .when_called(injectorpp::func!(fn (connect)(u16) -> bool))
+        .record_call_order()
+        .will_return_boolean(true);
+    let send_handle = injector
+        .when_called(injectorpp::func!(fn (send)(&[u8]) -> usize))
+        .record_call_order()
+        .will_execute(|payload: &[u8]| payload.len());
+
+    [init_handle, connect_handle, send_handle]
+}
+
+#[test]
+fn test_record_call_order_when_called_in_order_should_record_sequence() {
+    let mut injector = InjectorPP::new();
+    let [init_handle, connect_handle, send_handle] = fake_client(&mut injector);
+
+    assert!(init());
+    assert!(connect(443));
+    assert_eq!(send(b"ping"), 4);
+    assert_eq!(send(b"pong"), 4);
+
+    assert_eq!(
+        injector.call_sequence(),
+        vec![init_handle, connect_handle, send_handle, send_handle]
+    );
+    injector.assert_called_in_order(&[init_handle, connect_handle, send_handle]);
+    injector.assert_called_in_order(&[init_handle, send_handle]);
+}
+
+#[test]
+#[should_panic(
+    expected = "Fakes were not called in the expected order, first difference at step 2\n  expected: fake #0, fake #1, fake #2\n  actual:   fake #1, fake #0, fake #2"
+)]
+fn test_assert_called_in_order_when_order_differs_should_panic() {
+    let mut injector = InjectorPP::new();
+    let handles = fake_client(&mut injector);
+
+    assert!(connect(443));
+    assert!(init());
+    assert_eq!(send(b"ping"), 4);
+
+    injector.assert_called_in_order(&handles);
+}
+
+#[test]
+#[should_panic(expected = "actual:   no calls")]
+fn test_assert_called_in_order_when_nothing_called_should_panic() {
+    let mut injector = InjectorPP::new();
+    let handles = fake_client(&mut injector);
+
+    injector.assert_called_in_order(&handles);
+}
+
+#[test]
+fn test_call_sequence_when_fake_does_not_record_should_skip_it() {
+    let mut injector = InjectorPP::new();
+    injector
+        .when_called(injectorpp::func!(fn (init)() -> bool))
+        .will_return_boolean(true);
+    let connect_handle = injector
+        .when_called(injectorpp::func!(fn (connect)(u16) -> bool))
+        .record_call_order()
+        .will_return_boolean(true);
+
+    assert!(init());
+    assert!(connect(443));
+
+    assert_eq!(injector.call_sequence(), vec![connect_handle]);
+}
+
+#[test]
+fn test_record_call_order_when_called_from_threads_should_record_every_call_once() {
+    const THREADS: usize = 4;
+    const CALLS: usize = 50;
+
+    let mut injector = InjectorPP::new();
+    let [init_handle, connect_handle, send_handle] = fake_client(&mut injector);
+
+    let barrier = Arc::new(Barrier::new(THREADS));
+    let threads: Vec<_> = (0..THREADS)
+        .map(|_| {
+            let barrier = barrier.clone();
+            std::thread::spawn(move || {
+                barrier.wait();
+                for _ in 0..CALLS {
+                    assert!(init());
+                    assert!(connect(443));
+                    assert_eq!(send(b"ping"), 4);
+                }
+            })
+        })
+        .collect();
+    for thread in threads {
+        thread.join().unwrap();
+    }
+
+    let sequence = injector.call_sequence();
+    assert_eq!(sequence.len(), THREADS * CALLS * 3);
+    for handle in [init_handle, connect_handle, send_handle] {
+        assert_eq!(
+            sequence.iter().filter(|call| **call == handle).count(),
+            THREADS * CALLS
+        );
+    }
+    assert_eq!(sequence[0], init_handle);
+    injector.assert_called_in_order(&[init_handle, connect_handle, send_handle]);
+}
+
+#[test]
+fn test_call_order_failure_to_json_should_list_install_numbers() {
+    let failure = Failure::CallOrder {
+        step: 2,
+        expected: vec![0, 1],
+        actual: vec![1, 0],
+    };
+
+    assert_eq!(
+        failure.to_json(),
+        r#"{"kind":"call_order","step":2,"expected":[0,1],"actual":[1,0]}"#
+    );
+}
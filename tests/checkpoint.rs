@@ -0,0 +1,99 @@
+use injectorpp::interface::injector::*;
+
+#[inline(never)]
+fn is_connected() -> bool {
+    std::hint::black_box(false)
+}
+
+#[inline(never)]
+fn is_authorized() -> bool {
+    std::hint::black_box(false)
+}
+
+#[inline(never)]
+fn retry_count() -> u32 {
+    std::hint::black_box(0)
+}
+
+#[test]
+fn test_rollback_when_mock_added_after_checkpoint_should_remove_only_that_mock() {
+    let mut injector = InjectorPP::new();
+    injector
+        .when_called(injectorpp::func!(fn (is_connected)() -> bool))
+        .will_return_boolean(true);
+    injector
+        .when_called(injectorpp::func!(fn (retry_count)() -> u32))
+        .will_execute(|| 3u32);
+
+    let base = injector.checkpoint();
+
+    injector
+        .when_called(injectorpp::func!(fn (is_authorized)() -> bool))
+        .will_return_boolean(true);
+    assert!(is_authorized());
+
+    injector.rollback(base);
+
+    assert!(!is_authorized());
+    assert!(is_connected());
+    assert_eq!(retry_count(), 3);
+
+    // The injector keeps working after a rollback.
+    injector
+        .when_called(injectorpp::func!(fn (is_authorized)() -> bool))
+        .will_return_boolean(true);
+    assert!(is_authorized());
+}
+
+#[test]
+fn test_rollback_when_same_function_faked_twice_should_restore_first_fake() {
+    let mut injector = InjectorPP::new();
+    injector
+        .when_called(injectorpp::func!(fn (retry_count)() -> u32))
+        .will_execute(|| 1u32);
+
+    let base = injector.checkpoint();
+
+    injector
+        .when_called(injectorpp::func!(fn (retry_count)() -> u32))
+        .will_execute(|| 2u32);
+    assert_eq!(retry_count(), 2);
+
+    injector.rollback(base);
+
+    assert_eq!(retry_count(), 1);
+}
+
+#[test]
+fn test_rollback_after_restoring_earlier_fake_should_remove_fakes_after_checkpoint() {
+    let mut injector = InjectorPP::new();
+    let connected = injector
+        .when_called(injectorpp::func!(fn (is_connected)() -> bool))
+        .will_return_boolean(true);
+
+    let base = injector.checkpoint();
+
+    injector
+        .when_called(injectorpp::func!(fn (is_authorized)() -> bool))
+        .will_return_boolean(true);
+    injector.restore(connected);
+
+    injector.rollback(base);
+
+    assert!(!is_connected());
+    assert!(!is_authorized());
+}
+
+#[test]
+#[should_panic(expected = "The MockHandle refers to a fake that was rolled back")]
+fn test_rollback_when_handle_of_removed_mock_used_should_panic() {
+    let mut injector = InjectorPP::new();
+    let base = injector.checkpoint();
+
+    let handle = injector
+        .when_called(injectorpp::func!(fn (is_authorized)() -> bool))
+        .will_return_boolean(true);
+
+    injector.rollback(base);
+    injector.disable(handle);
+}
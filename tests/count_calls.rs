@@ -0,0 +1,119 @@
+use injectorpp::interface::injector::*;
+
+#[inline(never)]
+fn fetch_token(user: u32) -> u32 {
+    std::hint::black_box(user)
+}
+
+#[inline(never)]
+fn is_expired(token: u32) -> bool {
+    std::hint::black_box(token) == u32::MAX
+}
+
+fn fake_token_target(_user: u32) -> u32 {
+    42
+}
+
+#[test]
+fn test_verify_called_times_when_called_from_threads_should_count_every_call() {
+    let mut injector = InjectorPP::new();
+    let handle = injector
+        .when_called(injectorpp::func!(fn (fetch_token)(u32) -> u32))
+        .count_calls()
+        .will_execute_raw(injectorpp::func!(fn (fake_token_target)(u32) -> u32));
+
+    injector.verify_called_times(handle, 0);
+
+    std::thread::scope(|scope| {
+        for user in 0..4 {
+            scope.spawn(move || {
+                for _ in 0..5 {
+                    assert_eq!(fetch_token(user), 42);
+                }
+            });
+        }
+    });
+
+    injector.verify_called_times(handle, 20);
+    assert_eq!(injector.call_count(handle), 20);
+}
+
+#[test]
+fn test_verify_called_times_when_never_called_should_pass() {
+    let mut injector = InjectorPP::new();
+    let handle = injector
+        .when_called(injectorpp::func!(fn (is_expired)(u32) -> bool))
+        .count_calls()
+        .will_return_boolean(true);
+
+    injector.verify_called_times(handle, 0);
+}
+
+#[test]
+#[should_panic(
+    expected = "Fake function was expected to be called 2 time(s), but it is actually called 1 time(s)"
+)]
+fn test_verify_called_times_when_count_differs_should_panic() {
+    let mut injector = InjectorPP::new();
+    let handle = injector
+        .when_called(injectorpp::func!(fn (is_expired)(u32) -> bool))
+        .count_calls()
+        .will_return_boolean(true);
+
+    assert!(is_expired(1));
+    injector.verify_called_times(handle, 2);
+}
+
+#[test]
+#[should_panic(expected = "The fake was not installed after count_calls")]
+fn test_verify_called_times_when_calls_not_counted_should_panic() {
+    let mut injector = InjectorPP::new();
+    let handle = injector
+        .when_called(injectorpp::func!(fn (is_expired)(u32) -> bool))
+        .will_return_boolean(true);
+
+    injector.verify_called_times(handle, 0);
+}
+
+#[test]
+fn test_verify_never_called_when_fake_returns_boolean_and_not_called_should_pass() {
+    let mut injector = InjectorPP::new();
+    let handle = injector
+        .when_called(injectorpp::func!(fn (is_expired)(u32) -> bool))
+        .count_calls()
+        .will_return_boolean(true);
+
+    injector.verify_never_called(handle);
+}
+
+#[test]
+#[should_panic(
+    expected = "Fake function was expected to be called 0 time(s), but it is actually called 3 time(s)"
+)]
+fn test_verify_never_called_when_fake_returns_boolean_and_called_should_panic() {
+    let mut injector = InjectorPP::new();
+    let handle = injector
+        .when_called(injectorpp::func!(fn (is_expired)(u32) -> bool))
+        .count_calls()
+        .will_return_boolean(true);
+
+    for token in 0..3 {
+        assert!(is_expired(token));
+    }
+    injector.verify_never_called(handle);
+}
+
+#[test]
+#[should_panic(
+    expected = "Fake function was expected to be called 0 time(s), but it is actually called 1 time(s)"
+)]
+fn test_verify_never_called_when_fake_executes_function_and_called_should_panic() {
+    let mut injector = InjectorPP::new();
+    let handle = injector
+        .when_called(injectorpp::func!(fn (fetch_token)(u32) -> u32))
+        .count_calls()
+        .will_execute_raw(injectorpp::func!(fn (fake_token_target)(u32) -> u32));
+
+    assert_eq!(fetch_token(1), 42);
+    injector.verify_never_called(handle);
+}
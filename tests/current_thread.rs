@@ -0,0 +1,69 @@
+use injectorpp::interface::injector::*;
+use std::sync::{Arc, Barrier};
+use std::thread;
+
+#[inline(never)]
+fn region() -> String {
+    std::hint::black_box("eu-west").to_string()
+}
+
+#[inline(never)]
+fn scale(n: u32, factor: u32) -> u32 {
+    std::hint::black_box(n) * factor
+}
+
+#[test]
+fn test_when_called_current_thread_only_when_other_thread_calls_concurrently_should_see_original() {
+    let mut injector = InjectorPP::new();
+    injector
+        .when_called_current_thread_only(injectorpp::func!(fn (region)() -> String))
+        .will_execute(|| "fake".to_string());
+
+    let barrier = Arc::new(Barrier::new(2));
+    let other = {
+        let barrier = barrier.clone();
+        thread::spawn(move || {
+            let mut seen = Vec::new();
+            for _ in 0..100 {
+                barrier.wait();
+                seen.push(region());
+            }
+            seen
+        })
+    };
+
+    for _ in 0..100 {
+        barrier.wait();
+        assert_eq!(region(), "fake");
+    }
+
+    let seen = other.join().unwrap();
+    assert!(seen.iter().all(|region| region == "eu-west"));
+}
+
+#[test]
+fn test_when_called_current_thread_only_when_dropped_should_restore_original() {
+    {
+        let mut injector = InjectorPP::new();
+        injector
+            .when_called_current_thread_only(injectorpp::func!(fn (scale)(u32, u32) -> u32))
+            .will_execute(|n: u32, _: u32| n);
+
+        assert_eq!(scale(3, 5), 3);
+        assert_eq!(thread::spawn(|| scale(3, 5)).join().unwrap(), 15);
+    }
+
+    assert_eq!(scale(3, 5), 15);
+}
+
+#[test]
+#[should_panic(expected = "when_called_current_thread_only requires a closure fake")]
+fn test_when_called_current_thread_only_when_fake_pair_should_panic() {
+    let mut injector = InjectorPP::new();
+    injector
+        .when_called_current_thread_only(injectorpp::func!(fn (scale)(u32, u32) -> u32))
+        .will_execute(injectorpp::fake!(
+            func_type: fn(n: u32, factor: u32) -> u32,
+            returns: 0
+        ));
+}
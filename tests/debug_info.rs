@@ -0,0 +1,111 @@
+// 32-bit ARM patches without JIT code, see tests/arm.rs.
+#![cfg(not(target_arch = "arm"))]
+
+use injectorpp::interface::injector::*;
+
+#[inline(never)]
+fn is_ready() -> bool {
+    std::hint::black_box(false)
+}
+
+#[inline(never)]
+fn answer() -> i32 {
+    std::hint::black_box(1)
+}
+
+fn read_code(address: usize, len: usize) -> Vec<u8> {
+    unsafe { std::slice::from_raw_parts(address as *const u8, len) }.to_vec()
+}
+
+#[test]
+fn test_debug_info_when_boolean_fake_installed_should_report_written_bytes() {
+    let address = is_ready as fn() -> bool as usize;
+
+    let mut injector = InjectorPP::new();
+    let handle = injector
+        .when_called(injectorpp::func!(fn (is_ready)() -> bool))
+        .will_return_boolean(true);
+
+    let debug = injector.debug_info(handle);
+
+    assert_eq!(debug.address, address);
+    assert_eq!(
+        read_code(address, debug.patch_bytes.len()),
+        debug.patch_bytes
+    );
+    assert_eq!(debug.original_bytes.len(), debug.patch_bytes.len());
+    assert_ne!(debug.original_bytes, debug.patch_bytes);
+    assert_ne!(debug.jit_address, 0);
+    assert_eq!(
+        read_code(debug.jit_address, debug.jit_bytes.len()),
+        debug.jit_bytes
+    );
+    assert_eq!(debug.branch_kind, BranchKind::Near);
+
+    #[cfg(target_arch = "aarch64")]
+    assert_eq!(debug.patch_bytes.len(), 12);
+
+    #[cfg(target_arch = "x86_64")]
+    assert_eq!(debug.patch_bytes[0], 0xE9);
+
+    drop(injector);
+    assert_eq!(
+        read_code(address, debug.original_bytes.len()),
+        debug.original_bytes
+    );
+}
+
+#[test]
+fn test_debug_info_when_function_faked_twice_should_report_previous_patch_as_original() {
+    let mut injector = InjectorPP::new();
+    let first = injector
+        .when_called(injectorpp::func!(fn (answer)() -> i32))
+        .will_execute(|| 2);
+    let second = injector
+        .when_called(injectorpp::func!(fn (answer)() -> i32))
+        .will_execute(|| 3);
+
+    let first = injector.debug_info(first);
+    let second = injector.debug_info(second);
+
+    let len = first.patch_bytes.len().min(second.original_bytes.len());
+    assert_eq!(second.original_bytes[..len], first.patch_bytes[..len]);
+}
+
+#[cfg(target_arch = "aarch64")]
+#[test]
+fn test_debug_info_when_jit_memory_out_of_branch_range_should_report_long_branch() {
+    let hint = (answer as fn() -> i32 as usize).wrapping_add(0x4000_0000);
+    let options = InjectorOptions::new().jit_alloc_strategy(JitAllocStrategy::Hint(hint));
+
+    let mut injector = InjectorPP::new_with_options(options);
+    let handle = injector
+        .when_called(injectorpp::func!(fn (answer)() -> i32))
+        .will_execute(|| 42);
+
+    assert_eq!(answer(), 42);
+    assert_eq!(injector.debug_info(handle).branch_kind, BranchKind::Long);
+}
+
+#[cfg(feature = "debug-disasm")]
+#[test]
+fn test_disassembly_when_boolean_fake_installed_should_list_branch_to_jit_code() {
+    let mut injector = InjectorPP::new();
+    let handle = injector
+        .when_called(injectorpp::func!(fn (is_ready)() -> bool))
+        .will_return_boolean(true);
+
+    let debug = injector.debug_info(handle);
+    let disassembly = debug.disassembly();
+
+    let branch = if cfg!(target_arch = "aarch64") {
+        "b"
+    } else {
+        "jmp"
+    };
+    let expected = format!("{branch} {:#x}", debug.jit_address);
+
+    assert!(disassembly.contains("patch:\n"), "{disassembly}");
+    assert!(disassembly.contains("jit:\n"), "{disassembly}");
+    assert!(disassembly.contains(&expected), "{disassembly}");
+}
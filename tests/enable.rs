@@ -0,0 +1,179 @@
+use injectorpp::interface::injector::*;
+
+#[inline(never)]
+fn read_config(key: u32) -> u32 {
+    std::hint::black_box(key)
+}
+
+#[inline(never)]
+fn is_online() -> bool {
+    std::hint::black_box(false)
+}
+
+#[test]
+fn test_enable_scoped_when_token_held_should_only_fake_inside_scope() {
+    let mut injector = InjectorPP::new();
+    let handle = injector
+        .when_called(injectorpp::func!(fn (read_config)(u32) -> u32))
+        .will_execute(injectorpp::fake!(
+            func_type: fn(key: u32) -> u32,
+            returns: key * 100,
+            times: 2
+        ));
+
+    injector.disable(handle);
+    assert_eq!(read_config(1), 1);
+
+    {
+        let _scope = injector.enable_scoped(handle);
+        assert_eq!(read_config(2), 200);
+    }
+
+    assert_eq!(read_config(3), 3);
+
+    {
+        let _scope = injector.enable_scoped(handle);
+        assert_eq!(read_config(4), 400);
+    }
+
+    assert_eq!(read_config(5), 5);
+    assert!(!injector.is_enabled(handle));
+
+    // The verifier sees the two calls made across both scopes when the injector is dropped.
+}
+
+#[test]
+fn test_enable_scoped_when_already_enabled_should_stay_enabled() {
+    let mut injector = InjectorPP::new();
+    let handle = injector
+        .when_called(injectorpp::func!(fn (is_online)() -> bool))
+        .will_return_boolean(true);
+
+    {
+        let _scope = injector.enable_scoped(handle);
+        assert!(is_online());
+    }
+
+    assert!(injector.is_enabled(handle));
+    assert!(is_online());
+}
+
+#[test]
+fn test_disable_when_injector_dropped_should_restore_original() {
+    {
+        let mut injector = InjectorPP::new();
+        let handle = injector
+            .when_called(injectorpp::func!(fn (is_online)() -> bool))
+            .will_return_boolean(true);
+
+        injector.disable(handle);
+        injector.disable(handle);
+        assert!(!is_online());
+    }
+
+    assert!(!is_online());
+}
+
+#[test]
+#[should_panic(expected = "created by another InjectorPP instance")]
+fn test_enable_when_handle_from_other_injector_should_panic() {
+    let handle = {
+        let mut injector = InjectorPP::new();
+        injector
+            .when_called(injectorpp::func!(fn (is_online)() -> bool))
+            .will_return_boolean(true)
+    };
+
+    let mut injector = InjectorPP::new();
+    injector.enable(handle);
+}
+
+#[test]
+fn test_same_function_faked_twice_when_injector_dropped_should_restore_original() {
+    {
+        let mut injector = InjectorPP::new();
+        injector
+            .when_called(injectorpp::func!(fn (read_config)(u32) -> u32))
+            .will_execute(|key: u32| key * 10);
+        injector
+            .when_called(injectorpp::func!(fn (read_config)(u32) -> u32))
+            .will_execute(|key: u32| key * 100);
+
+        assert_eq!(read_config(2), 200);
+    }
+
+    assert_eq!(read_config(2), 2);
+}
+
+#[test]
+fn test_disable_when_same_function_faked_twice_should_run_latest_enabled_fake() {
+    let mut injector = InjectorPP::new();
+    let first = injector
+        .when_called(injectorpp::func!(fn (read_config)(u32) -> u32))
+        .will_execute(|key: u32| key * 10);
+    let second = injector
+        .when_called(injectorpp::func!(fn (read_config)(u32) -> u32))
+        .will_execute(|key: u32| key * 100);
+
+    // Disabling the earlier fake leaves the later one in place.
+    injector.disable(first);
+    assert_eq!(read_config(3), 300);
+
+    // With both disabled, the real code runs rather than the bytes of the first fake.
+    injector.disable(second);
+    assert_eq!(read_config(3), 3);
+
+    injector.enable(first);
+    assert_eq!(read_config(3), 30);
+
+    injector.enable(second);
+    assert_eq!(read_config(3), 300);
+
+    injector.disable(second);
+    assert_eq!(read_config(3), 30);
+
+    // Dropping the later fake while the earlier one is disabled must not revive it.
+    injector.disable(first);
+    injector.restore(second);
+    assert_eq!(read_config(3), 3);
+
+    drop(injector);
+    assert_eq!(read_config(3), 3);
+}
+
+#[inline(never)]
+fn scale(x: i32) -> i32 {
+    std::hint::black_box(x) * 2
+}
+
+#[test]
+fn test_disable_when_mapping_fake_stacked_on_earlier_fake_should_map_real_function() {
+    let mut injector = InjectorPP::new();
+    let first = injector
+        .when_called(injectorpp::func!(fn (scale)(i32) -> i32))
+        .will_execute(|x: i32| x * 100);
+    let second = injector
+        .when_called(injectorpp::func!(fn (scale)(i32) -> i32))
+        .will_map(|_x: i32, original: i32| original + 1);
+
+    // The original of the mapping fake is the real function, not the earlier fake.
+    assert_eq!(scale(3), 7);
+
+    injector.disable(first);
+    assert_eq!(scale(3), 7);
+
+    injector.disable(second);
+    assert_eq!(scale(3), 6);
+
+    injector.enable(first);
+    assert_eq!(scale(3), 300);
+
+    injector.enable(second);
+    assert_eq!(scale(3), 7);
+
+    injector.restore(second);
+    assert_eq!(scale(3), 300);
+
+    drop(injector);
+    assert_eq!(scale(3), 6);
+}
@@ -0,0 +1,56 @@
+use injectorpp::interface::injector::*;
+use std::panic::{catch_unwind, AssertUnwindSafe};
+use std::sync::{Arc, Mutex};
+
+#[inline(never)]
+fn fetch_quota(user: u32) -> u32 {
+    std::hint::black_box(user)
+}
+
+#[test]
+fn test_set_failure_sink_when_times_mismatch_should_receive_call_count_failure() {
+    let function = fetch_quota as fn(u32) -> u32 as usize;
+    let failures = Arc::new(Mutex::new(Vec::new()));
+
+    let sink_failures = failures.clone();
+    InjectorPP::set_failure_sink(move |failure| {
+        // The sink is global, keep only the failures of this test.
+        if matches!(failure, Failure::CallCount { function: f, .. } if *f == function) {
+            sink_failures.lock().unwrap().push(failure.clone());
+        }
+    });
+
+    let result = catch_unwind(AssertUnwindSafe(|| {
+        let mut injector = InjectorPP::new();
+        injector
+            .when_called(injectorpp::func!(fn (fetch_quota)(u32) -> u32))
+            .will_execute(injectorpp::fake!(
+                func_type: fn(user: u32) -> u32,
+                returns: user + 1,
+                times: 2
+            ));
+
+        assert_eq!(fetch_quota(1), 2);
+    }));
+    InjectorPP::clear_failure_sink();
+
+    let message = result.unwrap_err();
+    assert_eq!(
+        message.downcast_ref::<String>().unwrap(),
+        "Fake function was expected to be called 2 time(s), but it is actually called 1 time(s)"
+    );
+
+    let failures = failures.lock().unwrap();
+    assert_eq!(
+        *failures,
+        vec![Failure::CallCount {
+            function,
+            expected: 2,
+            actual: 1,
+        }]
+    );
+    assert_eq!(
+        failures[0].to_json(),
+        format!(r#"{{"kind":"call_count","function":"{function:#x}","expected":2,"actual":1}}"#)
+    );
+}
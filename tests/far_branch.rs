@@ -0,0 +1,41 @@
+#![cfg(target_arch = "aarch64")]
+
+use injectorpp::interface::injector::*;
+
+#[inline(never)]
+fn answer() -> i32 {
+    std::hint::black_box(1)
+}
+
+fn far_options() -> InjectorOptions {
+    // More than the 128MB a single branch instruction reaches.
+    let hint = (answer as fn() -> i32 as usize).wrapping_add(0x4000_0000);
+    InjectorOptions::new().jit_alloc_strategy(JitAllocStrategy::Hint(hint))
+}
+
+#[test]
+fn test_fake_with_jit_memory_out_of_branch_range_should_return_fake_value() {
+    let mut injector = InjectorPP::new_with_options(far_options());
+    injector
+        .when_called(injectorpp::func!(fn (answer)() -> i32))
+        .will_execute(injectorpp::fake!(
+            func_type: fn() -> i32,
+            returns: 42
+        ));
+
+    assert_eq!(answer(), 42);
+}
+
+#[test]
+fn test_fake_with_jit_memory_out_of_branch_range_and_dropped_should_restore_original() {
+    {
+        let mut injector = InjectorPP::new_with_options(far_options());
+        injector
+            .when_called(injectorpp::func!(fn (answer)() -> i32))
+            .will_execute(|| 7);
+
+        assert_eq!(answer(), 7);
+    }
+
+    assert_eq!(answer(), 1);
+}
@@ -0,0 +1,114 @@
+// Every free page near the patched function is reserved, which would starve the other tests
+// of this binary of nearby JIT memory, so it holds a single test.
+#![cfg(all(target_os = "linux", target_arch = "x86_64"))]
+
+use injectorpp::interface::injector::*;
+
+/// How far a `jmp rel32` written by injectorpp on Linux may branch.
+const NEAR_RANGE: usize = 0x800_0000;
+
+#[inline(never)]
+fn is_ready() -> bool {
+    std::hint::black_box(false)
+}
+
+/// Returns the mapped ranges of the process, in ascending order.
+fn mappings() -> Vec<(usize, usize)> {
+    std::fs::read_to_string("/proc/self/maps")
+        .unwrap()
+        .lines()
+        .map(|line| {
+            let (low, high) = line
+                .split_whitespace()
+                .next()
+                .unwrap()
+                .split_once('-')
+                .unwrap();
+            (
+                usize::from_str_radix(low, 16).unwrap(),
+                usize::from_str_radix(high, 16).unwrap(),
+            )
+        })
+        .collect()
+}
+
+/// Maps inaccessible memory over every free page of `window`, so nothing else can be mapped
+/// there, and returns the reserved ranges.
+fn reserve(window: std::ops::Range<usize>) -> Vec<(usize, usize)> {
+    let mut reserved = Vec::new();
+
+    // Other threads may map memory meanwhile, so look again until no gap is left.
+    loop {
+        let mut end = window.start;
+        let mut gaps = Vec::new();
+        for (low, high) in mappings() {
+            if low > end {
+                gaps.push((end, low.min(window.end)));
+            }
+            end = end.max(high);
+            if end >= window.end {
+                break;
+            }
+        }
+        if end < window.end {
+            gaps.push((end, window.end));
+        }
+        gaps.retain(|(low, high)| low < high);
+
+        if gaps.is_empty() {
+            return reserved;
+        }
+
+        for (low, high) in gaps {
+            let ptr = unsafe {
+                libc::mmap(
+                    low as *mut libc::c_void,
+                    high - low,
+                    libc::PROT_NONE,
+                    libc::MAP_PRIVATE
+                        | libc::MAP_ANONYMOUS
+                        | libc::MAP_NORESERVE
+                        | libc::MAP_FIXED_NOREPLACE,
+                    -1,
+                    0,
+                )
+            };
+            if ptr != libc::MAP_FAILED {
+                reserved.push((low, high));
+            }
+        }
+    }
+}
+
+#[test]
+fn test_when_no_memory_near_function_should_branch_to_far_jit_memory_through_absolute_jump() {
+    let address = is_ready as fn() -> bool as usize;
+    let page_size = unsafe { libc::sysconf(libc::_SC_PAGESIZE) as usize };
+    let low = address.saturating_sub(NEAR_RANGE + page_size).max(0x1_0000) & !(page_size - 1);
+    let high = (address + NEAR_RANGE + 2 * page_size) & !(page_size - 1);
+    let reserved = reserve(low..high);
+
+    {
+        let mut injector = InjectorPP::new();
+        let handle = injector
+            .when_called(injectorpp::func!(fn (is_ready)() -> bool))
+            .will_return_boolean(true);
+
+        assert!(is_ready());
+
+        let debug = injector.debug_info(handle);
+        assert_eq!(debug.branch_kind, BranchKind::Long);
+        assert!(debug.jit_address.abs_diff(address) > NEAR_RANGE);
+        // movabs rax, imm64 and jmp rax.
+        assert_eq!(debug.patch_bytes[..2], [0x48, 0xB8]);
+        assert_eq!(debug.patch_bytes[10..12], [0xFF, 0xE0]);
+    }
+
+    assert!(!is_ready());
+
+    for (low, high) in reserved {
+        unsafe {
+            libc::munmap(low as *mut libc::c_void, high - low);
+        }
+    }
+}
@@ -0,0 +1,44 @@
+use std::path::PathBuf;
+
+fn main() {
+    println!("cargo:rerun-if-changed=native.c");
+    println!("cargo:rerun-if-changed=plugin.c");
+
+    cc::Build::new()
+        .file("native.c")
+        .compile("injectorpp_native_fixture");
+
+    build_plugin();
+}
+
+/// Builds plugin.c into a shared library and passes its path on as `INJECTORPP_PLUGIN_PATH`.
+fn build_plugin() {
+    let out_dir = PathBuf::from(std::env::var("OUT_DIR").unwrap());
+    let target_os = std::env::var("CARGO_CFG_TARGET_OS").unwrap();
+
+    let file_name = match target_os.as_str() {
+        "windows" => "injectorpp_plugin.dll",
+        "macos" => "libinjectorpp_plugin.dylib",
+        _ => "libinjectorpp_plugin.so",
+    };
+    let path = out_dir.join(file_name);
+
+    let compiler = cc::Build::new().get_compiler();
+    let mut command = compiler.to_command();
+    if compiler.is_like_msvc() {
+        command
+            .arg("/LD")
+            .arg("plugin.c")
+            .arg(format!("/Fe{}", path.display()))
+            .arg(format!("/Fo{}\\", out_dir.display()));
+    } else {
+        command
+            .args(["-shared", "-fPIC", "plugin.c", "-o"])
+            .arg(&path);
+    }
+
+    let status = command.status().expect("failed to run the C compiler");
+    assert!(status.success(), "failed to build plugin.c");
+
+    println!("cargo:rustc-env=INJECTORPP_PLUGIN_PATH={}", path.display());
+}
@@ -0,0 +1,38 @@
+/* Compiled into a static archive by build.rs and linked into the injectorpp tests. */
+
+#include <stdarg.h>
+#include <stdio.h>
+
+#if defined(_MSC_VER)
+#define NOINLINE __declspec(noinline)
+#else
+#define NOINLINE __attribute__((noinline))
+#endif
+
+NOINLINE int injectorpp_native_checksum(int value)
+{
+    return value * 31 + 7;
+}
+
+int injectorpp_native_checksum_pair(int first, int second)
+{
+    return injectorpp_native_checksum(first) + injectorpp_native_checksum(second);
+}
+
+/* Returns the length of the formatted string, like a printf that prints nothing. */
+NOINLINE int injectorpp_native_format_length(const char *format, ...)
+{
+    va_list args;
+    int length;
+
+    va_start(args, format);
+    length = vsnprintf(NULL, 0, format, args);
+    va_end(args);
+
+    return length;
+}
+
+int injectorpp_native_describe(int value)
+{
+    return injectorpp_native_format_length("value=%d", value);
+}
@@ -0,0 +1,23 @@
+/* Compiled into a shared library by build.rs, which the injectorpp tests load at runtime. */
+
+#if defined(_WIN32)
+#define EXPORT __declspec(dllexport)
+#else
+#define EXPORT __attribute__((visibility("default")))
+#endif
+
+#if defined(_MSC_VER)
+#define NOINLINE __declspec(noinline)
+#else
+#define NOINLINE __attribute__((noinline))
+#endif
+
+EXPORT NOINLINE int injectorpp_plugin_scale(int value)
+{
+    return value * 3 + 1;
+}
+
+EXPORT int injectorpp_plugin_scale_twice(int value)
+{
+    return injectorpp_plugin_scale(injectorpp_plugin_scale(value));
+}
@@ -0,0 +1,28 @@
+//! Bindings to a static C library, so the injectorpp tests can fake functions that only
+//! exist in an archive linked by a build script, and the path of a shared library for the
+//! tests to load at runtime.
+
+use std::os::raw::{c_char, c_int};
+
+extern "C" {
+    pub fn injectorpp_native_checksum(value: c_int) -> c_int;
+    pub fn injectorpp_native_checksum_pair(first: c_int, second: c_int) -> c_int;
+    pub fn injectorpp_native_format_length(format: *const c_char, ...) -> c_int;
+    pub fn injectorpp_native_describe(value: c_int) -> c_int;
+}
+
+/// Calls `injectorpp_native_checksum_pair`, which calls `injectorpp_native_checksum` twice
+/// from inside the archive.
+pub fn checksum_pair(first: i32, second: i32) -> i32 {
+    unsafe { injectorpp_native_checksum_pair(first, second) }
+}
+
+/// Calls `injectorpp_native_describe`, which passes `value` to the variadic
+/// `injectorpp_native_format_length` from inside the archive.
+pub fn describe(value: i32) -> i32 {
+    unsafe { injectorpp_native_describe(value) }
+}
+
+/// The path of the shared library built from plugin.c, which exports
+/// `injectorpp_plugin_scale` and `injectorpp_plugin_scale_twice`.
+pub const PLUGIN_PATH: &str = env!("INJECTORPP_PLUGIN_PATH");
@@ -0,0 +1,62 @@
+use injectorpp::interface::injector::*;
+
+#[inline(never)]
+fn foo() -> i32 {
+    std::hint::black_box(6)
+}
+
+#[inline(never)]
+fn is_enabled() -> bool {
+    std::hint::black_box(false)
+}
+
+fn fake_is_enabled() -> bool {
+    true
+}
+
+#[test]
+fn test_func_address_from_raw_with_signature_should_fake_function() {
+    let raw = foo as fn() -> i32 as *const ();
+    let address = unsafe { FuncAddress::from_raw(raw) }.unwrap();
+    assert_eq!(address.as_ptr(), raw);
+
+    {
+        let mut injector = InjectorPP::new();
+        injector
+            .when_called(address.with_signature::<fn() -> i32>())
+            .will_execute(|| 42);
+
+        assert_eq!(foo(), 42);
+    }
+
+    assert_eq!(foo(), 6);
+}
+
+#[test]
+fn test_func_address_into_func_ptr_should_fake_function_unchecked() {
+    let address =
+        unsafe { FuncAddress::from_raw(is_enabled as fn() -> bool as *const ()) }.unwrap();
+
+    let mut injector = InjectorPP::new();
+    unsafe {
+        injector
+            .when_called(FuncPtr::from(address))
+            .will_execute_raw_unchecked(injectorpp::func_unchecked!(fake_is_enabled));
+    }
+
+    assert!(is_enabled());
+}
+
+#[test]
+fn test_func_address_from_raw_when_null_should_return_error() {
+    let result = unsafe { FuncAddress::from_raw(std::ptr::null()) };
+
+    assert_eq!(result, Err(InjectError::NullAddress));
+}
+
+#[test]
+fn test_func_address_from_raw_when_unreadable_should_return_error() {
+    let result = unsafe { FuncAddress::from_raw(0x10 as *const ()) };
+
+    assert!(matches!(result, Err(InjectError::UnreadableMemory { .. })));
+}
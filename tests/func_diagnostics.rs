@@ -0,0 +1,47 @@
+// Compile-time diagnostics of `func!` for callables that are invoked indirectly.
+#[test]
+fn test_func_when_given_indirect_callable_should_fail_to_compile() {
+    let cases = trybuild::TestCases::new();
+    cases.compile_fail("tests/ui/func_*.rs");
+}
+
+// Compile-time diagnostics of `closure!` for fakes returning dangling references.
+#[test]
+fn test_closure_when_returning_dangling_reference_should_fail_to_compile() {
+    let cases = trybuild::TestCases::new();
+    cases.compile_fail("tests/ui/closure_*.rs");
+}
+
+// Compile-time diagnostics of the async fakes for outputs borrowing from the test.
+#[test]
+fn test_async_fake_when_returning_borrowed_reference_should_fail_to_compile() {
+    let cases = trybuild::TestCases::new();
+    cases.compile_fail("tests/ui/async_*.rs");
+}
+
+// Compile-time signature checks of `will_execute_raw` for replacements of another type.
+#[test]
+fn test_will_execute_raw_when_signatures_differ_should_fail_to_compile() {
+    let cases = trybuild::TestCases::new();
+    cases.compile_fail("tests/ui/raw_mismatch_*.rs");
+}
+
+#[test]
+fn test_will_execute_raw_when_signatures_match_should_compile() {
+    let cases = trybuild::TestCases::new();
+    cases.pass("tests/ui/raw_match_*.rs");
+}
+
+// Compile-time checks of `will_return_aggregate` for types not returned in two registers.
+#[test]
+fn test_will_return_aggregate_when_not_register_pair_should_fail_to_compile() {
+    let cases = trybuild::TestCases::new();
+    cases.compile_fail("tests/ui/aggregate_*.rs");
+}
+
+// Compile-time checks of `will_return` for types whose return registers depend on their layout.
+#[test]
+fn test_will_return_when_not_return_value_should_fail_to_compile() {
+    let cases = trybuild::TestCases::new();
+    cases.compile_fail("tests/ui/return_*.rs");
+}
@@ -0,0 +1,70 @@
+use injectorpp::interface::injector::*;
+use std::fmt::Display;
+
+#[inline(never)]
+fn identity<T>(value: T) -> T {
+    std::hint::black_box(value)
+}
+
+#[inline(never)]
+fn describe<T: Display>(value: T) -> String {
+    format!("value: {}", std::hint::black_box(value))
+}
+
+mod convert {
+    #[inline(never)]
+    pub fn widen<T: Into<u64>>(value: T) -> u64 {
+        std::hint::black_box(value.into())
+    }
+}
+
+#[test]
+fn test_func_when_turbofish_selects_monomorphization_should_fake_only_it() {
+    {
+        let mut injector = InjectorPP::new();
+        injector
+            .when_called(injectorpp::func!(fn (identity::<u32>)(u32) -> u32))
+            .will_execute(|value: u32| value + 1);
+
+        assert_eq!(identity(1u32), 2);
+        assert_eq!(identity(1u64), 1);
+        assert_eq!(identity("text"), "text");
+    }
+
+    assert_eq!(identity(1u32), 1);
+}
+
+#[test]
+fn test_func_when_two_monomorphizations_faked_should_not_interfere() {
+    let mut injector = InjectorPP::new();
+    injector
+        .when_called(injectorpp::func!(fn (describe::<u32>)(u32) -> String))
+        .will_execute(|value: u32| format!("u32 {value}"));
+    injector
+        .when_called(injectorpp::func!(fn (describe::<&'static str>)(&'static str) -> String))
+        .will_execute(|value: &str| format!("str {value}"));
+
+    assert_eq!(describe(7u32), "u32 7");
+    assert_eq!(describe("seven"), "str seven");
+    assert_eq!(describe(7i64), "value: 7");
+}
+
+#[test]
+fn test_func_when_turbofish_follows_module_path_should_fake_it() {
+    let mut injector = InjectorPP::new();
+    injector
+        .when_called(injectorpp::func!(fn (convert::widen::<u8>)(u8) -> u64))
+        .will_return_u64(0);
+
+    assert_eq!(convert::widen(5u8), 0);
+    assert_eq!(convert::widen(5u32), 5);
+}
+
+#[test]
+#[should_panic(expected = "Signature mismatch")]
+fn test_func_when_fake_takes_other_monomorphization_should_panic() {
+    let mut injector = InjectorPP::new();
+    injector
+        .when_called(injectorpp::func!(fn (identity::<u32>)(u32) -> u32))
+        .will_execute(|value: u64| value);
+}
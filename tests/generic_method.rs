@@ -0,0 +1,79 @@
+use injectorpp::interface::injector::*;
+
+struct Container<T> {
+    items: Vec<T>,
+}
+
+impl<T: Copy + Default> Container<T> {
+    fn new(items: Vec<T>) -> Self {
+        Self { items }
+    }
+
+    #[inline(never)]
+    fn first(&self) -> T {
+        std::hint::black_box(&self.items)
+            .first()
+            .copied()
+            .unwrap_or_default()
+    }
+
+    #[inline(never)]
+    fn get_or(&self, index: usize, default: T) -> T {
+        std::hint::black_box(&self.items)
+            .get(index)
+            .copied()
+            .unwrap_or(default)
+    }
+}
+
+#[test]
+fn test_func_of_when_method_on_container_u32_should_fake_it() {
+    let container = Container::new(vec![1u32, 2, 3]);
+
+    let mut injector = InjectorPP::new();
+    injector
+        .when_called(injectorpp::func_of!(container, Container::first, u32))
+        .will_execute(|_: &Container<u32>| 42u32);
+
+    assert_eq!(container.first(), 42);
+    assert_eq!(Container::new(vec![7u32]).first(), 42);
+}
+
+#[test]
+fn test_func_of_when_method_takes_arguments_should_fake_it() {
+    let container = Container::new(vec![1u32, 2, 3]);
+
+    let mut injector = InjectorPP::new();
+    injector
+        .when_called(injectorpp::func_of!(
+            container,
+            Container::get_or,
+            (usize, u32) -> u32
+        ))
+        .will_execute(|_: &Container<u32>, index: usize, default: u32| index as u32 + default);
+
+    assert_eq!(container.get_or(0, 10), 10);
+    assert_eq!(container.get_or(5, 10), 15);
+}
+
+#[test]
+fn test_func_of_when_other_instantiation_called_should_keep_original() {
+    let container = Container::new(vec![1u32, 2, 3]);
+
+    let mut injector = InjectorPP::new();
+    injector
+        .when_called(injectorpp::func_of!(container, Container::first, u32))
+        .will_execute(|_: &Container<u32>| 42u32);
+
+    assert_eq!(Container::new(vec![5u64]).first(), 5);
+}
+
+#[test]
+fn test_func_when_type_arguments_spelled_out_should_not_need_instance() {
+    let mut injector = InjectorPP::new();
+    injector
+        .when_called(injectorpp::func!(fn (Container::<u32>::first)(&Container<u32>) -> u32))
+        .will_execute(|_: &Container<u32>| 42u32);
+
+    assert_eq!(Container::new(vec![1u32]).first(), 42);
+}
@@ -0,0 +1,86 @@
+use injectorpp::interface::injector::*;
+
+#[inline(never)]
+fn read_config(key: u32) -> u32 {
+    std::hint::black_box(key)
+}
+
+#[inline(never)]
+fn is_online() -> bool {
+    std::hint::black_box(false)
+}
+
+#[test]
+fn test_is_patched_when_function_not_faked_should_return_false() {
+    let mut injector = InjectorPP::new();
+    injector
+        .when_called(injectorpp::func!(fn (is_online)() -> bool))
+        .will_return_boolean(true);
+
+    assert!(!injector.is_patched(injectorpp::func!(fn (read_config)(u32) -> u32)));
+}
+
+#[test]
+fn test_is_patched_when_function_faked_should_return_true() {
+    let mut injector = InjectorPP::new();
+    injector
+        .when_called(injectorpp::func!(fn (read_config)(u32) -> u32))
+        .will_execute(|key: u32| key * 100);
+
+    assert!(injector.is_patched(injectorpp::func!(fn (read_config)(u32) -> u32)));
+}
+
+#[test]
+fn test_is_patched_when_fake_restored_should_return_false() {
+    let mut injector = InjectorPP::new();
+    let handle = injector
+        .when_called(injectorpp::func!(fn (read_config)(u32) -> u32))
+        .will_execute(|key: u32| key * 100);
+
+    injector.restore(handle);
+
+    assert!(!injector.is_patched(injectorpp::func!(fn (read_config)(u32) -> u32)));
+}
+
+#[test]
+fn test_is_patched_when_fake_disabled_should_return_false_until_enabled() {
+    let mut injector = InjectorPP::new();
+    let handle = injector
+        .when_called(injectorpp::func!(fn (is_online)() -> bool))
+        .will_return_boolean(true);
+
+    injector.disable(handle);
+    assert!(!injector.is_patched(injectorpp::func!(fn (is_online)() -> bool)));
+
+    injector.enable(handle);
+    assert!(injector.is_patched(injectorpp::func!(fn (is_online)() -> bool)));
+}
+
+#[test]
+fn test_is_patched_when_one_of_two_fakes_restored_should_return_true() {
+    let mut injector = InjectorPP::new();
+    injector
+        .when_called(injectorpp::func!(fn (read_config)(u32) -> u32))
+        .will_execute(|key: u32| key * 100);
+    let later = injector
+        .when_called(injectorpp::func!(fn (read_config)(u32) -> u32))
+        .will_execute(|key: u32| key * 1000);
+
+    injector.restore(later);
+
+    assert!(injector.is_patched(injectorpp::func!(fn (read_config)(u32) -> u32)));
+    assert_eq!(read_config(2), 200);
+}
+
+#[test]
+fn test_is_patched_when_rolled_back_should_return_false() {
+    let mut injector = InjectorPP::new();
+    let checkpoint = injector.checkpoint();
+    injector
+        .when_called(injectorpp::func!(fn (is_online)() -> bool))
+        .will_return_boolean(true);
+
+    injector.rollback(checkpoint);
+
+    assert!(!injector.is_patched(injectorpp::func!(fn (is_online)() -> bool)));
+}
@@ -0,0 +1,51 @@
+use injectorpp::interface::injector::*;
+
+#[inline(never)]
+fn is_ready() -> bool {
+    std::hint::black_box(false)
+}
+
+#[inline(never)]
+fn retries() -> u64 {
+    std::hint::black_box(3)
+}
+
+fn fake_with(strategy: JitAllocStrategy) {
+    let mut injector =
+        InjectorPP::new_with_options(InjectorOptions::new().jit_alloc_strategy(strategy));
+    injector
+        .when_called(injectorpp::func!(fn (is_ready)() -> bool))
+        .will_return_boolean(true);
+    injector
+        .when_called(injectorpp::func!(fn (retries)() -> u64))
+        .will_return_u64(7);
+
+    assert!(is_ready());
+    assert_eq!(retries(), 7);
+}
+
+#[test]
+fn test_new_with_options_when_strategy_is_nearest_should_fake() {
+    fake_with(JitAllocStrategy::Nearest);
+    assert!(!is_ready());
+}
+
+#[test]
+fn test_new_with_options_when_strategy_is_ascending_should_fake() {
+    fake_with(JitAllocStrategy::Ascending);
+    assert!(!is_ready());
+}
+
+#[test]
+fn test_new_with_options_when_hint_is_near_target_should_fake() {
+    let target = is_ready as fn() -> bool as usize;
+    fake_with(JitAllocStrategy::Hint(target + 0x100_0000));
+    assert!(!is_ready());
+}
+
+#[test]
+fn test_new_with_options_when_hint_is_out_of_branch_range_should_fake() {
+    fake_with(JitAllocStrategy::Hint(usize::MAX));
+    fake_with(JitAllocStrategy::Hint(0));
+    assert_eq!(retries(), 3);
+}
@@ -0,0 +1,35 @@
+#![cfg(target_os = "macos")]
+
+use injectorpp::interface::injector::*;
+
+#[inline(never)]
+fn foo() -> i32 {
+    std::hint::black_box(1)
+}
+
+#[test]
+fn test_fake_when_on_macos_should_return_fake_value() {
+    let mut injector = InjectorPP::new();
+    injector
+        .when_called(injectorpp::func!(fn (foo)() -> i32))
+        .will_execute(injectorpp::fake!(
+            func_type: fn() -> i32,
+            returns: 42
+        ));
+
+    assert_eq!(foo(), 42);
+}
+
+#[test]
+fn test_fake_when_on_macos_and_dropped_should_restore_original() {
+    {
+        let mut injector = InjectorPP::new();
+        injector
+            .when_called(injectorpp::func!(fn (foo)() -> i32))
+            .will_execute(|| 7);
+
+        assert_eq!(foo(), 7);
+    }
+
+    assert_eq!(foo(), 1);
+}
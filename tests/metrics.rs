@@ -0,0 +1,43 @@
+// The counters are global, so this binary holds a single test for them to be exact.
+#![cfg(feature = "metrics")]
+
+use injectorpp::interface::injector::*;
+
+#[inline(never)]
+fn is_ready() -> bool {
+    std::hint::black_box(false)
+}
+
+#[inline(never)]
+fn scale(value: u64) -> u64 {
+    std::hint::black_box(value) * 2
+}
+
+#[test]
+fn test_jit_bytes_used_should_rise_with_fakes_and_fall_once_restored() {
+    let patches = InjectorPP::live_patch_count();
+    let bytes = InjectorPP::jit_bytes_used();
+
+    {
+        let mut injector = InjectorPP::new();
+        injector
+            .when_called(injectorpp::func!(fn (is_ready)() -> bool))
+            .will_return_boolean(true);
+
+        let with_one_fake = InjectorPP::jit_bytes_used();
+        assert!(with_one_fake > bytes);
+        assert_eq!(InjectorPP::live_patch_count(), patches + 1);
+
+        injector
+            .when_called(injectorpp::func!(fn (scale)(u64) -> u64))
+            .will_execute(|value: u64| value + 1);
+
+        assert!(is_ready());
+        assert_eq!(scale(20), 21);
+        assert!(InjectorPP::jit_bytes_used() >= with_one_fake);
+        assert_eq!(InjectorPP::live_patch_count(), patches + 2);
+    }
+
+    assert_eq!(InjectorPP::jit_bytes_used(), bytes);
+    assert_eq!(InjectorPP::live_patch_count(), patches);
+}
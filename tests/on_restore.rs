@@ -0,0 +1,66 @@
+use injectorpp::interface::injector::*;
+use std::cell::Cell;
+use std::rc::Rc;
+
+#[inline(never)]
+fn acquire_connection() -> bool {
+    std::hint::black_box(false)
+}
+
+#[inline(never)]
+fn release_connection() -> bool {
+    std::hint::black_box(false)
+}
+
+fn fake_acquire_expecting_two_calls(injector: &mut InjectorPP) {
+    injector
+        .when_called(injectorpp::func!(fn (acquire_connection)() -> bool))
+        .on_restore(|record| assert_eq!(record.call_count(), 2))
+        .will_return_boolean(true);
+}
+
+#[test]
+fn test_on_restore_when_called_twice_should_pass() {
+    let mut injector = InjectorPP::new();
+    fake_acquire_expecting_two_calls(&mut injector);
+
+    assert!(acquire_connection());
+    assert!(acquire_connection());
+}
+
+#[test]
+#[should_panic(expected = "left: 1\n right: 2")]
+fn test_on_restore_when_called_once_should_panic() {
+    let mut injector = InjectorPP::new();
+    fake_acquire_expecting_two_calls(&mut injector);
+
+    assert!(acquire_connection());
+}
+
+#[test]
+fn test_on_restore_when_rolled_back_should_run_after_original_restored() {
+    let restored_count = Rc::new(Cell::new(None));
+    let mut injector = InjectorPP::new();
+
+    injector
+        .when_called(injectorpp::func!(fn (acquire_connection)() -> bool))
+        .will_return_boolean(true);
+
+    let checkpoint = injector.checkpoint();
+    let restored = restored_count.clone();
+    injector
+        .when_called(injectorpp::func!(fn (release_connection)() -> bool))
+        .on_restore(move |record| {
+            assert!(!release_connection());
+            restored.set(Some(record.call_count()));
+        })
+        .will_return_boolean(true);
+
+    assert!(release_connection());
+    assert_eq!(restored_count.get(), None);
+
+    injector.rollback(checkpoint);
+
+    assert_eq!(restored_count.get(), Some(1));
+    assert!(acquire_connection());
+}
@@ -0,0 +1,74 @@
+use injectorpp::interface::injector::*;
+
+#[inline(never)]
+fn is_ready() -> bool {
+    std::hint::black_box(false)
+}
+
+#[inline(never)]
+fn is_busy() -> bool {
+    std::hint::black_box(false)
+}
+
+#[inline(never)]
+fn is_idle() -> bool {
+    std::hint::black_box(false)
+}
+
+#[test]
+fn test_try_when_called_when_limit_reached_should_fail_with_patch_limit_exceeded() {
+    let mut injector = InjectorPP::new();
+    injector.set_max_active_patches(2);
+
+    injector
+        .try_when_called(injectorpp::func!(fn (is_ready)() -> bool))
+        .unwrap()
+        .will_return_boolean(true);
+    injector
+        .try_when_called(injectorpp::func!(fn (is_busy)() -> bool))
+        .unwrap()
+        .will_return_boolean(true);
+
+    let result = injector.try_when_called(injectorpp::func!(fn (is_idle)() -> bool));
+
+    assert_eq!(
+        result.err(),
+        Some(InjectError::PatchLimitExceeded { limit: 2 })
+    );
+    assert!(is_ready());
+    assert!(is_busy());
+    assert!(!is_idle());
+}
+
+#[test]
+fn test_set_max_active_patches_when_rolled_back_should_free_room() {
+    let mut injector = InjectorPP::new();
+    injector.set_max_active_patches(1);
+
+    let empty = injector.checkpoint();
+    injector
+        .when_called(injectorpp::func!(fn (is_ready)() -> bool))
+        .will_return_boolean(true);
+    injector.rollback(empty);
+
+    injector
+        .when_called(injectorpp::func!(fn (is_idle)() -> bool))
+        .will_return_boolean(true);
+
+    assert!(!is_ready());
+    assert!(is_idle());
+}
+
+#[test]
+#[should_panic(expected = "Cannot install more than 1 fake(s) with this injector")]
+fn test_when_called_when_limit_reached_should_panic() {
+    let mut injector = InjectorPP::new();
+    injector.set_max_active_patches(1);
+
+    injector
+        .when_called(injectorpp::func!(fn (is_ready)() -> bool))
+        .will_return_boolean(true);
+    injector
+        .when_called(injectorpp::func!(fn (is_busy)() -> bool))
+        .will_return_boolean(true);
+}
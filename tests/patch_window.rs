@@ -0,0 +1,235 @@
+#![cfg(target_arch = "x86_64")]
+
+use injectorpp::interface::injector::*;
+
+// Tiny functions whose 5-byte patch is longer than their code. The first one is immediately
+// followed by another function, the next one by a single `int3` as MSVC pads functions, and
+// the others are padded with `int3` and `nop`.
+macro_rules! tiny_functions {
+    ($prefix:literal) => {
+        std::arch::global_asm!(
+            ".text",
+            ".p2align 4",
+            concat!(".globl ", $prefix, "injectorpp_tiny_unpadded"),
+            concat!($prefix, "injectorpp_tiny_unpadded:"),
+            "xor eax, eax",
+            "ret",
+            concat!(".globl ", $prefix, "injectorpp_tiny_neighbor"),
+            concat!($prefix, "injectorpp_tiny_neighbor:"),
+            "mov eax, 7",
+            "ret",
+            ".p2align 4",
+            concat!(".globl ", $prefix, "injectorpp_tiny_short_int3"),
+            concat!($prefix, "injectorpp_tiny_short_int3:"),
+            "xor eax, eax",
+            "ret",
+            "int3",
+            concat!(".globl ", $prefix, "injectorpp_tiny_short_int3_neighbor"),
+            concat!($prefix, "injectorpp_tiny_short_int3_neighbor:"),
+            "mov eax, 13",
+            "ret",
+            ".p2align 4, 0xcc",
+            concat!(".globl ", $prefix, "injectorpp_tiny_int3_padded"),
+            concat!($prefix, "injectorpp_tiny_int3_padded:"),
+            "xor eax, eax",
+            "ret",
+            ".p2align 4, 0xcc",
+            concat!(".globl ", $prefix, "injectorpp_tiny_nop_padded"),
+            concat!($prefix, "injectorpp_tiny_nop_padded:"),
+            "xor eax, eax",
+            "ret",
+            ".p2align 4",
+            concat!(".globl ", $prefix, "injectorpp_tiny_last"),
+            concat!($prefix, "injectorpp_tiny_last:"),
+            "mov eax, 9",
+            "ret",
+            ".p2align 4",
+            concat!(".globl ", $prefix, "injectorpp_split_prologue"),
+            concat!($prefix, "injectorpp_split_prologue:"),
+            "push rbx",
+            "movabs rax, 5",
+            "pop rbx",
+            "ret",
+            ".p2align 4",
+            concat!(".globl ", $prefix, "injectorpp_trap_tail"),
+            concat!($prefix, "injectorpp_trap_tail:"),
+            "xor eax, eax",
+            "ret",
+            "ud2",
+            "ud2",
+            "ud2",
+            "ud2",
+            "ud2",
+            "ud2",
+            ".p2align 4",
+            concat!(".globl ", $prefix, "injectorpp_trap_tail_neighbor"),
+            concat!($prefix, "injectorpp_trap_tail_neighbor:"),
+            "mov eax, 11",
+            "ret",
+        );
+    };
+}
+
+#[cfg(target_os = "macos")]
+tiny_functions!("_");
+
+#[cfg(not(target_os = "macos"))]
+tiny_functions!("");
+
+extern "C" {
+    fn injectorpp_tiny_unpadded() -> u32;
+    fn injectorpp_tiny_neighbor() -> u32;
+    fn injectorpp_tiny_short_int3() -> u32;
+    fn injectorpp_tiny_short_int3_neighbor() -> u32;
+    fn injectorpp_tiny_int3_padded() -> u32;
+    fn injectorpp_tiny_nop_padded() -> u32;
+    fn injectorpp_tiny_last() -> u32;
+    fn injectorpp_split_prologue() -> u32;
+    fn injectorpp_trap_tail() -> u32;
+    fn injectorpp_trap_tail_neighbor() -> u32;
+}
+
+unsafe extern "C" fn fake_tiny() -> u32 {
+    42
+}
+
+#[test]
+fn test_try_when_called_when_patch_overwrites_next_function_should_fail() {
+    let mut injector = InjectorPP::new();
+
+    let result = injector.try_when_called(injectorpp::func!(
+        unsafe{} extern "C" fn (injectorpp_tiny_unpadded)() -> u32
+    ));
+
+    assert!(matches!(
+        result.err(),
+        Some(InjectError::PatchWindowTooSmall {
+            available: 3,
+            required: 5,
+            ..
+        })
+    ));
+    assert_eq!(unsafe { injectorpp_tiny_neighbor() }, 7);
+}
+
+#[test]
+#[should_panic(expected = "only leaves 3 byte(s) to patch but 5 are needed")]
+fn test_when_called_when_patch_overwrites_next_function_should_panic() {
+    let mut injector = InjectorPP::new();
+    injector
+        .when_called(injectorpp::func!(
+            unsafe{} extern "C" fn (injectorpp_tiny_unpadded)() -> u32
+        ))
+        .will_execute_raw(injectorpp::func!(unsafe{} extern "C" fn (fake_tiny)() -> u32));
+}
+
+#[test]
+fn test_try_when_called_when_int3_padding_is_shorter_than_patch_should_fail() {
+    let mut injector = InjectorPP::new();
+
+    let result = injector.try_when_called(injectorpp::func!(
+        unsafe{} extern "C" fn (injectorpp_tiny_short_int3)() -> u32
+    ));
+
+    assert!(matches!(
+        result.err(),
+        Some(InjectError::PatchWindowTooSmall {
+            available: 3,
+            required: 5,
+            ..
+        })
+    ));
+    assert_eq!(unsafe { injectorpp_tiny_short_int3_neighbor() }, 13);
+}
+
+#[test]
+fn test_when_called_when_tiny_function_is_padded_should_fake() {
+    {
+        let mut injector = InjectorPP::new();
+        injector
+            .when_called(injectorpp::func!(
+                unsafe{} extern "C" fn (injectorpp_tiny_int3_padded)() -> u32
+            ))
+            .will_execute_raw(injectorpp::func!(unsafe{} extern "C" fn (fake_tiny)() -> u32));
+        injector
+            .when_called(injectorpp::func!(
+                unsafe{} extern "C" fn (injectorpp_tiny_nop_padded)() -> u32
+            ))
+            .will_execute_raw(injectorpp::func!(unsafe{} extern "C" fn (fake_tiny)() -> u32));
+
+        assert_eq!(unsafe { injectorpp_tiny_int3_padded() }, 42);
+        assert_eq!(unsafe { injectorpp_tiny_nop_padded() }, 42);
+        assert_eq!(unsafe { injectorpp_tiny_last() }, 9);
+    }
+
+    assert_eq!(unsafe { injectorpp_tiny_int3_padded() }, 0);
+    assert_eq!(unsafe { injectorpp_tiny_nop_padded() }, 0);
+}
+
+#[test]
+fn test_when_called_when_patch_splits_instruction_should_pad_it_with_nops() {
+    // push rbx; movabs rax, 5; pop rbx; ret
+    let original = [0x53, 0x48, 0xB8, 5, 0, 0, 0, 0, 0, 0, 0, 0x5B, 0xC3];
+    let code = injectorpp_split_prologue as *const u8;
+
+    {
+        let mut injector = InjectorPP::new();
+        injector
+            .when_called(injectorpp::func!(
+                unsafe{} extern "C" fn (injectorpp_split_prologue)() -> u32
+            ))
+            .will_execute_raw(injectorpp::func!(unsafe{} extern "C" fn (fake_tiny)() -> u32));
+
+        let patched = unsafe { std::slice::from_raw_parts(code, original.len()) };
+        if patched[0] == 0xE9 {
+            // A 5-byte `jmp rel32` cuts into the `movabs`, which is replaced by nops.
+            assert_eq!(patched[5..11], [0x90; 6]);
+            assert_eq!(patched[11..], original[11..]);
+        } else {
+            // A 12-byte absolute jump ends right before the `pop rbx`.
+            assert_eq!(patched[12..], original[12..]);
+        }
+        assert_eq!(unsafe { injectorpp_split_prologue() }, 42);
+    }
+
+    let restored = unsafe { std::slice::from_raw_parts(code, original.len()) };
+    assert_eq!(restored, original);
+    assert_eq!(unsafe { injectorpp_split_prologue() }, 5);
+}
+
+#[test]
+fn test_assume_patchable_when_function_ends_with_traps_should_fake() {
+    // The `ud2`s after the `ret` make the function look 3 bytes long.
+    assert!(matches!(
+        InjectorPP::new().try_when_called(injectorpp::func!(
+            unsafe{} extern "C" fn (injectorpp_trap_tail)() -> u32
+        )),
+        Err(InjectError::PatchWindowTooSmall { available: 3, .. })
+    ));
+
+    {
+        let mut injector = InjectorPP::new();
+        unsafe {
+            injector
+                .when_called_unchecked(injectorpp::func_unchecked!(injectorpp_trap_tail))
+                .assume_patchable()
+                .will_execute_raw_unchecked(injectorpp::func_unchecked!(fake_tiny));
+        }
+
+        assert_eq!(unsafe { injectorpp_trap_tail() }, 42);
+        assert_eq!(unsafe { injectorpp_trap_tail_neighbor() }, 11);
+    }
+
+    assert_eq!(unsafe { injectorpp_trap_tail() }, 0);
+}
+
+#[test]
+#[should_panic(expected = "only leaves 3 byte(s) to patch but 5 are needed")]
+fn test_when_called_unchecked_when_function_ends_with_traps_should_still_check_window() {
+    let mut injector = InjectorPP::new();
+    unsafe {
+        injector
+            .when_called_unchecked(injectorpp::func_unchecked!(injectorpp_trap_tail))
+            .will_execute_raw_unchecked(injectorpp::func_unchecked!(fake_tiny));
+    }
+}
@@ -0,0 +1,115 @@
+use injectorpp::prelude::*;
+use injectorpp_native_fixture::injectorpp_native_format_length;
+use std::os::raw::{c_char, c_int};
+use std::sync::atomic::{AtomicU32, Ordering};
+use std::sync::Arc;
+
+#[inline(never)]
+fn lookup_port(service: &str) -> Option<u16> {
+    std::hint::black_box(service).parse().ok()
+}
+
+#[inline(never)]
+fn is_cached(key: u32) -> bool {
+    std::hint::black_box(key) == u32::MAX
+}
+
+async fn fetch_version() -> u32 {
+    1
+}
+
+trait Store {
+    fn size(&self) -> usize;
+}
+
+struct DiskStore;
+
+impl Store for DiskStore {
+    #[inline(never)]
+    fn size(&self) -> usize {
+        std::hint::black_box(0)
+    }
+}
+
+#[test]
+fn test_prelude_when_only_import_should_fake_and_verify() {
+    let sequence = Sequence::new();
+    let mut injector = InjectorPP::new();
+
+    let handle: MockHandle = injector
+        .when_called(func!(fn (lookup_port)(&str) -> Option<u16>))
+        .in_sequence(&sequence, "lookup")
+        .will_execute(fake!(
+            func_type: fn(service: &str) -> Option<u16>,
+            when: service == "http",
+            returns: Some(80),
+            times: 1
+        ));
+
+    let checkpoint: Checkpoint = injector.checkpoint();
+    let result: Result<WhenCalledBuilder<'_, _>, InjectError> =
+        injector.try_when_called(func!(fn (is_cached)(u32) -> bool));
+    result.unwrap().will_execute(|key: u32| key > 10);
+
+    let _expectations = Expectations::new(&sequence).then("lookup", 1);
+
+    assert_eq!(lookup_port("http"), Some(80));
+    assert!(is_cached(11));
+    assert!(injector.is_enabled(handle));
+
+    injector.rollback(checkpoint);
+    assert!(!is_cached(11));
+}
+
+#[tokio::test]
+async fn test_prelude_when_only_import_should_fake_async_function() {
+    let mut injector = InjectorPP::new();
+    injector
+        .when_called_async(async_func!(fetch_version(), u32))
+        .will_return_async(async_return!(7, u32));
+
+    assert_eq!(fetch_version().await, 7);
+}
+
+#[test]
+fn test_prelude_when_only_import_should_fake_trait_method() {
+    let mut injector = InjectorPP::new();
+    injector
+        .when_called(trait_method!(fn (<DiskStore as Store>::size)(&self) -> usize))
+        .will_execute(|_: &DiskStore| 42usize);
+
+    let store: &dyn Store = &DiskStore;
+    assert_eq!(store.size(), 42);
+}
+
+#[test]
+fn test_prelude_when_only_import_should_fake_variadic_function() {
+    let mut injector = InjectorPP::new();
+    injector
+        .when_called(variadic_func!(
+            unsafe{} extern "C" fn (injectorpp_native_format_length)(*const c_char, ...) -> c_int
+        ))
+        .will_return_i32(3);
+
+    assert_eq!(
+        unsafe { injectorpp_native_format_length(c"%d".as_ptr(), 10) },
+        3
+    );
+}
+
+#[test]
+fn test_prelude_when_only_import_should_fake_with_capturing_closure() {
+    let offset = Arc::new(AtomicU32::new(100));
+    let captured = offset.clone();
+
+    let mut injector = InjectorPP::new();
+    injector
+        .when_called(func!(fn (is_cached)(u32) -> bool))
+        .will_execute(closure_capturing!(
+            move |key| key == captured.load(Ordering::SeqCst),
+            fn(u32) -> bool
+        ));
+
+    assert!(is_cached(100));
+    assert!(!is_cached(7));
+}
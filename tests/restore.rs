@@ -0,0 +1,109 @@
+use injectorpp::interface::injector::*;
+use std::cell::Cell;
+use std::rc::Rc;
+use std::sync::Arc;
+
+#[inline(never)]
+fn read_config(key: u32) -> u32 {
+    std::hint::black_box(key)
+}
+
+#[inline(never)]
+fn is_online() -> bool {
+    std::hint::black_box(false)
+}
+
+#[test]
+fn test_restore_when_called_should_call_original_right_away() {
+    let mut injector = InjectorPP::new();
+    let handle = injector
+        .when_called(injectorpp::func!(fn (read_config)(u32) -> u32))
+        .will_execute(|key: u32| key * 100);
+
+    assert_eq!(read_config(2), 200);
+
+    injector.restore(handle);
+
+    assert_eq!(read_config(2), 2);
+}
+
+#[test]
+fn test_restore_when_called_twice_should_do_nothing() {
+    let mut injector = InjectorPP::new();
+    let handle = injector
+        .when_called(injectorpp::func!(fn (is_online)() -> bool))
+        .will_return_boolean(true);
+
+    injector.restore(handle);
+    injector.restore(handle);
+
+    assert!(!is_online());
+}
+
+#[test]
+fn test_restore_when_other_fakes_installed_should_keep_them() {
+    let mut injector = InjectorPP::new();
+    let config = injector
+        .when_called(injectorpp::func!(fn (read_config)(u32) -> u32))
+        .will_execute(|key: u32| key + 1);
+    injector
+        .when_called(injectorpp::func!(fn (is_online)() -> bool))
+        .will_return_boolean(true);
+
+    injector.restore(config);
+
+    assert_eq!(read_config(2), 2);
+    assert!(is_online());
+}
+
+#[test]
+fn test_restore_should_run_on_restore_callbacks() {
+    let restored = Rc::new(Cell::new(false));
+    let flag = restored.clone();
+
+    let mut injector = InjectorPP::new();
+    let handle = injector
+        .when_called(injectorpp::func!(fn (is_online)() -> bool))
+        .on_restore(move |_| flag.set(true))
+        .will_return_boolean(true);
+
+    injector.restore(handle);
+
+    assert!(restored.get());
+}
+
+#[test]
+fn test_restore_should_drop_the_closure_of_the_fake() {
+    let state = Arc::new(5);
+    let captured = state.clone();
+
+    let mut injector = InjectorPP::new();
+    for _ in 0..3 {
+        let captured = captured.clone();
+        let handle = injector
+            .when_called(injectorpp::func!(fn (read_config)(u32) -> u32))
+            .count_calls()
+            .will_execute(move |key: u32| key + *captured);
+        assert_eq!(read_config(1), 6);
+
+        injector.restore(handle);
+    }
+    drop(captured);
+
+    // Only the test holds the state, the restored fakes kept nothing alive.
+    assert_eq!(Arc::strong_count(&state), 1);
+}
+
+#[test]
+#[should_panic(expected = "before the later fakes of the same function")]
+fn test_restore_when_same_function_faked_later_should_panic() {
+    let mut injector = InjectorPP::new();
+    let first = injector
+        .when_called(injectorpp::func!(fn (is_online)() -> bool))
+        .will_return_boolean(true);
+    injector
+        .when_called(injectorpp::func!(fn (is_online)() -> bool))
+        .will_return_boolean(false);
+
+    injector.restore(first);
+}
@@ -0,0 +1,90 @@
+#![cfg(all(
+    target_os = "linux",
+    any(target_arch = "x86_64", target_arch = "aarch64")
+))]
+
+use injectorpp::interface::injector::*;
+use std::panic::{catch_unwind, AssertUnwindSafe};
+
+/// `fn() -> bool` returning false, followed by padding so the patch stays inside the page.
+#[cfg(target_arch = "x86_64")]
+const RETURN_FALSE: &[u8] = &[
+    0xB8, 0x00, 0x00, 0x00, 0x00, // mov eax, 0
+    0xC3, // ret
+    0x90, 0x90, 0x90, 0x90, 0x90, 0x90, 0x90, 0x90, 0x90, 0x90, // nop
+];
+
+#[cfg(target_arch = "aarch64")]
+const RETURN_FALSE: &[u8] = &[
+    0x00, 0x00, 0x80, 0x52, // movz w0, #0
+    0xC0, 0x03, 0x5F, 0xD6, // ret
+    0x1F, 0x20, 0x03, 0xD5, // nop
+    0x1F, 0x20, 0x03, 0xD5, // nop
+    0x1F, 0x20, 0x03, 0xD5, // nop
+    0x1F, 0x20, 0x03, 0xD5, // nop
+];
+
+/// Maps a page holding `RETURN_FALSE` and returns it with its size.
+fn map_return_false() -> (*mut u8, usize) {
+    unsafe {
+        let page_size = libc::sysconf(libc::_SC_PAGESIZE) as usize;
+        let page = libc::mmap(
+            std::ptr::null_mut(),
+            page_size,
+            libc::PROT_READ | libc::PROT_WRITE | libc::PROT_EXEC,
+            libc::MAP_PRIVATE | libc::MAP_ANONYMOUS,
+            -1,
+            0,
+        );
+        assert_ne!(page, libc::MAP_FAILED);
+
+        std::ptr::copy_nonoverlapping(RETURN_FALSE.as_ptr(), page as *mut u8, RETURN_FALSE.len());
+        (page as *mut u8, page_size)
+    }
+}
+
+#[test]
+fn test_take_restore_failures_when_code_unmapped_should_report_failure_without_aborting() {
+    let (page, page_size) = map_return_false();
+    let address = unsafe { FuncAddress::from_raw(page as *const ()) }.unwrap();
+
+    let result = catch_unwind(AssertUnwindSafe(|| {
+        let mut injector = InjectorPP::new();
+        injector
+            .when_called(address.with_signature::<fn() -> bool>())
+            .will_return_boolean(true);
+
+        let faked: fn() -> bool = unsafe { std::mem::transmute(page) };
+        assert!(faked());
+
+        // Restoring needs to write the unmapped code again, while the test unwinds.
+        unsafe {
+            libc::munmap(page as *mut libc::c_void, page_size);
+        }
+        panic!("The test failed");
+    }));
+    assert!(result.is_err());
+
+    let failures = InjectorPP::take_restore_failures();
+    let failure = failures
+        .iter()
+        .find(|failure| matches!(failure, Failure::Restore { function, .. } if *function == page as usize))
+        .expect("The failure to restore the unmapped function should be reported");
+
+    assert!(matches!(
+        failure,
+        Failure::Restore {
+            error: InjectError::ProtectionFailed {
+                call: "mprotect",
+                ..
+            },
+            ..
+        }
+    ));
+    assert!(failure
+        .to_json()
+        .starts_with(r#"{"kind":"restore","function":"#));
+    assert!(InjectorPP::take_restore_failures()
+        .iter()
+        .all(|failure| !matches!(failure, Failure::Restore { function, .. } if *function == page as usize)));
+}
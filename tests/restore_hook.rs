@@ -0,0 +1,105 @@
+use injectorpp::interface::injector::*;
+use std::sync::{Arc, Mutex};
+
+#[inline(never)]
+fn read_config(key: u32) -> u32 {
+    std::hint::black_box(key)
+}
+
+#[inline(never)]
+fn is_online() -> bool {
+    std::hint::black_box(false)
+}
+
+#[inline(never)]
+fn retries() -> u8 {
+    std::hint::black_box(3)
+}
+
+// The hook and the count are global, so every test here measures them while its injector,
+// which keeps any other from being created, is alive.
+
+#[test]
+fn test_on_restore_when_fakes_rolled_back_should_report_each_and_count_down_to_zero() {
+    let mut injector = InjectorPP::new();
+    let before = InjectorPP::live_patch_count();
+
+    let restored = Arc::new(Mutex::new(Vec::new()));
+    let recorder = restored.clone();
+    InjectorPP::on_restore(move |info| recorder.lock().unwrap().push(*info));
+
+    let checkpoint = injector.checkpoint();
+    injector
+        .when_called(injectorpp::func!(fn (read_config)(u32) -> u32))
+        .will_execute(|key: u32| key * 100);
+    injector
+        .when_called(injectorpp::func!(fn (is_online)() -> bool))
+        .will_return_boolean(true);
+    injector
+        .when_called(injectorpp::func!(fn (retries)() -> u8))
+        .will_return(0u8);
+
+    assert_eq!(InjectorPP::live_patch_count(), before + 3);
+
+    injector.rollback(checkpoint);
+    InjectorPP::clear_on_restore();
+
+    assert_eq!(InjectorPP::live_patch_count(), before);
+
+    let restored = restored.lock().unwrap();
+    assert_eq!(restored.len(), 3);
+
+    let mut functions: Vec<_> = restored.iter().map(|info| info.function).collect();
+    functions.sort();
+    let mut expected = vec![
+        read_config as fn(u32) -> u32 as usize,
+        is_online as fn() -> bool as usize,
+        retries as fn() -> u8 as usize,
+    ];
+    expected.sort();
+    assert_eq!(functions, expected);
+
+    assert!(restored
+        .iter()
+        .all(|info| info.jit_address != 0 && info.jit_size > 0));
+}
+
+#[test]
+fn test_on_restore_when_fake_restored_should_report_its_jit_memory() {
+    let mut injector = InjectorPP::new();
+
+    let restored = Arc::new(Mutex::new(Vec::new()));
+    let recorder = restored.clone();
+    InjectorPP::on_restore(move |info| recorder.lock().unwrap().push(*info));
+
+    let handle = injector
+        .when_called(injectorpp::func!(fn (is_online)() -> bool))
+        .will_return_boolean(true);
+    let debug = injector.debug_info(handle);
+
+    injector.restore(handle);
+    InjectorPP::clear_on_restore();
+
+    let restored = restored.lock().unwrap();
+    assert_eq!(restored.len(), 1);
+    assert_eq!(restored[0].function, debug.address);
+    assert_eq!(restored[0].jit_address, debug.jit_address);
+    assert_eq!(restored[0].jit_size, debug.jit_bytes.len());
+}
+
+#[test]
+fn test_live_patch_count_when_fake_disabled_should_still_count_it() {
+    let mut injector = InjectorPP::new();
+    let before = InjectorPP::live_patch_count();
+
+    let handle = injector
+        .when_called(injectorpp::func!(fn (retries)() -> u8))
+        .will_return(0u8);
+    injector.disable(handle);
+
+    assert_eq!(InjectorPP::live_patch_count(), before + 1);
+
+    injector.restore(handle);
+
+    assert_eq!(InjectorPP::live_patch_count(), before);
+}
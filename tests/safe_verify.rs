@@ -0,0 +1,100 @@
+#![cfg(all(feature = "safe-verify", any(target_os = "linux", target_os = "macos")))]
+
+use injectorpp::interface::injector::*;
+
+#[inline(never)]
+fn load_level() -> u32 {
+    std::hint::black_box(1)
+}
+
+/// Bytes that are not code: branching to them raises `SIGSEGV`, since they are mapped without
+/// execute permission.
+static NOT_CODE: [u8; 16] = [0; 16];
+
+/// An invalid instruction in executable memory: `ud2` on x86_64, and zeros elsewhere, which
+/// are `udf #0` on AArch64 and the defined illegal instruction on RISC-V.
+fn invalid_instruction() -> *const () {
+    #[cfg(target_arch = "x86_64")]
+    let code: &[u8] = &[0x0F, 0x0B];
+    #[cfg(not(target_arch = "x86_64"))]
+    let code: &[u8] = &[0x00, 0x00, 0x00, 0x00];
+
+    unsafe {
+        let page = libc::mmap(
+            std::ptr::null_mut(),
+            4096,
+            libc::PROT_READ | libc::PROT_WRITE,
+            libc::MAP_PRIVATE | libc::MAP_ANONYMOUS,
+            -1,
+            0,
+        );
+        assert_ne!(page, libc::MAP_FAILED);
+        std::ptr::copy_nonoverlapping(code.as_ptr(), page as *mut u8, code.len());
+        assert_eq!(
+            libc::mprotect(page, 4096, libc::PROT_READ | libc::PROT_EXEC),
+            0
+        );
+        page as *const ()
+    }
+}
+
+#[test]
+fn test_safe_verify_when_fake_branches_to_data_should_return_fault_with_debug_info() {
+    let mut injector = InjectorPP::new();
+    let handle = unsafe {
+        injector
+            .when_called_unchecked(injectorpp::func!(fn (load_level)() -> u32))
+            .will_execute_raw_unchecked(FuncPtr::new(NOT_CODE.as_ptr() as *const (), ""))
+    };
+
+    let error = injector.safe_verify(handle, load_level).unwrap_err();
+
+    let InjectError::PatchFaulted { signal, debug } = &error else {
+        panic!("unexpected error {error:?}");
+    };
+    assert!([libc::SIGSEGV, libc::SIGBUS].contains(signal));
+    assert_eq!(**debug, injector.debug_info(handle));
+    assert!(error.to_string().starts_with(&format!(
+        "Calling the fake of the function at {:#x} raised signal {signal}",
+        load_level as fn() -> u32 as usize
+    )));
+}
+
+#[test]
+fn test_safe_verify_when_fake_runs_invalid_instruction_should_return_fault() {
+    let mut injector = InjectorPP::new();
+    let handle = unsafe {
+        injector
+            .when_called_unchecked(injectorpp::func!(fn (load_level)() -> u32))
+            .will_execute_raw_unchecked(FuncPtr::new(invalid_instruction(), ""))
+    };
+
+    let result = injector.safe_verify(handle, load_level);
+
+    assert!(matches!(
+        result,
+        Err(InjectError::PatchFaulted { signal, .. }) if signal == libc::SIGILL
+    ));
+}
+
+#[test]
+fn test_safe_verify_when_fake_works_should_return_result_of_call() {
+    let mut injector = InjectorPP::new();
+    let handle = injector
+        .when_called(injectorpp::func!(fn (load_level)() -> u32))
+        .will_execute(|| -> u32 { 7 });
+
+    assert_eq!(injector.safe_verify(handle, load_level), Ok(7));
+    assert_eq!(injector.safe_verify(handle, || load_level() + 1), Ok(8));
+}
+
+#[test]
+#[should_panic(expected = "level too low")]
+fn test_safe_verify_when_call_panics_should_resume_panic() {
+    let mut injector = InjectorPP::new();
+    let handle = injector
+        .when_called(injectorpp::func!(fn (load_level)() -> u32))
+        .will_execute(|| -> u32 { 0 });
+
+    let _ = injector.safe_verify(handle, || assert!(load_level() > 0, "level too low"));
+}
@@ -0,0 +1,62 @@
+use injectorpp::interface::injector::*;
+use std::panic::{catch_unwind, AssertUnwindSafe};
+
+#[inline(never)]
+fn fetch_limit() -> u64 {
+    std::hint::black_box(10)
+}
+
+#[inline(never)]
+fn is_online() -> bool {
+    std::hint::black_box(false)
+}
+
+#[test]
+fn test_scoped_should_fake_inside_closure_and_restore_after() {
+    let limit = InjectorPP::scoped(|injector| {
+        injector
+            .when_called(injectorpp::func!(fn (fetch_limit)() -> u64))
+            .will_return_u64(99);
+        injector
+            .when_called(injectorpp::func!(fn (is_online)() -> bool))
+            .will_return_boolean(true);
+
+        assert!(is_online());
+        fetch_limit()
+    });
+
+    assert_eq!(limit, 99);
+    assert_eq!(fetch_limit(), 10);
+    assert!(!is_online());
+}
+
+#[test]
+fn test_scoped_when_closure_panics_should_restore_before_catch_unwind_returns() {
+    let result = catch_unwind(AssertUnwindSafe(|| {
+        InjectorPP::scoped(|injector| {
+            injector
+                .when_called(injectorpp::func!(fn (fetch_limit)() -> u64))
+                .will_return_u64(99);
+
+            assert_eq!(fetch_limit(), 99);
+            panic!("failure inside the scope");
+        })
+    }));
+
+    let message = result.unwrap_err();
+    assert_eq!(
+        *message.downcast_ref::<&str>().unwrap(),
+        "failure inside the scope"
+    );
+    assert_eq!(fetch_limit(), 10);
+
+    // The injector lock was released, so a new injector can be created.
+    InjectorPP::scoped(|injector| {
+        injector
+            .when_called(injectorpp::func!(fn (is_online)() -> bool))
+            .will_return_boolean(true);
+
+        assert!(is_online());
+    });
+    assert!(!is_online());
+}
@@ -0,0 +1,12 @@
+use injectorpp::interface::injector::*;
+
+#[test]
+fn test_self_test_should_return_ok() {
+    assert_eq!(InjectorPP::self_test(), Ok(()));
+}
+
+#[test]
+fn test_self_test_when_run_twice_should_return_ok() {
+    assert_eq!(InjectorPP::self_test(), Ok(()));
+    assert_eq!(InjectorPP::self_test(), Ok(()));
+}
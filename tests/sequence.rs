@@ -0,0 +1,104 @@
+use injectorpp::interface::injector::*;
+
+#[inline(never)]
+fn open_device(id: u32) -> bool {
+    std::hint::black_box(id) == u32::MAX
+}
+
+#[inline(never)]
+fn read_chunk(buffer: &mut Vec<u8>, len: usize) -> usize {
+    buffer.truncate(std::hint::black_box(len));
+    0
+}
+
+#[inline(never)]
+fn close_device(id: u32) -> bool {
+    std::hint::black_box(id) == u32::MAX - 1
+}
+
+fn run_protocol(reads: usize) -> Vec<u8> {
+    let mut buffer = Vec::new();
+
+    assert!(open_device(7));
+    for _ in 0..reads {
+        assert_eq!(read_chunk(&mut buffer, 2), 2);
+    }
+    assert!(close_device(7));
+
+    buffer
+}
+
+fn fake_protocol(injector: &mut InjectorPP, sequence: &Sequence) {
+    injector
+        .when_called(injectorpp::func!(fn (open_device)(u32) -> bool))
+        .in_sequence(sequence, "open")
+        .will_return_boolean(true);
+
+    injector
+        .when_called(injectorpp::func!(fn (read_chunk)(&mut Vec<u8>, usize) -> usize))
+        .in_sequence(sequence, "read")
+        .will_execute_raw(injectorpp::closure!(
+            |buffer: &mut Vec<u8>, len: usize| -> usize {
+                buffer.extend(std::iter::repeat_n(0xAB, len));
+                len
+            },
+            fn(&mut Vec<u8>, usize) -> usize
+        ));
+
+    injector
+        .when_called(injectorpp::func!(fn (close_device)(u32) -> bool))
+        .in_sequence(sequence, "close")
+        .will_return_boolean(true);
+}
+
+#[test]
+fn test_expectations_when_protocol_followed_should_pass() {
+    let sequence = Sequence::new();
+    let mut injector = InjectorPP::new();
+    fake_protocol(&mut injector, &sequence);
+
+    let _expectations = Expectations::new(&sequence)
+        .then("open", 1)
+        .then("read", 3)
+        .then("close", 1);
+
+    let buffer = run_protocol(3);
+
+    assert_eq!(buffer, vec![0xAB; 6]);
+    assert_eq!(
+        sequence.calls(),
+        vec!["open", "read", "read", "read", "close"]
+    );
+}
+
+#[test]
+#[should_panic(
+    expected = "first difference at step 2\n  expected: open x1, read x3, close x1\n  actual:   open x1, read x2, close x1"
+)]
+fn test_expectations_when_read_missing_should_panic() {
+    let sequence = Sequence::new();
+    let mut injector = InjectorPP::new();
+    fake_protocol(&mut injector, &sequence);
+
+    let _expectations = Expectations::new(&sequence)
+        .then("open", 1)
+        .then("read", 3)
+        .then("close", 1);
+
+    run_protocol(2);
+}
+
+#[test]
+fn test_in_sequence_when_injector_dropped_should_stop_recording() {
+    let sequence = Sequence::new();
+
+    {
+        let mut injector = InjectorPP::new();
+        fake_protocol(&mut injector, &sequence);
+
+        run_protocol(1);
+    }
+
+    assert!(!open_device(7));
+    assert_eq!(sequence.calls(), vec!["open", "read", "close"]);
+}
@@ -0,0 +1,157 @@
+#![cfg(target_arch = "aarch64")]
+
+use injectorpp::interface::injector::*;
+
+// Leaf functions of exactly 4, 8 and 12 bytes, each immediately followed by another function
+// that a 12-byte patch of the shorter ones would overwrite.
+macro_rules! short_functions {
+    ($prefix:literal) => {
+        std::arch::global_asm!(
+            ".text",
+            ".p2align 2",
+            concat!(".globl ", $prefix, "injectorpp_leaf_4"),
+            concat!($prefix, "injectorpp_leaf_4:"),
+            "ret",
+            concat!(".globl ", $prefix, "injectorpp_leaf_4_neighbor"),
+            concat!($prefix, "injectorpp_leaf_4_neighbor:"),
+            "mov w0, #7",
+            "ret",
+            ".p2align 2",
+            concat!(".globl ", $prefix, "injectorpp_leaf_8"),
+            concat!($prefix, "injectorpp_leaf_8:"),
+            "mov w0, #1",
+            "ret",
+            concat!(".globl ", $prefix, "injectorpp_leaf_8_neighbor"),
+            concat!($prefix, "injectorpp_leaf_8_neighbor:"),
+            "mov w0, #8",
+            "ret",
+            ".p2align 2",
+            concat!(".globl ", $prefix, "injectorpp_leaf_12"),
+            concat!($prefix, "injectorpp_leaf_12:"),
+            "mov w0, #1",
+            "add w0, w0, #1",
+            "ret",
+            concat!(".globl ", $prefix, "injectorpp_leaf_12_neighbor"),
+            concat!($prefix, "injectorpp_leaf_12_neighbor:"),
+            "mov w0, #9",
+            "ret",
+        );
+    };
+}
+
+#[cfg(target_os = "macos")]
+short_functions!("_");
+
+#[cfg(not(target_os = "macos"))]
+short_functions!("");
+
+extern "C" {
+    fn injectorpp_leaf_4(value: u32) -> u32;
+    fn injectorpp_leaf_4_neighbor() -> u32;
+    fn injectorpp_leaf_8() -> u32;
+    fn injectorpp_leaf_8_neighbor() -> u32;
+    fn injectorpp_leaf_12() -> u32;
+    fn injectorpp_leaf_12_neighbor() -> u32;
+}
+
+unsafe extern "C" fn fake_leaf_4(value: u32) -> u32 {
+    value * 100
+}
+
+#[test]
+fn test_four_byte_function_should_only_replace_its_ret() {
+    assert_eq!(unsafe { injectorpp_leaf_4(3) }, 3);
+
+    {
+        let mut injector = InjectorPP::new();
+        let handle = injector
+            .when_called(injectorpp::func!(
+                unsafe{} extern "C" fn (injectorpp_leaf_4)(u32) -> u32
+            ))
+            .will_execute_raw(injectorpp::func!(
+                unsafe{} extern "C" fn (fake_leaf_4)(u32) -> u32
+            ));
+
+        assert_eq!(unsafe { injectorpp_leaf_4(3) }, 300);
+        assert_eq!(unsafe { injectorpp_leaf_4_neighbor() }, 7);
+        assert_eq!(injector.debug_info(handle).patch_bytes.len(), 4);
+    }
+
+    assert_eq!(unsafe { injectorpp_leaf_4(3) }, 3);
+    assert_eq!(unsafe { injectorpp_leaf_4_neighbor() }, 7);
+}
+
+#[test]
+fn test_eight_byte_function_should_only_replace_its_first_instruction() {
+    assert_eq!(unsafe { injectorpp_leaf_8() }, 1);
+
+    {
+        let mut injector = InjectorPP::new();
+        let handle = injector
+            .when_called(injectorpp::func!(unsafe{} extern "C" fn (injectorpp_leaf_8)() -> u32))
+            .will_return_boolean(false);
+
+        assert_eq!(unsafe { injectorpp_leaf_8() }, 0);
+        assert_eq!(unsafe { injectorpp_leaf_8_neighbor() }, 8);
+
+        let debug = injector.debug_info(handle);
+        assert_eq!(debug.patch_bytes.len(), 4);
+        assert_eq!(debug.branch_kind, BranchKind::Near);
+    }
+
+    assert_eq!(unsafe { injectorpp_leaf_8() }, 1);
+    assert_eq!(unsafe { injectorpp_leaf_8_neighbor() }, 8);
+}
+
+#[test]
+fn test_eight_byte_function_when_spied_should_call_relocated_original() {
+    let mut injector = InjectorPP::new();
+    let spy = injector
+        .when_called(injectorpp::func!(unsafe{} extern "C" fn (injectorpp_leaf_8)() -> u32))
+        .spy()
+        .capture_return();
+
+    assert_eq!(unsafe { injectorpp_leaf_8() }, 1);
+    assert_eq!(unsafe { injectorpp_leaf_8_neighbor() }, 8);
+    assert_eq!(spy.returns(), vec![1]);
+}
+
+#[test]
+fn test_twelve_byte_function_should_keep_full_patch() {
+    assert_eq!(unsafe { injectorpp_leaf_12() }, 2);
+
+    {
+        let mut injector = InjectorPP::new();
+        let handle = injector
+            .when_called(injectorpp::func!(unsafe{} extern "C" fn (injectorpp_leaf_12)() -> u32))
+            .will_return_boolean(false);
+
+        assert_eq!(unsafe { injectorpp_leaf_12() }, 0);
+        assert_eq!(unsafe { injectorpp_leaf_12_neighbor() }, 9);
+        assert_eq!(injector.debug_info(handle).patch_bytes.len(), 12);
+    }
+
+    assert_eq!(unsafe { injectorpp_leaf_12() }, 2);
+    assert_eq!(unsafe { injectorpp_leaf_12_neighbor() }, 9);
+}
+
+#[test]
+fn test_short_function_when_jit_memory_out_of_branch_range_should_fail() {
+    let hint =
+        (injectorpp_leaf_8 as unsafe extern "C" fn() -> u32 as usize).wrapping_add(0x4000_0000);
+    let options = InjectorOptions::new().jit_alloc_strategy(JitAllocStrategy::Hint(hint));
+
+    let mut injector = InjectorPP::new_with_options(options);
+    let result = injector.try_when_called(injectorpp::func!(
+        unsafe{} extern "C" fn (injectorpp_leaf_8)() -> u32
+    ));
+
+    assert!(matches!(
+        result.err(),
+        Some(InjectError::PatchWindowTooSmall {
+            available: 8,
+            required: 12,
+            ..
+        })
+    ));
+}
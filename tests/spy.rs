@@ -0,0 +1,96 @@
+use injectorpp::interface::injector::*;
+
+#[inline(never)]
+fn foo() -> usize {
+    std::hint::black_box(6)
+}
+
+#[cfg(target_arch = "x86_64")]
+#[inline(never)]
+fn join(first: &str, second: &str) -> String {
+    format!("{}-{}", std::hint::black_box(first), second)
+}
+
+#[inline(never)]
+fn split(value: u64) -> (u64, u64) {
+    (std::hint::black_box(value) >> 32, value & 0xFFFF_FFFF)
+}
+
+#[test]
+fn test_spy_capture_return_should_record_original_result() {
+    let mut injector = InjectorPP::new();
+    let spy = injector
+        .when_called(injectorpp::func!(fn (foo)() -> usize))
+        .spy()
+        .capture_return();
+
+    assert_eq!(foo(), 6);
+    assert_eq!(spy.call_count(), 1);
+    assert_eq!(spy.returns(), vec![6]);
+}
+
+#[test]
+fn test_spy_without_capture_return_should_only_count_calls() {
+    let mut injector = InjectorPP::new();
+    let spy = injector
+        .when_called(injectorpp::func!(fn (foo)() -> usize))
+        .spy();
+
+    assert_eq!(foo(), 6);
+    assert_eq!(foo(), 6);
+
+    assert_eq!(spy.call_count(), 2);
+    assert!(spy.returns().is_empty());
+}
+
+// AArch64 passes the address of a large return value in x8, which the spy does not forward.
+#[cfg(target_arch = "x86_64")]
+#[test]
+fn test_spy_when_function_returns_owned_value_should_forward_arguments() {
+    let mut injector = InjectorPP::new();
+    let spy = injector
+        .when_called(injectorpp::func!(fn (join)(&str, &str) -> String))
+        .spy();
+
+    assert_eq!(join("left", "right"), "left-right");
+    assert_eq!(spy.call_count(), 1);
+}
+
+#[test]
+fn test_spy_capture_return_when_pair_returned_should_record_both_registers() {
+    let mut injector = InjectorPP::new();
+    let spy = injector
+        .when_called(injectorpp::func!(fn (split)(u64) -> (u64, u64)))
+        .spy()
+        .capture_return();
+
+    assert_eq!(split(0x0000_0001_0000_0002), (1, 2));
+    assert_eq!(spy.return_pairs(), vec![(1, 2)]);
+}
+
+#[inline(never)]
+fn process_job(id: usize) -> usize {
+    std::hint::black_box(id) + 1
+}
+
+#[test]
+fn test_spy_capture_thread_ids_should_count_distinct_calling_threads() {
+    let mut injector = InjectorPP::new();
+    let spy = injector
+        .when_called(injectorpp::func!(fn (process_job)(usize) -> usize))
+        .spy()
+        .capture_thread_ids();
+
+    std::thread::scope(|scope| {
+        for worker in 0..4 {
+            scope.spawn(move || {
+                for job in 0..3 {
+                    assert_eq!(process_job(worker * 10 + job), worker * 10 + job + 1);
+                }
+            });
+        }
+    });
+
+    assert_eq!(spy.call_count(), 12);
+    assert_eq!(spy.distinct_threads(), 4);
+}
@@ -0,0 +1,43 @@
+use injectorpp::interface::injector::*;
+use injectorpp_native_fixture::*;
+use std::os::raw::c_int;
+
+extern "C" fn fake_checksum(value: c_int) -> c_int {
+    value
+}
+
+#[test]
+fn test_static_lib_when_fake_with_func_should_apply_inside_archive() {
+    let mut injector = InjectorPP::new();
+    injector
+        .when_called(injectorpp::func!(
+            unsafe{} extern "C" fn (injectorpp_native_checksum)(c_int) -> c_int
+        ))
+        .will_execute(injectorpp::fake!(
+            func_type: unsafe extern "C" fn(_value: c_int) -> c_int,
+            returns: 1,
+            times: 2
+        ));
+
+    assert_eq!(checksum_pair(3, 4), 2);
+}
+
+#[cfg(target_os = "linux")]
+#[test]
+fn test_static_lib_when_fake_with_when_named_should_apply_inside_archive() {
+    assert_eq!(checksum_pair(1, 2), 38 + 69);
+
+    {
+        let mut injector = InjectorPP::new();
+
+        unsafe {
+            injector
+                .when_named("injectorpp_native_checksum")
+                .will_execute_raw_unchecked(injectorpp::func_unchecked!(fake_checksum));
+        }
+
+        assert_eq!(checksum_pair(1, 2), 3);
+    }
+
+    assert_eq!(checksum_pair(1, 2), 38 + 69);
+}
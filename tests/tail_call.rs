@@ -0,0 +1,144 @@
+use injectorpp::interface::injector::*;
+
+#[inline(never)]
+fn describe_len(value: &str) -> usize {
+    std::hint::black_box(value).len()
+}
+
+#[test]
+fn test_allow_tail_call_target_when_function_is_not_tail_call_should_fake_normally() {
+    let mut injector = InjectorPP::new();
+    injector
+        .when_called(injectorpp::func!(fn (describe_len)(&str) -> usize))
+        .allow_tail_call_target()
+        .will_execute(|_: &str| 42usize);
+
+    assert_eq!(describe_len("abc"), 42);
+}
+
+#[test]
+fn test_follow_branch_thunk_when_function_is_not_thunk_should_fake_normally() {
+    let mut injector = InjectorPP::new();
+    injector
+        .when_called(injectorpp::func!(fn (describe_len)(&str) -> usize))
+        .follow_branch_thunk()
+        .will_execute(|_: &str| 7usize);
+
+    assert_eq!(describe_len("abc"), 7);
+}
+
+#[cfg(target_arch = "aarch64")]
+mod aarch64 {
+    use injectorpp::interface::injector::*;
+
+    #[no_mangle]
+    extern "C" fn injectorpp_tail_call_target(value: u32) -> u32 {
+        std::hint::black_box(value) + 1
+    }
+
+    // A function whose whole body is a tail call, immediately followed by another function
+    // that a 12-byte patch would overwrite.
+    macro_rules! tail_call_functions {
+        ($prefix:literal) => {
+            std::arch::global_asm!(
+                ".text",
+                ".p2align 2",
+                concat!(".globl ", $prefix, "injectorpp_tail_call"),
+                concat!($prefix, "injectorpp_tail_call:"),
+                concat!("b ", $prefix, "injectorpp_tail_call_target"),
+                concat!(".globl ", $prefix, "injectorpp_tail_call_neighbor"),
+                concat!($prefix, "injectorpp_tail_call_neighbor:"),
+                "mov w0, #7",
+                "ret",
+                // An incremental linking style thunk to a separate body.
+                ".p2align 2",
+                concat!(".globl ", $prefix, "injectorpp_thunk"),
+                concat!($prefix, "injectorpp_thunk:"),
+                concat!("b ", $prefix, "injectorpp_thunk_body"),
+                concat!(".globl ", $prefix, "injectorpp_thunk_body"),
+                concat!($prefix, "injectorpp_thunk_body:"),
+                "add w0, w0, #1",
+                "nop",
+                "nop",
+                "ret",
+            );
+        };
+    }
+
+    #[cfg(target_os = "macos")]
+    tail_call_functions!("_");
+
+    #[cfg(not(target_os = "macos"))]
+    tail_call_functions!("");
+
+    extern "C" {
+        fn injectorpp_tail_call(value: u32) -> u32;
+        fn injectorpp_tail_call_neighbor() -> u32;
+        fn injectorpp_thunk(value: u32) -> u32;
+        fn injectorpp_thunk_body(value: u32) -> u32;
+    }
+
+    unsafe extern "C" fn fake_tail_call(value: u32) -> u32 {
+        value * 100
+    }
+
+    #[test]
+    fn test_tail_call_function_when_allowed_should_only_replace_branch() {
+        assert_eq!(unsafe { injectorpp_tail_call(3) }, 4);
+
+        {
+            let mut injector = InjectorPP::new();
+            injector
+                .when_called(injectorpp::func!(
+                    unsafe{} extern "C" fn (injectorpp_tail_call)(u32) -> u32
+                ))
+                .allow_tail_call_target()
+                .will_execute_raw(injectorpp::func!(
+                    unsafe{} extern "C" fn (fake_tail_call)(u32) -> u32
+                ));
+
+            assert_eq!(unsafe { injectorpp_tail_call(3) }, 300);
+            assert_eq!(unsafe { injectorpp_tail_call_neighbor() }, 7);
+        }
+
+        assert_eq!(unsafe { injectorpp_tail_call(3) }, 4);
+        assert_eq!(unsafe { injectorpp_tail_call_neighbor() }, 7);
+    }
+
+    #[test]
+    #[should_panic(expected = "call allow_tail_call_target()")]
+    fn test_tail_call_function_when_not_allowed_should_panic() {
+        let mut injector = InjectorPP::new();
+        injector
+            .when_called(injectorpp::func!(
+                unsafe{} extern "C" fn (injectorpp_tail_call)(u32) -> u32
+            ))
+            .will_execute_raw(injectorpp::func!(
+                unsafe{} extern "C" fn (fake_tail_call)(u32) -> u32
+            ));
+    }
+
+    #[test]
+    fn test_follow_branch_thunk_should_fake_function_behind_thunk() {
+        assert_eq!(unsafe { injectorpp_thunk_body(3) }, 4);
+
+        {
+            let mut injector = InjectorPP::new();
+            injector
+                .when_called(injectorpp::func!(
+                    unsafe{} extern "C" fn (injectorpp_thunk)(u32) -> u32
+                ))
+                .follow_branch_thunk()
+                .will_execute_raw(injectorpp::func!(
+                    unsafe{} extern "C" fn (fake_tail_call)(u32) -> u32
+                ));
+
+            // Callers reaching the body directly see the fake too.
+            assert_eq!(unsafe { injectorpp_thunk_body(3) }, 300);
+            assert_eq!(unsafe { injectorpp_thunk(3) }, 300);
+        }
+
+        assert_eq!(unsafe { injectorpp_thunk_body(3) }, 4);
+        assert_eq!(unsafe { injectorpp_thunk(3) }, 4);
+    }
+}
@@ -28,6 +28,79 @@ fn test_multi_thread_function_call() {
     handle.join().unwrap();
 }
 
+#[test]
+fn test_prevent_all_when_held_should_block_patching_from_other_threads() {
+    use std::sync::atomic::{AtomicBool, Ordering};
+    use std::sync::Arc;
+    use std::time::Duration;
+
+    let guard = InjectorPP::prevent_all();
+    let patched = Arc::new(AtomicBool::new(false));
+
+    let handle = {
+        let patched = patched.clone();
+        thread::spawn(move || {
+            let mut injector = InjectorPP::new();
+            injector
+                .when_called(injectorpp::func!(fn (foo)() -> i32))
+                .will_execute_raw(injectorpp::closure!(|| { 9 }, fn() -> i32));
+            patched.store(true, Ordering::SeqCst);
+
+            assert_eq!(foo(), 9);
+        })
+    };
+
+    for _ in 0..10 {
+        thread::sleep(Duration::from_millis(5));
+        assert!(!patched.load(Ordering::SeqCst));
+        assert_eq!(foo(), 6);
+    }
+
+    drop(guard);
+    handle.join().unwrap();
+    assert!(patched.load(Ordering::SeqCst));
+}
+
+#[test]
+fn test_second_injector_faking_same_function_should_wait_for_first_to_restore() {
+    use std::sync::atomic::{AtomicBool, Ordering};
+    use std::sync::Arc;
+    use std::time::Duration;
+
+    let mut first = InjectorPP::new();
+    first
+        .when_called(injectorpp::func!(fn (foo)() -> i32))
+        .will_execute_raw(injectorpp::closure!(|| { 9 }, fn() -> i32));
+    let created = Arc::new(AtomicBool::new(false));
+
+    let handle = {
+        let created = created.clone();
+        thread::spawn(move || {
+            let mut second = InjectorPP::new();
+            created.store(true, Ordering::SeqCst);
+
+            // The first injector restored the real code before this one could exist.
+            assert_eq!(foo(), 6);
+            second
+                .when_called(injectorpp::func!(fn (foo)() -> i32))
+                .will_execute_raw(injectorpp::closure!(|| { 7 }, fn() -> i32));
+            assert_eq!(foo(), 7);
+        })
+    };
+
+    for _ in 0..10 {
+        thread::sleep(Duration::from_millis(5));
+        assert!(!created.load(Ordering::SeqCst));
+        assert_eq!(foo(), 9);
+    }
+
+    drop(first);
+    handle.join().unwrap();
+
+    let _guard = InjectorPP::prevent();
+    assert_eq!(foo(), 6);
+}
+
 #[test]
 fn test_original_function_call() {
     let _guard = InjectorPP::prevent();
@@ -44,3 +117,144 @@ fn test_faked_function_call() {
 
     assert_eq!(foo(), 9);
 }
+
+#[inline(never)]
+pub fn bar() -> i32 {
+    6
+}
+
+#[test]
+fn test_concurrent_injectors_should_restore_original() {
+    let handles: Vec<_> = (0..8)
+        .map(|_| {
+            thread::spawn(|| {
+                for _ in 0..50 {
+                    let mut injector = InjectorPP::new();
+                    injector
+                        .when_called(injectorpp::func!(fn (bar)() -> i32))
+                        .will_execute_raw(injectorpp::closure!(|| { 9 }, fn() -> i32));
+
+                    assert_eq!(bar(), 9);
+                }
+            })
+        })
+        .collect();
+
+    for handle in handles {
+        handle.join().unwrap();
+    }
+
+    // Every install captured the real prologue, so the last restore leaves the original in place.
+    let _guard = InjectorPP::prevent();
+    assert_eq!(bar(), 6);
+}
+
+#[inline(never)]
+pub fn baz() -> i32 {
+    std::hint::black_box(6)
+}
+
+// The patching thread issues no serializing instruction on x86_64: a thread released through
+// a synchronization primitive after the install must still run the new code.
+#[test]
+fn test_patch_installed_on_one_thread_should_be_visible_to_released_thread() {
+    let (start_sender, start_receiver) = std::sync::mpsc::channel::<()>();
+    let (result_sender, result_receiver) = std::sync::mpsc::channel::<i32>();
+
+    let handle = thread::spawn(move || {
+        for _ in start_receiver {
+            result_sender.send(baz()).unwrap();
+        }
+    });
+
+    for _ in 0..100 {
+        let mut injector = InjectorPP::new();
+        injector
+            .when_called(injectorpp::func!(fn (baz)() -> i32))
+            .will_execute_raw(injectorpp::closure!(|| { 9 }, fn() -> i32));
+
+        start_sender.send(()).unwrap();
+        assert_eq!(result_receiver.recv().unwrap(), 9);
+    }
+
+    drop(start_sender);
+    handle.join().unwrap();
+}
+
+#[inline(never)]
+fn hammered() -> u64 {
+    std::hint::black_box(6)
+}
+
+#[test]
+fn test_patch_while_called_from_many_threads_should_never_run_torn_code() {
+    use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+    use std::sync::Arc;
+
+    let stop = Arc::new(AtomicBool::new(false));
+    let calls = Arc::new(AtomicUsize::new(0));
+
+    let handles: Vec<_> = (0..8)
+        .map(|_| {
+            let stop = stop.clone();
+            let calls = calls.clone();
+            thread::spawn(move || {
+                while !stop.load(Ordering::Relaxed) {
+                    let value = hammered();
+                    assert!(value == 6 || value == 9, "unexpected value {value}");
+                    calls.fetch_add(1, Ordering::Relaxed);
+                }
+            })
+        })
+        .collect();
+
+    for _ in 0..200 {
+        let mut injector = InjectorPP::new();
+        injector
+            .when_called(injectorpp::func!(fn (hammered)() -> u64))
+            .will_return_u64(9);
+
+        assert_eq!(hammered(), 9);
+    }
+
+    stop.store(true, Ordering::Relaxed);
+    for handle in handles {
+        handle.join().unwrap();
+    }
+
+    assert!(calls.load(Ordering::Relaxed) > 0);
+    assert_eq!(hammered(), 6);
+}
+
+#[test]
+#[should_panic(expected = "An InjectorPP or a Preventer is already alive on this thread")]
+fn test_new_when_thread_already_holds_injector_should_panic() {
+    let _first = InjectorPP::new();
+    let _second = InjectorPP::new();
+}
+
+#[test]
+#[should_panic(expected = "An InjectorPP or a Preventer is already alive on this thread")]
+fn test_prevent_when_thread_already_holds_injector_should_panic() {
+    let _injector = InjectorPP::new();
+    let _guard = InjectorPP::prevent();
+}
+
+#[test]
+#[should_panic(expected = "An InjectorPP or a Preventer is already alive on this thread")]
+fn test_new_when_thread_holds_preventer_should_panic() {
+    let _guard = InjectorPP::prevent_all();
+    let _injector = InjectorPP::new();
+}
+
+#[test]
+fn test_new_after_previous_injector_dropped_on_same_thread_should_succeed() {
+    drop(InjectorPP::new());
+    drop(InjectorPP::prevent());
+
+    let mut injector = InjectorPP::new();
+    injector
+        .when_called(injectorpp::func!(fn (bar)() -> i32))
+        .will_execute_raw(injectorpp::closure!(|| { 9 }, fn() -> i32));
+    assert_eq!(bar(), 9);
+}
@@ -0,0 +1,80 @@
+use injectorpp::interface::injector::*;
+use injectorpp::time::FakeClock;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+#[inline(never)]
+fn is_expired(created: Instant, ttl: Duration) -> bool {
+    created.elapsed() >= ttl
+}
+
+#[test]
+fn test_fake_clock_when_installed_should_freeze_time() {
+    let mut injector = InjectorPP::new();
+    let clock = FakeClock::install(&mut injector);
+
+    let instant = Instant::now();
+    let system_time = SystemTime::now();
+    std::thread::sleep(Duration::from_millis(20));
+
+    assert_eq!(Instant::now(), instant);
+    assert_eq!(SystemTime::now(), system_time);
+    assert_eq!(clock.elapsed(), Duration::ZERO);
+}
+
+#[test]
+fn test_fake_clock_when_advanced_should_move_both_clocks_forward() {
+    let mut injector = InjectorPP::new();
+    let clock = FakeClock::install(&mut injector);
+
+    let created = Instant::now();
+    let since_epoch = SystemTime::now().duration_since(UNIX_EPOCH).unwrap();
+    assert!(!is_expired(created, Duration::from_secs(60)));
+
+    clock.advance(Duration::from_secs(59));
+    assert!(!is_expired(created, Duration::from_secs(60)));
+
+    clock.clone().advance(Duration::from_millis(1_000));
+    assert!(is_expired(created, Duration::from_secs(60)));
+    assert_eq!(clock.elapsed(), Duration::from_secs(60));
+    assert_eq!(
+        SystemTime::now().duration_since(UNIX_EPOCH).unwrap() - since_epoch,
+        Duration::from_secs(60)
+    );
+}
+
+#[test]
+fn test_fake_clock_when_advanced_from_another_thread_should_be_seen_everywhere() {
+    let mut injector = InjectorPP::new();
+    let clock = FakeClock::install(&mut injector);
+    let start = Instant::now();
+
+    let advancing = clock.clone();
+    std::thread::spawn(move || advancing.advance(Duration::from_secs(5)))
+        .join()
+        .unwrap();
+
+    let seen = std::thread::spawn(move || start.elapsed()).join().unwrap();
+    assert_eq!(seen, Duration::from_secs(5));
+}
+
+#[test]
+fn test_fake_clock_when_injector_dropped_should_restore_real_time() {
+    let before = Instant::now();
+    {
+        let mut injector = InjectorPP::new();
+        let clock = FakeClock::install(&mut injector);
+        clock.advance(Duration::from_secs(3600));
+        assert!(before.elapsed() >= Duration::from_secs(3600));
+    }
+
+    assert!(before.elapsed() < Duration::from_secs(3600));
+}
+
+#[test]
+#[should_panic(expected = "Cannot advance the fake clock")]
+fn test_fake_clock_when_advanced_past_u64_nanoseconds_should_panic() {
+    let mut injector = InjectorPP::new();
+    let clock = FakeClock::install(&mut injector);
+
+    clock.advance(Duration::MAX);
+}
@@ -0,0 +1,166 @@
+use injectorpp::interface::injector::*;
+
+trait Service {
+    fn get(&self, path: &str) -> u16;
+
+    fn reset(&mut self);
+
+    fn into_status(self) -> u16;
+}
+
+struct HttpClientTest {
+    status: u16,
+}
+
+impl Service for HttpClientTest {
+    #[inline(never)]
+    fn get(&self, path: &str) -> u16 {
+        std::hint::black_box(path);
+        self.status
+    }
+
+    #[inline(never)]
+    fn reset(&mut self) {
+        self.status = std::hint::black_box(0);
+    }
+
+    #[inline(never)]
+    fn into_status(self) -> u16 {
+        std::hint::black_box(self.status)
+    }
+}
+
+struct OtherClient;
+
+impl Service for OtherClient {
+    #[inline(never)]
+    fn get(&self, path: &str) -> u16 {
+        std::hint::black_box(path.len() as u16)
+    }
+
+    fn reset(&mut self) {}
+
+    fn into_status(self) -> u16 {
+        0
+    }
+}
+
+fn fake_reset(client: &mut HttpClientTest) {
+    client.status = 204;
+}
+
+#[test]
+fn test_trait_method_when_called_through_dyn_trait_should_fake_implementation() {
+    let client = HttpClientTest { status: 500 };
+
+    {
+        let mut injector = InjectorPP::new();
+        injector
+            .when_called(injectorpp::trait_method!(
+                fn (<HttpClientTest as Service>::get)(&self, &str) -> u16
+            ))
+            .will_execute(
+                |_: &HttpClientTest, path: &str| if path == "/health" { 200u16 } else { 404 },
+            );
+
+        let service: &dyn Service = &client;
+        assert_eq!(service.get("/health"), 200);
+        assert_eq!(service.get("/missing"), 404);
+
+        let boxed: Box<dyn Service> = Box::new(HttpClientTest { status: 500 });
+        assert_eq!(boxed.get("/health"), 200);
+
+        // Other implementations of the trait are left alone.
+        let other: &dyn Service = &OtherClient;
+        assert_eq!(other.get("/health"), 7);
+    }
+
+    let service: &dyn Service = &client;
+    assert_eq!(service.get("/health"), 500);
+}
+
+#[test]
+fn test_trait_method_when_receiver_is_mut_or_by_value_should_fake_implementation() {
+    let mut injector = InjectorPP::new();
+    injector
+        .when_called(injectorpp::trait_method!(
+            fn (<HttpClientTest as Service>::reset)(&mut self)
+        ))
+        .will_execute_raw(injectorpp::func!(fn (fake_reset)(&mut HttpClientTest)));
+    injector
+        .when_called(injectorpp::trait_method!(
+            fn (<HttpClientTest as Service>::into_status)(self) -> u16
+        ))
+        .will_execute(|client: HttpClientTest| client.status + 1);
+
+    let mut client = HttpClientTest { status: 500 };
+    let service: &mut dyn Service = &mut client;
+    service.reset();
+
+    assert_eq!(client.status, 204);
+    assert_eq!(client.into_status(), 205);
+}
+
+trait Greeter {
+    #[inline(never)]
+    fn greeting(&self, name: &str) -> String {
+        format!("Hello, {}", std::hint::black_box(name))
+    }
+}
+
+struct English;
+
+impl Greeter for English {}
+
+struct Pirate;
+
+impl Greeter for Pirate {}
+
+#[test]
+fn test_trait_method_when_default_method_faked_for_each_type_should_intercept_all_of_them() {
+    {
+        let mut injector = InjectorPP::new();
+        injector
+            .when_called(injectorpp::trait_method!(
+                fn (<English as Greeter>::greeting)(&self, &str) -> String
+            ))
+            .will_execute(|_: &English, name: &str| format!("Hi, {name}"));
+        injector
+            .when_called(injectorpp::trait_method!(
+                fn (<Pirate as Greeter>::greeting)(&self, &str) -> String
+            ))
+            .will_execute(|_: &Pirate, name: &str| format!("Hi, {name}"));
+
+        assert_eq!(English.greeting("Ada"), "Hi, Ada");
+        assert_eq!(Pirate.greeting("Ada"), "Hi, Ada");
+
+        let greeters: [&dyn Greeter; 2] = [&English, &Pirate];
+        for greeter in greeters {
+            assert_eq!(greeter.greeting("Bob"), "Hi, Bob");
+        }
+    }
+
+    assert_eq!(English.greeting("Ada"), "Hello, Ada");
+    assert_eq!(Pirate.greeting("Ada"), "Hello, Ada");
+}
+
+#[test]
+fn test_trait_method_when_default_method_faked_for_one_type_should_affect_types_sharing_its_code() {
+    let english = <English as Greeter>::greeting as fn(&English, &str) -> String as usize;
+    let pirate = <Pirate as Greeter>::greeting as fn(&Pirate, &str) -> String as usize;
+
+    let mut injector = InjectorPP::new();
+    injector
+        .when_called(injectorpp::trait_method!(
+            fn (<English as Greeter>::greeting)(&self, &str) -> String
+        ))
+        .will_execute(|_: &English, name: &str| format!("Hi, {name}"));
+
+    assert_eq!(English.greeting("Ada"), "Hi, Ada");
+    // Each type has its own copy of the default method, unless the optimizer folded them.
+    if english == pirate {
+        assert_eq!(Pirate.greeting("Ada"), "Hi, Ada");
+    } else {
+        assert_eq!(Pirate.greeting("Ada"), "Hello, Ada");
+    }
+}
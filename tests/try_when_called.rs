@@ -0,0 +1,96 @@
+use injectorpp::interface::injector::*;
+
+#[inline(never)]
+fn is_ready() -> bool {
+    std::hint::black_box(false)
+}
+
+/// The first page is never mapped, so nothing can be read at this address.
+const UNMAPPED_ADDRESS: usize = 0x1000;
+
+#[test]
+fn test_try_when_called_when_function_unreadable_should_fail_without_patching() {
+    let mut injector = InjectorPP::new();
+
+    let func = unsafe { FuncPtr::new(UNMAPPED_ADDRESS as *const (), "fn() -> bool") };
+    let result = injector.try_when_called(func);
+
+    assert!(matches!(
+        result.err(),
+        Some(InjectError::UnreadableMemory {
+            address: UNMAPPED_ADDRESS,
+            ..
+        })
+    ));
+}
+
+#[test]
+fn test_try_when_called_after_error_should_keep_injector_usable() {
+    let mut injector = InjectorPP::new();
+
+    let func = unsafe { FuncPtr::new(UNMAPPED_ADDRESS as *const (), "fn() -> bool") };
+    assert!(injector.try_when_called(func).is_err());
+
+    injector
+        .try_when_called(injectorpp::func!(fn (is_ready)() -> bool))
+        .unwrap()
+        .will_return_boolean(true);
+
+    assert!(is_ready());
+}
+
+#[test]
+fn test_inject_error_display_should_describe_patch_failures() {
+    assert_eq!(
+        InjectError::AllocationFailed { size: 4096 }.to_string(),
+        format!(
+            "Failed to allocate 4096 byte(s) of executable memory on {} arch",
+            std::env::consts::ARCH
+        )
+    );
+    assert_eq!(
+        InjectError::OutOfBranchRange {
+            address: 0x1000,
+            max_range: 0x800_0000,
+        }
+        .to_string(),
+        format!(
+            "Failed to allocate JIT memory within ±134217728 of source 0x1000 on {} arch",
+            std::env::consts::ARCH
+        )
+    );
+    assert_eq!(
+        InjectError::PatchWindowTooSmall {
+            address: 0x1000,
+            available: 4,
+            required: 12,
+        }
+        .to_string(),
+        "The function at 0x1000 only leaves 4 byte(s) to patch but 12 are needed to reach its fake"
+    );
+}
+
+/// Readable data where an inlined function's code is expected, as when a function fully
+/// inlined into its callers is resolved to an address that holds no code.
+static INLINED_AWAY: [u8; 64] = [0xC3; 64];
+
+#[test]
+fn test_try_when_called_when_address_is_not_executable_should_fail_without_patching() {
+    let mut injector = InjectorPP::new();
+
+    let address = INLINED_AWAY.as_ptr() as usize;
+    let func = unsafe { FuncPtr::new(address as *const (), "fn() -> bool") };
+    let result = injector.try_when_called(func);
+
+    assert_eq!(result.err(), Some(InjectError::NotExecutable { address }));
+    assert_eq!(INLINED_AWAY, [0xC3; 64]);
+}
+
+#[test]
+#[should_panic(expected = "mark it #[inline(never)] instead")]
+fn test_when_called_when_address_is_not_executable_should_suggest_inline_never() {
+    let mut injector = InjectorPP::new();
+
+    let func = unsafe { FuncPtr::new(INLINED_AWAY.as_ptr() as *const (), "fn() -> bool") };
+    injector.when_called(func).will_return_boolean(true);
+}
@@ -0,0 +1,13 @@
+use injectorpp::interface::injector::*;
+
+#[inline(never)]
+fn bounds() -> [u64; 2] {
+    [1, 2]
+}
+
+fn main() {
+    let mut injector = InjectorPP::new();
+    injector
+        .when_called(injectorpp::func!(fn (bounds)() -> [u64; 2]))
+        .will_return_aggregate([7u64, 8u64]);
+}
@@ -0,0 +1,13 @@
+use injectorpp::interface::injector::*;
+
+#[inline(never)]
+fn pair() -> (u32, u32) {
+    (1, 2)
+}
+
+fn main() {
+    let mut injector = InjectorPP::new();
+    injector
+        .when_called(injectorpp::func!(fn (pair)() -> (u32, u32)))
+        .will_return_aggregate((1u32, 2u32));
+}
@@ -0,0 +1,29 @@
+use injectorpp::interface::injector::*;
+
+#[derive(Clone, Copy)]
+#[repr(C)]
+struct Header {
+    kind: u32,
+    flags: u32,
+    length: u64,
+}
+
+#[inline(never)]
+fn header() -> Header {
+    Header {
+        kind: 0,
+        flags: 0,
+        length: 0,
+    }
+}
+
+fn main() {
+    let mut injector = InjectorPP::new();
+    injector
+        .when_called(injectorpp::func!(fn (header)() -> Header))
+        .will_return_aggregate(Header {
+            kind: 1,
+            flags: 2,
+            length: 3,
+        });
+}
@@ -0,0 +1,27 @@
+use injectorpp::interface::injector::*;
+
+struct Config {
+    retries: u32,
+}
+
+struct Service {
+    config: Config,
+}
+
+impl Service {
+    async fn config(&self) -> &Config {
+        &self.config
+    }
+}
+
+fn main() {
+    let service = Service {
+        config: Config { retries: 1 },
+    };
+    let local = Config { retries: 5 };
+
+    let mut injector = InjectorPP::new();
+    injector
+        .when_called_async(injectorpp::async_func!(service.config(), &Config))
+        .will_execute_async(|| async { &local });
+}
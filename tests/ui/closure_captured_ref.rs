@@ -0,0 +1,22 @@
+use injectorpp::interface::injector::*;
+
+struct Config {
+    name: String,
+}
+
+impl Config {
+    fn name(&self) -> &str {
+        &self.name
+    }
+}
+
+fn main() {
+    let name = String::from("fake");
+    let mut injector = InjectorPP::new();
+    injector
+        .when_called(injectorpp::func!(fn (Config::name)(&Config) -> &str))
+        .will_execute_raw(injectorpp::closure!(
+            move |_: &Config| -> &str { name.as_str() },
+            fn(&Config) -> &str
+        ));
+}
@@ -0,0 +1,24 @@
+use injectorpp::interface::injector::*;
+
+struct Config {
+    name: String,
+}
+
+impl Config {
+    fn name(&self) -> &str {
+        &self.name
+    }
+}
+
+fn main() {
+    let mut injector = InjectorPP::new();
+    injector
+        .when_called(injectorpp::func!(fn (Config::name)(&Config) -> &str))
+        .will_execute_raw(injectorpp::closure!(
+            |_: &Config| -> &str {
+                let name = String::from("fake");
+                &name
+            },
+            fn(&Config) -> &str
+        ));
+}
@@ -0,0 +1,8 @@
+use injectorpp::interface::injector::*;
+
+fn main() {
+    let mut injector = InjectorPP::new();
+    injector
+        .when_called(injectorpp::func!(fn (|value: u32| value + 1)(u32) -> u32))
+        .will_return_boolean(true);
+}
@@ -0,0 +1,12 @@
+use injectorpp::interface::injector::*;
+
+trait Probe {
+    fn ready(&self) -> bool;
+}
+
+fn main() {
+    let mut injector = InjectorPP::new();
+    injector
+        .when_called(injectorpp::func!(fn (<dyn Probe>::ready)(&(dyn Probe + 'static)) -> bool))
+        .will_return_boolean(true);
+}
@@ -0,0 +1,18 @@
+use injectorpp::interface::injector::*;
+
+struct Handlers {
+    on_event: fn(u32) -> bool,
+}
+
+fn handle(_event: u32) -> bool {
+    false
+}
+
+fn main() {
+    let handlers = Handlers { on_event: handle };
+
+    let mut injector = InjectorPP::new();
+    injector
+        .when_called(injectorpp::func!(fn (handlers.on_event)(u32) -> bool))
+        .will_return_boolean(true);
+}
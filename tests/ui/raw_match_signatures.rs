@@ -0,0 +1,28 @@
+use injectorpp::interface::injector::*;
+
+#[inline(never)]
+fn answer() -> i32 {
+    std::hint::black_box(42)
+}
+
+#[inline(never)]
+fn name_length(name: &str) -> usize {
+    std::hint::black_box(name.len())
+}
+
+fn fake_name_length(_name: &str) -> usize {
+    7
+}
+
+fn main() {
+    let mut injector = InjectorPP::new();
+    injector
+        .when_called(injectorpp::func!(fn (answer)() -> i32))
+        .will_execute_raw(injectorpp::closure!(|| 9, fn() -> i32));
+    injector
+        .when_called(injectorpp::func!(fn (name_length)(&str) -> usize))
+        .will_execute_raw(injectorpp::func!(fn (fake_name_length)(&str) -> usize));
+
+    assert_eq!(answer(), 9);
+    assert_eq!(name_length("injectorpp"), 7);
+}
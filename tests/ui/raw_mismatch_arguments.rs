@@ -0,0 +1,17 @@
+use injectorpp::interface::injector::*;
+
+#[inline(never)]
+fn primary_port() -> u16 {
+    443
+}
+
+fn fallback_port(base: u16) -> u16 {
+    base + 1
+}
+
+fn main() {
+    let mut injector = InjectorPP::new();
+    injector
+        .when_called(injectorpp::func!(fn (primary_port)() -> u16))
+        .will_forward_to(injectorpp::func!(fn (fallback_port)(u16) -> u16));
+}
@@ -0,0 +1,13 @@
+use injectorpp::interface::injector::*;
+
+#[inline(never)]
+fn answer() -> i32 {
+    42
+}
+
+fn main() {
+    let mut injector = InjectorPP::new();
+    injector
+        .when_called(injectorpp::func!(fn (answer)() -> i32))
+        .will_execute_raw(injectorpp::closure!(|| 9u8, fn() -> u8));
+}
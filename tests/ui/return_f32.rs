@@ -0,0 +1,13 @@
+use injectorpp::interface::injector::*;
+
+#[inline(never)]
+fn scale() -> f32 {
+    1.0
+}
+
+fn main() {
+    let mut injector = InjectorPP::new();
+    injector
+        .when_called(injectorpp::func!(fn (scale)() -> f32))
+        .will_return(1.5f32);
+}
@@ -0,0 +1,13 @@
+use injectorpp::interface::injector::*;
+
+#[inline(never)]
+fn worker_slot() -> Option<u32> {
+    Some(1)
+}
+
+fn main() {
+    let mut injector = InjectorPP::new();
+    injector
+        .when_called(injectorpp::func!(fn (worker_slot)() -> Option<u32>))
+        .will_return(Some(5u32));
+}
@@ -0,0 +1,13 @@
+use injectorpp::interface::injector::*;
+
+#[inline(never)]
+fn position() -> (u32, u32) {
+    (1, 2)
+}
+
+fn main() {
+    let mut injector = InjectorPP::new();
+    injector
+        .when_called(injectorpp::func!(fn (position)() -> (u32, u32)))
+        .will_return((7u32, 8u32));
+}
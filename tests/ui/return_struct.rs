@@ -0,0 +1,32 @@
+use injectorpp::interface::injector::*;
+
+#[repr(C)]
+#[derive(Clone, Copy)]
+struct Version {
+    major: u16,
+    minor: u16,
+    patch: u16,
+    build: u16,
+}
+
+#[inline(never)]
+fn version() -> Version {
+    Version {
+        major: 1,
+        minor: 0,
+        patch: 0,
+        build: 0,
+    }
+}
+
+fn main() {
+    let mut injector = InjectorPP::new();
+    injector
+        .when_called(injectorpp::func!(fn (version)() -> Version))
+        .will_return(Version {
+            major: 2,
+            minor: 7,
+            patch: 1,
+            build: 0xBEEF,
+        });
+}
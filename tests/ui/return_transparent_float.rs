@@ -0,0 +1,17 @@
+use injectorpp::interface::injector::*;
+
+#[repr(transparent)]
+#[derive(Clone, Copy)]
+struct Meters(f64);
+
+#[inline(never)]
+fn distance() -> Meters {
+    Meters(1.0)
+}
+
+fn main() {
+    let mut injector = InjectorPP::new();
+    injector
+        .when_called(injectorpp::func!(fn (distance)() -> Meters))
+        .will_return(Meters(42.5));
+}
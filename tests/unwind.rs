@@ -0,0 +1,66 @@
+#![cfg(all(target_arch = "x86_64", target_os = "linux", target_env = "gnu"))]
+
+use injectorpp::interface::injector::*;
+use std::backtrace::Backtrace;
+use std::sync::Mutex;
+
+#[inline(never)]
+fn for_each_event(callback: fn(u64)) {
+    std::hint::black_box(callback);
+}
+
+#[inline(never)]
+fn caller_of_for_each(callback: fn(u64)) {
+    for_each_event(callback);
+    std::hint::black_box(());
+}
+
+fn on_event(_value: u64) {
+    panic!("callback panicked");
+}
+
+static BACKTRACE: Mutex<String> = Mutex::new(String::new());
+
+fn panic_with_backtrace(f: impl FnOnce() + std::panic::UnwindSafe) -> bool {
+    let previous = std::panic::take_hook();
+    std::panic::set_hook(Box::new(|_| {
+        *BACKTRACE.lock().unwrap() = Backtrace::force_capture().to_string();
+    }));
+    let panicked = std::panic::catch_unwind(f).is_err();
+    std::panic::set_hook(previous);
+    panicked
+}
+
+#[test]
+fn test_will_invoke_callback_when_callback_panics_should_unwind_to_the_caller() {
+    let panicked = panic_with_backtrace(|| {
+        let mut injector = InjectorPP::new();
+        injector
+            .when_called(injectorpp::func!(fn (for_each_event)(fn(u64))))
+            .will_invoke_callback(0, &[&[1]]);
+
+        caller_of_for_each(on_event);
+    });
+
+    assert!(panicked);
+    let backtrace = BACKTRACE.lock().unwrap().clone();
+    assert!(backtrace.contains("on_event"), "{backtrace}");
+    assert!(backtrace.contains("caller_of_for_each"), "{backtrace}");
+
+    // The injector was dropped while unwinding, so the original runs again.
+    caller_of_for_each(on_event);
+}
+
+#[test]
+fn test_count_calls_after_a_panicking_callback_should_keep_counting() {
+    let mut injector = InjectorPP::new();
+    let handle = injector
+        .when_called(injectorpp::func!(fn (for_each_event)(fn(u64))))
+        .count_calls()
+        .will_invoke_callback(0, &[&[1]]);
+
+    assert!(panic_with_backtrace(|| caller_of_for_each(on_event)));
+    assert!(panic_with_backtrace(|| caller_of_for_each(on_event)));
+
+    assert_eq!(injector.call_count(handle), 2);
+}
@@ -0,0 +1,49 @@
+use injectorpp::interface::injector::*;
+use injectorpp_native_fixture::*;
+use std::os::raw::{c_char, c_int};
+
+fn format_length() -> FuncPtr<unsafe extern "C" fn(*const c_char, ...) -> c_int> {
+    injectorpp::variadic_func!(
+        unsafe{} extern "C" fn (injectorpp_native_format_length)(*const c_char, ...) -> c_int
+    )
+}
+
+#[test]
+fn test_variadic_when_will_return_i32_should_return_constant_for_any_arguments() {
+    let real = unsafe { injectorpp_native_format_length(c"%d-%s".as_ptr(), 42, c"ab".as_ptr()) };
+    assert_eq!(real, 5);
+
+    {
+        let mut injector = InjectorPP::new();
+        injector.when_called(format_length()).will_return_i32(-7);
+
+        unsafe {
+            assert_eq!(injectorpp_native_format_length(c"plain".as_ptr()), -7);
+            assert_eq!(
+                injectorpp_native_format_length(c"%d %f %s".as_ptr(), 1, 2.5f64, c"x".as_ptr()),
+                -7
+            );
+        }
+    }
+
+    let restored =
+        unsafe { injectorpp_native_format_length(c"%d-%s".as_ptr(), 42, c"ab".as_ptr()) };
+    assert_eq!(restored, 5);
+}
+
+#[test]
+fn test_variadic_when_called_from_inside_archive_should_apply() {
+    assert_eq!(describe(123), "value=123".len() as i32);
+
+    let mut injector = InjectorPP::new();
+    injector.when_called(format_length()).will_return_i32(0);
+
+    assert_eq!(describe(123), 0);
+}
+
+#[test]
+#[should_panic(expected = "Signature mismatch: will_return_i64 requires a function returning i64")]
+fn test_variadic_when_return_type_differs_should_panic() {
+    let mut injector = InjectorPP::new();
+    injector.when_called(format_length()).will_return_i64(0);
+}
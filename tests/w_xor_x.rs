@@ -0,0 +1,65 @@
+// Mappings are inspected through /proc/self/maps, and no other test of this binary makes
+// code pages writable.
+#![cfg(all(target_os = "linux", not(target_arch = "arm")))]
+
+use injectorpp::interface::injector::*;
+
+#[inline(never)]
+fn is_ready() -> bool {
+    std::hint::black_box(false)
+}
+
+#[inline(never)]
+fn scale(value: u64) -> u64 {
+    std::hint::black_box(value) * 2
+}
+
+/// Returns the permissions of the mapping containing `address`, such as `r-xp`.
+fn permissions(address: usize) -> String {
+    let maps = std::fs::read_to_string("/proc/self/maps").unwrap();
+
+    maps.lines()
+        .find_map(|line| {
+            let mut fields = line.split_whitespace();
+            let (low, high) = fields.next()?.split_once('-')?;
+            let range =
+                usize::from_str_radix(low, 16).ok()?..usize::from_str_radix(high, 16).ok()?;
+            range
+                .contains(&address)
+                .then(|| fields.next().unwrap().to_string())
+        })
+        .unwrap_or_else(|| panic!("{address:#x} is not mapped"))
+}
+
+#[test]
+fn test_w_xor_x_when_faked_should_run_code_flipped_from_writable_to_executable() {
+    let is_ready_address = is_ready as fn() -> bool as usize;
+    let scale_address = scale as fn(u64) -> u64 as usize;
+    let code_permissions = permissions(is_ready_address);
+    assert_eq!(&code_permissions[..3], "r-x");
+
+    {
+        let mut injector = InjectorPP::new_with_options(InjectorOptions::new().w_xor_x(true));
+        let boolean = injector
+            .when_called(injectorpp::func!(fn (is_ready)() -> bool))
+            .will_return_boolean(true);
+        let closure = injector
+            .when_called(injectorpp::func!(fn (scale)(u64) -> u64))
+            .will_execute(|value: u64| value + 1);
+
+        assert!(is_ready());
+        assert_eq!(scale(20), 21);
+
+        for handle in [boolean, closure] {
+            let debug = injector.debug_info(handle);
+            assert_eq!(permissions(debug.address), code_permissions);
+            assert_eq!(&permissions(debug.jit_address)[..3], "r-x");
+        }
+        assert_eq!(permissions(scale_address), code_permissions);
+    }
+
+    assert!(!is_ready());
+    assert_eq!(scale(20), 40);
+    assert_eq!(permissions(is_ready_address), code_permissions);
+    assert_eq!(permissions(scale_address), code_permissions);
+}
@@ -0,0 +1,120 @@
+use injectorpp::interface::injector::*;
+use injectorpp_native_fixture::PLUGIN_PATH;
+use std::ffi::{c_void, CString};
+use std::os::raw::c_int;
+
+type Scale = unsafe extern "C" fn(c_int) -> c_int;
+
+#[cfg(target_os = "windows")]
+extern "system" {
+    fn LoadLibraryA(lpLibFileName: *const std::ffi::c_char) -> *mut c_void;
+    fn GetProcAddress(hModule: *mut c_void, lpProcName: *const std::ffi::c_char) -> *mut c_void;
+    fn FreeLibrary(hLibModule: *mut c_void) -> i32;
+}
+
+/// The shared library built from plugin.c by the native fixture, unloaded when dropped.
+struct Plugin(*mut c_void);
+
+impl Plugin {
+    fn load() -> Self {
+        let path = CString::new(PLUGIN_PATH).unwrap();
+
+        #[cfg(not(target_os = "windows"))]
+        let handle = unsafe { libc::dlopen(path.as_ptr(), libc::RTLD_NOW | libc::RTLD_LOCAL) };
+
+        #[cfg(target_os = "windows")]
+        let handle = unsafe { LoadLibraryA(path.as_ptr()) };
+
+        assert!(!handle.is_null(), "failed to load {PLUGIN_PATH}");
+        Plugin(handle)
+    }
+
+    fn function(&self, name: &str) -> Scale {
+        let name = CString::new(name).unwrap();
+
+        #[cfg(not(target_os = "windows"))]
+        let address = unsafe { libc::dlsym(self.0, name.as_ptr()) };
+
+        #[cfg(target_os = "windows")]
+        let address = unsafe { GetProcAddress(self.0, name.as_ptr()) };
+
+        assert!(!address.is_null());
+        unsafe { std::mem::transmute::<*mut c_void, Scale>(address) }
+    }
+}
+
+impl Drop for Plugin {
+    fn drop(&mut self) {
+        #[cfg(not(target_os = "windows"))]
+        unsafe {
+            libc::dlclose(self.0);
+        }
+
+        #[cfg(target_os = "windows")]
+        unsafe {
+            FreeLibrary(self.0);
+        }
+    }
+}
+
+extern "C" fn fake_scale(value: c_int) -> c_int {
+    value
+}
+
+#[test]
+fn test_when_called_in_library_should_fake_function_of_loaded_library() {
+    let plugin = Plugin::load();
+    let scale = plugin.function("injectorpp_plugin_scale");
+    let scale_twice = plugin.function("injectorpp_plugin_scale_twice");
+
+    assert_eq!(unsafe { scale(2) }, 7);
+    assert_eq!(unsafe { scale_twice(2) }, 22);
+
+    {
+        let mut injector = InjectorPP::new();
+
+        unsafe {
+            injector
+                .when_called_in_library(plugin.0, "injectorpp_plugin_scale")
+                .will_execute_raw_unchecked(injectorpp::func_unchecked!(fake_scale));
+        }
+
+        assert_eq!(unsafe { scale(2) }, 2);
+        // Calls made from inside the library see the fake too.
+        assert_eq!(unsafe { scale_twice(2) }, 2);
+    }
+
+    // The library is still loaded, and runs its original code again.
+    assert_eq!(unsafe { scale(2) }, 7);
+    assert_eq!(unsafe { scale_twice(2) }, 22);
+}
+
+#[test]
+fn test_when_called_in_library_when_fake_restored_should_call_original() {
+    let plugin = Plugin::load();
+    let scale = plugin.function("injectorpp_plugin_scale");
+
+    let mut injector = InjectorPP::new();
+    let handle = unsafe {
+        injector
+            .when_called_in_library(plugin.0, "injectorpp_plugin_scale")
+            .will_execute_raw_unchecked(injectorpp::func_unchecked!(fake_scale))
+    };
+
+    assert_eq!(unsafe { scale(5) }, 5);
+
+    injector.restore(handle);
+
+    assert_eq!(unsafe { scale(5) }, 16);
+}
+
+#[test]
+#[should_panic(expected = "Failed to resolve symbol \"injectorpp_plugin_missing\"")]
+fn test_when_called_in_library_when_symbol_missing_should_panic() {
+    let plugin = Plugin::load();
+
+    let mut injector = InjectorPP::new();
+    unsafe {
+        injector.when_called_in_library(plugin.0, "injectorpp_plugin_missing");
+    }
+}
@@ -0,0 +1,41 @@
+#![cfg(target_os = "linux")]
+
+use injectorpp::interface::injector::*;
+
+extern "C" {
+    fn getpid() -> i32;
+}
+
+extern "C" fn fake_getpid() -> i32 {
+    4242
+}
+
+#[test]
+fn test_when_called_symbol_when_fake_getpid_should_return_fixed_value() {
+    let real = std::process::id();
+
+    {
+        let mut injector = InjectorPP::new();
+
+        unsafe {
+            injector
+                .when_called_symbol("getpid")
+                .will_execute_raw_unchecked(injectorpp::func_unchecked!(fake_getpid));
+        }
+
+        assert_eq!(unsafe { getpid() }, 4242);
+        assert_eq!(std::process::id(), 4242);
+    }
+
+    assert_eq!(std::process::id(), real);
+}
+
+#[test]
+#[should_panic(expected = "Failed to resolve symbol")]
+fn test_when_called_symbol_when_symbol_does_not_exist_should_panic() {
+    let mut injector = InjectorPP::new();
+
+    unsafe {
+        injector.when_called_symbol("injectorpp_test_symbol_that_does_not_exist");
+    }
+}
@@ -0,0 +1,91 @@
+use injectorpp::interface::injector::*;
+
+#[inline(never)]
+fn classify(n: i32) -> &'static str {
+    if std::hint::black_box(n) < 0 {
+        "negative"
+    } else {
+        "non-negative"
+    }
+}
+
+#[inline(never)]
+fn lookup(table: &str, key: u32) -> Option<String> {
+    Some(format!("{}/{}", std::hint::black_box(table), key))
+}
+
+#[inline(never)]
+fn checked_half(n: u32) -> u32 {
+    std::hint::black_box(n) / 2
+}
+
+#[test]
+fn test_when_called_with_when_predicate_accepts_negatives_should_fake_only_those() {
+    {
+        let mut injector = InjectorPP::new();
+        injector
+            .when_called_with(
+                injectorpp::func!(fn (classify)(i32) -> &'static str),
+                |n: &i32| *n < 0,
+            )
+            .will_execute(|_: i32| "fake");
+
+        assert_eq!(classify(-5), "fake");
+        assert_eq!(classify(0), "non-negative");
+        assert_eq!(classify(7), "non-negative");
+        assert_eq!(classify(i32::MIN), "fake");
+    }
+
+    assert_eq!(classify(-5), "negative");
+}
+
+#[test]
+fn test_when_called_with_when_predicate_takes_several_arguments_should_receive_them() {
+    let mut injector = InjectorPP::new();
+    injector
+        .when_called_with(
+            injectorpp::func!(fn (lookup)(&str, u32) -> Option<String>),
+            |table: &&str, key: &u32| *table == "users" && *key > 100,
+        )
+        .will_execute(|_: &str, _: u32| -> Option<String> { None });
+
+    assert_eq!(lookup("users", 101), None);
+    assert_eq!(lookup("users", 100), Some("users/100".to_string()));
+    assert_eq!(lookup("groups", 101), Some("groups/101".to_string()));
+}
+
+#[test]
+fn test_when_called_with_when_predicate_calls_faked_function_should_run_original() {
+    let mut injector = InjectorPP::new();
+    injector
+        .when_called_with(
+            injectorpp::func!(fn (checked_half)(u32) -> u32),
+            |n: &u32| checked_half(*n) > 10,
+        )
+        .will_execute(|_: u32| 0u32);
+
+    assert_eq!(checked_half(100), 0);
+    assert_eq!(checked_half(8), 4);
+}
+
+#[test]
+fn test_when_called_with_when_called_from_threads_should_evaluate_each_call() {
+    let mut injector = InjectorPP::new();
+    injector
+        .when_called_with(
+            injectorpp::func!(fn (classify)(i32) -> &'static str),
+            |n: &i32| n % 2 == 0,
+        )
+        .will_execute(|_: i32| "even");
+
+    std::thread::scope(|scope| {
+        for thread in 0..4 {
+            scope.spawn(move || {
+                for n in thread * 100..thread * 100 + 50 {
+                    let expected = if n % 2 == 0 { "even" } else { "non-negative" };
+                    assert_eq!(classify(n), expected);
+                }
+            });
+        }
+    });
+}
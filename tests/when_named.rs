@@ -0,0 +1,57 @@
+#![cfg(target_os = "linux")]
+
+use injectorpp::interface::injector::*;
+
+#[export_name = "injectorpp_test_export_name_func"]
+pub fn export_name_func() -> i32 {
+    1
+}
+
+#[no_mangle]
+pub extern "C" fn injectorpp_test_no_mangle_func() -> i32 {
+    1
+}
+
+fn fake_returns_two() -> i32 {
+    2
+}
+
+extern "C" fn fake_c_returns_two() -> i32 {
+    2
+}
+
+#[test]
+fn test_when_named_when_fake_export_name_function_should_success() {
+    let mut injector = InjectorPP::new();
+
+    unsafe {
+        injector
+            .when_named("injectorpp_test_export_name_func")
+            .will_execute_raw_unchecked(injectorpp::func_unchecked!(fake_returns_two));
+    }
+
+    assert_eq!(export_name_func(), 2);
+}
+
+#[test]
+fn test_when_named_when_fake_no_mangle_function_should_success() {
+    let mut injector = InjectorPP::new();
+
+    unsafe {
+        injector
+            .when_named("injectorpp_test_no_mangle_func")
+            .will_execute_raw_unchecked(injectorpp::func_unchecked!(fake_c_returns_two));
+    }
+
+    assert_eq!(injectorpp_test_no_mangle_func(), 2);
+}
+
+#[test]
+#[should_panic(expected = "Failed to resolve symbol")]
+fn test_when_named_when_symbol_does_not_exist_should_panic() {
+    let mut injector = InjectorPP::new();
+
+    unsafe {
+        injector.when_named("injectorpp_test_symbol_that_does_not_exist");
+    }
+}
@@ -0,0 +1,142 @@
+use injectorpp::interface::injector::*;
+
+#[inline(never)]
+fn log(level: u32, message: &str) {
+    std::hint::black_box((level, message));
+}
+
+#[inline(never)]
+fn resize(width: u16, height: i64, scale: f64, keep_ratio: bool) -> bool {
+    std::hint::black_box(width as f64 * height as f64 * scale > 0.0 && keep_ratio)
+}
+
+#[inline(never)]
+fn checksum(data: &[u8]) -> u64 {
+    std::hint::black_box(data)
+        .iter()
+        .map(|byte| *byte as u64)
+        .sum()
+}
+
+#[test]
+fn test_will_capture_when_called_should_keep_latest_str_arguments() {
+    let mut injector = InjectorPP::new();
+    let capture = injector
+        .when_called(injectorpp::func!(fn (log)(u32, &str)))
+        .will_capture(|_: u32, _: &str| ());
+
+    assert_eq!(capture.last_args(), None);
+
+    log(1, "starting");
+    let message = format!("{} files left", 3);
+    log(2, &message);
+    drop(message);
+
+    assert_eq!(capture.last_args(), Some((2, "3 files left".to_string())));
+}
+
+#[test]
+fn test_will_capture_when_primitive_arguments_should_copy_them_and_run_closure() {
+    let mut injector = InjectorPP::new();
+    let capture = injector
+        .when_called(injectorpp::func!(fn (resize)(u16, i64, f64, bool) -> bool))
+        .will_capture(|width: u16, _: i64, _: f64, _: bool| width > 100);
+
+    assert!(!resize(80, -3, 1.5, true));
+    assert_eq!(capture.last_args(), Some((80, -3, 1.5, true)));
+
+    assert!(resize(640, 480, 0.25, false));
+    assert_eq!(capture.last_args(), Some((640, 480, 0.25, false)));
+}
+
+#[test]
+fn test_will_capture_when_slice_argument_should_capture_vec() {
+    let mut injector = InjectorPP::new();
+    let capture = injector
+        .when_called(injectorpp::func!(fn (checksum)(&[u8]) -> u64))
+        .will_capture(|data: &[u8]| data.len() as u64);
+
+    assert_eq!(checksum(&[4, 5, 6]), 3);
+    assert_eq!(capture.last_args(), Some((vec![4, 5, 6],)));
+}
+
+#[test]
+#[should_panic(expected = "Signature mismatch")]
+fn test_will_capture_when_signature_differs_should_panic() {
+    let mut injector = InjectorPP::new();
+    injector
+        .when_called(injectorpp::func!(fn (log)(u32, &str)))
+        .will_capture(|_: u64, _: &str| ());
+}
+
+#[inline(never)]
+fn connect(host: &str, port: u16) -> bool {
+    std::hint::black_box((host, port));
+    false
+}
+
+#[test]
+fn test_verify_called_with_when_any_call_matches_should_pass() {
+    let mut injector = InjectorPP::new();
+    let capture = injector
+        .when_called(injectorpp::func!(fn (connect)(&str, u16) -> bool))
+        .will_capture(|_: &str, _: u16| true);
+
+    assert!(connect("example.com", 443));
+    assert!(connect("localhost", 8080));
+    assert!(connect("localhost", 9090));
+
+    assert_eq!(capture.call_count(), 3);
+    assert_eq!(
+        capture.all_args(),
+        vec![
+            ("example.com".to_string(), 443),
+            ("localhost".to_string(), 8080),
+            ("localhost".to_string(), 9090),
+        ]
+    );
+    injector.verify_called_with(&capture, ("localhost", 8080));
+    injector.verify_called_with(&capture, ("example.com".to_string(), AnyArg));
+    injector.verify_called_with(&capture, (AnyArg, 9090));
+    assert!(!capture.was_called_with(("localhost", 443)));
+}
+
+#[test]
+fn test_verify_called_with_when_slice_argument_should_compare_with_slice() {
+    let mut injector = InjectorPP::new();
+    let capture = injector
+        .when_called(injectorpp::func!(fn (checksum)(&[u8]) -> u64))
+        .will_capture(|data: &[u8]| data.len() as u64);
+
+    checksum(&[1, 2]);
+
+    injector.verify_called_with(&capture, (&[1u8, 2][..],));
+    assert!(!capture.was_called_with((vec![2u8, 1],)));
+}
+
+#[test]
+#[should_panic(expected = "none of its 2 call(s) matched")]
+fn test_verify_called_with_when_no_call_matches_should_panic() {
+    let mut injector = InjectorPP::new();
+    let capture = injector
+        .when_called(injectorpp::func!(fn (connect)(&str, u16) -> bool))
+        .will_capture(|_: &str, _: u16| true);
+
+    connect("localhost", 80);
+    connect("example.com", 8080);
+
+    injector.verify_called_with(&capture, ("localhost", 8080));
+}
+
+#[test]
+fn test_called_with_failure_to_json_should_report_call_count() {
+    let failure = Failure::CalledWith {
+        function: 0x1000,
+        calls: 2,
+    };
+
+    assert_eq!(
+        failure.to_json(),
+        r#"{"kind":"called_with","function":"0x1000","calls":2}"#
+    );
+}
@@ -565,3 +565,119 @@ fn test_will_execute_fake_unsafe_unit_assign_and_times_over_called_should_panic(
     });
     assert!(result.is_err());
 }
+
+#[inline(never)]
+fn open_handle(path: &str) -> Result<u32, String> {
+    Err(format!("{path} is not available"))
+}
+
+#[inline(never)]
+fn find_slot(key: u64, fallback: Option<u64>) -> Option<u64> {
+    std::hint::black_box(fallback).filter(|slot| *slot != key)
+}
+
+#[test]
+fn test_will_execute_when_closure_returns_result_from_args_should_success() {
+    let mut injector = InjectorPP::new();
+    injector
+        .when_called(injectorpp::func!(fn (open_handle)(&str) -> Result<u32, String>))
+        .will_execute(|path: &str| -> Result<u32, String> {
+            if path == "/dev/ok" {
+                Ok(42)
+            } else {
+                Err(format!("cannot open {path}"))
+            }
+        });
+
+    assert_eq!(open_handle("/dev/ok"), Ok(42));
+    assert_eq!(
+        open_handle("/dev/missing"),
+        Err("cannot open /dev/missing".to_string())
+    );
+}
+
+#[test]
+fn test_will_execute_when_capturing_closure_returns_option_should_success() {
+    let calls = std::sync::Arc::new(std::sync::atomic::AtomicUsize::new(0));
+    let counter = calls.clone();
+
+    let mut injector = InjectorPP::new();
+    injector
+        .when_called(injectorpp::func!(fn (find_slot)(u64, Option<u64>) -> Option<u64>))
+        .will_execute(move |key: u64, fallback: Option<u64>| -> Option<u64> {
+            counter.fetch_add(1, std::sync::atomic::Ordering::SeqCst);
+            fallback.map(|slot| slot + key)
+        });
+
+    assert_eq!(find_slot(1, Some(10)), Some(11));
+    assert_eq!(find_slot(1, None), None);
+    assert_eq!(calls.load(std::sync::atomic::Ordering::SeqCst), 2);
+
+    drop(injector);
+    assert_eq!(find_slot(1, Some(10)), Some(10));
+}
+
+#[test]
+#[should_panic(expected = "Signature mismatch")]
+fn test_will_execute_when_closure_signature_mismatch_should_panic() {
+    let mut injector = InjectorPP::new();
+    injector
+        .when_called(injectorpp::func!(fn (open_handle)(&str) -> Result<u32, String>))
+        .will_execute(|_path: &str| -> Option<u32> { None });
+}
+
+#[inline(never)]
+fn retry_delay(attempt: u32) -> u64 {
+    std::hint::black_box(attempt) as u64 * 100
+}
+
+#[test]
+fn test_will_execute_when_closure_capturing_counts_calls_should_keep_it_until_drop() {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+
+    let calls = Arc::new(AtomicUsize::new(0));
+    let counter = calls.clone();
+
+    let mut injector = InjectorPP::new();
+    injector
+        .when_called(injectorpp::func!(fn (retry_delay)(u32) -> u64))
+        .will_execute(injectorpp::closure_capturing!(
+            move |attempt| counter.fetch_add(1, Ordering::SeqCst) as u64 + attempt as u64,
+            fn(u32) -> u64
+        ));
+
+    assert_eq!(retry_delay(10), 10);
+    assert_eq!(retry_delay(10), 11);
+    assert_eq!(retry_delay(10), 12);
+    assert_eq!(calls.load(Ordering::SeqCst), 3);
+    assert_eq!(Arc::strong_count(&calls), 2);
+
+    drop(injector);
+    assert_eq!(Arc::strong_count(&calls), 1);
+    assert_eq!(retry_delay(10), 1000);
+}
+
+#[test]
+fn test_will_execute_when_closure_capturing_shared_state_by_reference_argument_should_success() {
+    use std::sync::{Arc, Mutex};
+
+    let seen = Arc::new(Mutex::new(Vec::new()));
+    let log = seen.clone();
+
+    let mut injector = InjectorPP::new();
+    injector
+        .when_called(injectorpp::func!(fn (open_handle)(&str) -> Result<u32, String>))
+        .will_execute(injectorpp::closure_capturing!(
+            move |path| {
+                let mut log = log.lock().unwrap();
+                log.push(path.to_string());
+                Ok(log.len() as u32)
+            },
+            fn(&str) -> Result<u32, String>
+        ));
+
+    assert_eq!(open_handle("/dev/a"), Ok(1));
+    assert_eq!(open_handle("/dev/b"), Ok(2));
+    assert_eq!(*seen.lock().unwrap(), ["/dev/a", "/dev/b"]);
+}
@@ -191,3 +191,240 @@ fn test_will_execute_raw_when_fake_generic_function_multiple_types_with_differen
     assert_eq!(CALL_COUNT_CONDITION_TWO_CLOSURE.load(Ordering::SeqCst), 1);
     assert_eq!(CALL_COUNT_CONDITION_THREE_CLOSURE.load(Ordering::SeqCst), 2);
 }
+
+fn sum0() -> i64 {
+    0
+}
+
+fn sum1(a: i64) -> i64 {
+    a
+}
+
+fn sum2(a: i64, b: i64) -> i64 {
+    a + b
+}
+
+fn sum3(a: i64, b: i64, c: i64) -> i64 {
+    a + b + c
+}
+
+fn sum4(a: i64, b: i64, c: i64, d: i64) -> i64 {
+    a + b + c + d
+}
+
+fn sum5(a: i64, b: i64, c: i64, d: i64, e: i64) -> i64 {
+    a + b + c + d + e
+}
+
+fn sum6(a: i64, b: i64, c: i64, d: i64, e: i64, f: i64) -> i64 {
+    a + b + c + d + e + f
+}
+
+fn sum7(a: i64, b: i64, c: i64, d: i64, e: i64, f: i64, g: i64) -> i64 {
+    a + b + c + d + e + f + g
+}
+
+#[allow(clippy::too_many_arguments)]
+fn sum8(a: i64, b: i64, c: i64, d: i64, e: i64, f: i64, g: i64, h: i64) -> i64 {
+    a + b + c + d + e + f + g + h
+}
+
+#[test]
+fn test_will_execute_raw_when_closure_takes_zero_to_eight_arguments_should_receive_them() {
+    let mut injector = InjectorPP::new();
+
+    // Each fake weighs its arguments by position, so a swapped or lost argument shows up.
+    injector
+        .when_called(injectorpp::func!(fn (sum0)() -> i64))
+        .will_execute_raw(injectorpp::closure!(|| 100, fn() -> i64));
+    injector
+        .when_called(injectorpp::func!(fn (sum1)(i64) -> i64))
+        .will_execute_raw(injectorpp::closure!(|a: i64| 100 + a, fn(i64) -> i64));
+    injector
+        .when_called(injectorpp::func!(fn (sum2)(i64, i64) -> i64))
+        .will_execute_raw(injectorpp::closure!(
+            |a: i64, b: i64| 100 + a + 10 * b,
+            fn(i64, i64) -> i64
+        ));
+    injector
+        .when_called(injectorpp::func!(fn (sum3)(i64, i64, i64) -> i64))
+        .will_execute_raw(injectorpp::closure!(
+            |a: i64, b: i64, c: i64| 100 + a + 10 * b + 100 * c,
+            fn(i64, i64, i64) -> i64
+        ));
+    injector
+        .when_called(injectorpp::func!(fn (sum4)(i64, i64, i64, i64) -> i64))
+        .will_execute_raw(injectorpp::closure!(
+            |a: i64, b: i64, c: i64, d: i64| 100 + a + 10 * b + 100 * c + 1000 * d,
+            fn(i64, i64, i64, i64) -> i64
+        ));
+    injector
+        .when_called(injectorpp::func!(fn (sum5)(i64, i64, i64, i64, i64) -> i64))
+        .will_execute_raw(injectorpp::closure!(
+            |a: i64, b: i64, c: i64, d: i64, e: i64| {
+                100 + a + 10 * b + 100 * c + 1000 * d + 10_000 * e
+            },
+            fn(i64, i64, i64, i64, i64) -> i64
+        ));
+    injector
+        .when_called(injectorpp::func!(fn (sum6)(i64, i64, i64, i64, i64, i64) -> i64))
+        .will_execute_raw(injectorpp::closure!(
+            |a: i64, b: i64, c: i64, d: i64, e: i64, f: i64| {
+                100 + a + 10 * b + 100 * c + 1000 * d + 10_000 * e + 100_000 * f
+            },
+            fn(i64, i64, i64, i64, i64, i64) -> i64
+        ));
+    injector
+        .when_called(injectorpp::func!(
+            fn (sum7)(i64, i64, i64, i64, i64, i64, i64) -> i64
+        ))
+        .will_execute_raw(injectorpp::closure!(
+            |a: i64, b: i64, c: i64, d: i64, e: i64, f: i64, g: i64| {
+                100 + a + 10 * b + 100 * c + 1000 * d + 10_000 * e + 100_000 * f + 1_000_000 * g
+            },
+            fn(i64, i64, i64, i64, i64, i64, i64) -> i64
+        ));
+    injector
+        .when_called(injectorpp::func!(
+            fn (sum8)(i64, i64, i64, i64, i64, i64, i64, i64) -> i64
+        ))
+        .will_execute_raw(injectorpp::closure!(
+            |a: i64, b: i64, c: i64, d: i64, e: i64, f: i64, g: i64, h: i64| {
+                100 + a
+                    + 10 * b
+                    + 100 * c
+                    + 1000 * d
+                    + 10_000 * e
+                    + 100_000 * f
+                    + 1_000_000 * g
+                    + 10_000_000 * h
+            },
+            fn(i64, i64, i64, i64, i64, i64, i64, i64) -> i64
+        ));
+
+    assert_eq!(sum0(), 100);
+    assert_eq!(sum1(1), 101);
+    assert_eq!(sum2(1, 2), 121);
+    assert_eq!(sum3(1, 2, 3), 421);
+    assert_eq!(sum4(1, 2, 3, 4), 4_421);
+    assert_eq!(sum5(1, 2, 3, 4, 5), 54_421);
+    assert_eq!(sum6(1, 2, 3, 4, 5, 6), 654_421);
+    assert_eq!(sum7(1, 2, 3, 4, 5, 6, 7), 7_654_421);
+    assert_eq!(sum8(1, 2, 3, 4, 5, 6, 7, 8), 87_654_421);
+}
+
+fn scale(count: i32, factor: f64, offset: i64, bias: f32) -> f64 {
+    count as f64 * factor + offset as f64 + bias as f64
+}
+
+#[test]
+fn test_will_execute_raw_when_closure_takes_integer_and_float_arguments_should_receive_them() {
+    let mut injector = InjectorPP::new();
+    injector
+        .when_called(injectorpp::func!(fn (scale)(i32, f64, i64, f32) -> f64))
+        .will_execute_raw(injectorpp::closure!(
+            |count: i32, factor: f64, offset: i64, bias: f32| {
+                -(count as f64 * factor + offset as f64 + bias as f64)
+            },
+            fn(i32, f64, i64, f32) -> f64
+        ));
+
+    assert_eq!(scale(3, 1.5, 10, 0.25), -14.75);
+}
+
+struct Config {
+    name: String,
+}
+
+impl Config {
+    #[inline(never)]
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    #[inline(never)]
+    fn label(&self) -> &'static str {
+        "real"
+    }
+}
+
+#[test]
+fn test_will_execute_raw_when_closure_returns_static_str_should_return_it() {
+    let mut injector = InjectorPP::new();
+    injector
+        .when_called(injectorpp::func!(fn (Config::label)(&Config) -> &'static str))
+        .will_execute_raw(injectorpp::closure!(
+            |_: &Config| "fake",
+            fn(&Config) -> &'static str
+        ));
+
+    let config = Config {
+        name: "real".to_string(),
+    };
+    assert_eq!(config.label(), "fake");
+}
+
+#[test]
+fn test_will_execute_raw_when_closure_returns_reference_into_argument_should_return_it() {
+    let mut injector = InjectorPP::new();
+    injector
+        .when_called(injectorpp::func!(fn (Config::name)(&Config) -> &str))
+        .will_execute_raw(injectorpp::closure!(
+            |config: &Config| &config.name[1..],
+            fn(&Config) -> &str
+        ));
+
+    let config = Config {
+        name: "real".to_string(),
+    };
+    assert_eq!(config.name(), "eal");
+}
+
+#[inline(never)]
+fn primary_id() -> i32 {
+    std::hint::black_box(1)
+}
+
+#[inline(never)]
+fn fallback_id() -> i32 {
+    std::hint::black_box(2)
+}
+
+#[inline(never)]
+fn multiply(value: i32, factor: i32) -> i32 {
+    std::hint::black_box(value) * factor
+}
+
+#[inline(never)]
+fn add_delta(value: i32, delta: i32) -> i32 {
+    std::hint::black_box(value) + delta
+}
+
+#[test]
+fn test_will_forward_to_when_called_should_return_result_of_other_function() {
+    {
+        let mut injector = InjectorPP::new();
+        injector
+            .when_called(injectorpp::func!(fn (primary_id)() -> i32))
+            .will_forward_to(injectorpp::func!(fn (fallback_id)() -> i32));
+        injector
+            .when_called(injectorpp::func!(fn (multiply)(i32, i32) -> i32))
+            .will_forward_to(injectorpp::func!(fn (add_delta)(i32, i32) -> i32));
+
+        assert_eq!(primary_id(), fallback_id());
+        assert_eq!(primary_id(), 2);
+        assert_eq!(multiply(6, 7), 13);
+    }
+
+    assert_eq!(primary_id(), 1);
+    assert_eq!(multiply(6, 7), 42);
+}
+
+#[test]
+#[should_panic(expected = "Signature mismatch")]
+fn test_will_forward_to_when_untyped_signatures_differ_should_panic() {
+    let mut injector = InjectorPP::new();
+    injector
+        .when_called(injectorpp::func!(fn (primary_id)() -> i32).untyped())
+        .will_forward_to(injectorpp::func!(fn (multiply)(i32, i32) -> i32).untyped());
+}
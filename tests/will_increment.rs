@@ -0,0 +1,61 @@
+use injectorpp::interface::injector::*;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+#[inline(never)]
+fn send_heartbeat(sequence: u32) {
+    panic!("heartbeat {sequence} reached the network");
+}
+
+#[inline(never)]
+fn heartbeat_acknowledged() -> bool {
+    std::hint::black_box(false)
+}
+
+#[test]
+fn test_will_increment_when_called_five_times_should_count_five() {
+    static HEARTBEATS: AtomicUsize = AtomicUsize::new(0);
+
+    let mut injector = InjectorPP::new();
+    injector
+        .when_called(injectorpp::func!(fn (send_heartbeat)(u32)))
+        .will_increment(&HEARTBEATS);
+
+    for sequence in 0..5 {
+        send_heartbeat(sequence);
+    }
+
+    assert_eq!(HEARTBEATS.load(Ordering::SeqCst), 5);
+}
+
+#[test]
+fn test_will_increment_when_called_from_threads_should_count_every_call() {
+    static HEARTBEATS: AtomicUsize = AtomicUsize::new(0);
+
+    let mut injector = InjectorPP::new();
+    injector
+        .when_called(injectorpp::func!(fn (send_heartbeat)(u32)))
+        .will_increment(&HEARTBEATS);
+
+    std::thread::scope(|scope| {
+        for _ in 0..4 {
+            scope.spawn(|| {
+                for sequence in 0..1000 {
+                    send_heartbeat(sequence);
+                }
+            });
+        }
+    });
+
+    assert_eq!(HEARTBEATS.load(Ordering::SeqCst), 4000);
+}
+
+#[test]
+#[should_panic(expected = "will_increment requires a function returning ()")]
+fn test_will_increment_when_function_returns_value_should_panic() {
+    static ACKS: AtomicUsize = AtomicUsize::new(0);
+
+    let mut injector = InjectorPP::new();
+    injector
+        .when_called(injectorpp::func!(fn (heartbeat_acknowledged)() -> bool))
+        .will_increment(&ACKS);
+}
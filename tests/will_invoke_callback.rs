@@ -0,0 +1,109 @@
+use injectorpp::interface::injector::*;
+use std::sync::Mutex;
+
+#[inline(never)]
+fn each(cb: fn(u32)) {
+    cb(std::hint::black_box(0));
+}
+
+#[inline(never)]
+fn each_labelled(label: u32, cb: extern "C" fn(u32, u64, bool)) {
+    cb(label, 0, false);
+}
+
+#[inline(never)]
+fn count() -> usize {
+    std::hint::black_box(0)
+}
+
+static SEEN: Mutex<Vec<u32>> = Mutex::new(Vec::new());
+
+fn record(value: u32) {
+    SEEN.lock().unwrap().push(value);
+}
+
+static SEEN_LABELLED: Mutex<Vec<(u32, u64, bool)>> = Mutex::new(Vec::new());
+
+extern "C" fn record_labelled(label: u32, value: u64, last: bool) {
+    SEEN_LABELLED.lock().unwrap().push((label, value, last));
+}
+
+#[test]
+fn test_will_invoke_callback_when_fn_pointer_should_call_it_in_order() {
+    let mut injector = InjectorPP::new();
+    injector
+        .when_called(injectorpp::func!(fn (each)(fn(u32))))
+        .will_invoke_callback(0, &[&[1], &[2], &[3]]);
+
+    each(record);
+
+    assert_eq!(*SEEN.lock().unwrap(), vec![1, 2, 3]);
+}
+
+#[test]
+fn test_will_invoke_callback_when_second_argument_should_pass_all_arguments() {
+    let mut injector = InjectorPP::new();
+    injector
+        .when_called(injectorpp::func!(fn (each_labelled)(u32, extern "C" fn(u32, u64, bool))))
+        .will_invoke_callback(1, &[&[7, u64::MAX, 0], &[8, 42, 1]]);
+
+    each_labelled(0, record_labelled);
+
+    assert_eq!(
+        *SEEN_LABELLED.lock().unwrap(),
+        vec![(7, u64::MAX, false), (8, 42, true)]
+    );
+}
+
+#[test]
+#[should_panic(expected = "will_invoke_callback requires a function returning ()")]
+fn test_will_invoke_callback_when_function_returns_value_should_panic() {
+    let mut injector = InjectorPP::new();
+    injector
+        .when_called(injectorpp::func!(fn (count)() -> usize))
+        .will_invoke_callback(0, &[]);
+}
+
+#[test]
+#[should_panic(
+    expected = "will_invoke_callback requires argument 0 to be a `fn` or `extern \"C\" fn` pointer"
+)]
+fn test_will_invoke_callback_when_argument_is_not_fn_pointer_should_panic() {
+    let mut injector = InjectorPP::new();
+    injector
+        .when_called(injectorpp::func!(fn (each_labelled)(u32, extern "C" fn(u32, u64, bool))))
+        .will_invoke_callback(0, &[&[1]]);
+}
+
+#[test]
+#[should_panic(expected = "the callback takes 3 argument(s) but a call passes 1")]
+fn test_will_invoke_callback_when_call_has_wrong_arity_should_panic() {
+    let mut injector = InjectorPP::new();
+    injector
+        .when_called(injectorpp::func!(fn (each_labelled)(u32, extern "C" fn(u32, u64, bool))))
+        .will_invoke_callback(1, &[&[7, 1, 0], &[8]]);
+}
+
+#[inline(never)]
+fn each_pair(cb: fn(&str, fn(u32) -> u32), tick: fn()) {
+    cb("", |value| value);
+    tick();
+}
+
+static TICKS: Mutex<usize> = Mutex::new(0);
+
+fn tick() {
+    *TICKS.lock().unwrap() += 1;
+}
+
+#[test]
+fn test_will_invoke_callback_when_other_arguments_are_fn_pointers_should_find_the_callback() {
+    let mut injector = InjectorPP::new();
+    injector
+        .when_called(injectorpp::func!(fn (each_pair)(fn(&str, fn(u32) -> u32), fn())))
+        .will_invoke_callback(1, &[&[], &[]]);
+
+    each_pair(|_, _| {}, tick);
+
+    assert_eq!(*TICKS.lock().unwrap(), 2);
+}
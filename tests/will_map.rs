@@ -0,0 +1,98 @@
+use injectorpp::interface::injector::*;
+
+#[inline(never)]
+fn scale(x: i32) -> i32 {
+    std::hint::black_box(x) * 2
+}
+
+#[inline(never)]
+fn describe(name: String, count: usize) -> String {
+    format!("{}: {}", std::hint::black_box(name), count)
+}
+
+#[test]
+fn test_will_map_when_original_doubles_should_add_argument() {
+    let mut injector = InjectorPP::new();
+    injector
+        .when_called(injectorpp::func!(fn (scale)(i32) -> i32))
+        .will_map(|x: i32, original_result: i32| original_result + x);
+
+    for x in [-5, 0, 1, 7, 1000] {
+        assert_eq!(scale(x), 3 * x);
+    }
+}
+
+#[test]
+fn test_will_map_when_arguments_owned_should_pass_clones() {
+    let mut injector = InjectorPP::new();
+    injector
+        .when_called(injectorpp::func!(fn (describe)(String, usize) -> String))
+        .will_map(|name: String, count: usize, original: String| {
+            format!("{original} ({} chars, {count} items)", name.len())
+        });
+
+    assert_eq!(
+        describe("apples".to_string(), 3),
+        "apples: 3 (6 chars, 3 items)"
+    );
+}
+
+#[test]
+fn test_will_map_when_injector_dropped_should_restore_original() {
+    {
+        let mut injector = InjectorPP::new();
+        injector
+            .when_called(injectorpp::func!(fn (scale)(i32) -> i32))
+            .will_map(|_x: i32, original_result: i32| original_result + 1);
+
+        assert_eq!(scale(4), 9);
+    }
+
+    assert_eq!(scale(4), 8);
+}
+
+#[inline(never)]
+fn parse_port(input: &str) -> Result<u16, String> {
+    std::hint::black_box(input)
+        .parse()
+        .map_err(|_| format!("invalid port {input}"))
+}
+
+#[test]
+fn test_will_call_original_with_hook_when_wrapping_should_call_real_function() {
+    let mut injector = InjectorPP::new();
+    injector
+        .when_called(injectorpp::func!(fn (parse_port)(&str) -> Result<u16, String>))
+        .will_call_original_with_hook(
+            |original: fn(&'static str) -> Result<u16, String>, input: &'static str| {
+                if input.is_empty() {
+                    return Ok(80);
+                }
+
+                original(input.trim()).map(|port| port + 1)
+            },
+        );
+
+    assert_eq!(parse_port(""), Ok(80));
+    assert_eq!(parse_port(" 8080 "), Ok(8081));
+    assert_eq!(parse_port("x"), Err("invalid port x".to_string()));
+}
+
+#[test]
+fn test_will_call_original_with_hook_when_original_skipped_should_not_run_it() {
+    let mut injector = InjectorPP::new();
+    injector
+        .when_called(injectorpp::func!(fn (scale)(i32) -> i32))
+        .will_call_original_with_hook(
+            |original: fn(i32) -> i32, x: i32| {
+                if x < 0 {
+                    0
+                } else {
+                    original(x)
+                }
+            },
+        );
+
+    assert_eq!(scale(-3), 0);
+    assert_eq!(scale(4), 8);
+}
@@ -0,0 +1,44 @@
+use injectorpp::interface::injector::*;
+use std::panic::catch_unwind;
+
+#[inline(never)]
+fn load_profile(user: u32) -> Result<String, String> {
+    Ok(format!("user {}", std::hint::black_box(user)))
+}
+
+#[inline(never)]
+fn flush_cache() {
+    std::hint::black_box(());
+}
+
+fn panic_message(payload: Box<dyn std::any::Any + Send>) -> String {
+    payload.downcast_ref::<String>().cloned().unwrap()
+}
+
+#[test]
+fn test_will_panic_when_called_should_unwind_to_caller() {
+    {
+        let mut injector = InjectorPP::new();
+        injector
+            .when_called(injectorpp::func!(fn (load_profile)(u32) -> Result<String, String>))
+            .will_panic("profile store unavailable");
+
+        for _ in 0..2 {
+            let payload = catch_unwind(|| load_profile(7)).unwrap_err();
+            assert_eq!(panic_message(payload), "profile store unavailable");
+        }
+    }
+
+    assert_eq!(load_profile(7), Ok("user 7".to_string()));
+}
+
+#[test]
+fn test_will_panic_when_target_returns_unit_should_unwind_to_caller() {
+    let mut injector = InjectorPP::new();
+    injector
+        .when_called(injectorpp::func!(fn (flush_cache)()))
+        .will_panic("disk full");
+
+    let payload = catch_unwind(flush_cache).unwrap_err();
+    assert_eq!(panic_message(payload), "disk full");
+}
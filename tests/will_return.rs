@@ -8,6 +8,11 @@ pub fn returns_false_in_scope() -> bool {
     return false;
 }
 
+#[inline(never)]
+fn returns_bool_for_register_check() -> bool {
+    std::hint::black_box(false)
+}
+
 fn complex_generic_multiple_types_func_return_false<A, B, C>(_a: A, _b: B, _c: C) -> bool {
     return false;
 }
@@ -63,6 +68,49 @@ fn test_will_return_boolean_when_fake_return_false_should_success() {
     assert_eq!(result, false);
 }
 
+#[test]
+fn test_will_return_boolean_when_read_as_byte_or_register_should_agree() {
+    // Read the return register of the faked function as its low byte and as a whole.
+    let as_byte: fn() -> u8 =
+        unsafe { std::mem::transmute(returns_bool_for_register_check as fn() -> bool) };
+    let as_register: fn() -> u64 =
+        unsafe { std::mem::transmute(returns_bool_for_register_check as fn() -> bool) };
+
+    for value in [true, false, true] {
+        let mut injector = InjectorPP::new();
+        injector
+            .when_called(injectorpp::func!(fn (returns_bool_for_register_check)() -> bool))
+            .will_return_boolean(value);
+
+        assert_eq!(returns_bool_for_register_check(), value);
+        assert_eq!(as_byte(), value as u8);
+        assert_eq!(as_register(), value as u64);
+    }
+}
+
+#[inline(never)]
+extern "C" fn ffi_is_connected() -> bool {
+    std::hint::black_box(false)
+}
+
+#[test]
+#[allow(clippy::bool_comparison)]
+fn test_will_return_boolean_when_extern_c_should_return_exactly_zero_or_one() {
+    let as_register: extern "C" fn() -> usize =
+        unsafe { std::mem::transmute(ffi_is_connected as extern "C" fn() -> bool) };
+
+    for value in [true, false] {
+        let mut injector = InjectorPP::new();
+        injector
+            .when_called(injectorpp::func!(unsafe{} extern "C" fn (ffi_is_connected)() -> bool))
+            .will_return_boolean(value);
+
+        assert_eq!(ffi_is_connected() == true, value);
+        assert_eq!(ffi_is_connected() as u8, value as u8);
+        assert_eq!(as_register(), value as usize);
+    }
+}
+
 #[test]
 fn test_will_return_boolean_when_fake_complex_generic_function_multiple_types_should_success() {
     let mut injector = InjectorPP::new();
@@ -92,3 +140,534 @@ fn test_will_return_boolean_when_fake_complex_generic_function_multiple_types_an
 
     assert_eq!(result, true);
 }
+
+pub fn pair() -> (u64, u64) {
+    (1, 2)
+}
+
+#[inline(never)]
+pub fn buffer() -> (usize, *const u8) {
+    (0, std::ptr::null())
+}
+
+#[test]
+fn test_will_return_aggregate_when_fake_tuple_should_success() {
+    let mut injector = InjectorPP::new();
+    injector
+        .when_called(injectorpp::func!(fn (pair)() -> (u64, u64)))
+        .will_return_aggregate((0xAAAAu64, 0xBBBBu64));
+
+    assert_eq!(pair(), (0xAAAA, 0xBBBB));
+}
+
+#[test]
+fn test_will_return_aggregate_when_fake_length_and_pointer_should_success() {
+    static DATA: [u8; 3] = [1, 2, 3];
+
+    let mut injector = InjectorPP::new();
+    injector
+        .when_called(injectorpp::func!(fn (buffer)() -> (usize, *const u8)))
+        .will_return_aggregate((DATA.len(), DATA.as_ptr()));
+
+    assert_eq!(buffer(), (3, DATA.as_ptr()));
+}
+
+#[inline(never)]
+fn level() -> u8 {
+    std::hint::black_box(1)
+}
+
+#[inline(never)]
+fn offset() -> i16 {
+    std::hint::black_box(1)
+}
+
+#[inline(never)]
+fn port() -> u32 {
+    std::hint::black_box(1)
+}
+
+#[repr(C)]
+#[derive(Clone, Copy, Debug, PartialEq)]
+struct Checksum {
+    high: u64,
+    low: u64,
+}
+
+#[inline(never)]
+fn checksum() -> Checksum {
+    std::hint::black_box(Checksum { high: 1, low: 2 })
+}
+
+#[inline(never)]
+fn ratio() -> f64 {
+    std::hint::black_box(1.0)
+}
+
+#[inline(never)]
+fn initial() -> char {
+    std::hint::black_box('a')
+}
+
+#[inline(never)]
+fn range() -> (u64, u64) {
+    std::hint::black_box((1, 2))
+}
+
+#[inline(never)]
+fn total() -> u128 {
+    std::hint::black_box(1)
+}
+
+#[inline(never)]
+fn label() -> &'static str {
+    std::hint::black_box("real")
+}
+
+#[test]
+fn test_will_return_when_fake_small_integers_should_return_exact_value() {
+    let mut injector = InjectorPP::new();
+    injector
+        .when_called(injectorpp::func!(fn (level)() -> u8))
+        .will_return(0xFEu8);
+    injector
+        .when_called(injectorpp::func!(fn (offset)() -> i16))
+        .will_return(-2i16);
+    injector
+        .when_called(injectorpp::func!(fn (port)() -> u32))
+        .will_return(0xFFFF_FFF0u32);
+
+    assert_eq!(level(), 0xFE);
+    assert_eq!(offset(), -2);
+    assert_eq!(i32::from(offset()), -2);
+    assert_eq!(port(), 0xFFFF_FFF0);
+    assert_eq!(u64::from(port()), 0xFFFF_FFF0);
+}
+
+#[test]
+fn test_will_return_when_fake_float_and_char_should_return_exact_value() {
+    let mut injector = InjectorPP::new();
+    injector
+        .when_called(injectorpp::func!(fn (ratio)() -> f64))
+        .will_return(0.25f64);
+    injector
+        .when_called(injectorpp::func!(fn (initial)() -> char))
+        .will_return('z');
+
+    assert_eq!(ratio(), 0.25);
+    assert_eq!(initial(), 'z');
+}
+
+#[test]
+#[cfg(target_pointer_width = "64")]
+fn test_will_return_when_fake_two_register_values_should_return_both_halves() {
+    let mut injector = InjectorPP::new();
+    injector
+        .when_called(injectorpp::func!(fn (range)() -> (u64, u64)))
+        .will_return((u64::MAX, 0x1234_5678_9ABC_DEF0u64));
+    injector
+        .when_called(injectorpp::func!(fn (total)() -> u128))
+        .will_return(0x0123_4567_89AB_CDEF_FEDC_BA98_7654_3210u128);
+    injector
+        .when_called(injectorpp::func!(fn (label)() -> &'static str))
+        .will_return("fake");
+
+    assert_eq!(range(), (u64::MAX, 0x1234_5678_9ABC_DEF0));
+    assert_eq!(total(), 0x0123_4567_89AB_CDEF_FEDC_BA98_7654_3210);
+    assert_eq!(label(), "fake");
+}
+
+#[test]
+#[should_panic(expected = "Signature mismatch: will_return requires a function returning u8")]
+fn test_will_return_when_return_type_differs_should_panic() {
+    let mut injector = InjectorPP::new();
+    injector
+        .when_called(injectorpp::func!(fn (port)() -> u32))
+        .will_return(1u8);
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+struct Config {
+    id: u64,
+    retries: u32,
+    verbose: bool,
+    ratio: f64,
+    name: [u8; 16],
+    limits: [u64; 3],
+}
+
+#[inline(never)]
+fn make_config(id: u64) -> Config {
+    std::hint::black_box(Config {
+        id,
+        retries: 0,
+        verbose: false,
+        ratio: 0.0,
+        name: [0; 16],
+        limits: [0; 3],
+    })
+}
+
+#[test]
+fn test_will_return_struct_when_fake_large_struct_should_return_every_field() {
+    let fake = Config {
+        id: 0x1234_5678_9ABC_DEF0,
+        retries: u32::MAX,
+        verbose: true,
+        ratio: 0.25,
+        name: *b"injectorpp-fake!",
+        limits: [1, u64::MAX, 3],
+    };
+    assert_eq!(std::mem::size_of::<Config>(), 64);
+
+    {
+        let mut injector = InjectorPP::new();
+        injector
+            .when_called(injectorpp::func!(fn (make_config)(u64) -> Config))
+            .will_return_struct(fake);
+
+        assert_eq!(make_config(1), fake);
+        assert_eq!(make_config(2).limits, [1, u64::MAX, 3]);
+    }
+
+    assert_eq!(make_config(7).id, 7);
+}
+
+#[test]
+#[should_panic(expected = "will_return_struct requires a type larger than 16 bytes")]
+fn test_will_return_struct_when_type_fits_in_registers_should_panic() {
+    let mut injector = InjectorPP::new();
+    injector
+        .when_called(injectorpp::func!(fn (checksum)() -> Checksum))
+        .will_return_struct(Checksum { high: 1, low: 2 });
+}
+
+#[inline(never)]
+fn current_timestamp() -> i64 {
+    std::hint::black_box(1_700_000_000)
+}
+
+#[inline(never)]
+fn free_bytes(volume: u32) -> u64 {
+    std::hint::black_box(volume) as u64 * 4096
+}
+
+#[test]
+fn test_will_return_i64_when_faked_should_return_exact_value() {
+    for value in [0, -1, i64::MAX, i64::MIN, 0x1234_5678_9ABC_DEF0] {
+        let mut injector = InjectorPP::new();
+        injector
+            .when_called(injectorpp::func!(fn (current_timestamp)() -> i64))
+            .will_return_i64(value);
+
+        assert_eq!(current_timestamp(), value);
+    }
+
+    assert_eq!(current_timestamp(), 1_700_000_000);
+}
+
+#[test]
+fn test_will_return_u64_when_faked_should_return_exact_value() {
+    for value in [0, 1, u64::MAX, 0x1234_5678_9ABC_DEF0, 0xFFFF_0000_0000_FFFF] {
+        let mut injector = InjectorPP::new();
+        injector
+            .when_called(injectorpp::func!(fn (free_bytes)(u32) -> u64))
+            .will_return_u64(value);
+
+        assert_eq!(free_bytes(3), value);
+    }
+
+    assert_eq!(free_bytes(3), 3 * 4096);
+}
+
+#[test]
+#[should_panic(
+    expected = "Signature mismatch: will_return_i64 requires a function returning i64 but got fn(u32) -> u64"
+)]
+fn test_will_return_i64_when_return_type_differs_should_panic() {
+    let mut injector = InjectorPP::new();
+    injector
+        .when_called(injectorpp::func!(fn (free_bytes)(u32) -> u64))
+        .will_return_i64(1);
+}
+
+#[inline(never)]
+fn exit_code(attempt: u32) -> i32 {
+    std::hint::black_box(attempt) as i32 - 1
+}
+
+#[test]
+fn test_will_return_i32_when_faked_should_return_exact_value() {
+    for value in [0, -1, i32::MAX, i32::MIN] {
+        let mut injector = InjectorPP::new();
+        injector
+            .when_called(injectorpp::func!(fn (exit_code)(u32) -> i32))
+            .will_return_i32(value);
+
+        assert_eq!(exit_code(5), value);
+    }
+
+    assert_eq!(exit_code(5), 4);
+}
+
+#[inline(never)]
+fn price(quantity: u32) -> f64 {
+    std::hint::black_box(quantity) as f64 * 9.99
+}
+
+#[test]
+fn test_will_return_f64_when_faked_should_preserve_bit_pattern() {
+    let values = [
+        f64::NAN,
+        f64::from_bits(0x7FF8_0000_DEAD_BEEF),
+        f64::INFINITY,
+        f64::NEG_INFINITY,
+        0.0,
+        -0.0,
+        1234.5678,
+    ];
+
+    for value in values {
+        let mut injector = InjectorPP::new();
+        injector
+            .when_called(injectorpp::func!(fn (price)(u32) -> f64))
+            .will_return_f64(value);
+
+        assert_eq!(price(2).to_bits(), value.to_bits());
+    }
+
+    assert_eq!(price(2), 2.0 * 9.99);
+}
+
+#[inline(never)]
+fn fetch() -> bool {
+    std::hint::black_box(false)
+}
+
+#[test]
+fn test_will_return_boolean_sequence_should_return_values_in_order() {
+    let mut injector = InjectorPP::new();
+    injector
+        .when_called(injectorpp::func!(fn (fetch)() -> bool))
+        .will_return_boolean_sequence(&[false, false, true]);
+
+    let results: Vec<bool> = (0..3).map(|_| fetch()).collect();
+
+    assert_eq!(results, [false, false, true]);
+}
+
+#[test]
+fn test_will_return_boolean_sequence_when_exhausted_should_repeat_last_value() {
+    let mut injector = InjectorPP::new();
+    injector
+        .when_called(injectorpp::func!(fn (fetch)() -> bool))
+        .will_return_boolean_sequence(&[true, false]);
+
+    let results: Vec<bool> = (0..5).map(|_| fetch()).collect();
+
+    assert_eq!(results, [true, false, false, false, false]);
+}
+
+#[inline(never)]
+fn poll_ready() -> bool {
+    std::hint::black_box(false)
+}
+
+#[test]
+fn test_will_return_boolean_sequence_when_read_as_register_should_return_exactly_zero_or_one() {
+    // Read the whole return register of the faked function.
+    let as_register: fn() -> usize = unsafe { std::mem::transmute(poll_ready as fn() -> bool) };
+
+    let mut injector = InjectorPP::new();
+    injector
+        .when_called(injectorpp::func!(fn (poll_ready)() -> bool))
+        .will_return_boolean_sequence(&[true, false, true]);
+
+    let results: Vec<usize> = (0..3).map(|_| as_register()).collect();
+
+    assert_eq!(results, [1, 0, 1]);
+}
+
+#[test]
+#[should_panic(expected = "will_return_boolean_sequence requires at least one value")]
+fn test_will_return_boolean_sequence_when_empty_should_panic() {
+    let mut injector = InjectorPP::new();
+    injector
+        .when_called(injectorpp::func!(fn (fetch)() -> bool))
+        .will_return_boolean_sequence(&[]);
+}
+
+struct Account {
+    owner: String,
+}
+
+impl Account {
+    #[inline(never)]
+    fn owner(&self) -> &str {
+        &self.owner
+    }
+
+    #[inline(never)]
+    fn balance(&self) -> &u64 {
+        std::hint::black_box(&0)
+    }
+}
+
+#[test]
+fn test_will_return_ref_when_value_is_owned_string_should_return_reference_into_it() {
+    let account = Account {
+        owner: "real".to_string(),
+    };
+
+    let owner = {
+        let mut injector = InjectorPP::new();
+        injector
+            .when_called(injectorpp::func!(fn (Account::owner)(&Account) -> &str))
+            .will_return_ref(String::from("fake owner").into_boxed_str());
+
+        assert_eq!(account.owner(), "fake owner");
+        account.owner()
+    };
+
+    assert_eq!(owner, "fake owner");
+    assert_eq!(account.owner(), "real");
+}
+
+#[test]
+fn test_will_return_ref_when_value_is_sized_should_return_reference_to_it() {
+    let mut injector = InjectorPP::new();
+    injector
+        .when_called(injectorpp::func!(fn (Account::balance)(&Account) -> &u64))
+        .will_return_ref(Box::new(42u64));
+
+    let account = Account {
+        owner: "real".to_string(),
+    };
+    assert_eq!(*account.balance(), 42);
+}
+
+#[test]
+#[should_panic(expected = "Signature mismatch")]
+fn test_will_return_ref_when_referent_type_differs_should_panic() {
+    let mut injector = InjectorPP::new();
+    injector
+        .when_called(injectorpp::func!(fn (Account::owner)(&Account) -> &str))
+        .will_return_ref(Box::new(42u64));
+}
+
+#[inline(never)]
+unsafe extern "C" fn device_name() -> *const std::ffi::c_char {
+    std::hint::black_box(c"real-device").as_ptr()
+}
+
+static THRESHOLDS: [u32; 3] = [5, 50, 500];
+
+#[inline(never)]
+unsafe extern "C" fn thresholds() -> *const u32 {
+    std::hint::black_box(std::ptr::null())
+}
+
+#[test]
+fn test_will_return_ptr_when_pointer_to_static_should_return_it() {
+    let mut injector = InjectorPP::new();
+    injector
+        .when_called(injectorpp::func!(unsafe{} extern "C" fn (thresholds)() -> *const u32))
+        .will_return_ptr(THRESHOLDS.as_ptr());
+
+    let ptr = unsafe { thresholds() };
+    assert_eq!(ptr, THRESHOLDS.as_ptr());
+    assert_eq!(unsafe { *ptr.add(2) }, 500);
+}
+
+#[test]
+fn test_will_return_cstr_when_called_through_ffi_should_return_copy_of_string() {
+    {
+        let name = std::ffi::CString::new(format!("fake-{}", 7)).unwrap();
+
+        let mut injector = InjectorPP::new();
+        injector
+            .when_called(
+                injectorpp::func!(unsafe{} extern "C" fn (device_name)() -> *const std::ffi::c_char),
+            )
+            .will_return_cstr(&name);
+        drop(name);
+
+        let callback: unsafe extern "C" fn() -> *const std::ffi::c_char =
+            std::hint::black_box(device_name);
+        let returned = unsafe { std::ffi::CStr::from_ptr(callback()) };
+        assert_eq!(returned, c"fake-7");
+        assert_eq!(
+            unsafe { std::ffi::CStr::from_ptr(device_name()) }.as_ptr(),
+            returned.as_ptr()
+        );
+    }
+
+    assert_eq!(
+        unsafe { std::ffi::CStr::from_ptr(device_name()) },
+        c"real-device"
+    );
+}
+
+#[test]
+#[should_panic(expected = "will_return_cstr requires a function returning *const")]
+fn test_will_return_cstr_when_function_returns_other_pointer_should_panic() {
+    let mut injector = InjectorPP::new();
+    injector
+        .when_called(injectorpp::func!(unsafe{} extern "C" fn (thresholds)() -> *const u32))
+        .will_return_cstr(c"five");
+}
+
+static DEFAULT_LIMIT: i32 = 10;
+
+#[inline(never)]
+fn find_limit(name: &str) -> Option<&'static i32> {
+    std::hint::black_box(name);
+    std::hint::black_box(Some(&DEFAULT_LIMIT))
+}
+
+#[inline(never)]
+fn worker_id() -> Option<std::num::NonZeroU32> {
+    std::hint::black_box(std::num::NonZeroU32::new(1))
+}
+
+#[test]
+fn test_will_return_when_fake_option_of_reference_should_return_pointer_or_null() {
+    static FAKE_LIMIT: i32 = 42;
+
+    {
+        let mut injector = InjectorPP::new();
+        injector
+            .when_called(injectorpp::func!(fn (find_limit)(&str) -> Option<&'static i32>))
+            .will_return(Some(&FAKE_LIMIT));
+
+        let limit = find_limit("uploads");
+        assert_eq!(limit, Some(&42));
+        assert!(std::ptr::eq(limit.unwrap(), &FAKE_LIMIT));
+    }
+
+    let mut injector = InjectorPP::new();
+    injector
+        .when_called(injectorpp::func!(fn (find_limit)(&str) -> Option<&'static i32>))
+        .will_return(None::<&'static i32>);
+
+    assert_eq!(find_limit("uploads"), None);
+}
+
+#[test]
+fn test_will_return_when_fake_option_of_non_zero_should_use_zero_for_none() {
+    {
+        let mut injector = InjectorPP::new();
+        injector
+            .when_called(injectorpp::func!(fn (worker_id)() -> Option<std::num::NonZeroU32>))
+            .will_return(std::num::NonZeroU32::new(0xFFFF_FFFE));
+
+        assert_eq!(worker_id().map(|id| id.get()), Some(0xFFFF_FFFE));
+    }
+
+    let mut injector = InjectorPP::new();
+    injector
+        .when_called(injectorpp::func!(fn (worker_id)() -> Option<std::num::NonZeroU32>))
+        .will_return(None::<std::num::NonZeroU32>);
+
+    assert_eq!(worker_id(), None);
+}
@@ -0,0 +1,52 @@
+use injectorpp::interface::injector::*;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::{Duration, Instant};
+
+static SAVED: AtomicBool = AtomicBool::new(false);
+
+#[inline(never)]
+fn save_settings() {
+    SAVED.store(std::hint::black_box(true), Ordering::SeqCst);
+}
+
+#[inline(never)]
+fn connect(port: u16) -> bool {
+    std::hint::black_box(port) == 0
+}
+
+#[test]
+fn test_will_sleep_when_void_function_called_should_delay_caller() {
+    let mut injector = InjectorPP::new();
+    injector
+        .when_called(injectorpp::func!(fn (save_settings)()))
+        .will_sleep(Duration::from_millis(50));
+
+    let start = Instant::now();
+    save_settings();
+
+    assert!(start.elapsed() >= Duration::from_millis(50));
+    assert!(!SAVED.load(Ordering::SeqCst));
+}
+
+#[test]
+#[should_panic(expected = "will_sleep requires a function returning ()")]
+fn test_will_sleep_when_function_returns_value_should_panic() {
+    let mut injector = InjectorPP::new();
+    injector
+        .when_called(injectorpp::func!(fn (connect)(u16) -> bool))
+        .will_sleep(Duration::from_millis(50));
+}
+
+#[test]
+fn test_with_delay_when_function_returns_value_should_delay_then_return_fake() {
+    let mut injector = InjectorPP::new();
+    injector
+        .when_called(injectorpp::func!(fn (connect)(u16) -> bool))
+        .with_delay(Duration::from_millis(50))
+        .will_execute(|port: u16| port == 8080);
+
+    let start = Instant::now();
+
+    assert!(connect(8080));
+    assert!(start.elapsed() >= Duration::from_millis(50));
+}
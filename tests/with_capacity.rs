@@ -0,0 +1,84 @@
+use injectorpp::interface::injector::*;
+
+#[inline(never)]
+fn is_ready() -> bool {
+    std::hint::black_box(false)
+}
+
+#[inline(never)]
+fn scale(value: u64) -> u64 {
+    std::hint::black_box(value) * 2
+}
+
+#[inline(never)]
+fn retries() -> u8 {
+    std::hint::black_box(3)
+}
+
+#[test]
+fn test_with_capacity_when_installing_and_restoring_repeatedly_should_fake_each_time() {
+    let mut injector = InjectorPP::with_capacity(1);
+
+    for _ in 0..1000 {
+        let handle = injector
+            .when_called(injectorpp::func!(fn (is_ready)() -> bool))
+            .will_return_boolean(true);
+        assert!(is_ready());
+
+        injector.restore(handle);
+        assert!(!is_ready());
+    }
+}
+
+#[test]
+fn test_with_capacity_when_exceeding_capacity_should_still_install_every_fake() {
+    {
+        let mut injector = InjectorPP::with_capacity(1);
+        injector
+            .when_called(injectorpp::func!(fn (is_ready)() -> bool))
+            .will_return_boolean(true);
+        injector
+            .when_called(injectorpp::func!(fn (scale)(u64) -> u64))
+            .will_execute(|value: u64| value + 1);
+        injector
+            .when_called(injectorpp::func!(fn (retries)() -> u8))
+            .will_return(7u8);
+
+        assert!(is_ready());
+        assert_eq!(scale(20), 21);
+        assert_eq!(retries(), 7);
+    }
+
+    assert!(!is_ready());
+    assert_eq!(scale(20), 40);
+    assert_eq!(retries(), 3);
+}
+
+#[test]
+fn test_with_capacity_when_zero_should_behave_like_new() {
+    let mut injector = InjectorPP::with_capacity(0);
+    injector
+        .when_called(injectorpp::func!(fn (scale)(u64) -> u64))
+        .will_return_u64(5);
+
+    assert_eq!(scale(20), 5);
+}
+
+#[cfg(feature = "metrics")]
+#[test]
+fn test_with_capacity_should_keep_pages_mapped_across_restores() {
+    let mut injector = InjectorPP::with_capacity(4);
+    let reserved = InjectorPP::jit_bytes_used();
+    assert!(reserved > 0);
+
+    for _ in 0..10 {
+        let handle = injector
+            .when_called(injectorpp::func!(fn (scale)(u64) -> u64))
+            .will_return_u64(5);
+        assert_eq!(scale(20), 5);
+        assert_eq!(InjectorPP::jit_bytes_used(), reserved);
+
+        injector.restore(handle);
+        assert_eq!(InjectorPP::jit_bytes_used(), reserved);
+    }
+}
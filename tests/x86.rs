@@ -0,0 +1,60 @@
+#![cfg(target_arch = "x86")]
+
+use injectorpp::interface::injector::*;
+
+#[inline(never)]
+fn is_enabled() -> bool {
+    std::hint::black_box(false)
+}
+
+#[inline(never)]
+fn add(a: i32, b: i32) -> i32 {
+    std::hint::black_box(a) + b
+}
+
+fn fake_add(a: i32, b: i32) -> i32 {
+    a * b
+}
+
+#[inline(never)]
+fn file_size() -> u64 {
+    std::hint::black_box(1)
+}
+
+#[test]
+fn test_x86_will_return_boolean_should_return_value_and_restore() {
+    {
+        let mut injector = InjectorPP::new();
+        injector
+            .when_called(injectorpp::func!(fn (is_enabled)() -> bool))
+            .will_return_boolean(true);
+
+        assert!(is_enabled());
+    }
+
+    assert!(!is_enabled());
+}
+
+#[test]
+fn test_x86_will_execute_raw_should_call_fake_with_arguments() {
+    {
+        let mut injector = InjectorPP::new();
+        injector
+            .when_called(injectorpp::func!(fn (add)(i32, i32) -> i32))
+            .will_execute_raw(injectorpp::func!(fn (fake_add)(i32, i32) -> i32));
+
+        assert_eq!(add(6, 7), 42);
+    }
+
+    assert_eq!(add(6, 7), 13);
+}
+
+#[test]
+fn test_x86_will_return_u64_should_return_value_in_edx_eax() {
+    let mut injector = InjectorPP::new();
+    injector
+        .when_called(injectorpp::func!(fn (file_size)() -> u64))
+        .will_return_u64(0x1_0000_0002);
+
+    assert_eq!(file_size(), 0x1_0000_0002);
+}